@@ -0,0 +1,74 @@
+use anyhow::Result;
+use dragonglass::{
+    config::{AxisCalibration, Config, InputCalibration, ResponseCurve},
+    gui::egui::{self, ComboBox, CtxRef, Slider, Ui},
+};
+
+const DEVICES: [(&str, [&str; 2]); 1] = [(InputCalibration::MOUSE, ["x", "y"])];
+
+#[derive(Default)]
+pub struct CalibrationWindow {
+    pub open: bool,
+    device: usize,
+}
+
+impl CalibrationWindow {
+    pub fn render(&mut self, context: &CtxRef, config: &mut Config) -> Result<()> {
+        let mut open = self.open;
+        let mut save = false;
+        egui::Window::new("Input Calibration")
+            .open(&mut open)
+            .resizable(false)
+            .show(context, |ui| {
+                ComboBox::from_label("Device")
+                    .selected_text(DEVICES[self.device].0)
+                    .show_ui(ui, |ui| {
+                        for (index, (device, _)) in DEVICES.iter().enumerate() {
+                            ui.selectable_value(&mut self.device, index, *device);
+                        }
+                    });
+
+                let (device, axes) = DEVICES[self.device];
+                for axis in axes.iter() {
+                    ui.separator();
+                    ui.heading(*axis);
+                    axis_calibration_widget(ui, config.input.axis_mut(device, axis));
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        config.input.devices.remove(device);
+                    }
+                    save = ui.button("Save").clicked();
+                });
+            });
+        self.open = open;
+
+        if save {
+            config.save(Config::FILE)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn axis_calibration_widget(ui: &mut Ui, calibration: &mut AxisCalibration) {
+    ui.add(Slider::new(&mut calibration.dead_zone, 0.0..=0.9).text("Dead Zone"));
+    ui.add(Slider::new(&mut calibration.sensitivity, 0.1..=10.0).text("Sensitivity"));
+
+    let exponential = matches!(calibration.curve, ResponseCurve::Exponential { .. });
+    ui.horizontal(|ui| {
+        if ui.radio(!exponential, "Linear").clicked() {
+            calibration.curve = ResponseCurve::Linear;
+        }
+        if ui.radio(exponential, "Exponential").clicked() && !exponential {
+            calibration.curve = ResponseCurve::Exponential { exponent: 2.0 };
+        }
+    });
+    if let ResponseCurve::Exponential { exponent } = &mut calibration.curve {
+        ui.add(Slider::new(exponent, 1.0..=5.0).text("Exponent"));
+    }
+
+    ui.checkbox(&mut calibration.inverted, "Inverted");
+}
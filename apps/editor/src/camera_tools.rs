@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use dragonglass::{
+    app::{set_camera_controller, CameraController, Orientation, Resources},
+    world::{legion::Entity, Camera, EntityStore, Projection, Transform},
+};
+use log::info;
+use nalgebra_glm as glm;
+use std::f32::consts::PI;
+
+// Where the editor camera is and what it looks at, for both orbit and fly cameras.
+// Orbit cameras place themselves from the orientation, and fly cameras only look with it.
+#[derive(Debug, Clone)]
+struct CameraPose {
+    orientation: Orientation,
+    translation: glm::Vec3,
+}
+
+impl CameraPose {
+    // Looking along a direction from a point, orbiting the point the distance ahead of it
+    fn looking(
+        orientation: &Orientation,
+        eye: glm::Vec3,
+        forward: glm::Vec3,
+        distance: f32,
+    ) -> Self {
+        let mut orientation = orientation.clone();
+        let direction = -forward.normalize();
+        orientation.direction = glm::vec2(
+            direction.x.atan2(direction.z),
+            direction
+                .y
+                .clamp(-1.0, 1.0)
+                .acos()
+                .clamp(10_f32.to_radians(), 170_f32.to_radians()),
+        );
+        orientation.offset = eye + forward.normalize() * distance;
+        orientation.radius = distance.max(orientation.min_radius);
+        orientation.max_radius = orientation.max_radius.max(orientation.radius);
+        Self {
+            orientation,
+            translation: eye,
+        }
+    }
+
+    fn lerp(&self, to: &Self, amount: f32) -> Self {
+        let mut orientation = to.orientation.clone();
+        let from = &self.orientation;
+        // Turns the short way around instead of unwinding every full turn taken before
+        let mut yaw = to.orientation.direction.x - from.direction.x;
+        yaw -= (yaw / (2.0 * PI)).round() * 2.0 * PI;
+        orientation.direction = glm::vec2(
+            from.direction.x + yaw * amount,
+            glm::lerp_scalar(from.direction.y, to.orientation.direction.y, amount),
+        );
+        orientation.offset = glm::lerp(&from.offset, &to.orientation.offset, amount);
+        orientation.radius = glm::lerp_scalar(from.radius, to.orientation.radius, amount);
+        Self {
+            orientation,
+            translation: glm::lerp(&self.translation, &to.translation, amount),
+        }
+    }
+}
+
+pub const CAMERA_BOOKMARKS: usize = 9;
+
+struct CameraTransition {
+    from: CameraPose,
+    to: CameraPose,
+    elapsed: f32,
+}
+
+// Framing the selection, numbered bookmarks, and aligning the camera and the selection to each other.
+// The camera moves to each new pose smoothly rather than jumping to it.
+#[derive(Default)]
+pub struct CameraTools {
+    bookmarks: [Option<CameraPose>; CAMERA_BOOKMARKS],
+    transition: Option<CameraTransition>,
+}
+
+impl CameraTools {
+    const TRANSITION_SECONDS: f32 = 0.35;
+    // Extra room around framed bounds, so they don't touch the edges of the view
+    const FRAMING_MARGIN: f32 = 1.2;
+
+    pub fn store_bookmark(&mut self, slot: usize, resources: &Resources) -> Result<()> {
+        if let Some(pose) = Self::current_pose(resources)? {
+            self.bookmarks[slot] = Some(pose);
+            info!("Stored camera bookmark {}", slot + 1);
+        }
+        Ok(())
+    }
+
+    pub fn recall_bookmark(&mut self, slot: usize, resources: &Resources) -> Result<()> {
+        match self.bookmarks[slot].clone() {
+            Some(pose) => self.move_to(pose, resources),
+            None => Ok(()),
+        }
+    }
+
+    // Fits the entity and everything beneath it in the view, keeping the direction the camera faces
+    pub fn frame_entity(&mut self, entity: Entity, resources: &Resources) -> Result<()> {
+        let current = match Self::current_pose(resources)? {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        let (center, radius) = match resources.world.hierarchy_bounds(entity) {
+            Some(bounds) => (bounds.center(), bounds.half_extents().norm()),
+            None => (
+                resources.world.entity_global_transform(entity)?.translation,
+                1.0,
+            ),
+        };
+        let camera = resources.world.active_camera()?;
+        let half_fov = match &resources
+            .world
+            .ecs
+            .entry_ref(camera)?
+            .get_component::<Camera>()?
+            .projection
+        {
+            Projection::Perspective(perspective) => perspective.y_fov_rad / 2.0,
+            Projection::Orthographic(_) => 45_f32.to_radians(),
+        };
+        let distance = radius.max(0.1) * Self::FRAMING_MARGIN / half_fov.sin();
+        let forward = resources.world.entity_global_transform(camera)?.forward();
+        let pose = CameraPose::looking(
+            &current.orientation,
+            center - forward * distance,
+            forward,
+            distance,
+        );
+        self.move_to(pose, resources)
+    }
+
+    // Moves the camera to look through the entity, such as a game camera being set up
+    pub fn align_view_to_entity(&mut self, entity: Entity, resources: &Resources) -> Result<()> {
+        let current = match Self::current_pose(resources)? {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        let transform = resources.world.entity_global_transform(entity)?;
+        let pose = CameraPose::looking(
+            &current.orientation,
+            transform.translation,
+            transform.forward(),
+            current.orientation.radius,
+        );
+        self.move_to(pose, resources)
+    }
+
+    // The local transform that places the entity where the camera is, facing the same way.
+    // Returns the transforms before and after, for the edit to be recorded.
+    pub fn align_entity_to_view(
+        entity: Entity,
+        resources: &Resources,
+    ) -> Result<(Transform, Transform)> {
+        let camera = resources.world.active_camera()?;
+        let view = resources.world.entity_global_transform(camera)?;
+        let mut global = resources.world.entity_global_transform(entity)?;
+        global.translation = view.translation;
+        global.rotation = view.rotation;
+        let before = *resources
+            .world
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Transform>()?;
+        let after = resources
+            .world
+            .local_transform_from_global(entity, &global.matrix())?;
+        Ok((before, after))
+    }
+
+    pub fn update(&mut self, resources: &mut Resources) -> Result<()> {
+        let transition = match self.transition.as_mut() {
+            Some(transition) => transition,
+            None => return Ok(()),
+        };
+        transition.elapsed += resources.system.delta_time as f32;
+        let progress = (transition.elapsed / Self::TRANSITION_SECONDS).min(1.0);
+        let eased = progress * progress * (3.0 - 2.0 * progress);
+        let pose = transition.from.lerp(&transition.to, eased);
+        if progress >= 1.0 {
+            self.transition = None;
+        }
+        Self::apply_pose(&pose, resources)
+    }
+
+    fn move_to(&mut self, to: CameraPose, resources: &Resources) -> Result<()> {
+        if let Some(from) = Self::current_pose(resources)? {
+            self.transition = Some(CameraTransition {
+                from,
+                to,
+                elapsed: 0.0,
+            });
+        }
+        Ok(())
+    }
+
+    // Only orbit and fly cameras have a pose the editor can move
+    fn current_pose(resources: &Resources) -> Result<Option<CameraPose>> {
+        let camera = resources.world.active_camera()?;
+        let entry = resources.world.ecs.entry_ref(camera)?;
+        let pose = match entry.get_component::<CameraController>() {
+            Ok(CameraController::Orbit(orbit)) => Some(CameraPose {
+                orientation: orbit.orientation.clone(),
+                translation: orbit.orientation.position(),
+            }),
+            Ok(CameraController::Fly(fly)) => Some(CameraPose {
+                orientation: fly.orientation.clone(),
+                translation: entry.get_component::<Transform>()?.translation,
+            }),
+            _ => None,
+        };
+        Ok(pose)
+    }
+
+    fn apply_pose(pose: &CameraPose, resources: &mut Resources) -> Result<()> {
+        let camera = resources.world.active_camera()?;
+        let controller = {
+            let mut entry = resources
+                .world
+                .ecs
+                .entry_mut(camera)
+                .context("Failed to find the editor camera")?;
+            let mut controller = entry.get_component::<CameraController>()?.clone();
+            match &mut controller {
+                CameraController::Orbit(orbit) => orbit.orientation = pose.orientation.clone(),
+                CameraController::Fly(fly) => {
+                    fly.orientation = pose.orientation.clone();
+                    entry.get_component_mut::<Transform>()?.translation = pose.translation;
+                }
+                CameraController::Follow(_) => return Ok(()),
+            }
+            controller
+        };
+        set_camera_controller(resources.world, camera, controller)
+    }
+}
@@ -0,0 +1,334 @@
+use anyhow::{bail, Context, Result};
+use dragonglass::{app::Resources, gui::egui::Ui};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+use winit::event::VirtualKeyCode;
+
+pub type CommandAction<T> = Box<dyn FnMut(&mut T, &mut Resources) -> Result<()>>;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub fn from_resources(resources: &Resources) -> Self {
+        let input = &resources.input;
+        Self {
+            ctrl: input.is_key_pressed(VirtualKeyCode::LControl)
+                || input.is_key_pressed(VirtualKeyCode::RControl),
+            shift: input.is_key_pressed(VirtualKeyCode::LShift)
+                || input.is_key_pressed(VirtualKeyCode::RShift),
+            alt: input.is_key_pressed(VirtualKeyCode::LAlt)
+                || input.is_key_pressed(VirtualKeyCode::RAlt),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Shortcut {
+    pub key: VirtualKeyCode,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+}
+
+impl Shortcut {
+    pub fn new(key: VirtualKeyCode) -> Self {
+        Self {
+            key,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    pub fn ctrl(mut self) -> Self {
+        self.modifiers.ctrl = true;
+        self
+    }
+
+    pub fn shift(mut self) -> Self {
+        self.modifiers.shift = true;
+        self
+    }
+
+    pub fn alt(mut self) -> Self {
+        self.modifiers.alt = true;
+        self
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct CommandDescriptor {
+    pub id: String,
+    pub label: String,
+    pub menu: Option<String>,
+    pub toolbar: bool,
+    pub shortcut: Option<Shortcut>,
+}
+
+impl CommandDescriptor {
+    pub fn new(id: &str, label: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn menu(mut self, menu: &str) -> Self {
+        self.menu = Some(menu.to_string());
+        self
+    }
+
+    pub fn toolbar(mut self) -> Self {
+        self.toolbar = true;
+        self
+    }
+
+    pub fn shortcut(mut self, shortcut: Shortcut) -> Self {
+        self.shortcut = Some(shortcut);
+        self
+    }
+}
+
+struct Command<T> {
+    descriptor: CommandDescriptor,
+    binding: Option<Shortcut>,
+    action: CommandAction<T>,
+}
+
+// Commands are kept in registration order so menus are stable between frames
+pub struct CommandRegistry<T> {
+    commands: Vec<Command<T>>,
+    menus: Vec<String>,
+}
+
+impl<T> Default for CommandRegistry<T> {
+    fn default() -> Self {
+        Self {
+            commands: Vec::new(),
+            menus: Vec::new(),
+        }
+    }
+}
+
+impl<T> CommandRegistry<T> {
+    pub const BINDINGS_FILE: &'static str = "editor_shortcuts.toml";
+
+    pub fn register(
+        &mut self,
+        descriptor: CommandDescriptor,
+        action: impl FnMut(&mut T, &mut Resources) -> Result<()> + 'static,
+    ) -> Result<()> {
+        if self.index_of(&descriptor.id).is_some() {
+            bail!(
+                "A command with the id '{}' has already been registered!",
+                descriptor.id
+            );
+        }
+
+        let mut binding = descriptor.shortcut;
+        if let Some(shortcut) = binding {
+            if let Some(existing) = self.command_bound_to(&shortcut) {
+                warn!(
+                    "Shortcut '{}' for command '{}' conflicts with command '{}' and was left unbound",
+                    shortcut, descriptor.id, existing
+                );
+                binding = None;
+            }
+        }
+
+        if let Some(menu) = descriptor.menu.as_ref() {
+            if !self.menus.contains(menu) {
+                self.menus.push(menu.to_string());
+            }
+        }
+
+        self.commands.push(Command {
+            descriptor,
+            binding,
+            action: Box::new(action),
+        });
+
+        Ok(())
+    }
+
+    pub fn bind(&mut self, id: &str, shortcut: Option<Shortcut>) -> Result<()> {
+        if let Some(shortcut) = shortcut {
+            if let Some(existing) = self.command_bound_to(&shortcut) {
+                if existing != id {
+                    bail!(
+                        "Shortcut '{}' is already bound to command '{}'",
+                        shortcut,
+                        existing
+                    );
+                }
+            }
+        }
+        let index = self
+            .index_of(id)
+            .context(format!("No command with the id '{}' exists", id))?;
+        self.commands[index].binding = shortcut;
+        Ok(())
+    }
+
+    pub fn binding(&self, id: &str) -> Option<Shortcut> {
+        self.index_of(id)
+            .and_then(|index| self.commands[index].binding)
+    }
+
+    pub fn command_bound_to(&self, shortcut: &Shortcut) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|command| command.binding.as_ref() == Some(shortcut))
+            .map(|command| command.descriptor.id.as_str())
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = (&CommandDescriptor, Option<Shortcut>)> {
+        self.commands
+            .iter()
+            .map(|command| (&command.descriptor, command.binding))
+    }
+
+    pub fn reset_bindings(&mut self) {
+        for index in 0..self.commands.len() {
+            self.commands[index].binding = None;
+        }
+        for index in 0..self.commands.len() {
+            let shortcut = self.commands[index].descriptor.shortcut;
+            let conflicts = shortcut
+                .map(|shortcut| self.command_bound_to(&shortcut).is_some())
+                .unwrap_or_default();
+            if !conflicts {
+                self.commands[index].binding = shortcut;
+            }
+        }
+    }
+
+    pub fn execute(&mut self, id: &str, target: &mut T, resources: &mut Resources) -> Result<()> {
+        let index = self
+            .index_of(id)
+            .context(format!("No command with the id '{}' exists", id))?;
+        (self.commands[index].action)(target, resources)
+    }
+
+    pub fn find_shortcut(&self, key: VirtualKeyCode, modifiers: Modifiers) -> Option<String> {
+        let shortcut = Shortcut { key, modifiers };
+        self.command_bound_to(&shortcut).map(str::to_string)
+    }
+
+    // Returns the ids of any commands that were triggered from the menus
+    pub fn render_menus(&self, ui: &mut Ui) -> Vec<String> {
+        let mut triggered = Vec::new();
+        for menu in self.menus.iter() {
+            ui.menu_button(menu, |ui| {
+                for command in self.commands.iter() {
+                    if command.descriptor.menu.as_ref() != Some(menu) {
+                        continue;
+                    }
+                    let label = match command.binding {
+                        Some(shortcut) => format!("{} ({})", command.descriptor.label, shortcut),
+                        None => command.descriptor.label.to_string(),
+                    };
+                    if ui.button(label).clicked() {
+                        triggered.push(command.descriptor.id.to_string());
+                        ui.close_menu();
+                    }
+                }
+            });
+        }
+        triggered
+    }
+
+    pub fn render_toolbar(&self, ui: &mut Ui) -> Vec<String> {
+        let mut triggered = Vec::new();
+        ui.horizontal(|ui| {
+            for command in self.commands.iter().filter(|c| c.descriptor.toolbar) {
+                let mut response = ui.button(&command.descriptor.label);
+                if let Some(shortcut) = command.binding {
+                    response = response.on_hover_text(shortcut.to_string());
+                }
+                if response.clicked() {
+                    triggered.push(command.descriptor.id.to_string());
+                }
+            }
+        });
+        triggered
+    }
+
+    // Unbound commands are saved too, so they stay unbound instead of getting their defaults back
+    pub fn save_bindings(&self, path: impl AsRef<Path>) -> Result<()> {
+        let bindings = self
+            .commands
+            .iter()
+            .map(|command| {
+                let binding = CommandBinding {
+                    shortcut: command.binding,
+                };
+                (command.descriptor.id.to_string(), binding)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let contents = toml::to_string_pretty(&ShortcutBindings { bindings })?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    // Persisted bindings override the defaults, conflicting entries are skipped
+    pub fn load_bindings(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(());
+        }
+        let contents = fs::read_to_string(path)?;
+        let ShortcutBindings { bindings } = toml::from_str(&contents)?;
+
+        for command in self.commands.iter_mut() {
+            if bindings.contains_key(&command.descriptor.id) {
+                command.binding = None;
+            }
+        }
+
+        for (id, binding) in bindings.into_iter() {
+            if let Err(error) = self.bind(&id, binding.shortcut) {
+                warn!("Skipping persisted shortcut for '{}': {}", id, error);
+            }
+        }
+
+        info!("Loaded editor shortcuts from {}", path.display());
+        Ok(())
+    }
+
+    fn index_of(&self, id: &str) -> Option<usize> {
+        self.commands
+            .iter()
+            .position(|command| command.descriptor.id == id)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ShortcutBindings {
+    bindings: BTreeMap<String, CommandBinding>,
+}
+
+// A command without a shortcut was unbound by the user
+#[derive(Default, Serialize, Deserialize)]
+struct CommandBinding {
+    shortcut: Option<Shortcut>,
+}
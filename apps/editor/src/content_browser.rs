@@ -0,0 +1,217 @@
+use anyhow::Result;
+use dragonglass::{
+    gui::egui::{self, CtxRef, Id, ScrollArea, SelectableLabel, Sense, Ui},
+    world::{is_equirectangular_image, MODEL_EXTENSIONS},
+};
+use log::warn;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContentKind {
+    Folder,
+    Model,
+    Texture,
+    Hdr,
+    Level,
+    Other,
+}
+
+impl ContentKind {
+    pub fn from_path(path: &Path) -> Self {
+        if path.is_dir() {
+            return Self::Folder;
+        }
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some(extension) if MODEL_EXTENSIONS.contains(&extension) => Self::Model,
+            // Panoramas are told apart from regular textures by their aspect ratio
+            Some("png") | Some("jpg") | Some("jpeg") if is_equirectangular_image(path) => Self::Hdr,
+            Some("png") | Some("jpg") | Some("jpeg") | Some("tga") | Some("bmp") => Self::Texture,
+            Some("hdr") | Some("ktx2") => Self::Hdr,
+            Some("dga") => Self::Level,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::Folder => "📁",
+            Self::Model => "📦",
+            Self::Texture => "🖼",
+            Self::Hdr => "🌄",
+            Self::Level => "🗺",
+            Self::Other => "📄",
+        }
+    }
+
+    // Whether the editor can load the file into the viewport
+    pub fn is_loadable(&self) -> bool {
+        matches!(self, Self::Model | Self::Hdr | Self::Level)
+    }
+}
+
+pub struct ContentEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub kind: ContentKind,
+}
+
+pub struct ContentBrowser {
+    pub root: PathBuf,
+    current: PathBuf,
+    entries: Vec<ContentEntry>,
+    selected: Option<PathBuf>,
+    dragging: Option<PathBuf>,
+    stale: bool,
+}
+
+impl Default for ContentBrowser {
+    fn default() -> Self {
+        Self::new("assets")
+    }
+}
+
+impl ContentBrowser {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            current: root.clone(),
+            root,
+            entries: Vec::new(),
+            selected: None,
+            dragging: None,
+            stale: true,
+        }
+    }
+
+    pub fn open_directory(&mut self, path: impl Into<PathBuf>) {
+        self.current = path.into();
+        self.selected = None;
+        self.stale = true;
+    }
+
+    pub fn refresh(&mut self) -> Result<()> {
+        self.stale = false;
+        self.entries.clear();
+        for entry in fs::read_dir(&self.current)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let kind = ContentKind::from_path(&path);
+            self.entries.push(ContentEntry { path, name, kind });
+        }
+        // Folders first, then files alphabetically
+        self.entries.sort_by(|a, b| {
+            (a.kind != ContentKind::Folder, a.name.to_lowercase())
+                .cmp(&(b.kind != ContentKind::Folder, b.name.to_lowercase()))
+        });
+        Ok(())
+    }
+
+    // Returns a file the user asked to load, either by double-clicking it
+    // or by dragging it out of the browser and dropping it on the viewport
+    pub fn render(&mut self, ui: &mut Ui) -> Option<PathBuf> {
+        if self.stale {
+            if let Err(error) = self.refresh() {
+                warn!(
+                    "Failed to scan directory '{}': {}",
+                    self.current.display(),
+                    error
+                );
+            }
+        }
+
+        let mut navigate_to = None;
+        let mut load = None;
+
+        ui.horizontal(|ui| {
+            let parent = self
+                .current
+                .parent()
+                .filter(|_| self.current != self.root)
+                .map(Path::to_path_buf);
+            if ui
+                .add_enabled(parent.is_some(), egui::Button::new("⬆"))
+                .clicked()
+            {
+                navigate_to = parent;
+            }
+            if ui.button("⟳").on_hover_text("Refresh").clicked() {
+                self.stale = true;
+            }
+            ui.label(self.current.display().to_string());
+        });
+        ui.separator();
+
+        ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for entry in self.entries.iter() {
+                    let selected = self.selected.as_ref() == Some(&entry.path);
+                    let text = format!("{} {}", entry.kind.icon(), entry.name);
+                    let response = ui
+                        .add(SelectableLabel::new(selected, text))
+                        .interact(Sense::click_and_drag());
+
+                    if response.clicked() {
+                        self.selected = Some(entry.path.clone());
+                    }
+
+                    if response.double_clicked() {
+                        match entry.kind {
+                            ContentKind::Folder => navigate_to = Some(entry.path.clone()),
+                            kind if kind.is_loadable() => load = Some(entry.path.clone()),
+                            _ => warn!("'{}' can't be loaded into the scene", entry.name),
+                        }
+                    }
+
+                    if response.drag_started() && entry.kind.is_loadable() {
+                        self.dragging = Some(entry.path.clone());
+                    }
+                }
+            });
+        });
+
+        if let Some(path) = navigate_to {
+            self.open_directory(path);
+        }
+
+        load
+    }
+
+    // Call after all panels are laid out, so the remaining space is the viewport
+    pub fn update_drag(&mut self, context: &CtxRef) -> Option<PathBuf> {
+        let path = self.dragging.as_ref()?;
+
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        egui::show_tooltip_text(context, Id::new("content_browser_drag"), name);
+
+        let (released, position) = {
+            let input = context.input();
+            (input.pointer.any_released(), input.pointer.interact_pos())
+        };
+        if !released {
+            return None;
+        }
+        let dropped_on_viewport = position
+            .map(|position| context.available_rect().contains(position))
+            .unwrap_or(false);
+
+        let path = self.dragging.take()?;
+        if dropped_on_viewport {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use dragonglass::{
+    app::{CVarValue, CVars},
+    gui::egui::{self, CtxRef, DragValue, Grid, ScrollArea, Ui},
+};
+
+// Lists the registered console variables with a widget to edit each one
+#[derive(Default)]
+pub struct CVarWindow {
+    pub open: bool,
+    filter: String,
+}
+
+impl CVarWindow {
+    pub fn render(&mut self, context: &CtxRef, cvars: &mut CVars) -> Result<()> {
+        let mut open = self.open;
+        let mut edits = Vec::new();
+        egui::Window::new("Console Variables")
+            .open(&mut open)
+            .default_size([400.0, 300.0])
+            .show(context, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Filter");
+                    ui.text_edit_singleline(&mut self.filter);
+                });
+                ui.separator();
+
+                let filter = self.filter.to_lowercase();
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("cvars_grid")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            let vars = cvars
+                                .vars()
+                                .filter(|(name, _)| name.to_lowercase().contains(&filter));
+                            for (name, var) in vars {
+                                let label = if var.is_persistent() {
+                                    format!("{} *", name)
+                                } else {
+                                    name.to_string()
+                                };
+                                ui.label(label).on_hover_text(&var.help);
+                                let mut value = var.value().clone();
+                                if cvar_widget(ui, &mut value) {
+                                    edits.push((name.to_string(), value));
+                                }
+                                let is_default = var.value() == var.default_value();
+                                if ui
+                                    .add_enabled(!is_default, egui::Button::new("Reset"))
+                                    .clicked()
+                                {
+                                    edits.push((name.to_string(), var.default_value().clone()));
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+                ui.separator();
+                ui.label("Variables marked with * are saved with the settings");
+            });
+        self.open = open;
+
+        for (name, value) in edits.into_iter() {
+            cvars.set_value(&name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// Returns true when the value was edited
+fn cvar_widget(ui: &mut Ui, value: &mut CVarValue) -> bool {
+    match value {
+        CVarValue::Bool(value) => ui.checkbox(value, "").changed(),
+        CVarValue::Int(value) => ui.add(DragValue::new(value)).changed(),
+        CVarValue::Float(value) => ui.add(DragValue::new(value).speed(0.01)).changed(),
+        CVarValue::String(value) => ui.text_edit_singleline(value).changed(),
+    }
+}
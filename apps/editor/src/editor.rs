@@ -1,156 +1,1194 @@
 use anyhow::{Context, Result};
 use dragonglass::{
-    app::{App, MouseOrbit, Resources},
+    app::{
+        load_default_font, set_camera_controller, AddShapeCommand, AddTerrainCommand, App,
+        CameraController, CameraControllerKind, Command, Console, DeleteEntityCommand, Handle, Hdr,
+        History, InstantiateModelCommand, LoadState, MaterialCommand, ModelReloaded, PasteCommand,
+        Resources, TransformCommand, UnlinkMeshCommand, ViewWindowDescriptor,
+    },
+    config::{VertexLayout, WindowMode},
     gui::{
         egui::{self, global_dark_light_mode_switch, menu, LayerId, SelectableLabel, Slider, Ui},
         egui_gizmo::GizmoMode,
-        GizmoWidget,
+        paint_axis_indicator, GizmoWidget,
     },
+    render::PanoramaLayout,
+    scripting::ScriptSystem,
     world::{
-        legion::Entity,
-        load_gltf,
+        batch_static_meshes, compact_world, fragmentation, is_environment_file, is_heightmap_file,
+        is_model_file,
+        legion::{component, Entity},
         petgraph::{graph::NodeIndex, EdgeDirection::Outgoing},
         rapier3d::{geometry::InteractionGroups, prelude::RigidBodyType},
-        register_component, Ecs, EntityStore, IntoQuery, MeshRender, Name, RigidBody, SceneGraph,
-        Transform,
+        register_component, Camera, Clipboard, Ecs, EntityStore, Heightmap, Hovered, IntoQuery,
+        Layers, Material, MeshRender, ModelAsset, Name, Ragdoll, RagdollSettings, RigidBody,
+        SceneGraph, Selected, Shape, SimWorld, Terrain, Transform, World, ENVIRONMENT_EXTENSIONS,
+        HEIGHTMAP_EXTENSIONS, MODEL_EXTENSIONS,
     },
 };
 use log::{info, warn};
 use nalgebra_glm as glm;
 use rfd::FileDialog;
-use serde::{Deserialize, Serialize};
 use std::path::Path;
 use winit::event::{ElementState, MouseButton, VirtualKeyCode};
 
-use crate::widgets::{rotation_widget, scale_widget, translation_widget};
+use crate::{
+    calibration::CalibrationWindow,
+    camera_tools::CameraTools,
+    commands::{CommandDescriptor, CommandRegistry, Modifiers, Shortcut},
+    content_browser::ContentBrowser,
+    cvars::CVarWindow,
+    layout::EditorLayout,
+    physics_inspector::PhysicsInspector,
+    play::PlayMode,
+    profiler::ProfilerWindow,
+    spline_editor::SplineEditor,
+    tabs::{SceneTabs, TabAction},
+    timeline::AnimationTimeline,
+    vertex_paint::VertexPainter,
+    widgets::{
+        instance_widget, material_widget, reflection_probe_widget, rotation_widget, scale_widget,
+        script_widget, shadow_widget, static_widget, time_scale_widget, translation_widget,
+    },
+};
+
+const EDITOR_COLLISION_GROUP: InteractionGroups = Layers::DEFAULT.collision_groups(Layers::DEFAULT);
 
-const EDITOR_COLLISION_GROUP: InteractionGroups = InteractionGroups::new(0b1, 0b1);
+// How often the editor checks whether the world's storage should be compacted
+const COMPACTION_INTERVAL_MS: u32 = 30_000;
+const COMPACTION_THRESHOLD: f32 = 0.25;
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct Selected;
+// The size of each cubemap face rendered for a panorama capture
+const PANORAMA_FACE_SIZE: u32 = 1024;
+
+const GAME_PREVIEW_SIZE: [u32; 2] = [1280, 720];
+
+struct SelectionCommand {
+    previous: Option<Entity>,
+    next: Option<Entity>,
+}
+
+impl SelectionCommand {
+    fn select(world: &mut World, entity: Option<Entity>) -> Result<()> {
+        let mut query = <(Entity, &Selected)>::query();
+        let selected = query.iter(&world.ecs).map(|(e, _)| *e).collect::<Vec<_>>();
+        for entity in selected.into_iter() {
+            if let Some(mut entry) = world.ecs.entry(entity) {
+                entry.remove_component::<Selected>();
+            }
+        }
+
+        if let Some(entity) = entity {
+            let mut entry = world.ecs.entry(entity).context("Failed to find entity!")?;
+            entry.add_component(Selected::default());
+        }
+        Ok(())
+    }
+}
+
+impl Command for SelectionCommand {
+    fn name(&self) -> &str {
+        "Selection"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        Self::select(world, self.next)
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        Self::select(world, self.previous)
+    }
+
+    fn remap_entity(&mut self, old: Entity, new: Entity) {
+        if self.previous == Some(old) {
+            self.previous = Some(new);
+        }
+        if self.next == Some(old) {
+            self.next = Some(new);
+        }
+    }
+}
 
 pub struct Editor {
-    camera: MouseOrbit,
     selected_entity: Option<Entity>,
     gizmo: GizmoWidget,
+    commands: CommandRegistry<Editor>,
+    pending_commands: Vec<String>,
+    // Set by the save shortcuts command, since the registry is moved out while commands run
+    save_shortcuts_requested: bool,
+    pending_imports: Vec<Handle<ModelAsset>>,
+    pending_skyboxes: Vec<Handle<Hdr>>,
+    tabs: SceneTabs,
+    clipboard: Clipboard,
+    last_compaction_check: u32,
+    calibration: CalibrationWindow,
+    physics_inspector: PhysicsInspector,
+    profiler: ProfilerWindow,
+    console: Console,
+    cvar_window: CVarWindow,
+    timeline: AnimationTimeline,
+    vertex_painter: VertexPainter,
+    spline_editor: SplineEditor,
+    // The anisotropy being dragged to, which is only applied once the slider is let go
+    anisotropy_edit: Option<f32>,
+    content_browser: ContentBrowser,
+    play_mode: PlayMode,
+    scripts: ScriptSystem,
+    layout: EditorLayout,
+    history: History,
+    // The entity and its transform from before an in-progress transform edit
+    transform_edit: Option<(Entity, Transform)>,
+    // The index of the material and how it was before an in-progress material edit
+    material_edit: Option<(usize, Material)>,
+    camera_tools: CameraTools,
+    show_grid: bool,
 }
 
 impl Default for Editor {
     fn default() -> Self {
         Self {
-            camera: MouseOrbit::default(),
             selected_entity: None,
             gizmo: GizmoWidget::new(),
+            commands: CommandRegistry::default(),
+            pending_commands: Vec::new(),
+            save_shortcuts_requested: false,
+            pending_imports: Vec::new(),
+            pending_skyboxes: Vec::new(),
+            tabs: SceneTabs::default(),
+            clipboard: Clipboard::default(),
+            last_compaction_check: 0,
+            calibration: CalibrationWindow::default(),
+            physics_inspector: PhysicsInspector::default(),
+            profiler: ProfilerWindow::default(),
+            console: Console::default(),
+            cvar_window: CVarWindow::default(),
+            timeline: AnimationTimeline::default(),
+            vertex_painter: VertexPainter::default(),
+            spline_editor: SplineEditor::default(),
+            anisotropy_edit: None,
+            content_browser: ContentBrowser::default(),
+            play_mode: PlayMode::default(),
+            scripts: ScriptSystem::default(),
+            layout: EditorLayout::default(),
+            history: History::default(),
+            transform_edit: None,
+            material_edit: None,
+            camera_tools: CameraTools::default(),
+            show_grid: true,
         }
     }
 }
 
 impl Editor {
-    fn load_hdr(path: impl AsRef<Path>, resources: &mut Resources) -> Result<()> {
-        // FIXME: We are loading the hdr even if it's already loaded here
-        resources.world.load_hdr(path)?;
-        resources.world.scene.skybox = Some(resources.world.hdr_textures.len() - 1);
-
-        // FIXME: Don't reload entire scene whenever something is added
-        match resources.renderer.load_world(resources.world) {
-            Ok(_) => {
-                info!("Reloaded gltf world");
-            }
-            Err(error) => {
-                warn!("Failed to load gltf world: {}", error);
-            }
+    // Scenes are lit by their ambient light until this finishes loading
+    const DEFAULT_SKYBOX: &'static str = "assets/skyboxes/desert.hdr";
+
+    pub fn register_command(
+        &mut self,
+        descriptor: CommandDescriptor,
+        action: impl FnMut(&mut Editor, &mut Resources) -> Result<()> + 'static,
+    ) -> Result<()> {
+        self.commands.register(descriptor, action)
+    }
+
+    fn register_default_commands(&mut self) -> Result<()> {
+        self.register_command(
+            CommandDescriptor::new("edit.undo", "Undo")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::Z).ctrl()),
+            |editor, resources| editor.undo(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.redo", "Redo")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::Y).ctrl()),
+            |editor, resources| editor.redo(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("scene.new", "New Scene")
+                .menu("Scene")
+                .shortcut(Shortcut::new(VirtualKeyCode::N).ctrl()),
+            |editor, resources| editor.new_scene(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("scene.close", "Close Scene")
+                .menu("Scene")
+                .shortcut(Shortcut::new(VirtualKeyCode::W).ctrl()),
+            |editor, resources| {
+                let index = editor.tabs.active();
+                editor.close_scene(index, resources)
+            },
+        )?;
+
+        for shape in Shape::ALL.iter().copied() {
+            self.register_command(
+                CommandDescriptor::new(
+                    &format!("add.{}", shape.name().to_lowercase()),
+                    shape.name(),
+                )
+                .menu("Add"),
+                move |editor, resources| editor.add_shape(shape, resources),
+            )?;
+        }
+
+        self.register_command(
+            CommandDescriptor::new("edit.copy", "Copy")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::C).ctrl()),
+            |editor, resources| editor.copy_selected(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.paste", "Paste")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::V).ctrl()),
+            |editor, resources| editor.paste(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.paste_linked", "Paste Linked")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::V).ctrl().shift()),
+            |editor, resources| editor.paste_linked(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.duplicate_linked", "Duplicate Linked")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::D).alt()),
+            |editor, resources| editor.duplicate_linked(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.unlink", "Unlink Instance").menu("Edit"),
+            |editor, resources| editor.unlink_selected(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.delete", "Delete")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::Delete)),
+            |editor, resources| editor.delete_selected(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.deselect_all", "Deselect All")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::Escape)),
+            |editor, resources| editor.deselect_all(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("edit.clear_world", "Clear World")
+                .menu("Edit")
+                .shortcut(Shortcut::new(VirtualKeyCode::C)),
+            |editor, resources| {
+                editor.stop(resources)?;
+                resources.world.clear()?;
+                editor.selected_entity = None;
+                editor.history.clear();
+                if let Err(error) = resources.renderer.load_world(resources.world) {
+                    warn!("Failed to load gltf world: {}", error);
+                }
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("play.play", "Play")
+                .menu("Play")
+                .toolbar()
+                .shortcut(Shortcut::new(VirtualKeyCode::F5)),
+            |editor, resources| editor.play(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("play.pause", "Pause")
+                .menu("Play")
+                .toolbar()
+                .shortcut(Shortcut::new(VirtualKeyCode::F6)),
+            |editor, resources| {
+                editor.play_mode.pause(resources.world);
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("play.step", "Step")
+                .menu("Play")
+                .toolbar()
+                .shortcut(Shortcut::new(VirtualKeyCode::F7)),
+            |editor, resources| {
+                editor.play_mode.step(resources.world);
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("play.stop", "Stop")
+                .menu("Play")
+                .toolbar()
+                .shortcut(Shortcut::new(VirtualKeyCode::F5).shift()),
+            |editor, resources| editor.stop(resources),
+        )?;
+
+        let gizmo_modes = [
+            (
+                "translate",
+                "Translate",
+                VirtualKeyCode::T,
+                GizmoMode::Translate,
+            ),
+            ("rotate", "Rotate", VirtualKeyCode::R, GizmoMode::Rotate),
+            ("scale", "Scale", VirtualKeyCode::S, GizmoMode::Scale),
+        ];
+        for (id, label, key, mode) in gizmo_modes.iter().copied() {
+            self.register_command(
+                CommandDescriptor::new(&format!("tools.{}", id), label)
+                    .menu("Tools")
+                    .toolbar()
+                    .shortcut(Shortcut::new(key)),
+                move |editor, _| {
+                    editor.gizmo.mode = mode;
+                    Ok(())
+                },
+            )?;
+        }
+
+        let camera_controllers = [
+            ("orbit", "Orbit Camera", CameraControllerKind::Orbit),
+            ("fly", "Fly Camera", CameraControllerKind::Fly),
+        ];
+        for (id, label, kind) in camera_controllers.iter().copied() {
+            self.register_command(
+                CommandDescriptor::new(&format!("camera.{}", id), label).menu("Camera"),
+                move |_, resources| {
+                    let camera = resources.world.active_camera()?;
+                    set_camera_controller(resources.world, camera, CameraController::from(kind))
+                },
+            )?;
+        }
+
+        self.register_command(
+            CommandDescriptor::new("camera.frame_selected", "Frame Selected")
+                .menu("Camera")
+                .shortcut(Shortcut::new(VirtualKeyCode::F)),
+            |editor, resources| match editor.selected_entity {
+                Some(entity) => editor.camera_tools.frame_entity(entity, resources),
+                None => Ok(()),
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("camera.align_view_to_selected", "Align View to Selected")
+                .menu("Camera"),
+            |editor, resources| match editor.selected_entity {
+                Some(entity) => editor.camera_tools.align_view_to_entity(entity, resources),
+                None => Ok(()),
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("camera.align_selected_to_view", "Align Selected to View")
+                .menu("Camera"),
+            |editor, resources| editor.align_selected_to_view(resources),
+        )?;
+
+        let bookmark_keys = [
+            VirtualKeyCode::Key1,
+            VirtualKeyCode::Key2,
+            VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5,
+            VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7,
+            VirtualKeyCode::Key8,
+            VirtualKeyCode::Key9,
+        ];
+        for (slot, key) in bookmark_keys.iter().copied().enumerate() {
+            self.register_command(
+                CommandDescriptor::new(
+                    &format!("camera.store_bookmark_{}", slot + 1),
+                    &format!("Store Camera Bookmark {}", slot + 1),
+                )
+                .shortcut(Shortcut::new(key).ctrl()),
+                move |editor, resources| editor.camera_tools.store_bookmark(slot, resources),
+            )?;
+            self.register_command(
+                CommandDescriptor::new(
+                    &format!("camera.recall_bookmark_{}", slot + 1),
+                    &format!("Recall Camera Bookmark {}", slot + 1),
+                )
+                .shortcut(Shortcut::new(key)),
+                move |editor, resources| editor.camera_tools.recall_bookmark(slot, resources),
+            )?;
         }
 
+        self.register_command(
+            CommandDescriptor::new("window.toggle_fullscreen", "Toggle Fullscreen")
+                .menu("Window")
+                .shortcut(Shortcut::new(VirtualKeyCode::F11)),
+            |_, resources| resources.toggle_fullscreen(),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("window.game_preview", "Toggle Game Preview").menu("Window"),
+            |editor, resources| editor.toggle_game_preview(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("window.toggle_skeletons", "Toggle Skeletons").menu("Window"),
+            |_, resources| {
+                let shown = resources.cvars.get::<bool>("debug.skeletons") == Some(true);
+                resources.cvars.set("debug.skeletons", !shown)
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("window.toggle_grid", "Toggle Grid")
+                .menu("Window")
+                .shortcut(Shortcut::new(VirtualKeyCode::G).ctrl()),
+            |editor, _| {
+                editor.show_grid = !editor.show_grid;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.compact", "Compact Memory").menu("Tools"),
+            |editor, resources| editor.compact_world(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.toggle_ragdoll", "Toggle Ragdoll").menu("Tools"),
+            |editor, resources| editor.toggle_ragdoll(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.toggle_hot_reload", "Toggle Asset Hot Reload")
+                .menu("Tools"),
+            |_, resources| {
+                let enabled = !resources.assets.hot_reload();
+                resources.assets.set_hot_reload(enabled);
+                info!(
+                    "Asset hot reload {}",
+                    if enabled { "enabled" } else { "disabled" }
+                );
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.batch_static", "Batch Static Meshes").menu("Tools"),
+            |editor, resources| editor.batch_static_meshes(resources),
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.bake_reflection_probes", "Bake Reflection Probes")
+                .menu("Tools"),
+            |_, resources| {
+                resources.renderer.bake_reflection_probes(resources.world)?;
+                info!("Baked reflection probes");
+                Ok(())
+            },
+        )?;
+
+        let panorama_layouts = [
+            (
+                "capture_panorama",
+                "Capture Panorama",
+                PanoramaLayout::Equirectangular,
+            ),
+            (
+                "capture_cubemap",
+                "Capture Cubemap",
+                PanoramaLayout::CubemapStrip,
+            ),
+        ];
+        for (id, label, layout) in panorama_layouts.iter().copied() {
+            self.register_command(
+                CommandDescriptor::new(&format!("tools.{}", id), label).menu("Tools"),
+                move |_, resources| Self::capture_panorama(resources, layout),
+            )?;
+        }
+
+        self.register_command(
+            CommandDescriptor::new("tools.input_calibration", "Input Calibration").menu("Tools"),
+            |editor, _| {
+                editor.calibration.open = !editor.calibration.open;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.physics_inspector", "Physics Inspector").menu("Tools"),
+            |editor, _| {
+                editor.physics_inspector.open = !editor.physics_inspector.open;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.profiler", "Profiler").menu("Tools"),
+            |editor, _| {
+                editor.profiler.open = !editor.profiler.open;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.console", "Console").menu("Tools"),
+            |editor, _| {
+                editor.console.toggle();
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.cvars", "Console Variables").menu("Tools"),
+            |editor, _| {
+                editor.cvar_window.open = !editor.cvar_window.open;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.animation_timeline", "Animation Timeline").menu("Tools"),
+            |editor, _| {
+                editor.timeline.open = !editor.timeline.open;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.vertex_paint", "Vertex Paint").menu("Tools"),
+            |editor, _| {
+                editor.vertex_painter.open = !editor.vertex_painter.open;
+                Ok(())
+            },
+        )?;
+
+        self.register_command(
+            CommandDescriptor::new("tools.save_shortcuts", "Save Shortcuts").menu("Tools"),
+            |editor, _| {
+                editor.save_shortcuts_requested = true;
+                Ok(())
+            },
+        )?;
+
         Ok(())
     }
 
-    pub fn select_entity(&mut self, entity: Entity, resources: &mut Resources) -> Result<()> {
-        let mut query = <(Entity, &Selected)>::query();
-        let already_selected = query
-            .iter(&resources.world.ecs)
-            .map(|(e, _)| *e)
-            .any(|e| e == entity);
-        if already_selected {
+    fn new_scene(&mut self, resources: &mut Resources) -> Result<()> {
+        let mut world = World::new()?;
+        world.add_default_light()?;
+        load_default_font(&mut world)?;
+        let index = self.tabs.add("", world, resources.worlds)?;
+        self.switch_scene(index, resources)?;
+        self.tabs.active_tab_mut().name = format!("Scene {}", index + 1);
+        Ok(())
+    }
+
+    fn switch_scene(&mut self, index: usize, resources: &mut Resources) -> Result<()> {
+        self.stop(resources)?;
+        self.tabs.switch(
+            index,
+            resources,
+            &mut self.selected_entity,
+            &mut self.history,
+        )
+    }
+
+    fn close_scene(&mut self, index: usize, resources: &mut Resources) -> Result<()> {
+        self.stop(resources)?;
+        if let Err(error) = self.tabs.close(
+            index,
+            resources,
+            &mut self.selected_entity,
+            &mut self.history,
+        ) {
+            warn!("Failed to close scene: {}", error);
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, resources: &mut Resources) -> Result<()> {
+        self.transform_edit = None;
+        self.material_edit = None;
+        self.play_mode.play(resources.world, &mut self.history)
+    }
+
+    // Restores the world from before play mode was entered
+    fn stop(&mut self, resources: &mut Resources) -> Result<()> {
+        if self.play_mode.stop(resources.world, &mut self.history)? {
+            self.scripts.reset();
+            self.on_history_changed(true, resources)?;
+        }
+        Ok(())
+    }
+
+    fn copy_selected(&mut self, resources: &mut Resources) -> Result<()> {
+        if let Some(entity) = self.selected_entity {
+            self.clipboard = Clipboard::copy(resources.world, entity)?;
+            info!("Copied entity: {:?}", entity);
+        }
+        Ok(())
+    }
+
+    fn paste(&mut self, resources: &mut Resources) -> Result<()> {
+        if self.clipboard.is_empty() {
             return Ok(());
         }
+        self.execute_paste(PasteCommand::new(self.clipboard.clone()), resources)
+    }
 
-        self.deselect_all(resources)?;
-        let mut entry = resources
+    fn paste_linked(&mut self, resources: &mut Resources) -> Result<()> {
+        if self.clipboard.is_empty() {
+            return Ok(());
+        }
+        self.execute_paste(PasteCommand::linked(self.clipboard.clone()), resources)
+    }
+
+    // Creates an instance of the selection that shares its meshes and materials,
+    // without replacing the contents of the clipboard
+    fn duplicate_linked(&mut self, resources: &mut Resources) -> Result<()> {
+        let entity = match self.selected_entity {
+            Some(entity) => entity,
+            None => return Ok(()),
+        };
+        let clipboard = Clipboard::copy(resources.world, entity)?;
+        self.execute_paste(PasteCommand::linked(clipboard), resources)
+    }
+
+    fn unlink_selected(&mut self, resources: &mut Resources) -> Result<()> {
+        let entity = match self.selected_entity {
+            Some(entity) => entity,
+            None => return Ok(()),
+        };
+        let is_instance = resources
             .world
             .ecs
-            .entry(entity)
-            .context("Failed to find entity")?;
-        entry.add_component(Selected::default());
-        self.selected_entity = Some(entity);
-        log::info!("Selected entity: {:?}", entity);
+            .entry_ref(entity)?
+            .get_component::<MeshRender>()
+            .map(|mesh_render| resources.world.mesh_instances(&mesh_render.name).len() > 1)
+            .unwrap_or_default();
+        if !is_instance {
+            return Ok(());
+        }
+        self.history
+            .execute(Box::new(UnlinkMeshCommand::new(entity)), resources.world)?;
+        Self::reload_world(resources)
+    }
+
+    fn execute_paste(
+        &mut self,
+        mut command: PasteCommand,
+        resources: &mut Resources,
+    ) -> Result<()> {
+        command.apply(resources.world)?;
+        let root = command.entities().first().copied();
+        self.history.record(Box::new(command));
+        Self::reload_world(resources)?;
+
+        if let Some(entity) = root {
+            self.select_entity(entity, resources)?;
+        }
         Ok(())
     }
 
-    pub fn deselect_all(&mut self, resources: &mut Resources) -> Result<()> {
+    // Spawns the shape where the active camera is looking
+    fn add_shape(&mut self, shape: Shape, resources: &mut Resources) -> Result<()> {
+        let transform = Transform {
+            translation: Self::camera_focus_point(resources)?,
+            ..Default::default()
+        };
+        let mut command = AddShapeCommand::new(shape, transform);
+        command.apply(resources.world)?;
+        let entity = command.entity();
+        self.history.record(Box::new(command));
+        // The renderer picks up the new mesh on its own, so only the collider used for picking is needed
+        Self::add_static_colliders(resources)?;
+
+        if let Some(entity) = entity {
+            self.select_entity(entity, resources)?;
+        }
+        Ok(())
+    }
+
+    // Heightmaps are small enough to decode right away, unlike the models imported in the background
+    fn add_terrain(&mut self, path: &Path, resources: &mut Resources) -> Result<()> {
+        let heightmap = Heightmap::from_file(path)?;
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("Terrain");
+        let terrain = Terrain::for_heightmap(&heightmap);
+        let mut command = AddTerrainCommand::new(name, heightmap, terrain, Transform::default());
+        command.apply(resources.world)?;
+        let root = command.entities().first().copied();
+        self.history.record(Box::new(command));
+
+        if let Some(root) = root {
+            self.select_entity(root, resources)?;
+        }
+        info!("Added terrain from heightmap: {}", path.display());
+        Ok(())
+    }
+
+    // The point an orbit camera orbits around, or a point a few units in front of any other camera
+    fn camera_focus_point(resources: &Resources) -> Result<glm::Vec3> {
+        let camera = resources.world.active_camera()?;
+        let entry = resources.world.ecs.entry_ref(camera)?;
+        if let Ok(CameraController::Orbit(orbit)) = entry.get_component::<CameraController>() {
+            return Ok(orbit.orientation.offset);
+        }
+        let transform = resources.world.entity_global_transform(camera)?;
+        Ok(transform.translation + transform.forward() * 5.0)
+    }
+
+    fn delete_selected(&mut self, resources: &mut Resources) -> Result<()> {
+        let entity = match self.selected_entity.take() {
+            Some(entity) => entity,
+            None => return Ok(()),
+        };
+        self.history
+            .execute(Box::new(DeleteEntityCommand::new(entity)), resources.world)?;
+        info!("Deleted entity: {:?}", entity);
+        resources.renderer.load_world(resources.world)?;
+        Ok(())
+    }
+
+    fn undo(&mut self, resources: &mut Resources) -> Result<()> {
+        let changes_geometry = match self.history.undo(resources.world)? {
+            Some(command) => {
+                info!("Undo: {}", command.name());
+                command.changes_geometry()
+            }
+            None => return Ok(()),
+        };
+        resources
+            .assets
+            .remap_instances(self.history.replaced_entities());
+        self.on_history_changed(changes_geometry, resources)
+    }
+
+    fn redo(&mut self, resources: &mut Resources) -> Result<()> {
+        let changes_geometry = match self.history.redo(resources.world)? {
+            Some(command) => {
+                info!("Redo: {}", command.name());
+                command.changes_geometry()
+            }
+            None => return Ok(()),
+        };
+        // Models instantiated again are tracked under their new entities, so reloads still patch them
+        resources
+            .assets
+            .remap_instances(self.history.replaced_entities());
+        self.on_history_changed(changes_geometry, resources)
+    }
+
+    fn on_history_changed(
+        &mut self,
+        changes_geometry: bool,
+        resources: &mut Resources,
+    ) -> Result<()> {
         let mut query = <(Entity, &Selected)>::query();
+        self.selected_entity = query.iter(&resources.world.ecs).map(|(e, _)| *e).next();
+        self.transform_edit = None;
+        self.material_edit = None;
+        if changes_geometry {
+            Self::reload_world(resources)?;
+        } else {
+            // Shapes added again by redoing need their colliders back
+            Self::add_static_colliders(resources)?;
+        }
+        Ok(())
+    }
 
-        let entities = query
-            .iter(&resources.world.ecs)
-            .map(|(e, _)| *e)
-            .collect::<Vec<_>>();
+    fn selected_transform(&self, resources: &Resources) -> Option<(Entity, Transform)> {
+        let entity = self.selected_entity?;
+        let entry = resources.world.ecs.entry_ref(entity).ok()?;
+        let transform = entry.get_component::<Transform>().ok()?;
+        Some((entity, *transform))
+    }
 
-        for entity in entities.into_iter() {
-            let mut entry = resources
+    // Continuous edits like gizmo drags are recorded as a single command once the pointer is released
+    fn track_transform_edit(
+        &mut self,
+        frame_start: Option<(Entity, Transform)>,
+        resources: &mut Resources,
+    ) {
+        if self.transform_edit.is_none() {
+            if let (Some((entity, before)), Some((current_entity, after))) =
+                (frame_start, self.selected_transform(resources))
+            {
+                if entity == current_entity && before != after {
+                    self.transform_edit = Some((entity, before));
+                }
+            }
+        }
+
+        if resources.gui.context().input().pointer.any_down() {
+            return;
+        }
+
+        if let Some((entity, before)) = self.transform_edit.take() {
+            let after = resources
                 .world
                 .ecs
-                .entry(entity)
-                .context("Failed to find entity!")?;
-            log::info!("Deselecting entity: {:?}", entity);
-            entry.remove_component::<Selected>();
+                .entry_ref(entity)
+                .ok()
+                .and_then(|entry| entry.get_component::<Transform>().ok().copied());
+            if let Some(after) = after {
+                if after != before {
+                    self.history
+                        .record(Box::new(TransformCommand::new(entity, before, after)));
+                }
+            }
+        }
+    }
+
+    fn paint_vertices(&mut self, resources: &mut Resources) -> Result<()> {
+        if !self.play_mode.is_editing() {
+            return Ok(());
+        }
+        if let Some(command) = self
+            .vertex_painter
+            .update(resources, self.selected_entity)?
+        {
+            self.history.record(Box::new(command));
         }
+        Ok(())
+    }
 
-        self.selected_entity = None;
+    // Moves the selection to the camera, such as to place a game camera where the editor is looking
+    fn align_selected_to_view(&mut self, resources: &mut Resources) -> Result<()> {
+        let entity = match self.selected_entity {
+            Some(entity) => entity,
+            None => return Ok(()),
+        };
+        let (before, after) = CameraTools::align_entity_to_view(entity, resources)?;
+        self.history.execute(
+            Box::new(TransformCommand::new(entity, before, after)),
+            resources.world,
+        )
+    }
 
+    // Shows the scene from its own camera in a separate window, which can be moved to another monitor
+    fn toggle_game_preview(&mut self, resources: &mut Resources) -> Result<()> {
+        let mut query = <(Entity, &Camera)>::query();
+        let camera = query
+            .iter(&resources.world.ecs)
+            .find(|(_, camera)| camera.name != SimWorld::MAIN_CAMERA_NAME)
+            .map(|(entity, _)| *entity);
+        let camera = match camera {
+            Some(camera) => camera,
+            None => {
+                warn!("The scene has no camera of its own to preview");
+                return Ok(());
+            }
+        };
+        if resources.views.is_open(camera) {
+            return resources.views.close(camera, resources.renderer);
+        }
+        resources.views.open(ViewWindowDescriptor {
+            title: "Game Preview".to_string(),
+            width: GAME_PREVIEW_SIZE[0],
+            height: GAME_PREVIEW_SIZE[1],
+            camera,
+        });
         Ok(())
     }
 
-    pub fn load_world_from_file(&self, path: &Path, resources: &mut Resources) -> Result<()> {
+    // Drops the selected skinned entity as a ragdoll, or has it get back up if it already is one
+    fn toggle_ragdoll(&mut self, resources: &mut Resources) -> Result<()> {
+        let entity = match self.selected_entity {
+            Some(entity) => entity,
+            None => return Ok(()),
+        };
+        // Physics only runs in play mode, so the ragdoll falls there and the scene comes back on stop
+        if self.play_mode.is_editing() {
+            self.play(resources)?;
+        }
+        let simulated = match resources
+            .world
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Ragdoll>()
+        {
+            Ok(ragdoll) => ragdoll.is_simulated(),
+            Err(_) => {
+                resources
+                    .world
+                    .generate_ragdoll(entity, &RagdollSettings::default())?;
+                false
+            }
+        };
+        resources.world.set_ragdoll_enabled(entity, !simulated)
+    }
+
+    // Recorded edits, such as deleted entities and vertex paint strokes, refer to meshes
+    // and materials that compaction removes or renumbers, so the history is cleared
+    fn compact_world(&mut self, resources: &mut Resources) -> Result<()> {
+        let report = compact_world(resources.world)?;
+        if report.reclaimed_anything() {
+            info!("Compacted world storage: {:?}", report);
+            self.history.clear();
+            resources.renderer.load_world(resources.world)?;
+        }
+        Ok(())
+    }
+
+    // Batching removes the meshes that recorded edits refer to, so the history is cleared
+    fn batch_static_meshes(&mut self, resources: &mut Resources) -> Result<()> {
+        if !self.play_mode.is_editing() {
+            warn!("Static meshes can only be batched while editing");
+            return Ok(());
+        }
+        let report = batch_static_meshes(resources.world)?;
+        info!("Batched static meshes: {:?}", report);
+        if report.batches_created > 0 {
+            self.history.clear();
+            resources.renderer.load_world(resources.world)?;
+        }
+        Ok(())
+    }
+
+    fn capture_panorama(resources: &mut Resources, layout: PanoramaLayout) -> Result<()> {
+        let path = match FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .add_filter("HDR", &["hdr"])
+            .save_file()
+        {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let capture = resources
+            .renderer
+            .capture_cubemap(resources.world, PANORAMA_FACE_SIZE)?;
+        capture.save(&path, layout)?;
+        info!("Saved {:?} capture to '{}'", layout, path.display());
+        Ok(())
+    }
+
+    fn compact_if_fragmented(&mut self, resources: &mut Resources) -> Result<()> {
+        let now = resources.system.milliseconds_since_start();
+        if now - self.last_compaction_check < COMPACTION_INTERVAL_MS {
+            return Ok(());
+        }
+        self.last_compaction_check = now;
+
+        // Avoid repacking while assets are still streaming in or the world is being played,
+        // and don't clear edits that could still be undone or redone
+        if !self.pending_imports.is_empty()
+            || !self.play_mode.is_editing()
+            || self.history.can_undo()
+            || self.history.can_redo()
+        {
+            return Ok(());
+        }
+
+        if fragmentation(resources.world).wasted_ratio() > COMPACTION_THRESHOLD {
+            self.compact_world(resources)?;
+        }
+        Ok(())
+    }
+
+    fn execute_command(&mut self, id: &str, resources: &mut Resources) -> Result<()> {
+        // The registry is moved out while the command runs so the action can borrow the editor
+        let mut commands = std::mem::take(&mut self.commands);
+        let result = commands.execute(id, self, resources);
+        self.commands = commands;
+        if std::mem::take(&mut self.save_shortcuts_requested) {
+            self.commands
+                .save_bindings(CommandRegistry::<Editor>::BINDINGS_FILE)?;
+        }
+        result
+    }
+
+    fn execute_pending_commands(&mut self, resources: &mut Resources) -> Result<()> {
+        let pending_commands = std::mem::take(&mut self.pending_commands);
+        for id in pending_commands.iter() {
+            self.execute_command(id, resources)?;
+        }
+        Ok(())
+    }
+
+    pub fn select_entity(&mut self, entity: Entity, resources: &mut Resources) -> Result<()> {
+        if self.selected_entity == Some(entity) {
+            return Ok(());
+        }
+        self.change_selection(Some(entity), resources)?;
+        log::info!("Selected entity: {:?}", entity);
+        Ok(())
+    }
+
+    pub fn deselect_all(&mut self, resources: &mut Resources) -> Result<()> {
+        if self.selected_entity.is_none() {
+            return Ok(());
+        }
+        log::info!("Deselecting entity: {:?}", self.selected_entity);
+        self.change_selection(None, resources)
+    }
+
+    fn change_selection(&mut self, next: Option<Entity>, resources: &mut Resources) -> Result<()> {
+        let command = SelectionCommand {
+            previous: self.selected_entity,
+            next,
+        };
+        self.history.execute(Box::new(command), resources.world)?;
+        self.selected_entity = next;
+        Ok(())
+    }
+
+    pub fn load_world_from_file(&mut self, path: &Path, resources: &mut Resources) -> Result<()> {
         let raw_path = match path.to_str() {
             Some(raw_path) => raw_path,
             None => return Ok(()),
         };
 
+        // Environments are checked first, since equirectangular images share extensions with heightmaps
+        // and cubemap faces are loaded from a folder
+        if is_environment_file(path) {
+            // Decoding an environment can take seconds, so it becomes the skybox once it finishes
+            let handle = resources.load_skybox_async(raw_path);
+            self.pending_skyboxes.push(handle);
+            return Ok(());
+        }
+
+        if is_model_file(path) {
+            // Large models are parsed in the background and added once they finish
+            let handle = resources.load_asset_async(raw_path);
+            self.pending_imports.push(handle);
+            return Ok(());
+        }
+
         if let Some(extension) = path.extension() {
             match extension.to_str() {
-                Some("glb") | Some("gltf") => {
-                    load_gltf(raw_path, resources.world)?;
-                }
-                Some("hdr") => Self::load_hdr(raw_path, resources)?,
+                _ if is_heightmap_file(path) => return self.add_terrain(path, resources),
                 Some("dga") => {
+                    self.stop(resources)?;
                     resources.world.reload(raw_path)?;
+                    self.selected_entity = None;
+                    self.history.clear();
+                    if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        self.tabs.active_tab_mut().name = stem.to_string();
+                    }
                     log::info!("Loaded world!");
                 }
                 _ => log::warn!(
-                    "File extension {:#?} is not a valid '.dga', '.glb', '.gltf', or '.hdr' extension",
-                    extension
+                    "File extension {:#?} is not a valid '.dga', environment ({:?}), model ({:?}), or grayscale heightmap ({:?}) extension",
+                    extension,
+                    ENVIRONMENT_EXTENSIONS,
+                    MODEL_EXTENSIONS,
+                    HEIGHTMAP_EXTENSIONS
                 ),
             }
 
-            // TODO: Probably don't want this added every time
-            resources.renderer.load_world(resources.world)?;
+            Self::reload_world(resources)?;
+        }
 
-            // TODO: Don't add an additional collider to existing entities...
-            let mut query = <(Entity, &MeshRender)>::query();
-            let entities = query
-                .iter(&resources.world.ecs)
-                .map(|(e, _)| *e)
-                .collect::<Vec<_>>();
+        Ok(())
+    }
 
-            for entity in entities.into_iter() {
-                resources
-                    .world
-                    .add_rigid_body(entity, RigidBodyType::Static)?;
+    fn reload_world(resources: &mut Resources) -> Result<()> {
+        resources.renderer.load_world(resources.world)?;
+        // TODO: Probably don't want this added every time
+        Self::add_static_colliders(resources)
+    }
+
+    // Gives meshes without a rigid body a static trimesh collider, so they can be picked
+    fn add_static_colliders(resources: &mut Resources) -> Result<()> {
+        // Terrain chunks collide through the heightfield on their terrain instead
+        let terrains = <(Entity, &Terrain)>::query()
+            .iter(&resources.world.ecs)
+            .map(|(e, _)| *e)
+            .collect::<Vec<_>>();
+        let mut query = <(Entity, &MeshRender)>::query().filter(!component::<RigidBody>());
+        let entities = query
+            .iter(&resources.world.ecs)
+            .map(|(e, _)| *e)
+            .filter(|e| {
                 resources
                     .world
-                    .add_trimesh_collider(entity, EDITOR_COLLISION_GROUP)?;
+                    .parent_of(*e)
+                    .map_or(true, |parent| !terrains.contains(&parent))
+            })
+            .collect::<Vec<_>>();
+
+        for entity in entities.into_iter() {
+            resources
+                .world
+                .add_rigid_body(entity, RigidBodyType::Static)?;
+            resources
+                .world
+                .add_trimesh_collider(entity, EDITOR_COLLISION_GROUP)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_pending_imports(&mut self, resources: &mut Resources) -> Result<()> {
+        let mut imported = false;
+        let mut pending_imports = Vec::new();
+        for handle in std::mem::take(&mut self.pending_imports).into_iter() {
+            match resources.assets.state(&handle) {
+                Some(LoadState::Loading) => pending_imports.push(handle),
+                Some(LoadState::Loaded) => {
+                    if let Some(asset) = resources.assets.get(&handle) {
+                        let mut command = InstantiateModelCommand::new(asset);
+                        command.apply(resources.world)?;
+                        // Tracked so the model is patched in place when its file changes
+                        resources
+                            .assets
+                            .add_instance(&handle, command.entities().to_vec());
+                        self.history.record(Box::new(command));
+                        imported = true;
+                    }
+                }
+                Some(LoadState::Failed(error)) => warn!("Failed to import model: {}", error),
+                None => {}
+            }
+        }
+        self.pending_imports = pending_imports;
+
+        if imported {
+            Self::reload_world(resources)?;
+        }
+
+        Ok(())
+    }
+
+    // Imports in the history refer to the patched instances and reloaded model from then on
+    fn process_reloaded_models(&mut self, resources: &mut Resources) {
+        for reloaded in resources.events.read::<ModelReloaded>() {
+            let asset = resources
+                .assets
+                .handle::<ModelAsset>(reloaded.id)
+                .and_then(|handle| resources.assets.get(&handle));
+            if let Some(asset) = asset {
+                self.history.model_reloaded(&asset, &reloaded.instances);
             }
         }
+    }
 
+    fn process_pending_skyboxes(&mut self, resources: &mut Resources) -> Result<()> {
+        let mut pending_skyboxes = Vec::new();
+        for handle in std::mem::take(&mut self.pending_skyboxes).into_iter() {
+            match resources.assets.state(&handle) {
+                Some(LoadState::Loading) => pending_skyboxes.push(handle),
+                Some(LoadState::Loaded) => match resources.instantiate_skybox(&handle) {
+                    Ok(_) => info!("Loaded skybox"),
+                    Err(error) => warn!("Failed to load skybox: {}", error),
+                },
+                Some(LoadState::Failed(error)) => warn!("Failed to load skybox: {}", error),
+                None => {}
+            }
+        }
+        self.pending_skyboxes = pending_skyboxes;
         Ok(())
     }
 
@@ -212,6 +1250,7 @@ impl Editor {
 
     fn top_panel(&mut self, resources: &mut Resources) -> Result<()> {
         let context = &resources.gui.context();
+        let mut tab_action = None;
 
         egui::TopBottomPanel::top("top_panel")
             .resizable(true)
@@ -232,9 +1271,9 @@ impl Editor {
                             ui.close_menu();
                         }
 
-                        if ui.button("Import gltf/glb").clicked() {
+                        if ui.button("Import Model").clicked() {
                             let path = FileDialog::new()
-                                .add_filter("GLTF Asset", &["glb", "gltf"])
+                                .add_filter("Model", MODEL_EXTENSIONS)
                                 .set_directory("/")
                                 .pick_file();
                             if let Some(path) = path {
@@ -274,18 +1313,56 @@ impl Editor {
                             resources.system.exit_requested = true;
                         }
                     });
+                    let triggered = self.commands.render_menus(ui);
+                    self.pending_commands.extend(triggered);
+                });
+                ui.horizontal(|ui| {
+                    let triggered = self.commands.render_toolbar(ui);
+                    self.pending_commands.extend(triggered);
+                    ui.separator();
+                    time_scale_widget(ui, &mut resources.world.clock);
                 });
+                tab_action = self.tabs.render(ui);
             });
+
+        match tab_action {
+            Some(TabAction::Select(index)) => self.switch_scene(index, resources)?,
+            Some(TabAction::Close(index)) => self.close_scene(index, resources)?,
+            Some(TabAction::New) => self.new_scene(resources)?,
+            None => {}
+        }
+
         Ok(())
     }
 
     fn bottom_panel(&mut self, resources: &mut Resources) -> Result<()> {
         let context = &resources.gui.context();
 
+        let mut load = None;
+        egui::TopBottomPanel::bottom("content_browser")
+            .resizable(true)
+            .show(context, |ui| {
+                ui.heading("Content Browser");
+                load = self.content_browser.render(ui);
+            });
+        if let Some(path) = load {
+            self.load_world_from_file(&path, resources)?;
+        }
+
         egui::TopBottomPanel::bottom("console")
             .resizable(true)
             .show(context, |ui| {
                 ui.heading("Console");
+                if !resources.assets.is_idle() {
+                    let (finished, total) = resources.assets.progress();
+                    ui.add(
+                        egui::ProgressBar::new(finished as f32 / total as f32)
+                            .text(format!("Loading assets ({}/{})", finished, total)),
+                    );
+                    for path in resources.assets.loading_paths() {
+                        ui.label(path.display().to_string());
+                    }
+                }
                 ui.allocate_space(ui.available_size());
             });
 
@@ -295,6 +1372,7 @@ impl Editor {
     fn left_panel(&mut self, resources: &mut Resources) -> Result<()> {
         let context = &resources.gui.context();
 
+        let mut window_mode = resources.config.window.mode;
         egui::SidePanel::left("scene_explorer")
             .resizable(true)
             .show(context, |ui| {
@@ -302,6 +1380,10 @@ impl Editor {
                     ui.heading("Tools");
                     self.gizmo.render_mode_selection(ui);
 
+                    ui.collapsing("Snapping", |ui| {
+                        self.gizmo.render_snapping_settings(ui);
+                    });
+
                     ui.heading("Post Processing");
 
                     ui.add(
@@ -332,10 +1414,63 @@ impl Editor {
 
                     ui.end_row();
 
+                    ui.heading("Texture Filtering");
+                    let texture_filtering = &mut resources.config.graphics.texture_filtering;
+                    // Changing the samplers reloads the world, so it isn't done on every step of a drag
+                    let anisotropy = self
+                        .anisotropy_edit
+                        .get_or_insert(texture_filtering.anisotropy);
+                    let response = ui.add(
+                        Slider::new(anisotropy, 1.0..=16.0)
+                            .integer()
+                            .text("Anisotropy"),
+                    );
+                    if !response.dragged() {
+                        if let Some(anisotropy) = self.anisotropy_edit.take() {
+                            texture_filtering.anisotropy = anisotropy;
+                        }
+                    }
+                    ui.checkbox(&mut texture_filtering.trilinear, "Trilinear Filtering");
+
+                    ui.end_row();
+
+                    ui.heading("Vertex Layout");
+                    let vertex_layout = &mut resources.config.graphics.vertex_layout;
+                    ui.horizontal(|ui| {
+                        ui.radio_value(vertex_layout, VertexLayout::Interleaved, "Interleaved");
+                        ui.radio_value(vertex_layout, VertexLayout::Streams, "Streams");
+                        ui.radio_value(
+                            vertex_layout,
+                            VertexLayout::QuantizedStreams,
+                            "Quantized Streams",
+                        );
+                    });
+                    ui.checkbox(
+                        &mut resources.config.graphics.compute_skinning,
+                        "Compute Skinning",
+                    );
+
+                    ui.end_row();
+
+                    ui.heading("Display");
+                    ui.checkbox(&mut resources.config.graphics.vsync, "VSync");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut window_mode, WindowMode::Windowed, "Windowed");
+                        ui.radio_value(&mut window_mode, WindowMode::Borderless, "Borderless");
+                        ui.radio_value(&mut window_mode, WindowMode::Exclusive, "Exclusive");
+                    });
+
+                    ui.end_row();
+
+                    ui.heading("Environment");
+                    self.environment_selection(ui, resources);
+
+                    ui.end_row();
+
                     ui.heading("Scenegraph");
                     ui.label(&resources.world.scene.name);
-                    let scene = &mut resources.world.scene;
-                    let ecs = &mut resources.world.ecs;
+                    let scene = &mut resources.world.sim.scene;
+                    let ecs = &mut resources.world.sim.ecs;
                     for graph in scene.graphs.iter_mut() {
                         self.print_node(ecs, graph, NodeIndex::new(0), ui);
                     }
@@ -344,28 +1479,187 @@ impl Editor {
                     ui.allocate_space(ui.available_size());
                 });
             });
+
+        if window_mode != resources.config.window.mode {
+            resources.set_window_mode(window_mode)?;
+        }
         Ok(())
     }
 
+    fn environment_selection(&mut self, ui: &mut Ui, resources: &mut Resources) {
+        let assets = &resources.world.assets;
+        let selected = resources.world.scene.skybox;
+        let mut selection = selected;
+        egui::ComboBox::from_label("Skybox")
+            .selected_text(match selected {
+                Some(index) => assets.hdr_name(index),
+                None => "None".to_string(),
+            })
+            .show_ui(ui, |ui| {
+                for index in 0..assets.hdr_textures.len() {
+                    ui.selectable_value(&mut selection, Some(index), assets.hdr_name(index));
+                }
+            });
+        if let Some(index) = selection.filter(|_| selection != selected) {
+            resources
+                .world
+                .scene
+                .fade_skybox(index, Resources::SKYBOX_FADE_SECONDS);
+        }
+
+        // Only used while the scene has no skybox
+        let ambient_light = &mut resources.world.scene.ambient_light;
+        ui.horizontal(|ui| {
+            ui.label("Ambient Light");
+            let mut color = [
+                ambient_light.color.x,
+                ambient_light.color.y,
+                ambient_light.color.z,
+            ];
+            if ui.color_edit_button_rgb(&mut color).changed() {
+                ambient_light.color = glm::vec3(color[0], color[1], color[2]);
+            }
+        });
+        ui.add(Slider::new(&mut ambient_light.intensity, 0.0..=5.0).text("Ambient Intensity"));
+
+        ui.horizontal(|ui| {
+            let mut path = None;
+            if ui.button("Load Skybox...").clicked() {
+                path = FileDialog::new()
+                    .add_filter("Environment", ENVIRONMENT_EXTENSIONS)
+                    .set_directory("/")
+                    .pick_file();
+            }
+            if ui.button("Load Cubemap Folder...").clicked() {
+                path = FileDialog::new().set_directory("/").pick_folder();
+            }
+            if let Some(path) = path.as_ref().and_then(|path| path.to_str()) {
+                let handle = resources.load_skybox_async(path);
+                self.pending_skyboxes.push(handle);
+            }
+        });
+
+        // Probes capture the skybox, so they are rebaked by hand after it or the scene changes
+        if ui.button("Bake Reflection Probes").clicked() {
+            self.pending_commands
+                .push("tools.bake_reflection_probes".to_string());
+        }
+    }
+
     fn right_panel(&mut self, resources: &mut Resources) -> Result<()> {
         let context = &resources.gui.context();
 
-        egui::SidePanel::right("inspector")
+        let selected_entity = self.selected_entity;
+        let (unlink_requested, material_edit) = egui::SidePanel::right("inspector")
             .resizable(true)
-            .show(context, |ui| -> Result<()> {
+            .show(context, |ui| -> Result<bool> {
                 ui.heading("Inspector");
-                let entity = match self.selected_entity {
+                let entity = match selected_entity {
                     Some(entity) => entity,
-                    None => return Ok(()),
+                    None => return Ok((false, None)),
                 };
 
                 translation_widget(resources, entity, ui)?;
                 rotation_widget(resources, entity, ui)?;
                 scale_widget(resources, entity, ui)?;
+                static_widget(resources, entity, ui)?;
+                shadow_widget(resources, entity, ui)?;
+                reflection_probe_widget(resources, entity, ui)?;
+                script_widget(resources, entity, ui)?;
+                let material_edit = material_widget(resources, entity, ui)?;
+                let unlink_requested = instance_widget(resources, entity, ui)?;
                 ui.allocate_space(ui.available_size());
 
-                Ok(())
-            });
+                Ok((unlink_requested, material_edit))
+            })
+            .inner?;
+        if unlink_requested {
+            self.unlink_selected(resources)?;
+        }
+        self.track_material_edit(material_edit, resources);
+        Ok(())
+    }
+
+    // Material sliders are recorded as a single command once the pointer is released, like transforms
+    fn track_material_edit(&mut self, edit: Option<(usize, Material)>, resources: &mut Resources) {
+        if let Some((index, before)) = edit {
+            match self.material_edit.as_ref() {
+                Some((pending, _)) if *pending == index => {}
+                _ => {
+                    self.finish_material_edit(resources);
+                    self.material_edit = Some((index, before));
+                }
+            }
+        }
+        if !resources.gui.context().input().pointer.any_down() {
+            self.finish_material_edit(resources);
+        }
+    }
+
+    fn finish_material_edit(&mut self, resources: &Resources) {
+        if let Some((index, before)) = self.material_edit.take() {
+            if let Ok(after) = resources.world.assets.material_at_index(index) {
+                self.history
+                    .record(Box::new(MaterialCommand::new(index, before, after.clone())));
+            }
+        }
+    }
+
+    // The app writes the config back to disk once it changes
+    fn store_layout(&mut self, resources: &mut Resources) -> Result<()> {
+        let layout = EditorLayout {
+            calibration: self.calibration.open,
+            physics_inspector: self.physics_inspector.open,
+            profiler: self.profiler.open,
+            console: self.console.open,
+            cvars: self.cvar_window.open,
+            timeline: self.timeline.open,
+            vertex_painter: self.vertex_painter.open,
+            hide_grid: !self.show_grid,
+        };
+        if layout != self.layout {
+            resources
+                .config
+                .set_preference(EditorLayout::PREFERENCE, &layout)?;
+            self.layout = layout;
+        }
+        Ok(())
+    }
+
+    // Marks the entity under the cursor as hovered, so the renderer outlines it.
+    // Nothing is hovered while playing, where the cursor belongs to the game.
+    fn update_hovered(&mut self, resources: &mut Resources) -> Result<()> {
+        let hovered = if !self.play_mode.is_editing() || resources.gui.wants_pointer_input() {
+            None
+        } else {
+            resources.world.pick_object(
+                &resources.mouse_ray_configuration()?,
+                f32::MAX,
+                EDITOR_COLLISION_GROUP,
+            )?
+        };
+
+        let mut query = <Entity>::query().filter(component::<Hovered>());
+        let previous = query
+            .iter(&resources.world.ecs)
+            .copied()
+            .collect::<Vec<_>>();
+        for entity in previous.into_iter() {
+            if Some(entity) == hovered {
+                continue;
+            }
+            if let Some(mut entry) = resources.world.ecs.entry(entity) {
+                entry.remove_component::<Hovered>();
+            }
+        }
+
+        if let Some(entity) = hovered {
+            if let Some(mut entry) = resources.world.ecs.entry(entity) {
+                if entry.get_component::<Hovered>().is_err() {
+                    entry.add_component(Hovered);
+                }
+            }
+        }
         Ok(())
     }
 
@@ -376,6 +1670,27 @@ impl Editor {
             .fixed_pos((0.0, 0.0))
             .show(context, |ui| {
                 ui.with_layer_id(LayerId::background(), |ui| {
+                    if let Ok((_, view)) = resources
+                        .world
+                        .active_camera_matrices(resources.system.aspect_ratio())
+                    {
+                        paint_axis_indicator(ui.painter(), ui.clip_rect(), &view);
+                    }
+                    // The brush replaces the gizmo while painting
+                    if self.vertex_painter.open {
+                        return;
+                    }
+                    // A selected control point takes the gizmo from its spline's entity
+                    let editing_point = self
+                        .spline_editor
+                        .render(ui, resources, &mut self.gizmo, self.selected_entity)
+                        .unwrap_or_else(|error| {
+                            warn!("Failed to edit spline: {}", error);
+                            false
+                        });
+                    if editing_point {
+                        return;
+                    }
                     if let Some(entity) = self.selected_entity {
                         let (projection, view) = resources
                             .world
@@ -388,14 +1703,19 @@ impl Editor {
                         if let Some(gizmo_result) =
                             self.gizmo.render(ui, transform.matrix(), view, projection)
                         {
+                            // The gizmo works in world space, so children keep their parents' transforms
                             let model_matrix: glm::Mat4 = gizmo_result.transform.into();
-                            let gizmo_transform = Transform::from(model_matrix);
-                            let mut entry = resources.world.ecs.entry_mut(entity).unwrap();
-                            let transform = entry.get_component_mut::<Transform>().unwrap();
-                            transform.translation = gizmo_transform.translation;
-                            transform.rotation = gizmo_transform.rotation;
-                            transform.scale = gizmo_transform.scale;
-                            if entry.get_component::<RigidBody>().is_ok() {
+                            resources
+                                .world
+                                .set_entity_global_transform(entity, &Transform::from(model_matrix))
+                                .expect("Failed to set entity transform!");
+                            let has_rigid_body = resources
+                                .world
+                                .ecs
+                                .entry_ref(entity)
+                                .map(|entry| entry.get_component::<RigidBody>().is_ok())
+                                .unwrap_or(false);
+                            if has_rigid_body {
                                 resources
                                     .world
                                     .sync_rigid_body_to_transform(entity)
@@ -414,22 +1734,48 @@ impl App for Editor {
     fn initialize(&mut self, resources: &mut dragonglass::app::Resources) -> Result<()> {
         register_component::<Selected>("selected")?;
         resources.world.add_default_light()?;
+        let skybox = resources.load_skybox_async(Self::DEFAULT_SKYBOX);
+        self.pending_skyboxes.push(skybox);
+        // Models and skyboxes exported again while the editor runs show up without reimporting them
+        resources.assets.set_hot_reload(true);
+        self.register_default_commands()?;
+        if let Err(error) = self
+            .commands
+            .load_bindings(CommandRegistry::<Editor>::BINDINGS_FILE)
+        {
+            warn!("Failed to load editor shortcuts: {}", error);
+        }
+        if let Some(layout) = resources
+            .config
+            .preference::<EditorLayout>(EditorLayout::PREFERENCE)
+        {
+            self.calibration.open = layout.calibration;
+            self.physics_inspector.open = layout.physics_inspector;
+            self.profiler.open = layout.profiler;
+            self.console.open = layout.console;
+            self.cvar_window.open = layout.cvars;
+            self.timeline.open = layout.timeline;
+            self.vertex_painter.open = layout.vertex_painter;
+            self.show_grid = !layout.hide_grid;
+            self.layout = layout;
+        }
         Ok(())
     }
 
     fn update(&mut self, resources: &mut dragonglass::app::Resources) -> Result<()> {
-        if resources.world.active_camera_is_main()? {
-            let camera_entity = resources.world.active_camera()?;
-            self.camera.update(resources, camera_entity)?;
+        self.process_pending_imports(resources)?;
+        self.process_reloaded_models(resources);
+        self.process_pending_skyboxes(resources)?;
+        self.compact_if_fragmented(resources)?;
+        self.play_mode.update(resources.world)?;
+        // Scripts wait while the world is paused, and run for frames stepped through
+        if !self.play_mode.is_editing() && resources.world.clock.delta() > 0.0 {
+            self.scripts.update(resources)?;
         }
-
-        // // Run first animation
-        // if let Some(animation) = resources.world.animations.first_mut() {
-        //     animation.animate(
-        //         &mut resources.world.ecs,
-        //         0.75 * resources.system.delta_time as f32,
-        //     )?;
-        // }
+        self.update_hovered(resources)?;
+        self.camera_tools.update(resources)?;
+        resources.config.graphics.grid.visible = self.show_grid;
+        self.store_layout(resources)?;
 
         Ok(())
     }
@@ -439,11 +1785,46 @@ impl App for Editor {
     }
 
     fn update_gui(&mut self, resources: &mut Resources) -> Result<()> {
+        let frame_start = self.selected_transform(resources);
         self.top_panel(resources)?;
         self.left_panel(resources)?;
         self.right_panel(resources)?;
         self.bottom_panel(resources)?;
         self.viewport_panel(resources)?;
+        if let Some(path) = self.content_browser.update_drag(&resources.gui.context()) {
+            self.load_world_from_file(&path, resources)?;
+        }
+        self.calibration
+            .render(&resources.gui.context(), resources.config)?;
+        self.physics_inspector.render(
+            &resources.gui.context(),
+            resources.world,
+            self.selected_entity,
+        )?;
+        self.profiler.render(
+            &resources.gui.context(),
+            &resources.renderer.gpu_timings(),
+            &resources.renderer.stats(),
+            &resources.world.occlusion.stats(),
+        );
+        self.console.show(resources);
+        self.cvar_window
+            .render(&resources.gui.context(), resources.cvars)?;
+        self.timeline
+            .render(&resources.gui.context(), resources.world)?;
+        self.vertex_painter.render(&resources.gui.context());
+        self.paint_vertices(resources)?;
+        self.track_transform_edit(frame_start, resources);
+        if let Some(command) = self.spline_editor.finish_edit(resources) {
+            self.history.record(command);
+        }
+        if let Some(command) = self
+            .physics_inspector
+            .finish_edit(&resources.gui.context(), resources.world)
+        {
+            self.history.record(command);
+        }
+        self.execute_pending_commands(resources)?;
         Ok(())
     }
 
@@ -453,13 +1834,22 @@ impl App for Editor {
         button_state: &ElementState,
         resources: &mut Resources,
     ) -> Result<()> {
-        if (MouseButton::Left, ElementState::Pressed) == (*button, *button_state) {
+        if (MouseButton::Left, ElementState::Pressed) == (*button, *button_state)
+            && !self.spline_editor.hovered
+        {
             let interact_distance = f32::MAX;
-            let picked_entity = resources.world.pick_object(
+            let mut picked_entity = resources.world.pick_object(
                 &resources.mouse_ray_configuration()?,
                 interact_distance,
                 EDITOR_COLLISION_GROUP,
             )?;
+            // Entities without colliders can still be picked by what was drawn under the cursor
+            if picked_entity.is_none() {
+                let position = resources.input.mouse.position;
+                picked_entity = resources
+                    .renderer
+                    .pick(resources.world, position.x, position.y)?;
+            }
             if let Some(entity) = picked_entity {
                 self.select_entity(entity, resources)?;
             }
@@ -481,27 +1871,11 @@ impl App for Editor {
         input: winit::event::KeyboardInput,
         resources: &mut dragonglass::app::Resources,
     ) -> Result<()> {
-        match (input.virtual_keycode, input.state) {
-            (Some(VirtualKeyCode::Escape), ElementState::Pressed) => {
-                self.deselect_all(resources)?;
-            }
-            (Some(VirtualKeyCode::T), ElementState::Pressed) => {
-                self.gizmo.mode = GizmoMode::Translate;
-            }
-            (Some(VirtualKeyCode::R), ElementState::Pressed) => {
-                self.gizmo.mode = GizmoMode::Rotate;
-            }
-            (Some(VirtualKeyCode::S), ElementState::Pressed) => {
-                self.gizmo.mode = GizmoMode::Scale;
-            }
-            (Some(VirtualKeyCode::C), ElementState::Pressed) => {
-                resources.world.clear()?;
-                self.selected_entity = None;
-                if let Err(error) = resources.renderer.load_world(resources.world) {
-                    warn!("Failed to load gltf world: {}", error);
-                }
+        if let (Some(keycode), ElementState::Pressed) = (input.virtual_keycode, input.state) {
+            let modifiers = Modifiers::from_resources(resources);
+            if let Some(id) = self.commands.find_shortcut(keycode, modifiers) {
+                self.execute_command(&id, resources)?;
             }
-            _ => {}
         }
         Ok(())
     }
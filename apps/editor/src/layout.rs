@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+// Which of the editor's windows are open, remembered across runs through the config's preferences
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorLayout {
+    pub calibration: bool,
+    pub physics_inspector: bool,
+    pub profiler: bool,
+    pub console: bool,
+    pub cvars: bool,
+    pub timeline: bool,
+    pub vertex_painter: bool,
+    pub hide_grid: bool,
+}
+
+impl EditorLayout {
+    pub const PREFERENCE: &'static str = "editor_layout";
+}
@@ -1,13 +1,32 @@
+mod calibration;
+mod camera_tools;
+mod commands;
+mod content_browser;
+mod cvars;
 mod editor;
+mod layout;
+mod physics_inspector;
+mod play;
+mod profiler;
+mod spline_editor;
+mod tabs;
+mod timeline;
+mod vertex_paint;
 mod widgets;
 
 use anyhow::Result;
 use dragonglass::{
-    app::{run_application, AppConfig},
+    app::{run_application, AppConfig, CameraControllerKind},
     render::Backend,
 };
 use editor::Editor;
 
+// Built with the webgpu feature, the editor renders with wgpu instead
+#[cfg(feature = "webgpu")]
+const BACKEND: Backend = Backend::Wgpu;
+#[cfg(not(feature = "webgpu"))]
+const BACKEND: Backend = Backend::Vulkan;
+
 fn main() -> Result<()> {
     run_application(
         Editor::default(),
@@ -16,7 +35,8 @@ fn main() -> Result<()> {
             height: 1080,
             icon: Some("assets/icon/icon.png".to_string()),
             title: "Dragonglass Editor".to_string(),
-            backend: Backend::Vulkan,
+            backend: BACKEND,
+            camera_controller: Some(CameraControllerKind::Orbit),
             ..Default::default()
         },
     )
@@ -0,0 +1,276 @@
+use anyhow::Result;
+use dragonglass::{
+    app::{Command, PhysicsMaterialCommand},
+    gui::egui::{self, CtxRef, DragValue, Grid, Ui},
+    world::{
+        rapier3d::{na::Vector3, prelude::RigidBodyType},
+        Entity, EntityStore, PhysicsLodStats, PhysicsMaterial, PhysicsStats, RigidBody,
+        RigidBodySettings, World,
+    },
+};
+use log::warn;
+
+#[derive(Default)]
+pub struct PhysicsInspector {
+    pub open: bool,
+    // The entity and its physics material from before an in-progress material edit
+    material_edit: Option<(Entity, PhysicsMaterial)>,
+}
+
+impl PhysicsInspector {
+    pub fn render(
+        &mut self,
+        context: &CtxRef,
+        world: &mut World,
+        selected_entity: Option<Entity>,
+    ) -> Result<()> {
+        let mut open = self.open;
+        let mut material_edit = None;
+        egui::Window::new("Physics Inspector")
+            .open(&mut open)
+            .resizable(false)
+            .show(context, |ui| {
+                stats_widget(ui, &world.physics.stats());
+                lod_stats_widget(ui, &world.physics_lod_stats());
+
+                ui.horizontal(|ui| {
+                    if ui.button("Wake All").clicked() {
+                        world.physics.wake_all();
+                    }
+                    if ui.button("Sleep All").clicked() {
+                        world.physics.sleep_all();
+                    }
+                });
+
+                ui.separator();
+                match selected_entity {
+                    Some(entity) => {
+                        selected_body_widget(ui, world, entity);
+                        settings_widget(ui, world, entity);
+                        material_edit =
+                            material_widget(ui, world, entity).map(|before| (entity, before));
+                    }
+                    None => {
+                        ui.label("No entity selected");
+                    }
+                }
+            });
+        self.open = open;
+        if self.material_edit.is_none() {
+            self.material_edit = material_edit;
+        }
+        Ok(())
+    }
+
+    // Returns a command for the material edit once the pointer is released,
+    // so a whole drag is undone at once
+    pub fn finish_edit(&mut self, context: &CtxRef, world: &World) -> Option<Box<dyn Command>> {
+        if context.input().pointer.any_down() {
+            return None;
+        }
+        let (entity, before) = self.material_edit.take()?;
+        let after = world.entity_physics_material(entity);
+        if after == before {
+            return None;
+        }
+        Some(Box::new(PhysicsMaterialCommand::new(entity, before, after)))
+    }
+}
+
+fn lod_stats_widget(ui: &mut Ui, stats: &PhysicsLodStats) {
+    Grid::new("physics_lod_stats").striped(true).show(ui, |ui| {
+        let rows = [
+            ("Full Detail Bodies", stats.full),
+            ("Simplified Bodies", stats.simplified),
+            ("Frozen Bodies", stats.frozen),
+        ];
+        for (label, value) in rows.iter() {
+            ui.label(*label);
+            ui.label(value.to_string());
+            ui.end_row();
+        }
+    });
+}
+
+fn stats_widget(ui: &mut Ui, stats: &PhysicsStats) {
+    Grid::new("physics_stats").striped(true).show(ui, |ui| {
+        let rows = [
+            ("Active Bodies", stats.active_bodies),
+            ("Sleeping Bodies", stats.sleeping_bodies),
+            ("Kinematic Bodies", stats.kinematic_bodies),
+            ("Static Bodies", stats.static_bodies),
+            ("Islands", stats.islands),
+            ("Active Islands", stats.active_islands),
+            ("Colliders", stats.colliders),
+            ("Joints", stats.impulse_joints),
+            ("Contact Pairs", stats.contact_pairs),
+        ];
+        for (label, value) in rows.iter() {
+            ui.label(*label);
+            ui.label(value.to_string());
+            ui.end_row();
+        }
+    });
+}
+
+fn selected_body_widget(ui: &mut Ui, world: &mut World, entity: Entity) {
+    let handle = match world.ecs.entry_ref(entity).ok().and_then(|entry| {
+        entry
+            .get_component::<RigidBody>()
+            .ok()
+            .map(|body| body.handle)
+    }) {
+        Some(handle) => handle,
+        None => {
+            ui.label("The selected entity has no rigid body");
+            return;
+        }
+    };
+    let body = match world.physics.bodies.get_mut(handle) {
+        Some(body) => body,
+        None => return,
+    };
+
+    let body_type = match body.body_type() {
+        RigidBodyType::Dynamic => "Dynamic",
+        RigidBodyType::Static => "Static",
+        _ => "Kinematic",
+    };
+    let state = if body.is_sleeping() {
+        "Sleeping"
+    } else {
+        "Awake"
+    };
+    ui.label(format!("{} body, {}", body_type, state));
+
+    let linear_velocity = *body.linvel();
+    let angular_velocity = *body.angvel();
+    Grid::new("physics_body").striped(true).show(ui, |ui| {
+        ui.label("Linear Velocity");
+        ui.label(format_vector(&linear_velocity));
+        ui.end_row();
+        ui.label("Speed");
+        ui.label(format!("{:.3}", linear_velocity.norm()));
+        ui.end_row();
+        ui.label("Angular Velocity");
+        ui.label(format_vector(&angular_velocity));
+        ui.end_row();
+    });
+
+    ui.horizontal(|ui| {
+        if ui.button("Wake").clicked() {
+            body.wake_up(true);
+        }
+        if ui.button("Sleep").clicked() {
+            body.sleep();
+        }
+        if ui.button("Reset Velocity").clicked() {
+            body.set_linvel(Vector3::zeros(), true);
+            body.set_angvel(Vector3::zeros(), true);
+        }
+    });
+}
+
+fn settings_widget(ui: &mut Ui, world: &mut World, entity: Entity) {
+    ui.separator();
+    ui.label("Body Settings");
+    let mut settings = world.entity_rigid_body_settings(entity);
+    Grid::new("rigid_body_settings")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Continuous Collisions");
+            ui.checkbox(&mut settings.ccd_enabled, "");
+            ui.end_row();
+            ui.label("Linear Damping");
+            ui.add(
+                DragValue::new(&mut settings.linear_damping)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.end_row();
+            ui.label("Angular Damping");
+            ui.add(
+                DragValue::new(&mut settings.angular_damping)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.end_row();
+            ui.label("Gravity Scale");
+            ui.add(DragValue::new(&mut settings.gravity_scale).speed(0.01));
+            ui.end_row();
+            ui.label("Lock Rotations");
+            ui.checkbox(&mut settings.lock_rotations, "");
+            ui.end_row();
+            ui.label("Can Sleep");
+            ui.checkbox(&mut settings.can_sleep, "");
+            ui.end_row();
+            ui.label("Sleep Linear Threshold");
+            ui.add(
+                DragValue::new(&mut settings.sleep_linear_threshold)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.end_row();
+            ui.label("Sleep Angular Threshold");
+            ui.add(
+                DragValue::new(&mut settings.sleep_angular_threshold)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.end_row();
+        });
+    if ui.button("Reset Settings").clicked() {
+        settings = RigidBodySettings::default();
+    }
+    if settings != world.entity_rigid_body_settings(entity) {
+        if let Err(error) = world.set_rigid_body_settings(entity, settings) {
+            warn!("Failed to set rigid body settings: {}", error);
+        }
+    }
+}
+
+// Returns the material from before the edit if it was changed
+fn material_widget(ui: &mut Ui, world: &mut World, entity: Entity) -> Option<PhysicsMaterial> {
+    ui.separator();
+    ui.label("Material");
+    let before = world.entity_physics_material(entity);
+    let mut material = before;
+    Grid::new("physics_material").striped(true).show(ui, |ui| {
+        ui.label("Friction");
+        ui.add(
+            DragValue::new(&mut material.friction)
+                .speed(0.01)
+                .clamp_range(0.0..=f32::MAX),
+        );
+        ui.end_row();
+        ui.label("Restitution");
+        ui.add(
+            DragValue::new(&mut material.restitution)
+                .speed(0.01)
+                .clamp_range(0.0..=1.0),
+        );
+        ui.end_row();
+        ui.label("Density");
+        ui.add(
+            DragValue::new(&mut material.density)
+                .speed(0.01)
+                .clamp_range(0.0..=f32::MAX),
+        );
+        ui.end_row();
+    });
+    if ui.button("Reset Material").clicked() {
+        material = PhysicsMaterial::default();
+    }
+    if material == before {
+        return None;
+    }
+    if let Err(error) = world.set_physics_material(entity, material) {
+        warn!("Failed to set physics material: {}", error);
+        return None;
+    }
+    Some(before)
+}
+
+fn format_vector(vector: &Vector3<f32>) -> String {
+    format!("({:.3}, {:.3}, {:.3})", vector.x, vector.y, vector.z)
+}
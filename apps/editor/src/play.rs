@@ -0,0 +1,93 @@
+use anyhow::Result;
+use dragonglass::{
+    app::History,
+    world::{World, WorldSnapshot},
+};
+use log::info;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayState {
+    Editing,
+    Playing,
+    Paused,
+}
+
+impl Default for PlayState {
+    fn default() -> Self {
+        Self::Editing
+    }
+}
+
+// Runs the world live inside the editor and puts it back the way it was afterwards.
+// Pausing and stepping go through the world's clock, so they also apply to everything else it drives.
+#[derive(Default)]
+pub struct PlayMode {
+    state: PlayState,
+    snapshot: Option<WorldSnapshot>,
+    // Changes made while playing are thrown away on stop,
+    // so the editing history is set aside until then
+    history: History,
+}
+
+impl PlayMode {
+    pub fn is_editing(&self) -> bool {
+        self.state == PlayState::Editing
+    }
+
+    pub fn play(&mut self, world: &mut World, history: &mut History) -> Result<()> {
+        if self.is_editing() {
+            let snapshot = world.snapshot()?;
+            info!(
+                "Entering play mode (snapshot: {} bytes)",
+                snapshot.size_in_bytes()
+            );
+            self.snapshot = Some(snapshot);
+            std::mem::swap(&mut self.history, history);
+        }
+        self.state = PlayState::Playing;
+        world.clock.resume();
+        Ok(())
+    }
+
+    pub fn pause(&mut self, world: &mut World) {
+        if self.state == PlayState::Playing {
+            self.state = PlayState::Paused;
+            world.clock.pause();
+        }
+    }
+
+    // Advances a paused world by one frame
+    pub fn step(&mut self, world: &mut World) {
+        if self.state == PlayState::Paused {
+            world.clock.step();
+        }
+    }
+
+    // Returns true if the world was restored
+    pub fn stop(&mut self, world: &mut World, history: &mut History) -> Result<bool> {
+        if self.is_editing() {
+            return Ok(false);
+        }
+        if let Some(snapshot) = self.snapshot.take() {
+            world.restore(&snapshot)?;
+        }
+        std::mem::swap(&mut self.history, history);
+        self.history.clear();
+        self.state = PlayState::Editing;
+        info!("Exited play mode");
+        Ok(true)
+    }
+
+    // The world clock is stopped while editing or paused, which also keeps physics frozen.
+    // Frames stepped while paused are still taken from the clock.
+    pub fn update(&self, world: &mut World) -> Result<()> {
+        world.clock.paused = self.state != PlayState::Playing;
+        if self.is_editing() {
+            return Ok(());
+        }
+
+        // Animations are played by the world as it ticks
+        world.sync_dynamic_rigid_bodies();
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use dragonglass::{
+    gui::egui::{
+        self, pos2, vec2, Align2, Color32, CtxRef, Grid, Rect, Sense, Stroke, TextStyle, Ui,
+    },
+    profiling::{FrameProfile, Profiler},
+    render::{GpuTiming, RenderStats},
+    world::OcclusionStats,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+// Shows the recorded frame times, a flame graph of the selected frame, and what the renderer submitted.
+// The profiler only records while this window is open.
+#[derive(Default)]
+pub struct ProfilerWindow {
+    pub open: bool,
+    // Follows the latest frame when nothing is selected
+    selected_frame: Option<u64>,
+}
+
+impl ProfilerWindow {
+    const HISTORY_HEIGHT: f32 = 60.0;
+    const ROW_HEIGHT: f32 = 18.0;
+    // Frames faster than this still fill a sensible part of the history graph
+    const MIN_GRAPH_MILLISECONDS: f32 = 1000.0 / 30.0;
+
+    pub fn render(
+        &mut self,
+        context: &CtxRef,
+        gpu_timings: &[GpuTiming],
+        render_stats: &RenderStats,
+        occlusion_stats: &OcclusionStats,
+    ) {
+        let mut open = self.open;
+        {
+            let mut profiler = Profiler::lock();
+            if profiler.is_enabled() != open {
+                profiler.set_enabled(open);
+            }
+        }
+        if !open {
+            return;
+        }
+
+        // The profiler is only locked long enough to copy what is shown,
+        // since profiled code can run on this thread while the window is drawn
+        let (paused, history, selected) = {
+            let profiler = Profiler::lock();
+            let history = profiler
+                .frames()
+                .map(|frame| (frame.index, frame.duration))
+                .collect::<Vec<_>>();
+            let selected = match self.selected_frame {
+                Some(index) => profiler.frames().find(|frame| frame.index == index),
+                None => profiler.latest_frame(),
+            }
+            .cloned();
+            (profiler.is_paused(), history, selected)
+        };
+
+        egui::Window::new("Profiler")
+            .open(&mut open)
+            .default_width(600.0)
+            .show(context, |ui| {
+                ui.horizontal(|ui| {
+                    let mut paused = paused;
+                    if ui.checkbox(&mut paused, "Paused").changed() {
+                        Profiler::lock().set_paused(paused);
+                    }
+                    if ui.button("Follow Latest").clicked() {
+                        self.selected_frame = None;
+                    }
+                    if ui.button("Clear").clicked() {
+                        Profiler::lock().clear();
+                        self.selected_frame = None;
+                    }
+                });
+
+                ui.separator();
+                self.history_graph(ui, &history, selected.as_ref().map(|frame| frame.index));
+
+                ui.separator();
+                match selected.as_ref() {
+                    Some(frame) => {
+                        ui.label(format!(
+                            "Frame {} took {:.2} ms",
+                            frame.index,
+                            milliseconds(frame.duration)
+                        ));
+                        flame_graph(ui, frame);
+                    }
+                    None => {
+                        ui.label("No frames have been recorded yet");
+                    }
+                }
+
+                ui.separator();
+                ui.heading("GPU");
+                gpu_timings_widget(ui, gpu_timings);
+
+                ui.separator();
+                ui.heading("Rendering");
+                render_stats_widget(ui, render_stats);
+
+                ui.separator();
+                ui.heading("Culling");
+                occlusion_stats_widget(ui, occlusion_stats);
+            });
+        self.open = open;
+        if !open {
+            Profiler::lock().set_enabled(false);
+        }
+    }
+
+    fn history_graph(&mut self, ui: &mut Ui, history: &[(u64, Duration)], selected: Option<u64>) {
+        let width = ui.available_width();
+        let (response, painter) =
+            ui.allocate_painter(vec2(width, Self::HISTORY_HEIGHT), Sense::click());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+        if history.is_empty() {
+            return;
+        }
+
+        let max_milliseconds = history
+            .iter()
+            .map(|(_, duration)| milliseconds(*duration))
+            .fold(Self::MIN_GRAPH_MILLISECONDS, f32::max);
+        let bar_width = width / history.len().max(Profiler::MAX_FRAMES / 2) as f32;
+        for (position, (index, duration)) in history.iter().enumerate() {
+            let height = milliseconds(*duration) / max_milliseconds * rect.height();
+            let left = rect.left() + position as f32 * bar_width;
+            let bar = Rect::from_min_max(
+                pos2(left, rect.bottom() - height),
+                pos2(left + bar_width.max(1.0), rect.bottom()),
+            );
+            let color = if Some(*index) == selected {
+                Color32::from_rgb(255, 200, 60)
+            } else {
+                Color32::from_rgb(80, 150, 220)
+            };
+            painter.rect_filled(bar, 0.0, color);
+        }
+
+        // A line at 60 fps for reference
+        let target = rect.bottom() - (1000.0 / 60.0) / max_milliseconds * rect.height();
+        painter.line_segment(
+            [pos2(rect.left(), target), pos2(rect.right(), target)],
+            Stroke::new(1.0, Color32::from_rgb(120, 200, 120)),
+        );
+
+        if response.clicked() {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let position = ((pointer.x - rect.left()) / bar_width) as usize;
+                if let Some((index, _)) = history.get(position) {
+                    self.selected_frame = Some(*index);
+                }
+            }
+        }
+    }
+}
+
+fn flame_graph(ui: &mut Ui, frame: &FrameProfile) {
+    let rows = frame
+        .scopes
+        .iter()
+        .map(|scope| scope.depth + 1)
+        .max()
+        .unwrap_or(1);
+    let width = ui.available_width();
+    let (response, painter) = ui.allocate_painter(
+        vec2(width, rows as f32 * ProfilerWindow::ROW_HEIGHT),
+        Sense::hover(),
+    );
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+    let frame_milliseconds = milliseconds(frame.duration).max(f32::EPSILON);
+    let mut hovered = None;
+    for scope in frame.scopes.iter() {
+        let left = rect.left() + milliseconds(scope.start) / frame_milliseconds * width;
+        let right = rect.left() + milliseconds(scope.end()) / frame_milliseconds * width;
+        let top = rect.top() + scope.depth as f32 * ProfilerWindow::ROW_HEIGHT;
+        let bar = Rect::from_min_max(
+            pos2(left, top),
+            pos2(
+                right.max(left + 1.0),
+                top + ProfilerWindow::ROW_HEIGHT - 1.0,
+            ),
+        );
+        painter.rect_filled(bar, 2.0, scope_color(scope.name));
+        if bar.width() > 40.0 {
+            painter.text(
+                pos2(bar.left() + 4.0, bar.center().y),
+                Align2::LEFT_CENTER,
+                scope.name,
+                TextStyle::Small,
+                Color32::BLACK,
+            );
+        }
+        if response
+            .hover_pos()
+            .map_or(false, |pointer| bar.contains(pointer))
+        {
+            hovered = Some(scope);
+        }
+    }
+
+    if let Some(scope) = hovered {
+        response.on_hover_text(format!(
+            "{}\n{:.3} ms\n{}",
+            scope.name,
+            milliseconds(scope.duration),
+            scope.location
+        ));
+    }
+}
+
+fn gpu_timings_widget(ui: &mut Ui, gpu_timings: &[GpuTiming]) {
+    if gpu_timings.is_empty() {
+        ui.label("No gpu timings are available");
+        return;
+    }
+    Grid::new("profiler_gpu_timings")
+        .striped(true)
+        .show(ui, |ui| {
+            for timing in gpu_timings.iter() {
+                ui.label(&timing.name);
+                ui.label(format!("{:.3} ms", timing.milliseconds));
+                ui.end_row();
+            }
+            ui.label("Total");
+            ui.label(format!(
+                "{:.3} ms",
+                gpu_timings
+                    .iter()
+                    .map(|timing| timing.milliseconds)
+                    .sum::<f64>()
+            ));
+            ui.end_row();
+        });
+}
+
+fn render_stats_widget(ui: &mut Ui, stats: &RenderStats) {
+    Grid::new("profiler_render_stats")
+        .striped(true)
+        .show(ui, |ui| {
+            for (label, value) in [
+                ("Draw Calls", stats.draw_calls),
+                ("Instances", stats.instances),
+                ("Triangles", stats.triangles),
+                ("Pipeline Switches", stats.pipeline_switches),
+                ("Compute Dispatches", stats.dispatches),
+                ("Downscaled Textures", stats.downscaled_textures),
+                ("Evicted Textures", stats.evicted_textures),
+            ] {
+                ui.label(label);
+                ui.label(value.to_string());
+                ui.end_row();
+            }
+            for (label, bytes) in [
+                ("Buffer Memory", stats.buffer_memory),
+                ("Geometry Memory", stats.geometry_memory),
+                ("Uniform Memory", stats.uniform_memory),
+                ("Texture Memory", stats.texture_memory),
+                ("Render Target Memory", stats.render_target_memory),
+                ("Total Memory", stats.total_memory()),
+            ] {
+                ui.label(label);
+                ui.label(format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0)));
+                ui.end_row();
+            }
+        });
+}
+
+fn occlusion_stats_widget(ui: &mut Ui, stats: &OcclusionStats) {
+    Grid::new("profiler_occlusion_stats")
+        .striped(true)
+        .show(ui, |ui| {
+            for (label, value) in [
+                ("Occluders", stats.occluders),
+                ("Meshes Tested", stats.tested),
+                ("Outside View", stats.frustum_culled),
+                ("Occluded", stats.occluded),
+                ("Visible", stats.visible()),
+            ] {
+                ui.label(label);
+                ui.label(value.to_string());
+                ui.end_row();
+            }
+        });
+}
+
+fn milliseconds(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+// Each scope name keeps the same color from frame to frame
+fn scope_color(name: &str) -> Color32 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    let hash = hasher.finish();
+    let channel = |shift: u64| 120 + ((hash >> shift) & 0x7f) as u8;
+    Color32::from_rgb(channel(0), channel(8), channel(16))
+}
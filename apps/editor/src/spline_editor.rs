@@ -0,0 +1,193 @@
+use anyhow::Result;
+use dragonglass::{
+    app::{Command, Resources, SplineCommand, TransformCommand},
+    gui::{
+        egui::{Color32, Stroke, Ui},
+        egui_gizmo::GizmoMode,
+        world_to_screen, GizmoWidget,
+    },
+    world::{legion::Entity, EntityStore, Spline, SplinePoint, Transform},
+};
+use nalgebra_glm as glm;
+
+// What a control point was stored in before it was moved
+enum PointEdit {
+    Spline(Entity, Spline),
+    Transform(Entity, Transform),
+}
+
+// Draws the selected entity's spline with a handle on each control point.
+// Clicking a handle moves that point with the gizmo instead of the entity, and clicking it again lets it go.
+#[derive(Default)]
+pub struct SplineEditor {
+    spline: Option<Entity>,
+    point: Option<usize>,
+    // Clicks over a handle select it rather than picking what is behind it
+    pub hovered: bool,
+    edit: Option<PointEdit>,
+}
+
+impl SplineEditor {
+    const HANDLE_RADIUS: f32 = 6.0;
+
+    // Returns whether a control point has the gizmo, so the entity's gizmo isn't shown
+    pub fn render(
+        &mut self,
+        ui: &mut Ui,
+        resources: &mut Resources,
+        gizmo: &mut GizmoWidget,
+        selected_entity: Option<Entity>,
+    ) -> Result<bool> {
+        self.hovered = false;
+        let entity = match selected_entity {
+            Some(entity)
+                if resources
+                    .world
+                    .ecs
+                    .entry_ref(entity)
+                    .map_or(false, |entry| entry.get_component::<Spline>().is_ok()) =>
+            {
+                entity
+            }
+            _ => {
+                self.spline = None;
+                self.point = None;
+                return Ok(false);
+            }
+        };
+        if self.spline != Some(entity) {
+            self.spline = Some(entity);
+            self.point = None;
+        }
+
+        resources
+            .world
+            .draw_spline(entity, glm::vec4(1.0, 0.6, 0.1, 1.0))?;
+
+        let viewport = resources.renderer.viewport();
+        let (projection, view) = resources
+            .world
+            .active_camera_matrices(viewport.aspect_ratio())?;
+        let rect = resources.viewport_rect();
+        let view_projection = projection * view;
+        let positions = resources.world.spline_positions(entity)?;
+        if self.point.map_or(false, |point| point >= positions.len()) {
+            self.point = None;
+        }
+
+        let pointer = ui.input().pointer.hover_pos();
+        let clicked = ui.input().pointer.primary_clicked();
+        for (index, position) in positions.iter().enumerate() {
+            let center = match world_to_screen(rect, &view_projection, position) {
+                Some(center) => center,
+                None => continue,
+            };
+            let hovered = pointer.map_or(false, |pointer| {
+                pointer.distance(center) <= Self::HANDLE_RADIUS * 1.5
+            });
+            let color = if self.point == Some(index) {
+                Color32::GOLD
+            } else if hovered {
+                Color32::WHITE
+            } else {
+                Color32::from_rgb(255, 153, 26)
+            };
+            ui.painter().circle(
+                center,
+                Self::HANDLE_RADIUS,
+                color,
+                Stroke::new(1.0, Color32::BLACK),
+            );
+            if hovered {
+                self.hovered = true;
+                if clicked {
+                    self.point = if self.point == Some(index) {
+                        None
+                    } else {
+                        Some(index)
+                    };
+                }
+            }
+        }
+
+        let index = match self.point {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+
+        // Control points only have a position to move
+        let mode = gizmo.mode;
+        gizmo.mode = GizmoMode::Translate;
+        let result = gizmo.render(ui, glm::translation(&positions[index]), view, projection);
+        gizmo.mode = mode;
+        if let Some(result) = result {
+            let matrix: glm::Mat4 = result.transform.into();
+            let position = Transform::from(matrix).translation;
+            self.move_point(resources, entity, index, &position)?;
+        }
+        Ok(true)
+    }
+
+    // Returns a command for the point that was moved once the pointer is released,
+    // so a whole drag is undone at once
+    pub fn finish_edit(&mut self, resources: &Resources) -> Option<Box<dyn Command>> {
+        if resources.gui.context().input().pointer.any_down() {
+            return None;
+        }
+        let entry = |entity: Entity| resources.world.ecs.entry_ref(entity).ok();
+        match self.edit.take()? {
+            PointEdit::Spline(entity, before) => {
+                let after = entry(entity)?.get_component::<Spline>().ok()?.clone();
+                Some(Box::new(SplineCommand::new(entity, before, after)))
+            }
+            PointEdit::Transform(entity, before) => {
+                let after = *entry(entity)?.get_component::<Transform>().ok()?;
+                Some(Box::new(TransformCommand::new(entity, before, after)))
+            }
+        }
+    }
+
+    fn move_point(
+        &mut self,
+        resources: &mut Resources,
+        entity: Entity,
+        index: usize,
+        position: &glm::Vec3,
+    ) -> Result<()> {
+        let world = &mut *resources.world;
+        let point = world
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Spline>()?
+            .points[index];
+        // Points are moved in the space of whatever they are stored relative to
+        let (target, space) = match point {
+            SplinePoint::Position(_) => (entity, Some(entity)),
+            SplinePoint::Entity(point_entity) => (point_entity, world.parent_of(point_entity)),
+        };
+        let local = match space {
+            Some(space) => world.world_to_local_point(space, position)?,
+            None => *position,
+        };
+        let mut entry = world.ecs.entry_mut(target)?;
+        if self.edit.is_none() {
+            self.edit = Some(match point {
+                SplinePoint::Position(_) => {
+                    PointEdit::Spline(target, entry.get_component::<Spline>()?.clone())
+                }
+                SplinePoint::Entity(_) => {
+                    PointEdit::Transform(target, *entry.get_component::<Transform>()?)
+                }
+            });
+        }
+        match point {
+            SplinePoint::Position(_) => {
+                entry.get_component_mut::<Spline>()?.points[index] = SplinePoint::Position(local);
+            }
+            SplinePoint::Entity(_) => {
+                entry.get_component_mut::<Transform>()?.translation = local;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{ensure, Result};
+use dragonglass::{
+    app::{History, Resources, Worlds},
+    gui::egui::{SelectableLabel, Ui},
+    world::{legion::Entity, World},
+};
+use std::mem::take;
+
+pub enum TabAction {
+    Select(usize),
+    Close(usize),
+    New,
+}
+
+pub struct SceneTab {
+    pub name: String,
+    // The key of the tab's world in the application's worlds
+    world: String,
+    selected_entity: Option<Entity>,
+    history: History,
+}
+
+impl SceneTab {
+    fn new(name: &str, world: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            world: world.to_string(),
+            selected_entity: None,
+            history: History::default(),
+        }
+    }
+}
+
+pub struct SceneTabs {
+    tabs: Vec<SceneTab>,
+    active: usize,
+    next_world_id: usize,
+}
+
+impl Default for SceneTabs {
+    fn default() -> Self {
+        Self {
+            tabs: vec![SceneTab::new("Untitled", Worlds::DEFAULT_WORLD)],
+            active: 0,
+            next_world_id: 0,
+        }
+    }
+}
+
+impl SceneTabs {
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn active_tab_mut(&mut self) -> &mut SceneTab {
+        &mut self.tabs[self.active]
+    }
+
+    // Loads the world alongside the others without activating it
+    pub fn add(&mut self, name: &str, world: World, worlds: &mut Worlds) -> Result<usize> {
+        let key = loop {
+            self.next_world_id += 1;
+            let key = format!("scene-{}", self.next_world_id);
+            if !worlds.contains(&key) {
+                break key;
+            }
+        };
+        worlds.insert(&key, world)?;
+        self.tabs.push(SceneTab::new(name, &key));
+        Ok(self.tabs.len() - 1)
+    }
+
+    pub fn switch(
+        &mut self,
+        index: usize,
+        resources: &mut Resources,
+        selected_entity: &mut Option<Entity>,
+        history: &mut History,
+    ) -> Result<()> {
+        ensure!(
+            index < self.tabs.len(),
+            "No scene tab exists at index {}",
+            index
+        );
+        if index == self.active {
+            return Ok(());
+        }
+
+        resources.activate_world(&self.tabs[index].world)?;
+
+        let active_tab = &mut self.tabs[self.active];
+        active_tab.selected_entity = selected_entity.take();
+        active_tab.history = take(history);
+
+        let next_tab = &mut self.tabs[index];
+        *selected_entity = next_tab.selected_entity.take();
+        *history = take(&mut next_tab.history);
+
+        self.active = index;
+        Ok(())
+    }
+
+    pub fn close(
+        &mut self,
+        index: usize,
+        resources: &mut Resources,
+        selected_entity: &mut Option<Entity>,
+        history: &mut History,
+    ) -> Result<()> {
+        ensure!(
+            index < self.tabs.len(),
+            "No scene tab exists at index {}",
+            index
+        );
+        ensure!(self.tabs.len() > 1, "The last open scene cannot be closed");
+
+        if index == self.active {
+            let neighbor = if index == 0 { 1 } else { index - 1 };
+            self.switch(neighbor, resources, selected_entity, history)?;
+        }
+
+        let tab = self.tabs.remove(index);
+        resources.worlds.remove(&tab.world)?;
+        if self.active > index {
+            self.active -= 1;
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, ui: &mut Ui) -> Option<TabAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            for (index, tab) in self.tabs.iter().enumerate() {
+                let response = ui.add(SelectableLabel::new(index == self.active, &tab.name));
+                if response.clicked() {
+                    action = Some(TabAction::Select(index));
+                }
+                if self.tabs.len() > 1 && ui.small_button("x").clicked() {
+                    action = Some(TabAction::Close(index));
+                }
+                ui.separator();
+            }
+            if ui.button("+").clicked() {
+                action = Some(TabAction::New);
+            }
+        });
+        action
+    }
+}
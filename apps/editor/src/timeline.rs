@@ -0,0 +1,289 @@
+use anyhow::Result;
+use dragonglass::{
+    gui::egui::{
+        self, pos2, vec2, Align2, Color32, CtxRef, DragValue, Rect, Sense, Stroke, TextStyle, Ui,
+    },
+    world::{Animation, Ecs, Entity, EntityStore, Name, TransformationSet, World},
+};
+
+// Lists the world's animations and shows the keyframes of the selected one on a timeline per target entity.
+// Dragging across the timeline scrubs the animation, which poses its targets even while editing.
+// Notifies are shown above the tracks and can be added at the playhead.
+#[derive(Default)]
+pub struct AnimationTimeline {
+    pub open: bool,
+    selected: usize,
+    notify_name: String,
+}
+
+const NOTIFY_COLOR: Color32 = Color32::from_rgb(240, 200, 60);
+
+impl AnimationTimeline {
+    const ROW_HEIGHT: f32 = 20.0;
+    const LABEL_WIDTH: f32 = 140.0;
+
+    pub fn render(&mut self, context: &CtxRef, world: &mut World) -> Result<()> {
+        let mut open = self.open;
+        let mut scrubbed = false;
+        let mut play = false;
+        egui::Window::new("Animation Timeline")
+            .open(&mut open)
+            .default_size([700.0, 300.0])
+            .show(context, |ui| {
+                let animations = &mut world.assets.animations;
+                if animations.is_empty() {
+                    ui.label("The world has no animations");
+                    return;
+                }
+                self.selected = self.selected.min(animations.len() - 1);
+
+                ui.horizontal_wrapped(|ui| {
+                    for (index, animation) in animations.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected, index, animation.name.as_str());
+                    }
+                });
+                ui.separator();
+
+                let animation = &mut animations[self.selected];
+                scrubbed |= playback_controls(ui, animation, &mut play);
+                ui.separator();
+
+                let names = target_names(animation, &world.sim.ecs);
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    scrubbed |= timeline(ui, animation, &names);
+                    ui.separator();
+                    notifies_editor(ui, animation, &mut self.notify_name);
+                });
+            });
+        self.open = open;
+
+        if play {
+            world.play_animation(self.selected);
+        }
+        if scrubbed {
+            if let Some(animation) = world.assets.animations.get(self.selected) {
+                animation.sample(&mut world.sim.ecs)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns true when the playhead was moved.
+// Playing is left to the world, which stops the clips posing the same entities.
+fn playback_controls(ui: &mut Ui, animation: &mut Animation, play: &mut bool) -> bool {
+    let mut scrubbed = false;
+    ui.horizontal(|ui| {
+        let label = if animation.playing { "Pause" } else { "Play" };
+        if ui.button(label).clicked() {
+            // Finished animations start over when played again
+            if !animation.playing
+                && !animation.looping
+                && animation.time >= animation.max_animation_time
+            {
+                animation.set_time(0.0);
+            }
+            if animation.playing {
+                animation.playing = false;
+            } else {
+                *play = true;
+            }
+        }
+        if ui.button("Rewind").clicked() {
+            animation.set_time(0.0);
+            scrubbed = true;
+        }
+        ui.checkbox(&mut animation.looping, "Loop");
+        ui.label("Speed");
+        ui.add(
+            DragValue::new(&mut animation.speed)
+                .speed(0.01)
+                .clamp_range(-4.0..=4.0),
+        );
+
+        let mut time = animation.time;
+        ui.label("Time");
+        let response = ui.add(
+            DragValue::new(&mut time)
+                .speed(0.01)
+                .clamp_range(0.0..=animation.max_animation_time)
+                .suffix(" s"),
+        );
+        if response.changed() {
+            animation.set_time(time);
+            scrubbed = true;
+        }
+        ui.label(format!("/ {:.2} s", animation.max_animation_time));
+    });
+    scrubbed
+}
+
+// A row per target entity with a tick for each keyframe of its channels, colored by what they animate.
+// Returns true when the playhead was dragged.
+fn timeline(ui: &mut Ui, animation: &mut Animation, names: &[(Entity, String)]) -> bool {
+    let width = ui
+        .available_width()
+        .max(AnimationTimeline::LABEL_WIDTH + 50.0);
+    let height = (names.len() + 1) as f32 * AnimationTimeline::ROW_HEIGHT;
+    let (response, painter) = ui.allocate_painter(vec2(width, height), Sense::click_and_drag());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+    let track = Rect::from_min_max(
+        pos2(rect.left() + AnimationTimeline::LABEL_WIDTH, rect.top()),
+        rect.max,
+    );
+    let duration = animation.max_animation_time.max(f32::EPSILON);
+    let x_at = |time: f32| track.left() + time / duration * track.width();
+
+    // Whole seconds along the top row
+    let mut second = 0;
+    while second as f32 <= duration {
+        let x = x_at(second as f32);
+        painter.line_segment(
+            [pos2(x, track.top()), pos2(x, track.bottom())],
+            Stroke::new(1.0, Color32::from_gray(45)),
+        );
+        painter.text(
+            pos2(x + 2.0, track.top()),
+            Align2::LEFT_TOP,
+            format!("{}s", second),
+            TextStyle::Small,
+            Color32::GRAY,
+        );
+        second += 1;
+    }
+
+    for (row, (target, name)) in names.iter().enumerate() {
+        let top = rect.top() + (row + 1) as f32 * AnimationTimeline::ROW_HEIGHT;
+        let center = top + AnimationTimeline::ROW_HEIGHT / 2.0;
+        painter.text(
+            pos2(rect.left() + 4.0, center),
+            Align2::LEFT_CENTER,
+            name,
+            TextStyle::Small,
+            Color32::LIGHT_GRAY,
+        );
+        let channels = animation
+            .channels
+            .iter()
+            .filter(|channel| channel.target == *target);
+        for channel in channels {
+            let color = channel_color(&channel.transformations);
+            for time in channel.inputs.iter() {
+                let x = x_at(*time);
+                painter.line_segment(
+                    [
+                        pos2(x, top + 4.0),
+                        pos2(x, top + AnimationTimeline::ROW_HEIGHT - 4.0),
+                    ],
+                    Stroke::new(2.0, color),
+                );
+            }
+        }
+    }
+
+    for notify in animation.notifies.iter() {
+        let x = x_at(notify.time);
+        let marker = Rect::from_center_size(
+            pos2(x, track.top() + AnimationTimeline::ROW_HEIGHT / 2.0),
+            vec2(6.0, 6.0),
+        );
+        painter.rect_filled(marker, 1.0, NOTIFY_COLOR);
+        painter.text(
+            pos2(x + 5.0, marker.center().y),
+            Align2::LEFT_CENTER,
+            &notify.name,
+            TextStyle::Small,
+            NOTIFY_COLOR,
+        );
+    }
+
+    let playhead = x_at(animation.time);
+    painter.line_segment(
+        [pos2(playhead, rect.top()), pos2(playhead, rect.bottom())],
+        Stroke::new(2.0, Color32::from_rgb(255, 80, 80)),
+    );
+
+    match response.interact_pointer_pos() {
+        Some(pointer) if pointer.x >= track.left() => {
+            animation.set_time((pointer.x - track.left()) / track.width() * duration);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn notifies_editor(ui: &mut Ui, animation: &mut Animation, notify_name: &mut String) {
+    ui.horizontal(|ui| {
+        ui.label("Notify");
+        ui.text_edit_singleline(notify_name);
+        let add = egui::Button::new("Add at Playhead");
+        if ui
+            .add_enabled(!notify_name.trim().is_empty(), add)
+            .clicked()
+        {
+            animation.add_notify(notify_name.trim(), animation.time);
+            notify_name.clear();
+        }
+    });
+
+    let max_time = animation.max_animation_time;
+    let mut removed = None;
+    let mut moved = false;
+    for (index, notify) in animation.notifies.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.push_id(index, |ui| {
+                ui.text_edit_singleline(&mut notify.name);
+            });
+            moved |= ui
+                .add(
+                    DragValue::new(&mut notify.time)
+                        .speed(0.01)
+                        .clamp_range(0.0..=max_time)
+                        .suffix(" s"),
+                )
+                .changed();
+            if ui.button("Remove").clicked() {
+                removed = Some(index);
+            }
+        });
+    }
+    if let Some(index) = removed {
+        animation.notifies.remove(index);
+    }
+    if moved {
+        animation.sort_notifies();
+    }
+}
+
+// The animated entities in the order they first appear in the channels, named when they have a name
+fn target_names(animation: &Animation, ecs: &Ecs) -> Vec<(Entity, String)> {
+    let mut names: Vec<(Entity, String)> = Vec::new();
+    for channel in animation.channels.iter() {
+        if names.iter().any(|(target, _)| *target == channel.target) {
+            continue;
+        }
+        let name = ecs
+            .entry_ref(channel.target)
+            .ok()
+            .and_then(|entry| {
+                entry
+                    .get_component::<Name>()
+                    .ok()
+                    .map(|name| name.0.clone())
+            })
+            .unwrap_or_else(|| format!("{:?}", channel.target));
+        names.push((channel.target, name));
+    }
+    names
+}
+
+fn channel_color(transformations: &TransformationSet) -> Color32 {
+    match transformations {
+        TransformationSet::Translations(_) => Color32::from_rgb(230, 90, 90),
+        TransformationSet::Rotations(_) => Color32::from_rgb(90, 200, 90),
+        TransformationSet::Scales(_) => Color32::from_rgb(90, 140, 240),
+        TransformationSet::MorphTargetWeights(_) => Color32::from_rgb(230, 200, 80),
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use dragonglass::{
+    app::{Resources, VertexPaintCommand},
+    gui::egui::{self, CtxRef, Slider},
+    world::{paint_vertex_colors, raycast_mesh, Entity, VertexBrush, VertexColors, World},
+};
+use nalgebra_glm as glm;
+
+// Paints vertex colors onto the selected mesh while the left mouse button is held over it
+#[derive(Default)]
+pub struct VertexPainter {
+    pub open: bool,
+    pub brush: VertexBrush,
+    // The colors the vertices touched by the current stroke had before it started
+    stroke: Option<VertexColors>,
+}
+
+impl VertexPainter {
+    pub fn render(&mut self, context: &CtxRef) {
+        let mut open = self.open;
+        egui::Window::new("Vertex Paint")
+            .open(&mut open)
+            .resizable(false)
+            .show(context, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Color");
+                    let mut color = [self.brush.color.x, self.brush.color.y, self.brush.color.z];
+                    if ui.color_edit_button_rgb(&mut color).changed() {
+                        self.brush.color = glm::vec3(color[0], color[1], color[2]);
+                    }
+                });
+                ui.add(Slider::new(&mut self.brush.radius, 0.01..=5.0).text("Radius"));
+                ui.add(Slider::new(&mut self.brush.falloff, 0.0..=1.0).text("Falloff"));
+                ui.add(Slider::new(&mut self.brush.strength, 0.0..=1.0).text("Strength"));
+                ui.separator();
+                ui.label("Hold the left mouse button over the selected mesh to paint");
+            });
+        self.open = open;
+    }
+
+    // Returns a command for the stroke once the mouse button is released
+    pub fn update(
+        &mut self,
+        resources: &mut Resources,
+        selected_entity: Option<Entity>,
+    ) -> Result<Option<VertexPaintCommand>> {
+        let painting = self.open
+            && resources.input.mouse.is_left_clicked
+            && !resources.gui.wants_pointer_input();
+        let entity = match selected_entity {
+            Some(entity) if painting => entity,
+            _ => return self.finish_stroke(resources.world),
+        };
+
+        let ray = resources
+            .world
+            .mouse_ray(&resources.mouse_ray_configuration()?)?;
+        let distance = match raycast_mesh(resources.world, entity, &ray)? {
+            Some(distance) => distance,
+            None => return Ok(None),
+        };
+        let center = ray.origin.coords + ray.dir * distance;
+
+        let previous = paint_vertex_colors(resources.world, entity, &center, &self.brush)?;
+        let range = match previous.vertex_range(resources.world) {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+
+        // The selection can't change mid-stroke, so every dab paints the same mesh
+        let stroke = self
+            .stroke
+            .get_or_insert_with(|| VertexColors::new(&previous.mesh));
+        for (vertex, color) in previous.colors.into_iter() {
+            stroke.colors.entry(vertex).or_insert(color);
+        }
+
+        // Only the vertices this dab touched are uploaded
+        resources.renderer.update_vertices(resources.world, range)?;
+        Ok(None)
+    }
+
+    fn finish_stroke(&mut self, world: &World) -> Result<Option<VertexPaintCommand>> {
+        let before = match self.stroke.take() {
+            Some(before) => before,
+            None => return Ok(None),
+        };
+        let after = before.current(world)?;
+        Ok(Some(VertexPaintCommand::new(before, after)))
+    }
+}
@@ -1,10 +1,15 @@
 use anyhow::{Context, Result};
 use dragonglass::{
     app::Resources,
-    gui::egui::{DragValue, Ui},
-    world::{Entity, RigidBody, Transform},
+    gui::egui::{ComboBox, DragValue, Slider, Ui},
+    world::{
+        AlphaMode, Clock, Entity, Light, Material, MeshRender, ProbeBounds, ReflectionProbe,
+        RigidBody, Script, ShadowResolution, Static, Texture, Transform,
+    },
 };
+use log::warn;
 use nalgebra_glm as glm;
+use rfd::FileDialog;
 
 pub fn translation_widget(resources: &mut Resources, entity: Entity, ui: &mut Ui) -> Result<()> {
     let ecs = &mut resources.world.ecs;
@@ -102,3 +107,435 @@ pub fn scale_widget(resources: &mut Resources, entity: Entity, ui: &mut Ui) -> R
 
     Ok(())
 }
+
+pub fn static_widget(resources: &mut Resources, entity: Entity, ui: &mut Ui) -> Result<()> {
+    let mut entry = resources
+        .world
+        .ecs
+        .entry(entity)
+        .context("Failed to find entity!")?;
+
+    let mut is_static = entry.get_component::<Static>().is_ok();
+    if ui.checkbox(&mut is_static, "Static").changed() {
+        if is_static {
+            entry.add_component(Static);
+        } else {
+            entry.remove_component::<Static>();
+        }
+    }
+
+    Ok(())
+}
+
+// Probes are only captured when they are baked, so edits show up after the next bake
+pub fn reflection_probe_widget(
+    resources: &mut Resources,
+    entity: Entity,
+    ui: &mut Ui,
+) -> Result<()> {
+    let mut entry = resources
+        .world
+        .ecs
+        .entry(entity)
+        .context("Failed to find entity!")?;
+
+    let mut has_probe = entry.get_component::<ReflectionProbe>().is_ok();
+    if ui.checkbox(&mut has_probe, "Reflection Probe").changed() {
+        if has_probe {
+            entry.add_component(ReflectionProbe::default());
+        } else {
+            entry.remove_component::<ReflectionProbe>();
+        }
+    }
+
+    let probe = match entry.get_component_mut::<ReflectionProbe>() {
+        Ok(probe) => probe,
+        Err(_) => return Ok(()),
+    };
+
+    let is_box = matches!(probe.bounds, ProbeBounds::Box { .. });
+    ComboBox::from_label("Bounds")
+        .selected_text(if is_box { "Box" } else { "Sphere" })
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(is_box, "Box").clicked() && !is_box {
+                probe.bounds = ProbeBounds::Box {
+                    half_extents: glm::vec3(5.0, 5.0, 5.0),
+                };
+            }
+            if ui.selectable_label(!is_box, "Sphere").clicked() && is_box {
+                probe.bounds = ProbeBounds::Sphere { radius: 5.0 };
+            }
+        });
+    match &mut probe.bounds {
+        ProbeBounds::Box { half_extents } => {
+            ui.horizontal(|ui| {
+                ui.label("Half Extents");
+                ui.add(DragValue::new(&mut half_extents.x).speed(0.1));
+                ui.add(DragValue::new(&mut half_extents.y).speed(0.1));
+                ui.add(DragValue::new(&mut half_extents.z).speed(0.1));
+            });
+        }
+        ProbeBounds::Sphere { radius } => {
+            ui.horizontal(|ui| {
+                ui.label("Radius");
+                ui.add(DragValue::new(radius).speed(0.1).clamp_range(0.0..=1000.0));
+            });
+        }
+    }
+    ComboBox::from_label("Probe Resolution")
+        .selected_text(format!("{}", probe.resolution))
+        .show_ui(ui, |ui| {
+            for resolution in [64, 128, 256, 512, 1024].iter().copied() {
+                ui.selectable_value(&mut probe.resolution, resolution, format!("{}", resolution));
+            }
+        });
+    ui.horizontal(|ui| {
+        ui.label("Blend Distance");
+        ui.add(
+            DragValue::new(&mut probe.blend_distance)
+                .speed(0.05)
+                .clamp_range(0.0..=100.0),
+        );
+    });
+
+    Ok(())
+}
+
+// Scripts only run while playing, and are reloaded when their file changes
+pub fn script_widget(resources: &mut Resources, entity: Entity, ui: &mut Ui) -> Result<()> {
+    let mut entry = resources
+        .world
+        .ecs
+        .entry(entity)
+        .context("Failed to find entity!")?;
+
+    let mut has_script = entry.get_component::<Script>().is_ok();
+    if ui.checkbox(&mut has_script, "Script").changed() {
+        if has_script {
+            entry.add_component(Script::default());
+        } else {
+            entry.remove_component::<Script>();
+        }
+    }
+
+    let script = match entry.get_component_mut::<Script>() {
+        Ok(script) => script,
+        Err(_) => return Ok(()),
+    };
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut script.path);
+        if ui.button("Browse...").clicked() {
+            let path = FileDialog::new()
+                .add_filter("Rhai Script", &["rhai"])
+                .pick_file();
+            if let Some(path) = path {
+                script.path = path.display().to_string();
+            }
+        }
+    });
+
+    Ok(())
+}
+
+pub fn shadow_widget(resources: &mut Resources, entity: Entity, ui: &mut Ui) -> Result<()> {
+    let mut entry = resources
+        .world
+        .ecs
+        .entry(entity)
+        .context("Failed to find entity!")?;
+    let shadows = match entry.get_component_mut::<Light>() {
+        Ok(light) => &mut light.shadows,
+        Err(_) => return Ok(()),
+    };
+
+    ui.label("Shadows");
+    ui.checkbox(&mut shadows.casts_shadows, "Cast Shadows");
+    ComboBox::from_label("Resolution")
+        .selected_text(format!("{}", shadows.resolution.size()))
+        .show_ui(ui, |ui| {
+            for resolution in ShadowResolution::ALL.iter().copied() {
+                ui.selectable_value(
+                    &mut shadows.resolution,
+                    resolution,
+                    format!("{}", resolution.size()),
+                );
+            }
+        });
+    ui.horizontal(|ui| {
+        ui.label("Depth Bias");
+        ui.add(
+            DragValue::new(&mut shadows.depth_bias)
+                .speed(0.0005)
+                .clamp_range(0.0..=0.1),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Normal Offset");
+        ui.add(
+            DragValue::new(&mut shadows.normal_offset_bias)
+                .speed(0.001)
+                .clamp_range(0.0..=1.0),
+        );
+    });
+    ui.add(Slider::new(&mut shadows.blur_radius, 0..=8).text("Blur Radius"));
+
+    Ok(())
+}
+
+// Returns true when the user asks to unlink the entity from the other instances of its mesh
+pub fn instance_widget(resources: &mut Resources, entity: Entity, ui: &mut Ui) -> Result<bool> {
+    let entry = resources.world.ecs.entry_ref(entity)?;
+    let mesh_name = match entry.get_component::<MeshRender>() {
+        Ok(mesh_render) => mesh_render.name.to_string(),
+        Err(_) => return Ok(false),
+    };
+
+    let instances = resources.world.mesh_instances(&mesh_name).len();
+    if instances < 2 {
+        return Ok(false);
+    }
+
+    ui.label(format!(
+        "Linked instance of '{}' ({} total)",
+        mesh_name, instances
+    ));
+    Ok(ui.button("Unlink").clicked())
+}
+
+// Edits the materials used by the entity's mesh, which are shared with every other primitive using them.
+// Returns the index of the material that was edited along with how it was before the edit,
+// so the editor can record the edit once it is finished.
+pub fn material_widget(
+    resources: &mut Resources,
+    entity: Entity,
+    ui: &mut Ui,
+) -> Result<Option<(usize, Material)>> {
+    let entry = resources.world.ecs.entry_ref(entity)?;
+    let mesh_name = match entry.get_component::<MeshRender>() {
+        Ok(mesh_render) => mesh_render.name.to_string(),
+        Err(_) => return Ok(None),
+    };
+    let mut material_indices = match resources.world.assets.geometry.meshes.get(&mesh_name) {
+        Some(mesh) => mesh
+            .primitives
+            .iter()
+            .filter_map(|primitive| primitive.material_index)
+            .collect::<Vec<_>>(),
+        None => return Ok(None),
+    };
+    material_indices.sort_unstable();
+    material_indices.dedup();
+    if material_indices.is_empty() {
+        return Ok(None);
+    }
+
+    ui.heading("Materials");
+    if ui.button("Load Texture...").clicked() {
+        let path = FileDialog::new()
+            .add_filter("Image", &["png", "jpg", "jpeg", "tga", "bmp", "ktx2"])
+            .pick_file();
+        if let Some(path) = path {
+            // The renderer uploads it into a free slot, after which any texture slot can pick it
+            match Texture::from_file(&path) {
+                Ok(texture) => {
+                    resources.world.add_texture(texture);
+                }
+                Err(error) => warn!("Failed to load texture {}: {}", path.display(), error),
+            }
+        }
+    }
+    let number_of_textures = resources.world.assets.textures.len();
+    let mut edit = None;
+    for index in material_indices.into_iter() {
+        let mut material = resources.world.assets.material_at_index(index)?.clone();
+        let mut changed = false;
+        ui.collapsing(format!("{} ({})", material.name, index), |ui| {
+            changed |= material_properties(ui, &mut material);
+            ui.label("Textures");
+            let slots = [
+                (
+                    "Base Color",
+                    &mut material.color_texture_index,
+                    &mut material.color_texture_set,
+                ),
+                (
+                    "Metallic Roughness",
+                    &mut material.metallic_roughness_texture_index,
+                    &mut material.metallic_roughness_texture_set,
+                ),
+                (
+                    "Normal",
+                    &mut material.normal_texture_index,
+                    &mut material.normal_texture_set,
+                ),
+                (
+                    "Occlusion",
+                    &mut material.occlusion_texture_index,
+                    &mut material.occlusion_texture_set,
+                ),
+                (
+                    "Emissive",
+                    &mut material.emissive_texture_index,
+                    &mut material.emissive_texture_set,
+                ),
+            ];
+            for (label, texture_index, texture_set) in slots {
+                changed |= texture_slot(
+                    ui,
+                    (index, label),
+                    label,
+                    texture_index,
+                    texture_set,
+                    number_of_textures,
+                );
+            }
+        });
+        if changed {
+            let before = resources.world.assets.material_at_index(index)?.clone();
+            resources.world.update_material(index, material)?;
+            edit.get_or_insert((index, before));
+        }
+    }
+
+    Ok(edit)
+}
+
+fn material_properties(ui: &mut Ui, material: &mut Material) -> bool {
+    let mut changed = false;
+
+    let mut base_color = [
+        material.base_color_factor.x,
+        material.base_color_factor.y,
+        material.base_color_factor.z,
+        material.base_color_factor.w,
+    ];
+    ui.horizontal(|ui| {
+        ui.label("Base Color");
+        if ui
+            .color_edit_button_rgba_unmultiplied(&mut base_color)
+            .changed()
+        {
+            material.base_color_factor = glm::Vec4::from(base_color);
+            changed = true;
+        }
+    });
+
+    let mut emissive = [
+        material.emissive_factor.x,
+        material.emissive_factor.y,
+        material.emissive_factor.z,
+    ];
+    ui.horizontal(|ui| {
+        ui.label("Emissive");
+        if ui.color_edit_button_rgb(&mut emissive).changed() {
+            material.emissive_factor = glm::Vec3::from(emissive);
+            changed = true;
+        }
+    });
+
+    let sliders = [
+        ("Metallic", &mut material.metallic_factor, 0.0..=1.0),
+        ("Roughness", &mut material.roughness_factor, 0.0..=1.0),
+        (
+            "Emissive Strength",
+            &mut material.emissive_strength,
+            0.0..=100.0,
+        ),
+        (
+            "Normal Scale",
+            &mut material.normal_texture_scale,
+            0.0..=4.0,
+        ),
+        (
+            "Occlusion Strength",
+            &mut material.occlusion_strength,
+            0.0..=1.0,
+        ),
+        ("Clearcoat", &mut material.clearcoat_factor, 0.0..=1.0),
+        (
+            "Clearcoat Roughness",
+            &mut material.clearcoat_roughness_factor,
+            0.0..=1.0,
+        ),
+        ("Transmission", &mut material.transmission_factor, 0.0..=1.0),
+        ("IOR", &mut material.ior, 1.0..=3.0),
+        ("Alpha Cutoff", &mut material.alpha_cutoff, 0.0..=1.0),
+    ];
+    for (label, value, range) in sliders {
+        changed |= ui.add(Slider::new(value, range).text(label)).changed();
+    }
+
+    ComboBox::from_id_source((material.name.as_str(), "alpha_mode"))
+        .selected_text(format!("{:?}", material.alpha_mode))
+        .show_ui(ui, |ui| {
+            for alpha_mode in [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend] {
+                changed |= ui
+                    .selectable_value(
+                        &mut material.alpha_mode,
+                        alpha_mode,
+                        format!("{:?}", alpha_mode),
+                    )
+                    .changed();
+            }
+        });
+
+    changed |= ui
+        .checkbox(&mut material.double_sided, "Double Sided")
+        .changed();
+
+    changed
+}
+
+// Texture indices of -1 mean the slot is empty
+fn texture_slot(
+    ui: &mut Ui,
+    id: impl std::hash::Hash,
+    label: &str,
+    texture_index: &mut i32,
+    texture_set: &mut i32,
+    number_of_textures: usize,
+) -> bool {
+    let texture_name = |index: i32| {
+        if index < 0 {
+            "None".to_string()
+        } else {
+            format!("Texture {}", index)
+        }
+    };
+    let previous = *texture_index;
+    ComboBox::from_id_source(id)
+        .selected_text(format!("{}: {}", label, texture_name(*texture_index)))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(texture_index, -1, texture_name(-1));
+            for index in 0..number_of_textures as i32 {
+                ui.selectable_value(texture_index, index, texture_name(index));
+            }
+        });
+    if *texture_index == previous {
+        return false;
+    }
+    // Newly assigned textures read the first set of texture coordinates
+    if *texture_index != -1 && *texture_set < 0 {
+        *texture_set = 0;
+    }
+    true
+}
+
+// Slow motion for watching physics interactions, shown next to the play controls
+pub fn time_scale_widget(ui: &mut Ui, clock: &mut Clock) {
+    let mut scale = clock.time_scale;
+    ui.label("Time Scale");
+    if ui
+        .add(Slider::new(&mut scale, 0.0..=2.0).fixed_decimals(2))
+        .changed()
+    {
+        clock.set_time_scale(scale);
+    }
+    if ui.button("1x").clicked() {
+        clock.set_time_scale(1.0);
+    }
+    if clock.paused {
+        ui.label("Paused");
+    }
+}
@@ -1,28 +1,57 @@
 use anyhow::{Context, Result};
 use dragonglass::{
-    app::{run_application, App, AppConfig, MouseLook, Resources},
+    app::{
+        bind_vehicle_controls, drive_vehicle, run_application, tasks, App, AppConfig, BehaviorNode,
+        BehaviorTreeSystem, Console, InputSource, MouseLook, Resources, Status,
+    },
     audio::Audio,
+    network::{NetworkClient, NetworkServer},
     render::Backend,
+    scripting::ScriptSystem,
     world::{
-        Camera as WorldCamera, Entity, EntityStore, Hidden, IntoQuery, Light, LightKind,
-        MeshRender, PerspectiveCamera, Projection, RigidBody, Transform,
+        BehaviorTree, BlackboardValue, Camera as WorldCamera, Entity, EntityStore, Hidden,
+        IntoQuery, Layers, Light, LightKind, MeshRender, PathFollower, PerspectiveCamera,
+        Projection, RaycastVehicle, RigidBody, RigidBodySettings, Shape, Spline, SplinePoint, Text,
+        TextSpace, Transform,
     },
 };
 use nalgebra_glm as glm;
-use rapier3d::{dynamics::RigidBodyBuilder, geometry::InteractionGroups, prelude::RigidBodyType};
-use winit::event::{ElementState, VirtualKeyCode};
+use rapier3d::{geometry::InteractionGroups, prelude::RigidBodyType};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 
 // TODO: Create trigger with event on collision
 // TODO: Visualize triangle mesh colliders as wireframes in renderer?
 
-const OBJECT_COLLISION_GROUP: InteractionGroups = InteractionGroups::new(0b100, 0b111);
-const PLAYER_COLLISION_GROUP: InteractionGroups = InteractionGroups::new(0b010, 0b101);
-const LEVEL_COLLISION_GROUP: InteractionGroups = InteractionGroups::new(0b001, 0b110);
+const LEVEL_LAYER: Layers = Layers::layer(0);
+const PLAYER_LAYER: Layers = Layers::layer(1);
+const OBJECT_LAYER: Layers = Layers::layer(2);
+
+// Objects collide with everything, while the player and the level only collide with each other and objects
+const OBJECT_COLLISION_GROUP: InteractionGroups =
+    OBJECT_LAYER.collision_groups(LEVEL_LAYER.union(PLAYER_LAYER).union(OBJECT_LAYER));
+const PLAYER_COLLISION_GROUP: InteractionGroups =
+    PLAYER_LAYER.collision_groups(LEVEL_LAYER.union(OBJECT_LAYER));
+const LEVEL_COLLISION_GROUP: InteractionGroups =
+    LEVEL_LAYER.collision_groups(PLAYER_LAYER.union(OBJECT_LAYER));
+
+// Players can rebind controls here without recompiling
+const BINDINGS_FILE: &str = "game_bindings.toml";
 
 #[derive(Default)]
 pub struct Game {
     player: Option<Entity>,
+    car: Option<Entity>,
     camera: MouseLook,
+    scripts: ScriptSystem,
+    behaviors: BehaviorTreeSystem,
+    console: Console,
+    network: Option<Network>,
+}
+
+// Hosts replicate the player and enemies to clients, which show them as cubes
+pub enum Network {
+    Server(NetworkServer),
+    Client(NetworkClient),
 }
 
 impl App for Game {
@@ -40,9 +69,38 @@ impl App for Game {
             .physics
             .set_gravity(glm::vec3(0.0, -4.0, 0.0));
 
-        resources.set_fullscreen();
         self.camera.orientation.sensitivity = glm::vec2(0.05, 0.05);
 
+        resources
+            .cvars
+            .register("game.move_speed", "How fast the player walks", 2.0_f32)
+            .persistent();
+        resources
+            .cvars
+            .register(
+                "game.jump_strength",
+                "The impulse applied when jumping",
+                0.5_f32,
+            )
+            .persistent();
+
+        {
+            let actions = &mut resources.input.actions;
+            actions.bind_action("Jump", InputSource::Key(VirtualKeyCode::Space));
+            actions.bind_axis(
+                "MoveForward",
+                InputSource::Key(VirtualKeyCode::W),
+                InputSource::Key(VirtualKeyCode::S),
+            );
+            actions.bind_axis(
+                "MoveRight",
+                InputSource::Key(VirtualKeyCode::D),
+                InputSource::Key(VirtualKeyCode::A),
+            );
+            bind_vehicle_controls(actions);
+            actions.load_overrides(BINDINGS_FILE)?;
+        }
+
         // Load light 1
         {
             let position = glm::vec3(-2.0, 5.0, 0.0);
@@ -108,9 +166,33 @@ impl App for Game {
             self.player = Some(player_entity);
         }
 
+        // Load the controls label
+        {
+            let transform = Transform {
+                translation: glm::vec3(24.0, 24.0, 0.0),
+                ..Default::default()
+            };
+            let label = Text {
+                size: 24.0,
+                ..Text::new("WASD to move, Space to jump", TextSpace::Screen)
+            };
+            let label_entity = resources.world.ecs.push((transform, label));
+            resources
+                .world
+                .scene
+                .default_scenegraph_mut()?
+                .add_node(label_entity);
+        }
+
         // Load the level
         resources.load_asset("assets/models/backrooms.glb")?;
 
+        // Thrown objects are fast enough to pass through the level's thin walls between steps
+        let object_settings = RigidBodySettings {
+            ccd_enabled: true,
+            ..Default::default()
+        };
+
         // Add static colliders to level meshes
         let mut level_meshes = Vec::new();
         let mut query = <(Entity, &MeshRender)>::query();
@@ -121,6 +203,9 @@ impl App for Game {
         for (entity, mesh_name) in level_meshes.into_iter() {
             if mesh_name == "Sphere" {
                 log::info!("Mesh '{}' will be dynamic", mesh_name);
+                resources
+                    .world
+                    .set_rigid_body_settings(entity, object_settings)?;
                 resources
                     .world
                     .add_rigid_body(entity, RigidBodyType::Dynamic)?;
@@ -129,6 +214,9 @@ impl App for Game {
                     .add_sphere_collider(entity, OBJECT_COLLISION_GROUP)?;
             } else if mesh_name == "Cube.020" {
                 log::info!("Mesh '{}' will be dynamic", mesh_name);
+                resources
+                    .world
+                    .set_rigid_body_settings(entity, object_settings)?;
                 resources
                     .world
                     .add_rigid_body(entity, RigidBodyType::Dynamic)?;
@@ -148,23 +236,51 @@ impl App for Game {
         // Setup player
         if let Some(entity) = self.player.as_ref() {
             activate_first_person(resources, *entity)?;
-            let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
-                .translation(transform.translation)
-                .lock_rotations()
-                .build();
-            let handle = resources.world.physics.bodies.insert(rigid_body);
+            let settings = RigidBodySettings {
+                ccd_enabled: true,
+                lock_rotations: true,
+                ..Default::default()
+            };
+            resources.world.set_rigid_body_settings(*entity, settings)?;
             resources
                 .world
-                .ecs
-                .entry(*entity)
-                .context("")?
-                .add_component(RigidBody::new(handle));
+                .add_rigid_body(*entity, RigidBodyType::Dynamic)?;
 
             resources
                 .world
                 .add_cylinder_collider(*entity, 1.2, 0.25, PLAYER_COLLISION_GROUP)?;
         }
 
+        // Clients show the host's world without simulating their own car
+        if !matches!(self.network, Some(Network::Client(_))) {
+            self.car = Some(spawn_car(resources)?);
+        }
+
+        // Load an enemy that patrols until the player comes close.
+        // Clients show the host's enemy instead of running their own.
+        match (self.network.as_mut(), self.player) {
+            (Some(Network::Client(client)), _) => {
+                for prefab in ["player", "enemy"] {
+                    client.on_spawn(prefab, |world| {
+                        let transform = Transform {
+                            scale: glm::vec3(0.5, 0.5, 0.5),
+                            ..Default::default()
+                        };
+                        world.add_shape(Shape::Cube, transform)
+                    });
+                }
+            }
+            (network, Some(player)) => {
+                self.behaviors.register("enemy", enemy_behavior());
+                let enemy = spawn_enemy(resources, player)?;
+                if let Some(Network::Server(server)) = network {
+                    server.replicate(resources.world, player, "player")?;
+                    server.replicate(resources.world, enemy, "enemy")?;
+                }
+            }
+            _ => {}
+        }
+
         Ok(())
     }
 
@@ -173,66 +289,107 @@ impl App for Game {
             resources.system.exit_requested = true;
         }
 
-        if let Some(player) = self.player.as_ref() {
+        // The player stays put while the console is taking keyboard input
+        if let Some(player) = self.player.as_ref().filter(|_| !self.console.open) {
             self.camera.update(resources, *player)?;
             update_player(resources, *player)?;
+            if resources.input.action_just_pressed("Jump") {
+                jump_player(resources, *player)?;
+            }
+        }
+
+        if let Some(car) = self.car {
+            if !self.console.open {
+                drive_vehicle(resources, car)?;
+            }
+            resources.world.sync_transform_to_rigid_body(car)?;
+        }
+
+        self.scripts.update(resources)?;
+        self.behaviors.update(resources, Some(&mut self.scripts))?;
+
+        let delta_time = resources.system.delta_time as f32;
+        match self.network.as_mut() {
+            Some(Network::Server(server)) => server.update(
+                resources.world,
+                &resources.world.assets.animations,
+                delta_time,
+            )?,
+            Some(Network::Client(client)) => client.update(resources.world, delta_time)?,
+            None => {}
         }
 
         Ok(())
     }
 
-    fn on_key(
-        &mut self,
-        input: winit::event::KeyboardInput,
-        resources: &mut Resources,
-    ) -> Result<()> {
-        if let (Some(VirtualKeyCode::Space), ElementState::Pressed) =
+    fn gui_active(&mut self) -> bool {
+        self.console.open
+    }
+
+    fn update_gui(&mut self, resources: &mut Resources) -> Result<()> {
+        self.console.show(resources);
+        Ok(())
+    }
+
+    // The ~ key opens the console
+    fn on_key(&mut self, input: KeyboardInput, _resources: &mut Resources) -> Result<()> {
+        if let (Some(VirtualKeyCode::Grave), ElementState::Pressed) =
             (input.virtual_keycode, input.state)
         {
-            if let Some(player) = self.player.as_ref() {
-                jump_player(resources, *player)?;
-            }
+            self.console.toggle();
         }
         Ok(())
     }
 }
 
+// '--host <port>' serves the world to clients, and '--connect <address>' shows a host's world
+fn network_from_args() -> Result<Option<Network>> {
+    let arguments = std::env::args().collect::<Vec<_>>();
+    let value = |flag: &str| {
+        arguments
+            .iter()
+            .position(|argument| argument == flag)
+            .and_then(|index| arguments.get(index + 1))
+    };
+    if let Some(port) = value("--host") {
+        let server = NetworkServer::bind(format!("0.0.0.0:{}", port))?;
+        return Ok(Some(Network::Server(server)));
+    }
+    if let Some(address) = value("--connect") {
+        return Ok(Some(Network::Client(NetworkClient::connect(
+            address.as_str(),
+        )?)));
+    }
+    Ok(None)
+}
+
 fn main() -> Result<()> {
     run_application(
-        Game::default(),
+        Game {
+            network: network_from_args()?,
+            ..Default::default()
+        },
         AppConfig {
             icon: Some("assets/icon/icon.png".to_string()),
             title: "Physics Test with Rapier3D".to_string(),
             backend: Backend::Vulkan,
+            is_fullscreen: true,
             ..Default::default()
         },
     )
 }
 
 fn update_player(resources: &mut Resources, entity: Entity) -> Result<()> {
-    let speed = 2.0 * resources.system.delta_time as f32;
+    let move_speed = resources.cvars.get::<f32>("game.move_speed").unwrap_or(2.0);
+    let speed = move_speed * resources.world.clock.delta();
     {
         let mut entry = resources.world.ecs.entry_mut(entity)?;
         let transform = entry.get_component_mut::<Transform>()?;
-        let mut translation = glm::vec3(0.0, 0.0, 0.0);
-
-        if resources.input.is_key_pressed(VirtualKeyCode::W) {
-            translation = speed * transform.forward();
-        }
-
-        if resources.input.is_key_pressed(VirtualKeyCode::A) {
-            translation = -speed * transform.right();
+        let translation = transform.forward() * resources.input.axis("MoveForward")
+            + transform.right() * resources.input.axis("MoveRight");
+        if translation.norm() > f32::EPSILON {
+            transform.translation += speed * translation.normalize();
         }
-
-        if resources.input.is_key_pressed(VirtualKeyCode::S) {
-            translation = -speed * transform.forward();
-        }
-
-        if resources.input.is_key_pressed(VirtualKeyCode::D) {
-            translation = speed * transform.right();
-        }
-
-        transform.translation += translation;
     }
     resources.world.sync_rigid_body_to_transform(entity)?;
     Ok(())
@@ -246,7 +403,10 @@ fn jump_player(resources: &mut Resources, entity: Entity) -> Result<()> {
         .get_component::<RigidBody>()?
         .handle;
     if let Some(rigid_body) = resources.world.physics.bodies.get_mut(rigid_body_handle) {
-        let jump_strength = 0.5;
+        let jump_strength = resources
+            .cvars
+            .get::<f32>("game.jump_strength")
+            .unwrap_or(0.5);
         let impulse = jump_strength * glm::Vec3::y();
         rigid_body.apply_impulse(impulse, true);
     }
@@ -254,6 +414,105 @@ fn jump_player(resources: &mut Resources, entity: Entity) -> Result<()> {
     Ok(())
 }
 
+// Enemies attack a player within reach, chase one they can see nearby, and otherwise patrol
+fn enemy_behavior() -> BehaviorNode {
+    // Reactive so the enemy stops patrolling or chasing as soon as the player comes into range
+    BehaviorNode::ReactiveSelector(vec![
+        BehaviorNode::Sequence(vec![
+            tasks::within_range("target", 1.5),
+            tasks::cooldown("attack_ready", 1.0),
+            BehaviorNode::task("attack", |context| {
+                let target = match context.blackboard.entity("target") {
+                    Some(target) => target,
+                    None => return Ok(Status::Failure),
+                };
+                log::info!("The enemy attacks!");
+                context.resources.world.add_camera_trauma(target, 0.6)?;
+                Ok(Status::Success)
+            }),
+        ]),
+        BehaviorNode::Sequence(vec![
+            tasks::within_range("target", 6.0),
+            tasks::move_toward("target", 1.5, 1.2),
+        ]),
+        tasks::patrol(),
+    ])
+}
+
+fn spawn_enemy(resources: &mut Resources, player: Entity) -> Result<Entity> {
+    let route = resources.world.ecs.push((
+        Transform::default(),
+        Spline {
+            looping: true,
+            ..Spline::catmull_rom(vec![
+                SplinePoint::Position(glm::vec3(-3.0, 0.5, -3.0)),
+                SplinePoint::Position(glm::vec3(3.0, 0.5, -3.0)),
+                SplinePoint::Position(glm::vec3(3.0, 0.5, 3.0)),
+                SplinePoint::Position(glm::vec3(-3.0, 0.5, 3.0)),
+            ])
+        },
+    ));
+    resources
+        .world
+        .scene
+        .default_scenegraph_mut()?
+        .add_node(route);
+
+    let transform = Transform {
+        translation: glm::vec3(-3.0, 0.5, -3.0),
+        scale: glm::vec3(0.5, 0.5, 0.5),
+        ..Default::default()
+    };
+    let enemy = resources.world.add_shape(Shape::Cube, transform)?;
+    let mut behavior = BehaviorTree::new("enemy");
+    behavior
+        .blackboard
+        .set("target", BlackboardValue::Entity(player));
+    let mut entry = resources
+        .world
+        .ecs
+        .entry(enemy)
+        .context("Failed to find the enemy")?;
+    entry.add_component(PathFollower::new(route, 1.0));
+    entry.add_component(behavior);
+    Ok(enemy)
+}
+
+// A box on four raycast wheels, driven with the arrow keys
+fn spawn_car(resources: &mut Resources) -> Result<Entity> {
+    let transform = Transform {
+        translation: glm::vec3(3.0, 2.0, 0.0),
+        scale: glm::vec3(1.2, 0.4, 2.4),
+        ..Default::default()
+    };
+    let car = resources.world.add_shape(Shape::Cube, transform)?;
+    let settings = RigidBodySettings {
+        ccd_enabled: true,
+        angular_damping: 1.0,
+        ..Default::default()
+    };
+    resources.world.set_rigid_body_settings(car, settings)?;
+    resources
+        .world
+        .add_rigid_body(car, RigidBodyType::Dynamic)?;
+    resources
+        .world
+        .add_box_collider(car, OBJECT_COLLISION_GROUP)?;
+
+    // The wheels only roll on the level
+    let vehicle = RaycastVehicle {
+        collision_groups: OBJECT_LAYER.collision_groups(LEVEL_LAYER),
+        ..RaycastVehicle::four_wheeled(transform.scale / 2.0)
+    };
+    resources
+        .world
+        .ecs
+        .entry(car)
+        .context("Failed to find the car")?
+        .add_component(vehicle);
+    Ok(car)
+}
+
 fn activate_first_person(resources: &mut Resources, entity: Entity) -> Result<()> {
     // Disable active camera
     let camera_entity = resources.world.active_camera()?;
@@ -284,6 +543,8 @@ fn activate_first_person(resources: &mut Resources, entity: Entity) -> Result<()
                 z_near: 0.001,
             }),
             enabled: true,
+            exposure: 1.0,
+            culling_mask: Layers::ALL,
         });
 
     Ok(())
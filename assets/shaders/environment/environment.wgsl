@@ -0,0 +1,219 @@
+// The wgpu backend's counterpart to the environment shaders, which create the maps a skybox lights the world with.
+// Every pass draws a fullscreen triangle over one face of a cubemap, or over the brdf lookup table.
+
+struct Parameters {
+    face: i32;
+    roughness: f32;
+    number_of_samples: u32;
+    padding: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> parameters: Parameters;
+[[group(0), binding(1)]] var environment_sampler: sampler;
+// Only bound when converting the hdr texture into a cubemap
+[[group(0), binding(2)]] var equirectangular_map: texture_2d<f32>;
+// Bound for every pass that reads from the environment's cubemap
+[[group(0), binding(3)]] var environment_map: texture_cube<f32>;
+
+let PI: f32 = 3.1415926536;
+
+let BRDFLUT_SAMPLES: u32 = 1024u;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    output.uv = uv;
+    return output;
+}
+
+// The direction through a texel of a cubemap face, in the order the faces are layered
+fn face_direction(face: i32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+    var direction = vec3<f32>(-u, -v, -1.0);
+    if (face == 0) {
+        direction = vec3<f32>(1.0, -v, -u);
+    } elseif (face == 1) {
+        direction = vec3<f32>(-1.0, -v, u);
+    } elseif (face == 2) {
+        direction = vec3<f32>(u, 1.0, v);
+    } elseif (face == 3) {
+        direction = vec3<f32>(u, -1.0, -v);
+    } elseif (face == 4) {
+        direction = vec3<f32>(u, -v, 1.0);
+    }
+    return normalize(direction);
+}
+
+// Based on http://byteblacksmith.com/improvements-to-the-canonical-one-liner-glsl-rand-for-opengl-es-2-0/
+fn random(co: vec2<f32>) -> f32 {
+    let dt = dot(co, vec2<f32>(12.9898, 78.233));
+    let sn = dt - 3.14 * floor(dt / 3.14);
+    return fract(sin(sn) * 43758.5453);
+}
+
+// Radical inverse based on http://holger.dammertz.org/stuff/notes_HammersleyOnHemisphere.html
+fn hammersley_2d(i: u32, n: u32) -> vec2<f32> {
+    var bits = (i << 16u) | (i >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    let rdi = f32(bits) * 2.3283064365386963e-10;
+    return vec2<f32>(f32(i) / f32(n), rdi);
+}
+
+// Based on http://blog.selfshadow.com/publications/s2013-shading-course/karis/s2013_pbs_epic_slides.pdf
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32, normal: vec3<f32>) -> vec3<f32> {
+    // Maps a 2D point to a hemisphere with spread based on roughness
+    let alpha = roughness * roughness;
+    let phi = 2.0 * PI * xi.x + random(normal.xz) * 0.1;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+
+    // Tangent space
+    var up = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(normal.z) < 0.999) {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent_x = normalize(cross(up, normal));
+    let tangent_y = normalize(cross(normal, tangent_x));
+
+    // Convert to world Space
+    return normalize(tangent_x * h.x + tangent_y * h.y + normal * h.z);
+}
+
+// Renders a flat hdr texture as a cubemap
+[[stage(fragment)]]
+fn fs_equirectangular(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let v = face_direction(parameters.face, input.uv);
+    let uv = vec2<f32>(atan2(v.z, v.x), asin(-v.y)) * vec2<f32>(0.1591, 0.3183) + vec2<f32>(0.5);
+    let color = textureSampleLevel(equirectangular_map, environment_sampler, uv, 0.0).rgb;
+    return vec4<f32>(color, 1.0);
+}
+
+// Fills a mip level of the environment's cubemap from the level above it, which is the only level bound
+[[stage(fragment)]]
+fn fs_downsample(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let direction = face_direction(parameters.face, input.uv);
+    return vec4<f32>(textureSampleLevel(environment_map, environment_sampler, direction, 0.0).rgb, 1.0);
+}
+
+// Generates an irradiance cube from an environment map using convolution
+[[stage(fragment)]]
+fn fs_irradiance(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let n = face_direction(parameters.face, input.uv);
+    let right = normalize(cross(vec3<f32>(0.0, 1.0, 0.0), n));
+    let up = cross(n, right);
+
+    let two_pi = PI * 2.0;
+    let half_pi = PI * 0.5;
+    let delta_phi = 2.0 * PI / 180.0;
+    let delta_theta = half_pi / 64.0;
+
+    var color = vec3<f32>(0.0);
+    var sample_count = 0u;
+    for (var phi = 0.0; phi < two_pi; phi = phi + delta_phi) {
+        for (var theta = 0.0; theta < half_pi; theta = theta + delta_theta) {
+            let temp = cos(phi) * right + sin(phi) * up;
+            let sample_vector = cos(theta) * n + sin(theta) * temp;
+            color = color + textureSampleLevel(environment_map, environment_sampler, sample_vector, 0.0).rgb * cos(theta) * sin(theta);
+            sample_count = sample_count + 1u;
+        }
+    }
+    return vec4<f32>(PI * color / f32(sample_count), 1.0);
+}
+
+// Normal Distribution function
+fn d_ggx(dot_n_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let denominator = dot_n_h * dot_n_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denominator * denominator);
+}
+
+[[stage(fragment)]]
+fn fs_prefilter(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let n = face_direction(parameters.face, input.uv);
+    let v = n;
+    let roughness = parameters.roughness;
+    let number_of_samples = parameters.number_of_samples;
+    let environment_map_size = f32(textureDimensions(environment_map).x);
+
+    var color = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var i = 0u; i < number_of_samples; i = i + 1u) {
+        let xi = hammersley_2d(i, number_of_samples);
+        let h = importance_sample_ggx(xi, roughness, n);
+        let l = 2.0 * dot(v, h) * h - v;
+        let dot_n_l = clamp(dot(n, l), 0.0, 1.0);
+        if (dot_n_l > 0.0) {
+            // Filtering based on https://placeholderart.wordpress.com/2015/07/28/implementation-notes-runtime-environment-map-filtering-for-image-based-lighting/
+            let dot_n_h = clamp(dot(n, h), 0.0, 1.0);
+            let dot_v_h = clamp(dot(v, h), 0.0, 1.0);
+
+            // Probability Distribution Function
+            let pdf = d_ggx(dot_n_h, roughness) * dot_n_h / (4.0 * dot_v_h) + 0.0001;
+            // Solid angle of current sample
+            let omega_s = 1.0 / (f32(number_of_samples) * pdf);
+            // Solid angle of 1 pixel across all cube faces
+            let omega_p = 4.0 * PI / (6.0 * environment_map_size * environment_map_size);
+            // Biased (+1.0) mip level for better result
+            var mip_level = 0.0;
+            if (roughness != 0.0) {
+                mip_level = max(0.5 * log2(omega_s / omega_p) + 1.0, 0.0);
+            }
+            color = color + textureSampleLevel(environment_map, environment_sampler, l, mip_level).rgb * dot_n_l;
+            total_weight = total_weight + dot_n_l;
+        }
+    }
+    return vec4<f32>(color / total_weight, 1.0);
+}
+
+// Geometric Shadowing function
+fn g_schlicksmith_ggx(dot_n_l: f32, dot_n_v: f32, roughness: f32) -> f32 {
+    let k = (roughness * roughness) / 2.0;
+    let gl = dot_n_l / (dot_n_l * (1.0 - k) + k);
+    let gv = dot_n_v / (dot_n_v * (1.0 - k) + k);
+    return gl * gv;
+}
+
+fn brdf(n_dot_v: f32, roughness: f32) -> vec2<f32> {
+    // Normal always points along z-axis for the 2D lookup
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var lut = vec2<f32>(0.0);
+    for (var i = 0u; i < BRDFLUT_SAMPLES; i = i + 1u) {
+        let xi = hammersley_2d(i, BRDFLUT_SAMPLES);
+        let h = importance_sample_ggx(xi, roughness, n);
+        let l = 2.0 * dot(v, h) * h - v;
+
+        let dot_n_l = max(dot(n, l), 0.0);
+        let dot_n_v = max(dot(n, v), 0.0);
+        let dot_v_h = max(dot(v, h), 0.0);
+        let dot_n_h = max(dot(h, n), 0.0);
+
+        if (dot_n_l > 0.0) {
+            let g = g_schlicksmith_ggx(dot_n_l, dot_n_v, roughness);
+            let g_vis = (g * dot_v_h) / (dot_n_h * dot_n_v);
+            let fc = pow(1.0 - dot_v_h, 5.0);
+            lut = lut + vec2<f32>((1.0 - fc) * g_vis, fc * g_vis);
+        }
+    }
+    return lut / f32(BRDFLUT_SAMPLES);
+}
+
+// From https://github.com/SaschaWillems/Vulkan-glTF-PBR
+[[stage(fragment)]]
+fn fs_brdflut(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(brdf(input.uv.x, 1.0 - input.uv.y), 0.0, 1.0);
+}
@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) in vec2 inUV;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0, set = 0) uniform sampler2D fog_texture;
+
+void main() { outColor = texture(fog_texture, inUV); }
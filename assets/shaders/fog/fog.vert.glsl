@@ -0,0 +1,18 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 mvp;
+} pushConstants;
+
+layout(location = 0) out vec2 outUV;
+
+const vec2 corners[6] = vec2[](
+  vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+  vec2(1.0, 1.0), vec2(0.0, 1.0), vec2(0.0, 0.0)
+);
+
+void main()
+{
+  outUV = corners[gl_VertexIndex];
+  gl_Position = pushConstants.mvp * vec4(outUV.x, 0.0, outUV.y, 1.0);
+}
@@ -0,0 +1,46 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 viewProjection;
+  vec4 camera;
+  vec4 color;
+} pushConstants;
+
+layout(location = 0) in vec3 inPosition;
+
+layout(location = 0) out vec4 outColor;
+
+// How much of a line of the given spacing covers this pixel, antialiased by the screen space derivatives
+float gridLine(vec2 coordinates, float spacing)
+{
+  vec2 scaled = coordinates / spacing;
+  vec2 width = fwidth(scaled);
+  vec2 distanceToLine = abs(fract(scaled - 0.5) - 0.5) / width;
+  return 1.0 - min(min(distanceToLine.x, distanceToLine.y), 1.0);
+}
+
+void main()
+{
+  // Each power of ten the camera rises shows lines ten times further apart,
+  // with the finer lines fading out on the way
+  float level = log(max(abs(pushConstants.camera.y), 1.0)) / log(10.0);
+  float spacing = pow(10.0, floor(level));
+  float minor = gridLine(inPosition.xz, spacing) * (1.0 - fract(level));
+  float major = gridLine(inPosition.xz, spacing * 10.0);
+  vec4 color = vec4(pushConstants.color.rgb, pushConstants.color.a * max(minor, major));
+
+  // The x axis runs along z = 0 and the z axis along x = 0
+  vec2 width = fwidth(inPosition.xz);
+  if (abs(inPosition.z) < width.y) {
+    color = vec4(0.9, 0.2, 0.2, 1.0);
+  } else if (abs(inPosition.x) < width.x) {
+    color = vec4(0.2, 0.4, 0.9, 1.0);
+  }
+
+  float distance = length(inPosition.xz - pushConstants.camera.xz);
+  color.a *= 1.0 - smoothstep(pushConstants.camera.w * 0.5, pushConstants.camera.w, distance);
+  if (color.a <= 0.0) {
+    discard;
+  }
+  outColor = color;
+}
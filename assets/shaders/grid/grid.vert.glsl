@@ -0,0 +1,23 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 viewProjection;
+  // The camera's position, with the distance the grid fades out at in w
+  vec4 camera;
+  vec4 color;
+} pushConstants;
+
+layout(location = 0) out vec3 outPosition;
+
+const vec2 corners[6] = vec2[](
+  vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+  vec2(1.0, 1.0), vec2(-1.0, 1.0), vec2(-1.0, -1.0)
+);
+
+void main()
+{
+  // A square beneath the camera just large enough to fade out before its edges
+  vec2 corner = corners[gl_VertexIndex] * pushConstants.camera.w;
+  outPosition = vec3(pushConstants.camera.x + corner.x, 0.0, pushConstants.camera.z + corner.y);
+  gl_Position = pushConstants.viewProjection * vec4(outPosition, 1.0);
+}
@@ -0,0 +1,42 @@
+// The wgpu backend's counterpart to gui.vert.glsl and gui.frag.glsl
+
+struct ScreenSize {
+    size: vec2<f32>;
+    padding: vec2<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> screen: ScreenSize;
+[[group(0), binding(1)]] var font_texture: texture_2d<f32>;
+[[group(0), binding(2)]] var font_sampler: sampler;
+
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+};
+
+// Normalized device coordinates point up in wgpu, while egui's point down
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(
+        2.0 * vertex.position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * vertex.position.y / screen.size.y,
+        0.0,
+        1.0,
+    );
+    output.color = vertex.color;
+    output.uv = vertex.uv;
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return input.color * textureSample(font_texture, font_sampler, input.uv);
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout(push_constant) uniform PushConstants{
+  vec4 color;
+  vec2 viewportSize;
+  float thickness;
+  int entityIndex;
+} pushConstants;
+
+layout(location=0) out vec4 outColor;
+
+void main()
+{
+  outColor = pushConstants.color;
+}
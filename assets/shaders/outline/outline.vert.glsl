@@ -0,0 +1,66 @@
+#version 450
+
+layout(location=0) in vec3 inPosition;
+layout(location=1) in vec3 inNormal;
+layout(location=4) in vec4 inJoint0;
+layout(location=5) in vec4 inWeight0;
+
+layout(binding=0) uniform UboView{
+  mat4 view;
+  mat4 projection;
+} uboView;
+
+struct Entity
+{
+  mat4 model;
+  vec4 node_info;
+};
+
+layout(std430, binding=1) readonly buffer EntityBuffer{
+  Entity entities[];
+} entityBuffer;
+
+layout(std430, binding=6) readonly buffer JointBuffer{
+  mat4 jointMatrices[];
+} jointBuffer;
+
+layout(push_constant) uniform PushConstants{
+  vec4 color;
+  vec2 viewportSize;
+  float thickness;
+  int entityIndex;
+} pushConstants;
+
+void main()
+{
+  Entity entity = entityBuffer.entities[pushConstants.entityIndex];
+  float jointCount = entity.node_info.x;
+  float jointOffset = entity.node_info.y;
+
+  mat4 skinMatrix = mat4(1.0);
+  if (jointCount > 0.0) {
+    skinMatrix =
+      inWeight0.x * jointBuffer.jointMatrices[int(inJoint0.x + jointOffset)] +
+      inWeight0.y * jointBuffer.jointMatrices[int(inJoint0.y + jointOffset)] +
+      inWeight0.z * jointBuffer.jointMatrices[int(inJoint0.z + jointOffset)] +
+      inWeight0.w * jointBuffer.jointMatrices[int(inJoint0.w + jointOffset)];
+  }
+  mat4 skinnedModel = entity.model * skinMatrix;
+
+  mat4 viewProjection = uboView.projection * uboView.view;
+  vec4 position = viewProjection * skinnedModel * vec4(inPosition, 1.0);
+  vec3 normal = normalize(transpose(inverse(mat3(skinnedModel))) * inNormal);
+  vec2 clipNormal = (viewProjection * vec4(normal, 0.0)).xy;
+
+  // The normal's direction on screen, accounting for the viewport's aspect ratio
+  vec2 screenNormal = clipNormal * pushConstants.viewportSize;
+  if (dot(screenNormal, screenNormal) > 0.0) {
+    screenNormal = normalize(screenNormal);
+  }
+
+  // Pushing out by the same number of pixels at any distance means scaling by w before the perspective divide
+  vec2 offset = screenNormal * pushConstants.thickness * 2.0 / pushConstants.viewportSize;
+  position.xy += offset * position.w;
+
+  gl_Position = position;
+}
@@ -0,0 +1,29 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 viewProjection;
+  vec4 cameraRight;
+  vec4 cameraUp;
+  int textureIndex;
+} pushConstants;
+
+layout(location = 0) in vec2 inUV;
+layout(location = 1) in vec4 inColor;
+
+layout(location = 0) out vec4 outColor;
+
+// The world's textures, shared with the world shader
+layout(constant_id = 0) const int MAX_NUMBER_OF_TEXTURES = 200;
+layout(binding = 2) uniform sampler2D textures[MAX_NUMBER_OF_TEXTURES];
+
+void main()
+{
+  vec4 color = inColor;
+  if (pushConstants.textureIndex >= 0) {
+    color *= texture(textures[pushConstants.textureIndex], inUV);
+  }
+  if (color.a <= 0.0) {
+    discard;
+  }
+  outColor = color;
+}
@@ -0,0 +1,38 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 viewProjection;
+  vec4 cameraRight;
+  vec4 cameraUp;
+  int textureIndex;
+} pushConstants;
+
+layout(location = 0) in vec4 inPositionSize;
+layout(location = 1) in vec4 inColor;
+
+layout(location = 0) out vec2 outUV;
+layout(location = 1) out vec4 outColor;
+
+// Two triangles per particle, expanded from the vertex index
+const vec2 corners[6] = vec2[](
+  vec2(0.0, 0.0),
+  vec2(1.0, 0.0),
+  vec2(1.0, 1.0),
+  vec2(1.0, 1.0),
+  vec2(0.0, 1.0),
+  vec2(0.0, 0.0)
+);
+
+void main()
+{
+  vec2 corner = corners[gl_VertexIndex];
+  outUV = corner;
+  outColor = inColor;
+
+  // Centered on the particle with y up
+  vec2 offset = vec2(corner.x - 0.5, 0.5 - corner.y) * inPositionSize.w;
+  vec3 position = inPositionSize.xyz
+    + pushConstants.cameraRight.xyz * offset.x
+    + pushConstants.cameraUp.xyz * offset.y;
+  gl_Position = pushConstants.viewProjection * vec4(position, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout(push_constant) uniform PushConstants{
+  vec2 viewportSize;
+  float proxySize;
+  int entityIndex;
+} pushConstants;
+
+// Offset by one so the cleared value of zero means no entity
+layout(location=0) out int outEntity;
+
+void main()
+{
+  outEntity = pushConstants.entityIndex + 1;
+}
@@ -0,0 +1,48 @@
+#version 450
+
+layout(location=0) in vec3 inPosition;
+layout(location=4) in vec4 inJoint0;
+layout(location=5) in vec4 inWeight0;
+
+layout(binding=0) uniform UboView{
+  mat4 view;
+  mat4 projection;
+} uboView;
+
+struct Entity
+{
+  mat4 model;
+  vec4 node_info;
+};
+
+layout(std430, binding=1) readonly buffer EntityBuffer{
+  Entity entities[];
+} entityBuffer;
+
+layout(std430, binding=6) readonly buffer JointBuffer{
+  mat4 jointMatrices[];
+} jointBuffer;
+
+layout(push_constant) uniform PushConstants{
+  vec2 viewportSize;
+  float proxySize;
+  int entityIndex;
+} pushConstants;
+
+void main()
+{
+  Entity entity = entityBuffer.entities[pushConstants.entityIndex];
+  float jointCount = entity.node_info.x;
+  float jointOffset = entity.node_info.y;
+
+  mat4 skinMatrix = mat4(1.0);
+  if (jointCount > 0.0) {
+    skinMatrix =
+      inWeight0.x * jointBuffer.jointMatrices[int(inJoint0.x + jointOffset)] +
+      inWeight0.y * jointBuffer.jointMatrices[int(inJoint0.y + jointOffset)] +
+      inWeight0.z * jointBuffer.jointMatrices[int(inJoint0.z + jointOffset)] +
+      inWeight0.w * jointBuffer.jointMatrices[int(inJoint0.w + jointOffset)];
+  }
+
+  gl_Position = uboView.projection * uboView.view * entity.model * skinMatrix * vec4(inPosition, 1.0);
+}
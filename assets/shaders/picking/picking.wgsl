@@ -0,0 +1,110 @@
+// The wgpu backend's counterpart to picking.vert.glsl, picking_proxy.vert.glsl, and picking.frag.glsl
+
+struct WorldUniforms {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+};
+
+struct Entity {
+    model: mat4x4<f32>;
+    // X is the joint count, Y is the joint matrix offset
+    node_info: vec4<f32>;
+};
+
+struct EntityBuffer {
+    entities: array<Entity>;
+};
+
+struct JointBuffer {
+    joint_matrices: array<mat4x4<f32>>;
+};
+
+struct Picking {
+    // Zooms in on the picked pixel so it fills the single pixel target
+    pick: mat4x4<f32>;
+    viewport_size: vec2<f32>;
+    proxy_size: f32;
+    padding: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: WorldUniforms;
+[[group(0), binding(1)]] var<storage, read> entity_buffer: EntityBuffer;
+[[group(0), binding(2)]] var<storage, read> joint_buffer: JointBuffer;
+
+[[group(1), binding(0)]] var<uniform> picking: Picking;
+
+struct MeshInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(4)]] joint_0: vec4<f32>;
+    [[location(5)]] weight_0: vec4<f32>;
+    [[location(8)]] entity_index: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0), interpolate(flat)]] entity_id: i32;
+};
+
+fn joint_matrix(joint: f32, offset: f32) -> mat4x4<f32> {
+    return joint_buffer.joint_matrices[u32(joint + offset)];
+}
+
+fn skin_matrix(joints: vec4<f32>, weights: vec4<f32>, offset: f32) -> mat4x4<f32> {
+    let joint_x = joint_matrix(joints.x, offset);
+    let joint_y = joint_matrix(joints.y, offset);
+    let joint_z = joint_matrix(joints.z, offset);
+    let joint_w = joint_matrix(joints.w, offset);
+    return mat4x4<f32>(
+        weights.x * joint_x[0] + weights.y * joint_y[0] + weights.z * joint_z[0] + weights.w * joint_w[0],
+        weights.x * joint_x[1] + weights.y * joint_y[1] + weights.z * joint_z[1] + weights.w * joint_w[1],
+        weights.x * joint_x[2] + weights.y * joint_y[2] + weights.z * joint_z[2] + weights.w * joint_w[2],
+        weights.x * joint_x[3] + weights.y * joint_y[3] + weights.z * joint_z[3] + weights.w * joint_w[3],
+    );
+}
+
+[[stage(vertex)]]
+fn vs_mesh(vertex: MeshInput) -> VertexOutput {
+    let entity = entity_buffer.entities[vertex.entity_index];
+
+    var model = entity.model;
+    if (entity.node_info.x > 0.0) {
+        model = model * skin_matrix(vertex.joint_0, vertex.weight_0, entity.node_info.y);
+    }
+
+    var output: VertexOutput;
+    output.clip_position = picking.pick * uniforms.projection * uniforms.view * model * vec4<f32>(vertex.position, 1.0);
+    // Offset by one so the cleared value of zero means no entity
+    output.entity_id = i32(vertex.entity_index) + 1;
+    return output;
+}
+
+// A quad of the same size in pixels at any distance, centered on the entity
+[[stage(vertex)]]
+fn vs_proxy(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[location(8)]] entity_index: u32,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+
+    let entity = entity_buffer.entities[entity_index];
+    var position = uniforms.projection * uniforms.view * vec4<f32>(entity.model[3].xyz, 1.0);
+    let offset = corners[vertex_index] * picking.proxy_size / picking.viewport_size;
+    position = vec4<f32>(position.xy + offset * position.w, position.zw);
+
+    var output: VertexOutput;
+    output.clip_position = picking.pick * position;
+    output.entity_id = i32(entity_index) + 1;
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] i32 {
+    return input.entity_id;
+}
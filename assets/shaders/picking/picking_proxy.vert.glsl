@@ -0,0 +1,41 @@
+#version 450
+
+layout(binding=0) uniform UboView{
+  mat4 view;
+  mat4 projection;
+} uboView;
+
+struct Entity
+{
+  mat4 model;
+  vec4 node_info;
+};
+
+layout(std430, binding=1) readonly buffer EntityBuffer{
+  Entity entities[];
+} entityBuffer;
+
+layout(push_constant) uniform PushConstants{
+  vec2 viewportSize;
+  float proxySize;
+  int entityIndex;
+} pushConstants;
+
+const vec2 corners[6] = vec2[](
+  vec2(-1.0, -1.0),
+  vec2(1.0, -1.0),
+  vec2(1.0, 1.0),
+  vec2(-1.0, -1.0),
+  vec2(1.0, 1.0),
+  vec2(-1.0, 1.0)
+);
+
+// A quad of the same size in pixels at any distance, centered on the entity
+void main()
+{
+  Entity entity = entityBuffer.entities[pushConstants.entityIndex];
+  vec4 position = uboView.projection * uboView.view * vec4(entity.model[3].xyz, 1.0);
+  vec2 offset = corners[gl_VertexIndex] * pushConstants.proxySize / pushConstants.viewportSize;
+  position.xy += offset * position.w;
+  gl_Position = position;
+}
@@ -0,0 +1,88 @@
+#version 450
+
+layout(local_size_x = 64) in;
+
+// Vertices are accessed as floats because vec3 members are padded to 16 bytes in storage buffers.
+// The layout matches the interleaved world vertex.
+#define VERTEX_SIZE 25
+#define POSITION 0
+#define NORMAL 3
+#define JOINT_0 10
+#define WEIGHT_0 14
+#define TANGENT 21
+
+layout(std430, binding = 0) readonly buffer SourceBuffer {
+  float source[];
+} sourceBuffer;
+
+layout(std430, binding = 1) readonly buffer JointBuffer {
+  mat4 jointMatrices[];
+} jointBuffer;
+
+layout(std430, binding = 2) writeonly buffer SkinnedBuffer {
+  float skinned[];
+} skinnedBuffer;
+
+layout(push_constant) uniform PushConstants {
+  uint firstSourceVertex;
+  uint firstSkinnedVertex;
+  uint numberOfVertices;
+  uint jointOffset;
+} pushConstants;
+
+vec3 read3(uint offset)
+{
+  return vec3(sourceBuffer.source[offset], sourceBuffer.source[offset + 1], sourceBuffer.source[offset + 2]);
+}
+
+vec4 read4(uint offset)
+{
+  return vec4(read3(offset), sourceBuffer.source[offset + 3]);
+}
+
+void write3(uint offset, vec3 value)
+{
+  skinnedBuffer.skinned[offset] = value.x;
+  skinnedBuffer.skinned[offset + 1] = value.y;
+  skinnedBuffer.skinned[offset + 2] = value.z;
+}
+
+mat4 jointMatrix(float joint)
+{
+  return jointBuffer.jointMatrices[int(joint) + int(pushConstants.jointOffset)];
+}
+
+void main()
+{
+  uint index = gl_GlobalInvocationID.x;
+  if (index >= pushConstants.numberOfVertices) {
+    return;
+  }
+
+  uint source = (pushConstants.firstSourceVertex + index) * VERTEX_SIZE;
+  uint destination = (pushConstants.firstSkinnedVertex + index) * VERTEX_SIZE;
+
+  // Attributes that skinning doesn't change are copied as they are
+  for (uint component = 0; component < VERTEX_SIZE; component++) {
+    skinnedBuffer.skinned[destination + component] = sourceBuffer.source[source + component];
+  }
+
+  vec4 joint0 = read4(source + JOINT_0);
+  vec4 weight0 = read4(source + WEIGHT_0);
+  mat4 skinMatrix =
+    weight0.x * jointMatrix(joint0.x) +
+    weight0.y * jointMatrix(joint0.y) +
+    weight0.z * jointMatrix(joint0.z) +
+    weight0.w * jointMatrix(joint0.w);
+
+  vec4 position = skinMatrix * vec4(read3(source + POSITION), 1.0);
+  write3(destination + POSITION, position.xyz / position.w);
+
+  mat3 surface = mat3(skinMatrix);
+  write3(destination + NORMAL, normalize(transpose(inverse(surface)) * read3(source + NORMAL)));
+
+  // Mirrored joints flip which way the bitangent points
+  vec4 tangent = read4(source + TANGENT);
+  write3(destination + TANGENT, surface * tangent.xyz);
+  skinnedBuffer.skinned[destination + TANGENT + 3] = tangent.w * sign(determinant(surface));
+}
@@ -3,6 +3,13 @@
 layout(location = 0) in vec3 vert_texcoord;
 
 layout(binding = 0) uniform samplerCube environmentMap;
+layout(binding = 1) uniform samplerCube fadedEnvironmentMap;
+
+layout(push_constant) uniform PushConstants{
+  mat4 view;
+  mat4 projection;
+  float blend;
+} pushConstants;
 
 layout(location = 0) out vec4 outColor;
 
@@ -38,6 +45,10 @@ vec4 srgb_to_linear(vec4 srgbIn)
 
 void main()
 {
-    vec3 envColor = srgb_to_linear(tonemap(textureLod(environmentMap, vert_texcoord, 0.0))).rgb;
+    vec4 color = mix(
+        textureLod(fadedEnvironmentMap, vert_texcoord, 0.0),
+        textureLod(environmentMap, vert_texcoord, 0.0),
+        pushConstants.blend);
+    vec3 envColor = srgb_to_linear(tonemap(color)).rgb;
     outColor = vec4(envColor, 1.0);
 }
\ No newline at end of file
@@ -5,6 +5,7 @@ layout (location = 0) in vec3 inPosition;
 layout(push_constant) uniform PushConstants{
   mat4 view;
   mat4 projection;
+  float blend;
 } pushConstants;
 
 layout(location = 0) out vec3 vert_texcoord;
@@ -0,0 +1,71 @@
+// The wgpu backend's counterpart to skybox.vert.glsl and skybox.frag.glsl.
+// A fullscreen triangle is drawn behind the world, looking up the environment in the direction of each pixel.
+
+struct SkyboxUniforms {
+    inverse_projection: mat4x4<f32>;
+    // Only the view's rotation, so the skybox stays around the camera wherever it moves
+    inverse_view: mat4x4<f32>;
+    // How much of the environment to show over the one being faded out
+    blend: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: SkyboxUniforms;
+[[group(0), binding(1)]] var environment_sampler: sampler;
+[[group(0), binding(2)]] var environment_map: texture_cube<f32>;
+[[group(0), binding(3)]] var faded_environment_map: texture_cube<f32>;
+
+let EXPOSURE: f32 = 4.5;
+let GAMMA: f32 = 2.2;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] direction: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    let position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+
+    // The point on the near plane behind the pixel, which is also its direction from the camera
+    let point = uniforms.inverse_projection * position;
+
+    var output: VertexOutput;
+    output.position = position;
+    output.direction = (uniforms.inverse_view * vec4<f32>(point.xyz / point.w, 0.0)).xyz;
+    return output;
+}
+
+// From http://filmicworlds.com/blog/filmic-tonemapping-operators/
+fn uncharted_2_tonemap(color: vec3<f32>) -> vec3<f32> {
+    let a = vec3<f32>(0.15);
+    let b = vec3<f32>(0.50);
+    let c = vec3<f32>(0.10);
+    let d = vec3<f32>(0.20);
+    let e = vec3<f32>(0.02);
+    let f = vec3<f32>(0.30);
+    return ((color * (a * color + c * b) + d * e) / (color * (a * color + b) + d * f)) - e / f;
+}
+
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+    var mapped = uncharted_2_tonemap(color * EXPOSURE);
+    mapped = mapped * (vec3<f32>(1.0) / uncharted_2_tonemap(vec3<f32>(11.2)));
+    return pow(mapped, vec3<f32>(1.0 / GAMMA));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let b_less = step(vec3<f32>(0.04045), color);
+    let lin_out = mix(color / vec3<f32>(12.92), pow((color + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4)), b_less);
+    return lin_out;
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let direction = normalize(input.direction);
+    let color = mix(
+        textureSampleLevel(faded_environment_map, environment_sampler, direction, 0.0),
+        textureSampleLevel(environment_map, environment_sampler, direction, 0.0),
+        vec4<f32>(uniforms.blend),
+    );
+    return vec4<f32>(srgb_to_linear(tonemap(color.rgb)), 1.0);
+}
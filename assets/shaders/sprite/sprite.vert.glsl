@@ -0,0 +1,35 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  mat4 viewProjection;
+  vec4 cameraRight;
+  vec4 cameraUp;
+  vec2 screenSize;
+  int screenSpace;
+  int textureIndex;
+} pushConstants;
+
+layout(location = 0) in vec3 inAnchor;
+layout(location = 1) in vec2 inOffset;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in vec4 inTint;
+
+layout(location = 0) out vec2 outUV;
+layout(location = 1) out vec4 outTint;
+
+void main()
+{
+  outUV = inUV;
+  outTint = inTint;
+
+  if (pushConstants.screenSpace == 1) {
+    vec2 position = inAnchor.xy + inOffset;
+    gl_Position = vec4(2.0 * position / pushConstants.screenSize - 1.0, 0.0, 1.0);
+    return;
+  }
+
+  vec3 position = inAnchor
+    + pushConstants.cameraRight.xyz * inOffset.x
+    + pushConstants.cameraUp.xyz * inOffset.y;
+  gl_Position = pushConstants.viewProjection * vec4(position, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout(location = 0) in vec2 inUV;
+layout(location = 1) in vec4 inColor;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0, set = 0) uniform sampler2D fontTexture;
+
+// The distance field is stored in the alpha channel, with the edges of the glyphs at one half
+void main()
+{
+  float distance = texture(fontTexture, inUV).a;
+  float width = max(fwidth(distance), 0.0001);
+  float alpha = smoothstep(0.5 - width, 0.5 + width, distance);
+  if (alpha <= 0.0) {
+    discard;
+  }
+  outColor = vec4(inColor.rgb, inColor.a * alpha);
+}
@@ -0,0 +1,71 @@
+#version 450
+
+// The unlit shading model, drawn by its own pipeline so no lighting or image based lighting is evaluated.
+// Shares the world vertex shader and descriptor set, only reading the material's textures.
+
+layout(location=0) in vec3 inPosition;
+layout(location=1) in vec3 inNormal;
+layout(location=2) in vec2 inUV0;
+layout(location=3) in vec2 inUV1;
+layout(location=4) in vec3 inColor0;
+layout(location=5) in vec4 inTangent;
+layout(location=6) flat in vec3 inObjectPosition;
+
+layout(constant_id = 1) const int MAX_NUMBER_OF_TEXTURES = 200;
+
+layout(binding=2) uniform sampler2D textures[MAX_NUMBER_OF_TEXTURES];
+
+layout(push_constant) uniform Material{
+    vec4 baseColorFactor;
+    vec3 emissiveFactor;
+    int colorTextureIndex;
+    int colorTextureSet;
+    int metallicRoughnessTextureIndex;
+    int metallicRoughnessTextureSet;
+    int normalTextureIndex;
+    int normalTextureSet;
+    float normalTextureScale;
+    int occlusionTextureIndex;
+    int occlusionTextureSet;
+    float occlusionStrength;
+    int emissiveTextureIndex;
+    int emissiveTextureSet;
+    float metallicFactor;
+    float roughnessFactor;
+    int alphaMode;
+    float alphaCutoff;
+    int toonBands;
+    float toonSmoothness;
+    float rimStrength;
+    float clearcoatFactor;
+    float clearcoatRoughnessFactor;
+    float transmissionFactor;
+    float ior;
+} material;
+
+layout(location = 0) out vec4 outColor;
+
+vec4 srgb_to_linear(vec4 srgbIn)
+{
+    return vec4(pow(srgbIn.xyz,vec3(2.2)),srgbIn.w);
+}
+
+void main()
+{
+    vec4 baseColor = material.baseColorFactor;
+    if (material.colorTextureIndex > -1) {
+        vec2 tex_coord = inUV0;
+        if(material.colorTextureSet == 1) {
+            tex_coord = inUV1;
+        }
+        baseColor *= srgb_to_linear(texture(textures[material.colorTextureIndex], tex_coord));
+    }
+    baseColor.rgb *= inColor0;
+
+    // alpha discard
+    if (material.alphaMode == 2 && baseColor.a < material.alphaCutoff) {
+        discard;
+    }
+
+    outColor = vec4(pow(baseColor.rgb, vec3(1.0 / 2.2)), baseColor.a);
+}
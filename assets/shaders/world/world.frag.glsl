@@ -5,14 +5,24 @@ layout(location=1) in vec3 inNormal;
 layout(location=2) in vec2 inUV0;
 layout(location=3) in vec2 inUV1;
 layout(location=4) in vec3 inColor0;
+layout(location=5) in vec4 inTangent;
+// The same for every fragment of a draw, so probe indices chosen from it are dynamically uniform
+layout(location=6) flat in vec3 inObjectPosition;
 
-#define MAX_NUMBER_OF_TEXTURES 200
+// Sized by the renderer, which allows far more textures on devices with descriptor indexing.
+// Materials index the array with push constants, so the indices are always dynamically uniform.
+layout(constant_id = 1) const int MAX_NUMBER_OF_TEXTURES = 200;
 
 layout(binding=2) uniform sampler2D textures[MAX_NUMBER_OF_TEXTURES];
 layout(binding=3) uniform sampler2D brdflut;
 layout(binding=4) uniform samplerCube prefilterMap;
 layout(binding=5) uniform samplerCube irradianceMap;
 
+#define MAX_NUMBER_OF_REFLECTION_PROBES 8
+
+layout(binding=7) uniform samplerCube probePrefilterMaps[MAX_NUMBER_OF_REFLECTION_PROBES];
+layout(binding=8) uniform samplerCube probeIrradianceMaps[MAX_NUMBER_OF_REFLECTION_PROBES];
+
 layout(push_constant) uniform Material{
     vec4 baseColorFactor;
     vec3 emissiveFactor;
@@ -32,9 +42,25 @@ layout(push_constant) uniform Material{
     float roughnessFactor;
     int alphaMode;
     float alphaCutoff;
-    int isUnlit;
+    int toonBands;
+    float toonSmoothness;
+    float rimStrength;
+    float clearcoatFactor;
+    float clearcoatRoughnessFactor;
+    float transmissionFactor;
+    float ior;
 } material;
 
+// Each shading model is compiled into its own pipeline,
+// so the branches for the other models are removed by the driver.
+// Unlit materials are drawn with unlit.frag instead.
+layout(constant_id = 0) const int SHADING_MODEL = 0;
+
+#define SHADING_MODEL_PBR 0
+#define SHADING_MODEL_UNLIT 1
+#define SHADING_MODEL_TOON 2
+#define SHADING_MODEL_TERRAIN 3
+
 layout(location = 0) out vec4 outColor;
 
 struct Light
@@ -55,17 +81,37 @@ struct Light
 };
 
 #define MAX_NUMBER_OF_LIGHTS 4
-#define MAX_NUMBER_OF_JOINTS 1000
+
+const int ProbeBounds_Box = 0;
+const int ProbeBounds_Sphere = 1;
+
+struct ReflectionProbe
+{
+    vec3 position;
+    int kind;
+
+    // The half extents of boxes, or the radius of spheres in x
+    vec3 extents;
+    float blendDistance;
+};
 
 layout(binding=0) uniform UboView{
   mat4 view;
   mat4 projection;
   vec3 cameraPosition;
   int numberOfLights;
-  mat4 jointMatrices[MAX_NUMBER_OF_JOINTS];
   Light lights[MAX_NUMBER_OF_LIGHTS];
+  vec3 ambientLight;
+  int environmentLighting;
+  int numberOfReflectionProbes;
+  float exposure;
+  ReflectionProbe reflectionProbes[MAX_NUMBER_OF_REFLECTION_PROBES];
 } uboView;
 
+// The probe lighting this object and how much it replaces the skybox's lighting, chosen once per fragment
+int probeIndex = -1;
+float probeWeight = 0.0;
+
 vec4 srgb_to_linear(vec4 srgbIn)
 {
     return vec4(pow(srgbIn.xyz,vec3(2.2)),srgbIn.w);
@@ -77,10 +123,70 @@ const int LightType_Spot = 2;
 
 const float PI = 3.14159265359;
 
+// One well inside the probe's bounds, falling off to zero at their edge
+float probeInfluence(ReflectionProbe probe, vec3 point)
+{
+    float distanceInside;
+    if (probe.kind == ProbeBounds_Box) {
+        vec3 inside = probe.extents - abs(point - probe.position);
+        distanceInside = min(inside.x, min(inside.y, inside.z));
+    } else if (probe.kind == ProbeBounds_Sphere) {
+        distanceInside = probe.extents.x - length(point - probe.position);
+    } else {
+        // Probes that were removed since they were baked
+        return 0.0;
+    }
+    if (distanceInside < 0.0) {
+        return 0.0;
+    }
+    if (probe.blendDistance <= 0.0) {
+        return 1.0;
+    }
+    return clamp(distanceInside / probe.blendDistance, 0.0, 1.0);
+}
+
+// The most influential probe around the object, with the nearest winning ties between overlapping probes
+void selectProbe()
+{
+    float nearest = 1.0e20;
+    for (int i = 0; i < uboView.numberOfReflectionProbes; ++i) {
+        ReflectionProbe probe = uboView.reflectionProbes[i];
+        float influence = probeInfluence(probe, inObjectPosition);
+        float distance = length(inObjectPosition - probe.position);
+        if (influence > probeWeight || (influence > 0.0 && influence == probeWeight && distance < nearest)) {
+            probeIndex = i;
+            probeWeight = influence;
+            nearest = distance;
+        }
+    }
+}
+
+vec3 sampleIrradiance(vec3 N)
+{
+    vec3 irradiance = srgb_to_linear(texture(irradianceMap, N)).rgb;
+    if (probeIndex > -1) {
+        vec3 probeIrradiance = srgb_to_linear(texture(probeIrradianceMaps[probeIndex], N)).rgb;
+        irradiance = mix(irradiance, probeIrradiance, probeWeight);
+    }
+    return irradiance;
+}
+
+vec3 samplePrefilter(vec3 direction, float lod)
+{
+    vec3 prefiltered = srgb_to_linear(textureLod(prefilterMap, direction, lod)).rgb;
+    if (probeIndex > -1) {
+        vec3 probePrefiltered = srgb_to_linear(textureLod(probePrefilterMaps[probeIndex], direction, lod)).rgb;
+        prefiltered = mix(prefiltered, probePrefiltered, probeWeight);
+    }
+    return prefiltered;
+}
+
 vec3 getNormal()
 {
+    // The back faces of double sided materials are lit from their own side
+    vec3 N = gl_FrontFacing ? normalize(inNormal) : -normalize(inNormal);
     if (material.normalTextureIndex <= -1) {
-        return normalize(inNormal);
+        return N;
     }
 
     vec2 tex_coord = inUV0;
@@ -88,20 +194,27 @@ vec3 getNormal()
         tex_coord = inUV1;
     }
 
-    // Perturb normal, see http://www.thetenthplanet.de/archives/1180
-	vec3 tangentNormal = texture(textures[material.normalTextureIndex], tex_coord).xyz * 2.0 - 1.0;
-
-	vec3 q1 = dFdx(inPosition);
-	vec3 q2 = dFdy(inPosition);
-	vec2 st1 = dFdx(tex_coord);
-	vec2 st2 = dFdy(tex_coord);
-
-	vec3 N = normalize(inNormal);
-	vec3 T = normalize(q1 * st2.t - q2 * st1.t);
-	vec3 B = -normalize(cross(N, T));
-	mat3 TBN = mat3(T, B, N);
+    vec3 tangentNormal = texture(textures[material.normalTextureIndex], tex_coord).xyz * 2.0 - 1.0;
+    tangentNormal *= vec3(vec2(material.normalTextureScale), 1.0);
+
+    vec3 T;
+    vec3 B;
+    if (material.normalTextureSet != 1 && dot(inTangent.xyz, inTangent.xyz) > 0.0) {
+        // Vertex tangents are generated from the first uv set, and are re-orthogonalized after interpolation
+        T = normalize(inTangent.xyz - N * dot(N, inTangent.xyz));
+        B = cross(N, T) * inTangent.w;
+    } else {
+        // Without tangents, they are derived in screen space, see http://www.thetenthplanet.de/archives/1180
+        vec3 q1 = dFdx(inPosition);
+        vec3 q2 = dFdy(inPosition);
+        vec2 st1 = dFdx(tex_coord);
+        vec2 st2 = dFdy(tex_coord);
+        T = normalize(q1 * st2.t - q2 * st1.t);
+        B = -normalize(cross(N, T));
+    }
+    mat3 TBN = mat3(T, B, N);
 
-	return normalize(TBN * tangentNormal) * vec3(vec2(material.normalTextureScale), 1.0);
+    return normalize(TBN * tangentNormal);
 }
 
 float DistributionGGX(vec3 N, vec3 H, float roughness)
@@ -187,11 +300,90 @@ vec3 getLightIntensity(Light light, vec3 pointToLight)
     return rangeAttenuation * spotAttenuation * light.intensity * light.color;
 }
 
+// Diffuse lighting quantized into bands, plus a rim light around the silhouette
+vec3 toonShading(vec3 albedo, vec3 N, vec3 V, float occlusion, vec3 emission)
+{
+    float bands = float(max(material.toonBands, 1));
+    float smoothness = max(material.toonSmoothness, 0.0001);
+
+    vec3 diffuse = vec3(0.0);
+    vec3 rimColor = vec3(0.0);
+    for(int i = 0; i < uboView.numberOfLights; ++i)
+    {
+        Light light = uboView.lights[i];
+
+        vec3 pointToLight;
+        if(light.kind != LightType_Directional)
+        {
+            pointToLight = light.position - inPosition;
+        } else {
+            pointToLight = -light.direction;
+        }
+        vec3 L = normalize(pointToLight);
+        vec3 radiance = getLightIntensity(light, pointToLight);
+
+        // Snap the lambert term down to its band, blending into the next band just before its edge
+        float NdotL = max(dot(N, L), 0.0);
+        float scaled = NdotL * bands;
+        float ramp = (floor(scaled) + smoothstep(1.0 - smoothness, 1.0, fract(scaled))) / bands;
+        diffuse += albedo * radiance * min(ramp, 1.0);
+
+        // The rim only appears on the side of the silhouette facing the light
+        rimColor += radiance * NdotL;
+    }
+
+    vec3 irradiance = uboView.ambientLight;
+    if (uboView.environmentLighting != 0) {
+        irradiance = sampleIrradiance(N);
+    }
+    vec3 ambient = mix(irradiance * albedo, irradiance * albedo * occlusion, material.occlusionStrength);
+
+    // The strength also widens the rim, kept within smoothstep's range so its edges never meet
+    float rimWidth = clamp(material.rimStrength, 0.0001, 1.0);
+    float rim = smoothstep(1.0 - rimWidth, 1.0, 1.0 - max(dot(N, V), 0.0));
+    vec3 color = ambient + diffuse + rim * material.rimStrength * rimColor + emission;
+
+    // HDR tonemapping
+    color *= uboView.exposure;
+    color = color / (color + vec3(1.0));
+
+    // gamma correct
+    return pow(color, vec3(1.0/2.2));
+}
+
+// The color texture is a splat map, and the texture set fields hold the layers' texture indices.
+// The first layer is the base, which the splat map's red, green, and blue channels paint the others over.
+vec4 terrainColor()
+{
+    vec3 splat = vec3(0.0);
+    if (material.colorTextureIndex > -1) {
+        splat = texture(textures[material.colorTextureIndex], inUV0).rgb;
+    }
+    vec4 weights = vec4(max(1.0 - splat.r - splat.g - splat.b, 0.0), splat);
+    weights /= max(weights.x + weights.y + weights.z + weights.w, 0.0001);
+
+    int layers[4] = int[4](material.colorTextureSet, material.metallicRoughnessTextureSet, material.occlusionTextureSet, material.emissiveTextureSet);
+    vec2 tex_coord = inUV0 * material.toonSmoothness;
+    vec4 color = vec4(0.0);
+    for (int i = 0; i < 4; ++i) {
+        vec4 layer = vec4(1.0);
+        if (layers[i] > -1) {
+            layer = srgb_to_linear(texture(textures[layers[i]], tex_coord));
+        }
+        color += layer * weights[i];
+    }
+    return color;
+}
+
 void main()
 {
+    selectProbe();
+
     // base color
     vec4 baseColor = material.baseColorFactor;
-    if (material.colorTextureIndex > -1) {
+    if (SHADING_MODEL == SHADING_MODEL_TERRAIN) {
+        baseColor *= terrainColor();
+    } else if (material.colorTextureIndex > -1) {
         vec2 tex_coord = inUV0;
         if(material.colorTextureSet == 1) {
             tex_coord = inUV1;
@@ -207,12 +399,6 @@ void main()
         discard;
     }
 
-    // unlit
-    if (material.isUnlit == 1) {
-        outColor = vec4(pow(albedo, vec3(1.0 / 2.2)), material.baseColorFactor.a);
-        return;
-    }
-
     // metallic
     float metallic = material.metallicFactor;
     float roughness = material.roughnessFactor;
@@ -251,11 +437,21 @@ void main()
     vec3 V = normalize(uboView.cameraPosition - inPosition);
     vec3 R = reflect(-V, N); 
 
+    // toon
+    if (SHADING_MODEL == SHADING_MODEL_TOON) {
+        outColor = vec4(toonShading(albedo, N, V, occlusion, emission), baseColor.a);
+        return;
+    }
+
     // calculate reflectance at normal incidence; if dia-electric (like plastic) use F0
-    // of 0.04 and if it's a metal, use the albedo color as F0 (metallic workflow)
-    vec3 F0 = vec3(0.04);
+    // from the index of refraction (0.04 by default) and if it's a metal, use the albedo color as F0 (metallic workflow)
+    vec3 F0 = vec3(pow((material.ior - 1.0) / (material.ior + 1.0), 2.0));
     F0 = mix(F0, albedo, metallic);
 
+    // The clearcoat layer is a dielectric with an ior of 1.5, sharing the surface normal
+    vec3 clearcoatF0 = vec3(0.04);
+    float clearcoatRoughness = material.clearcoatRoughnessFactor;
+
     // reflectance equation
     vec3 Lo = vec3(0.0);
     for(int i = 0; i < uboView.numberOfLights; ++i)
@@ -298,12 +494,26 @@ void main()
         // have diffuse lighting, or a linear blend if partly metal (pure metals
         // have no diffuse light).
         kD *= 1.0 - metallic;
+        // transmitted light passes through instead of being scattered diffusely
+        kD *= 1.0 - material.transmissionFactor;
 
         // scale light by NdotL
         float NdotL = max(dot(N, L), 0.0);
 
+        // note that we already multiplied the BRDF by the Fresnel (kS) so we won't multiply by kS again
+        vec3 radianceOut = (kD * albedo / PI + specular) * radiance * NdotL;
+
+        // the clearcoat reflects some of the light before it reaches the base layer
+        if (material.clearcoatFactor > 0.0) {
+            vec3 Fc = fresnelSchlick(max(dot(H, V), 0.0), clearcoatF0) * material.clearcoatFactor;
+            float NDFc = DistributionGGX(N, H, clearcoatRoughness);
+            float Gc = GeometrySmith(N, V, L, clearcoatRoughness);
+            vec3 clearcoatSpecular = NDFc * Gc * Fc / denominator;
+            radianceOut = radianceOut * (1.0 - Fc) + clearcoatSpecular * radiance * NdotL;
+        }
+
         // add to outgoing radiance Lo
-        Lo += (kD * albedo / PI + specular) * radiance * NdotL;  // note that we already multiplied the BRDF by the Fresnel (kS) so we won't multiply by kS again
+        Lo += radianceOut;
     }
 
     // IBL
@@ -311,17 +521,39 @@ void main()
     vec3 kS = F;
     vec3 kD = 1.0 - kS;
     kD *= 1.0 - metallic;	  
+    vec3 kT = kD * material.transmissionFactor;
+    kD *= 1.0 - material.transmissionFactor;
     
-    vec3 irradiance = srgb_to_linear(texture(irradianceMap, N)).rgb;
-    vec3 diffuse      = irradiance * albedo;
-    
-    // sample both the pre-filter map and the BRDF lut and combine them together as per the Split-Sum approximation to get the IBL specular part.
     const float MAX_REFLECTION_LOD = 4.0;
-    vec3 prefilteredColor = srgb_to_linear(textureLod(prefilterMap, R,  roughness * MAX_REFLECTION_LOD)).rgb;    
-    vec2 brdf  = texture(brdflut, vec2(max(dot(N, V), 0.0), roughness)).rg;
-    vec3 specular = prefilteredColor * (F * brdf.x + brdf.y);
+    vec3 ambient;
+    if (uboView.environmentLighting != 0) {
+        vec3 irradiance = sampleIrradiance(N);
+        vec3 diffuse      = irradiance * albedo;
+        
+        // sample both the pre-filter map and the BRDF lut and combine them together as per the Split-Sum approximation to get the IBL specular part.
+        vec3 prefilteredColor = samplePrefilter(R, roughness * MAX_REFLECTION_LOD);    
+        vec2 brdf  = texture(brdflut, vec2(max(dot(N, V), 0.0), roughness)).rg;
+        vec3 specular = prefilteredColor * (F * brdf.x + brdf.y);
+
+        // transmission is approximated by looking through the surface into the environment
+        vec3 T = refract(-V, N, 1.0 / max(material.ior, 1.0));
+        vec3 transmittedColor = samplePrefilter(T, roughness * MAX_REFLECTION_LOD);
+        vec3 transmission = kT * transmittedColor * albedo;
+
+        ambient = kD * diffuse + specular + transmission;
+    } else {
+        // Without an environment there is nothing to reflect, so only a constant diffuse term remains
+        ambient = (kD + kT) * uboView.ambientLight * albedo;
+    }
 
-    vec3 ambient = kD * diffuse + specular;
+    // clearcoat
+    if (material.clearcoatFactor > 0.0 && uboView.environmentLighting != 0) {
+        float NdotV = max(dot(N, V), 0.0);
+        vec3 Fc = fresnelSchlickRoughness(NdotV, clearcoatF0, clearcoatRoughness) * material.clearcoatFactor;
+        vec3 clearcoatColor = samplePrefilter(R, clearcoatRoughness * MAX_REFLECTION_LOD);
+        vec2 clearcoatBrdf = texture(brdflut, vec2(NdotV, clearcoatRoughness)).rg;
+        ambient = ambient * (1.0 - Fc) + clearcoatColor * (Fc * clearcoatBrdf.x + clearcoatBrdf.y * material.clearcoatFactor);
+    }
 
     // occlusion
     ambient = mix(ambient, ambient * occlusion, material.occlusionStrength);
@@ -332,6 +564,7 @@ void main()
     color += emission;
 
     // HDR tonemapping
+    color *= uboView.exposure;
     color = color / (color + vec3(1.0));
 
     // gamma correct
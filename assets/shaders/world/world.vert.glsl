@@ -7,9 +7,9 @@ layout(location=3) in vec2 inUV1;
 layout(location=4) in vec4 inJoint0;
 layout(location=5) in vec4 inWeight0;
 layout(location=6) in vec3 inColor0;
+layout(location=7) in vec4 inTangent;
 
 #define MAX_NUMBER_OF_LIGHTS 4
-#define MAX_NUMBER_OF_JOINTS 1000
 
 struct Light
 {
@@ -33,42 +33,66 @@ layout(binding=0) uniform UboView{
   mat4 projection;
   vec3 cameraPosition;
   int numberOfLights;
-  mat4 jointMatrices[MAX_NUMBER_OF_JOINTS];
   Light lights[MAX_NUMBER_OF_LIGHTS];
+  vec3 ambientLight;
+  int environmentLighting;
 } uboView;
 
-layout(binding=1) uniform UboInstance{
+struct Entity
+{
   mat4 model;
   vec4 node_info;
-} uboInstance;
+};
+
+layout(std430, binding=1) readonly buffer EntityBuffer{
+  Entity entities[];
+} entityBuffer;
+
+layout(std430, binding=6) readonly buffer JointBuffer{
+  mat4 jointMatrices[];
+} jointBuffer;
+
+// The material occupies the push constants before the entity index
+layout(push_constant) uniform PushConstants{
+  layout(offset=120) int entityIndex;
+} pushConstants;
 
 layout(location=0) out vec3 outPosition;
 layout(location=1) out vec3 outNormal;
 layout(location=2) out vec2 outUV0;
 layout(location=3) out vec2 outUV1;
 layout(location=4) out vec3 outColor0;
+layout(location=5) out vec4 outTangent;
+layout(location=6) flat out vec3 outObjectPosition;
 
 void main()
 {
-  float jointCount = uboInstance.node_info.x;
-  float jointOffset = uboInstance.node_info.y;
+  Entity entity = entityBuffer.entities[pushConstants.entityIndex];
+  float jointCount = entity.node_info.x;
+  float jointOffset = entity.node_info.y;
 
   mat4 skinMatrix = mat4(1.0);
   if (jointCount > 0.0) {
     skinMatrix =
-      inWeight0.x * uboView.jointMatrices[int(inJoint0.x + jointOffset)] +
-      inWeight0.y * uboView.jointMatrices[int(inJoint0.y + jointOffset)] +
-      inWeight0.z * uboView.jointMatrices[int(inJoint0.z + jointOffset)] +
-      inWeight0.w * uboView.jointMatrices[int(inJoint0.w + jointOffset)];
+      inWeight0.x * jointBuffer.jointMatrices[int(inJoint0.x + jointOffset)] +
+      inWeight0.y * jointBuffer.jointMatrices[int(inJoint0.y + jointOffset)] +
+      inWeight0.z * jointBuffer.jointMatrices[int(inJoint0.z + jointOffset)] +
+      inWeight0.w * jointBuffer.jointMatrices[int(inJoint0.w + jointOffset)];
   }
-  mat4 skinnedModel = uboInstance.model * skinMatrix;
+  mat4 skinnedModel = entity.model * skinMatrix;
 
-  vec4 position = uboInstance.model * skinMatrix * vec4(inPosition, 1.0);
-  outNormal = normalize(transpose(inverse(mat3(uboInstance.model * skinMatrix))) * inNormal);
+  vec4 position = skinnedModel * vec4(inPosition, 1.0);
+  outNormal = normalize(transpose(inverse(mat3(skinnedModel))) * inNormal);
   outPosition = position.xyz / position.w;
   outUV0 = inUV0;
   outUV1 = inUV1;
   outColor0 = inColor0;
+  // Reflection probes are chosen per object rather than per fragment
+  outObjectPosition = entity.model[3].xyz;
+
+  // Mirrored transforms flip which way the bitangent points
+  mat3 surface = mat3(skinnedModel);
+  outTangent = vec4(surface * inTangent.xyz, inTangent.w * sign(determinant(surface)));
 
   gl_Position = uboView.projection * uboView.view * vec4(outPosition, 1.0);
 }
\ No newline at end of file
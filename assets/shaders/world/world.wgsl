@@ -0,0 +1,536 @@
+// The wgpu backend's counterpart to world.vert.glsl and world.frag.glsl.
+// Reflection probes aren't available to it yet, so surfaces are lit by the scene's lights and its skybox,
+// or its ambient light when it has no skybox.
+
+struct Light {
+    direction: vec3<f32>;
+    range: f32;
+
+    color: vec3<f32>;
+    intensity: f32;
+
+    position: vec3<f32>;
+    inner_cone_cos: f32;
+
+    outer_cone_cos: f32;
+    kind: i32;
+
+    padding: vec2<f32>;
+};
+
+struct WorldUniforms {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    camera_position: vec3<f32>;
+    number_of_lights: u32;
+    lights: array<Light, 4>;
+    ambient_light: vec3<f32>;
+    environment_lighting: i32;
+    number_of_reflection_probes: i32;
+    exposure: f32;
+};
+
+struct Entity {
+    model: mat4x4<f32>;
+    // X is the joint count, Y is the joint matrix offset
+    node_info: vec4<f32>;
+};
+
+struct EntityBuffer {
+    entities: array<Entity>;
+};
+
+struct JointBuffer {
+    joint_matrices: array<mat4x4<f32>>;
+};
+
+struct Material {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec3<f32>;
+    color_texture_index: i32;
+    color_texture_set: i32;
+    metallic_roughness_texture_index: i32;
+    metallic_roughness_texture_set: i32;
+    normal_texture_index: i32;
+    normal_texture_set: i32;
+    normal_texture_scale: f32;
+    occlusion_texture_index: i32;
+    occlusion_texture_set: i32;
+    occlusion_strength: f32;
+    emissive_texture_index: i32;
+    emissive_texture_set: i32;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    alpha_mode: i32;
+    alpha_cutoff: f32;
+    toon_bands: i32;
+    toon_smoothness: f32;
+    rim_strength: f32;
+    clearcoat_factor: f32;
+    clearcoat_roughness_factor: f32;
+    transmission_factor: f32;
+    ior: f32;
+    shading_model: i32;
+};
+
+[[group(0), binding(0)]] var<uniform> uniforms: WorldUniforms;
+[[group(0), binding(1)]] var<storage, read> entity_buffer: EntityBuffer;
+[[group(0), binding(2)]] var<storage, read> joint_buffer: JointBuffer;
+
+// Each material has its own bind group, with a white texture in the slots it leaves empty
+[[group(1), binding(0)]] var<uniform> material: Material;
+[[group(1), binding(1)]] var color_texture: texture_2d<f32>;
+[[group(1), binding(2)]] var color_sampler: sampler;
+[[group(1), binding(3)]] var metallic_roughness_texture: texture_2d<f32>;
+[[group(1), binding(4)]] var metallic_roughness_sampler: sampler;
+[[group(1), binding(5)]] var normal_texture: texture_2d<f32>;
+[[group(1), binding(6)]] var normal_sampler: sampler;
+[[group(1), binding(7)]] var occlusion_texture: texture_2d<f32>;
+[[group(1), binding(8)]] var occlusion_sampler: sampler;
+[[group(1), binding(9)]] var emissive_texture: texture_2d<f32>;
+[[group(1), binding(10)]] var emissive_sampler: sampler;
+// Only bound for terrain, whose color texture is a splat map painting these layers
+[[group(1), binding(11)]] var layer_0_texture: texture_2d<f32>;
+[[group(1), binding(12)]] var layer_0_sampler: sampler;
+[[group(1), binding(13)]] var layer_1_texture: texture_2d<f32>;
+[[group(1), binding(14)]] var layer_1_sampler: sampler;
+[[group(1), binding(15)]] var layer_2_texture: texture_2d<f32>;
+[[group(1), binding(16)]] var layer_2_sampler: sampler;
+[[group(1), binding(17)]] var layer_3_texture: texture_2d<f32>;
+[[group(1), binding(18)]] var layer_3_sampler: sampler;
+
+// The active skybox's environment maps, which are black when there is no skybox
+[[group(2), binding(0)]] var brdflut: texture_2d<f32>;
+[[group(2), binding(1)]] var prefilter_map: texture_cube<f32>;
+[[group(2), binding(2)]] var irradiance_map: texture_cube<f32>;
+[[group(2), binding(3)]] var environment_sampler: sampler;
+
+let PI: f32 = 3.14159265359;
+
+let MAX_NUMBER_OF_LIGHTS: u32 = 4u;
+
+let MAX_REFLECTION_LOD: f32 = 4.0;
+
+let LIGHT_DIRECTIONAL: i32 = 0;
+let LIGHT_SPOT: i32 = 2;
+
+let ALPHA_MODE_MASK: i32 = 2;
+
+let SHADING_MODEL_UNLIT: i32 = 1;
+let SHADING_MODEL_TOON: i32 = 2;
+let SHADING_MODEL_TERRAIN: i32 = 3;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv_0: vec2<f32>;
+    [[location(3)]] uv_1: vec2<f32>;
+    [[location(4)]] joint_0: vec4<f32>;
+    [[location(5)]] weight_0: vec4<f32>;
+    [[location(6)]] color_0: vec3<f32>;
+    [[location(7)]] tangent: vec4<f32>;
+    // Read per instance, since the instance index builtin doesn't include the first instance on every backend
+    [[location(8)]] entity_index: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv_0: vec2<f32>;
+    [[location(3)]] uv_1: vec2<f32>;
+    [[location(4)]] color_0: vec3<f32>;
+    [[location(5)]] tangent: vec4<f32>;
+};
+
+fn joint_matrix(joint: f32, offset: f32) -> mat4x4<f32> {
+    return joint_buffer.joint_matrices[u32(joint + offset)];
+}
+
+fn skin_matrix(joints: vec4<f32>, weights: vec4<f32>, offset: f32) -> mat4x4<f32> {
+    let joint_x = joint_matrix(joints.x, offset);
+    let joint_y = joint_matrix(joints.y, offset);
+    let joint_z = joint_matrix(joints.z, offset);
+    let joint_w = joint_matrix(joints.w, offset);
+    return mat4x4<f32>(
+        weights.x * joint_x[0] + weights.y * joint_y[0] + weights.z * joint_z[0] + weights.w * joint_w[0],
+        weights.x * joint_x[1] + weights.y * joint_y[1] + weights.z * joint_z[1] + weights.w * joint_w[1],
+        weights.x * joint_x[2] + weights.y * joint_y[2] + weights.z * joint_z[2] + weights.w * joint_w[2],
+        weights.x * joint_x[3] + weights.y * joint_y[3] + weights.z * joint_z[3] + weights.w * joint_w[3],
+    );
+}
+
+// Entities are drawn as a single instance whose first instance is the entity's index in the entity buffer
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    let entity = entity_buffer.entities[vertex.entity_index];
+
+    var model = entity.model;
+    if (entity.node_info.x > 0.0) {
+        model = model * skin_matrix(vertex.joint_0, vertex.weight_0, entity.node_info.y);
+    }
+
+    let position = model * vec4<f32>(vertex.position, 1.0);
+    let surface = mat3x3<f32>(model[0].xyz, model[1].xyz, model[2].xyz);
+
+    // The inverse transpose, up to the determinant's scale, which the normalization removes
+    let determinant = determinant(surface);
+    let normal_matrix = mat3x3<f32>(
+        cross(surface[1], surface[2]),
+        cross(surface[2], surface[0]),
+        cross(surface[0], surface[1]),
+    );
+
+    var output: VertexOutput;
+    output.position = position.xyz / position.w;
+    output.normal = normalize(normal_matrix * vertex.normal * sign(determinant));
+    output.uv_0 = vertex.uv_0;
+    output.uv_1 = vertex.uv_1;
+    output.color_0 = vertex.color_0;
+    // Mirrored transforms flip which way the bitangent points
+    output.tangent = vec4<f32>(surface * vertex.tangent.xyz, vertex.tangent.w * sign(determinant));
+    output.clip_position = uniforms.projection * uniforms.view * vec4<f32>(output.position, 1.0);
+    return output;
+}
+
+fn srgb_to_linear(color: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(pow(color.rgb, vec3<f32>(2.2)), color.a);
+}
+
+// Sampled at explicit levels, since environment lighting is only evaluated in some branches
+fn sample_irradiance(n: vec3<f32>) -> vec3<f32> {
+    return srgb_to_linear(textureSampleLevel(irradiance_map, environment_sampler, n, 0.0)).rgb;
+}
+
+fn sample_prefilter(direction: vec3<f32>, lod: f32) -> vec3<f32> {
+    return srgb_to_linear(textureSampleLevel(prefilter_map, environment_sampler, direction, lod)).rgb;
+}
+
+fn sample_brdflut(n_dot_v: f32, roughness: f32) -> vec2<f32> {
+    return textureSampleLevel(brdflut, environment_sampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+}
+
+// HDR tonemapping followed by gamma correction
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+    let exposed = color * uniforms.exposure;
+    let mapped = exposed / (exposed + vec3<f32>(1.0));
+    return pow(mapped, vec3<f32>(1.0 / 2.2));
+}
+
+fn texture_uv(input: VertexOutput, set: i32) -> vec2<f32> {
+    return select(input.uv_0, input.uv_1, set == 1);
+}
+
+fn distribution_ggx(n: vec3<f32>, h: vec3<f32>, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let n_dot_h = max(dot(n, h), 0.0);
+    let denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denominator * denominator);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = (r * r) / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+fn geometry_smith(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, roughness: f32) -> f32 {
+    let ggx_v = geometry_schlick_ggx(max(dot(n, v), 0.0), roughness);
+    let ggx_l = geometry_schlick_ggx(max(dot(n, l), 0.0), roughness);
+    return ggx_v * ggx_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(max(1.0 - cos_theta, 0.0), 5.0);
+}
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(max(1.0 - cos_theta, 0.0), 5.0);
+}
+
+// https://github.com/KhronosGroup/glTF/blob/master/extensions/2.0/Khronos/KHR_lights_punctual/README.md#range-property
+fn range_attenuation(range: f32, distance: f32) -> f32 {
+    if (range <= 0.0) {
+        // negative range means unlimited
+        return 1.0;
+    }
+    return max(min(1.0 - pow(distance / range, 4.0), 1.0), 0.0) / pow(distance, 2.0);
+}
+
+// https://github.com/KhronosGroup/glTF/blob/master/extensions/2.0/Khronos/KHR_lights_punctual/README.md#inner-and-outer-cone-angles
+fn spot_attenuation(point_to_light: vec3<f32>, spot_direction: vec3<f32>, outer_cone_cos: f32, inner_cone_cos: f32) -> f32 {
+    let actual_cos = dot(normalize(spot_direction), normalize(-point_to_light));
+    if (actual_cos > outer_cone_cos) {
+        if (actual_cos < inner_cone_cos) {
+            return smoothStep(outer_cone_cos, inner_cone_cos, actual_cos);
+        }
+        return 1.0;
+    }
+    return 0.0;
+}
+
+fn light_intensity(light: Light, point_to_light: vec3<f32>) -> vec3<f32> {
+    var attenuation = 1.0;
+    if (light.kind != LIGHT_DIRECTIONAL) {
+        attenuation = attenuation * range_attenuation(light.range, length(point_to_light));
+    }
+    if (light.kind == LIGHT_SPOT) {
+        attenuation = attenuation * spot_attenuation(point_to_light, light.direction, light.outer_cone_cos, light.inner_cone_cos);
+    }
+    return attenuation * light.intensity * light.color;
+}
+
+fn point_to_light(light: Light, position: vec3<f32>) -> vec3<f32> {
+    if (light.kind != LIGHT_DIRECTIONAL) {
+        return light.position - position;
+    }
+    return -light.direction;
+}
+
+// Diffuse lighting quantized into bands, plus a rim light around the silhouette
+fn toon_shading(albedo: vec3<f32>, n: vec3<f32>, v: vec3<f32>, position: vec3<f32>, occlusion: f32, emission: vec3<f32>) -> vec3<f32> {
+    let bands = f32(max(material.toon_bands, 1));
+    let smoothness = max(material.toon_smoothness, 0.0001);
+
+    var diffuse = vec3<f32>(0.0);
+    var rim_color = vec3<f32>(0.0);
+    let number_of_lights = min(uniforms.number_of_lights, MAX_NUMBER_OF_LIGHTS);
+    for (var index: u32 = 0u; index < number_of_lights; index = index + 1u) {
+        let light = uniforms.lights[index];
+        let to_light = point_to_light(light, position);
+        let l = normalize(to_light);
+        let radiance = light_intensity(light, to_light);
+
+        // Snap the lambert term down to its band, blending into the next band just before its edge
+        let n_dot_l = max(dot(n, l), 0.0);
+        let scaled = n_dot_l * bands;
+        let ramp = (floor(scaled) + smoothStep(1.0 - smoothness, 1.0, fract(scaled))) / bands;
+        diffuse = diffuse + albedo * radiance * min(ramp, 1.0);
+
+        // The rim only appears on the side of the silhouette facing the light
+        rim_color = rim_color + radiance * n_dot_l;
+    }
+
+    var irradiance = uniforms.ambient_light;
+    if (uniforms.environment_lighting != 0) {
+        irradiance = sample_irradiance(n);
+    }
+    let ambient = mix(irradiance * albedo, irradiance * albedo * occlusion, material.occlusion_strength);
+
+    // The strength also widens the rim, kept within smoothStep's range so its edges never meet
+    let rim_width = clamp(material.rim_strength, 0.0001, 1.0);
+    let rim = smoothStep(1.0 - rim_width, 1.0, 1.0 - max(dot(n, v), 0.0));
+    return ambient + diffuse + rim * material.rim_strength * rim_color + emission;
+}
+
+// The color texture is a splat map, and the first layer is the base the splat map's channels paint the others over
+fn terrain_color(splat_sample: vec4<f32>, layers: array<vec4<f32>, 4>) -> vec4<f32> {
+    var splat = vec3<f32>(0.0);
+    if (material.color_texture_index > -1) {
+        splat = splat_sample.rgb;
+    }
+    var weights = vec4<f32>(max(1.0 - splat.r - splat.g - splat.b, 0.0), splat);
+    weights = weights / max(weights.x + weights.y + weights.z + weights.w, 0.0001);
+    return layers[0] * weights.x + layers[1] * weights.y + layers[2] * weights.z + layers[3] * weights.w;
+}
+
+fn terrain_layer(layer_sample: vec4<f32>, layer: i32) -> vec4<f32> {
+    if (layer > -1) {
+        return srgb_to_linear(layer_sample);
+    }
+    return vec4<f32>(1.0);
+}
+
+// Unlit materials are drawn by their own pipelines with this entry point,
+// which only reads the base color so none of the lighting is evaluated
+[[stage(fragment)]]
+fn fs_unlit(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color_sample = textureSample(color_texture, color_sampler, texture_uv(input, material.color_texture_set));
+    var base_color = material.base_color_factor;
+    if (material.color_texture_index > -1) {
+        base_color = base_color * srgb_to_linear(color_sample);
+    }
+    let albedo = base_color.rgb * input.color_0;
+
+    // alpha discard
+    if (material.alpha_mode == ALPHA_MODE_MASK && base_color.a < material.alpha_cutoff) {
+        discard;
+    }
+
+    return vec4<f32>(pow(albedo, vec3<f32>(1.0 / 2.2)), base_color.a);
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput, [[builtin(front_facing)]] front_facing: bool) -> [[location(0)]] vec4<f32> {
+    let is_terrain = material.shading_model == SHADING_MODEL_TERRAIN;
+
+    // Everything is sampled up front, since textures can only be sampled and differentiated in uniform control flow.
+    // Terrain is always textured with its first uv set, and its texture set fields hold its layers instead.
+    let color_uv = select(texture_uv(input, material.color_texture_set), input.uv_0, is_terrain);
+    let normal_uv = texture_uv(input, material.normal_texture_set);
+    let tiled_uv = input.uv_0 * material.toon_smoothness;
+    let color_sample = textureSample(color_texture, color_sampler, color_uv);
+    let metallic_roughness_sample = textureSample(metallic_roughness_texture, metallic_roughness_sampler, texture_uv(input, material.metallic_roughness_texture_set));
+    let normal_sample = textureSample(normal_texture, normal_sampler, normal_uv);
+    let occlusion_sample = textureSample(occlusion_texture, occlusion_sampler, texture_uv(input, material.occlusion_texture_set));
+    let emissive_sample = textureSample(emissive_texture, emissive_sampler, texture_uv(input, material.emissive_texture_set));
+    let layers = array<vec4<f32>, 4>(
+        terrain_layer(textureSample(layer_0_texture, layer_0_sampler, tiled_uv), material.color_texture_set),
+        terrain_layer(textureSample(layer_1_texture, layer_1_sampler, tiled_uv), material.metallic_roughness_texture_set),
+        terrain_layer(textureSample(layer_2_texture, layer_2_sampler, tiled_uv), material.occlusion_texture_set),
+        terrain_layer(textureSample(layer_3_texture, layer_3_sampler, tiled_uv), material.emissive_texture_set),
+    );
+    let position_dx = dpdx(input.position);
+    let position_dy = dpdy(input.position);
+    let uv_dx = dpdx(normal_uv);
+    let uv_dy = dpdy(normal_uv);
+
+    // base color
+    var base_color = material.base_color_factor;
+    if (is_terrain) {
+        base_color = base_color * terrain_color(color_sample, layers);
+    } elseif (material.color_texture_index > -1) {
+        base_color = base_color * srgb_to_linear(color_sample);
+    }
+
+    let albedo = base_color.rgb * input.color_0;
+
+    // alpha discard
+    if (material.alpha_mode == ALPHA_MODE_MASK && base_color.a < material.alpha_cutoff) {
+        discard;
+    }
+
+    // metallic
+    var metallic = material.metallic_factor;
+    var roughness = material.roughness_factor;
+    if (material.metallic_roughness_texture_index > -1) {
+        roughness = roughness * metallic_roughness_sample.g;
+        metallic = metallic * metallic_roughness_sample.b;
+    }
+
+    // occlusion
+    var occlusion = 1.0;
+    if (material.occlusion_texture_index > -1) {
+        occlusion = occlusion_sample.r;
+    }
+
+    // emission
+    var emission = vec3<f32>(0.0);
+    if (material.emissive_texture_index > -1) {
+        emission = srgb_to_linear(emissive_sample).rgb * material.emissive_factor;
+    }
+
+    // normal, facing the viewer on the back faces of double sided materials
+    var n = normalize(input.normal);
+    if (!front_facing) {
+        n = -n;
+    }
+    if (material.normal_texture_index > -1) {
+        let tangent_normal = (normal_sample.xyz * 2.0 - vec3<f32>(1.0)) * vec3<f32>(material.normal_texture_scale, material.normal_texture_scale, 1.0);
+        var t: vec3<f32>;
+        var b: vec3<f32>;
+        if (material.normal_texture_set != 1 && dot(input.tangent.xyz, input.tangent.xyz) > 0.0) {
+            // Vertex tangents are generated from the first uv set, and are re-orthogonalized after interpolation
+            t = normalize(input.tangent.xyz - n * dot(n, input.tangent.xyz));
+            b = cross(n, t) * input.tangent.w;
+        } else {
+            // Without tangents, they are derived in screen space, see http://www.thetenthplanet.de/archives/1180
+            t = normalize(position_dx * uv_dy.y - position_dy * uv_dx.y);
+            b = -normalize(cross(n, t));
+        }
+        n = normalize(mat3x3<f32>(t, b, n) * tangent_normal);
+    }
+    let v = normalize(uniforms.camera_position - input.position);
+
+    // toon
+    if (material.shading_model == SHADING_MODEL_TOON) {
+        return vec4<f32>(tonemap(toon_shading(albedo, n, v, input.position, occlusion, emission)), base_color.a);
+    }
+
+    // reflectance at normal incidence, from the index of refraction for dielectrics and the albedo for metals
+    var f0 = vec3<f32>(pow((material.ior - 1.0) / (material.ior + 1.0), 2.0));
+    f0 = mix(f0, albedo, vec3<f32>(metallic));
+
+    // The clearcoat layer is a dielectric with an ior of 1.5, sharing the surface normal
+    let clearcoat_f0 = vec3<f32>(0.04);
+    let clearcoat_roughness = material.clearcoat_roughness_factor;
+
+    // reflectance equation
+    var lo = vec3<f32>(0.0);
+    let number_of_lights = min(uniforms.number_of_lights, MAX_NUMBER_OF_LIGHTS);
+    for (var index: u32 = 0u; index < number_of_lights; index = index + 1u) {
+        let light = uniforms.lights[index];
+        let to_light = point_to_light(light, input.position);
+        let l = normalize(to_light);
+        let h = normalize(v + l);
+        let radiance = light_intensity(light, to_light);
+
+        // Cook-Torrance BRDF
+        let ndf = distribution_ggx(n, h, roughness);
+        let g = geometry_smith(n, v, l, roughness);
+        let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+
+        let denominator = 4.0 * max(dot(n, v), 0.0) * max(dot(n, l), 0.0) + 0.001;
+        let specular = ndf * g * f / denominator;
+
+        // Only non-metals have diffuse lighting, and transmitted light passes through instead of scattering
+        let k_d = (vec3<f32>(1.0) - f) * (1.0 - metallic) * (1.0 - material.transmission_factor);
+
+        let n_dot_l = max(dot(n, l), 0.0);
+        var radiance_out = (k_d * albedo / PI + specular) * radiance * n_dot_l;
+
+        // the clearcoat reflects some of the light before it reaches the base layer
+        if (material.clearcoat_factor > 0.0) {
+            let f_c = fresnel_schlick(max(dot(h, v), 0.0), clearcoat_f0) * material.clearcoat_factor;
+            let ndf_c = distribution_ggx(n, h, clearcoat_roughness);
+            let g_c = geometry_smith(n, v, l, clearcoat_roughness);
+            let clearcoat_specular = ndf_c * g_c * f_c / denominator;
+            radiance_out = radiance_out * (vec3<f32>(1.0) - f_c) + clearcoat_specular * radiance * n_dot_l;
+        }
+
+        lo = lo + radiance_out;
+    }
+
+    // IBL
+    let n_dot_v = max(dot(n, v), 0.0);
+    let f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    var k_d = (vec3<f32>(1.0) - f) * (1.0 - metallic);
+    let k_t = k_d * material.transmission_factor;
+    k_d = k_d * (1.0 - material.transmission_factor);
+
+    let r = reflect(-v, n);
+    var ambient: vec3<f32>;
+    if (uniforms.environment_lighting != 0) {
+        let diffuse = sample_irradiance(n) * albedo;
+
+        // sample both the pre-filter map and the BRDF lut and combine them together as per the Split-Sum approximation to get the IBL specular part.
+        let prefiltered_color = sample_prefilter(r, roughness * MAX_REFLECTION_LOD);
+        let brdf = sample_brdflut(n_dot_v, roughness);
+        let specular = prefiltered_color * (f * brdf.x + vec3<f32>(brdf.y));
+
+        // transmission is approximated by looking through the surface into the environment
+        let t = refract(-v, n, 1.0 / max(material.ior, 1.0));
+        let transmitted_color = sample_prefilter(t, roughness * MAX_REFLECTION_LOD);
+        let transmission = k_t * transmitted_color * albedo;
+
+        ambient = k_d * diffuse + specular + transmission;
+    } else {
+        // Without an environment there is nothing to reflect, so only a constant diffuse term remains
+        ambient = (k_d + k_t) * uniforms.ambient_light * albedo;
+    }
+
+    // clearcoat
+    if (material.clearcoat_factor > 0.0 && uniforms.environment_lighting != 0) {
+        let f_c = fresnel_schlick_roughness(n_dot_v, clearcoat_f0, clearcoat_roughness) * material.clearcoat_factor;
+        let clearcoat_color = sample_prefilter(r, clearcoat_roughness * MAX_REFLECTION_LOD);
+        let clearcoat_brdf = sample_brdflut(n_dot_v, clearcoat_roughness);
+        ambient = ambient * (vec3<f32>(1.0) - f_c) + clearcoat_color * (f_c * clearcoat_brdf.x + vec3<f32>(clearcoat_brdf.y * material.clearcoat_factor));
+    }
+
+    // occlusion
+    ambient = mix(ambient, ambient * occlusion, vec3<f32>(material.occlusion_strength));
+
+    let color = ambient + lo + emission;
+    return vec4<f32>(tonemap(color), base_color.a);
+}
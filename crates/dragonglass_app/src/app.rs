@@ -1,19 +1,28 @@
 use std::path::Path;
 
-use crate::{logger::create_logger, Input, Resources, System};
+use crate::{
+    logger::create_logger, register_engine_cvars, settings::Settings, sync_engine_cvars, AppEvent,
+    AssetManager, CVars, CameraControllerKind, CameraControllerSystem, Events, FrameCapture, Input,
+    Replay, Resources, System, ViewWindows, Worlds,
+};
 use anyhow::Result;
-use dragonglass_config::Config;
+use dragonglass_config::{Config, RenderBackend, WindowMode};
 use dragonglass_gui::{Gui, ScreenDescriptor};
-use dragonglass_render::{create_render_backend, Backend};
-use dragonglass_world::{SdfFont, Viewport, World};
+use dragonglass_profiling::{profile_scope, Profiler};
+use dragonglass_render::{create_render_backend, Backend, DeviceLost, Renderer};
+use dragonglass_world::{SdfFont, Text, Viewport, World};
 use image::io::Reader;
+use log::warn;
 use winit::{
     dpi::PhysicalSize,
-    event::{ElementState, Event, KeyboardInput, MouseButton, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::{Icon, WindowBuilder},
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    window::{Icon, Window, WindowBuilder},
 };
 
+// Captures the next frame with RenderDoc, when the app was built with the renderdoc feature
+pub const FRAME_CAPTURE_KEY: VirtualKeyCode = VirtualKeyCode::F9;
+
 pub trait App {
     fn initialize(&mut self, _resources: &mut Resources) -> Result<()> {
         Ok(())
@@ -52,10 +61,13 @@ pub trait App {
 pub struct AppConfig {
     pub width: u32,
     pub height: u32,
-    pub is_fullscreen: bool, // TODO: This isn't respected yet
+    // Starts in borderless fullscreen unless the window settings have already been saved
+    pub is_fullscreen: bool,
     pub title: String,
     pub icon: Option<String>,
     pub backend: Backend,
+    // Given to the main camera when it has no controller of its own
+    pub camera_controller: Option<CameraControllerKind>,
 }
 
 impl Default for AppConfig {
@@ -65,102 +77,61 @@ impl Default for AppConfig {
             height: 600,
             is_fullscreen: false,
             title: "Dragonglass Application".to_string(),
-            backend: Backend::Vulkan,
+            backend: default_backend(),
             icon: None,
+            camera_controller: None,
         }
     }
 }
 
-pub fn run_application(mut app: impl App + 'static, config: AppConfig) -> Result<()> {
-    create_logger()?;
-
-    let event_loop = EventLoop::new();
-
-    let mut window_builder = WindowBuilder::new()
-        .with_title(config.title.to_string())
-        .with_inner_size(PhysicalSize::new(config.width, config.height));
-
-    if let Some(icon_path) = config.icon.as_ref() {
-        let image = Reader::open(icon_path)?.decode()?.into_rgba8();
-        let (width, height) = image.dimensions();
-        let icon = Icon::from_rgba(image.into_raw(), width, height)?;
-        window_builder = window_builder.with_window_icon(Some(icon));
-    }
-
-    let mut window = window_builder.build(&event_loop)?;
-
-    let window_dimensions = window.inner_size();
-
-    let mut input = Input::default();
-    let mut system = System::new(window_dimensions);
-
-    let screen_descriptor = ScreenDescriptor {
-        dimensions: window_dimensions,
-        scale_factor: window.scale_factor() as _,
-    };
-    let mut gui = Gui::new(screen_descriptor);
-
-    let viewport = Viewport {
-        x: 0.0,
-        y: 0.0,
-        width: window_dimensions.width as _,
-        height: window_dimensions.height as _,
-    };
-    let mut renderer = create_render_backend(&config.backend, &window, viewport)?;
-
-    let mut world = World::new()?;
-    world.fonts.insert(
-        "default".to_string(),
-        SdfFont::new("assets/fonts/font.fnt", "assets/fonts/font_sdf_rgba.png")?,
-    );
+// Browsers can only render with wgpu
+fn default_backend() -> Backend {
+    #[cfg(all(target_arch = "wasm32", feature = "webgpu"))]
+    return Backend::Wgpu;
+    #[cfg(not(all(target_arch = "wasm32", feature = "webgpu")))]
+    return Backend::Vulkan;
+}
 
-    // TODO: Load config from local file if available
-    let mut config = Config::default();
-
-    app.initialize(&mut Resources {
-        config: &mut config,
-        window: &mut window,
-        world: &mut world,
-        gui: &mut gui,
-        renderer: &mut renderer,
-        input: &mut input,
-        system: &mut system,
-    })?;
-
-    event_loop.run(move |event, _, control_flow| {
-        let state = Resources {
-            config: &mut config,
-            window: &mut window,
-            world: &mut world,
-            gui: &mut gui,
-            renderer: &mut renderer,
-            input: &mut input,
-            system: &mut system,
-        };
-        if let Err(error) = run_loop(&mut app, state, event, control_flow) {
-            eprintln!("Application Error: {}", error);
-        }
-    });
+pub fn run_application(app: impl App + 'static, config: AppConfig) -> Result<()> {
+    create_logger()?;
+    initialize_resources(app, config)
 }
 
-fn run_loop(
+pub(crate) fn run_loop(
     app: &mut impl App,
     mut resources: Resources,
+    camera_controllers: &CameraControllerSystem,
+    settings: &mut Settings,
     event: Event<()>,
+    target: &EventLoopWindowTarget<()>,
     control_flow: &mut ControlFlow,
 ) -> Result<()> {
     *control_flow = ControlFlow::Poll;
 
-    // if app.gui_active() {
-    resources.gui.handle_event(&event);
-    // }
-    // if !app.gui_active() || !resources.gui.captures_event(&event) {
-    app.handle_events(&event, &mut resources)?;
-    resources.system.handle_event(&event);
-    resources
-        .input
-        .handle_event(&event, resources.system.window_center());
-    // }
+    // Each pass through the event loop is profiled as a frame,
+    // and the previous frame's events are dropped
+    if let Event::NewEvents(_) = event {
+        Profiler::lock().new_frame();
+        resources.events.clear();
+    }
+
+    if resources.views.handle_event(&event, resources.renderer)? {
+        return Ok(());
+    }
+
+    {
+        profile_scope!("input");
+        // if app.gui_active() {
+        resources.gui.handle_event(&event);
+        // }
+        // if !app.gui_active() || !resources.gui.captures_event(&event) {
+        app.handle_events(&event, &mut resources)?;
+        resources.system.handle_event(&event);
+        resources
+            .input
+            .handle_event(&event, resources.system.window_center());
+        // }
+    }
 
     match event {
         Event::NewEvents(_) => {
@@ -169,38 +140,90 @@ fn run_loop(
             }
         }
         Event::WindowEvent { ref event, .. } => match event {
-            WindowEvent::Resized(physical_size) => resources.renderer.set_viewport(Viewport {
-                x: 0.0,
-                y: 0.0,
-                width: physical_size.width as _,
-                height: physical_size.height as _,
-            }),
-            WindowEvent::DroppedFile(ref path) => app.on_file_dropped(path, &mut resources)?,
-            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::DroppedFile(ref path) => {
+                resources.events.send(AppEvent::FileDropped(path.clone()));
+                app.on_file_dropped(path, &mut resources)?
+            }
+            WindowEvent::Focused(focused) => {
+                resources.events.send(AppEvent::Focused(*focused));
+                resources.apply_cursor_mode(*focused)?
+            }
+            WindowEvent::CloseRequested => {
+                resources.events.send(AppEvent::CloseRequested);
+                *control_flow = ControlFlow::Exit
+            }
             WindowEvent::MouseInput { button, state, .. } => {
                 app.on_mouse(button, state, &mut resources)?
             }
-            WindowEvent::KeyboardInput { input, .. } => {
+            // Keys typed into the gui, such as into a text field, aren't shortcuts
+            WindowEvent::KeyboardInput { input, .. } if !resources.gui.wants_keyboard_input() => {
+                if let (Some(FRAME_CAPTURE_KEY), ElementState::Pressed) =
+                    (input.virtual_keycode, input.state)
+                {
+                    if let Err(error) = resources.capture.trigger() {
+                        warn!("{}", error);
+                    }
+                }
                 app.on_key(*input, &mut resources)?;
             }
             _ => (),
         },
         Event::MainEventsCleared => {
-            resources.world.tick(resources.system.delta_time as f32)?;
+            if let Some(physical_size) = resources.system.take_resize() {
+                resize(&mut resources, physical_size);
+            }
+            resources.assets.update();
+            resources.events.send_all(resources.assets.take_finished());
+            resources.apply_reloaded_assets()?;
+            {
+                profile_scope!("simulation");
+                resources.update_physics_lod()?;
+                // The world's clock scales, pauses, and steps the frame time
+                let delta_time = resources.replay.update(
+                    resources.input,
+                    resources.world,
+                    resources.system.delta_time as f32,
+                );
+                resources.world.tick(delta_time)?;
+                resources
+                    .events
+                    .send_all(resources.world.take_collision_events());
+                resources
+                    .events
+                    .send_all(resources.world.take_animation_events());
+                if resources.cvars.get::<bool>("debug.skeletons") == Some(true) {
+                    resources.world.draw_skeletons()?;
+                }
+            }
 
             let clipped_meshes = if app.gui_active() {
+                profile_scope!("gui");
                 let _frame_data = resources
                     .gui
                     .start_frame(resources.window.scale_factor() as _);
 
                 app.update_gui(&mut resources)?;
+                resources.paint_debug_draw();
                 let shapes = resources.gui.end_frame(resources.window);
                 resources.gui.context().tessellate(shapes)
             } else {
+                resources.world.debug_draw.clear();
                 Vec::new()
             };
 
-            app.update(&mut resources)?;
+            {
+                profile_scope!("app update");
+                app.update(&mut resources)?;
+                camera_controllers.update(&mut resources)?;
+                sync_engine_cvars(&mut resources)?;
+                resources.update_mesh_lods()?;
+            }
+
+            if resources.worlds.take_active_changed() {
+                resources.renderer.load_world(resources.world)?;
+            }
+            resources.views.open_pending(target, resources.renderer)?;
+            resources.update_occlusion()?;
 
             let context_ref = &resources.gui.context();
             let gui_context = if app.gui_active() {
@@ -208,14 +231,27 @@ fn run_loop(
             } else {
                 None
             };
-            resources.renderer.update(
-                resources.world,
-                gui_context,
-                &clipped_meshes,
-                resources.system.milliseconds_since_start(),
-                resources.config,
-            )?;
-            resources.renderer.render(resources.world, clipped_meshes)?;
+            profile_scope!("render");
+            let rendered = resources
+                .renderer
+                .update(
+                    resources.world,
+                    gui_context,
+                    &clipped_meshes,
+                    resources.system.milliseconds_since_start(),
+                    resources.config,
+                )
+                .and_then(|_| resources.renderer.render(resources.world, clipped_meshes))
+                .and_then(|_| resources.views.render(resources.world, resources.renderer));
+            match rendered {
+                Err(error) if error.is::<DeviceLost>() => {
+                    resources.renderer.recover(resources.world)?;
+                    resources.events.send(AppEvent::DeviceLost);
+                }
+                result => result?,
+            }
+            resources.capture.update();
+            settings.update(&mut resources);
         }
         Event::LoopDestroyed => {
             app.cleanup()?;
@@ -226,71 +262,190 @@ fn run_loop(
     Ok(())
 }
 
+fn resize(resources: &mut Resources, physical_size: PhysicalSize<u32>) {
+    resources.renderer.set_viewport(Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: physical_size.width as _,
+        height: physical_size.height as _,
+    });
+    resources.events.send(AppEvent::Resized {
+        width: physical_size.width,
+        height: physical_size.height,
+    });
+    // Minimizing reports a size of zero, which isn't worth restoring
+    let minimized = physical_size.width == 0 || physical_size.height == 0;
+    if resources.config.window.mode == WindowMode::Windowed && !minimized {
+        resources.config.window.size = Some([physical_size.width, physical_size.height]);
+    }
+}
+
 pub fn initialize_resources(mut app: impl App + 'static, config: AppConfig) -> Result<()> {
     let event_loop = EventLoop::new();
+    let mut state = AppState::new(&event_loop, &config)?;
+    let camera_controllers = CameraControllerSystem::new(config.camera_controller)?;
+    app.initialize(&mut state.resources())?;
+    let mut settings = Settings::new(&mut state.resources());
+
+    event_loop.run(move |event, target, control_flow| {
+        if let Err(error) = run_loop(
+            &mut app,
+            state.resources(),
+            &camera_controllers,
+            &mut settings,
+            event,
+            target,
+            control_flow,
+        ) {
+            eprintln!("Application Error: {}", error);
+        }
+    });
+}
 
-    let mut window_builder = WindowBuilder::new()
-        .with_title(config.title.to_string())
-        .with_inner_size(PhysicalSize::new(config.width, config.height));
+// Everything the resources borrow, owned for the lifetime of the event loop.
+// Fields drop in order, so the renderer goes before the windows it presents to.
+pub(crate) struct AppState {
+    pub renderer: Box<dyn Renderer>,
+    pub gui: Gui,
+    pub world: World,
+    pub worlds: Worlds,
+    pub input: Input,
+    pub system: System,
+    pub assets: AssetManager,
+    pub events: Events,
+    pub cvars: CVars,
+    pub replay: Replay,
+    pub capture: FrameCapture,
+    pub views: ViewWindows,
+    pub config: Config,
+    pub window: Window,
+}
 
-    if let Some(icon_path) = config.icon.as_ref() {
-        let image = Reader::open(icon_path)?.decode()?.into_rgba8();
-        let (width, height) = image.dimensions();
-        let icon = Icon::from_rgba(image.into_raw(), width, height)?;
-        window_builder = window_builder.with_window_icon(Some(icon));
-    }
+impl AppState {
+    pub fn new(event_loop: &EventLoop<()>, app_config: &AppConfig) -> Result<Self> {
+        let mut config = Config::load_or_default(Config::FILE);
+        if app_config.is_fullscreen && !Path::new(Config::FILE).exists() {
+            config.window.mode = WindowMode::Borderless;
+        }
 
-    let mut window = window_builder.build(&event_loop)?;
+        let [width, height] = config
+            .window
+            .size
+            .unwrap_or([app_config.width, app_config.height]);
+        let mut window_builder = WindowBuilder::new()
+            .with_title(app_config.title.to_string())
+            .with_inner_size(PhysicalSize::new(width, height));
+
+        if let Some(icon_path) = app_config.icon.as_ref() {
+            let image = Reader::open(icon_path)?.decode()?.into_rgba8();
+            let (width, height) = image.dimensions();
+            let icon = Icon::from_rgba(image.into_raw(), width, height)?;
+            window_builder = window_builder.with_window_icon(Some(icon));
+        }
 
-    let window_dimensions = window.inner_size();
+        let window = window_builder.build(event_loop)?;
+        #[cfg(target_arch = "wasm32")]
+        attach_canvas(&window)?;
 
-    let mut input = Input::default();
-    let mut system = System::new(window_dimensions);
+        let window_dimensions = window.inner_size();
 
-    let screen_descriptor = ScreenDescriptor {
-        dimensions: window_dimensions,
-        scale_factor: window.scale_factor() as _,
-    };
-    let mut gui = Gui::new(screen_descriptor);
+        let screen_descriptor = ScreenDescriptor {
+            dimensions: window_dimensions,
+            scale_factor: window.scale_factor() as _,
+        };
 
-    let viewport = Viewport {
-        x: 0.0,
-        y: 0.0,
-        width: window_dimensions.width as _,
-        height: window_dimensions.height as _,
-    };
-    let mut renderer = create_render_backend(&config.backend, &window, viewport)?;
-
-    let mut world = World::new()?;
-    world.fonts.insert(
-        "default".to_string(),
+        let viewport = Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: window_dimensions.width as _,
+            height: window_dimensions.height as _,
+        };
+        // RenderDoc only sees devices created after it is loaded
+        let capture = FrameCapture::default();
+        let backend = preferred_backend(app_config, &config);
+        let renderer = create_render_backend(&backend, &window, viewport, &config.graphics)?;
+
+        let mut world = World::new()?;
+        load_default_font(&mut world)?;
+
+        let mut cvars = CVars::default();
+        register_engine_cvars(&mut cvars);
+
+        let mut state = Self {
+            renderer,
+            gui: Gui::new(screen_descriptor),
+            world,
+            worlds: Worlds::default(),
+            input: Input::default(),
+            system: System::new(window_dimensions),
+            assets: AssetManager::default(),
+            events: Events::default(),
+            cvars,
+            replay: Replay::default(),
+            capture,
+            views: ViewWindows::default(),
+            config,
+            window,
+        };
+        state.resources().apply_window_settings()?;
+        Ok(state)
+    }
+
+    pub fn resources(&mut self) -> Resources {
+        Resources {
+            config: &mut self.config,
+            window: &mut self.window,
+            world: &mut self.world,
+            worlds: &mut self.worlds,
+            gui: &mut self.gui,
+            renderer: &mut self.renderer,
+            input: &mut self.input,
+            system: &mut self.system,
+            assets: &mut self.assets,
+            events: &mut self.events,
+            cvars: &mut self.cvars,
+            replay: &mut self.replay,
+            capture: &mut self.capture,
+            views: &mut self.views,
+        }
+    }
+}
+
+// Text that doesn't name a font of its own is drawn with this one,
+// so every new world needs it
+pub fn load_default_font(world: &mut World) -> Result<()> {
+    world.assets.fonts.insert(
+        Text::DEFAULT_FONT.to_string(),
         SdfFont::new("assets/fonts/font.fnt", "assets/fonts/font_sdf_rgba.png")?,
     );
+    Ok(())
+}
 
-    let mut config = Config::default();
-
-    app.initialize(&mut Resources {
-        config: &mut config,
-        window: &mut window,
-        world: &mut world,
-        gui: &mut gui,
-        renderer: &mut renderer,
-        input: &mut input,
-        system: &mut system,
-    })?;
-
-    event_loop.run(move |event, _, control_flow| {
-        let state = Resources {
-            config: &mut config,
-            window: &mut window,
-            world: &mut world,
-            gui: &mut gui,
-            renderer: &mut renderer,
-            input: &mut input,
-            system: &mut system,
-        };
-        if let Err(error) = run_loop(&mut app, state, event, control_flow) {
-            eprintln!("Application Error: {}", error);
+// The window is drawn to a canvas, which has to be added to the page to be seen
+#[cfg(target_arch = "wasm32")]
+fn attach_canvas(window: &Window) -> Result<()> {
+    use anyhow::Context;
+    use winit::platform::web::WindowExtWebSys;
+    let body = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.body())
+        .context("Failed to find the body of the page")?;
+    body.append_child(&window.canvas())
+        .map_err(|_| anyhow::anyhow!("Failed to add the canvas to the page"))?;
+    Ok(())
+}
+
+// The backend chosen in the settings wins over the app's, when it was built in
+fn preferred_backend(app_config: &AppConfig, config: &Config) -> Backend {
+    match config.graphics.backend {
+        Some(RenderBackend::Vulkan) => Backend::Vulkan,
+        #[cfg(feature = "webgpu")]
+        Some(RenderBackend::Wgpu) => Backend::Wgpu,
+        #[cfg(not(feature = "webgpu"))]
+        Some(RenderBackend::Wgpu) => {
+            warn!("The wgpu backend is not available in this build");
+            app_config.backend
         }
-    });
+        None => app_config.backend,
+    }
 }
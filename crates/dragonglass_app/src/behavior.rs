@@ -0,0 +1,383 @@
+use crate::Resources;
+use anyhow::Result;
+use dragonglass_world::{
+    BehaviorTree, Blackboard, BlackboardValue, Entity, EntityStore, IntoQuery, PathFollower,
+    RigidBody, Transform,
+};
+use log::warn;
+use nalgebra_glm as glm;
+use std::{collections::HashMap, rc::Rc};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+// What a leaf task can see and change while it runs for an entity
+pub struct TaskContext<'a, 'b> {
+    pub resources: &'a mut Resources<'b>,
+    pub entity: Entity,
+    pub blackboard: &'a mut Blackboard,
+}
+
+type TaskFn = dyn Fn(&mut TaskContext) -> Result<Status>;
+
+// Runs leaf tasks that live outside of the tree, such as functions in an entity's script
+pub trait TaskRunner {
+    fn run_task(&mut self, resources: &mut Resources, entity: Entity, task: &str)
+        -> Result<Status>;
+}
+
+// Sent when a leaf task succeeds or fails, so other systems can react to what entities are doing
+#[derive(Debug, Clone, PartialEq)]
+pub struct BehaviorTaskFinished {
+    pub entity: Entity,
+    pub task: String,
+    pub status: Status,
+}
+
+#[derive(Clone)]
+pub enum BehaviorNode {
+    // Runs its children in order until one fails
+    Sequence(Vec<BehaviorNode>),
+    // Runs its children in order until one succeeds
+    Selector(Vec<BehaviorNode>),
+    // Like a selector, but checks its children from the first every tick instead of resuming the one
+    // that was running, so a higher priority child like an attack can interrupt a lower one like a patrol
+    ReactiveSelector(Vec<BehaviorNode>),
+    // Swaps success and failure
+    Invert(Box<BehaviorNode>),
+    // Succeeds once its child finishes, whether it failed or not
+    Succeed(Box<BehaviorNode>),
+    // Runs its child again each time it succeeds, and succeeds once it fails
+    RepeatUntilFail(Box<BehaviorNode>),
+    Task(String, Rc<TaskFn>),
+    // Calls a function in the entity's script through the system's task runner
+    Script(String),
+}
+
+impl BehaviorNode {
+    pub fn task(
+        name: impl Into<String>,
+        task: impl Fn(&mut TaskContext) -> Result<Status> + 'static,
+    ) -> Self {
+        Self::Task(name.into(), Rc::new(task))
+    }
+
+    // A task that succeeds when the condition holds and fails otherwise
+    pub fn condition(
+        name: impl Into<String>,
+        condition: impl Fn(&mut TaskContext) -> Result<bool> + 'static,
+    ) -> Self {
+        Self::task(name, move |context| {
+            Ok(if condition(context)? {
+                Status::Success
+            } else {
+                Status::Failure
+            })
+        })
+    }
+
+    pub fn invert(child: BehaviorNode) -> Self {
+        Self::Invert(Box::new(child))
+    }
+
+    pub fn succeed(child: BehaviorNode) -> Self {
+        Self::Succeed(Box::new(child))
+    }
+
+    pub fn repeat_until_fail(child: BehaviorNode) -> Self {
+        Self::RepeatUntilFail(Box::new(child))
+    }
+
+    // The number of nodes in this subtree, which numbers nodes in the order they are declared
+    fn size(&self) -> usize {
+        match self {
+            Self::Sequence(children)
+            | Self::Selector(children)
+            | Self::ReactiveSelector(children) => {
+                1 + children.iter().map(Self::size).sum::<usize>()
+            }
+            Self::Invert(child) | Self::Succeed(child) | Self::RepeatUntilFail(child) => {
+                1 + child.size()
+            }
+            Self::Task(..) | Self::Script(_) => 1,
+        }
+    }
+}
+
+// Ticks the behavior tree of every entity with a BehaviorTree component while the simulation runs.
+// Trees are registered by name, and their leaf tasks are closures shared by every entity running them.
+#[derive(Default)]
+pub struct BehaviorTreeSystem {
+    trees: HashMap<String, BehaviorNode>,
+}
+
+impl BehaviorTreeSystem {
+    pub fn register(&mut self, name: impl Into<String>, root: BehaviorNode) {
+        self.trees.insert(name.into(), root);
+    }
+
+    pub fn update(
+        &self,
+        resources: &mut Resources,
+        mut runner: Option<&mut dyn TaskRunner>,
+    ) -> Result<()> {
+        if resources.world.clock.delta() <= 0.0 {
+            return Ok(());
+        }
+        let mut query = <(Entity, &BehaviorTree)>::query();
+        let entities = query
+            .iter(&resources.world.ecs)
+            .filter(|(_, tree)| tree.enabled)
+            .map(|(entity, tree)| (*entity, tree.clone()))
+            .collect::<Vec<_>>();
+
+        for (entity, mut state) in entities.into_iter() {
+            let root = match self.trees.get(&state.tree) {
+                Some(root) => root,
+                None => {
+                    warn!("Behavior tree '{}' is not registered", state.tree);
+                    continue;
+                }
+            };
+            let mut tick = Tick {
+                resources: &mut *resources,
+                runner: runner.as_deref_mut(),
+                entity,
+                state: &mut state,
+            };
+            let status = tick.node(root, 0)?;
+            // Finished trees start over from the beginning
+            if status != Status::Running {
+                state.cursors.clear();
+            }
+            // Tasks may have despawned the entity
+            if let Some(mut entry) = resources.world.ecs.entry(entity) {
+                if let Ok(component) = entry.get_component_mut::<BehaviorTree>() {
+                    *component = state;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Tick<'a, 'b, 'c> {
+    resources: &'a mut Resources<'b>,
+    runner: Option<&'a mut (dyn TaskRunner + 'c)>,
+    entity: Entity,
+    state: &'a mut BehaviorTree,
+}
+
+impl<'a, 'b, 'c> Tick<'a, 'b, 'c> {
+    fn node(&mut self, node: &BehaviorNode, index: usize) -> Result<Status> {
+        match node {
+            BehaviorNode::Sequence(children) => {
+                self.composite(children, index, Status::Success, false)
+            }
+            BehaviorNode::Selector(children) => {
+                self.composite(children, index, Status::Failure, false)
+            }
+            BehaviorNode::ReactiveSelector(children) => {
+                self.composite(children, index, Status::Failure, true)
+            }
+            BehaviorNode::Invert(child) => Ok(match self.node(child, index + 1)? {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            }),
+            BehaviorNode::Succeed(child) => Ok(match self.node(child, index + 1)? {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            }),
+            BehaviorNode::RepeatUntilFail(child) => Ok(match self.node(child, index + 1)? {
+                Status::Failure => Status::Success,
+                _ => Status::Running,
+            }),
+            BehaviorNode::Task(name, task) => {
+                let mut context = TaskContext {
+                    resources: &mut *self.resources,
+                    entity: self.entity,
+                    blackboard: &mut self.state.blackboard,
+                };
+                let status = task(&mut context)?;
+                self.finished(name, status);
+                Ok(status)
+            }
+            BehaviorNode::Script(function) => {
+                let status = match self.runner.as_deref_mut() {
+                    Some(runner) => runner.run_task(self.resources, self.entity, function)?,
+                    None => {
+                        warn!("Script task '{}' has no task runner to call it", function);
+                        Status::Failure
+                    }
+                };
+                self.finished(function, status);
+                Ok(status)
+            }
+        }
+    }
+
+    // Sequences continue past children that succeed, and selectors past children that fail.
+    // Reactive composites start from their first child every tick.
+    fn composite(
+        &mut self,
+        children: &[BehaviorNode],
+        index: usize,
+        continue_on: Status,
+        reactive: bool,
+    ) -> Result<Status> {
+        let cursor = self.state.cursors.get(&index).copied();
+        let start = if reactive {
+            0
+        } else {
+            cursor.unwrap_or_default()
+        };
+        let mut child_index = index
+            + 1
+            + children[..start.min(children.len())]
+                .iter()
+                .map(BehaviorNode::size)
+                .sum::<usize>();
+        for (position, child) in children.iter().enumerate().skip(start) {
+            let status = self.node(child, child_index)?;
+            if status != continue_on {
+                if let Some(running) = cursor.filter(|running| *running > position) {
+                    self.interrupt(children, index, running);
+                }
+            }
+            if status == Status::Running {
+                self.state.cursors.insert(index, position);
+                return Ok(Status::Running);
+            }
+            if status != continue_on {
+                self.state.cursors.remove(&index);
+                return Ok(status);
+            }
+            child_index += child.size();
+        }
+        self.state.cursors.remove(&index);
+        Ok(continue_on)
+    }
+
+    // Forgets where a running child of a reactive composite was, once an earlier child takes over
+    fn interrupt(&mut self, children: &[BehaviorNode], index: usize, running: usize) {
+        let start = index
+            + 1
+            + children[..running]
+                .iter()
+                .map(BehaviorNode::size)
+                .sum::<usize>();
+        let end = start + children[running].size();
+        self.state
+            .cursors
+            .retain(|cursor, _| !(start..end).contains(cursor));
+    }
+
+    fn finished(&mut self, task: &str, status: Status) {
+        if status == Status::Running {
+            return;
+        }
+        self.resources.events.send(BehaviorTaskFinished {
+            entity: self.entity,
+            task: task.to_string(),
+            status,
+        });
+    }
+}
+
+// Leaf tasks for common behaviors.
+// There is no navigation mesh, so movement heads straight for its destination.
+pub mod tasks {
+    use super::*;
+
+    // Succeeds while the entity stored under the key is within the distance
+    pub fn within_range(key: &'static str, distance: f32) -> BehaviorNode {
+        BehaviorNode::condition(format!("within_range {}", key), move |context| {
+            let target = match context.blackboard.entity(key) {
+                Some(target) => target,
+                None => return Ok(false),
+            };
+            let world = &context.resources.world;
+            let (position, target) = match (
+                world.entity_global_transform(context.entity),
+                world.entity_global_transform(target),
+            ) {
+                (Ok(position), Ok(target)) => (position.translation, target.translation),
+                _ => return Ok(false),
+            };
+            Ok(glm::distance(&position, &target) <= distance)
+        })
+    }
+
+    // Moves toward the entity stored under the key, succeeding once within the arrival distance.
+    // The entity stops following its path while it moves on its own.
+    pub fn move_toward(key: &'static str, speed: f32, arrive_distance: f32) -> BehaviorNode {
+        BehaviorNode::task(format!("move_toward {}", key), move |context| {
+            let target = match context.blackboard.entity(key) {
+                Some(target) => target,
+                None => return Ok(Status::Failure),
+            };
+            let world = &mut *context.resources.world;
+            let target = match world.entity_global_transform(target) {
+                Ok(transform) => transform.translation,
+                Err(_) => return Ok(Status::Failure),
+            };
+            let delta_time = world.clock.delta();
+            let has_rigid_body = {
+                let mut entry = world.ecs.entry_mut(context.entity)?;
+                if let Ok(follower) = entry.get_component_mut::<PathFollower>() {
+                    follower.playing = false;
+                }
+                let transform = entry.get_component_mut::<Transform>()?;
+                let mut offset = target - transform.translation;
+                offset.y = 0.0;
+                let distance = offset.norm();
+                if distance <= arrive_distance {
+                    return Ok(Status::Success);
+                }
+                let direction = offset / distance;
+                transform.translation += direction * (speed * delta_time).min(distance);
+                transform.look_at(&direction, &glm::Vec3::y());
+                entry.get_component::<RigidBody>().is_ok()
+            };
+            if has_rigid_body {
+                world.sync_rigid_body_to_transform(context.entity)?;
+            }
+            Ok(Status::Running)
+        })
+    }
+
+    // Follows the entity's path for as long as the task runs
+    pub fn patrol() -> BehaviorNode {
+        BehaviorNode::task("patrol", |context| {
+            let mut entry = context.resources.world.ecs.entry_mut(context.entity)?;
+            match entry.get_component_mut::<PathFollower>() {
+                Ok(follower) => {
+                    follower.playing = true;
+                    Ok(Status::Running)
+                }
+                Err(_) => Ok(Status::Failure),
+            }
+        })
+    }
+
+    // Succeeds at most once every given number of seconds, failing in between
+    pub fn cooldown(key: &'static str, seconds: f32) -> BehaviorNode {
+        BehaviorNode::condition(format!("cooldown {}", key), move |context| {
+            let now = context.resources.world.clock.elapsed() as f32;
+            match context.blackboard.number(key) {
+                Some(ready) if now < ready => Ok(false),
+                _ => {
+                    context
+                        .blackboard
+                        .set(key, BlackboardValue::Number(now + seconds));
+                    Ok(true)
+                }
+            }
+        })
+    }
+}
@@ -1,20 +1,135 @@
-use crate::Resources;
+use crate::{CursorMode, Resources};
 use anyhow::Result;
-use dragonglass_world::{Entity, EntityStore, Transform};
+use dragonglass_config::InputCalibration;
+use dragonglass_world::{register_component, CameraPath, Entity, EntityStore, Transform, World};
 use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
 use winit::event::VirtualKeyCode;
 
-#[derive(Default)]
-pub struct MouseOrbit {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraControllerKind {
+    Orbit,
+    Fly,
+    Follow,
+}
+
+// Drives the transform of the camera entity it is attached to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CameraController {
+    Orbit(OrbitCamera),
+    Fly(FlyCamera),
+    Follow(FollowCamera),
+}
+
+impl CameraController {
+    pub fn kind(&self) -> CameraControllerKind {
+        match self {
+            Self::Orbit(_) => CameraControllerKind::Orbit,
+            Self::Fly(_) => CameraControllerKind::Fly,
+            Self::Follow(_) => CameraControllerKind::Follow,
+        }
+    }
+
+    pub fn update(&mut self, resources: &mut Resources, entity: Entity) -> Result<()> {
+        match self {
+            Self::Orbit(camera) => camera.update(resources, entity),
+            Self::Fly(camera) => camera.update(resources, entity),
+            Self::Follow(camera) => camera.update(resources, entity),
+        }
+    }
+}
+
+impl From<CameraControllerKind> for CameraController {
+    fn from(kind: CameraControllerKind) -> Self {
+        match kind {
+            CameraControllerKind::Orbit => Self::Orbit(OrbitCamera::default()),
+            CameraControllerKind::Fly => Self::Fly(FlyCamera::default()),
+            CameraControllerKind::Follow => Self::Follow(FollowCamera::default()),
+        }
+    }
+}
+
+// Replaces whichever controller the camera had, taking effect on the next update
+pub fn set_camera_controller(
+    world: &mut World,
+    entity: Entity,
+    controller: CameraController,
+) -> Result<()> {
+    if let Some(mut entry) = world.ecs.entry(entity) {
+        entry.add_component(controller);
+    }
+    Ok(())
+}
+
+// Updates the controller of the active camera each frame.
+// A main camera without a controller is given the default one, if there is a default.
+pub struct CameraControllerSystem {
+    pub default_controller: Option<CameraControllerKind>,
+    // The camera and controller that last ran, so the cursor is only set when either changes
+    active: Cell<Option<(Entity, CameraControllerKind)>>,
+}
+
+impl CameraControllerSystem {
+    pub fn new(default_controller: Option<CameraControllerKind>) -> Result<Self> {
+        register_component::<CameraController>("camera_controller")?;
+        Ok(Self {
+            default_controller,
+            active: Cell::new(None),
+        })
+    }
+
+    pub fn update(&self, resources: &mut Resources) -> Result<()> {
+        let entity = match resources.world.active_camera() {
+            Ok(entity) => entity,
+            Err(_) => return Ok(()),
+        };
+
+        let existing = {
+            let entry = resources.world.ecs.entry_ref(entity)?;
+            // Cameras playing a path are moved by it instead
+            if let Ok(path) = entry.get_component::<CameraPath>() {
+                if path.playing {
+                    return Ok(());
+                }
+            }
+            entry.get_component::<CameraController>().ok().cloned()
+        };
+        let mut controller = match (existing, self.default_controller) {
+            (Some(controller), _) => controller,
+            (None, Some(kind)) if resources.world.active_camera_is_main()? => {
+                CameraController::from(kind)
+            }
+            _ => return Ok(()),
+        };
+
+        let active = Some((entity, controller.kind()));
+        if self.active.replace(active) != active {
+            match controller.kind() {
+                CameraControllerKind::Orbit | CameraControllerKind::Fly => {
+                    resources.set_cursor_mode(CursorMode::Free)?
+                }
+                CameraControllerKind::Follow => {}
+            }
+        }
+
+        controller.update(resources, entity)?;
+        set_camera_controller(resources.world, entity, controller)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct OrbitCamera {
     pub orientation: Orientation,
 }
 
-impl MouseOrbit {
+impl OrbitCamera {
     pub fn update(&mut self, resources: &mut Resources, entity: Entity) -> Result<()> {
         self.orientation
             .zoom(resources.input.mouse.wheel_delta.y * 0.3);
 
-        let mouse_delta = resources.input.mouse.position_delta * resources.system.delta_time as f32;
+        let mouse_delta = calibrated_mouse_delta(resources, resources.input.mouse.position_delta)
+            * resources.system.delta_time as f32;
 
         if resources.input.mouse.is_right_clicked
             && !resources.input.is_key_pressed(VirtualKeyCode::LShift)
@@ -36,39 +151,156 @@ impl MouseOrbit {
             transform.rotation = self.orientation.look_at_offset();
         }
 
-        resources.set_cursor_grab(false)?;
-        resources.set_cursor_visible(true);
-
         Ok(())
     }
 }
 
-#[derive(Default)]
-pub struct MouseLook {
+// Looks around while the right mouse button is held and moves with WASD, Q, and E.
+// Holding left shift moves faster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlyCamera {
     pub orientation: Orientation,
+    pub speed: f32,
+    pub boost: f32,
 }
 
-impl MouseLook {
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self {
+            orientation: Orientation::default(),
+            speed: 5.0,
+            boost: 4.0,
+        }
+    }
+}
+
+impl FlyCamera {
     pub fn update(&mut self, resources: &mut Resources, entity: Entity) -> Result<()> {
-        let mouse_delta =
-            resources.input.mouse.offset_from_center * resources.system.delta_time as f32;
+        let delta_time = resources.system.delta_time as f32;
 
-        self.orientation.rotate(&mouse_delta);
+        if resources.input.mouse.is_right_clicked {
+            let mouse_delta =
+                calibrated_mouse_delta(resources, resources.input.mouse.position_delta)
+                    * delta_time;
+            self.orientation.rotate(&mouse_delta);
+        }
+
+        let mut speed = self.speed * delta_time;
+        if resources.input.is_key_pressed(VirtualKeyCode::LShift) {
+            speed *= self.boost;
+        }
 
         {
             let mut entry = resources.world.ecs.entry_mut(entity)?;
             let transform = entry.get_component_mut::<Transform>()?;
             transform.rotation = self.orientation.look_forward();
+
+            let bindings = [
+                (VirtualKeyCode::W, transform.forward()),
+                (VirtualKeyCode::S, -transform.forward()),
+                (VirtualKeyCode::D, transform.right()),
+                (VirtualKeyCode::A, -transform.right()),
+                (VirtualKeyCode::E, glm::Vec3::y()),
+                (VirtualKeyCode::Q, -glm::Vec3::y()),
+            ];
+            let direction = bindings
+                .iter()
+                .filter(|(keycode, _)| resources.input.is_key_pressed(*keycode))
+                .fold(glm::Vec3::zeros(), |direction, (_, axis)| direction + axis);
+            if direction.norm() > f32::EPSILON {
+                transform.translation += direction.normalize() * speed;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Trails a target entity, looking at it from an offset in the target's local space
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FollowCamera {
+    pub target: Option<Entity>,
+    pub offset: glm::Vec3,
+    // How quickly the camera catches up with the target. Zero snaps to the target immediately.
+    pub smoothing: f32,
+}
+
+impl Default for FollowCamera {
+    fn default() -> Self {
+        Self {
+            target: None,
+            offset: glm::vec3(0.0, 2.0, 5.0),
+            smoothing: 5.0,
         }
+    }
+}
 
-        resources.set_cursor_grab(true)?;
-        resources.set_cursor_visible(false);
-        resources.center_cursor()?;
+impl FollowCamera {
+    pub fn update(&mut self, resources: &mut Resources, entity: Entity) -> Result<()> {
+        // The camera holds its position once the target is removed
+        let target = match self
+            .target
+            .and_then(|target| resources.world.entity_global_transform(target).ok())
+        {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        let desired_position =
+            target.translation + glm::quat_rotate_vec3(&target.rotation.normalize(), &self.offset);
+
+        // Frame rate independent exponential smoothing.
+        // Gameplay cameras follow the world's clock, so they pause and replay along with it.
+        let blend = if self.smoothing > 0.0 {
+            1.0 - (-self.smoothing * resources.world.clock.delta()).exp()
+        } else {
+            1.0
+        };
+
+        let mut entry = resources.world.ecs.entry_mut(entity)?;
+        let transform = entry.get_component_mut::<Transform>()?;
+        transform.translation = glm::lerp(&transform.translation, &desired_position, blend);
+        let direction = target.translation - transform.translation;
+        if direction.norm() > f32::EPSILON {
+            transform.look_at(&direction, &glm::Vec3::y());
+        }
 
         Ok(())
     }
 }
 
+#[derive(Default)]
+pub struct MouseLook {
+    pub orientation: Orientation,
+}
+
+impl MouseLook {
+    pub fn update(&mut self, resources: &mut Resources, entity: Entity) -> Result<()> {
+        resources.set_cursor_mode(CursorMode::Locked)?;
+
+        // Rotations are driven by offsets toward the window center, which run opposite to the motion.
+        // Like the follow camera, this turns with the world's clock.
+        let mouse_delta = calibrated_mouse_delta(resources, -resources.input.mouse.position_delta)
+            * resources.world.clock.delta();
+
+        self.orientation.rotate(&mouse_delta);
+
+        let mut entry = resources.world.ecs.entry_mut(entity)?;
+        let transform = entry.get_component_mut::<Transform>()?;
+        transform.rotation = self.orientation.look_forward();
+
+        Ok(())
+    }
+}
+
+fn calibrated_mouse_delta(resources: &Resources, delta: glm::Vec2) -> glm::Vec2 {
+    let calibration = &resources.config.input;
+    glm::vec2(
+        calibration.apply(InputCalibration::MOUSE, "x", delta.x),
+        calibration.apply(InputCalibration::MOUSE, "y", delta.y),
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Orientation {
     pub min_radius: f32,
     pub max_radius: f32,
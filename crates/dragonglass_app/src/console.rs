@@ -0,0 +1,312 @@
+use crate::{apply_log_settings, clear_log_lines, with_log_lines, CVars, Recording, Resources};
+use anyhow::{bail, Context, Result};
+use dragonglass_config::LogLevel;
+use dragonglass_gui::egui::{self, Color32, ComboBox, Key, Rect, ScrollArea, TextEdit, TextStyle};
+use log::{info, warn, Level, LevelFilter};
+use std::{collections::BTreeMap, time::SystemTime};
+
+const LEVELS: [LevelFilter; 5] = [
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+type DebugCommand = Box<dyn FnMut(&[&str], &mut Resources) -> Result<String>>;
+
+struct RegisteredCommand {
+    help: String,
+    run: DebugCommand,
+}
+
+// A window of recent log lines that runs debug commands and adjusts console variables.
+// Commands registered with the console itself are looked up before those in the resources.
+// Commands and their output are logged, so they show up alongside everything else.
+pub struct Console {
+    pub open: bool,
+    filter: String,
+    level: LevelFilter,
+    input: String,
+    commands: BTreeMap<String, RegisteredCommand>,
+    // The newest line when the window last scrolled, so it only follows new lines
+    newest_line: Option<SystemTime>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        let mut console = Self {
+            open: false,
+            filter: String::new(),
+            level: LevelFilter::Trace,
+            input: String::new(),
+            commands: BTreeMap::new(),
+            newest_line: None,
+        };
+        console.register_builtin_commands();
+        console
+    }
+}
+
+impl Console {
+    pub const TARGET: &'static str = "console";
+
+    // Commands receive the words typed after their name, and return text to log
+    pub fn register(
+        &mut self,
+        name: &str,
+        help: &str,
+        command: impl FnMut(&[&str], &mut Resources) -> Result<String> + 'static,
+    ) {
+        self.commands.insert(
+            name.to_string(),
+            RegisteredCommand {
+                help: help.to_string(),
+                run: Box::new(command),
+            },
+        );
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    // Failed commands are logged as warnings rather than returned
+    pub fn execute(&mut self, line: &str, resources: &mut Resources) {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return,
+        };
+        let arguments = words.collect::<Vec<_>>();
+        info!(target: Self::TARGET, "> {}", line.trim());
+
+        if name == "help" {
+            info!(target: Self::TARGET, "help - Lists the available commands and variables");
+            let commands = self
+                .commands
+                .iter()
+                .map(|(name, command)| (name.as_str(), command.help.as_str()))
+                .chain(resources.cvars.commands());
+            for (name, help) in commands {
+                info!(target: Self::TARGET, "{} - {}", name, help);
+            }
+            for (name, var) in resources.cvars.vars() {
+                info!(target: Self::TARGET, "{} = {} - {}", name, var.value(), var.help);
+            }
+            return;
+        }
+
+        let result = match self.commands.get_mut(name) {
+            Some(command) => (command.run)(&arguments, resources),
+            None => CVars::execute(line, resources),
+        };
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => info!(target: Self::TARGET, "{}", output),
+            Err(error) => warn!(target: Self::TARGET, "{}: {}", name, error),
+        }
+    }
+
+    pub fn show(&mut self, resources: &mut Resources) {
+        if !self.open {
+            return;
+        }
+
+        let context = resources.gui.context();
+        let mut open = self.open;
+        let mut submitted = None;
+        egui::Window::new("Console")
+            .open(&mut open)
+            .default_size([700.0, 400.0])
+            .show(&context, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Filter");
+                    ui.text_edit_singleline(&mut self.filter);
+                    ComboBox::from_label("Level")
+                        .selected_text(self.level.to_string())
+                        .show_ui(ui, |ui| {
+                            for level in LEVELS.iter() {
+                                ui.selectable_value(&mut self.level, *level, level.to_string());
+                            }
+                        });
+                    if ui.button("Clear").clicked() {
+                        clear_log_lines();
+                    }
+                });
+                ui.separator();
+
+                let filter = self.filter.to_lowercase();
+                let level = self.level;
+                let newest_line = &mut self.newest_line;
+                let row_height = ui.fonts()[TextStyle::Body].row_height();
+                with_log_lines(|lines| {
+                    let visible = lines
+                        .iter()
+                        .filter(|line| {
+                            line.level <= level
+                                && (filter.is_empty()
+                                    || line.message.to_lowercase().contains(&filter)
+                                    || line.target.to_lowercase().contains(&filter))
+                        })
+                        .collect::<Vec<_>>();
+                    // Only the rows in view are laid out, since there can be thousands of lines
+                    ScrollArea::vertical()
+                        .max_height(ui.available_height() - 30.0)
+                        .show_rows(ui, row_height, visible.len(), |ui, rows| {
+                            for line in visible[rows.clone()].iter() {
+                                ui.colored_label(
+                                    level_color(line.level),
+                                    format!("[{}] {}: {}", line.level, line.target, line.message),
+                                );
+                            }
+                            let newest = lines.back().map(|line| line.timestamp);
+                            if newest != *newest_line {
+                                *newest_line = newest;
+                                // The rows past the ones in view aren't laid out, so the bottom is found from their height
+                                let row_spacing = row_height + ui.spacing().item_spacing.y;
+                                let bottom = ui.max_rect().top()
+                                    + (visible.len() - rows.start) as f32 * row_spacing;
+                                let rect =
+                                    Rect::from_x_y_ranges(ui.max_rect().x_range(), bottom..=bottom);
+                                ui.scroll_to_rect(rect, egui::Align::Max);
+                            }
+                        });
+                });
+                ui.separator();
+
+                let response = ui.add(
+                    TextEdit::singleline(&mut self.input)
+                        .hint_text("Enter a command, or 'help'")
+                        .desired_width(f32::INFINITY),
+                );
+                if response.lost_focus() && ui.input().key_pressed(Key::Enter) {
+                    submitted = Some(std::mem::take(&mut self.input));
+                    response.request_focus();
+                }
+            });
+        self.open = open;
+
+        if let Some(line) = submitted {
+            self.execute(&line, resources);
+        }
+    }
+
+    fn register_builtin_commands(&mut self) {
+        self.register("clear", "Clears the console", |_, _| {
+            clear_log_lines();
+            Ok(String::new())
+        });
+
+        self.register(
+            "log_level",
+            "Sets the level of every module, or of one module: log_level [module] <level>",
+            |arguments, resources| {
+                let (module, level) = match arguments {
+                    [level] => (None, *level),
+                    [module, level] => (Some(*module), *level),
+                    _ => bail!("Expected a level, optionally preceded by a module"),
+                };
+                let level = parse_log_level(level)
+                    .context("Levels are off, error, warn, info, debug, or trace")?;
+                let logging = &mut resources.config.logging;
+                match module {
+                    Some(module) => {
+                        logging.modules.insert(module.to_string(), level);
+                    }
+                    None => logging.level = level,
+                }
+                apply_log_settings(logging);
+                Ok(format!("Log level set to {:?}", level))
+            },
+        );
+
+        self.register(
+            "log_file_level",
+            "Sets the level of every module in the log file, or 'default' to follow the other levels: log_file_level <level>",
+            |arguments, resources| {
+                let level = match arguments {
+                    ["default"] => None,
+                    [level] => Some(
+                        parse_log_level(level)
+                            .context("Levels are off, error, warn, info, debug, trace, or default")?,
+                    ),
+                    _ => bail!("Expected a level"),
+                };
+                let logging = &mut resources.config.logging;
+                logging.file_level = level;
+                apply_log_settings(logging);
+                Ok(format!("Log file level set to {:?}", level))
+            },
+        );
+
+        self.register(
+            "replay_record",
+            "Records the session until saved with replay_save",
+            |_, resources| {
+                resources.replay.start_recording(resources.world)?;
+                Ok("Recording".to_string())
+            },
+        );
+
+        self.register(
+            "replay_save",
+            "Stops recording and saves the replay: replay_save <path>",
+            |arguments, resources| {
+                let path = arguments.first().context("Expected a path to save to")?;
+                let recording = resources
+                    .replay
+                    .stop_recording(resources.world)
+                    .context("Nothing is being recorded")?;
+                recording.save(path)?;
+                Ok(format!(
+                    "Saved {} frames to '{}'",
+                    recording.frames.len(),
+                    path
+                ))
+            },
+        );
+
+        self.register(
+            "replay_play",
+            "Plays back a saved replay: replay_play <path>",
+            |arguments, resources| {
+                let path = arguments.first().context("Expected a path to play")?;
+                let recording = Recording::load(path)?;
+                resources.replay.play(recording, resources.world)?;
+                Ok(format!("Playing '{}'", path))
+            },
+        );
+
+        self.register("replay_stop", "Stops playing a replay", |_, resources| {
+            resources.replay.stop(resources.world);
+            Ok(String::new())
+        });
+
+        self.register("quit", "Exits the app", |_, resources| {
+            resources.system.exit_requested = true;
+            Ok(String::new())
+        });
+    }
+}
+
+fn parse_log_level(level: &str) -> Option<LogLevel> {
+    match level.to_lowercase().as_str() {
+        "off" => Some(LogLevel::Off),
+        "error" => Some(LogLevel::Error),
+        "warn" => Some(LogLevel::Warn),
+        "info" => Some(LogLevel::Info),
+        "debug" => Some(LogLevel::Debug),
+        "trace" => Some(LogLevel::Trace),
+        _ => None,
+    }
+}
+
+fn level_color(level: Level) -> Color32 {
+    match level {
+        Level::Error => Color32::from_rgb(255, 90, 90),
+        Level::Warn => Color32::from_rgb(255, 200, 80),
+        Level::Info => Color32::LIGHT_GRAY,
+        Level::Debug | Level::Trace => Color32::GRAY,
+    }
+}
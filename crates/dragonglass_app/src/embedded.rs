@@ -0,0 +1,151 @@
+use crate::{
+    app::{run_loop, AppState},
+    settings::Settings,
+    App, AppConfig, CameraControllerSystem, Resources,
+};
+use anyhow::Result;
+use image::RgbaImage;
+use winit::{
+    event::{DeviceId, Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+};
+
+// Runs an app one frame at a time for hosts that own the main loop,
+// such as editors written in other languages embedding dragonglass through the ffi
+pub struct EmbeddedApp<T: App> {
+    app: T,
+    state: AppState,
+    camera_controllers: CameraControllerSystem,
+    settings: Settings,
+    // Input from the host, delivered at the start of the next frame
+    injected_events: Vec<WindowEvent<'static>>,
+    closed: bool,
+    event_loop: EventLoop<()>,
+}
+
+impl<T: App> EmbeddedApp<T> {
+    pub fn new(mut app: T, config: AppConfig) -> Result<Self> {
+        let event_loop = EventLoop::new();
+        let mut state = AppState::new(&event_loop, &config)?;
+        let camera_controllers = CameraControllerSystem::new(config.camera_controller)?;
+        app.initialize(&mut state.resources())?;
+        let settings = Settings::new(&mut state.resources());
+        Ok(Self {
+            app,
+            state,
+            camera_controllers,
+            settings,
+            injected_events: Vec::new(),
+            closed: false,
+            event_loop,
+        })
+    }
+
+    pub fn app(&mut self) -> &mut T {
+        &mut self.app
+    }
+
+    pub fn resources(&mut self) -> Resources {
+        self.state.resources()
+    }
+
+    // Once closed, stepping does nothing
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // Queues an event as if the window had received it
+    pub fn inject_event(&mut self, event: WindowEvent<'static>) {
+        self.injected_events.push(event);
+    }
+
+    // Injected events have no real device behind them
+    pub fn device_id() -> DeviceId {
+        unsafe { DeviceId::dummy() }
+    }
+
+    // Handles pending window events, updates, and renders a single frame.
+    // Returns false once the app has been closed.
+    pub fn step(&mut self) -> Result<bool> {
+        if self.closed {
+            return Ok(false);
+        }
+
+        let Self {
+            app,
+            state,
+            camera_controllers,
+            settings,
+            injected_events,
+            closed,
+            event_loop,
+        } = self;
+        let window_id = state.window.id();
+        let mut result = Ok(());
+        event_loop.run_return(|event, target, control_flow| {
+            if result.is_err() {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+
+            // Exiting the nested loop destroys it, which only ends the app when it asked to close
+            let frame_finished = matches!(event, Event::RedrawEventsCleared);
+            if matches!(event, Event::LoopDestroyed) && !*closed {
+                return;
+            }
+            let starting_frame = matches!(event, Event::NewEvents(_));
+
+            result = run_loop(
+                app,
+                state.resources(),
+                camera_controllers,
+                settings,
+                event,
+                target,
+                control_flow,
+            );
+            if starting_frame {
+                for event in injected_events.drain(..) {
+                    if result.is_err() {
+                        break;
+                    }
+                    let event = Event::WindowEvent { window_id, event };
+                    result = run_loop(
+                        app,
+                        state.resources(),
+                        camera_controllers,
+                        settings,
+                        event,
+                        target,
+                        control_flow,
+                    );
+                }
+            }
+
+            if *control_flow == ControlFlow::Exit {
+                *closed = true;
+            }
+            if frame_finished {
+                *control_flow = ControlFlow::Exit;
+            }
+        });
+        result?;
+        Ok(!self.closed)
+    }
+
+    pub fn capture_frame(&mut self) -> Result<RgbaImage> {
+        let state = &mut self.state;
+        state.renderer.capture_frame(&state.world)
+    }
+}
+
+impl<T: App> Drop for EmbeddedApp<T> {
+    fn drop(&mut self) {
+        if !self.closed {
+            if let Err(error) = self.app.cleanup() {
+                log::error!("{}", error);
+            }
+        }
+    }
+}
@@ -0,0 +1,753 @@
+use anyhow::{Context, Result};
+use dragonglass_world::{
+    load_model_asset, unlink_mesh, Clipboard, Entity, EntityStore, Heightmap, Material, MeshRender,
+    ModelAsset, PhysicsMaterial, RemovedEntities, RigidBody, Shape, Spline, Terrain, Transform,
+    VertexColors, World,
+};
+use std::{collections::VecDeque, sync::Arc};
+
+// A reversible world mutation
+pub trait Command {
+    fn name(&self) -> &str;
+
+    fn apply(&mut self, world: &mut World) -> Result<()>;
+
+    fn revert(&mut self, world: &mut World) -> Result<()>;
+
+    // Commands that add or remove geometry require the renderer to reload the world
+    fn changes_geometry(&self) -> bool {
+        false
+    }
+
+    // Entities replaced during the last apply or revert, as (old, new) pairs.
+    // Other commands in the history are remapped so they keep referring to the same objects.
+    fn replaced_entities(&mut self) -> Vec<(Entity, Entity)> {
+        Vec::new()
+    }
+
+    fn remap_entity(&mut self, _old: Entity, _new: Entity) {}
+
+    // A model was imported again and the instance that had the previous entities now has these
+    fn model_reloaded(
+        &mut self,
+        _asset: &Arc<ModelAsset>,
+        _previous: &[Entity],
+        _entities: &[Entity],
+    ) {
+    }
+}
+
+pub struct History {
+    pub limit: usize,
+    undo_stack: VecDeque<Box<dyn Command>>,
+    redo_stack: Vec<Box<dyn Command>>,
+    // Entities replaced by the last undo or redo
+    replaced: Vec<(Entity, Entity)>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            limit: 100,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            replaced: Vec::new(),
+        }
+    }
+}
+
+impl History {
+    pub fn execute(&mut self, mut command: Box<dyn Command>, world: &mut World) -> Result<()> {
+        command.apply(world)?;
+        self.record(command);
+        Ok(())
+    }
+
+    // Adds a command whose changes have already been made to the world
+    pub fn record(&mut self, command: Box<dyn Command>) {
+        self.redo_stack.clear();
+        self.undo_stack.push_back(command);
+        while self.undo_stack.len() > self.limit {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    // Returns the command that was undone, if any
+    pub fn undo(&mut self, world: &mut World) -> Result<Option<&dyn Command>> {
+        let mut command = match self.undo_stack.pop_back() {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        if let Err(error) = command.revert(world) {
+            self.undo_stack.push_back(command);
+            return Err(error);
+        }
+        let replaced = command.replaced_entities();
+        self.remap_entities(replaced);
+        self.redo_stack.push(command);
+        Ok(self.redo_stack.last().map(|command| command.as_ref()))
+    }
+
+    // Returns the command that was redone, if any
+    pub fn redo(&mut self, world: &mut World) -> Result<Option<&dyn Command>> {
+        let mut command = match self.redo_stack.pop() {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        if let Err(error) = command.apply(world) {
+            self.redo_stack.push(command);
+            return Err(error);
+        }
+        let replaced = command.replaced_entities();
+        self.remap_entities(replaced);
+        self.undo_stack.push_back(command);
+        Ok(self.undo_stack.back().map(|command| command.as_ref()))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo_name(&self) -> Option<&str> {
+        self.undo_stack.back().map(|command| command.name())
+    }
+
+    pub fn redo_name(&self) -> Option<&str> {
+        self.redo_stack.last().map(|command| command.name())
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    // Entities the last undo or redo replaced, as (old, new) pairs,
+    // for anything outside the history that refers to them
+    pub fn replaced_entities(&self) -> &[(Entity, Entity)] {
+        &self.replaced
+    }
+
+    // Keeps commands that instantiated a model in step with its reloaded instances
+    pub fn model_reloaded(
+        &mut self,
+        asset: &Arc<ModelAsset>,
+        instances: &[(Vec<Entity>, Vec<Entity>)],
+    ) {
+        for (previous, entities) in instances.iter() {
+            self.undo_stack
+                .iter_mut()
+                .chain(self.redo_stack.iter_mut())
+                .for_each(|command| command.model_reloaded(asset, previous, entities));
+        }
+    }
+
+    fn remap_entities(&mut self, replaced: Vec<(Entity, Entity)>) {
+        for (old, new) in replaced.iter() {
+            self.undo_stack
+                .iter_mut()
+                .chain(self.redo_stack.iter_mut())
+                .for_each(|command| command.remap_entity(*old, *new));
+        }
+        self.replaced = replaced;
+    }
+}
+
+pub struct TransformCommand {
+    pub entity: Entity,
+    pub before: Transform,
+    pub after: Transform,
+}
+
+impl TransformCommand {
+    pub fn new(entity: Entity, before: Transform, after: Transform) -> Self {
+        Self {
+            entity,
+            before,
+            after,
+        }
+    }
+
+    fn set_transform(&self, world: &mut World, transform: Transform) -> Result<()> {
+        let has_rigid_body = {
+            let mut entry = world
+                .ecs
+                .entry(self.entity)
+                .context("Failed to find entity!")?;
+            *entry.get_component_mut::<Transform>()? = transform;
+            entry.get_component::<RigidBody>().is_ok()
+        };
+        if has_rigid_body {
+            world.sync_rigid_body_to_transform(self.entity)?;
+        }
+        Ok(())
+    }
+}
+
+impl Command for TransformCommand {
+    fn name(&self) -> &str {
+        "Transform"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        self.set_transform(world, self.after)
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        self.set_transform(world, self.before)
+    }
+
+    fn remap_entity(&mut self, old: Entity, new: Entity) {
+        if self.entity == old {
+            self.entity = new;
+        }
+    }
+}
+
+pub struct SplineCommand {
+    pub entity: Entity,
+    pub before: Spline,
+    pub after: Spline,
+}
+
+impl SplineCommand {
+    pub fn new(entity: Entity, before: Spline, after: Spline) -> Self {
+        Self {
+            entity,
+            before,
+            after,
+        }
+    }
+
+    fn set_spline(&self, world: &mut World, spline: &Spline) -> Result<()> {
+        let mut entry = world
+            .ecs
+            .entry(self.entity)
+            .context("Failed to find entity!")?;
+        *entry.get_component_mut::<Spline>()? = spline.clone();
+        Ok(())
+    }
+}
+
+impl Command for SplineCommand {
+    fn name(&self) -> &str {
+        "Edit Spline"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        self.set_spline(world, &self.after)
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        self.set_spline(world, &self.before)
+    }
+
+    fn remap_entity(&mut self, old: Entity, new: Entity) {
+        if self.entity == old {
+            self.entity = new;
+        }
+    }
+}
+
+// Edits a material shared by every primitive that uses it
+pub struct MaterialCommand {
+    pub index: usize,
+    pub before: Material,
+    pub after: Material,
+}
+
+impl MaterialCommand {
+    pub fn new(index: usize, before: Material, after: Material) -> Self {
+        Self {
+            index,
+            before,
+            after,
+        }
+    }
+}
+
+impl Command for MaterialCommand {
+    fn name(&self) -> &str {
+        "Edit Material"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        world.update_material(self.index, self.after.clone())
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        world.update_material(self.index, self.before.clone())
+    }
+}
+
+pub struct PhysicsMaterialCommand {
+    pub entity: Entity,
+    pub before: PhysicsMaterial,
+    pub after: PhysicsMaterial,
+}
+
+impl PhysicsMaterialCommand {
+    pub fn new(entity: Entity, before: PhysicsMaterial, after: PhysicsMaterial) -> Self {
+        Self {
+            entity,
+            before,
+            after,
+        }
+    }
+}
+
+impl Command for PhysicsMaterialCommand {
+    fn name(&self) -> &str {
+        "Edit Physics Material"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        world.set_physics_material(self.entity, self.after)
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        world.set_physics_material(self.entity, self.before)
+    }
+
+    fn remap_entity(&mut self, old: Entity, new: Entity) {
+        if self.entity == old {
+            self.entity = new;
+        }
+    }
+}
+
+pub struct DeleteEntityCommand {
+    entity: Entity,
+    // Everything that was deleted, which undoing puts back exactly as it was
+    removed: Option<RemovedEntities>,
+}
+
+impl DeleteEntityCommand {
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            removed: None,
+        }
+    }
+}
+
+impl Command for DeleteEntityCommand {
+    fn name(&self) -> &str {
+        "Delete Entity"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        self.removed = Some(world.take_entities(&[self.entity])?);
+        Ok(())
+    }
+
+    // The entities come back with the same handles, so nothing else in the history needs remapping
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        match self.removed.take() {
+            Some(removed) => world.restore_entities(removed),
+            None => Ok(()),
+        }
+    }
+
+    fn remap_entity(&mut self, old: Entity, new: Entity) {
+        if self.entity == old {
+            self.entity = new;
+        }
+    }
+}
+
+pub struct PasteCommand {
+    clipboard: Clipboard,
+    linked: bool,
+    entities: Vec<Entity>,
+    replaced: Vec<(Entity, Entity)>,
+}
+
+impl PasteCommand {
+    pub fn new(clipboard: Clipboard) -> Self {
+        Self {
+            clipboard,
+            linked: false,
+            entities: Vec::new(),
+            replaced: Vec::new(),
+        }
+    }
+
+    // Pastes instances that share their meshes and materials with the copied entities
+    pub fn linked(clipboard: Clipboard) -> Self {
+        Self {
+            linked: true,
+            ..Self::new(clipboard)
+        }
+    }
+
+    // The pasted entities, starting with the root of the copied hierarchy
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+impl Command for PasteCommand {
+    fn name(&self) -> &str {
+        if self.linked {
+            "Paste Linked"
+        } else {
+            "Paste"
+        }
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        let restored = if self.linked {
+            self.clipboard.restore_linked(world, None)?
+        } else {
+            self.clipboard.restore(world, None)?
+        };
+        let entities = restored
+            .into_iter()
+            .map(|(_, entity)| entity)
+            .collect::<Vec<_>>();
+        self.replaced = self
+            .entities
+            .iter()
+            .copied()
+            .zip(entities.iter().copied())
+            .collect();
+        self.entities = entities;
+        Ok(())
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        remove_entities(world, &self.entities)
+    }
+
+    fn changes_geometry(&self) -> bool {
+        true
+    }
+
+    fn replaced_entities(&mut self) -> Vec<(Entity, Entity)> {
+        std::mem::take(&mut self.replaced)
+    }
+}
+
+pub struct UnlinkMeshCommand {
+    entity: Entity,
+    previous_mesh: Option<String>,
+}
+
+impl UnlinkMeshCommand {
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            previous_mesh: None,
+        }
+    }
+}
+
+impl Command for UnlinkMeshCommand {
+    fn name(&self) -> &str {
+        "Unlink Instance"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        let previous_mesh = world
+            .ecs
+            .entry_ref(self.entity)?
+            .get_component::<MeshRender>()?
+            .name
+            .to_string();
+        if unlink_mesh(world, self.entity)? {
+            self.previous_mesh = Some(previous_mesh);
+        }
+        Ok(())
+    }
+
+    // The unlinked copy is left for compaction to reclaim
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        if let Some(previous_mesh) = self.previous_mesh.take() {
+            world
+                .ecs
+                .entry(self.entity)
+                .context("Failed to find entity!")?
+                .get_component_mut::<MeshRender>()?
+                .name = previous_mesh;
+        }
+        Ok(())
+    }
+
+    fn changes_geometry(&self) -> bool {
+        true
+    }
+
+    fn remap_entity(&mut self, old: Entity, new: Entity) {
+        if self.entity == old {
+            self.entity = new;
+        }
+    }
+}
+
+// A whole brush stroke, recorded once the stroke ends
+pub struct VertexPaintCommand {
+    before: VertexColors,
+    after: VertexColors,
+}
+
+impl VertexPaintCommand {
+    pub fn new(before: VertexColors, after: VertexColors) -> Self {
+        Self { before, after }
+    }
+}
+
+impl Command for VertexPaintCommand {
+    fn name(&self) -> &str {
+        "Vertex Paint"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        self.after.apply(world)
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        self.before.apply(world)
+    }
+
+    fn changes_geometry(&self) -> bool {
+        true
+    }
+}
+
+pub struct InstantiateModelCommand {
+    asset: Arc<ModelAsset>,
+    entities: Vec<Entity>,
+    replaced: Vec<(Entity, Entity)>,
+}
+
+impl InstantiateModelCommand {
+    pub fn new(asset: Arc<ModelAsset>) -> Self {
+        Self {
+            asset,
+            entities: Vec::new(),
+            replaced: Vec::new(),
+        }
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+impl Command for InstantiateModelCommand {
+    fn name(&self) -> &str {
+        "Import Model"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        let entities = load_model_asset(&self.asset, world)?;
+        // Reimporting creates the nodes in the same order, so earlier references can be remapped
+        self.replaced = self
+            .entities
+            .iter()
+            .copied()
+            .zip(entities.iter().copied())
+            .collect();
+        self.entities = entities;
+        Ok(())
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        remove_entities(world, &self.entities)
+    }
+
+    fn changes_geometry(&self) -> bool {
+        true
+    }
+
+    fn replaced_entities(&mut self) -> Vec<(Entity, Entity)> {
+        std::mem::take(&mut self.replaced)
+    }
+
+    fn model_reloaded(
+        &mut self,
+        asset: &Arc<ModelAsset>,
+        previous: &[Entity],
+        entities: &[Entity],
+    ) {
+        if self.entities.as_slice() == previous {
+            self.asset = asset.clone();
+            self.entities = entities.to_vec();
+        }
+    }
+}
+
+pub struct AddShapeCommand {
+    shape: Shape,
+    transform: Transform,
+    entity: Option<Entity>,
+    // The mesh and material made for the shape, which are removed along with it
+    assets: (Vec<String>, Vec<usize>),
+    replaced: Vec<(Entity, Entity)>,
+}
+
+impl AddShapeCommand {
+    pub fn new(shape: Shape, transform: Transform) -> Self {
+        Self {
+            shape,
+            transform,
+            entity: None,
+            assets: (Vec::new(), Vec::new()),
+            replaced: Vec::new(),
+        }
+    }
+
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+}
+
+impl Command for AddShapeCommand {
+    fn name(&self) -> &str {
+        "Add Shape"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        let entity = world.add_shape(self.shape, self.transform)?;
+        self.assets = entity_assets(world, &[entity]);
+        if let Some(previous) = self.entity.replace(entity) {
+            self.replaced = vec![(previous, entity)];
+        }
+        Ok(())
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        if let Some(entity) = self.entity {
+            remove_entities(world, &[entity])?;
+            remove_assets(world, &self.assets);
+        }
+        Ok(())
+    }
+
+    fn replaced_entities(&mut self) -> Vec<(Entity, Entity)> {
+        std::mem::take(&mut self.replaced)
+    }
+}
+
+pub struct AddTerrainCommand {
+    name: String,
+    heightmap: Heightmap,
+    terrain: Terrain,
+    transform: Transform,
+    entities: Vec<Entity>,
+    // The chunk meshes and material made for the terrain, which are removed along with it
+    assets: (Vec<String>, Vec<usize>),
+    replaced: Vec<(Entity, Entity)>,
+}
+
+impl AddTerrainCommand {
+    pub fn new(
+        name: impl Into<String>,
+        heightmap: Heightmap,
+        terrain: Terrain,
+        transform: Transform,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            heightmap,
+            terrain,
+            transform,
+            entities: Vec::new(),
+            assets: (Vec::new(), Vec::new()),
+            replaced: Vec::new(),
+        }
+    }
+
+    // The terrain's root entity comes first, followed by its chunks
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+impl Command for AddTerrainCommand {
+    fn name(&self) -> &str {
+        "Add Terrain"
+    }
+
+    fn apply(&mut self, world: &mut World) -> Result<()> {
+        let entities =
+            world.add_terrain(&self.name, &self.heightmap, self.terrain, self.transform)?;
+        // The chunks are created in the same order each time, so earlier references can be remapped
+        self.replaced = self
+            .entities
+            .iter()
+            .copied()
+            .zip(entities.iter().copied())
+            .collect();
+        self.assets = entity_assets(world, &entities);
+        self.entities = entities;
+        Ok(())
+    }
+
+    fn revert(&mut self, world: &mut World) -> Result<()> {
+        remove_entities(world, &self.entities)?;
+        remove_assets(world, &self.assets);
+        Ok(())
+    }
+
+    // Renderers pick up the chunk meshes as they are added and removed, and the terrain brings its own
+    // heightfield collider, so reloading the world would only give the chunks trimesh colliders as well
+    fn changes_geometry(&self) -> bool {
+        false
+    }
+
+    fn replaced_entities(&mut self) -> Vec<(Entity, Entity)> {
+        std::mem::take(&mut self.replaced)
+    }
+}
+
+// The meshes the entities draw along with the materials of those meshes, in the order they were added
+fn entity_assets(world: &World, entities: &[Entity]) -> (Vec<String>, Vec<usize>) {
+    let mut meshes = Vec::new();
+    let mut materials = Vec::new();
+    for entity in entities.iter() {
+        let name = match world.ecs.entry_ref(*entity) {
+            Ok(entry) => match entry.get_component::<MeshRender>() {
+                Ok(mesh_render) => mesh_render.name.to_string(),
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+        if let Some(mesh) = world.assets.geometry.meshes.get(&name) {
+            for primitive in mesh.primitives.iter() {
+                if let Some(index) = primitive.material_index {
+                    if !materials.contains(&index) {
+                        materials.push(index);
+                    }
+                }
+            }
+        }
+        meshes.push(name);
+    }
+    (meshes, materials)
+}
+
+// Removes what entity_assets found once its entities are gone, newest first so that
+// geometry and materials at the end of the world's assets are reclaimed right away
+fn remove_assets(world: &mut World, (meshes, materials): &(Vec<String>, Vec<usize>)) {
+    for mesh in meshes.iter().rev() {
+        world.remove_mesh(mesh);
+    }
+    for material in materials.iter().rev() {
+        world.remove_material(*material);
+    }
+}
+
+// Removing an entity also removes its descendants, so some may already be gone
+fn remove_entities(world: &mut World, entities: &[Entity]) -> Result<()> {
+    for entity in entities.iter() {
+        if world.ecs.entry_ref(*entity).is_ok() {
+            world.remove_entity(*entity)?;
+        }
+    }
+    Ok(())
+}
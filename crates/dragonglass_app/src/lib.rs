@@ -1,7 +1,18 @@
 mod app;
+mod behavior;
 mod camera;
+mod console;
+mod embedded;
+mod history;
 mod logger;
 mod resources;
+mod settings;
 mod state;
+mod streaming;
+mod vehicle;
+mod worlds;
 
-pub use self::{app::*, camera::*, logger::*, resources::*, state::*};
+pub use self::{
+    app::*, behavior::*, camera::*, console::*, embedded::*, history::*, logger::*, resources::*,
+    state::*, streaming::*, vehicle::*, worlds::*,
+};
@@ -1,25 +1,323 @@
-use anyhow::{Context, Result};
-use simplelog::{
-    ColorChoice, CombinedLogger, Config, LevelFilter, TermLogger, TerminalMode, WriteLogger,
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+use anyhow::Result;
+use dragonglass_config::{Config, LogLevel, Logging};
+use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+#[cfg(not(target_arch = "wasm32"))]
+use simplelog::{ColorChoice, Config as LogConfig, TermLogger, TerminalMode, WriteLogger};
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, RwLock},
+    time::SystemTime,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
-use std::fs::File;
 
 pub const LOG_FILE: &str = "dragonglass.log";
 
+// How many of the most recent log lines are kept for the console
+pub const LOG_HISTORY: usize = 1000;
+
+lazy_static! {
+    static ref FILTERS: RwLock<LogFilters> = RwLock::new(LogFilters::default());
+    static ref LOG_LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+}
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub timestamp: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+// Logs to the terminal, a rolling log file, and the console's history,
+// filtered by the logging settings in the config file
+#[cfg(not(target_arch = "wasm32"))]
 pub fn create_logger() -> Result<()> {
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Info,
-            Config::default(),
+    let settings = Config::load_or_default(Config::FILE).logging;
+    let file = RollingFile::create(LOG_FILE, settings.max_file_size, settings.max_files)
+        .context(format!("Failed to create log file named: {}", LOG_FILE))?;
+    let logger = EngineLogger {
+        terminal: TermLogger::new(
+            LevelFilter::Trace,
+            LogConfig::default(),
             TerminalMode::Mixed,
             ColorChoice::Auto,
         ),
-        WriteLogger::new(
-            LevelFilter::max(),
-            Config::default(),
-            File::create(LOG_FILE)
-                .context(format!("Failed to create log file named: {}", LOG_FILE))?,
-        ),
-    ])?;
+        file: WriteLogger::new(LevelFilter::Trace, LogConfig::default(), file),
+    };
+    log::set_boxed_logger(Box::new(logger))?;
+    apply_log_settings(&settings);
     Ok(())
 }
+
+// Browsers have neither a terminal nor files to log to, so messages go to the browser's console instead
+#[cfg(target_arch = "wasm32")]
+pub fn create_logger() -> Result<()> {
+    let settings = Config::load_or_default(Config::FILE).logging;
+    log::set_boxed_logger(Box::new(EngineLogger))?;
+    apply_log_settings(&settings);
+    Ok(())
+}
+
+// Changes which messages are logged from now on
+pub fn apply_log_settings(settings: &Logging) {
+    let filters = LogFilters::new(settings);
+    log::set_max_level(filters.max_level());
+    if let Ok(mut current) = FILTERS.write() {
+        *current = filters;
+    }
+}
+
+// Runs the closure with the most recent log lines, oldest first
+pub fn with_log_lines<T>(action: impl FnOnce(&VecDeque<LogLine>) -> T) -> T {
+    match LOG_LINES.lock() {
+        Ok(lines) => action(&lines),
+        Err(_) => action(&VecDeque::new()),
+    }
+}
+
+pub fn clear_log_lines() {
+    if let Ok(mut lines) = LOG_LINES.lock() {
+        lines.clear();
+    }
+}
+
+pub fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::Off,
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+        LogLevel::Trace => LevelFilter::Trace,
+    }
+}
+
+struct LogFilters {
+    level: LevelFilter,
+    // Sorted from the longest module path to the shortest, so the most specific match is found first
+    modules: Vec<(String, LevelFilter)>,
+    file: Option<LevelFilter>,
+}
+
+impl Default for LogFilters {
+    fn default() -> Self {
+        Self::new(&Logging::default())
+    }
+}
+
+impl LogFilters {
+    fn new(settings: &Logging) -> Self {
+        let mut modules = settings
+            .modules
+            .iter()
+            .map(|(module, level)| (module.to_string(), level_filter(*level)))
+            .collect::<Vec<_>>();
+        modules.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+        Self {
+            level: level_filter(settings.level),
+            modules,
+            file: settings.file_level.map(level_filter),
+        }
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target == module.as_str()
+                    || (target.starts_with(module.as_str())
+                        && target[module.len()..].starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    fn file_level(&self, target: &str) -> LevelFilter {
+        self.file.unwrap_or_else(|| self.level(target))
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .chain(self.file)
+            .fold(self.level, LevelFilter::max)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct EngineLogger {
+    terminal: Box<TermLogger>,
+    file: Box<WriteLogger<RollingFile>>,
+}
+
+#[cfg(target_arch = "wasm32")]
+struct EngineLogger;
+
+impl Log for EngineLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        FILTERS
+            .read()
+            .map(|filters| {
+                let target = metadata.target();
+                metadata.level() <= filters.level(target).max(filters.file_level(target))
+            })
+            .unwrap_or(true)
+    }
+
+    // The log file can be more or less detailed than the terminal and the console
+    fn log(&self, record: &Record) {
+        let (logged, filed) = FILTERS
+            .read()
+            .map(|filters| {
+                let target = record.target();
+                (
+                    record.level() <= filters.level(target),
+                    record.level() <= filters.file_level(target),
+                )
+            })
+            .unwrap_or((true, true));
+        #[cfg(not(target_arch = "wasm32"))]
+        if filed {
+            self.file.log(record);
+        }
+        // There is no log file in the browser
+        #[cfg(target_arch = "wasm32")]
+        let _ = filed;
+        if !logged {
+            return;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.terminal.log(record);
+        #[cfg(target_arch = "wasm32")]
+        log_to_console(record);
+        if let Ok(mut lines) = LOG_LINES.lock() {
+            if lines.len() == LOG_HISTORY {
+                lines.pop_front();
+            }
+            lines.push_back(LogLine {
+                timestamp: now(),
+                level: record.level(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn flush(&self) {
+        self.terminal.flush();
+        self.file.flush();
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn flush(&self) {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> SystemTime {
+    SystemTime::now()
+}
+
+// The system clock can't be read from the browser, only through javascript
+#[cfg(target_arch = "wasm32")]
+fn now() -> SystemTime {
+    SystemTime::UNIX_EPOCH + std::time::Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn log_to_console(record: &Record) {
+    let message = format!("[{}] {}", record.target(), record.args()).into();
+    match record.level() {
+        Level::Error => web_sys::console::error_1(&message),
+        Level::Warn => web_sys::console::warn_1(&message),
+        Level::Info => web_sys::console::info_1(&message),
+        Level::Debug | Level::Trace => web_sys::console::debug_1(&message),
+    }
+}
+
+// A log file that is moved to a numbered backup once it grows too large, dropping the oldest backup.
+// The previous run's log is moved aside on startup as well, so each run starts a fresh file.
+#[cfg(not(target_arch = "wasm32"))]
+struct RollingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RollingFile {
+    fn create(path: impl AsRef<Path>, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::rotate(&path, max_files)?;
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            size: 0,
+            max_size,
+            max_files,
+        })
+    }
+
+    // dragonglass.log is backed up as dragonglass.1.log, which becomes dragonglass.2.log, and so on
+    fn backup_path(path: &Path, index: usize) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = match path.extension() {
+            Some(extension) => format!("{}.{}.{}", stem, index, extension.to_string_lossy()),
+            None => format!("{}.{}", stem, index),
+        };
+        path.with_file_name(name)
+    }
+
+    fn rotate(path: &Path, max_files: usize) -> io::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        if max_files == 0 {
+            return fs::remove_file(path);
+        }
+        let oldest = Self::backup_path(path, max_files);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for index in (1..max_files).rev() {
+            let backup = Self::backup_path(path, index);
+            if backup.exists() {
+                fs::rename(&backup, Self::backup_path(path, index + 1))?;
+            }
+        }
+        fs::rename(path, Self::backup_path(path, 1))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Write for RollingFile {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buffer.len() as u64 > self.max_size {
+            self.file.flush()?;
+            Self::rotate(&self.path, self.max_files)?;
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+        }
+        let written = self.file.write(buffer)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
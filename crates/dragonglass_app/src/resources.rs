@@ -1,18 +1,33 @@
+mod actions;
+mod assets;
+mod capture;
+mod cvars;
+mod events;
 mod input;
+mod replay;
 mod system;
+mod views;
+mod window;
 
-pub use self::{input::*, system::*};
+pub use self::{
+    actions::*, assets::*, capture::*, cvars::*, events::*, input::*, replay::*, system::*,
+    views::*, window::*,
+};
 
-use anyhow::Result;
+use crate::Worlds;
+use anyhow::{Context, Result};
 use dragonglass_config::Config;
-use dragonglass_gui::Gui;
+use dragonglass_gui::{
+    egui::{pos2, vec2, Color32, LayerId, Rect, Stroke},
+    paint_world_line, Gui,
+};
 use dragonglass_render::Renderer;
-use dragonglass_world::{load_gltf, MouseRayConfiguration, World};
-use nalgebra_glm as glm;
-use winit::{
-    dpi::PhysicalPosition,
-    window::{Fullscreen, Window},
+use dragonglass_world::{
+    load_model_asset, reload_model_asset, EntityStore, LodGeneration, ModelAsset,
+    MouseRayConfiguration, TextureCompression, World,
 };
+use nalgebra_glm as glm;
+use winit::{dpi::PhysicalPosition, window::Window};
 
 // TODO: Don't include renderer (or world) in this
 pub struct Resources<'a> {
@@ -23,9 +38,40 @@ pub struct Resources<'a> {
     pub gui: &'a mut Gui,
     pub renderer: &'a mut Box<dyn Renderer>,
     pub world: &'a mut World,
+    pub worlds: &'a mut Worlds,
+    pub assets: &'a mut AssetManager,
+    pub events: &'a mut Events,
+    pub cvars: &'a mut CVars,
+    pub replay: &'a mut Replay,
+    pub capture: &'a mut FrameCapture,
+    pub views: &'a mut ViewWindows,
 }
 
 impl<'a> Resources<'a> {
+    // How long skyboxes loaded through the resources take to fade in
+    pub const SKYBOX_FADE_SECONDS: f32 = 1.0;
+
+    pub fn set_cursor_mode(&mut self, mode: CursorMode) -> Result<()> {
+        if self.input.mouse.cursor_mode == mode {
+            return Ok(());
+        }
+        self.input.mouse.cursor_mode = mode;
+        self.apply_cursor_mode(true)
+    }
+
+    // The cursor is released while the window is unfocused so it can be used in other windows,
+    // and the chosen mode is restored when focus returns
+    pub(crate) fn apply_cursor_mode(&mut self, focused: bool) -> Result<()> {
+        let mode = if focused {
+            self.input.mouse.cursor_mode
+        } else {
+            CursorMode::Free
+        };
+        self.set_cursor_grab(mode == CursorMode::Locked)?;
+        self.set_cursor_visible(mode == CursorMode::Free);
+        Ok(())
+    }
+
     pub fn set_cursor_grab(&mut self, grab: bool) -> Result<()> {
         Ok(self.window.set_cursor_grab(grab)?)
     }
@@ -44,9 +90,15 @@ impl<'a> Resources<'a> {
             .set_cursor_position(PhysicalPosition::new(position.x, position.y))?)
     }
 
-    pub fn set_fullscreen(&mut self) {
+    // Places the platform's input method candidate window, usually next to the focused text field
+    pub fn set_ime_position(&mut self, position: &glm::Vec2) {
         self.window
-            .set_fullscreen(Some(Fullscreen::Borderless(self.window.primary_monitor())));
+            .set_ime_position(PhysicalPosition::new(position.x, position.y));
+    }
+
+    // The renderer picks up the newly active world at the end of the frame
+    pub fn activate_world(&mut self, name: &str) -> Result<bool> {
+        self.worlds.activate(name, self.world)
     }
 
     pub fn mouse_ray_configuration(&self) -> Result<MouseRayConfiguration> {
@@ -64,9 +116,203 @@ impl<'a> Resources<'a> {
         Ok(mouse_ray_configuration)
     }
 
+    // Bodies are culled against the renderer's viewport, so off-screen bodies can be simplified
+    pub fn update_physics_lod(&mut self) -> Result<()> {
+        let aspect_ratio = self.renderer.viewport().aspect_ratio();
+        let view_projection = self
+            .world
+            .active_camera_matrices(aspect_ratio)
+            .ok()
+            .map(|(projection, view)| projection * view);
+        self.world.update_physics_lod(view_projection.as_ref())
+    }
+
+    // The rect the scene is rendered to, in gui points
+    pub fn viewport_rect(&self) -> Rect {
+        let viewport = self.renderer.viewport();
+        let scale = self.window.scale_factor() as f32;
+        Rect::from_min_size(
+            pos2(viewport.x / scale, viewport.y / scale),
+            vec2(viewport.width / scale, viewport.height / scale),
+        )
+    }
+
+    // Draws the lines queued in the world's debug draw over the viewport, then clears them for the next frame
+    pub fn paint_debug_draw(&mut self) {
+        let viewport = self.renderer.viewport();
+        if let Ok((projection, view)) = self.world.active_camera_matrices(viewport.aspect_ratio()) {
+            let rect = self.viewport_rect();
+            let view_projection = projection * view;
+            let painter = self.gui.context().layer_painter(LayerId::background());
+            for line in self.world.debug_draw.lines() {
+                let color = line.color * 255.0;
+                let color = Color32::from_rgba_unmultiplied(
+                    color.x as u8,
+                    color.y as u8,
+                    color.z as u8,
+                    color.w as u8,
+                );
+                paint_world_line(
+                    &painter,
+                    rect,
+                    &view_projection,
+                    &line.start,
+                    &line.end,
+                    Stroke::new(1.5, color),
+                );
+            }
+        }
+        self.world.debug_draw.clear();
+    }
+
+    // Switches meshes with levels of detail to the level the active camera calls for
+    pub fn update_mesh_lods(&mut self) -> Result<()> {
+        let aspect_ratio = self.renderer.viewport().aspect_ratio();
+        self.world.update_lods(aspect_ratio)
+    }
+
+    // Culls meshes the active camera can't see, so the renderer skips them this frame
+    pub fn update_occlusion(&mut self) -> Result<()> {
+        let aspect_ratio = self.renderer.viewport().aspect_ratio();
+        self.world.update_occlusion(aspect_ratio)
+    }
+
     pub fn load_asset(&mut self, path: &str) -> Result<()> {
-        load_gltf(path, self.world)?;
+        self.apply_import_settings();
+        let handle = self.assets.load::<ModelAsset>(path);
+        self.assets.wait(&handle)?;
+        self.instantiate_model(&handle)
+    }
+
+    // Starts loading the model file in the background without blocking the main thread
+    pub fn load_asset_async(&mut self, path: &str) -> Handle<ModelAsset> {
+        self.apply_import_settings();
+        self.assets.load::<ModelAsset>(path)
+    }
+
+    pub fn instantiate_model(&mut self, handle: &Handle<ModelAsset>) -> Result<()> {
+        let asset = self
+            .assets
+            .get(handle)
+            .context("Attempted to instantiate a model that has not finished loading")?;
+        self.apply_import_settings();
+        let entities = load_model_asset(&asset, self.world)?;
+        self.assets.add_instance(handle, entities);
         self.renderer.load_world(self.world)?;
         Ok(())
     }
+
+    // Patches the instances of models and the skyboxes whose files were imported again after changing on disk.
+    // Instances keep their entities where node names still match, so selections and added components survive.
+    pub fn apply_reloaded_assets(&mut self) -> Result<()> {
+        let mut world_changed = false;
+        for reloaded in self.assets.take_reloaded() {
+            if let Some(handle) = self.assets.handle::<ModelAsset>(reloaded.id) {
+                let asset = self
+                    .assets
+                    .get(&handle)
+                    .context("Reloaded model is missing its asset")?;
+                self.apply_import_settings();
+                let mut instances = Vec::new();
+                for entities in self.assets.instances(&handle).to_vec() {
+                    // Instances made in other worlds are left alone
+                    let in_world = entities
+                        .iter()
+                        .any(|entity| self.world.ecs.entry_ref(*entity).is_ok());
+                    if in_world {
+                        let patched = reload_model_asset(&asset, self.world, &entities)?;
+                        instances.push((entities, patched));
+                        world_changed = true;
+                    } else {
+                        instances.push((entities.clone(), entities));
+                    }
+                }
+                self.assets.set_instances(
+                    &handle,
+                    instances
+                        .iter()
+                        .map(|(_, entities)| entities.clone())
+                        .collect(),
+                );
+                self.events.send(ModelReloaded {
+                    id: reloaded.id,
+                    instances,
+                });
+            } else if let Some(handle) = self.assets.handle::<Hdr>(reloaded.id) {
+                let name = reloaded
+                    .path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let is_skybox = self
+                    .world
+                    .scene
+                    .skybox
+                    .and_then(|index| self.world.assets.hdr_names.get(index))
+                    == Some(&name);
+                if is_skybox {
+                    self.instantiate_skybox(&handle)?;
+                }
+            }
+        }
+        if world_changed {
+            self.renderer.load_world(self.world)?;
+        }
+        Ok(())
+    }
+
+    fn apply_import_settings(&mut self) {
+        let texture_compression = if self.config.graphics.compress_textures {
+            TextureCompression::Bc7
+        } else {
+            TextureCompression::None
+        };
+        self.world.assets.texture_compression = texture_compression;
+        let settings = &mut self.assets.import_settings;
+        settings.texture_compression = texture_compression;
+        settings.processed_cache = if self.config.graphics.cache_imports {
+            Some(settings.processed_cache.take().unwrap_or_default())
+        } else {
+            None
+        };
+        self.world.assets.lod_generation = if self.config.graphics.generate_lods {
+            Some(LodGeneration::default())
+        } else {
+            None
+        };
+    }
+
+    pub fn load_skybox(&mut self, path: &str) -> Result<()> {
+        let handle = self.assets.load::<Hdr>(path);
+        self.assets.wait(&handle)?;
+        self.instantiate_skybox(&handle)
+    }
+
+    // Starts decoding the hdr in the background without blocking the main thread
+    pub fn load_skybox_async(&mut self, path: &str) -> Handle<Hdr> {
+        self.assets.load::<Hdr>(path)
+    }
+
+    // Makes the hdr the skybox of the active world.
+    // Its environment maps are only generated the first time the hdr is used, after which they are read from disk.
+    pub fn instantiate_skybox(&mut self, handle: &Handle<Hdr>) -> Result<()> {
+        let hdr = self
+            .assets
+            .get(handle)
+            .context("Attempted to instantiate a skybox that has not finished loading")?;
+        let name = self
+            .assets
+            .path(handle)
+            .and_then(|path| path.file_stem())
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let index = self
+            .world
+            .assets
+            .add_hdr_with_key(name, hdr.texture.clone(), hdr.key);
+        self.world
+            .scene
+            .fade_skybox(index, Self::SKYBOX_FADE_SECONDS);
+        Ok(())
+    }
 }
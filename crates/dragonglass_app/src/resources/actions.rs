@@ -0,0 +1,116 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputSource {
+    Key(VirtualKeyCode),
+    MouseButton(MouseButton),
+    ScrollUp,
+    ScrollDown,
+}
+
+// An axis reads 1.0 while any positive source is held, -1.0 while any negative source is held,
+// and 0.0 when both or neither are. Scroll sources report the scroll amount instead.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    #[serde(default)]
+    pub positive: Vec<InputSource>,
+    #[serde(default)]
+    pub negative: Vec<InputSource>,
+}
+
+// Named actions and axes mapped to the inputs that trigger them,
+// so gameplay code never needs to know which keys the player chose
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionMap {
+    #[serde(default)]
+    pub actions: HashMap<String, Vec<InputSource>>,
+    #[serde(default)]
+    pub axes: HashMap<String, AxisBinding>,
+}
+
+impl ActionMap {
+    pub fn bind_action(&mut self, action: &str, source: InputSource) {
+        let sources = self.actions.entry(action.to_string()).or_default();
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, positive: InputSource, negative: InputSource) {
+        let binding = self.axes.entry(axis.to_string()).or_default();
+        if !binding.positive.contains(&positive) {
+            binding.positive.push(positive);
+        }
+        if !binding.negative.contains(&negative) {
+            binding.negative.push(negative);
+        }
+    }
+
+    // Replaces every source bound to the action
+    pub fn rebind_action(&mut self, action: &str, sources: Vec<InputSource>) {
+        self.actions.insert(action.to_string(), sources);
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.insert(axis.to_string(), binding);
+    }
+
+    pub fn action_sources(&self, action: &str) -> &[InputSource] {
+        self.actions
+            .get(action)
+            .map(|sources| sources.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn axis_binding(&self, axis: &str) -> Option<&AxisBinding> {
+        self.axes.get(axis)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Bindings in the file replace the bindings of the same name,
+    // so defaults registered in code only need to be overridden where the player changed them.
+    // A missing file leaves the bindings untouched.
+    pub fn load_overrides(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(());
+        }
+        let overrides: ActionMap = toml::from_str(&fs::read_to_string(path)?)?;
+        self.apply_overrides(overrides);
+        Ok(())
+    }
+
+    pub fn apply_overrides(&mut self, overrides: ActionMap) {
+        self.actions.extend(overrides.actions.into_iter());
+        self.axes.extend(overrides.axes.into_iter());
+    }
+
+    // The bindings that differ from the defaults, which applied as overrides restore these bindings.
+    // Bindings removed since the defaults were registered are not included.
+    pub fn overrides_from(&self, defaults: &ActionMap) -> ActionMap {
+        let actions = self
+            .actions
+            .iter()
+            .filter(|(action, sources)| defaults.actions.get(*action) != Some(sources))
+            .map(|(action, sources)| (action.clone(), sources.clone()))
+            .collect();
+        let axes = self
+            .axes
+            .iter()
+            .filter(|(axis, binding)| defaults.axes.get(*axis) != Some(binding))
+            .map(|(axis, binding)| (axis.clone(), binding.clone()))
+            .collect();
+        ActionMap { actions, axes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty() && self.axes.is_empty()
+    }
+}
@@ -0,0 +1,539 @@
+use crate::AssetLoaded;
+use anyhow::{bail, Result};
+use dragonglass_world::{
+    Entity, GltfAsset, ModelAsset, ProcessedModelCache, Texture, TextureCompression,
+};
+use log::{info, warn};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt, fs,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+// How source files are turned into assets, as of when each load is requested
+#[derive(Debug, Clone)]
+pub struct ImportSettings {
+    // Where models are cached after being processed into the runtime format.
+    // Unset imports the source file every time.
+    pub processed_cache: Option<ProcessedModelCache>,
+    pub texture_compression: TextureCompression,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        Self {
+            processed_cache: Some(ProcessedModelCache::default()),
+            texture_compression: TextureCompression::default(),
+        }
+    }
+}
+
+pub trait Asset: Send + Sync + Sized + 'static {
+    fn load(path: &Path, settings: &ImportSettings) -> Result<Self>;
+}
+
+impl Asset for GltfAsset {
+    fn load(path: &Path, _: &ImportSettings) -> Result<Self> {
+        GltfAsset::import(path)
+    }
+}
+
+// The processed model is preferred, with the source only imported when it has changed
+impl Asset for ModelAsset {
+    fn load(path: &Path, settings: &ImportSettings) -> Result<Self> {
+        match settings.processed_cache.as_ref() {
+            Some(cache) => Ok(ModelAsset::Processed(
+                cache.load_or_process(path, settings.texture_compression)?,
+            )),
+            None => ModelAsset::import(path),
+        }
+    }
+}
+
+impl Asset for Texture {
+    fn load(path: &Path, _: &ImportSettings) -> Result<Self> {
+        Texture::from_file(path)
+    }
+}
+
+// Hashed while loading in the background, so the renderer can find its cached environment maps without hashing it
+pub struct Hdr {
+    pub texture: Texture,
+    pub key: u64,
+}
+
+impl Asset for Hdr {
+    fn load(path: &Path, _: &ImportSettings) -> Result<Self> {
+        let texture = Texture::from_environment(path)?;
+        let key = texture.content_hash();
+        Ok(Self { texture, key })
+    }
+}
+
+pub struct Handle<T> {
+    id: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.id)
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+type LoadResult = (u64, Result<Arc<dyn Any + Send + Sync>, String>);
+type Loader = Arc<dyn Fn(&Path) -> Result<Arc<dyn Any + Send + Sync>, String> + Send + Sync>;
+
+// The files that are imported again when hot reloading sees them change.
+// Only the file itself is watched, so a .gltf reloads when it is exported rather than when its .bin changes alone.
+pub const HOT_RELOAD_EXTENSIONS: &[&str] = &["gltf", "glb", "hdr"];
+
+struct AssetSlot {
+    path: PathBuf,
+    state: LoadState,
+    asset: Option<Arc<dyn Any + Send + Sync>>,
+    loader: Loader,
+    // When the file was last changed as of its latest load
+    modified: Option<SystemTime>,
+    // Reloading keeps the current asset until the new one is ready
+    reloading: bool,
+}
+
+pub struct AssetManager {
+    slots: HashMap<u64, AssetSlot>,
+    lookup: HashMap<(TypeId, PathBuf), u64>,
+    next_id: u64,
+    job_sender: Option<Sender<Job>>,
+    result_sender: Sender<LoadResult>,
+    result_receiver: Receiver<LoadResult>,
+    workers: Vec<JoinHandle<()>>,
+    // Loads finished since the app last sent them as events
+    finished: Vec<AssetLoaded>,
+    // How many of the finished loads were already there at the last update.
+    // They are dropped at the next one if no one took them, so they can't pile up.
+    unclaimed: usize,
+    // Captured by each load as it is requested, and used again when it is hot reloaded
+    pub import_settings: ImportSettings,
+    hot_reload: bool,
+    last_poll: Instant,
+    // Assets that were imported again after their files changed, for their instances to be patched
+    reloaded: Vec<AssetLoaded>,
+    // The entities each model was instantiated as, for reloads to patch in place
+    instances: HashMap<u64, Vec<Vec<Entity>>>,
+}
+
+impl Default for AssetManager {
+    // Browsers can't spawn threads, so assets are loaded as they are requested there
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Self {
+        Self::new(0)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default() -> Self {
+        let number_of_workers = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(4)
+            .clamp(1, 8);
+        Self::new(number_of_workers)
+    }
+}
+
+impl AssetManager {
+    pub fn new(number_of_workers: usize) -> Self {
+        let (job_sender, job_receiver) = channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (result_sender, result_receiver) = channel();
+
+        let workers = (0..number_of_workers)
+            .map(|index| {
+                let job_receiver = job_receiver.clone();
+                thread::Builder::new()
+                    .name(format!("asset_loader_{}", index))
+                    .spawn(move || loop {
+                        let job = match job_receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => break,
+                        };
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn asset loader thread!")
+            })
+            .collect();
+
+        Self {
+            slots: HashMap::new(),
+            lookup: HashMap::new(),
+            next_id: 0,
+            job_sender: Some(job_sender),
+            result_sender,
+            result_receiver,
+            workers,
+            finished: Vec::new(),
+            unclaimed: 0,
+            import_settings: ImportSettings::default(),
+            hot_reload: false,
+            last_poll: Instant::now(),
+            reloaded: Vec::new(),
+            instances: HashMap::new(),
+        }
+    }
+
+    // How often files are checked for changes while hot reloading
+    const HOT_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+    // Requests for a path that is already loaded or loading return the existing handle.
+    // Loads that failed are evicted and tried again, since the file may have been fixed since.
+    pub fn load<T: Asset>(&mut self, path: impl AsRef<Path>) -> Handle<T> {
+        let path = Self::normalize_path(path.as_ref());
+        let key = (TypeId::of::<T>(), path.clone());
+        if let Some(id) = self.lookup.get(&key).copied() {
+            let failed = self
+                .slots
+                .get(&id)
+                .map_or(true, |slot| matches!(slot.state, LoadState::Failed(_)));
+            if !failed {
+                return Handle::new(id);
+            }
+            self.slots.remove(&id);
+            self.lookup.remove(&key);
+            self.instances.remove(&id);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.lookup.insert(key, id);
+        let settings = self.import_settings.clone();
+        let loader: Loader = Arc::new(move |path| {
+            T::load(path, &settings)
+                .map(|asset| Arc::new(asset) as Arc<dyn Any + Send + Sync>)
+                .map_err(|error| error.to_string())
+        });
+        self.slots.insert(
+            id,
+            AssetSlot {
+                modified: modified_time(&path),
+                path,
+                state: LoadState::Loading,
+                asset: None,
+                loader,
+                reloading: false,
+            },
+        );
+
+        if !self.queue(id) {
+            if let Some(slot) = self.slots.get_mut(&id) {
+                slot.state = LoadState::Failed("Asset loader has shut down".to_string());
+            }
+        }
+
+        Handle::new(id)
+    }
+
+    // Applies the results of any finished background loads, call once per frame
+    pub fn update(&mut self) {
+        let unclaimed = self.unclaimed.min(self.finished.len());
+        self.finished.drain(..unclaimed);
+        while let Ok((id, result)) = self.result_receiver.try_recv() {
+            self.finish(id, result);
+        }
+        if self.hot_reload && self.last_poll.elapsed() >= Self::HOT_RELOAD_INTERVAL {
+            self.last_poll = Instant::now();
+            self.reload_changed_files();
+        }
+        self.unclaimed = self.finished.len();
+    }
+
+    // Loads that finished since this was last called, which are kept until the update after the one they finished in
+    pub fn take_finished(&mut self) -> Vec<AssetLoaded> {
+        self.unclaimed = 0;
+        std::mem::take(&mut self.finished)
+    }
+
+    // Watches the files of loaded models and hdrs, importing them again when they change on disk
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        self.hot_reload = enabled;
+    }
+
+    pub fn hot_reload(&self) -> bool {
+        self.hot_reload
+    }
+
+    pub fn take_reloaded(&mut self) -> Vec<AssetLoaded> {
+        std::mem::take(&mut self.reloaded)
+    }
+
+    // The handle for an asset id, if the asset is of the requested type
+    pub fn handle<T: Asset>(&self, id: u64) -> Option<Handle<T>> {
+        self.slots
+            .get(&id)
+            .and_then(|slot| slot.asset.as_ref())
+            .filter(|asset| asset.is::<T>())
+            .map(|_| Handle::new(id))
+    }
+
+    // Remembers the entities a model was instantiated as, so reloading the model patches them
+    pub fn add_instance(&mut self, handle: &Handle<ModelAsset>, entities: Vec<Entity>) {
+        self.instances.entry(handle.id).or_default().push(entities);
+    }
+
+    pub fn instances(&self, handle: &Handle<ModelAsset>) -> &[Vec<Entity>] {
+        self.instances
+            .get(&handle.id)
+            .map(|instances| instances.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn set_instances(&mut self, handle: &Handle<ModelAsset>, instances: Vec<Vec<Entity>>) {
+        self.instances.insert(handle.id, instances);
+    }
+
+    // Follows instances whose entities were replaced, such as when undoing and redoing an import
+    pub fn remap_instances(&mut self, replaced: &[(Entity, Entity)]) {
+        if replaced.is_empty() {
+            return;
+        }
+        let replaced = replaced.iter().copied().collect::<HashMap<_, _>>();
+        for entity in self.instances.values_mut().flatten().flatten() {
+            if let Some(new) = replaced.get(entity) {
+                *entity = *new;
+            }
+        }
+    }
+
+    // Blocks until the asset has finished loading
+    pub fn wait<T: Asset>(&mut self, handle: &Handle<T>) -> Result<Arc<T>> {
+        loop {
+            match self.state(handle) {
+                Some(LoadState::Loaded) => break,
+                Some(LoadState::Failed(error)) => bail!("Failed to load asset: {}", error),
+                Some(LoadState::Loading) => match self.result_receiver.recv() {
+                    Ok((id, result)) => self.finish(id, result),
+                    Err(_) => bail!("Asset loader has shut down"),
+                },
+                None => bail!("No asset exists for {:?}", handle),
+            }
+        }
+        match self.get(handle) {
+            Some(asset) => Ok(asset),
+            None => bail!("Asset {:?} is not of the requested type", handle),
+        }
+    }
+
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<Arc<T>> {
+        self.slots
+            .get(&handle.id)
+            .and_then(|slot| slot.asset.clone())
+            .and_then(|asset| asset.downcast::<T>().ok())
+    }
+
+    pub fn state<T>(&self, handle: &Handle<T>) -> Option<LoadState> {
+        self.slots.get(&handle.id).map(|slot| slot.state.clone())
+    }
+
+    pub fn is_loaded<T>(&self, handle: &Handle<T>) -> bool {
+        self.state(handle) == Some(LoadState::Loaded)
+    }
+
+    pub fn path<T>(&self, handle: &Handle<T>) -> Option<&Path> {
+        self.slots.get(&handle.id).map(|slot| slot.path.as_path())
+    }
+
+    // Returns the number of finished loads and the total number of requested loads
+    pub fn progress(&self) -> (usize, usize) {
+        let finished = self
+            .slots
+            .values()
+            .filter(|slot| slot.state != LoadState::Loading)
+            .count();
+        (finished, self.slots.len())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        let (finished, total) = self.progress();
+        finished == total
+    }
+
+    pub fn loading_paths(&self) -> impl Iterator<Item = &Path> {
+        self.slots
+            .values()
+            .filter(|slot| slot.state == LoadState::Loading)
+            .map(|slot| slot.path.as_path())
+    }
+
+    // Drops the cached asset so the next load for its path reads the file again
+    pub fn unload<T: Asset>(&mut self, handle: &Handle<T>) {
+        if let Some(slot) = self.slots.remove(&handle.id) {
+            self.lookup.remove(&(TypeId::of::<T>(), slot.path));
+        }
+        self.instances.remove(&handle.id);
+    }
+
+    fn queue(&self, id: u64) -> bool {
+        let (path, loader) = match self.slots.get(&id) {
+            Some(slot) => (slot.path.clone(), slot.loader.clone()),
+            None => return false,
+        };
+        let result_sender = self.result_sender.clone();
+        let job: Job = Box::new(move || {
+            let _ = result_sender.send((id, loader(&path)));
+        });
+        // Without any workers the load finishes before this returns,
+        // and is picked up at the next update like any other
+        if self.workers.is_empty() {
+            job();
+            return true;
+        }
+        match self.job_sender.as_ref() {
+            Some(sender) => sender.send(job).is_ok(),
+            None => false,
+        }
+    }
+
+    fn reload_changed_files(&mut self) {
+        let mut changed = Vec::new();
+        for (id, slot) in self.slots.iter_mut() {
+            let watched = slot
+                .path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map_or(false, |extension| {
+                    HOT_RELOAD_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                });
+            if !watched || slot.state != LoadState::Loaded || slot.reloading {
+                continue;
+            }
+            let modified = modified_time(&slot.path);
+            if modified.is_some() && modified != slot.modified {
+                slot.modified = modified;
+                slot.reloading = true;
+                changed.push(*id);
+            }
+        }
+        for id in changed {
+            if let Some(slot) = self.slots.get(&id) {
+                info!("Reloading changed asset: {}", slot.path.display());
+            }
+            if !self.queue(id) {
+                if let Some(slot) = self.slots.get_mut(&id) {
+                    slot.reloading = false;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, id: u64, result: Result<Arc<dyn Any + Send + Sync>, String>) {
+        let slot = match self.slots.get_mut(&id) {
+            Some(slot) => slot,
+            None => return,
+        };
+        if slot.reloading {
+            slot.reloading = false;
+            // A failed reload keeps the asset that was loaded before, such as while a file is half written
+            match result {
+                Ok(asset) => {
+                    info!("Reloaded asset: {}", slot.path.display());
+                    slot.asset = Some(asset);
+                    self.reloaded.push(AssetLoaded {
+                        id,
+                        path: slot.path.clone(),
+                        state: slot.state.clone(),
+                    });
+                }
+                Err(error) => warn!("Failed to reload asset {}: {}", slot.path.display(), error),
+            }
+            return;
+        }
+        match result {
+            Ok(asset) => {
+                info!("Loaded asset: {}", slot.path.display());
+                slot.asset = Some(asset);
+                slot.state = LoadState::Loaded;
+            }
+            Err(error) => {
+                warn!("Failed to load asset {}: {}", slot.path.display(), error);
+                slot.state = LoadState::Failed(error);
+            }
+        }
+        self.finished.push(AssetLoaded {
+            id,
+            path: slot.path.clone(),
+            state: slot.state.clone(),
+        });
+    }
+
+    fn normalize_path(path: &Path) -> PathBuf {
+        path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+fn modified_time(path: impl AsRef<Path>) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Drop for AssetManager {
+    fn drop(&mut self) {
+        // Closing the job channel lets the workers exit their loops
+        self.job_sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{bail, Result};
+#[cfg(feature = "renderdoc")]
+use log::{debug, info};
+#[cfg(feature = "renderdoc")]
+use renderdoc::{RenderDoc, V110};
+
+// Triggers RenderDoc frame captures from inside the app when built with the renderdoc feature.
+// RenderDoc has to be loaded before the renderer is created, so the app connects to it at startup,
+// either when launched from RenderDoc or by loading its library if it is installed.
+pub struct FrameCapture {
+    #[cfg(feature = "renderdoc")]
+    renderdoc: Option<RenderDoc<V110>>,
+    // Captures already reported to the log
+    #[cfg(feature = "renderdoc")]
+    reported: u32,
+}
+
+impl Default for FrameCapture {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "renderdoc")]
+            renderdoc: match RenderDoc::new() {
+                Ok(renderdoc) => {
+                    info!("Connected to RenderDoc");
+                    Some(renderdoc)
+                }
+                Err(error) => {
+                    debug!("RenderDoc is not available: {}", error);
+                    None
+                }
+            },
+            #[cfg(feature = "renderdoc")]
+            reported: 0,
+        }
+    }
+}
+
+impl FrameCapture {
+    pub fn available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.renderdoc.is_some();
+        #[cfg(not(feature = "renderdoc"))]
+        return false;
+    }
+
+    // Captures the next frame the app presents
+    pub fn trigger(&mut self) -> Result<()> {
+        #[cfg(feature = "renderdoc")]
+        if let Some(renderdoc) = self.renderdoc.as_mut() {
+            renderdoc.trigger_capture();
+            return Ok(());
+        }
+        bail!("Frame capture needs the renderdoc feature and RenderDoc to be installed")
+    }
+
+    // Opens RenderDoc's replay ui connected to the app, to browse the captures taken so far
+    pub fn open_replay_ui(&self) -> Result<()> {
+        #[cfg(feature = "renderdoc")]
+        if let Some(renderdoc) = self.renderdoc.as_ref() {
+            if let Err(error) = renderdoc.launch_replay_ui(true, None) {
+                bail!("Failed to open RenderDoc: {}", error);
+            }
+            return Ok(());
+        }
+        bail!("Frame capture needs the renderdoc feature and RenderDoc to be installed")
+    }
+
+    // Logs where finished captures were written
+    pub(crate) fn update(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        if let Some(renderdoc) = self.renderdoc.as_ref() {
+            let captures = renderdoc.get_num_captures();
+            while self.reported < captures {
+                if let Some((path, _)) = renderdoc.get_capture(self.reported) {
+                    info!("Saved frame capture to '{}'", path.display());
+                }
+                self.reported += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use crate::Resources;
+use anyhow::{bail, Context, Result};
+use dragonglass_world::{Camera, Projection};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CVarValue {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+impl CVarValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Bool(_) => "bool",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+        }
+    }
+
+    // Parses text as a value of the same type, accepting 0 and 1 for booleans
+    pub fn parse_as(&self, text: &str) -> Result<Self> {
+        let value = match self {
+            Self::Bool(_) => match text.to_lowercase().as_str() {
+                "true" | "1" | "on" => Self::Bool(true),
+                "false" | "0" | "off" => Self::Bool(false),
+                _ => bail!("Expected true or false"),
+            },
+            Self::Int(_) => Self::Int(text.parse().context("Expected an integer")?),
+            Self::Float(_) => Self::Float(text.parse().context("Expected a number")?),
+            Self::String(_) => Self::String(text.to_string()),
+        };
+        Ok(value)
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        match self {
+            Self::Bool(value) => toml::Value::Boolean(*value),
+            Self::Int(value) => toml::Value::Integer(*value as i64),
+            Self::Float(value) => toml::Value::Float(*value as f64),
+            Self::String(value) => toml::Value::String(value.to_string()),
+        }
+    }
+
+    // None when the saved value is not of the same type
+    fn read_toml(&self, value: &toml::Value) -> Option<Self> {
+        match (self, value) {
+            (Self::Bool(_), toml::Value::Boolean(value)) => Some(Self::Bool(*value)),
+            (Self::Int(_), toml::Value::Integer(value)) => Some(Self::Int(*value as i32)),
+            (Self::Float(_), toml::Value::Float(value)) => Some(Self::Float(*value as f32)),
+            (Self::Float(_), toml::Value::Integer(value)) => Some(Self::Float(*value as f32)),
+            (Self::String(_), toml::Value::String(value)) => Some(Self::String(value.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CVarValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Int(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "\"{}\"", value),
+        }
+    }
+}
+
+// The rust types console variables can hold
+pub trait CVarType: Sized {
+    fn into_value(self) -> CVarValue;
+    fn from_value(value: &CVarValue) -> Option<Self>;
+}
+
+impl CVarType for bool {
+    fn into_value(self) -> CVarValue {
+        CVarValue::Bool(self)
+    }
+
+    fn from_value(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl CVarType for i32 {
+    fn into_value(self) -> CVarValue {
+        CVarValue::Int(self)
+    }
+
+    fn from_value(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl CVarType for f32 {
+    fn into_value(self) -> CVarValue {
+        CVarValue::Float(self)
+    }
+
+    fn from_value(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl CVarType for String {
+    fn into_value(self) -> CVarValue {
+        CVarValue::String(self)
+    }
+
+    fn from_value(value: &CVarValue) -> Option<Self> {
+        match value {
+            CVarValue::String(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+}
+
+pub struct CVar {
+    pub help: String,
+    value: CVarValue,
+    default: CVarValue,
+    persistent: bool,
+    changed: bool,
+}
+
+impl CVar {
+    pub fn value(&self) -> &CVarValue {
+        &self.value
+    }
+
+    pub fn default_value(&self) -> &CVarValue {
+        &self.default
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    // Persistent variables that differ from their default are saved with the settings
+    pub fn persistent(&mut self) -> &mut Self {
+        self.persistent = true;
+        self
+    }
+}
+
+pub type CommandCallback = Box<dyn FnMut(&[&str], &mut Resources) -> Result<String>>;
+
+struct CVarCommand {
+    help: String,
+    // Taken while the command runs, because it is given the resources that own it
+    callback: Option<CommandCallback>,
+}
+
+// Named, typed variables and debug commands that engine and game code register,
+// so they can be tweaked at runtime from the console and the editor.
+// Variables are named by their system, such as "r.wireframe", "phys.gravity_y", or "cam.fov".
+#[derive(Default)]
+pub struct CVars {
+    vars: BTreeMap<String, CVar>,
+    commands: BTreeMap<String, CVarCommand>,
+    // Values loaded from the settings for variables that have not been registered yet
+    saved: BTreeMap<String, toml::Value>,
+}
+
+impl CVars {
+    pub const PREFERENCE: &'static str = "cvars";
+
+    // Registering a variable again keeps its current value if the type is unchanged
+    pub fn register<T: CVarType>(&mut self, name: &str, help: &str, default: T) -> &mut CVar {
+        let default = default.into_value();
+        let mut value = match self.vars.get(name) {
+            Some(var) if var.value.type_name() == default.type_name() => var.value.clone(),
+            _ => default.clone(),
+        };
+        let mut changed = false;
+        if let Some(saved) = self
+            .saved
+            .remove(name)
+            .and_then(|saved| default.read_toml(&saved))
+        {
+            value = saved;
+            changed = true;
+        }
+        let var = CVar {
+            help: help.to_string(),
+            value,
+            default,
+            persistent: false,
+            changed,
+        };
+        self.vars.insert(name.to_string(), var);
+        self.vars
+            .get_mut(name)
+            .expect("The variable was just registered")
+    }
+
+    pub fn register_command(
+        &mut self,
+        name: &str,
+        help: &str,
+        callback: impl FnMut(&[&str], &mut Resources) -> Result<String> + 'static,
+    ) {
+        self.commands.insert(
+            name.to_string(),
+            CVarCommand {
+                help: help.to_string(),
+                callback: Some(Box::new(callback)),
+            },
+        );
+    }
+
+    pub fn var(&self, name: &str) -> Option<&CVar> {
+        self.vars.get(name)
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &CVar)> {
+        self.vars.iter().map(|(name, var)| (name.as_str(), var))
+    }
+
+    // Names and help of the registered commands
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.commands
+            .iter()
+            .map(|(name, command)| (name.as_str(), command.help.as_str()))
+    }
+
+    // None when the variable is missing or holds another type
+    pub fn get<T: CVarType>(&self, name: &str) -> Option<T> {
+        self.vars
+            .get(name)
+            .and_then(|var| T::from_value(&var.value))
+    }
+
+    pub fn set<T: CVarType>(&mut self, name: &str, value: T) -> Result<()> {
+        self.set_value(name, value.into_value())
+    }
+
+    pub fn set_value(&mut self, name: &str, value: CVarValue) -> Result<()> {
+        let var = self
+            .vars
+            .get_mut(name)
+            .context(format!("No variable is named '{}'", name))?;
+        if var.value.type_name() != value.type_name() {
+            bail!(
+                "'{}' is a {}, not a {}",
+                name,
+                var.value.type_name(),
+                value.type_name()
+            );
+        }
+        if var.value != value {
+            var.value = value;
+            var.changed = true;
+        }
+        Ok(())
+    }
+
+    pub fn set_from_str(&mut self, name: &str, text: &str) -> Result<()> {
+        let value = self
+            .vars
+            .get(name)
+            .context(format!("No variable is named '{}'", name))?
+            .value
+            .parse_as(text)?;
+        self.set_value(name, value)
+    }
+
+    pub fn reset(&mut self, name: &str) -> Result<()> {
+        let default = self
+            .vars
+            .get(name)
+            .context(format!("No variable is named '{}'", name))?
+            .default
+            .clone();
+        self.set_value(name, default)
+    }
+
+    // True once after the variable is set, for systems that only apply it when it changes
+    pub fn take_changed(&mut self, name: &str) -> bool {
+        match self.vars.get_mut(name) {
+            Some(var) => std::mem::take(&mut var.changed),
+            None => false,
+        }
+    }
+
+    // Keeps a variable mirroring state owned elsewhere.
+    // Returns the variable's value when it was set since the last sync,
+    // otherwise the variable is quietly updated to the current state.
+    pub fn sync<T: CVarType>(&mut self, name: &str, current: T) -> Option<T> {
+        if self.take_changed(name) {
+            return self.get(name);
+        }
+        if let Some(var) = self.vars.get_mut(name) {
+            let current = current.into_value();
+            if var.value.type_name() == current.type_name() {
+                var.value = current;
+            }
+        }
+        None
+    }
+
+    // Runs a command, or shows a variable when given only its name and sets it when given a value
+    pub fn execute(line: &str, resources: &mut Resources) -> Result<String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(String::new()),
+        };
+        let arguments = words.collect::<Vec<_>>();
+
+        if resources.cvars.vars.contains_key(name) {
+            if !arguments.is_empty() {
+                resources.cvars.set_from_str(name, &arguments.join(" "))?;
+            }
+            let var = &resources.cvars.vars[name];
+            return Ok(format!("{} = {} ({})", name, var.value, var.help));
+        }
+
+        let mut callback = match resources.cvars.commands.get_mut(name) {
+            Some(command) => command
+                .callback
+                .take()
+                .context(format!("'{}' is already running", name))?,
+            None => bail!("Unknown command or variable '{}', try 'help'", name),
+        };
+        let result = callback(&arguments, resources);
+        if let Some(command) = resources.cvars.commands.get_mut(name) {
+            command.callback.get_or_insert(callback);
+        }
+        result
+    }
+
+    // Saved values are applied to the variables already registered, and kept for the rest
+    pub(crate) fn load_saved(&mut self, saved: BTreeMap<String, toml::Value>) {
+        for (name, value) in saved.into_iter() {
+            match self.vars.get(name.as_str()) {
+                Some(var) => {
+                    if let Some(value) = var.default.read_toml(&value) {
+                        let _ = self.set_value(&name, value);
+                    }
+                }
+                None => {
+                    self.saved.insert(name, value);
+                }
+            }
+        }
+    }
+
+    // Persistent variables that differ from their default,
+    // along with saved values of variables this run never registered
+    pub(crate) fn saved_values(&self) -> BTreeMap<String, toml::Value> {
+        let mut values = self.saved.clone();
+        for (name, var) in self.vars.iter() {
+            if var.persistent && var.value != var.default {
+                values.insert(name.to_string(), var.value.to_toml());
+            }
+        }
+        values
+    }
+}
+
+// Variables for the engine's own systems, registered before the app initializes
+pub(crate) fn register_engine_cvars(cvars: &mut CVars) {
+    cvars.register(
+        "r.wireframe",
+        "Draws the world's meshes as wireframes",
+        false,
+    );
+    cvars.register(
+        "r.vsync",
+        "Caps presentation to the display's refresh rate",
+        true,
+    );
+    cvars.register(
+        "r.occlusion_culling",
+        "Skips drawing meshes outside the view or behind occluders",
+        true,
+    );
+    cvars.register(
+        "phys.gravity_y",
+        "The vertical gravity of the active world",
+        -9.812_f32,
+    );
+    cvars.register(
+        "time.scale",
+        "Speeds up or slows down the simulation",
+        1.0_f32,
+    );
+    cvars.register("time.paused", "Freezes the simulation", false);
+    cvars.register(
+        "time.fixed_step",
+        "Ticks the simulation by this many seconds every frame, or by the frame time when zero",
+        0.0_f32,
+    );
+    cvars.register_command(
+        "step",
+        "Advances the paused simulation by some frames: step [frames]",
+        |arguments, resources| {
+            let frames = match arguments.first() {
+                Some(frames) => frames.parse().context("Expected a number of frames")?,
+                None => 1,
+            };
+            if !resources.world.clock.paused {
+                bail!("The simulation must be paused to step it");
+            }
+            resources.world.clock.step_frames(frames);
+            Ok(String::new())
+        },
+    );
+    cvars.register_command(
+        "r.capture",
+        "Captures the next frame with RenderDoc, or opens RenderDoc's replay ui: r.capture [open]",
+        |arguments, resources| match arguments.first() {
+            Some(&"open") => {
+                resources.capture.open_replay_ui()?;
+                Ok(String::new())
+            }
+            Some(argument) => bail!("Unknown argument '{}'", argument),
+            None => {
+                resources.capture.trigger()?;
+                Ok("Capturing the next frame".to_string())
+            }
+        },
+    );
+    cvars.register(
+        "debug.skeletons",
+        "Draws the joints of every skin over the viewport",
+        false,
+    );
+    cvars.register(
+        "cam.fov",
+        "The vertical field of view of the active camera in degrees",
+        70.0_f32,
+    );
+    cvars.register(
+        "cam.exposure",
+        "Scales the light the active camera sees before it is tonemapped",
+        1.0_f32,
+    );
+}
+
+// Applies engine variables that were set, and otherwise updates them to what the engine is using
+pub(crate) fn sync_engine_cvars(resources: &mut Resources) -> Result<()> {
+    let cvars = &mut *resources.cvars;
+    let graphics = &mut resources.config.graphics;
+    if let Some(wireframe) = cvars.sync("r.wireframe", graphics.wireframe) {
+        graphics.wireframe = wireframe;
+    }
+    if let Some(vsync) = cvars.sync("r.vsync", graphics.vsync) {
+        graphics.vsync = vsync;
+    }
+
+    let occlusion = &mut resources.world.occlusion;
+    if let Some(enabled) = cvars.sync("r.occlusion_culling", occlusion.enabled) {
+        occlusion.enabled = enabled;
+    }
+
+    let clock = &mut resources.world.clock;
+    if let Some(scale) = cvars.sync("time.scale", clock.time_scale) {
+        clock.set_time_scale(scale);
+    }
+    if let Some(paused) = cvars.sync("time.paused", clock.paused) {
+        if paused {
+            clock.pause();
+        } else {
+            clock.resume();
+        }
+    }
+    let fixed_step = clock.fixed_step().unwrap_or_default();
+    if let Some(fixed_step) = cvars.sync("time.fixed_step", fixed_step) {
+        clock.set_fixed_step(Some(fixed_step));
+    }
+
+    let gravity = &mut resources.world.physics.gravity;
+    if let Some(gravity_y) = cvars.sync("phys.gravity_y", gravity.y) {
+        gravity.y = gravity_y;
+    }
+
+    let camera_entity = match resources.world.active_camera() {
+        Ok(entity) => entity,
+        Err(_) => return Ok(()),
+    };
+    let mut entry = resources.world.ecs.entry_mut(camera_entity)?;
+    if let Ok(camera) = entry.get_component_mut::<Camera>() {
+        if let Some(exposure) = cvars.sync("cam.exposure", camera.exposure) {
+            camera.exposure = exposure;
+        }
+        if let Projection::Perspective(perspective) = &mut camera.projection {
+            if let Some(fov) = cvars.sync("cam.fov", perspective.y_fov_rad.to_degrees()) {
+                perspective.y_fov_rad = fov.to_radians();
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use crate::{Handle, LoadState};
+use dragonglass_world::Entity;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    path::PathBuf,
+};
+
+// Changes to the app's window, sent as they are received
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppEvent {
+    // Sent once the window has stopped changing size
+    Resized { width: u32, height: u32 },
+    Focused(bool),
+    FileDropped(PathBuf),
+    CloseRequested,
+    // The gpu was reset or removed and the renderer was recreated,
+    // so anything the app uploaded to it directly has to be uploaded again
+    DeviceLost,
+}
+
+// Sent when a background load finishes, whether it succeeded or failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetLoaded {
+    pub id: u64,
+    pub path: PathBuf,
+    pub state: LoadState,
+}
+
+impl AssetLoaded {
+    pub fn is<T>(&self, handle: &Handle<T>) -> bool {
+        self.id == handle.id()
+    }
+}
+
+// Sent when a model was imported again after its file changed and its instances were patched in place.
+// Each instance's entities from before the reload are paired with its entities after,
+// which are unchanged for instances that aren't in the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelReloaded {
+    pub id: u64,
+    pub instances: Vec<(Vec<Entity>, Vec<Entity>)>,
+}
+
+impl ModelReloaded {
+    pub fn is<T>(&self, handle: &Handle<T>) -> bool {
+        self.id == handle.id()
+    }
+}
+
+trait EventQueue {
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> EventQueue for Vec<T> {
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Events of any type sent by the engine's systems and by game code.
+// Every reader sees all of a frame's events, which are dropped when the next frame starts,
+// so events sent after a reader has run that frame are missed by it.
+// The engine sends AppEvent, AssetLoaded, ModelReloaded, BehaviorTaskFinished,
+// and the world's CollisionEvent and AnimationEvent.
+#[derive(Default)]
+pub struct Events {
+    queues: HashMap<TypeId, Box<dyn EventQueue>>,
+}
+
+impl Events {
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.queue_mut::<T>().push(event);
+    }
+
+    pub fn send_all<T: 'static>(&mut self, events: impl IntoIterator<Item = T>) {
+        self.queue_mut::<T>().extend(events);
+    }
+
+    pub fn read<T: 'static>(&self) -> &[T] {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .and_then(|queue| queue.as_any().downcast_ref::<Vec<T>>())
+            .map(|queue| queue.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // Takes this frame's events of a type, so systems that run afterwards won't see them
+    pub fn drain<T: 'static>(&mut self) -> Vec<T> {
+        std::mem::take(self.queue_mut::<T>())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.queues.values_mut().for_each(|queue| queue.clear());
+    }
+
+    fn queue_mut<T: 'static>(&mut self) -> &mut Vec<T> {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Vec::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Event queues are stored under their event type")
+    }
+}
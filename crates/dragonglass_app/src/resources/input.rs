@@ -1,27 +1,96 @@
+use crate::{ActionMap, InputSource};
 use nalgebra_glm as glm;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem,
+    time::{Duration, Instant},
+};
 use winit::{
     dpi::PhysicalPosition,
     event::{
-        ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
-        WindowEvent,
+        DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+        MouseScrollDelta, VirtualKeyCode, WindowEvent,
     },
 };
 
 pub type KeyMap = HashMap<VirtualKeyCode, ElementState>;
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputEventKind {
+    Key {
+        keycode: VirtualKeyCode,
+        state: ElementState,
+    },
+    // Sent by the platform while a key is held, after its initial press
+    KeyRepeat {
+        keycode: VirtualKeyCode,
+    },
+    // A typed character, after keyboard layout and input method composition are applied
+    Text {
+        character: char,
+    },
+    MouseButton {
+        button: MouseButton,
+        state: ElementState,
+    },
+    CursorMoved {
+        position: glm::Vec2,
+    },
+    MouseWheel {
+        delta: glm::Vec2,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InputEvent {
+    pub timestamp: Instant,
+    // The frame the event was received in
+    pub frame: u64,
+    pub kind: InputEventKind,
+}
+
+// Everything games read from the input in one frame, so it can be recorded and played back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub keys: Vec<VirtualKeyCode>,
+    pub modifiers: ModifiersState,
+    pub buttons: Vec<MouseButton>,
+    pub mouse_position: glm::Vec2,
+    pub position_delta: glm::Vec2,
+    pub offset_from_center: glm::Vec2,
+    pub motion_delta: glm::Vec2,
+    pub wheel_delta: glm::Vec2,
+    pub events: Vec<InputEventKind>,
+    pub text: String,
+}
+
 pub struct Input {
     pub keystates: KeyMap,
+    pub modifiers: ModifiersState,
     pub mouse: Mouse,
+    pub actions: ActionMap,
     pub allowed: bool,
+    pub history_duration: Duration,
+    frame: u64,
+    events: Vec<InputEvent>,
+    history: VecDeque<InputEvent>,
+    text: String,
 }
 
 impl Default for Input {
     fn default() -> Self {
         Self {
             keystates: KeyMap::default(),
+            modifiers: ModifiersState::default(),
             mouse: Mouse::default(),
+            actions: ActionMap::default(),
             allowed: true,
+            history_duration: Duration::from_secs(1),
+            frame: 0,
+            events: Vec::new(),
+            history: VecDeque::new(),
+            text: String::new(),
         }
     }
 }
@@ -31,7 +100,240 @@ impl Input {
         self.keystates.contains_key(&keycode) && self.keystates[&keycode] == ElementState::Pressed
     }
 
+    // Whether the platform repeated the key this frame because it is being held down
+    pub fn is_key_repeated(&self, keycode: VirtualKeyCode) -> bool {
+        self.events
+            .iter()
+            .any(|event| event.kind == InputEventKind::KeyRepeat { keycode })
+    }
+
+    // The text typed this frame, for text fields and chat boxes.
+    // Editing keys like backspace and enter are not included, read them as key events instead.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Takes the text typed this frame so other readers don't handle it again
+    pub fn take_text(&mut self) -> String {
+        mem::take(&mut self.text)
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse.buttons.contains(&button)
+    }
+
+    // How strongly the source is held, from 0.0 to 1.0 for keys and buttons.
+    // Scroll sources report this frame's scroll amount.
+    pub fn source_value(&self, source: InputSource) -> f32 {
+        let held = |pressed: bool| if pressed { 1.0 } else { 0.0 };
+        match source {
+            InputSource::Key(keycode) => held(self.is_key_pressed(keycode)),
+            InputSource::MouseButton(button) => held(self.is_mouse_button_pressed(button)),
+            InputSource::ScrollUp => self.mouse.wheel_delta.y.max(0.0),
+            InputSource::ScrollDown => (-self.mouse.wheel_delta.y).max(0.0),
+        }
+    }
+
+    fn source_just_pressed(&self, source: InputSource) -> bool {
+        self.events.iter().any(|event| match (source, event.kind) {
+            (
+                InputSource::Key(keycode),
+                InputEventKind::Key {
+                    keycode: pressed,
+                    state: ElementState::Pressed,
+                },
+            ) => keycode == pressed,
+            (
+                InputSource::MouseButton(button),
+                InputEventKind::MouseButton {
+                    button: pressed,
+                    state: ElementState::Pressed,
+                },
+            ) => button == pressed,
+            (InputSource::ScrollUp, InputEventKind::MouseWheel { delta }) => delta.y > 0.0,
+            (InputSource::ScrollDown, InputEventKind::MouseWheel { delta }) => delta.y < 0.0,
+            _ => false,
+        })
+    }
+
+    // Whether any input bound to the action is held
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.actions
+            .action_sources(action)
+            .iter()
+            .any(|source| self.source_value(*source) > 0.0)
+    }
+
+    // Whether any input bound to the action went down this frame
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        self.actions
+            .action_sources(action)
+            .iter()
+            .any(|source| self.source_just_pressed(*source))
+    }
+
+    // Unbound axes read 0.0
+    pub fn axis(&self, axis: &str) -> f32 {
+        let binding = match self.actions.axis_binding(axis) {
+            Some(binding) => binding,
+            None => return 0.0,
+        };
+        let strongest = |sources: &[InputSource]| {
+            sources
+                .iter()
+                .map(|source| self.source_value(*source))
+                .fold(0.0, f32::max)
+        };
+        strongest(&binding.positive) - strongest(&binding.negative)
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // The input events received this frame, in the order they arrived
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    // Input events from previous and current frames, oldest first, within the history duration
+    pub fn history(&self) -> impl Iterator<Item = &InputEvent> {
+        self.history.iter()
+    }
+
+    pub fn events_within(&self, duration: Duration) -> impl Iterator<Item = &InputEvent> {
+        let now = Instant::now();
+        self.history
+            .iter()
+            .filter(move |event| now.duration_since(event.timestamp) <= duration)
+    }
+
+    pub fn key_presses_within(&self, keycode: VirtualKeyCode, duration: Duration) -> usize {
+        self.events_within(duration)
+            .filter(|event| {
+                event.kind
+                    == InputEventKind::Key {
+                        keycode,
+                        state: ElementState::Pressed,
+                    }
+            })
+            .count()
+    }
+
+    pub fn capture_frame(&self) -> InputFrame {
+        InputFrame {
+            keys: self
+                .keystates
+                .iter()
+                .filter(|(_, state)| **state == ElementState::Pressed)
+                .map(|(keycode, _)| *keycode)
+                .collect(),
+            modifiers: self.modifiers,
+            buttons: self.mouse.buttons.iter().copied().collect(),
+            mouse_position: self.mouse.position,
+            position_delta: self.mouse.position_delta,
+            offset_from_center: self.mouse.offset_from_center,
+            motion_delta: self.mouse.motion_delta,
+            wheel_delta: self.mouse.wheel_delta,
+            events: self.events.iter().map(|event| event.kind).collect(),
+            text: self.text.clone(),
+        }
+    }
+
+    // Replaces this frame's input with a captured frame, as if it had just been received
+    pub fn apply_frame(&mut self, frame: &InputFrame) {
+        self.keystates = frame
+            .keys
+            .iter()
+            .map(|keycode| (*keycode, ElementState::Pressed))
+            .collect();
+        self.modifiers = frame.modifiers;
+        self.mouse.buttons = frame.buttons.iter().copied().collect();
+        self.mouse.is_left_clicked = self.mouse.buttons.contains(&MouseButton::Left);
+        self.mouse.is_right_clicked = self.mouse.buttons.contains(&MouseButton::Right);
+        self.mouse.position = frame.mouse_position;
+        self.mouse.position_delta = frame.position_delta;
+        self.mouse.offset_from_center = frame.offset_from_center;
+        self.mouse.motion_delta = frame.motion_delta;
+        self.mouse.wheel_delta = frame.wheel_delta;
+        self.events.clear();
+        for kind in frame.events.iter() {
+            self.push_event(*kind);
+        }
+        self.text = frame.text.clone();
+    }
+
+    fn push_event(&mut self, kind: InputEventKind) {
+        let event = InputEvent {
+            timestamp: Instant::now(),
+            frame: self.frame,
+            kind,
+        };
+        self.events.push(event);
+        self.history.push_back(event);
+    }
+
+    fn new_frame(&mut self) {
+        self.frame += 1;
+        self.events.clear();
+        self.text.clear();
+        let now = Instant::now();
+        while let Some(event) = self.history.front() {
+            if now.duration_since(event.timestamp) <= self.history_duration {
+                break;
+            }
+            self.history.pop_front();
+        }
+    }
+
+    fn record_event<T>(&mut self, event: &Event<T>) {
+        match event {
+            Event::NewEvents(_) => self.new_frame(),
+            Event::WindowEvent { event, .. } => match *event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(keycode),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    // Key repeats are recorded separately so presses map to physical key transitions
+                    let repeated = state == ElementState::Pressed && self.is_key_pressed(keycode);
+                    if repeated {
+                        self.push_event(InputEventKind::KeyRepeat { keycode });
+                    } else {
+                        self.push_event(InputEventKind::Key { keycode, state });
+                    }
+                }
+                WindowEvent::ReceivedCharacter(character) if !character.is_control() => {
+                    self.text.push(character);
+                    self.push_event(InputEventKind::Text { character });
+                }
+                WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers,
+                WindowEvent::MouseInput { button, state, .. } => {
+                    self.push_event(InputEventKind::MouseButton { button, state })
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.push_event(InputEventKind::CursorMoved {
+                        position: glm::vec2(position.x as _, position.y as _),
+                    })
+                }
+                WindowEvent::MouseWheel {
+                    delta: MouseScrollDelta::LineDelta(h_lines, v_lines),
+                    ..
+                } => self.push_event(InputEventKind::MouseWheel {
+                    delta: glm::vec2(h_lines, v_lines),
+                }),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
     pub fn handle_event<T>(&mut self, event: &Event<T>, window_center: glm::Vec2) {
+        self.record_event(event);
         if let Event::WindowEvent {
             event:
                 WindowEvent::KeyboardInput {
@@ -52,6 +354,21 @@ impl Input {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CursorMode {
+    Free,
+    // Grabbed and hidden, with movement reported as raw relative deltas
+    Locked,
+    // Hidden while over the window, but free to leave it
+    Hidden,
+}
+
+impl Default for CursorMode {
+    fn default() -> Self {
+        Self::Free
+    }
+}
+
 #[derive(Default)]
 pub struct Mouse {
     pub is_left_clicked: bool,
@@ -60,8 +377,12 @@ pub struct Mouse {
     pub position_delta: glm::Vec2,
     pub offset_from_center: glm::Vec2,
     pub wheel_delta: glm::Vec2,
+    pub buttons: HashSet<MouseButton>,
     pub moved: bool,
     pub scrolled: bool,
+    // Raw motion from the device this frame, unaffected by cursor acceleration or the window edges
+    pub motion_delta: glm::Vec2,
+    pub(crate) cursor_mode: CursorMode,
 }
 
 impl Mouse {
@@ -79,11 +400,21 @@ impl Mouse {
                 } => self.mouse_wheel(h_lines, v_lines),
                 _ => {}
             },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (x, y) },
+                ..
+            } => self.mouse_motion(glm::vec2(*x as _, *y as _)),
             _ => {}
         }
     }
 
+    pub fn cursor_mode(&self) -> CursorMode {
+        self.cursor_mode
+    }
+
     fn new_events(&mut self) {
+        self.motion_delta = glm::vec2(0.0, 0.0);
+
         if !self.scrolled {
             self.wheel_delta = glm::vec2(0.0, 0.0);
         }
@@ -99,9 +430,21 @@ impl Mouse {
         let last_position = self.position;
         let current_position = glm::vec2(position.x as _, position.y as _);
         self.position = current_position;
-        self.position_delta = current_position - last_position;
         self.offset_from_center = window_center - glm::vec2(position.x as _, position.y as _);
-        self.moved = true;
+
+        // A locked cursor doesn't move, so its deltas come from the raw device motion instead
+        if self.cursor_mode != CursorMode::Locked {
+            self.position_delta = current_position - last_position;
+            self.moved = true;
+        }
+    }
+
+    fn mouse_motion(&mut self, delta: glm::Vec2) {
+        self.motion_delta += delta;
+        if self.cursor_mode == CursorMode::Locked {
+            self.position_delta = self.motion_delta;
+            self.moved = true;
+        }
     }
 
     fn mouse_wheel(&mut self, h_lines: f32, v_lines: f32) {
@@ -111,6 +454,11 @@ impl Mouse {
 
     fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
         let clicked = state == ElementState::Pressed;
+        if clicked {
+            self.buttons.insert(button);
+        } else {
+            self.buttons.remove(&button);
+        }
         match button {
             MouseButton::Left => self.is_left_clicked = clicked,
             MouseButton::Right => self.is_right_clicked = clicked,
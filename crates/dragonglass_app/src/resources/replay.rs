@@ -0,0 +1,174 @@
+use crate::{Input, InputFrame};
+use anyhow::{bail, Result};
+use dragonglass_world::{Clock, World, WorldSnapshot};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    // The time the world was ticked with
+    pub delta_time: f32,
+    pub input: InputFrame,
+}
+
+// The world as it was when recording started, followed by every frame's input and time step.
+// Playing it back restores the world and ticks it the same way, which reproduces the session exactly
+// as long as the game only changes the world in response to input and the world's clock.
+// Recordings tick with a fixed step, which is saved with the world's clock.
+// State kept outside the world, such as in the app itself, isn't recorded,
+// so recordings are best started right after a level loads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub world: Vec<u8>,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Recording {
+    // Bumped whenever the format worlds are saved in changes
+    pub const VERSION: u32 = 3;
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let recording: Self = bincode::deserialize(&fs::read(path)?)?;
+        if recording.version != Self::VERSION {
+            bail!(
+                "Replay version {} is not supported, expected version {}",
+                recording.version,
+                Self::VERSION
+            );
+        }
+        Ok(recording)
+    }
+
+    // The simulated seconds the recording covers
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.delta_time).sum()
+    }
+}
+
+enum ReplayState {
+    Idle,
+    Recording(Recording),
+    Playing { recording: Recording, frame: usize },
+}
+
+impl Default for ReplayState {
+    fn default() -> Self {
+        Self::Idle
+    }
+}
+
+// Records sessions and plays them back. While playing, the recorded input replaces
+// what the player does and the recorded time steps replace the frame time.
+#[derive(Default)]
+pub struct Replay {
+    state: ReplayState,
+    // The world's fixed step from before recording or playing, put back afterwards
+    previous_fixed_step: Option<f32>,
+}
+
+impl Replay {
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, ReplayState::Recording(_))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, ReplayState::Playing { .. })
+    }
+
+    // The frame being played back and the number of frames in the recording
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        match &self.state {
+            ReplayState::Playing { recording, frame } => Some((*frame, recording.frames.len())),
+            _ => None,
+        }
+    }
+
+    // How long each recorded frame is when the world doesn't already tick with a fixed step
+    pub const FIXED_STEP: f32 = Clock::STEP_SECONDS;
+
+    // Recording begins with the next tick of the world.
+    // The world ticks with a fixed step while recording, so the frame rate doesn't change the outcome.
+    pub fn start_recording(&mut self, world: &mut World) -> Result<()> {
+        self.previous_fixed_step = world.clock.fixed_step();
+        if world.clock.fixed_step().is_none() {
+            world.clock.set_fixed_step(Some(Self::FIXED_STEP));
+        }
+        let snapshot = world.snapshot()?;
+        self.state = ReplayState::Recording(Recording {
+            version: Recording::VERSION,
+            world: snapshot.bytes().to_vec(),
+            frames: Vec::new(),
+        });
+        info!("Started recording a replay");
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self, world: &mut World) -> Option<Recording> {
+        match std::mem::take(&mut self.state) {
+            ReplayState::Recording(recording) => {
+                world.clock.set_fixed_step(self.previous_fixed_step);
+                info!("Recorded {} frames", recording.frames.len());
+                Some(recording)
+            }
+            state => {
+                self.state = state;
+                None
+            }
+        }
+    }
+
+    // Restores the world to where the recording started, and plays it back from the next frame
+    pub fn play(&mut self, recording: Recording, world: &mut World) -> Result<()> {
+        if !self.is_playing() {
+            self.previous_fixed_step = world.clock.fixed_step();
+        }
+        world.restore(&WorldSnapshot::from_bytes(recording.world.clone()))?;
+        info!("Playing a replay of {} frames", recording.frames.len());
+        self.state = ReplayState::Playing {
+            recording,
+            frame: 0,
+        };
+        Ok(())
+    }
+
+    pub fn stop(&mut self, world: &mut World) {
+        if self.is_playing() {
+            world.clock.set_fixed_step(self.previous_fixed_step);
+        }
+        self.state = ReplayState::Idle;
+    }
+
+    // Records this frame, or replaces its input and time step with the recorded ones,
+    // returning the time to tick the world with
+    pub(crate) fn update(&mut self, input: &mut Input, world: &mut World, delta_time: f32) -> f32 {
+        match &mut self.state {
+            ReplayState::Idle => delta_time,
+            ReplayState::Recording(recording) => {
+                recording.frames.push(ReplayFrame {
+                    delta_time,
+                    input: input.capture_frame(),
+                });
+                delta_time
+            }
+            ReplayState::Playing { recording, frame } => match recording.frames.get(*frame) {
+                Some(recorded) => {
+                    *frame += 1;
+                    input.apply_frame(&recorded.input);
+                    recorded.delta_time
+                }
+                None => {
+                    info!("Finished playing the replay");
+                    self.stop(world);
+                    delta_time
+                }
+            },
+        }
+    }
+}
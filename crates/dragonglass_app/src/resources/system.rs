@@ -1,5 +1,8 @@
 use nalgebra_glm as glm;
-use std::{cmp, time::Instant};
+use std::{
+    cmp,
+    time::{Duration, Instant},
+};
 use winit::{
     dpi::PhysicalSize,
     event::{Event, WindowEvent},
@@ -11,9 +14,15 @@ pub struct System {
     pub start_time: Instant,
     pub last_frame: Instant,
     pub exit_requested: bool,
+    // The newest size the window was resized to and when, until it settles
+    pending_resize: Option<(PhysicalSize<u32>, Instant)>,
 }
 
 impl System {
+    // How long the window has to keep its size before the resize is applied,
+    // so dragging its edges doesn't rebuild the swapchain every frame
+    pub const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+
     pub fn new(window_dimensions: PhysicalSize<u32>) -> Self {
         let now = Instant::now();
         Self {
@@ -22,7 +31,20 @@ impl System {
             window_dimensions,
             delta_time: 0.01,
             exit_requested: false,
+            pending_resize: None,
+        }
+    }
+
+    // The size the window settled on, once it has stopped resizing
+    pub fn take_resize(&mut self) -> Option<PhysicalSize<u32>> {
+        let (dimensions, resized_at) = self.pending_resize?;
+        // Minimizing is applied right away, since there is nothing to render until it is restored
+        let minimized = dimensions.width == 0 || dimensions.height == 0;
+        if !minimized && resized_at.elapsed() < Self::RESIZE_DEBOUNCE {
+            return None;
         }
+        self.pending_resize = None;
+        Some(dimensions)
     }
 
     pub fn milliseconds_since_start(&self) -> u32 {
@@ -54,6 +76,7 @@ impl System {
                 WindowEvent::CloseRequested => self.exit_requested = true,
                 WindowEvent::Resized(dimensions) => {
                     self.window_dimensions = dimensions;
+                    self.pending_resize = Some((dimensions, Instant::now()));
                 }
                 _ => {}
             },
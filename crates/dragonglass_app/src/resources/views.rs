@@ -0,0 +1,147 @@
+use anyhow::Result;
+use dragonglass_render::{Renderer, ViewId};
+use dragonglass_world::{Entity, EntityStore, Viewport, World};
+use raw_window_handle::HasRawWindowHandle;
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder, WindowId},
+};
+
+// Describes an additional window that shows the world from a camera of its own,
+// such as a game preview detached to a second monitor
+#[derive(Debug, Clone)]
+pub struct ViewWindowDescriptor {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub camera: Entity,
+}
+
+struct ViewWindow {
+    view: ViewId,
+    camera: Entity,
+    window: Window,
+}
+
+// The windows besides the main one. They share the renderer's device and the world's resources,
+// and are drawn after the main window without post processing or the gui.
+// Windows are opened at the end of the frame they were requested in,
+// and closed when their camera is removed from the world.
+#[derive(Default)]
+pub struct ViewWindows {
+    pending: Vec<ViewWindowDescriptor>,
+    windows: Vec<ViewWindow>,
+}
+
+impl ViewWindows {
+    pub fn open(&mut self, descriptor: ViewWindowDescriptor) {
+        self.pending.push(descriptor);
+    }
+
+    // Closes every window showing the camera
+    pub fn close(&mut self, camera: Entity, renderer: &mut Box<dyn Renderer>) -> Result<()> {
+        let (closed, windows) = self
+            .windows
+            .drain(..)
+            .partition::<Vec<_>, _>(|window| window.camera == camera);
+        self.windows = windows;
+        self.pending
+            .retain(|descriptor| descriptor.camera != camera);
+        closed
+            .iter()
+            .try_for_each(|window| renderer.remove_view(window.view))
+    }
+
+    pub fn is_open(&self, camera: Entity) -> bool {
+        self.windows.iter().any(|window| window.camera == camera)
+            || self
+                .pending
+                .iter()
+                .any(|descriptor| descriptor.camera == camera)
+    }
+
+    pub fn cameras(&self) -> Vec<Entity> {
+        self.windows.iter().map(|window| window.camera).collect()
+    }
+
+    pub(crate) fn open_pending(
+        &mut self,
+        target: &EventLoopWindowTarget<()>,
+        renderer: &mut Box<dyn Renderer>,
+    ) -> Result<()> {
+        for descriptor in self.pending.drain(..) {
+            let window = WindowBuilder::new()
+                .with_title(descriptor.title)
+                .with_inner_size(PhysicalSize::new(descriptor.width, descriptor.height))
+                .build(target)?;
+            let view = renderer.create_view(window.raw_window_handle(), viewport_of(&window))?;
+            self.windows.push(ViewWindow {
+                view,
+                camera: descriptor.camera,
+                window,
+            });
+        }
+        Ok(())
+    }
+
+    // Returns true when the event was for one of these windows,
+    // so the main window's input and gui don't receive it
+    pub(crate) fn handle_event(
+        &mut self,
+        event: &Event<()>,
+        renderer: &mut Box<dyn Renderer>,
+    ) -> Result<bool> {
+        let (window_id, event) = match event {
+            Event::WindowEvent { window_id, event } => (*window_id, event),
+            _ => return Ok(false),
+        };
+        let index = match self.index_of(window_id) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        match event {
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                let window = &self.windows[index];
+                renderer.set_view_viewport(window.view, viewport_of(&window.window))?;
+            }
+            WindowEvent::CloseRequested => {
+                let window = self.windows.remove(index);
+                renderer.remove_view(window.view)?;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn render(&mut self, world: &World, renderer: &mut Box<dyn Renderer>) -> Result<()> {
+        let (removed, windows) = self
+            .windows
+            .drain(..)
+            .partition::<Vec<_>, _>(|window| world.ecs.entry_ref(window.camera).is_err());
+        self.windows = windows;
+        for window in removed {
+            renderer.remove_view(window.view)?;
+        }
+        self.windows
+            .iter()
+            .try_for_each(|window| renderer.render_view(world, window.view, window.camera))
+    }
+
+    fn index_of(&self, window_id: WindowId) -> Option<usize> {
+        self.windows
+            .iter()
+            .position(|window| window.window.id() == window_id)
+    }
+}
+
+fn viewport_of(window: &Window) -> Viewport {
+    let size = window.inner_size();
+    Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: size.width as _,
+        height: size.height as _,
+    }
+}
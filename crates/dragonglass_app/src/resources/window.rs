@@ -0,0 +1,138 @@
+use crate::Resources;
+use anyhow::{Context, Result};
+use dragonglass_config::WindowMode;
+use winit::{
+    dpi::PhysicalSize,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Window},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub size: PhysicalSize<u32>,
+    pub scale_factor: f64,
+    // Sorted from highest to lowest
+    pub refresh_rates: Vec<u16>,
+    pub is_primary: bool,
+}
+
+impl<'a> Resources<'a> {
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        let primary = self.window.primary_monitor();
+        self.window
+            .available_monitors()
+            .map(|monitor| {
+                let mut refresh_rates = monitor
+                    .video_modes()
+                    .map(|video_mode| video_mode.refresh_rate())
+                    .collect::<Vec<_>>();
+                refresh_rates.sort_unstable_by(|a, b| b.cmp(a));
+                refresh_rates.dedup();
+                MonitorInfo {
+                    name: monitor_name(&monitor),
+                    size: monitor.size(),
+                    scale_factor: monitor.scale_factor(),
+                    refresh_rates,
+                    is_primary: primary.as_ref() == Some(&monitor),
+                }
+            })
+            .collect()
+    }
+
+    pub fn set_window_mode(&mut self, mode: WindowMode) -> Result<()> {
+        self.config.window.mode = mode;
+        self.apply_window_settings()
+    }
+
+    pub fn set_fullscreen(&mut self) -> Result<()> {
+        self.set_window_mode(WindowMode::Borderless)
+    }
+
+    // Switches between windowed and borderless fullscreen
+    pub fn toggle_fullscreen(&mut self) -> Result<()> {
+        let mode = match self.config.window.mode {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless | WindowMode::Exclusive => WindowMode::Windowed,
+        };
+        self.set_window_mode(mode)
+    }
+
+    // Passing None uses the primary monitor
+    pub fn set_monitor(&mut self, name: Option<&str>) -> Result<()> {
+        self.config.window.monitor = name.map(|name| name.to_string());
+        self.apply_window_settings()
+    }
+
+    pub fn set_resizable(&mut self, resizable: bool) -> Result<()> {
+        self.config.window.resizable = resizable;
+        self.apply_window_settings()
+    }
+
+    pub fn set_size_limits(
+        &mut self,
+        min_size: Option<[u32; 2]>,
+        max_size: Option<[u32; 2]>,
+    ) -> Result<()> {
+        self.config.window.min_size = min_size;
+        self.config.window.max_size = max_size;
+        self.apply_window_settings()
+    }
+
+    // The renderer switches present modes at the end of the frame
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.config.graphics.vsync = vsync;
+    }
+
+    // Brings the window in line with the window settings in the config
+    pub fn apply_window_settings(&mut self) -> Result<()> {
+        let settings = &self.config.window;
+        self.window.set_resizable(settings.resizable);
+        self.window.set_min_inner_size(
+            settings
+                .min_size
+                .map(|[width, height]| PhysicalSize::new(width, height)),
+        );
+        self.window.set_max_inner_size(
+            settings
+                .max_size
+                .map(|[width, height]| PhysicalSize::new(width, height)),
+        );
+
+        let monitor = selected_monitor(self.window, settings.monitor.as_deref());
+        let fullscreen = match settings.mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Exclusive => {
+                let monitor = monitor.context("No monitor is available for fullscreen!")?;
+                let video_mode = monitor
+                    .video_modes()
+                    .max_by_key(|video_mode| {
+                        let size = video_mode.size();
+                        (size.width * size.height, video_mode.refresh_rate())
+                    })
+                    .context("The monitor has no video modes for exclusive fullscreen!")?;
+                Some(Fullscreen::Exclusive(video_mode))
+            }
+        };
+        if self.window.fullscreen() != fullscreen {
+            self.window.set_fullscreen(fullscreen);
+        }
+        Ok(())
+    }
+}
+
+fn monitor_name(monitor: &MonitorHandle) -> String {
+    monitor
+        .name()
+        .unwrap_or_else(|| format!("{}x{}", monitor.size().width, monitor.size().height))
+}
+
+fn selected_monitor(window: &Window, name: Option<&str>) -> Option<MonitorHandle> {
+    name.and_then(|name| {
+        window
+            .available_monitors()
+            .find(|monitor| monitor_name(monitor) == name)
+    })
+    .or_else(|| window.primary_monitor())
+}
@@ -0,0 +1,81 @@
+use crate::{ActionMap, CVars, Resources};
+use anyhow::Result;
+use dragonglass_config::Config;
+use log::warn;
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+// Writes the config back to its file whenever it changes, so user preferences persist across runs.
+// Key bindings the player changed are kept with it, and restored over the defaults the app binds on startup,
+// as are persistent console variables that differ from their defaults.
+pub(crate) struct Settings {
+    saved: String,
+    default_bindings: ActionMap,
+    last_check: Instant,
+}
+
+impl Settings {
+    pub const BINDINGS_PREFERENCE: &'static str = "bindings";
+    const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    // Created once the app has registered its default bindings
+    pub fn new(resources: &mut Resources) -> Self {
+        let default_bindings = resources.input.actions.clone();
+        if let Some(overrides) = resources
+            .config
+            .preference::<ActionMap>(Self::BINDINGS_PREFERENCE)
+        {
+            resources.input.actions.apply_overrides(overrides);
+        }
+        if let Some(saved) = resources.config.preference(CVars::PREFERENCE) {
+            resources.cvars.load_saved(saved);
+        }
+        Self {
+            saved: resources.config.to_toml().unwrap_or_default(),
+            default_bindings,
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, resources: &mut Resources) {
+        if self.last_check.elapsed() < Self::CHECK_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+        if let Err(error) = self.save_changes(resources) {
+            warn!("Failed to save settings: {}", error);
+        }
+    }
+
+    fn save_changes(&mut self, resources: &mut Resources) -> Result<()> {
+        let overrides = resources
+            .input
+            .actions
+            .overrides_from(&self.default_bindings);
+        if overrides.is_empty() {
+            resources
+                .config
+                .remove_preference(Self::BINDINGS_PREFERENCE);
+        } else {
+            resources
+                .config
+                .set_preference(Self::BINDINGS_PREFERENCE, &overrides)?;
+        }
+
+        let cvars = resources.cvars.saved_values();
+        if cvars.is_empty() {
+            resources.config.remove_preference(CVars::PREFERENCE);
+        } else {
+            resources.config.set_preference(CVars::PREFERENCE, &cvars)?;
+        }
+
+        let contents = resources.config.to_toml()?;
+        if contents != self.saved {
+            fs::write(Config::FILE, &contents)?;
+            self.saved = contents;
+        }
+        Ok(())
+    }
+}
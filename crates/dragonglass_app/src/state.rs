@@ -1,10 +1,18 @@
 use std::path::Path;
 
-use crate::Resources;
+use crate::{App, Resources};
 use anyhow::{Context, Result};
 use winit::event::{ElementState, Event, KeyboardInput, MouseButton};
 
+// One screen or mode of a game, such as a main menu, gameplay, or a loading screen.
+// Only the state on top of the stack is updated and receives input.
 pub trait State {
+    // States pushed over others, like pause menus, can freeze the world underneath them.
+    // The world's clock is paused while they are on top, which stops physics and animation.
+    fn pauses_world(&self) -> bool {
+        false
+    }
+
     fn on_start(&mut self, _resources: &mut Resources) -> Result<()> {
         Ok(())
     }
@@ -67,16 +75,27 @@ pub enum Transition {
     Quit,
 }
 
+struct StackedState {
+    state: Box<dyn State>,
+    // Whether the world's clock was paused before this state was pushed, restored when it is popped
+    clock_was_paused: bool,
+}
+
+// A pushdown automaton of states, which can be run directly as an app.
+// Quitting, or popping the last state, exits the app.
 pub struct StateMachine {
     running: bool,
-    states: Vec<Box<dyn State>>,
+    states: Vec<StackedState>,
 }
 
 impl StateMachine {
     pub fn new(initial_state: impl State + 'static) -> Self {
         Self {
             running: false,
-            states: vec![Box::new(initial_state)],
+            states: vec![StackedState {
+                state: Box::new(initial_state),
+                clock_was_paused: false,
+            }],
         }
     }
 
@@ -86,11 +105,15 @@ impl StateMachine {
 
     pub fn start(&mut self, resources: &mut Resources) -> Result<()> {
         if !self.running {
-            let state = self
+            let entry = self
                 .states
                 .last_mut()
                 .context("Tried to start state machine with no states present!")?;
-            state.on_start(resources)?;
+            entry.clock_was_paused = resources.world.clock.paused;
+            if entry.state.pauses_world() {
+                resources.world.clock.paused = true;
+            }
+            entry.state.on_start(resources)?;
             self.running = true;
         }
         Ok(())
@@ -99,7 +122,7 @@ impl StateMachine {
     pub fn handle_event(&mut self, resources: &mut Resources, event: &Event<()>) -> Result<()> {
         if self.running {
             let transition = match self.states.last_mut() {
-                Some(state) => state.handle_event(resources, event)?,
+                Some(entry) => entry.state.handle_event(resources, event)?,
                 None => Transition::None,
             };
             self.transition(transition, resources)?;
@@ -110,7 +133,7 @@ impl StateMachine {
     pub fn update(&mut self, resources: &mut Resources) -> Result<()> {
         if self.running {
             let transition = match self.states.last_mut() {
-                Some(state) => state.update(resources)?,
+                Some(entry) => entry.state.update(resources)?,
                 None => Transition::None,
             };
             self.transition(transition, resources)?;
@@ -131,13 +154,20 @@ impl StateMachine {
         Ok(())
     }
 
+    // The replacement inherits the clock state from before the replaced state was pushed
     fn switch(&mut self, state: Box<dyn State>, resources: &mut Resources) -> Result<()> {
         if self.running {
-            if let Some(mut state) = self.states.pop() {
-                state.on_stop(resources)?;
+            let mut clock_was_paused = resources.world.clock.paused;
+            if let Some(mut entry) = self.states.pop() {
+                entry.state.on_stop(resources)?;
+                clock_was_paused = entry.clock_was_paused;
             }
-            self.states.push(state);
-            let new_state = self.states.last_mut().unwrap();
+            resources.world.clock.paused = clock_was_paused || state.pauses_world();
+            self.states.push(StackedState {
+                state,
+                clock_was_paused,
+            });
+            let new_state = &mut self.states.last_mut().unwrap().state;
             new_state.on_start(resources)?;
         }
         Ok(())
@@ -145,11 +175,18 @@ impl StateMachine {
 
     fn push(&mut self, state: Box<dyn State>, resources: &mut Resources) -> Result<()> {
         if self.running {
-            if let Some(state) = self.states.last_mut() {
-                state.on_pause(resources)?;
+            if let Some(entry) = self.states.last_mut() {
+                entry.state.on_pause(resources)?;
+            }
+            let clock_was_paused = resources.world.clock.paused;
+            if state.pauses_world() {
+                resources.world.clock.paused = true;
             }
-            self.states.push(state);
-            let new_state = self.states.last_mut().unwrap();
+            self.states.push(StackedState {
+                state,
+                clock_was_paused,
+            });
+            let new_state = &mut self.states.last_mut().unwrap().state;
             new_state.on_start(resources)?;
         }
         Ok(())
@@ -157,11 +194,12 @@ impl StateMachine {
 
     fn pop(&mut self, resources: &mut Resources) -> Result<()> {
         if self.running {
-            if let Some(mut state) = self.states.pop() {
-                state.on_stop(resources)?;
+            if let Some(mut entry) = self.states.pop() {
+                entry.state.on_stop(resources)?;
+                resources.world.clock.paused = entry.clock_was_paused;
             }
-            if let Some(state) = self.states.last_mut() {
-                state.on_resume(resources)?;
+            if let Some(entry) = self.states.last_mut() {
+                entry.state.on_resume(resources)?;
             } else {
                 self.running = false;
             }
@@ -171,11 +209,79 @@ impl StateMachine {
 
     pub fn stop(&mut self, resources: &mut Resources) -> Result<()> {
         if self.running {
-            while let Some(mut state) = self.states.pop() {
-                state.on_stop(resources)?;
+            while let Some(mut entry) = self.states.pop() {
+                entry.state.on_stop(resources)?;
+                resources.world.clock.paused = entry.clock_was_paused;
             }
             self.running = false;
         }
         Ok(())
     }
+
+    fn active_state(&mut self) -> Option<&mut dyn State> {
+        self.states.last_mut().map(|entry| entry.state.as_mut())
+    }
+
+    fn exit_if_stopped(&self, resources: &mut Resources) {
+        if !self.running {
+            resources.system.exit_requested = true;
+        }
+    }
+}
+
+impl App for StateMachine {
+    fn initialize(&mut self, resources: &mut Resources) -> Result<()> {
+        self.start(resources)
+    }
+
+    fn update(&mut self, resources: &mut Resources) -> Result<()> {
+        StateMachine::update(self, resources)?;
+        self.exit_if_stopped(resources);
+        Ok(())
+    }
+
+    fn gui_active(&mut self) -> bool {
+        self.active_state()
+            .map(|state| state.gui_active())
+            .unwrap_or(false)
+    }
+
+    fn update_gui(&mut self, resources: &mut Resources) -> Result<()> {
+        match self.active_state() {
+            Some(state) => state.update_gui(resources),
+            None => Ok(()),
+        }
+    }
+
+    fn on_file_dropped(&mut self, path: &Path, resources: &mut Resources) -> Result<()> {
+        match self.active_state() {
+            Some(state) => state.on_file_dropped(resources, path),
+            None => Ok(()),
+        }
+    }
+
+    fn on_mouse(
+        &mut self,
+        button: &MouseButton,
+        button_state: &ElementState,
+        resources: &mut Resources,
+    ) -> Result<()> {
+        match self.active_state() {
+            Some(state) => state.on_mouse(resources, button, button_state),
+            None => Ok(()),
+        }
+    }
+
+    fn on_key(&mut self, input: KeyboardInput, resources: &mut Resources) -> Result<()> {
+        match self.active_state() {
+            Some(state) => state.on_key(resources, input),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_events(&mut self, event: &Event<()>, resources: &mut Resources) -> Result<()> {
+        self.handle_event(resources, event)?;
+        self.exit_if_stopped(resources);
+        Ok(())
+    }
 }
@@ -0,0 +1,128 @@
+use crate::{Handle, LoadState, Resources};
+use anyhow::Result;
+use dragonglass_world::{is_layer_loaded, load_gltf_layer_asset, unload_layer, GltfAsset};
+use log::{info, warn};
+use nalgebra_glm as glm;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+pub struct StreamingChunk {
+    pub layer: String,
+    pub path: PathBuf,
+    pub center: glm::Vec3,
+    pub load_radius: f32,
+    // Kept larger than the load radius so chunks don't thrash at the boundary
+    pub unload_radius: f32,
+}
+
+impl StreamingChunk {
+    pub fn new(layer: &str, path: impl Into<PathBuf>, center: glm::Vec3, load_radius: f32) -> Self {
+        Self {
+            layer: layer.to_string(),
+            path: path.into(),
+            center,
+            load_radius,
+            unload_radius: load_radius * 1.25,
+        }
+    }
+}
+
+pub struct LayerStreamer {
+    pub enabled: bool,
+    chunks: Vec<StreamingChunk>,
+    pending: HashMap<String, Handle<GltfAsset>>,
+    failed: HashSet<String>,
+}
+
+impl Default for LayerStreamer {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            chunks: Vec::new(),
+            pending: HashMap::new(),
+            failed: HashSet::new(),
+        }
+    }
+}
+
+impl LayerStreamer {
+    pub fn add_chunk(&mut self, chunk: StreamingChunk) {
+        self.chunks.push(chunk);
+    }
+
+    pub fn chunks(&self) -> &[StreamingChunk] {
+        &self.chunks
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Loads chunks near the active camera and unloads distant ones.
+    // Returns true if the world changed and the renderer was reloaded.
+    pub fn update(&mut self, resources: &mut Resources) -> Result<bool> {
+        if !self.enabled {
+            return Ok(false);
+        }
+
+        let camera_entity = resources.world.active_camera()?;
+        let camera_position = resources
+            .world
+            .entity_global_transform(camera_entity)?
+            .translation;
+
+        let mut changed = false;
+        for chunk in self.chunks.iter() {
+            if self.failed.contains(&chunk.layer) {
+                continue;
+            }
+            let distance = glm::distance(&camera_position, &chunk.center);
+            let loaded = is_layer_loaded(resources.world, &chunk.layer);
+            let pending = self.pending.contains_key(&chunk.layer);
+
+            if distance <= chunk.load_radius && !loaded && !pending {
+                let handle = resources.assets.load::<GltfAsset>(&chunk.path);
+                self.pending.insert(chunk.layer.to_string(), handle);
+            } else if distance > chunk.unload_radius {
+                self.pending.remove(&chunk.layer);
+                if loaded {
+                    info!("Unloading streamed layer '{}'", chunk.layer);
+                    unload_layer(resources.world, &chunk.layer)?;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut finished = Vec::new();
+        for (layer, handle) in self.pending.iter() {
+            match resources.assets.state(handle) {
+                Some(LoadState::Loaded) => {
+                    if let Some(asset) = resources.assets.get(handle) {
+                        info!("Loading streamed layer '{}'", layer);
+                        load_gltf_layer_asset(&asset, resources.world, layer)?;
+                        changed = true;
+                    }
+                    finished.push(layer.to_string());
+                }
+                Some(LoadState::Failed(error)) => {
+                    warn!("Failed to stream layer '{}': {}", layer, error);
+                    self.failed.insert(layer.to_string());
+                    finished.push(layer.to_string());
+                }
+                Some(LoadState::Loading) => {}
+                None => finished.push(layer.to_string()),
+            }
+        }
+        for layer in finished.iter() {
+            self.pending.remove(layer);
+        }
+
+        if changed {
+            resources.renderer.load_world(resources.world)?;
+        }
+
+        Ok(changed)
+    }
+}
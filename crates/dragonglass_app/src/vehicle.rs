@@ -0,0 +1,47 @@
+use crate::{ActionMap, InputSource, Resources};
+use anyhow::Result;
+use dragonglass_world::{Entity, RaycastVehicle, VehicleControls};
+use winit::event::VirtualKeyCode;
+
+// The action map bindings vehicles are driven with
+pub const THROTTLE_AXIS: &str = "Throttle";
+pub const STEER_AXIS: &str = "Steer";
+pub const BRAKE_ACTION: &str = "Brake";
+
+// Binds the vehicle controls to the arrow keys, braking with the right shift key.
+// Bindings loaded from a file afterwards still override these.
+pub fn bind_vehicle_controls(actions: &mut ActionMap) {
+    actions.bind_axis(
+        THROTTLE_AXIS,
+        InputSource::Key(VirtualKeyCode::Up),
+        InputSource::Key(VirtualKeyCode::Down),
+    );
+    actions.bind_axis(
+        STEER_AXIS,
+        InputSource::Key(VirtualKeyCode::Right),
+        InputSource::Key(VirtualKeyCode::Left),
+    );
+    actions.bind_action(BRAKE_ACTION, InputSource::Key(VirtualKeyCode::RShift));
+}
+
+// The controls the player is giving through the vehicle bindings this frame
+pub fn vehicle_controls(resources: &Resources) -> VehicleControls {
+    let input = &resources.input;
+    VehicleControls {
+        throttle: input.axis(THROTTLE_AXIS),
+        brake: if input.action_pressed(BRAKE_ACTION) {
+            1.0
+        } else {
+            0.0
+        },
+        steer: input.axis(STEER_AXIS),
+    }
+}
+
+// Hands the player's controls to the entity's vehicle, which applies them in the next physics step
+pub fn drive_vehicle(resources: &mut Resources, entity: Entity) -> Result<()> {
+    let controls = vehicle_controls(resources);
+    let mut entry = resources.world.ecs.entry_mut(entity)?;
+    entry.get_component_mut::<RaycastVehicle>()?.controls = controls;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use anyhow::{bail, Context, Result};
+use dragonglass_world::World;
+use std::{
+    collections::HashMap,
+    mem::{replace, swap},
+};
+
+// Every world the application has loaded, keyed by name.
+// The active world lives in the resources and is the only one that is ticked and rendered,
+// while the rest wait here until they are activated.
+pub struct Worlds {
+    active: String,
+    inactive: HashMap<String, World>,
+    active_changed: bool,
+}
+
+impl Default for Worlds {
+    fn default() -> Self {
+        Self {
+            active: Self::DEFAULT_WORLD.to_string(),
+            inactive: HashMap::new(),
+            active_changed: false,
+        }
+    }
+}
+
+impl Worlds {
+    pub const DEFAULT_WORLD: &'static str = "Main";
+
+    pub fn active_name(&self) -> &str {
+        &self.active
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.active == name || self.inactive.contains_key(name)
+    }
+
+    // The names of all loaded worlds, including the active one
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self
+            .inactive
+            .keys()
+            .cloned()
+            .chain(std::iter::once(self.active.to_string()))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn insert(&mut self, name: &str, world: World) -> Result<()> {
+        if self.contains(name) {
+            bail!("A world named '{}' is already loaded", name);
+        }
+        self.inactive.insert(name.to_string(), world);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&World> {
+        self.inactive.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut World> {
+        self.inactive.get_mut(name)
+    }
+
+    // The active world can't be removed, activate another world first
+    pub fn remove(&mut self, name: &str) -> Result<World> {
+        if self.active == name {
+            bail!("The active world '{}' cannot be removed", name);
+        }
+        self.inactive
+            .remove(name)
+            .context(format!("No world named '{}' is loaded", name))
+    }
+
+    // Swaps the named world into the active slot, storing the previously active world under its name.
+    // Returns false if the world was already active.
+    pub fn activate(&mut self, name: &str, active_world: &mut World) -> Result<bool> {
+        if self.active == name {
+            return Ok(false);
+        }
+        let mut world = self
+            .inactive
+            .remove(name)
+            .context(format!("No world named '{}' is loaded", name))?;
+        swap(active_world, &mut world);
+        let previous = replace(&mut self.active, name.to_string());
+        self.inactive.insert(previous, world);
+        self.active_changed = true;
+        Ok(true)
+    }
+
+    pub(crate) fn take_active_changed(&mut self) -> bool {
+        replace(&mut self.active_changed, false)
+    }
+}
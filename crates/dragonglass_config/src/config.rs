@@ -1,16 +1,308 @@
-use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
 
+// User preferences, persisted to a toml file across runs
 #[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub graphics: Graphics,
+    pub input: InputCalibration,
+    pub window: WindowSettings,
+    pub logging: Logging,
+    // Preferences of the crates and apps built on the engine, such as key bindings and editor layouts, keyed by name
+    pub preferences: BTreeMap<String, toml::Value>,
 }
 
-#[derive(Default, Serialize, Deserialize)]
+impl Config {
+    pub const FILE: &'static str = "config.toml";
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        Self::load(path).unwrap_or_default()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    // Converted to a toml value first, which writes each table's plain values ahead of its nested tables
+    pub fn to_toml(&self) -> Result<String> {
+        let value = toml::Value::try_from(self)?;
+        Ok(toml::to_string_pretty(&value)?)
+    }
+
+    // None when the preference is unset or no longer matches the type
+    pub fn preference<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        self.preferences
+            .get(name)
+            .and_then(|value| value.clone().try_into().ok())
+    }
+
+    pub fn set_preference<T: Serialize>(&mut self, name: &str, value: &T) -> Result<()> {
+        self.preferences
+            .insert(name.to_string(), toml::Value::try_from(value)?);
+        Ok(())
+    }
+
+    pub fn remove_preference(&mut self, name: &str) {
+        self.preferences.remove(name);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Graphics {
+    // Caps presentation to the display's refresh rate.
+    // Without it, frames are presented as soon as they are ready.
+    pub vsync: bool,
     pub post_processing: PostProcessing,
+    pub texture_filtering: TextureFiltering,
+    // Block compresses the textures of imported gltf files to save gpu memory
+    pub compress_textures: bool,
+    // Processes imported models into a binary format cached under .cache, so importing them again
+    // skips decoding their images and building their geometry until their files change
+    pub cache_imports: bool,
+    // The most MiB of gpu memory the world's textures can take up. Past it, the least recently
+    // used textures are downscaled, then evicted until they are drawn again. Unset doesn't limit them.
+    pub texture_budget: Option<u32>,
+    // Generates simplified levels of detail for the meshes of imported models.
+    // Only takes effect when built with the lod_generation feature.
+    pub generate_lods: bool,
+    pub vertex_layout: VertexLayout,
+    // Skins vertices once per frame in a compute pass rather than each time they are drawn.
+    // Only takes effect with the interleaved vertex layout.
+    pub compute_skinning: bool,
+    pub outline: Outline,
+    pub grid: Grid,
+    // The most samples per pixel to multisample with, rounded down to a power of two.
+    // Unset uses the most the device supports. Takes effect the next time the app starts.
+    pub msaa_samples: Option<u32>,
+    // Overrides the backend the app asks for. Takes effect the next time the app starts.
+    pub backend: Option<RenderBackend>,
+    pub debug: GraphicsDebug,
+    // Draws meshes as wireframes. A debug view, so it is not saved.
+    #[serde(skip)]
+    pub wireframe: bool,
+}
+
+impl Default for Graphics {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            post_processing: PostProcessing::default(),
+            texture_filtering: TextureFiltering::default(),
+            compress_textures: false,
+            cache_imports: true,
+            texture_budget: None,
+            generate_lods: false,
+            vertex_layout: VertexLayout::default(),
+            compute_skinning: false,
+            outline: Outline::default(),
+            grid: Grid::default(),
+            msaa_samples: None,
+            backend: None,
+            debug: GraphicsDebug::default(),
+            wireframe: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderBackend {
+    Vulkan,
+    // Only available when the app is built with the webgpu feature
+    Wgpu,
+}
+
+// Debugging aids for the Vulkan backend, which slow rendering down and are off by default.
+// Takes effect the next time the app starts.
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsDebug {
+    // Reports invalid api usage through the log, when the Vulkan validation layers are installed
+    pub validation: bool,
+    // Names buffers, images, and pipelines after what they hold and labels each render pass,
+    // so frame captures are readable
+    pub object_names: bool,
+}
+
+// How selected and hovered entities are outlined in the viewport
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Outline {
+    // Linear rgba
+    pub selected_color: [f32; 4],
+    pub hovered_color: [f32; 4],
+    // In pixels
+    pub thickness: f32,
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self {
+            selected_color: [1.0, 0.5, 0.0, 1.0],
+            hovered_color: [0.3, 0.6, 1.0, 1.0],
+            thickness: 2.0,
+        }
+    }
+}
+
+// A ground grid on the xz plane, drawn by the editor to show scale and the world's axes.
+// Its spacing grows and shrinks with the camera's height above it.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Grid {
+    // Set by the app each frame rather than saved, so only apps that want a grid draw one
+    #[serde(skip)]
+    pub visible: bool,
+    // Linear rgba
+    pub color: [f32; 4],
+    // How far from the camera the grid fades out, in world units
+    pub fade_distance: f32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            color: [0.5, 0.5, 0.5, 0.6],
+            fade_distance: 100.0,
+        }
+    }
+}
+
+// How the world's vertices are laid out in gpu memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VertexLayout {
+    // Every attribute of a vertex is stored together in a single buffer
+    Interleaved,
+    // Positions, surface attributes, and skinning attributes are stored in separate buffers
+    Streams,
+    // Separate buffers, with normals, tangents, uvs, and colors packed into 16 bit values
+    QuantizedStreams,
+}
+
+impl Default for VertexLayout {
+    fn default() -> Self {
+        Self::Interleaved
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    // A borderless window covering the whole monitor at its current resolution
+    Borderless,
+    // Takes exclusive control of the monitor using its largest video mode
+    Exclusive,
+}
+
+impl Default for WindowMode {
+    fn default() -> Self {
+        Self::Windowed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: WindowMode,
+    // The monitor to go fullscreen on, falling back to the primary monitor when unset or disconnected
+    pub monitor: Option<String>,
+    pub resizable: bool,
+    // Limits on the window's inner size in physical pixels
+    pub min_size: Option<[u32; 2]>,
+    pub max_size: Option<[u32; 2]>,
+    // The window's inner size from the last time it was resized while windowed,
+    // used instead of the app's default size on startup
+    pub size: Option<[u32; 2]>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            mode: WindowMode::Windowed,
+            monitor: None,
+            resizable: true,
+            min_size: None,
+            max_size: None,
+            size: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Default for LogLevel {
+    fn default() -> Self {
+        Self::Info
+    }
+}
+
+// Which log messages are written to the terminal, the log file, and the in-app console.
+// Read when the logger is created, and again when changed through the console.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Logging {
+    // The level of modules without a level of their own
+    pub level: LogLevel,
+    // Levels keyed by module path, such as "wgpu_core" or "dragonglass_render::vulkan".
+    // The longest path matching a message's module applies.
+    pub modules: BTreeMap<String, LogLevel>,
+    // The level of every module in the log file, which uses the levels above when unset
+    pub file_level: Option<LogLevel>,
+    // The log file is moved to a numbered backup once it grows past this many bytes
+    pub max_file_size: u64,
+    // How many backups of the log file are kept, counting the ones moved aside on startup
+    pub max_files: usize,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            modules: BTreeMap::new(),
+            file_level: None,
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+// Applied to every texture sampler, limited by what the graphics device supports
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureFiltering {
+    // Values of 1 or less disable anisotropic filtering
+    pub anisotropy: f32,
+    pub trilinear: bool,
+}
+
+impl Default for TextureFiltering {
+    fn default() -> Self {
+        Self {
+            anisotropy: 16.0,
+            trilinear: true,
+        }
+    }
 }
 
 #[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PostProcessing {
     pub film_grain: FilmGrain,
     pub chromatic_aberration: ChromaticAberration,
@@ -25,3 +317,106 @@ pub struct ChromaticAberration {
 pub struct FilmGrain {
     pub strength: f32,
 }
+
+// Calibration settings keyed by device name, then by axis name.
+// Only mouse look is calibrated, since there is no gamepad or XR input to apply it to yet.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputCalibration {
+    pub devices: BTreeMap<String, BTreeMap<String, AxisCalibration>>,
+}
+
+impl InputCalibration {
+    pub const MOUSE: &'static str = "mouse";
+
+    pub fn axis(&self, device: &str, axis: &str) -> AxisCalibration {
+        self.devices
+            .get(device)
+            .and_then(|axes| axes.get(axis))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn axis_mut(&mut self, device: &str, axis: &str) -> &mut AxisCalibration {
+        self.devices
+            .entry(device.to_string())
+            .or_default()
+            .entry(axis.to_string())
+            .or_default()
+    }
+
+    pub fn apply(&self, device: &str, axis: &str, value: f32) -> f32 {
+        self.axis(device, axis).apply(value)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ResponseCurve {
+    Linear,
+    Exponential { exponent: f32 },
+}
+
+impl Default for ResponseCurve {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AxisCalibration {
+    pub dead_zone: f32,
+    pub sensitivity: f32,
+    pub curve: ResponseCurve,
+    pub inverted: bool,
+    // The maximum raw value of a bounded axis such as a thumbstick.
+    // Unbounded axes like mouse deltas leave this unset.
+    pub saturation: Option<f32>,
+}
+
+impl Default for AxisCalibration {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.0,
+            sensitivity: 1.0,
+            curve: ResponseCurve::Linear,
+            inverted: false,
+            saturation: None,
+        }
+    }
+}
+
+impl AxisCalibration {
+    // Unbounded axes are curved relative to this many units, such as pixels of mouse movement,
+    // so a delta of this size is left as it is and larger ones don't explode
+    pub const UNBOUNDED_RANGE: f32 = 20.0;
+
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = (value.abs() - self.dead_zone).max(0.0);
+        if magnitude == 0.0 {
+            return 0.0;
+        }
+
+        // Bounded axes give a value from 0 to 1, while unbounded ones are scaled back to their own units
+        let magnitude = match self.saturation {
+            Some(saturation) => {
+                let range = (saturation - self.dead_zone).max(f32::EPSILON);
+                self.apply_curve((magnitude / range).min(1.0))
+            }
+            None => self.apply_curve(magnitude / Self::UNBOUNDED_RANGE) * Self::UNBOUNDED_RANGE,
+        };
+
+        let mut result = value.signum() * magnitude * self.sensitivity;
+        if self.inverted {
+            result = -result;
+        }
+        result
+    }
+
+    fn apply_curve(&self, magnitude: f32) -> f32 {
+        match self.curve {
+            ResponseCurve::Linear => magnitude,
+            ResponseCurve::Exponential { exponent } => magnitude.powf(exponent),
+        }
+    }
+}
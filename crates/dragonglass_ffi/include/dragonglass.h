@@ -0,0 +1,59 @@
+#ifndef DRAGONGLASS_H
+#define DRAGONGLASS_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An app with its own window, world, and renderer. Created and destroyed through the functions below.
+   An app owns a window and its event loop, so it must be created on the host's main thread
+   and every call for it must be made from that same thread. */
+typedef struct DragonglassApp DragonglassApp;
+
+/* dragonglass_last_error describes any status other than DRAGONGLASS_OK and DRAGONGLASS_CLOSED */
+typedef enum DragonglassStatus {
+    DRAGONGLASS_OK = 0,
+    DRAGONGLASS_ERROR = 1,
+    /* The engine panicked. The app may be left in an inconsistent state and should be destroyed. */
+    DRAGONGLASS_PANIC = 2,
+    /* Returned by dragonglass_app_step once the window has been closed */
+    DRAGONGLASS_CLOSED = 3
+} DragonglassStatus;
+
+/* Returns NULL if the window or renderer couldn't be created. A NULL title keeps the default. */
+DragonglassApp *dragonglass_app_create(uint32_t width, uint32_t height, const char *title);
+void dragonglass_app_destroy(DragonglassApp *app);
+
+/* Runs a single frame. Returns DRAGONGLASS_CLOSED once the window has been closed. */
+DragonglassStatus dragonglass_app_step(DragonglassApp *app);
+
+/* Adds a .glb or .gltf file to the world */
+DragonglassStatus dragonglass_app_load_asset(DragonglassApp *app, const char *path);
+/* Replaces the world with one saved as a .dga file */
+DragonglassStatus dragonglass_app_load_world(DragonglassApp *app, const char *path);
+
+/* Input is delivered at the start of the next frame.
+   Keys are named the same way as in the input bindings, such as "W", "Space", or "Escape". */
+DragonglassStatus dragonglass_app_key(DragonglassApp *app, const char *key, bool pressed);
+/* In physical pixels from the top left of the window */
+DragonglassStatus dragonglass_app_mouse_move(DragonglassApp *app, float x, float y);
+/* 0, 1, and 2 are the left, right, and middle buttons */
+DragonglassStatus dragonglass_app_mouse_button(DragonglassApp *app, uint32_t button, bool pressed);
+/* Scrolls by a number of lines */
+DragonglassStatus dragonglass_app_mouse_wheel(DragonglassApp *app, float x, float y);
+
+/* Renders the active camera's view and returns tightly packed RGBA8 pixels, or NULL on failure.
+   The pixels stay valid until the next capture or until the app is destroyed. */
+const uint8_t *dragonglass_app_capture_frame(DragonglassApp *app, uint32_t *width, uint32_t *height);
+
+/* The message for the most recent failure on this thread, or NULL if nothing has failed */
+const char *dragonglass_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,346 @@
+// A C ABI for embedding dragonglass in hosts written in other languages.
+// The matching header is include/dragonglass.h.
+//
+// Every function taking a DragonglassApp pointer expects one returned by dragonglass_app_create
+// that hasn't been destroyed yet. An app owns a window and its event loop, so it must be created
+// and used from the host's main thread, and every call for it must come from that thread.
+// Failures return a status other than DRAGONGLASS_OK or null,
+// and dragonglass_last_error describes the most recent one on the calling thread.
+// Panics are caught before they reach the host and are reported as DRAGONGLASS_PANIC.
+#![allow(clippy::missing_safety_doc)]
+
+use anyhow::{anyhow, bail, Context, Result};
+use dragonglass_app::{create_logger, App, AppConfig, EmbeddedApp, Resources};
+use serde::{de::IntoDeserializer, Deserialize};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr,
+    thread::{self, ThreadId},
+};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{
+        ElementState, KeyboardInput, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode,
+        WindowEvent,
+    },
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+// The host drives everything through the ffi, so the app itself does nothing extra
+#[derive(Default)]
+struct HostApp;
+
+impl App for HostApp {}
+
+pub struct DragonglassApp {
+    app: EmbeddedApp<HostApp>,
+    // The last captured frame, kept alive until the next capture so the host can copy it
+    frame: Vec<u8>,
+    // The window can only be used from the thread that created it
+    thread: ThreadId,
+}
+
+// Matches DragonglassStatus in the header
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DragonglassStatus {
+    Ok = 0,
+    Error = 1,
+    // The app may be left in an inconsistent state and should be destroyed
+    Panic = 2,
+    // Returned by dragonglass_app_step once the window has been closed
+    Closed = 3,
+}
+
+fn set_last_error(error: &anyhow::Error) {
+    let message = CString::new(format!("{:?}", error).replace('\0', ""))
+        .unwrap_or_else(|_| CString::default());
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn report(error: anyhow::Error) {
+    log::error!("{:?}", error);
+    set_last_error(&error);
+}
+
+// Runs the body of an export so that neither errors nor panics unwind into the host
+fn guard<T>(action: impl FnOnce() -> Result<T>) -> Result<T, DragonglassStatus> {
+    match panic::catch_unwind(AssertUnwindSafe(action)) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(error)) => {
+            report(error);
+            Err(DragonglassStatus::Error)
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            report(anyhow!("The engine panicked: {}", message));
+            Err(DragonglassStatus::Panic)
+        }
+    }
+}
+
+fn status(result: Result<(), DragonglassStatus>) -> DragonglassStatus {
+    result.err().unwrap_or(DragonglassStatus::Ok)
+}
+
+unsafe fn string_argument<'a>(value: *const c_char, name: &str) -> Result<&'a str> {
+    if value.is_null() {
+        bail!("The {} must not be null!", name);
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .context(format!("The {} must be valid UTF-8!", name))
+}
+
+unsafe fn app_argument<'a>(app: *mut DragonglassApp) -> Result<&'a mut DragonglassApp> {
+    let app = app.as_mut().context("The app must not be null!")?;
+    if app.thread != thread::current().id() {
+        bail!("The app must be used from the thread that created it!");
+    }
+    Ok(app)
+}
+
+fn with_app<T>(
+    app: *mut DragonglassApp,
+    action: impl FnOnce(&mut DragonglassApp) -> Result<T>,
+) -> Result<T, DragonglassStatus> {
+    guard(|| unsafe { app_argument(app) }.and_then(action))
+}
+
+// Returns null if the window or renderer couldn't be created
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_create(
+    width: u32,
+    height: u32,
+    title: *const c_char,
+) -> *mut DragonglassApp {
+    let result = guard(|| {
+        // A null title keeps the default
+        let title = if title.is_null() {
+            AppConfig::default().title
+        } else {
+            string_argument(title, "title")?.to_string()
+        };
+        create_app(width, height, title)
+    });
+    match result {
+        Ok(app) => Box::into_raw(Box::new(app)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+fn create_app(width: u32, height: u32, title: String) -> Result<DragonglassApp> {
+    // The host may already have installed a logger of its own
+    let _ = create_logger();
+    let config = AppConfig {
+        width,
+        height,
+        title,
+        ..Default::default()
+    };
+    Ok(DragonglassApp {
+        app: EmbeddedApp::new(HostApp::default(), config)?,
+        frame: Vec::new(),
+        thread: thread::current().id(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_destroy(app: *mut DragonglassApp) {
+    if !app.is_null() {
+        let _ = guard(|| {
+            app_argument(app)?;
+            drop(Box::from_raw(app));
+            Ok(())
+        });
+    }
+}
+
+// Runs a single frame. Returns DRAGONGLASS_CLOSED once the window has been closed.
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_step(app: *mut DragonglassApp) -> DragonglassStatus {
+    match with_app(app, |app| app.app.step()) {
+        Ok(true) => DragonglassStatus::Ok,
+        Ok(false) => DragonglassStatus::Closed,
+        Err(status) => status,
+    }
+}
+
+// Adds a .glb or .gltf file to the world
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_load_asset(
+    app: *mut DragonglassApp,
+    path: *const c_char,
+) -> DragonglassStatus {
+    status(with_app(app, |app| {
+        let path = string_argument(path, "path")?;
+        let mut resources = app.app.resources();
+        resources.load_asset(path)?;
+        resources.renderer.load_world(resources.world)
+    }))
+}
+
+// Replaces the world with one saved as a .dga file
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_load_world(
+    app: *mut DragonglassApp,
+    path: *const c_char,
+) -> DragonglassStatus {
+    status(with_app(app, |app| {
+        let path = string_argument(path, "path")?;
+        let resources = app.app.resources();
+        reload_world(resources, Path::new(path))
+    }))
+}
+
+fn reload_world(resources: Resources, path: &Path) -> Result<()> {
+    resources.world.reload(path)?;
+    resources.renderer.load_world(resources.world)
+}
+
+// Keys are named the same way as in the input bindings, such as "W", "Space", or "Escape"
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_key(
+    app: *mut DragonglassApp,
+    key: *const c_char,
+    pressed: bool,
+) -> DragonglassStatus {
+    status(with_app(app, |app| {
+        let key = string_argument(key, "key")?;
+        let keycode = VirtualKeyCode::deserialize(key.into_deserializer())
+            .map_err(|_: serde::de::value::Error| anyhow::anyhow!("Unknown key '{}'!", key))?;
+        #[allow(deprecated)]
+        app.app.inject_event(WindowEvent::KeyboardInput {
+            device_id: EmbeddedApp::<HostApp>::device_id(),
+            input: KeyboardInput {
+                scancode: 0,
+                state: element_state(pressed),
+                virtual_keycode: Some(keycode),
+                modifiers: Default::default(),
+            },
+            is_synthetic: true,
+        });
+        Ok(())
+    }))
+}
+
+// The position is in physical pixels from the top left of the window
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_mouse_move(
+    app: *mut DragonglassApp,
+    x: f32,
+    y: f32,
+) -> DragonglassStatus {
+    status(with_app(app, |app| {
+        #[allow(deprecated)]
+        app.app.inject_event(WindowEvent::CursorMoved {
+            device_id: EmbeddedApp::<HostApp>::device_id(),
+            position: PhysicalPosition::new(x as f64, y as f64),
+            modifiers: Default::default(),
+        });
+        Ok(())
+    }))
+}
+
+// Buttons 0, 1, and 2 are left, right, and middle. Anything higher is passed along as another button.
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_mouse_button(
+    app: *mut DragonglassApp,
+    button: u32,
+    pressed: bool,
+) -> DragonglassStatus {
+    status(with_app(app, |app| {
+        let button = match button {
+            0 => MouseButton::Left,
+            1 => MouseButton::Right,
+            2 => MouseButton::Middle,
+            other => MouseButton::Other(other as u16),
+        };
+        #[allow(deprecated)]
+        app.app.inject_event(WindowEvent::MouseInput {
+            device_id: EmbeddedApp::<HostApp>::device_id(),
+            state: element_state(pressed),
+            button,
+            modifiers: Default::default(),
+        });
+        Ok(())
+    }))
+}
+
+// Scrolls by a number of lines
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_mouse_wheel(
+    app: *mut DragonglassApp,
+    x: f32,
+    y: f32,
+) -> DragonglassStatus {
+    status(with_app(app, |app| {
+        #[allow(deprecated)]
+        app.app.inject_event(WindowEvent::MouseWheel {
+            device_id: EmbeddedApp::<HostApp>::device_id(),
+            delta: MouseScrollDelta::LineDelta(x, y),
+            phase: TouchPhase::Moved,
+            modifiers: Default::default(),
+        });
+        Ok(())
+    }))
+}
+
+// Renders the active camera's view and returns tightly packed RGBA8 pixels,
+// or null on failure. The pixels stay valid until the next capture or until the app is destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn dragonglass_app_capture_frame(
+    app: *mut DragonglassApp,
+    width: *mut u32,
+    height: *mut u32,
+) -> *const u8 {
+    let captured = with_app(app, |app| {
+        let image = app.app.capture_frame()?;
+        let dimensions = image.dimensions();
+        app.frame = image.into_raw();
+        Ok((app.frame.as_ptr(), dimensions))
+    });
+    match captured {
+        Ok((pixels, (frame_width, frame_height))) => {
+            if let Some(width) = width.as_mut() {
+                *width = frame_width;
+            }
+            if let Some(height) = height.as_mut() {
+                *height = frame_height;
+            }
+            pixels
+        }
+        Err(_) => ptr::null(),
+    }
+}
+
+// The message for the most recent failure on this thread, or null if nothing has failed.
+// The string stays valid until the next failure on the same thread.
+#[no_mangle]
+pub extern "C" fn dragonglass_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+fn element_state(pressed: bool) -> ElementState {
+    if pressed {
+        ElementState::Pressed
+    } else {
+        ElementState::Released
+    }
+}
@@ -2,12 +2,30 @@ use egui::{color_picker::Alpha, pos2, Align2, Color32, Slider, TextStyle, Ui, Wi
 use egui_gizmo::{Gizmo, GizmoMode, GizmoOrientation, GizmoResult, GizmoVisuals};
 use nalgebra_glm as glm;
 
+// The steps the gizmo snaps to while ctrl is held, which are halved while shift is held as well
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GizmoSnapping {
+    pub translation: f32,
+    // In radians
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+impl Default for GizmoSnapping {
+    fn default() -> Self {
+        Self {
+            translation: egui_gizmo::DEFAULT_SNAP_DISTANCE,
+            rotation: egui_gizmo::DEFAULT_SNAP_ANGLE,
+            scale: 0.1,
+        }
+    }
+}
+
 pub struct GizmoWidget {
     pub mode: GizmoMode,
+    pub snapping: GizmoSnapping,
     orientation: GizmoOrientation,
     last_gizmo_response: Option<GizmoResult>,
-    snap_angle: f32,
-    snap_distance: f32,
     visuals: GizmoVisuals,
 }
 
@@ -15,10 +33,9 @@ impl Default for GizmoWidget {
     fn default() -> Self {
         Self {
             mode: GizmoMode::Rotate,
+            snapping: GizmoSnapping::default(),
             orientation: GizmoOrientation::Global,
             last_gizmo_response: None,
-            snap_angle: egui_gizmo::DEFAULT_SNAP_ANGLE,
-            snap_distance: egui_gizmo::DEFAULT_SNAP_DISTANCE,
             visuals: GizmoVisuals {
                 stroke_width: 4.0,
                 gizmo_size: 75.0,
@@ -58,6 +75,24 @@ impl GizmoWidget {
         ui.end_row();
     }
 
+    pub fn render_snapping_settings(&mut self, ui: &mut Ui) {
+        Slider::new(&mut self.snapping.translation, 0.01..=10.0)
+            .text("Translation step")
+            .ui(ui);
+        let mut degrees = self.snapping.rotation.to_degrees();
+        if Slider::new(&mut degrees, 1.0..=90.0)
+            .text("Rotation step (degrees)")
+            .ui(ui)
+            .changed()
+        {
+            self.snapping.rotation = degrees.to_radians();
+        }
+        Slider::new(&mut self.snapping.scale, 0.01..=1.0)
+            .text("Scale step")
+            .ui(ui);
+        ui.end_row();
+    }
+
     pub fn render_controls(&mut self, ui: &mut Ui) {
         self.render_mode_selection(ui);
 
@@ -130,20 +165,15 @@ impl GizmoWidget {
         // Snapping is enabled with ctrl key.
         let snapping = ui.input().modifiers.command;
 
-        // Snap angle to use for rotation when snapping is enabled.
-        // Smaller snap angle is used when shift key is pressed.
-        let snap_angle = if ui.input().modifiers.shift {
-            self.snap_angle / 2.0
-        } else {
-            self.snap_angle
-        };
-
-        // Snap distance to use for translation when snapping is enabled.
-        // Smaller snap distance is used when shift key is pressed.
-        let snap_distance = if ui.input().modifiers.shift {
-            self.snap_distance / 2.0
+        // Smaller steps are used when shift key is pressed.
+        let steps = if ui.input().modifiers.shift {
+            GizmoSnapping {
+                translation: self.snapping.translation / 2.0,
+                rotation: self.snapping.rotation / 2.0,
+                scale: self.snapping.scale / 2.0,
+            }
         } else {
-            self.snap_distance
+            self.snapping
         };
 
         let gizmo = Gizmo::new("My gizmo")
@@ -153,11 +183,19 @@ impl GizmoWidget {
             .mode(self.mode)
             .orientation(self.orientation)
             .snapping(snapping)
-            .snap_angle(snap_angle)
-            .snap_distance(snap_distance)
+            .snap_angle(steps.rotation)
+            .snap_distance(steps.translation)
             .visuals(self.visuals);
 
-        let response = gizmo.interact(ui);
+        let mut response = gizmo.interact(ui);
+
+        // The gizmo only snaps translation and rotation itself
+        if let Some(result) = response.as_mut() {
+            if snapping && result.mode == GizmoMode::Scale && steps.scale > 0.0 {
+                let transform: glm::Mat4 = result.transform.into();
+                result.transform = snap_scale(&transform, steps.scale).into();
+            }
+        }
 
         if let Some(gizmo_response) = self.last_gizmo_response {
             self.show_gizmo_status(ui, gizmo_response);
@@ -188,3 +226,17 @@ impl GizmoWidget {
         );
     }
 }
+
+// Rounds the length of each of the transform's axes to a multiple of the step
+fn snap_scale(transform: &glm::Mat4, step: f32) -> glm::Mat4 {
+    let mut snapped = *transform;
+    for axis in 0..3 {
+        let column = transform.column(axis).into_owned();
+        let scale = column.xyz().norm();
+        if scale > f32::EPSILON {
+            let target = ((scale / step).round() * step).max(step);
+            snapped.set_column(axis, &(column * (target / scale)));
+        }
+    }
+    snapped
+}
@@ -57,6 +57,16 @@ impl Gui {
         self.platform.handle_event(event);
     }
 
+    // True while a gui widget such as a text field has keyboard focus,
+    // so games can avoid treating the same keys as gameplay input
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.platform.context().wants_keyboard_input()
+    }
+
+    pub fn wants_pointer_input(&self) -> bool {
+        self.platform.context().wants_pointer_input()
+    }
+
     pub fn context(&self) -> CtxRef {
         self.platform.context()
     }
@@ -1,6 +1,7 @@
 mod gizmo;
 mod gui;
+mod overlay;
 
-pub use self::{gizmo::*, gui::*};
+pub use self::{gizmo::*, gui::*, overlay::*};
 pub use egui;
 pub use egui_gizmo;
@@ -0,0 +1,72 @@
+use egui::{pos2, vec2, Align2, Color32, Painter, Pos2, Rect, Stroke, TextStyle};
+use nalgebra_glm as glm;
+
+// Draws a line in world space over the rect the scene is rendered to.
+// Lines are clipped against the camera's near plane so lines passing behind the camera are still drawn.
+pub fn paint_world_line(
+    painter: &Painter,
+    rect: Rect,
+    view_projection: &glm::Mat4,
+    start: &glm::Vec3,
+    end: &glm::Vec3,
+    stroke: Stroke,
+) {
+    const NEAR: f32 = 0.0001;
+    let mut start = view_projection * glm::vec4(start.x, start.y, start.z, 1.0);
+    let mut end = view_projection * glm::vec4(end.x, end.y, end.z, 1.0);
+    if start.w < NEAR && end.w < NEAR {
+        return;
+    }
+    if start.w < NEAR {
+        start = glm::mix(&start, &end, (NEAR - start.w) / (end.w - start.w));
+    } else if end.w < NEAR {
+        end = glm::mix(&end, &start, (NEAR - end.w) / (start.w - end.w));
+    }
+    painter.line_segment(
+        [clip_to_screen(&start, rect), clip_to_screen(&end, rect)],
+        stroke,
+    );
+}
+
+// Where a point in world space appears over the rect the scene is rendered to,
+// or nothing when it is behind the camera
+pub fn world_to_screen(
+    rect: Rect,
+    view_projection: &glm::Mat4,
+    position: &glm::Vec3,
+) -> Option<Pos2> {
+    let clip = view_projection * glm::vec4(position.x, position.y, position.z, 1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    Some(clip_to_screen(&clip, rect))
+}
+
+// Draws the world axes in the bottom left corner of the rect, turned the way the camera sees them
+pub fn paint_axis_indicator(painter: &Painter, rect: Rect, view: &glm::Mat4) {
+    const RADIUS: f32 = 30.0;
+    const MARGIN: f32 = 16.0;
+    let center = rect.left_bottom() + vec2(MARGIN + RADIUS, -(MARGIN + RADIUS));
+    let mut axes = [
+        ("X", glm::Vec3::x(), Color32::from_rgb(230, 70, 70)),
+        ("Y", glm::Vec3::y(), Color32::from_rgb(90, 200, 90)),
+        ("Z", glm::Vec3::z(), Color32::from_rgb(70, 120, 230)),
+    ]
+    .map(|(label, axis, color)| (label, view.fixed_slice::<3, 3>(0, 0) * axis, color));
+
+    // Axes pointing away from the camera are drawn first, so the nearer ones are drawn over them
+    axes.sort_by(|(_, a, _), (_, b, _)| a.z.partial_cmp(&b.z).unwrap_or(std::cmp::Ordering::Equal));
+    for (label, axis, color) in axes.iter() {
+        let end = center + vec2(axis.x, -axis.y) * RADIUS;
+        painter.line_segment([center, end], Stroke::new(2.0, *color));
+        painter.text(end, Align2::CENTER_CENTER, label, TextStyle::Small, *color);
+    }
+}
+
+fn clip_to_screen(clip: &glm::Vec4, rect: Rect) -> Pos2 {
+    let ndc = clip.xy() / clip.w;
+    pos2(
+        rect.left() + (ndc.x + 1.0) / 2.0 * rect.width(),
+        rect.top() + (1.0 - ndc.y) / 2.0 * rect.height(),
+    )
+}
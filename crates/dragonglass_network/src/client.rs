@@ -0,0 +1,336 @@
+use crate::{ClientMessage, EntityState, NetworkState, Replication, ServerMessage, Snapshot};
+use anyhow::{Context, Result};
+use dragonglass_world::{Entity, Transform, World};
+use laminar::{Packet, Socket, SocketEvent};
+use log::{info, warn};
+use nalgebra_glm as glm;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{SocketAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+type Spawner = dyn Fn(&mut World) -> Result<Entity>;
+
+// Mirrors the entities a server replicates, showing them a little in the past
+// so their transforms can be interpolated between the two snapshots around that time.
+pub struct NetworkClient {
+    socket: Socket,
+    server: SocketAddr,
+    // Rebuilt server states by tick, with the server time they were taken at
+    states: BTreeMap<u32, (f64, NetworkState)>,
+    // The local copy of each replicated entity, by its replication id
+    entities: HashMap<u32, Entity>,
+    spawners: HashMap<String, Box<Spawner>>,
+    pub replication: Replication,
+    // Seconds the shown state trails the newest snapshot, which should cover a couple of ticks
+    pub interpolation_delay: f64,
+    // The server time being shown
+    time: Option<f64>,
+    connected: bool,
+    last_connect: Option<Instant>,
+    // The server's answer to connecting, which is sent back to join
+    challenge: Option<u64>,
+    // Parts of snapshots that were too large for one packet, by tick
+    parts: BTreeMap<u32, Vec<Option<Vec<u8>>>>,
+}
+
+impl NetworkClient {
+    const CONNECT_INTERVAL: Duration = Duration::from_secs(1);
+    const STATES_KEPT: usize = 64;
+    const PARTIAL_SNAPSHOTS_KEPT: usize = 8;
+
+    pub fn connect(server: impl ToSocketAddrs) -> Result<Self> {
+        let server = server
+            .to_socket_addrs()?
+            .next()
+            .context("Failed to resolve the server's address")?;
+        let socket = Socket::bind_any()?;
+        info!("Connecting to {}", server);
+        Ok(Self {
+            socket,
+            server,
+            states: BTreeMap::new(),
+            entities: HashMap::new(),
+            spawners: HashMap::new(),
+            replication: Replication::default(),
+            interpolation_delay: 0.1,
+            time: None,
+            connected: false,
+            last_connect: None,
+            challenge: None,
+            parts: BTreeMap::new(),
+        })
+    }
+
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+
+    // Spawns the local copy of entities replicated with the prefab
+    pub fn on_spawn(
+        &mut self,
+        prefab: &str,
+        spawner: impl Fn(&mut World) -> Result<Entity> + 'static,
+    ) {
+        self.spawners.insert(prefab.to_string(), Box::new(spawner));
+    }
+
+    // The local copy of a replicated entity
+    pub fn entity(&self, id: u32) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+
+    pub fn disconnect(&mut self) -> Result<()> {
+        let payload = bincode::serialize(&ClientMessage::Disconnect)?;
+        self.socket.send(Packet::unreliable(self.server, payload))?;
+        self.socket.manual_poll(Instant::now());
+        self.connected = false;
+        self.challenge = None;
+        Ok(())
+    }
+
+    pub fn update(&mut self, world: &mut World, delta_time: f32) -> Result<()> {
+        self.socket.manual_poll(Instant::now());
+        self.receive()?;
+
+        if !self.connected
+            && self
+                .last_connect
+                .map_or(true, |last| last.elapsed() >= Self::CONNECT_INTERVAL)
+        {
+            self.last_connect = Some(Instant::now());
+            let message = match self.challenge {
+                Some(challenge) => ClientMessage::Join(challenge),
+                None => ClientMessage::connect(),
+            };
+            self.send(message)?;
+        }
+
+        self.advance_time(delta_time as f64);
+        self.show(world)?;
+
+        self.socket.manual_poll(Instant::now());
+        Ok(())
+    }
+
+    fn send(&mut self, message: ClientMessage) -> Result<()> {
+        let payload = bincode::serialize(&message)?;
+        self.socket
+            .send(Packet::unreliable_sequenced(self.server, payload, Some(0)))?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<()> {
+        while let Some(event) = self.socket.recv() {
+            let packet = match event {
+                SocketEvent::Packet(packet) if packet.addr() == self.server => packet,
+                SocketEvent::Timeout(address) if address == self.server => {
+                    warn!("Lost the connection to {}", address);
+                    self.connected = false;
+                    self.challenge = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            let message = match bincode::deserialize::<ServerMessage>(packet.payload()) {
+                Ok(message) => message,
+                Err(error) => {
+                    warn!("Invalid message from the server: {}", error);
+                    continue;
+                }
+            };
+            match message {
+                ServerMessage::Challenge(challenge) => {
+                    if !self.connected {
+                        self.challenge = Some(challenge);
+                        self.send(ClientMessage::Join(challenge))?;
+                    }
+                }
+                ServerMessage::Snapshot(snapshot) => self.receive_snapshot(snapshot)?,
+                ServerMessage::SnapshotPart {
+                    tick,
+                    index,
+                    count,
+                    bytes,
+                } => {
+                    if let Some(snapshot) = self.receive_part(tick, index, count, bytes) {
+                        self.receive_snapshot(snapshot)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn receive_snapshot(&mut self, snapshot: Snapshot) -> Result<()> {
+        if self.states.contains_key(&snapshot.tick) {
+            return Ok(());
+        }
+        let state = match snapshot.baseline {
+            Some(baseline) => match self.states.get(&baseline) {
+                Some((_, baseline)) => snapshot.apply(baseline),
+                // The baseline was already dropped, so wait for a snapshot against a newer one
+                None => return Ok(()),
+            },
+            None => snapshot.apply(&NetworkState::default()),
+        };
+        if !self.connected {
+            info!("Connected to {}", self.server);
+            self.connected = true;
+        }
+        self.states.insert(snapshot.tick, (snapshot.time, state));
+        while self.states.len() > Self::STATES_KEPT {
+            let oldest = *self.states.keys().next().unwrap();
+            self.states.remove(&oldest);
+        }
+        self.send(ClientMessage::Ack(snapshot.tick))
+    }
+
+    // Returns the snapshot once all of its parts have arrived
+    fn receive_part(
+        &mut self,
+        tick: u32,
+        index: u16,
+        count: u16,
+        bytes: Vec<u8>,
+    ) -> Option<Snapshot> {
+        let parts = self
+            .parts
+            .entry(tick)
+            .or_insert_with(|| vec![None; count as usize]);
+        if let Some(part) = parts.get_mut(index as usize) {
+            *part = Some(bytes);
+        }
+        // Snapshots still missing parts once newer ones are complete are never finished
+        while self.parts.len() > Self::PARTIAL_SNAPSHOTS_KEPT {
+            let oldest = *self.parts.keys().next().unwrap();
+            self.parts.remove(&oldest);
+        }
+        if !self.parts.get(&tick)?.iter().all(Option::is_some) {
+            return None;
+        }
+        let bytes = self
+            .parts
+            .remove(&tick)?
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect::<Vec<_>>();
+        match bincode::deserialize::<Snapshot>(&bytes) {
+            Ok(snapshot) => Some(snapshot),
+            Err(error) => {
+                warn!("Invalid snapshot from the server: {}", error);
+                None
+            }
+        }
+    }
+
+    // Follows the newest snapshot's time minus the delay, snapping to it when too far off
+    fn advance_time(&mut self, delta_time: f64) {
+        let newest = match self.states.values().next_back() {
+            Some((time, _)) => *time,
+            None => return,
+        };
+        let target = newest - self.interpolation_delay;
+        let time = self.time.map_or(target, |time| time + delta_time);
+        self.time = Some(if (time - target).abs() > self.interpolation_delay * 2.0 {
+            target
+        } else {
+            time.min(newest)
+        });
+    }
+
+    fn show(&mut self, world: &mut World) -> Result<()> {
+        let time = match self.time {
+            Some(time) => time,
+            None => return Ok(()),
+        };
+        // The states on either side of the shown time
+        let mut previous = None;
+        let mut next = None;
+        for (state_time, state) in self.states.values() {
+            if *state_time <= time {
+                previous = Some((*state_time, state));
+            } else {
+                next = Some((*state_time, state));
+                break;
+            }
+        }
+        let (from, to, t) = match (previous, next) {
+            (Some((from_time, from)), Some((to_time, to))) => (
+                from,
+                to,
+                ((time - from_time) / (to_time - from_time)) as f32,
+            ),
+            (Some((_, state)), None) | (None, Some((_, state))) => (state, state, 1.0),
+            (None, None) => return Ok(()),
+        };
+
+        let despawned = self
+            .entities
+            .keys()
+            .filter(|id| !to.entities.contains_key(id))
+            .copied()
+            .collect::<Vec<_>>();
+        for id in despawned.into_iter() {
+            if let Some(entity) = self.entities.remove(&id) {
+                world.remove_entity(entity)?;
+            }
+        }
+
+        for (id, state) in to.entities.iter() {
+            let entity = match self.entities.get(id) {
+                Some(entity) => *entity,
+                None => {
+                    let entity = self.spawn(world, state)?;
+                    self.entities.insert(*id, entity);
+                    entity
+                }
+            };
+            let transform = match from.entities.get(id) {
+                Some(from) if from.prefab == state.prefab => {
+                    interpolate(&from.transform, &state.transform, t)
+                }
+                _ => state.transform,
+            };
+            let mut entry = match world.ecs.entry(entity) {
+                Some(entry) => entry,
+                None => {
+                    // Despawned locally, so it is spawned again next frame
+                    self.entities.remove(id);
+                    continue;
+                }
+            };
+            entry.add_component(transform);
+            self.replication.apply(&mut entry, state);
+        }
+
+        for (animation, state) in world.assets.animations.iter_mut().zip(to.animations.iter()) {
+            animation.time = state.time;
+            animation.playing = state.playing;
+        }
+        Ok(())
+    }
+
+    fn spawn(&self, world: &mut World, state: &EntityState) -> Result<Entity> {
+        match self.spawners.get(&state.prefab) {
+            Some(spawner) => spawner(world),
+            None => {
+                warn!("No spawner is registered for prefab '{}'", state.prefab);
+                let entity = world.ecs.push((state.transform,));
+                world.scene.default_scenegraph_mut()?.add_node(entity);
+                Ok(entity)
+            }
+        }
+    }
+}
+
+// Replicated entities are spawned at the root of the scene, so their world transform is their local one
+fn interpolate(from: &Transform, to: &Transform, t: f32) -> Transform {
+    Transform {
+        translation: glm::lerp(&from.translation, &to.translation, t),
+        rotation: glm::quat_slerp(&from.rotation, &to.rotation, t),
+        scale: glm::lerp(&from.scale, &to.scale, t),
+    }
+}
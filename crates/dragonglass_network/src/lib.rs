@@ -0,0 +1,11 @@
+// Clients apply snapshots to a world with render assets, which servers don't need
+#[cfg(feature = "client")]
+mod client;
+mod protocol;
+mod replication;
+mod server;
+
+#[cfg(feature = "client")]
+pub use self::client::*;
+pub use self::{protocol::*, replication::*, server::*};
+pub use laminar;
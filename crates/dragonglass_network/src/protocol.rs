@@ -0,0 +1,202 @@
+use dragonglass_world::Transform;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// What clients see of a replicated entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityState {
+    pub prefab: String,
+    // The entity's transform in world space
+    pub transform: Transform,
+    // Custom replicated components, serialized under the name they were registered with
+    pub components: BTreeMap<String, Vec<u8>>,
+}
+
+// The playback of one of the world's animations, by its index in the world's assets
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationState {
+    pub time: f32,
+    pub playing: bool,
+}
+
+// Everything the server replicates at one tick, by each entity's replication id
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkState {
+    pub entities: BTreeMap<u32, EntityState>,
+    pub animations: Vec<AnimationState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EntityDelta {
+    Spawned(EntityState),
+    // Only what differs from the baseline is sent, and components set to None were removed
+    Changed {
+        transform: Option<Transform>,
+        components: Vec<(String, Option<Vec<u8>>)>,
+    },
+    Despawned,
+}
+
+// The changes between the state a client last acknowledged and the server's current state.
+// Clients without an acknowledged state get every entity as if it just spawned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u32,
+    // Seconds since the server started when the snapshot was taken, which clients interpolate by
+    pub time: f64,
+    pub baseline: Option<u32>,
+    pub entities: Vec<(u32, EntityDelta)>,
+    // Animations are only sent when they changed, and they are sent whole since they are small
+    pub animations: Option<Vec<AnimationState>>,
+}
+
+impl Snapshot {
+    pub fn new(
+        tick: u32,
+        time: f64,
+        baseline: Option<(u32, &NetworkState)>,
+        current: &NetworkState,
+    ) -> Self {
+        let empty = NetworkState::default();
+        let (baseline_tick, previous) = match baseline {
+            Some((tick, state)) => (Some(tick), state),
+            None => (None, &empty),
+        };
+
+        let mut entities = Vec::new();
+        for (id, state) in current.entities.iter() {
+            let previous = match previous.entities.get(id) {
+                // A different prefab means the id was reused, so the client's copy is replaced
+                Some(previous) if previous.prefab == state.prefab => previous,
+                _ => {
+                    entities.push((*id, EntityDelta::Spawned(state.clone())));
+                    continue;
+                }
+            };
+            if previous == state {
+                continue;
+            }
+            let transform =
+                Some(state.transform).filter(|transform| *transform != previous.transform);
+            let mut components = state
+                .components
+                .iter()
+                .filter(|(name, bytes)| previous.components.get(*name) != Some(*bytes))
+                .map(|(name, bytes)| (name.to_string(), Some(bytes.clone())))
+                .collect::<Vec<_>>();
+            components.extend(
+                previous
+                    .components
+                    .keys()
+                    .filter(|name| !state.components.contains_key(*name))
+                    .map(|name| (name.to_string(), None)),
+            );
+            entities.push((
+                *id,
+                EntityDelta::Changed {
+                    transform,
+                    components,
+                },
+            ));
+        }
+        entities.extend(
+            previous
+                .entities
+                .keys()
+                .filter(|id| !current.entities.contains_key(id))
+                .map(|id| (*id, EntityDelta::Despawned)),
+        );
+
+        let animations = if current.animations != previous.animations {
+            Some(current.animations.clone())
+        } else {
+            None
+        };
+
+        Self {
+            tick,
+            time,
+            baseline: baseline_tick,
+            entities,
+            animations,
+        }
+    }
+
+    // Rebuilds the server's state from the baseline the snapshot was made against
+    pub fn apply(&self, baseline: &NetworkState) -> NetworkState {
+        let mut state = baseline.clone();
+        for (id, delta) in self.entities.iter() {
+            match delta {
+                EntityDelta::Spawned(entity) => {
+                    state.entities.insert(*id, entity.clone());
+                }
+                EntityDelta::Changed {
+                    transform,
+                    components,
+                } => {
+                    let entity = match state.entities.get_mut(id) {
+                        Some(entity) => entity,
+                        None => continue,
+                    };
+                    if let Some(transform) = transform {
+                        entity.transform = *transform;
+                    }
+                    for (name, bytes) in components.iter() {
+                        match bytes {
+                            Some(bytes) => {
+                                entity.components.insert(name.to_string(), bytes.clone());
+                            }
+                            None => {
+                                entity.components.remove(name);
+                            }
+                        }
+                    }
+                }
+                EntityDelta::Despawned => {
+                    state.entities.remove(id);
+                }
+            }
+        }
+        if let Some(animations) = self.animations.as_ref() {
+            state.animations = animations.clone();
+        }
+        state
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    // Sent until the server answers with a challenge, since the server only learns of clients that send something.
+    // It is padded so the challenge is never larger, which keeps spoofed addresses from amplifying traffic.
+    Connect { padding: Vec<u8> },
+    // Echoes the server's challenge, proving the client can receive at its address.
+    // Sent until the first snapshot arrives.
+    Join(u64),
+    // The newest snapshot the client has rebuilt, which the server sends its next changes against
+    Ack(u32),
+    Disconnect,
+}
+
+impl ClientMessage {
+    pub const CONNECT_PADDING: usize = 32;
+
+    pub fn connect() -> Self {
+        Self::Connect {
+            padding: vec![0; Self::CONNECT_PADDING],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    // A token the client has to send back to join, which only someone at the address can see
+    Challenge(u64),
+    Snapshot(Snapshot),
+    // Snapshots too large to send as one packet are split into parts of their serialized bytes
+    SnapshotPart {
+        tick: u32,
+        index: u16,
+        count: u16,
+        bytes: Vec<u8>,
+    },
+}
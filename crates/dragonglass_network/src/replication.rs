@@ -0,0 +1,103 @@
+use crate::{AnimationState, EntityState, NetworkState};
+use anyhow::Result;
+use dragonglass_world::{Animation, Entity, EntityStore, IntoQuery, SimWorld};
+use legion::{
+    storage::Component,
+    world::{Entry, EntryRef},
+};
+use log::warn;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Marks an entity the server replicates to its clients.
+// Clients make their copy of it with the spawner they registered for its prefab.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replicated {
+    pub id: u32,
+    pub prefab: String,
+}
+
+type ReadFn = dyn Fn(&EntryRef) -> Option<Result<Vec<u8>>>;
+type WriteFn = dyn Fn(&mut Entry, Option<&[u8]>) -> Result<()>;
+
+struct ReplicatedComponent {
+    read: Box<ReadFn>,
+    write: Box<WriteFn>,
+}
+
+// The components replicated alongside each entity's transform, by name.
+// The server and its clients must register the same components under the same names.
+#[derive(Default)]
+pub struct Replication {
+    components: BTreeMap<String, ReplicatedComponent>,
+}
+
+impl Replication {
+    pub fn register<T: Component + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        self.components.insert(
+            name.to_string(),
+            ReplicatedComponent {
+                read: Box::new(|entry| {
+                    let component = entry.get_component::<T>().ok()?;
+                    Some(bincode::serialize(component).map_err(Into::into))
+                }),
+                write: Box::new(|entry, bytes| {
+                    match bytes {
+                        Some(bytes) => entry.add_component(bincode::deserialize::<T>(bytes)?),
+                        None => entry.remove_component::<T>(),
+                    }
+                    Ok(())
+                }),
+            },
+        );
+    }
+
+    // The replicated state of every entity with a Replicated component, along with the world's animations
+    pub fn capture(&self, world: &SimWorld, animations: &[Animation]) -> Result<NetworkState> {
+        let mut query = <(Entity, &Replicated)>::query();
+        let replicated = query
+            .iter(&world.ecs)
+            .map(|(entity, replicated)| (*entity, replicated.clone()))
+            .collect::<Vec<_>>();
+
+        let mut state = NetworkState::default();
+        for (entity, replicated) in replicated.into_iter() {
+            let entry = match world.ecs.entry_ref(entity) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let mut components = BTreeMap::new();
+            for (name, component) in self.components.iter() {
+                if let Some(bytes) = (component.read)(&entry) {
+                    components.insert(name.to_string(), bytes?);
+                }
+            }
+            state.entities.insert(
+                replicated.id,
+                EntityState {
+                    prefab: replicated.prefab,
+                    transform: world.entity_global_transform(entity)?,
+                    components,
+                },
+            );
+        }
+        state.animations = animations
+            .iter()
+            .map(|animation| AnimationState {
+                time: animation.time,
+                playing: animation.playing,
+            })
+            .collect();
+        Ok(state)
+    }
+
+    // Adds, updates, and removes the registered components so they match the entity's state
+    pub fn apply(&self, entry: &mut Entry, state: &EntityState) {
+        for (name, component) in self.components.iter() {
+            let bytes = state.components.get(name).map(Vec::as_slice);
+            if let Err(error) = (component.write)(entry, bytes) {
+                warn!("Failed to replicate component '{}': {}", name, error);
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{ClientMessage, NetworkState, Replicated, Replication, ServerMessage, Snapshot};
+use anyhow::{Context, Result};
+use dragonglass_world::{Animation, Entity, SimWorld};
+use laminar::{Config, Packet, Socket, SocketEvent};
+use log::{info, warn};
+use std::{
+    collections::{
+        hash_map::{DefaultHasher, RandomState},
+        HashMap, VecDeque,
+    },
+    hash::{BuildHasher, Hash, Hasher},
+    net::{SocketAddr, ToSocketAddrs},
+    time::Instant,
+};
+
+struct ClientConnection {
+    // The newest snapshot the client has rebuilt
+    acked: Option<u32>,
+}
+
+// Owns the world and sends snapshots of its replicated entities to every connected client
+// at a fixed rate. Each snapshot only holds what changed since the client's last acknowledged one.
+pub struct NetworkServer {
+    socket: Socket,
+    clients: HashMap<SocketAddr, ClientConnection>,
+    // Recently sent states, which snapshots are made against once clients acknowledge them
+    history: VecDeque<(u32, NetworkState)>,
+    pub replication: Replication,
+    // Snapshots sent each second
+    pub tick_rate: f32,
+    tick: u32,
+    time: f64,
+    accumulator: f32,
+    next_id: u32,
+    // Keys the challenges sent to connecting clients, so they can be checked without remembering them
+    secret: RandomState,
+}
+
+impl NetworkServer {
+    const HISTORY_LENGTH: usize = 64;
+    // Room left in each packet for the message wrapping a snapshot part
+    const PART_OVERHEAD: usize = 64;
+    // Snapshots that would need more parts than this are dropped rather than flooding the client
+    const MAX_PARTS: usize = 64;
+
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Self> {
+        let socket = Socket::bind(address)?;
+        info!("Server listening on {}", socket.local_addr()?);
+        Ok(Self {
+            socket,
+            clients: HashMap::new(),
+            history: VecDeque::new(),
+            replication: Replication::default(),
+            tick_rate: 20.0,
+            tick: 0,
+            time: 0.0,
+            accumulator: 0.0,
+            next_id: 0,
+            secret: RandomState::new(),
+        })
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.clients.keys()
+    }
+
+    // Starts replicating an entity, which clients spawn with the spawner registered for the prefab
+    pub fn replicate(&mut self, world: &mut SimWorld, entity: Entity, prefab: &str) -> Result<u32> {
+        let id = self.next_id;
+        self.next_id += 1;
+        world
+            .ecs
+            .entry(entity)
+            .context("Failed to find the entity to replicate")?
+            .add_component(Replicated {
+                id,
+                prefab: prefab.to_string(),
+            });
+        Ok(id)
+    }
+
+    // Receives acknowledgements and sends a snapshot to each client once a tick has passed.
+    // The delta time is the app's real frame time, so pausing the simulation doesn't stall clients.
+    // Headless servers without render assets have no animations to send.
+    pub fn update(
+        &mut self,
+        world: &SimWorld,
+        animations: &[Animation],
+        delta_time: f32,
+    ) -> Result<()> {
+        self.socket.manual_poll(Instant::now());
+        self.receive();
+
+        self.time += delta_time as f64;
+        self.accumulator += delta_time;
+        let interval = 1.0 / self.tick_rate;
+        if self.accumulator < interval {
+            return Ok(());
+        }
+        // Long frames send a single snapshot rather than catching up
+        self.accumulator %= interval;
+        self.tick += 1;
+
+        let state = self.replication.capture(world, animations)?;
+        for (address, client) in self.clients.iter() {
+            let baseline = client.acked.and_then(|acked| {
+                self.history
+                    .iter()
+                    .find(|(tick, _)| *tick == acked)
+                    .map(|(tick, state)| (*tick, state))
+            });
+            let snapshot = Snapshot::new(self.tick, self.time, baseline, &state);
+            for packet in Self::snapshot_packets(*address, snapshot)? {
+                self.socket.send(packet)?;
+            }
+        }
+        self.history.push_back((self.tick, state));
+        if self.history.len() > Self::HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+
+        self.socket.manual_poll(Instant::now());
+        Ok(())
+    }
+
+    // Only reliable packets are split into fragments, and only up to laminar's packet size,
+    // so larger snapshots are sent as parts the client puts back together
+    fn snapshot_packets(address: SocketAddr, snapshot: Snapshot) -> Result<Vec<Packet>> {
+        let config = Config::default();
+        let bytes = bincode::serialize(&snapshot)?;
+        if bytes.len() + Self::PART_OVERHEAD <= config.max_packet_size {
+            let payload = bincode::serialize(&ServerMessage::Snapshot(snapshot))?;
+            let packet = if payload.len() <= config.fragment_size as usize {
+                Packet::unreliable_sequenced(address, payload, Some(0))
+            } else {
+                Packet::reliable_sequenced(address, payload, Some(0))
+            };
+            return Ok(vec![packet]);
+        }
+
+        let chunks = bytes
+            .chunks(config.max_packet_size - Self::PART_OVERHEAD)
+            .collect::<Vec<_>>();
+        if chunks.len() > Self::MAX_PARTS {
+            warn!(
+                "Dropped a snapshot of {} bytes for {}, which is too large to send",
+                bytes.len(),
+                address
+            );
+            return Ok(Vec::new());
+        }
+        let count = chunks.len() as u16;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let part = ServerMessage::SnapshotPart {
+                    tick: snapshot.tick,
+                    index: index as u16,
+                    count,
+                    bytes: chunk.to_vec(),
+                };
+                Ok(Packet::reliable_unordered(
+                    address,
+                    bincode::serialize(&part)?,
+                ))
+            })
+            .collect()
+    }
+
+    // A token only the holder of the address sees, so spoofed addresses can't join
+    fn challenge(&self, address: SocketAddr) -> u64 {
+        let mut hasher: DefaultHasher = self.secret.build_hasher();
+        address.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn receive(&mut self) {
+        while let Some(event) = self.socket.recv() {
+            match event {
+                SocketEvent::Packet(packet) => {
+                    let message = match bincode::deserialize::<ClientMessage>(packet.payload()) {
+                        Ok(message) => message,
+                        Err(error) => {
+                            warn!("Invalid message from {}: {}", packet.addr(), error);
+                            continue;
+                        }
+                    };
+                    self.handle_message(packet.addr(), message);
+                }
+                SocketEvent::Timeout(address) | SocketEvent::Disconnect(address) => {
+                    if self.clients.remove(&address).is_some() {
+                        info!("Client {} timed out", address);
+                    }
+                }
+                SocketEvent::Connect(_) => {}
+            }
+        }
+    }
+
+    fn handle_message(&mut self, address: SocketAddr, message: ClientMessage) {
+        match message {
+            // Nothing is remembered about clients until they answer the challenge
+            ClientMessage::Connect { padding } => {
+                if padding.len() < ClientMessage::CONNECT_PADDING {
+                    return;
+                }
+                let challenge = ServerMessage::Challenge(self.challenge(address));
+                match bincode::serialize(&challenge) {
+                    Ok(payload) => {
+                        if let Err(error) = self.socket.send(Packet::unreliable(address, payload)) {
+                            warn!("Failed to challenge {}: {}", address, error);
+                        }
+                    }
+                    Err(error) => warn!("Failed to challenge {}: {}", address, error),
+                }
+            }
+            ClientMessage::Join(token) => {
+                if token != self.challenge(address) {
+                    warn!("Client {} answered with the wrong challenge", address);
+                    return;
+                }
+                if !self.clients.contains_key(&address) {
+                    info!("Client {} connected", address);
+                    self.clients
+                        .insert(address, ClientConnection { acked: None });
+                }
+            }
+            ClientMessage::Ack(tick) => {
+                if let Some(client) = self.clients.get_mut(&address) {
+                    if client.acked.map_or(true, |acked| tick > acked) {
+                        client.acked = Some(tick);
+                    }
+                }
+            }
+            ClientMessage::Disconnect => {
+                if self.clients.remove(&address).is_some() {
+                    info!("Client {} disconnected", address);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+mod profiler;
+
+pub use self::profiler::*;
+
+// Times the rest of the enclosing block as a named scope of the current frame
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::ScopeGuard::new($name, concat!(file!(), ":", line!()));
+    };
+}
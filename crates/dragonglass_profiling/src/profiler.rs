@@ -0,0 +1,206 @@
+use lazy_static::lazy_static;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+lazy_static! {
+    static ref PROFILER: Mutex<Profiler> = Mutex::new(Profiler::default());
+}
+
+// Lets disabled scopes skip locking the profiler
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
+pub struct ProfileScope {
+    pub name: &'static str,
+    pub location: &'static str,
+    // How many scopes this one is nested in
+    pub depth: usize,
+    // From the start of the frame
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+impl ProfileScope {
+    pub fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameProfile {
+    pub index: u64,
+    pub duration: Duration,
+    // In the order they started
+    pub scopes: Vec<ProfileScope>,
+}
+
+// Collects the scopes of the thread that starts frames, usually the main thread.
+// Scopes on other threads are ignored so the timeline stays a single stack.
+pub struct Profiler {
+    enabled: bool,
+    paused: bool,
+    thread: Option<ThreadId>,
+    frame_index: u64,
+    frame_start: Instant,
+    depth: usize,
+    scopes: Vec<ProfileScope>,
+    history: VecDeque<FrameProfile>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paused: false,
+            thread: None,
+            frame_index: 0,
+            frame_start: Instant::now(),
+            depth: 0,
+            scopes: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl Profiler {
+    pub const MAX_FRAMES: usize = 300;
+
+    pub fn lock() -> MutexGuard<'static, Profiler> {
+        PROFILER.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        ENABLED.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.scopes.clear();
+            self.depth = 0;
+        }
+        #[cfg(feature = "puffin")]
+        puffin_profiler::set_scopes_on(enabled);
+    }
+
+    // Paused profilers keep their history but stop recording new frames
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    // Oldest first
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.history.iter()
+    }
+
+    pub fn latest_frame(&self) -> Option<&FrameProfile> {
+        self.history.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    // Finishes the frame in progress and starts the next one
+    pub fn new_frame(&mut self) {
+        let now = Instant::now();
+        self.thread = Some(thread::current().id());
+
+        if self.is_recording() && !self.scopes.is_empty() {
+            let frame = FrameProfile {
+                index: self.frame_index,
+                duration: now.duration_since(self.frame_start),
+                scopes: std::mem::take(&mut self.scopes),
+            };
+            self.history.push_back(frame);
+            while self.history.len() > Self::MAX_FRAMES {
+                self.history.pop_front();
+            }
+        }
+        self.scopes.clear();
+        self.depth = 0;
+        self.frame_index += 1;
+        self.frame_start = now;
+
+        #[cfg(feature = "puffin")]
+        puffin_profiler::GlobalProfiler::lock().new_frame();
+    }
+
+    fn is_recording(&self) -> bool {
+        self.enabled && !self.paused
+    }
+
+    fn begin_scope(&mut self, name: &'static str, location: &'static str) -> Option<usize> {
+        if !self.is_recording() || self.thread != Some(thread::current().id()) {
+            return None;
+        }
+        self.scopes.push(ProfileScope {
+            name,
+            location,
+            depth: self.depth,
+            start: Instant::now().duration_since(self.frame_start),
+            duration: Duration::default(),
+        });
+        self.depth += 1;
+        Some(self.scopes.len() - 1)
+    }
+
+    fn end_scope(&mut self, frame_index: u64, scope_index: usize) {
+        // Scopes left open when the frame ended were already dropped along with it
+        if frame_index != self.frame_index {
+            return;
+        }
+        let end = Instant::now().duration_since(self.frame_start);
+        if let Some(scope) = self.scopes.get_mut(scope_index) {
+            scope.duration = end.saturating_sub(scope.start);
+            self.depth = self.depth.saturating_sub(1);
+        }
+    }
+}
+
+// Records a scope from its creation until it is dropped.
+// Created through the profile_scope macro.
+pub struct ScopeGuard {
+    frame_index: u64,
+    scope_index: Option<usize>,
+    #[cfg(feature = "puffin")]
+    _puffin_scope: Option<puffin_profiler::ProfilerScope>,
+}
+
+impl ScopeGuard {
+    pub fn new(name: &'static str, location: &'static str) -> Self {
+        let (frame_index, scope_index) = if ENABLED.load(Ordering::Relaxed) {
+            let mut profiler = Profiler::lock();
+            (profiler.frame_index, profiler.begin_scope(name, location))
+        } else {
+            (0, None)
+        };
+        Self {
+            frame_index,
+            scope_index,
+            #[cfg(feature = "puffin")]
+            _puffin_scope: puffin_profiler::are_scopes_on()
+                .then(|| puffin_profiler::ProfilerScope::new(name, location, "")),
+        }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        if let Some(scope_index) = self.scope_index {
+            Profiler::lock().end_scope(self.frame_index, scope_index);
+        }
+    }
+}
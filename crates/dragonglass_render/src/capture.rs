@@ -0,0 +1,172 @@
+use anyhow::{bail, Context, Result};
+use dragonglass_world::{Format, Sampler, Texture};
+use image::{codecs::hdr::HdrEncoder, Rgb, Rgba, RgbaImage};
+use nalgebra_glm as glm;
+use std::{f32::consts::PI, fs::File, io::BufWriter, path::Path};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PanoramaLayout {
+    // A single 2:1 image covering every direction around the camera
+    Equirectangular,
+    // The six faces side by side in +X, -X, +Y, -Y, +Z, -Z order
+    CubemapStrip,
+}
+
+// The six square faces rendered from a single point in the world
+pub struct CubemapCapture {
+    pub size: u32,
+    pub faces: Vec<RgbaImage>,
+}
+
+impl CubemapCapture {
+    // The forward and up directions each face is rendered with.
+    // The side faces are upright, while the top and bottom faces have -Z and +Z as their up direction.
+    pub fn face_orientations() -> [(glm::Vec3, glm::Vec3); 6] {
+        let x = glm::Vec3::x();
+        let y = glm::Vec3::y();
+        let z = glm::Vec3::z();
+        [(x, y), (-x, y), (y, -z), (-y, z), (z, y), (-z, y)]
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, layout: PanoramaLayout) -> Result<()> {
+        let image = match layout {
+            PanoramaLayout::Equirectangular => self.equirectangular(),
+            PanoramaLayout::CubemapStrip => self.strip(),
+        };
+
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "hdr" => save_hdr(&image, path),
+            "png" => Ok(image.save(path)?),
+            _ => bail!(
+                "Panoramas can only be saved as .png or .hdr, not '{}'",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn strip(&self) -> RgbaImage {
+        let mut strip = RgbaImage::new(self.size * 6, self.size);
+        for (index, face) in self.faces.iter().enumerate() {
+            image::imageops::replace(&mut strip, face, index as u32 * self.size, 0);
+        }
+        strip
+    }
+
+    pub fn equirectangular(&self) -> RgbaImage {
+        let width = self.size * 4;
+        let height = self.size * 2;
+        RgbaImage::from_fn(width, height, |x, y| {
+            let longitude = ((x as f32 + 0.5) / width as f32) * 2.0 * PI - PI;
+            let latitude = PI / 2.0 - ((y as f32 + 0.5) / height as f32) * PI;
+
+            // The center of the image looks down -Z, the same as an unrotated camera
+            let direction = glm::vec3(
+                latitude.cos() * longitude.sin(),
+                latitude.sin(),
+                -latitude.cos() * longitude.cos(),
+            );
+            self.sample(&direction)
+        })
+    }
+
+    // Unwraps the faces into the float equirectangular layout environment maps are generated from,
+    // matching the mapping of the equirectangular to cubemap shader rather than the saved panoramas.
+    // The rendered colors are gamma encoded, which the world shader decodes when sampling environment maps.
+    pub fn environment_texture(&self) -> Texture {
+        let width = self.size * 4;
+        let height = self.size * 2;
+        let mut pixels = Vec::with_capacity((width * height * 16) as usize);
+        for y in 0..height {
+            let theta = ((y as f32 + 0.5) / height as f32 - 0.5) * PI;
+            for x in 0..width {
+                let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+                let direction = glm::vec3(
+                    theta.cos() * phi.cos(),
+                    -theta.sin(),
+                    theta.cos() * phi.sin(),
+                );
+                let pixel = self.sample(&direction);
+                let color = [pixel[0], pixel[1], pixel[2]]
+                    .iter()
+                    .map(|channel| *channel as f32 / 255.0)
+                    .chain(std::iter::once(1.0));
+                for channel in color {
+                    pixels.extend_from_slice(&channel.to_ne_bytes());
+                }
+            }
+        }
+        Texture {
+            pixels,
+            format: Format::R32G32B32A32F,
+            width,
+            height,
+            mip_levels: 1,
+            sampler: Sampler::default(),
+        }
+    }
+
+    fn sample(&self, direction: &glm::Vec3) -> Rgba<u8> {
+        let (face_index, (forward, up)) = Self::face_orientations()
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|(_, (a, _)), (_, (b, _))| {
+                direction
+                    .dot(a)
+                    .partial_cmp(&direction.dot(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .expect("Cubemaps always have six faces");
+        let right = forward.cross(&up);
+
+        let depth = direction.dot(&forward);
+        let u = (direction.dot(&right) / depth + 1.0) * 0.5;
+        let v = (1.0 - direction.dot(&up) / depth) * 0.5;
+        bilinear_sample(&self.faces[face_index], u, v)
+    }
+}
+
+fn bilinear_sample(image: &RgbaImage, u: f32, v: f32) -> Rgba<u8> {
+    let max_x = image.width() - 1;
+    let max_y = image.height() - 1;
+    let x = (u * image.width() as f32 - 0.5).clamp(0.0, max_x as f32);
+    let y = (v * image.height() as f32 - 0.5).clamp(0.0, max_y as f32);
+
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(max_x), (y0 + 1).min(max_y));
+    let (tx, ty) = (x.fract(), y.fract());
+
+    let mut pixel = [0_u8; 4];
+    for (channel, value) in pixel.iter_mut().enumerate() {
+        let texel = |x, y| image.get_pixel(x, y)[channel] as f32;
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x1, y0) * tx;
+        let bottom = texel(x0, y1) * (1.0 - tx) + texel(x1, y1) * tx;
+        *value = (top * (1.0 - ty) + bottom * ty).round() as u8;
+    }
+    Rgba(pixel)
+}
+
+// The scene is rendered to an 8 bit target, so the values are converted
+// back to linear space but stay within the low dynamic range
+fn save_hdr(image: &RgbaImage, path: &Path) -> Result<()> {
+    let pixels = image
+        .pixels()
+        .map(|pixel| {
+            let linear = |channel: u8| (channel as f32 / 255.0).powf(2.2);
+            Rgb([linear(pixel[0]), linear(pixel[1]), linear(pixel[2])])
+        })
+        .collect::<Vec<_>>();
+    let file = File::create(path).context(format!("Failed to create '{}'", path.display()))?;
+    HdrEncoder::new(BufWriter::new(file)).encode(
+        &pixels,
+        image.width() as usize,
+        image.height() as usize,
+    )?;
+    Ok(())
+}
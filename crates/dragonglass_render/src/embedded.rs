@@ -0,0 +1,93 @@
+use crate::{create_render_backend, Backend, DeviceLost, Renderer};
+use anyhow::Result;
+use dragonglass_config::Config;
+use dragonglass_world::{Viewport, World};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::time::Instant;
+
+// A window the renderer presents to that it didn't create,
+// such as a widget in a Qt, Tauri, or egui host application
+pub struct WindowHandle(pub(crate) RawWindowHandle);
+
+impl WindowHandle {
+    // The handle has to stay valid for as long as a renderer presents to it
+    pub unsafe fn new(handle: RawWindowHandle) -> Self {
+        Self(handle)
+    }
+}
+
+unsafe impl HasRawWindowHandle for WindowHandle {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.0
+    }
+}
+
+// Drives a renderer from a host application's own loop, without dragonglass_app's window and event loop.
+// The host owns the window, forwards its size whenever it changes, and renders once per frame.
+pub struct EmbeddedRenderer {
+    renderer: Box<dyn Renderer>,
+    // Read every frame, so settings like vsync can be changed between frames
+    pub config: Config,
+    started: Instant,
+}
+
+impl EmbeddedRenderer {
+    // The window handle has to stay valid for as long as the renderer exists,
+    // and the size is the window's in physical pixels
+    pub unsafe fn new(
+        backend: Backend,
+        window_handle: RawWindowHandle,
+        (width, height): (u32, u32),
+        config: Config,
+    ) -> Result<Self> {
+        let window_handle = WindowHandle::new(window_handle);
+        let renderer = create_render_backend(
+            &backend,
+            &window_handle,
+            viewport(width, height),
+            &config.graphics,
+        )?;
+        Ok(Self {
+            renderer,
+            config,
+            started: Instant::now(),
+        })
+    }
+
+    // For everything else the renderer does, such as picking and captures
+    pub fn renderer(&mut self) -> &mut dyn Renderer {
+        self.renderer.as_mut()
+    }
+
+    // The swapchain is recreated at the new size with the next frame
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.renderer.set_viewport(viewport(width, height));
+    }
+
+    pub fn load_world(&mut self, world: &World) -> Result<()> {
+        self.renderer.load_world(world)
+    }
+
+    // Renders the world from its active camera. Ticking the world is left to the host.
+    // A lost device is recreated and the world reloaded before this returns.
+    pub fn render(&mut self, world: &World) -> Result<()> {
+        let elapsed_milliseconds = self.started.elapsed().as_millis() as u32;
+        let rendered = self
+            .renderer
+            .update(world, None, &[], elapsed_milliseconds, &self.config)
+            .and_then(|_| self.renderer.render(world, Vec::new()));
+        match rendered {
+            Err(error) if error.is::<DeviceLost>() => self.renderer.recover(world),
+            result => result,
+        }
+    }
+}
+
+fn viewport(width: u32, height: u32) -> Viewport {
+    Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: width as _,
+        height: height as _,
+    }
+}
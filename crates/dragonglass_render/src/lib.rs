@@ -1,10 +1,37 @@
+mod capture;
+mod embedded;
+mod stats;
+mod uniforms;
+#[cfg(not(target_arch = "wasm32"))]
 mod vulkan;
+#[cfg(feature = "webgpu")]
+mod webgpu;
 
 pub mod render;
 
-pub use crate::render::{create_render_backend, Backend, Renderer};
+pub use crate::{
+    capture::{CubemapCapture, PanoramaLayout},
+    embedded::{EmbeddedRenderer, WindowHandle},
+    render::{create_render_backend, Backend, DeviceLost, Renderer, ViewId},
+    stats::{GpuTiming, RenderStats},
+};
 
 unsafe fn byte_slice_from<T: Sized>(data: &T) -> &[u8] {
     let data_ptr = (data as *const T) as *const u8;
     std::slice::from_raw_parts(data_ptr, std::mem::size_of::<T>())
 }
+
+// Entities are indexed in the order the scene graphs are walked, the same as the entity buffers
+fn entity_at_index(
+    world: &dragonglass_world::World,
+    index: usize,
+) -> anyhow::Result<Option<dragonglass_world::Entity>> {
+    let mut entities = Vec::new();
+    for graph in world.scene.graphs.iter() {
+        graph.walk(|node_index| {
+            entities.push(graph[node_index]);
+            Ok(())
+        })?;
+    }
+    Ok(entities.get(index).copied())
+}
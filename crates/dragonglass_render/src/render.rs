@@ -1,16 +1,49 @@
-use crate::vulkan::VulkanRenderBackend;
+use crate::{CubemapCapture, GpuTiming, RenderStats};
 use anyhow::Result;
-use dragonglass_config::Config;
+use dragonglass_config::{Config, Graphics};
 use dragonglass_gui::egui::{ClippedMesh, CtxRef};
-use dragonglass_world::{Viewport, World};
-use raw_window_handle::HasRawWindowHandle;
+use dragonglass_world::{Entity, Viewport, World};
+use image::RgbaImage;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::{fmt, ops::Range};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::vulkan::VulkanRenderBackend;
+#[cfg(feature = "webgpu")]
+use crate::webgpu::WgpuRenderBackend;
+#[cfg(not(target_arch = "wasm32"))]
+use dragonglass_vulkan::core::DebugSettings;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Backend {
     Vulkan,
+    // Runs on whichever of Metal, DirectX 12, and Vulkan the platform provides.
+    // Only renders a subset of what the vulkan backend does.
+    #[cfg(feature = "webgpu")]
+    Wgpu,
 }
 
+// Returned when the gpu was reset or removed. Everything that was on it is gone,
+// so the renderer has to recover before it can render again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeviceLost;
+
+impl fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The graphics device was lost")
+    }
+}
+
+impl std::error::Error for DeviceLost {}
+
+// Identifies a view created for an additional window
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ViewId(pub u64);
+
 pub trait Renderer {
     fn load_world(&mut self, world: &World) -> Result<()>;
+    // Reuploads a range of the world's vertices, for edits that change vertex data in place
+    fn update_vertices(&mut self, world: &World, vertices: Range<usize>) -> Result<()>;
     // TODO: make this just take Resources instead of world, elapsed, config, etc
     fn update(
         &mut self,
@@ -21,18 +54,62 @@ pub trait Renderer {
         config: &Config,
     ) -> Result<()>;
     fn render(&mut self, world: &World, clipped_meshes: Vec<ClippedMesh>) -> Result<()>;
+    // Recreates the device and everything on it after a DeviceLost error, then reloads the world
+    fn recover(&mut self, world: &World) -> Result<()>;
     fn viewport(&self) -> Viewport;
     fn set_viewport(&mut self, viewport: Viewport);
+    // Views present the world to additional windows from cameras of their own,
+    // sharing the device and the world's resources with the main window.
+    // They are rendered without post processing or the gui.
+    // The window has to outlive the view.
+    fn create_view(&mut self, window_handle: RawWindowHandle, viewport: Viewport)
+        -> Result<ViewId>;
+    fn remove_view(&mut self, view: ViewId) -> Result<()>;
+    fn set_view_viewport(&mut self, view: ViewId, viewport: Viewport) -> Result<()>;
+    // Meant to be called after the frame is rendered, since it replaces the uniforms of the main view
+    fn render_view(&mut self, world: &World, view: ViewId, camera: Entity) -> Result<()>;
+    fn capture_cubemap(&mut self, world: &World, size: u32) -> Result<CubemapCapture>;
+    // Captures the scene around every reflection probe, replacing the maps from the last bake
+    fn bake_reflection_probes(&mut self, world: &World) -> Result<()>;
+    // Reads back the world as seen from the active camera at the viewport's size
+    fn capture_frame(&mut self, world: &World) -> Result<RgbaImage>;
+    // The entity drawn at a window position, including entities without colliders
+    fn pick(&mut self, world: &World, x: f32, y: f32) -> Result<Option<Entity>>;
+    // How long each pass of the most recently completed frame took on the gpu
+    fn gpu_timings(&self) -> Vec<GpuTiming>;
+    // Draw calls, triangles, and pipeline switches of the most recently recorded frame
+    // along with the buffer and texture memory currently allocated
+    fn stats(&self) -> RenderStats;
 }
 
+// The graphics settings that can only be applied when the renderer is created are read here
 pub fn create_render_backend(
     backend: &Backend,
     window_handle: &impl HasRawWindowHandle,
     viewport: Viewport,
+    graphics: &Graphics,
 ) -> Result<Box<dyn Renderer>> {
     match backend {
+        #[cfg(not(target_arch = "wasm32"))]
         Backend::Vulkan => {
-            let backend = VulkanRenderBackend::new(window_handle, viewport)?;
+            let debug_settings = DebugSettings {
+                validation: graphics.debug.validation,
+                object_names: graphics.debug.object_names,
+            };
+            let backend = VulkanRenderBackend::new(
+                window_handle,
+                viewport,
+                graphics.msaa_samples,
+                debug_settings,
+            )?;
+            Ok(Box::new(backend) as Box<dyn Renderer>)
+        }
+        // Browsers can't load vulkan, so web builds only render with wgpu
+        #[cfg(target_arch = "wasm32")]
+        Backend::Vulkan => anyhow::bail!("The vulkan backend is not available on the web"),
+        #[cfg(feature = "webgpu")]
+        Backend::Wgpu => {
+            let backend = WgpuRenderBackend::new(window_handle, viewport)?;
             Ok(Box::new(backend) as Box<dyn Renderer>)
         }
     }
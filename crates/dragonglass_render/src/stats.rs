@@ -0,0 +1,57 @@
+#[cfg(not(target_arch = "wasm32"))]
+pub use dragonglass_vulkan::core::GpuTiming;
+#[cfg(not(target_arch = "wasm32"))]
+use dragonglass_vulkan::core::{CommandStats, MemoryStats};
+
+// The same as the vulkan backend's timings, for builds that don't link it
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuTiming {
+    pub name: String,
+    pub milliseconds: f64,
+}
+
+// What the renderer submitted for the most recent frame,
+// along with the gpu memory it currently holds
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u64,
+    pub instances: u64,
+    pub triangles: u64,
+    pub pipeline_switches: u64,
+    pub dispatches: u64,
+    pub buffer_memory: u64,
+    pub texture_memory: u64,
+    pub render_target_memory: u64,
+    // Parts of the buffer memory
+    pub geometry_memory: u64,
+    pub uniform_memory: u64,
+    // World textures kept within the texture budget
+    pub downscaled_textures: u64,
+    pub evicted_textures: u64,
+}
+
+impl RenderStats {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(commands: CommandStats, memory: MemoryStats) -> Self {
+        Self {
+            draw_calls: commands.draw_calls,
+            instances: commands.instances,
+            triangles: commands.triangles,
+            pipeline_switches: commands.pipeline_switches,
+            dispatches: commands.dispatches,
+            buffer_memory: memory.buffer_bytes(),
+            texture_memory: memory.textures,
+            render_target_memory: memory.render_targets,
+            geometry_memory: memory.geometry,
+            uniform_memory: memory.uniforms,
+            downscaled_textures: 0,
+            evicted_textures: 0,
+        }
+    }
+
+    // In bytes
+    pub fn total_memory(&self) -> u64 {
+        self.buffer_memory + self.texture_memory + self.render_target_memory
+    }
+}
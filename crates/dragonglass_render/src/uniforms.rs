@@ -0,0 +1,254 @@
+// The uniforms and storage buffers the vulkan and wgpu backends' shaders read, laid out the same way for both
+use anyhow::Result;
+use dragonglass_world::{
+    legion::EntityStore, Camera, Entity, LightKind, Material, Mesh, PerspectiveCamera,
+    ShadingModel, Skin, Transform, World,
+};
+use nalgebra_glm as glm;
+
+// These should match the constants defined in the shaders
+pub const MAX_NUMBER_OF_LIGHTS: usize = 4; // TODO: Increase this once a deferred or forward+ pipeline is in use
+pub const MAX_NUMBER_OF_REFLECTION_PROBES: usize = 8;
+
+// The storage buffers start out with room for this many and double in size whenever they run out
+pub const INITIAL_NUMBER_OF_ENTITIES: usize = 512;
+pub const INITIAL_NUMBER_OF_JOINTS: usize = 1024;
+
+// In bytes, so that empty worlds still get valid buffers to add geometry to
+pub const MINIMUM_GEOMETRY_BUFFER_SIZE: u64 = 64 * 1024;
+
+pub struct PushConstantMaterial {
+    pub base_color_factor: glm::Vec4,
+    pub emissive_factor: glm::Vec3,
+    pub color_texture_index: i32,
+    pub color_texture_set: i32,
+    pub metallic_roughness_texture_index: i32,
+    pub metallic_roughness_texture_set: i32, // B channel - metalness values. G channel - roughness values
+    pub normal_texture_index: i32,
+    pub normal_texture_set: i32,
+    pub normal_texture_scale: f32,
+    pub occlusion_texture_index: i32,
+    pub occlusion_texture_set: i32, // R channel - occlusion values
+    pub occlusion_strength: f32,
+    pub emissive_texture_index: i32,
+    pub emissive_texture_set: i32,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub alpha_mode: i32,
+    pub alpha_cutoff: f32,
+    pub toon_bands: i32,
+    pub toon_smoothness: f32,
+    pub rim_strength: f32,
+    pub clearcoat_factor: f32,
+    pub clearcoat_roughness_factor: f32,
+    pub transmission_factor: f32,
+    pub ior: f32,
+}
+
+impl From<&Material> for PushConstantMaterial {
+    fn from(material: &Material) -> Self {
+        // Only the toon variant reads these, the other shading models are selected by specialization constant
+        let (toon_bands, toon_smoothness, rim_strength) = match material.shading_model {
+            ShadingModel::Toon {
+                bands,
+                smoothness,
+                rim_strength,
+            } => (bands.max(1) as i32, smoothness, rim_strength),
+            ShadingModel::Pbr | ShadingModel::Unlit | ShadingModel::Terrain { .. } => (1, 0.0, 0.0),
+        };
+        let mut push_constant = Self {
+            base_color_factor: material.base_color_factor,
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            // Push constants are limited to 128 bytes on some devices, so the strength is applied here
+            emissive_factor: material.emissive_factor * material.emissive_strength,
+            alpha_mode: material.alpha_mode as i32,
+            alpha_cutoff: material.alpha_cutoff,
+            color_texture_index: material.color_texture_index,
+            color_texture_set: material.color_texture_set,
+            metallic_roughness_texture_index: material.metallic_roughness_texture_index,
+            metallic_roughness_texture_set: material.metallic_roughness_texture_set,
+            normal_texture_index: material.normal_texture_index,
+            normal_texture_set: material.normal_texture_set,
+            normal_texture_scale: material.normal_texture_scale,
+            occlusion_texture_index: material.occlusion_texture_index,
+            occlusion_texture_set: material.occlusion_texture_set,
+            occlusion_strength: material.occlusion_strength,
+            emissive_texture_index: material.emissive_texture_index,
+            emissive_texture_set: material.emissive_texture_set,
+            toon_bands,
+            toon_smoothness,
+            rim_strength,
+            clearcoat_factor: material.clearcoat_factor,
+            clearcoat_roughness_factor: material.clearcoat_roughness_factor,
+            transmission_factor: material.transmission_factor,
+            ior: material.ior,
+        };
+        // Terrain is always textured with its first uv set, so there is room in the texture set fields
+        // for its layers. The textures those sets belong to aren't sampled by terrain.
+        if let ShadingModel::Terrain { layers, tiling } = material.shading_model {
+            push_constant.color_texture_set = layers[0];
+            push_constant.metallic_roughness_texture_set = layers[1];
+            push_constant.occlusion_texture_set = layers[2];
+            push_constant.emissive_texture_set = layers[3];
+            push_constant.metallic_roughness_texture_index = -1;
+            push_constant.occlusion_texture_index = -1;
+            push_constant.emissive_texture_index = -1;
+            push_constant.toon_smoothness = tiling;
+        }
+        push_constant
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct Light {
+    pub direction: glm::Vec3,
+    pub range: f32,
+
+    pub color: glm::Vec3,
+    pub intensity: f32,
+
+    pub position: glm::Vec3,
+    pub inner_cone_cos: f32,
+
+    pub outer_cone_cos: f32,
+    pub kind: i32,
+
+    pub padding: glm::Vec2,
+}
+
+impl Light {
+    pub fn from_node(transform: &Transform, light: &dragonglass_world::Light) -> Self {
+        let mut inner_cone_cos: f32 = 0.0;
+        let mut outer_cone_cos: f32 = 0.0;
+        let kind = match light.kind {
+            LightKind::Directional => 0,
+            LightKind::Point => 1,
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                inner_cone_cos = inner_cone_angle;
+                outer_cone_cos = outer_cone_angle;
+                2
+            }
+        };
+        Self {
+            direction: -1.0 * glm::quat_rotate_vec3(&transform.rotation, &glm::Vec3::z()),
+            range: light.range,
+            color: light.color,
+            intensity: light.intensity,
+            position: transform.translation,
+            inner_cone_cos,
+            outer_cone_cos,
+            kind,
+            padding: glm::vec2(0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WorldUniformBuffer {
+    pub view: glm::Mat4,
+    pub projection: glm::Mat4,
+    pub camera_position: glm::Vec3,
+    pub number_of_lights: u32,
+    pub lights: [Light; MAX_NUMBER_OF_LIGHTS],
+    pub ambient_light: glm::Vec3,
+    // Zero when there are no environment maps, so surfaces are lit by the ambient light instead
+    pub environment_lighting: i32,
+    pub number_of_reflection_probes: i32,
+    // The active camera's exposure, applied before tonemapping
+    pub exposure: f32,
+    // Arrays of structs start on a 16 byte boundary in uniform buffers
+    pub padding: glm::Vec2,
+    pub reflection_probes: [ReflectionProbeData; MAX_NUMBER_OF_REFLECTION_PROBES],
+}
+
+// A baked reflection probe's bounds in world space, indexing the probe maps in the descriptor set
+#[derive(Default, Debug, Copy, Clone)]
+pub struct ReflectionProbeData {
+    pub position: glm::Vec3,
+    // Zero for boxes and one for spheres
+    pub kind: i32,
+    // The half extents of boxes, or the radius of spheres in x
+    pub extents: glm::Vec3,
+    pub blend_distance: f32,
+}
+
+// Read from a storage buffer by the index pushed for each entity as it is drawn
+#[derive(Default, Debug, Clone, Copy)]
+pub struct EntityData {
+    pub model: glm::Mat4,
+    // X is the joint count.
+    // Y is the joint matrix offset.
+    // A vec4 is needed to meet shader uniform data layout requirements
+    pub node_info: glm::Vec4,
+}
+
+// Entities are stored in the order the scene graphs are walked.
+// Entities skinned before they are drawn are given no joints, so their vertices aren't skinned twice.
+pub fn entity_data(
+    world: &World,
+    mut skinned_before_drawing: impl FnMut(Entity) -> Result<bool>,
+) -> Result<Vec<EntityData>> {
+    let mut entities = Vec::new();
+    let mut joint_offset = 0;
+    let mut weight_offset = 0;
+    for graph in world.scene.graphs.iter() {
+        graph.walk(|node_index| {
+            let entity = graph[node_index];
+
+            let model = world.global_transform(graph, node_index)?;
+
+            let mut node_info = glm::vec4(0.0, 0.0, 0.0, 0.0);
+
+            if let Ok(skin) = world.ecs.entry_ref(entity)?.get_component::<Skin>() {
+                let joint_count = skin.joints.len();
+                node_info.x = joint_count as f32;
+                node_info.y = joint_offset as f32;
+                joint_offset += joint_count;
+            }
+
+            if skinned_before_drawing(entity)? {
+                node_info.x = 0.0;
+            }
+
+            if let Ok(mesh) = world.ecs.entry_ref(entity)?.get_component::<Mesh>() {
+                let weight_count = mesh.weights.len();
+                node_info.z = weight_count as f32;
+                node_info.w = weight_offset as f32;
+                weight_offset += weight_count;
+            }
+
+            entities.push(EntityData { model, node_info });
+
+            Ok(())
+        })?;
+    }
+    Ok(entities)
+}
+
+// Orthographic cameras still get a perspective skybox
+pub fn skybox_projection(
+    world: &World,
+    camera: Entity,
+    projection: glm::Mat4,
+    aspect_ratio: f32,
+) -> Result<glm::Mat4> {
+    let using_ortho_projection = world
+        .ecs
+        .entry_ref(camera)?
+        .get_component::<Camera>()?
+        .is_orthographic();
+    if !using_ortho_projection {
+        return Ok(projection);
+    }
+    let camera = PerspectiveCamera {
+        aspect_ratio: None,
+        y_fov_rad: 70_f32.to_radians(),
+        z_far: Some(1000.0),
+        z_near: 0.01,
+    };
+    Ok(camera.matrix(aspect_ratio))
+}
@@ -1,6 +1,16 @@
 pub use self::device::VulkanRenderBackend;
 
 mod device;
+mod fog;
+mod grid;
 mod gui;
+mod outline;
+mod particles;
+mod picking;
+mod probes;
 mod scene;
+mod skinning;
+mod sprite;
+mod text;
+mod vertex;
 mod world;
@@ -1,48 +1,88 @@
-use crate::{vulkan::scene::Scene, Renderer};
-use anyhow::Result;
+use crate::{
+    vulkan::{scene::Scene, world::PbrPipelineData},
+    CubemapCapture, DeviceLost, RenderStats, Renderer, ViewId, WindowHandle,
+};
+use anyhow::{bail, Context as _, Error, Result};
 use dragonglass_config::Config;
 use dragonglass_gui::egui::{ClippedMesh, CtxRef};
-use dragonglass_vulkan::core::{Context, Frame};
-use dragonglass_world::{Viewport, World};
-use log::error;
-use raw_window_handle::HasRawWindowHandle;
-use std::sync::Arc;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{Context, DebugSettings, Frame, GpuTiming},
+};
+use dragonglass_world::{Entity, Viewport, World};
+use image::RgbaImage;
+use log::{error, warn};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+// An additional window the world is presented to
+struct View {
+    frame: Frame,
+    viewport: Viewport,
+    window_handle: WindowHandle,
+    // Where the view's camera is kept in the world's uniforms, after the main view's
+    uniform_slot: usize,
+}
 
 pub struct VulkanRenderBackend {
     viewport: Viewport,
+    // Declared before the main frame, so their swapchains are destroyed first
+    views: HashMap<ViewId, View>,
+    next_view_id: u64,
     frame: Frame,
     scene: Scene,
     context: Arc<Context>,
+    // Kept so the surface can be recreated along with a lost device
+    window_handle: WindowHandle,
+    max_samples: Option<u32>,
+    debug_settings: DebugSettings,
 }
 
 impl VulkanRenderBackend {
     const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
-    pub fn new(window_handle: &impl HasRawWindowHandle, viewport: Viewport) -> Result<Self> {
-        let context = Arc::new(Context::new(window_handle)?);
-        let frame = Frame::new(context.clone(), viewport, Self::MAX_FRAMES_IN_FLIGHT)?;
-        let scene = Scene::new(
-            context.clone(),
-            frame.swapchain()?,
-            &frame.swapchain_properties,
-        )?;
+    pub fn new(
+        window_handle: &impl HasRawWindowHandle,
+        viewport: Viewport,
+        max_samples: Option<u32>,
+        debug_settings: DebugSettings,
+    ) -> Result<Self> {
+        let window_handle = WindowHandle(window_handle.raw_window_handle());
+        let (context, frame, scene) =
+            Self::create_device(&window_handle, viewport, max_samples, debug_settings)?;
         let renderer = Self {
             viewport,
+            views: HashMap::new(),
+            next_view_id: 0,
             frame,
             scene,
             context,
+            window_handle,
+            max_samples,
+            debug_settings,
         };
         Ok(renderer)
     }
-}
 
-impl Renderer for VulkanRenderBackend {
-    fn load_world(&mut self, world: &World) -> Result<()> {
-        self.scene.load_world(world)?;
-        Ok(())
+    fn create_device(
+        window_handle: &WindowHandle,
+        viewport: Viewport,
+        max_samples: Option<u32>,
+        debug_settings: DebugSettings,
+    ) -> Result<(Arc<Context>, Frame, Scene)> {
+        let context = Arc::new(Context::new(window_handle, debug_settings)?);
+        let frame = Frame::new(context.clone(), viewport, Self::MAX_FRAMES_IN_FLIGHT)?;
+        let scene = Scene::new(
+            context.clone(),
+            frame.swapchain()?,
+            &frame.swapchain_properties,
+            Self::MAX_FRAMES_IN_FLIGHT,
+            max_samples,
+        )?;
+        Ok((context, frame, scene))
     }
 
-    fn update(
+    fn prepare_frame(
         &mut self,
         world: &World,
         gui_context: Option<&CtxRef>,
@@ -50,6 +90,48 @@ impl Renderer for VulkanRenderBackend {
         elapsed_milliseconds: u32,
         config: &Config,
     ) -> Result<()> {
+        // The swapchain is recreated with the new present mode before the next frame
+        self.frame.set_vsync(config.graphics.vsync);
+
+        self.scene.poll_uploads()?;
+        if self.scene.textures_uploaded() {
+            // Frames in flight may still be sampling the dummy texture in those slots
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.scene.bind_uploaded_textures();
+        }
+
+        if self.scene.vertex_layout_changed(config) {
+            // Frames in flight may still be reading the geometry buffers being replaced
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.scene.update_vertex_layout(world, config)?;
+        }
+
+        if self.scene.environment_changed(world) {
+            // Frames in flight may still be using the descriptor sets of the previous skybox
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.scene.update_environment(world);
+        }
+
+        if self.scene.textures_changed(world) {
+            // Frames in flight may still be sampling the textures being replaced
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.scene.update_textures(world)?;
+        }
+
+        if self.scene.geometry_changed(world) {
+            // Frames in flight may still be reading the geometry being grown or rewritten
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.scene.update_geometry(world)?;
+        }
+
+        let residency_changes = self.scene.texture_residency_changes(world, config)?;
+        if !residency_changes.is_empty() {
+            // Frames in flight may still be sampling the textures being evicted or replaced
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.scene
+                .update_texture_residency(world, &residency_changes)?;
+        }
+
         let aspect_ratio = self.frame.swapchain_properties.aspect_ratio();
         self.scene.update(
             world,
@@ -62,17 +144,18 @@ impl Renderer for VulkanRenderBackend {
         Ok(())
     }
 
-    fn render(&mut self, world: &World, clipped_meshes: Vec<ClippedMesh>) -> Result<()> {
+    fn render_frame(&mut self, world: &World, clipped_meshes: Vec<ClippedMesh>) -> Result<()> {
         let Self { frame, scene, .. } = self;
 
         let aspect_ratio = frame.swapchain_properties.aspect_ratio();
         let viewport = self.viewport;
+        let frame_index = frame.index();
         frame.render(viewport, |command_buffer, image_index| {
             // TODO: Make this take less parameters...
             scene.execute_passes(
                 command_buffer,
                 world,
-                image_index,
+                (frame_index, image_index),
                 aspect_ratio,
                 viewport,
                 &clipped_meshes,
@@ -86,6 +169,136 @@ impl Renderer for VulkanRenderBackend {
         Ok(())
     }
 
+    fn create_view_frame(
+        context: &Arc<Context>,
+        window_handle: &WindowHandle,
+        viewport: Viewport,
+    ) -> Result<Frame> {
+        let surface = context.create_surface(window_handle)?;
+        Frame::with_surface(
+            context.clone(),
+            surface,
+            viewport,
+            Self::MAX_FRAMES_IN_FLIGHT,
+        )
+    }
+
+    fn render_view_frame(&mut self, world: &World, id: ViewId, camera: Entity) -> Result<()> {
+        let Self { views, scene, .. } = self;
+        let view = views
+            .get_mut(&id)
+            .context("Attempted to render a view that does not exist!")?;
+
+        // Like the frames in flight, views share the offscreen images but have their own uniforms
+        scene.update_view(
+            world,
+            camera,
+            view.viewport.aspect_ratio(),
+            view.uniform_slot,
+        )?;
+
+        let viewport = view.viewport;
+        let images = view.frame.swapchain()?.images()?;
+        let extent = view.frame.swapchain_properties.extent;
+        let rendered = view.frame.render(viewport, |command_buffer, image_index| {
+            scene.execute_view_passes(
+                command_buffer,
+                world,
+                viewport,
+                world.camera_culling_mask(camera),
+                (images[image_index], extent),
+            )
+        });
+        scene.finish_view();
+        rendered
+    }
+
+    // The lowest slot no other view is using
+    fn free_uniform_slot(&self) -> Result<usize> {
+        match (1..=PbrPipelineData::MAX_NUMBER_OF_VIEWS)
+            .find(|slot| !self.views.values().any(|view| view.uniform_slot == *slot))
+        {
+            Some(slot) => Ok(slot),
+            None => bail!(
+                "Only {} views can be open at once",
+                PbrPipelineData::MAX_NUMBER_OF_VIEWS
+            ),
+        }
+    }
+}
+
+impl Renderer for VulkanRenderBackend {
+    fn load_world(&mut self, world: &World) -> Result<()> {
+        // Frames in flight may still reference the previous world's buffers and textures
+        unsafe { self.context.device.handle.device_wait_idle() }
+            .map_err(|error| check_device_lost(error.into()))?;
+        self.scene.load_world(world).map_err(check_device_lost)
+    }
+
+    fn update_vertices(&mut self, world: &World, vertices: Range<usize>) -> Result<()> {
+        unsafe { self.context.device.handle.device_wait_idle() }
+            .map_err(|error| check_device_lost(error.into()))?;
+        self.scene
+            .update_vertices(world, vertices)
+            .map_err(check_device_lost)
+    }
+
+    fn update(
+        &mut self,
+        world: &World,
+        gui_context: Option<&CtxRef>,
+        clipped_meshes: &[ClippedMesh],
+        elapsed_milliseconds: u32,
+        config: &Config,
+    ) -> Result<()> {
+        self.prepare_frame(
+            world,
+            gui_context,
+            clipped_meshes,
+            elapsed_milliseconds,
+            config,
+        )
+        .map_err(check_device_lost)
+    }
+
+    fn render(&mut self, world: &World, clipped_meshes: Vec<ClippedMesh>) -> Result<()> {
+        self.render_frame(world, clipped_meshes)
+            .map_err(check_device_lost)
+    }
+
+    fn recover(&mut self, world: &World) -> Result<()> {
+        warn!("The graphics device was lost, recreating it");
+        // Views present through the lost device too, so they are recreated along with it
+        let views = self
+            .views
+            .drain()
+            .map(|(id, view)| (id, view.window_handle, view.viewport, view.uniform_slot))
+            .collect::<Vec<_>>();
+        // The window can only be presented to by one swapchain at a time
+        self.frame.release_swapchain();
+        let (context, frame, scene) = Self::create_device(
+            &self.window_handle,
+            self.viewport,
+            self.max_samples,
+            self.debug_settings,
+        )?;
+        // Everything on the lost device is destroyed as it is replaced
+        self.scene = scene;
+        self.frame = frame;
+        self.context = context;
+        for (id, window_handle, viewport, uniform_slot) in views {
+            let frame = Self::create_view_frame(&self.context, &window_handle, viewport)?;
+            let view = View {
+                frame,
+                viewport,
+                window_handle,
+                uniform_slot,
+            };
+            self.views.insert(id, view);
+        }
+        self.scene.load_world(world)
+    }
+
     fn viewport(&self) -> Viewport {
         self.viewport
     }
@@ -93,6 +306,82 @@ impl Renderer for VulkanRenderBackend {
     fn set_viewport(&mut self, viewport: Viewport) {
         self.viewport = viewport;
     }
+
+    fn create_view(
+        &mut self,
+        window_handle: RawWindowHandle,
+        viewport: Viewport,
+    ) -> Result<ViewId> {
+        let window_handle = WindowHandle(window_handle);
+        let uniform_slot = self.free_uniform_slot()?;
+        let frame = Self::create_view_frame(&self.context, &window_handle, viewport)?;
+        let id = ViewId(self.next_view_id);
+        self.next_view_id += 1;
+        let view = View {
+            frame,
+            viewport,
+            window_handle,
+            uniform_slot,
+        };
+        self.views.insert(id, view);
+        Ok(id)
+    }
+
+    fn remove_view(&mut self, view: ViewId) -> Result<()> {
+        // The view's last frames may still be presenting
+        unsafe { self.context.device.handle.device_wait_idle() }?;
+        self.views.remove(&view);
+        Ok(())
+    }
+
+    fn set_view_viewport(&mut self, view: ViewId, viewport: Viewport) -> Result<()> {
+        self.views
+            .get_mut(&view)
+            .context("Attempted to resize a view that does not exist!")?
+            .viewport = viewport;
+        Ok(())
+    }
+
+    fn render_view(&mut self, world: &World, view: ViewId, camera: Entity) -> Result<()> {
+        self.render_view_frame(world, view, camera)
+            .map_err(check_device_lost)
+    }
+
+    fn capture_cubemap(&mut self, world: &World, size: u32) -> Result<CubemapCapture> {
+        // The capture reuses the offscreen images and uniform buffers of the frames in flight
+        unsafe { self.context.device.handle.device_wait_idle() }?;
+        self.scene.capture_cubemap(world, size)
+    }
+
+    fn bake_reflection_probes(&mut self, world: &World) -> Result<()> {
+        // Baking captures the scene the same way, and rewrites the world's descriptor set
+        unsafe { self.context.device.handle.device_wait_idle() }?;
+        self.scene.bake_reflection_probes(world)
+    }
+
+    fn gpu_timings(&self) -> Vec<GpuTiming> {
+        self.scene.gpu_timer.timings().to_vec()
+    }
+
+    fn stats(&self) -> RenderStats {
+        let (downscaled_textures, evicted_textures) = self.scene.texture_residency_counts();
+        RenderStats {
+            downscaled_textures,
+            evicted_textures,
+            ..RenderStats::new(self.scene.command_stats, self.context.device.memory.stats())
+        }
+    }
+
+    fn capture_frame(&mut self, world: &World) -> Result<RgbaImage> {
+        unsafe { self.context.device.handle.device_wait_idle() }?;
+        self.scene.capture_frame(world, self.viewport)
+    }
+
+    fn pick(&mut self, world: &World, x: f32, y: f32) -> Result<Option<Entity>> {
+        // The picking pass reads the same uniform and entity buffers as the frames in flight
+        unsafe { self.context.device.handle.device_wait_idle() }?;
+        self.scene.pick(world, self.viewport, (x, y))
+    }
 }
 
 impl Drop for VulkanRenderBackend {
@@ -104,3 +393,11 @@ impl Drop for VulkanRenderBackend {
         }
     }
 }
+
+// Errors from a lost device are replaced with DeviceLost, which the app recovers from
+fn check_device_lost(error: Error) -> Error {
+    match error.downcast_ref::<vk::Result>() {
+        Some(&vk::Result::ERROR_DEVICE_LOST) => DeviceLost.into(),
+        _ => error,
+    }
+}
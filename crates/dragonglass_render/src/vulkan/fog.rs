@@ -0,0 +1,317 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        CommandPool, Context, DescriptorPool, DescriptorSetLayout, Device,
+        GraphicsPipelineSettingsBuilder, ImageDescription, Pipeline, PipelineLayout, RenderPass,
+        Sampler, ShaderCache, ShaderPathSetBuilder, Texture,
+    },
+};
+use dragonglass_world::{FogOfWar, World};
+use nalgebra_glm as glm;
+use std::{mem, sync::Arc};
+
+pub struct FogPushConstantBlock {
+    pub mvp: glm::Mat4,
+}
+
+// The visibility texture with its own descriptor set, so a replaced one can stay bound to frames in flight
+struct FogTexture {
+    texture: Texture,
+    width: u32,
+    height: u32,
+    _descriptor_pool: DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+}
+
+// Draws the fog of war as a textured plane over the scene, one texel per visibility cell
+pub struct FogRender {
+    pub pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    pub mvp: glm::Mat4,
+    texture: Option<FogTexture>,
+    // Textures replaced when the fog was resized, with the number of updates left until
+    // the frames that may still be sampling them are done
+    retired: Vec<(FogTexture, usize)>,
+    frames_in_flight: usize,
+    pixels: Vec<u8>,
+    sampler: Sampler,
+    descriptor_set_layout: Arc<DescriptorSetLayout>,
+    context: Arc<Context>,
+}
+
+impl FogRender {
+    pub fn new(context: Arc<Context>, frames_in_flight: usize) -> Result<Self> {
+        let device = context.device.clone();
+        let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(device.clone())?);
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.0)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .mip_lod_bias(0.0)
+            .min_lod(0.0)
+            .max_lod(0.0);
+        let sampler = Sampler::new(device, sampler_info)?;
+
+        Ok(Self {
+            pipeline: None,
+            pipeline_layout: None,
+            mvp: glm::Mat4::identity(),
+            texture: None,
+            retired: Vec::new(),
+            frames_in_flight,
+            pixels: Vec::new(),
+            sampler,
+            descriptor_set_layout,
+            context,
+        })
+    }
+
+    pub fn create_pipeline(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        render_pass: Arc<RenderPass>,
+        samples: vk::SampleCountFlags,
+    ) -> Result<()> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .size(mem::size_of::<FogPushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/fog/fog.vert.spv")
+            .fragment("assets/shaders/fog/fog.frag.spv")
+            .build()?;
+        let shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &shader_paths)?;
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(render_pass)
+            .vertex_inputs(Vec::new())
+            .vertex_attributes(Vec::new())
+            .descriptor_set_layout(self.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .name("fog of war")
+            .rasterization_samples(samples)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .blended(true)
+            .depth_test_enabled(true)
+            .depth_write_enabled(false)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+        self.pipeline = None;
+        self.pipeline_layout = None;
+        let (pipeline, pipeline_layout) = settings
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        self.pipeline = Some(pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    fn descriptor_set_layout(device: Arc<Device>) -> Result<DescriptorSetLayout> {
+        let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let bindings = [sampler_binding];
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        DescriptorSetLayout::new(device, create_info)
+    }
+
+    fn descriptor_pool(device: Arc<Device>) -> Result<DescriptorPool> {
+        let sampler_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        };
+
+        let pool_sizes = [sampler_pool_size];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+
+        DescriptorPool::new(device, pool_info)
+    }
+
+    fn update_descriptor_set(&self, descriptor_set: vk::DescriptorSet, texture: &Texture) {
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.view.handle)
+            .sampler(self.sampler.handle)
+            .build();
+        let image_infos = [image_info];
+
+        let sampler_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos)
+            .build();
+
+        let descriptor_writes = [sampler_descriptor_write];
+
+        unsafe {
+            self.context
+                .device
+                .handle
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+    }
+
+    // Views draw the texture the main view uploaded from their own camera
+    pub fn update_view(&mut self, world: &World, view_projection: glm::Mat4) {
+        if let Some(fog_of_war) = world.fog_of_war.as_ref() {
+            self.mvp = view_projection * overlay_model(fog_of_war);
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        world: &World,
+        command_pool: &CommandPool,
+        view_projection: glm::Mat4,
+    ) -> Result<()> {
+        for (_, updates_left) in self.retired.iter_mut() {
+            *updates_left -= 1;
+        }
+        self.retired.retain(|(_, updates_left)| *updates_left > 0);
+
+        let fog_of_war = match world.fog_of_war.as_ref() {
+            Some(fog_of_war) if fog_of_war.width > 0 && fog_of_war.depth > 0 => fog_of_war,
+            _ => {
+                self.retire_texture();
+                self.pixels.clear();
+                return Ok(());
+            }
+        };
+
+        self.mvp = view_projection * overlay_model(fog_of_war);
+
+        // The texture is only written when the visible cells change
+        let pixels = fog_of_war.overlay_pixels();
+        if self.texture.is_some() && pixels == self.pixels {
+            return Ok(());
+        }
+
+        let description = ImageDescription {
+            format: vk::Format::R8G8B8A8_UNORM,
+            width: fog_of_war.width as _,
+            height: fog_of_war.depth as _,
+            mip_levels: 1,
+            pixels,
+            mip_offsets: Vec::new(),
+        };
+
+        // The texture is updated in place and only recreated when the fog is resized
+        let resized = self.texture.as_ref().map_or(true, |texture| {
+            texture.width != description.width || texture.height != description.height
+        });
+        if resized {
+            self.retire_texture();
+            self.texture = Some(self.create_texture(command_pool, &description)?);
+        } else if let Some(texture) = self.texture.as_ref() {
+            texture
+                .texture
+                .image
+                .update_data(command_pool, &description)?;
+        }
+        self.pixels = description.pixels;
+        Ok(())
+    }
+
+    fn create_texture(
+        &self,
+        command_pool: &CommandPool,
+        description: &ImageDescription,
+    ) -> Result<FogTexture> {
+        let texture = Texture::new(&self.context, command_pool, "fog of war", description)?;
+        let descriptor_pool = Self::descriptor_pool(self.context.device.clone())?;
+        let descriptor_set = descriptor_pool.allocate_descriptor_sets(
+            self.descriptor_set_layout.handle,
+            "fog of war",
+            1,
+        )?[0];
+        self.update_descriptor_set(descriptor_set, &texture);
+        Ok(FogTexture {
+            texture,
+            width: description.width,
+            height: description.height,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set,
+        })
+    }
+
+    fn retire_texture(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            self.retired.push((texture, self.frames_in_flight + 1));
+        }
+    }
+
+    pub fn issue_commands(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        let texture = match self.texture.as_ref() {
+            Some(texture) => texture,
+            None => return Ok(()),
+        };
+
+        let (pipeline, pipeline_layout) =
+            match (self.pipeline.as_ref(), self.pipeline_layout.as_ref()) {
+                (Some(pipeline), Some(pipeline_layout)) => (pipeline, pipeline_layout),
+                _ => return Ok(()),
+            };
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        pipeline.bind(device, command_buffer);
+
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout.handle,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                byte_slice_from(&FogPushConstantBlock { mvp: self.mvp }),
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout.handle,
+                0,
+                &[texture.descriptor_set],
+                &[],
+            );
+
+            device.cmd_draw(command_buffer, 6, 1, 0, 0);
+        }
+        commands.record_draw(2, 1);
+
+        Ok(())
+    }
+}
+
+fn overlay_model(fog_of_war: &FogOfWar) -> glm::Mat4 {
+    let extents = fog_of_war.extents();
+    glm::translation(&glm::vec3(
+        fog_of_war.origin.x,
+        fog_of_war.overlay_height,
+        fog_of_war.origin.y,
+    )) * glm::scaling(&glm::vec3(extents.x, 1.0, extents.y))
+}
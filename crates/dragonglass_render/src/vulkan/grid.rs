@@ -0,0 +1,132 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_config::Grid;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        Context, DescriptorSetLayout, GraphicsPipelineSettingsBuilder, Pipeline, PipelineLayout,
+        RenderPass, ShaderCache, ShaderPathSetBuilder,
+    },
+};
+use nalgebra_glm as glm;
+use std::{mem, sync::Arc};
+
+pub struct GridPushConstantBlock {
+    pub view_projection: glm::Mat4,
+    // The camera's position, with the distance the grid fades out at in w
+    pub camera: glm::Vec4,
+    pub color: glm::Vec4,
+}
+
+// Draws the editor's ground grid over the scene, as a square beneath the camera that fades out at its edges
+pub struct GridRender {
+    pub pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    push_constants: Option<GridPushConstantBlock>,
+    // The grid is drawn from its push constants alone
+    descriptor_set_layout: Arc<DescriptorSetLayout>,
+    context: Arc<Context>,
+}
+
+impl GridRender {
+    pub fn new(context: Arc<Context>) -> Result<Self> {
+        let descriptor_set_layout = DescriptorSetLayout::new(
+            context.device.clone(),
+            vk::DescriptorSetLayoutCreateInfo::builder(),
+        )?;
+        Ok(Self {
+            pipeline: None,
+            pipeline_layout: None,
+            push_constants: None,
+            descriptor_set_layout: Arc::new(descriptor_set_layout),
+            context,
+        })
+    }
+
+    pub fn create_pipeline(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        render_pass: Arc<RenderPass>,
+        samples: vk::SampleCountFlags,
+    ) -> Result<()> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .size(mem::size_of::<GridPushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/grid/grid.vert.spv")
+            .fragment("assets/shaders/grid/grid.frag.spv")
+            .build()?;
+        let shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &shader_paths)?;
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(render_pass)
+            .vertex_inputs(Vec::new())
+            .vertex_attributes(Vec::new())
+            .descriptor_set_layout(self.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .name("grid")
+            .rasterization_samples(samples)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .blended(true)
+            .depth_test_enabled(true)
+            .depth_write_enabled(false)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+        self.pipeline = None;
+        self.pipeline_layout = None;
+        let (pipeline, pipeline_layout) = settings
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        self.pipeline = Some(pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    pub fn update(&mut self, settings: &Grid, view_projection: glm::Mat4, camera: glm::Vec3) {
+        self.push_constants = if settings.visible {
+            Some(GridPushConstantBlock {
+                view_projection,
+                camera: glm::vec4(camera.x, camera.y, camera.z, settings.fade_distance),
+                color: glm::Vec4::from(settings.color),
+            })
+        } else {
+            None
+        };
+    }
+
+    pub fn issue_commands(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        let push_constants = match self.push_constants.as_ref() {
+            Some(push_constants) => push_constants,
+            None => return Ok(()),
+        };
+
+        let (pipeline, pipeline_layout) =
+            match (self.pipeline.as_ref(), self.pipeline_layout.as_ref()) {
+                (Some(pipeline), Some(pipeline_layout)) => (pipeline, pipeline_layout),
+                _ => return Ok(()),
+            };
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        pipeline.bind(device, command_buffer);
+
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout.handle,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                byte_slice_from(push_constants),
+            );
+            device.cmd_draw(command_buffer, 6, 1, 0, 0);
+        }
+        commands.record_draw(2, 1);
+
+        Ok(())
+    }
+}
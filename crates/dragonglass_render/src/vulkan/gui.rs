@@ -2,7 +2,7 @@ use crate::byte_slice_from;
 use anyhow::Result;
 use dragonglass_gui::egui::{ClippedMesh, CtxRef};
 use dragonglass_vulkan::{
-    ash::vk::{self, Handle},
+    ash::vk,
     core::{
         CommandPool, Context, DescriptorPool, DescriptorSetLayout, Device, GeometryBuffer,
         GraphicsPipelineSettingsBuilder, ImageDescription, Pipeline, PipelineLayout, RenderPass,
@@ -42,13 +42,14 @@ impl GuiRender {
         let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(device.clone())?);
         let descriptor_pool = Self::create_descriptor_pool(device.clone())?;
         let descriptor_set =
-            descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, 1)?[0];
+            descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, "gui", 1)?[0];
 
         let vertex_buffer_size = 1024 * 1024 * 4;
         let index_buffer_size = 1024 * 1024 * 4;
         let geometry_buffer = GeometryBuffer::new(
             device,
             context.allocator.clone(),
+            "egui",
             vertex_buffer_size,
             Some(index_buffer_size),
         )?;
@@ -59,8 +60,6 @@ impl GuiRender {
             .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
             .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
             .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
-            .anisotropy_enable(true)
-            .max_anisotropy(16.0)
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false)
             .compare_enable(false)
@@ -69,7 +68,7 @@ impl GuiRender {
             .mip_lod_bias(0.0)
             .min_lod(0.0)
             .max_lod(vk::LOD_CLAMP_NONE);
-        let font_texture_sampler = Sampler::new(context.device.clone(), sampler_info)?;
+        let font_texture_sampler = Sampler::filtered(context.device.clone(), sampler_info)?;
 
         let mut gui_renderer = Self {
             descriptor_set,
@@ -138,6 +137,7 @@ impl GuiRender {
             .vertex_attributes(Self::vertex_attributes())
             .descriptor_set_layout(self.descriptor_set_layout.clone())
             .shader_set(shader_set)
+            .name("gui")
             .push_constant_range(push_constant_range)
             .cull_mode(vk::CullModeFlags::NONE)
             .blended(true)
@@ -251,13 +251,15 @@ impl GuiRender {
                 height: font_image.height as _,
                 mip_levels: 1,
                 pixels: data,
+                mip_offsets: Vec::new(),
             };
-            Texture::new(&self.context, command_pool, &font_texture_description)?
+            Texture::new(
+                &self.context,
+                command_pool,
+                "egui font",
+                &font_texture_description,
+            )?
         };
-        if let Ok(debug) = &self.context.debug() {
-            debug.name_image("egui font", font_texture.image.handle.as_raw())?;
-            debug.name_image_view("egui font view", font_texture.view.handle.as_raw())?;
-        }
         self.update_descriptor_set(&font_texture);
         self.font_texture = Some(font_texture);
         Ok(())
@@ -399,6 +401,9 @@ impl GuiRender {
                         0,
                     )
                 };
+                device
+                    .commands
+                    .record_draw((mesh.indices.len() / 3) as _, 1);
             }
 
             index_offset += mesh.indices.len() as u32;
@@ -0,0 +1,314 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_config::Outline;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        Context, GraphicsPipelineSettingsBuilder, Pipeline, PipelineLayout, RenderPass,
+        ShaderCache, ShaderPathSetBuilder,
+    },
+};
+use dragonglass_world::{legion::EntityStore, Hidden, Hovered, MeshRender, Selected, World};
+use nalgebra_glm as glm;
+use std::{mem, sync::Arc};
+
+use super::{vertex, world::WorldRender};
+
+pub struct OutlinePushConstantBlock {
+    pub color: glm::Vec4,
+    pub viewport_size: glm::Vec2,
+    // In pixels
+    pub thickness: f32,
+    pub entity_index: i32,
+}
+
+// One primitive of an outlined entity
+struct OutlineDraw {
+    entity_index: i32,
+    color: glm::Vec4,
+    first_index: u32,
+    number_of_indices: u32,
+    first_vertex: u32,
+    number_of_vertices: u32,
+}
+
+// Outlines selected and hovered entities on top of the scene.
+// Their meshes are first drawn into the stencil buffer without writing color,
+// then drawn again with their vertices pushed out along their normals in screen space.
+// The pushed out meshes are only drawn where the stencil is unmarked, which leaves just a rim around each.
+// Depth testing is disabled for both draws so outlines show through whatever is in front of them.
+pub struct OutlineRender {
+    pub mask_pipeline: Option<Pipeline>,
+    pub outline_pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    thickness: f32,
+    draws: Vec<OutlineDraw>,
+    context: Arc<Context>,
+}
+
+impl OutlineRender {
+    const STENCIL_REFERENCE: u32 = 1;
+
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            mask_pipeline: None,
+            outline_pipeline: None,
+            pipeline_layout: None,
+            thickness: 0.0,
+            draws: Vec::new(),
+            context,
+        }
+    }
+
+    // The pipelines use the world render's descriptor set layout and vertex layout,
+    // so they are recreated along with the world render
+    pub fn create_pipelines(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        offscreen_render_pass: Arc<RenderPass>,
+        samples: vk::SampleCountFlags,
+        world_render: &WorldRender,
+    ) -> Result<()> {
+        let pipeline_data = &world_render.pbr_pipeline_data;
+
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .size(mem::size_of::<OutlinePushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/outline/outline.vert.spv")
+            .fragment("assets/shaders/outline/outline.frag.spv")
+            .build()?;
+        let shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &shader_paths)?;
+
+        let mask_stencil_state = vk::StencilOpState::builder()
+            .fail_op(vk::StencilOp::REPLACE)
+            .pass_op(vk::StencilOp::REPLACE)
+            .depth_fail_op(vk::StencilOp::REPLACE)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .compare_mask(0xff)
+            .write_mask(0xff)
+            .reference(Self::STENCIL_REFERENCE)
+            .build();
+
+        let outline_stencil_state = vk::StencilOpState::builder()
+            .fail_op(vk::StencilOp::KEEP)
+            .pass_op(vk::StencilOp::KEEP)
+            .depth_fail_op(vk::StencilOp::KEEP)
+            .compare_op(vk::CompareOp::NOT_EQUAL)
+            .compare_mask(0xff)
+            .write_mask(0)
+            .reference(Self::STENCIL_REFERENCE)
+            .build();
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(offscreen_render_pass)
+            .vertex_inputs(vertex::vertex_inputs(pipeline_data.vertex_layout))
+            .vertex_attributes(vertex::vertex_attributes(pipeline_data.vertex_layout))
+            .descriptor_set_layout(pipeline_data.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .rasterization_samples(samples)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .depth_test_enabled(false)
+            .depth_write_enabled(false)
+            .stencil_test_enabled(true)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+        let mut mask_settings = settings.clone();
+        mask_settings
+            .color_write_mask(vk::ColorComponentFlags::empty())
+            .stencil_front_state(mask_stencil_state)
+            .stencil_back_state(mask_stencil_state);
+
+        let mut outline_settings = settings;
+        outline_settings
+            .stencil_front_state(outline_stencil_state)
+            .stencil_back_state(outline_stencil_state);
+
+        self.mask_pipeline = None;
+        self.outline_pipeline = None;
+        self.pipeline_layout = None;
+
+        let (mask_pipeline, pipeline_layout) = mask_settings
+            .name("outline mask")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        let (outline_pipeline, _) = outline_settings
+            .name("outline")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+
+        self.mask_pipeline = Some(mask_pipeline);
+        self.outline_pipeline = Some(outline_pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    // Gathers the primitives of every visible selected or hovered entity.
+    // Entities are indexed in the order the scene graphs are walked, like the world render's entity buffer.
+    pub fn update(&mut self, world: &World, settings: &Outline) -> Result<()> {
+        self.draws.clear();
+        self.thickness = settings.thickness.max(0.0);
+        if self.thickness == 0.0 {
+            return Ok(());
+        }
+
+        let culling_mask = world.active_camera_culling_mask();
+        let mut entity_index: i32 = -1;
+        for graph in world.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                entity_index += 1;
+                let entity = graph[node_index];
+                let entry = world.ecs.entry_ref(entity)?;
+                if entry.get_component::<Hidden>().is_ok()
+                    || !world.is_layer_visible(entity, culling_mask)
+                {
+                    return Ok(());
+                }
+
+                // Selection takes precedence when an entity is both selected and hovered
+                let color = if entry.get_component::<Selected>().is_ok() {
+                    settings.selected_color
+                } else if entry.get_component::<Hovered>().is_ok() {
+                    settings.hovered_color
+                } else {
+                    return Ok(());
+                };
+
+                let mesh = match entry.get_component::<MeshRender>() {
+                    Ok(mesh_render) => match world.assets.geometry.meshes.get(&mesh_render.name) {
+                        Some(mesh) => mesh,
+                        None => return Ok(()),
+                    },
+                    Err(_) => return Ok(()),
+                };
+
+                for primitive in mesh.primitives.iter() {
+                    self.draws.push(OutlineDraw {
+                        entity_index,
+                        color: glm::make_vec4(&color),
+                        first_index: primitive.first_index as _,
+                        number_of_indices: primitive.number_of_indices as _,
+                        first_vertex: primitive.first_vertex as _,
+                        number_of_vertices: primitive.number_of_vertices as _,
+                    });
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    // Every mask is drawn before any outline, so overlapping entities aren't outlined across each other
+    pub fn issue_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        world_render: &WorldRender,
+        viewport_size: glm::Vec2,
+    ) -> Result<()> {
+        if self.draws.is_empty() {
+            return Ok(());
+        }
+
+        let (mask_pipeline, outline_pipeline, pipeline_layout) = match (
+            self.mask_pipeline.as_ref(),
+            self.outline_pipeline.as_ref(),
+            self.pipeline_layout.as_ref(),
+        ) {
+            (Some(mask_pipeline), Some(outline_pipeline), Some(pipeline_layout)) => {
+                (mask_pipeline, outline_pipeline, pipeline_layout)
+            }
+            _ => return Ok(()),
+        };
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        let pipeline_data = &world_render.pbr_pipeline_data;
+        let has_indices = pipeline_data.geometry_buffer.index_buffer.is_some();
+
+        for pipeline in [mask_pipeline, outline_pipeline].iter() {
+            pipeline.bind(device, command_buffer);
+            unsafe {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout.handle,
+                    0,
+                    &[pipeline_data.descriptor_set()],
+                    &[],
+                );
+            }
+
+            for draw in self.draws.iter() {
+                // Geometry that is still uploading isn't drawn by the scene either
+                if !pipeline_data.is_range_drawable(
+                    (draw.first_vertex + draw.number_of_vertices) as usize,
+                    (draw.first_index + draw.number_of_indices) as usize,
+                ) {
+                    continue;
+                }
+                pipeline_data.geometry_buffer.bind(device, command_buffer)?;
+
+                // Pre-skinned vertices replace the interleaved vertex buffer
+                let vertex_offset = world_render
+                    .skinning
+                    .vertex_offset(draw.entity_index as usize);
+                if vertex_offset.is_some() {
+                    unsafe {
+                        device.cmd_bind_vertex_buffers(
+                            command_buffer,
+                            0,
+                            &[world_render.skinning.skinned_vertex_buffer()],
+                            &[0],
+                        );
+                    }
+                }
+                let vertex_offset = vertex_offset.unwrap_or_default();
+
+                let push_constants = OutlinePushConstantBlock {
+                    color: draw.color,
+                    viewport_size,
+                    thickness: self.thickness,
+                    entity_index: draw.entity_index,
+                };
+                unsafe {
+                    device.cmd_push_constants(
+                        command_buffer,
+                        pipeline_layout.handle,
+                        vk::ShaderStageFlags::ALL_GRAPHICS,
+                        0,
+                        byte_slice_from(&push_constants),
+                    );
+                    if has_indices {
+                        device.cmd_draw_indexed(
+                            command_buffer,
+                            draw.number_of_indices,
+                            1,
+                            draw.first_index,
+                            vertex_offset,
+                            0,
+                        );
+                        commands.record_draw(draw.number_of_indices / 3, 1);
+                    } else {
+                        device.cmd_draw(
+                            command_buffer,
+                            draw.number_of_vertices,
+                            1,
+                            (draw.first_vertex as i32 + vertex_offset) as _,
+                            0,
+                        );
+                        commands.record_draw(draw.number_of_vertices / 3, 1);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        Context, CpuToGpuBuffer, GraphicsPipelineSettingsBuilder, Pipeline, PipelineLayout,
+        RenderPass, ShaderCache, ShaderPathSetBuilder,
+    },
+};
+use dragonglass_world::{
+    legion::component, Hidden, IntoQuery, ParticleBlend, ParticleEmitter, World,
+};
+use nalgebra_glm as glm;
+use std::{mem, sync::Arc};
+
+use super::world::PbrPipelineData;
+
+pub struct ParticlePushConstantBlock {
+    pub view_projection: glm::Mat4,
+    // Particles are laid out along these to face the camera
+    pub camera_right: glm::Vec4,
+    pub camera_up: glm::Vec4,
+    pub texture_index: i32,
+}
+
+// One per particle. The vertex shader expands each instance into a camera facing quad.
+#[derive(Debug, Copy, Clone)]
+pub struct ParticleInstance {
+    // The particle's position in xyz and its size in w
+    pub position_size: glm::Vec4,
+    pub color: glm::Vec4,
+}
+
+// The particles of one emitter, drawn with a single instanced draw call
+struct ParticleBatch {
+    blend: ParticleBlend,
+    texture_index: i32,
+    first_instance: u32,
+    number_of_instances: u32,
+}
+
+// Draws the particles of the world's emitters into the scene after its opaque geometry.
+// Particles are depth tested against the scene but do not write depth, so they never hide each other.
+// Alpha blended emitters are drawn first, from back to front, then additive emitters in any order.
+pub struct ParticleRender {
+    pub alpha_pipeline: Option<Pipeline>,
+    pub additive_pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    pub view_projection: glm::Mat4,
+    pub view: glm::Mat4,
+    instance_buffer: CpuToGpuBuffer,
+    batches: Vec<ParticleBatch>,
+    context: Arc<Context>,
+}
+
+impl ParticleRender {
+    // The instance buffer starts out with room for this many particles and doubles in size whenever it runs out
+    pub const INITIAL_NUMBER_OF_PARTICLES: usize = 4096;
+
+    const VERTICES_PER_QUAD: u32 = 6;
+
+    pub fn new(context: Arc<Context>) -> Result<Self> {
+        let instance_buffer = Self::instance_buffer(&context, Self::INITIAL_NUMBER_OF_PARTICLES)?;
+        Ok(Self {
+            alpha_pipeline: None,
+            additive_pipeline: None,
+            pipeline_layout: None,
+            view_projection: glm::Mat4::identity(),
+            view: glm::Mat4::identity(),
+            instance_buffer,
+            batches: Vec::new(),
+            context,
+        })
+    }
+
+    // The pipelines use the world render's descriptor set layout,
+    // so they are recreated along with the world render
+    pub fn create_pipelines(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        offscreen_render_pass: Arc<RenderPass>,
+        samples: vk::SampleCountFlags,
+        pipeline_data: &PbrPipelineData,
+    ) -> Result<()> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .size(mem::size_of::<ParticlePushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/particles/particles.vert.spv")
+            .fragment("assets/shaders/particles/particles.frag.spv")
+            .build()?;
+        let shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &shader_paths)?;
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(offscreen_render_pass)
+            .vertex_inputs(Self::vertex_inputs())
+            .vertex_attributes(Self::vertex_attributes())
+            .descriptor_set_layout(pipeline_data.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .rasterization_samples(samples)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .blended(true)
+            .depth_test_enabled(true)
+            .depth_write_enabled(false)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+            .specialization_constants(vec![pipeline_data.texture_capacity as u32]);
+
+        let mut additive_settings = settings.clone();
+        additive_settings.blended_dst_color_blend_factor(vk::BlendFactor::ONE);
+
+        self.alpha_pipeline = None;
+        self.additive_pipeline = None;
+        self.pipeline_layout = None;
+
+        let (alpha_pipeline, pipeline_layout) = settings
+            .name("particles alpha")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        let (additive_pipeline, _) = additive_settings
+            .name("particles additive")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+
+        self.alpha_pipeline = Some(alpha_pipeline);
+        self.additive_pipeline = Some(additive_pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    fn vertex_attributes() -> [vk::VertexInputAttributeDescription; 2] {
+        let float_size = std::mem::size_of::<f32>();
+
+        let position_size = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset(0)
+            .build();
+
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((4 * float_size) as _)
+            .build();
+
+        [position_size, color]
+    }
+
+    fn vertex_inputs() -> [vk::VertexInputBindingDescription; 1] {
+        let instance_input_binding_description = vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(mem::size_of::<ParticleInstance>() as _)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build();
+        [instance_input_binding_description]
+    }
+
+    fn instance_buffer(context: &Context, number_of_particles: usize) -> Result<CpuToGpuBuffer> {
+        CpuToGpuBuffer::vertex_buffer(
+            context.device.clone(),
+            context.allocator.clone(),
+            "particle instances",
+            (number_of_particles * mem::size_of::<ParticleInstance>()) as _,
+        )
+    }
+
+    // Gathers the live particles of every visible emitter into the instance buffer
+    pub fn update(&mut self, world: &World, view: glm::Mat4, projection: glm::Mat4) -> Result<()> {
+        self.view = view;
+        self.view_projection = projection * view;
+        self.batches.clear();
+
+        let number_of_textures = world.assets.textures.len() as i32;
+        let mut query = <&ParticleEmitter>::query().filter(!component::<Hidden>());
+        let emitters = query
+            .iter(&world.ecs)
+            .filter(|emitter| !emitter.particles().is_empty())
+            .collect::<Vec<_>>();
+        if emitters.is_empty() {
+            return Ok(());
+        }
+
+        // The distance along the view direction, which is negative z in view space
+        let depth =
+            |position: &glm::Vec3| -(view * glm::vec4(position.x, position.y, position.z, 1.0)).z;
+        let emitter_depth = |emitter: &ParticleEmitter| {
+            let count = emitter.particles().len() as f32;
+            let center = emitter
+                .particles()
+                .iter()
+                .fold(glm::Vec3::zeros(), |sum, particle| sum + particle.position)
+                / count;
+            depth(&center)
+        };
+
+        // Alpha blended emitters come first and farthest first, so nearer particles are blended over farther ones
+        let mut keyed = emitters
+            .into_iter()
+            .map(|emitter| {
+                (
+                    emitter.blend == ParticleBlend::Additive,
+                    emitter_depth(emitter),
+                    emitter,
+                )
+            })
+            .collect::<Vec<_>>();
+        keyed.sort_by(|(additive, depth, _), (other_additive, other_depth, _)| {
+            additive.cmp(other_additive).then(
+                other_depth
+                    .partial_cmp(depth)
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+        });
+
+        let mut instances = Vec::new();
+        for (_, _, emitter) in keyed.into_iter() {
+            let first_instance = instances.len();
+            for particle in emitter.particles().iter() {
+                let size = emitter.size(particle);
+                instances.push(ParticleInstance {
+                    position_size: glm::vec4(
+                        particle.position.x,
+                        particle.position.y,
+                        particle.position.z,
+                        size,
+                    ),
+                    color: emitter.color(particle),
+                });
+            }
+            if emitter.blend == ParticleBlend::Alpha {
+                instances[first_instance..].sort_by(|lhs, rhs| {
+                    let lhs_depth = depth(&lhs.position_size.xyz());
+                    let rhs_depth = depth(&rhs.position_size.xyz());
+                    rhs_depth
+                        .partial_cmp(&lhs_depth)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
+            let texture_index = if emitter.texture_index < number_of_textures {
+                emitter.texture_index.max(-1)
+            } else {
+                -1
+            };
+            self.batches.push(ParticleBatch {
+                blend: emitter.blend,
+                texture_index,
+                first_instance: first_instance as u32,
+                number_of_instances: (instances.len() - first_instance) as u32,
+            });
+        }
+
+        let capacity = self.instance_buffer.size() as usize / mem::size_of::<ParticleInstance>();
+        if instances.len() > capacity {
+            // Frames in flight may still be reading the buffer being replaced
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.instance_buffer =
+                Self::instance_buffer(&self.context, instances.len().next_power_of_two())?;
+        }
+        self.instance_buffer.upload_data(&instances, 0)?;
+
+        Ok(())
+    }
+
+    pub fn issue_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline_data: &PbrPipelineData,
+    ) -> Result<()> {
+        if self.batches.is_empty() {
+            return Ok(());
+        }
+
+        let (alpha_pipeline, additive_pipeline, pipeline_layout) = match (
+            self.alpha_pipeline.as_ref(),
+            self.additive_pipeline.as_ref(),
+            self.pipeline_layout.as_ref(),
+        ) {
+            (Some(alpha_pipeline), Some(additive_pipeline), Some(pipeline_layout)) => {
+                (alpha_pipeline, additive_pipeline, pipeline_layout)
+            }
+            _ => return Ok(()),
+        };
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        let mut bound_blend = None;
+        for batch in self.batches.iter() {
+            if bound_blend != Some(batch.blend) {
+                match batch.blend {
+                    ParticleBlend::Alpha => alpha_pipeline.bind(device, command_buffer),
+                    ParticleBlend::Additive => additive_pipeline.bind(device, command_buffer),
+                }
+                if bound_blend.is_none() {
+                    unsafe {
+                        device.cmd_bind_descriptor_sets(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline_layout.handle,
+                            0,
+                            &[pipeline_data.descriptor_set()],
+                            &[],
+                        );
+                        device.cmd_bind_vertex_buffers(
+                            command_buffer,
+                            0,
+                            &[self.instance_buffer.handle()],
+                            &[0],
+                        );
+                    }
+                }
+                bound_blend = Some(batch.blend);
+            }
+
+            let push_constants = ParticlePushConstantBlock {
+                view_projection: self.view_projection,
+                camera_right: glm::row(&self.view, 0).transpose(),
+                camera_up: glm::row(&self.view, 1).transpose(),
+                texture_index: batch.texture_index,
+            };
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    pipeline_layout.handle,
+                    vk::ShaderStageFlags::ALL_GRAPHICS,
+                    0,
+                    byte_slice_from(&push_constants),
+                );
+                device.cmd_draw(
+                    command_buffer,
+                    Self::VERTICES_PER_QUAD,
+                    batch.number_of_instances,
+                    0,
+                    batch.first_instance,
+                );
+            }
+            commands.record_draw(2, batch.number_of_instances);
+        }
+
+        Ok(())
+    }
+}
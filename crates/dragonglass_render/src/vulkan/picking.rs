@@ -0,0 +1,424 @@
+use crate::{byte_slice_from, entity_at_index};
+use anyhow::Result;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        transition_image, CommandPool, Context, GpuToCpuBuffer, GraphicsPipelineSettingsBuilder,
+        Image, ImageLayoutTransitionBuilder, ImageNode, ImageToBufferCopyBuilder, Pipeline,
+        PipelineLayout, RenderGraph, ShaderCache, ShaderPathSetBuilder,
+    },
+};
+use dragonglass_world::{
+    legion::EntityStore, Camera, Entity, Hidden, Light, MeshRender, Viewport, World,
+};
+use nalgebra_glm as glm;
+use std::{mem, sync::Arc};
+
+use super::{vertex, world::WorldRender};
+
+pub struct PickingPushConstantBlock {
+    pub viewport_size: glm::Vec2,
+    // The size in pixels of the quads drawn for lights and cameras
+    pub proxy_size: f32,
+    pub entity_index: i32,
+}
+
+// Renders the index of the entity covering each pixel, offset by one so zero means nothing was drawn.
+// Only the pixel under the cursor is needed, so the target is a single pixel
+// and the viewport is shifted to put the picked pixel on it.
+// Lights and cameras have no geometry, so they are drawn as small screen facing quads instead.
+pub struct PickingRender {
+    pub mesh_pipeline: Option<Pipeline>,
+    pub proxy_pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    rendergraph: RenderGraph,
+    readback_buffer: GpuToCpuBuffer,
+    context: Arc<Context>,
+}
+
+impl PickingRender {
+    pub const PROXY_SIZE: f32 = 24.0;
+
+    const PASS: &'static str = "picking";
+    const ID: &'static str = "entity_id";
+
+    // Vulkan guarantees viewports at least this large
+    const MAX_VIEWPORT_SIZE: f32 = 4096.0;
+
+    const VERTICES_PER_QUAD: u32 = 6;
+
+    pub fn new(context: Arc<Context>) -> Result<Self> {
+        let extent = vk::Extent2D::builder().width(1).height(1).build();
+        let mut rendergraph = RenderGraph::new(
+            &[Self::PASS],
+            vec![
+                ImageNode {
+                    name: Self::ID.to_string(),
+                    extent,
+                    format: vk::Format::R32_SINT,
+                    clear_value: vk::ClearValue {
+                        color: vk::ClearColorValue { int32: [0; 4] },
+                    },
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    force_store: true,
+                    force_shader_read: false,
+                },
+                ImageNode {
+                    name: RenderGraph::DEPTH_STENCIL.to_owned(),
+                    extent,
+                    format: vk::Format::D24_UNORM_S8_UINT,
+                    clear_value: vk::ClearValue {
+                        depth_stencil: vk::ClearDepthStencilValue {
+                            depth: 1.0,
+                            stencil: 0,
+                        },
+                    },
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    force_store: false,
+                    force_shader_read: false,
+                },
+            ],
+            &[
+                (Self::PASS, Self::ID),
+                (Self::PASS, RenderGraph::DEPTH_STENCIL),
+            ],
+        )?;
+        rendergraph.build(context.device.clone(), context.allocator.clone())?;
+
+        let readback_buffer = GpuToCpuBuffer::readback_buffer(
+            context.device.clone(),
+            context.allocator.clone(),
+            "picking readback",
+            mem::size_of::<i32>() as _,
+        )?;
+
+        Ok(Self {
+            mesh_pipeline: None,
+            proxy_pipeline: None,
+            pipeline_layout: None,
+            rendergraph,
+            readback_buffer,
+            context,
+        })
+    }
+
+    // The pipelines use the world render's descriptor set layout and vertex layout,
+    // so they are recreated along with the world render
+    pub fn create_pipelines(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        world_render: &WorldRender,
+    ) -> Result<()> {
+        let pipeline_data = &world_render.pbr_pipeline_data;
+
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .size(mem::size_of::<PickingPushConstantBlock>() as u32)
+            .build();
+
+        let mesh_shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/picking/picking.vert.spv")
+            .fragment("assets/shaders/picking/picking.frag.spv")
+            .build()?;
+        let mesh_shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &mesh_shader_paths)?;
+
+        let proxy_shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/picking/picking_proxy.vert.spv")
+            .fragment("assets/shaders/picking/picking.frag.spv")
+            .build()?;
+        let proxy_shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &proxy_shader_paths)?;
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(self.rendergraph.pass_handle(Self::PASS)?)
+            .vertex_inputs(vertex::vertex_inputs(pipeline_data.vertex_layout))
+            .vertex_attributes(vertex::vertex_attributes(pipeline_data.vertex_layout))
+            .descriptor_set_layout(pipeline_data.descriptor_set_layout.clone())
+            .shader_set(mesh_shader_set)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+        let mut proxy_settings = settings.clone();
+        proxy_settings
+            .vertex_inputs(Vec::new())
+            .vertex_attributes(Vec::new())
+            .shader_set(proxy_shader_set)
+            .cull_mode(vk::CullModeFlags::NONE);
+
+        self.mesh_pipeline = None;
+        self.proxy_pipeline = None;
+        self.pipeline_layout = None;
+
+        let (mesh_pipeline, pipeline_layout) = settings
+            .name("picking mesh")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        let (proxy_pipeline, _) = proxy_settings
+            .name("picking proxy")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+
+        self.mesh_pipeline = Some(mesh_pipeline);
+        self.proxy_pipeline = Some(proxy_pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    // Renders the world as it was last uploaded and reads back the entity under the given window position.
+    // No frames may be in flight, since the world's uniform and entity buffers are reused.
+    pub fn pick(
+        &self,
+        command_pool: &CommandPool,
+        world: &World,
+        world_render: &WorldRender,
+        viewport: Viewport,
+        (x, y): (f32, f32),
+    ) -> Result<Option<Entity>> {
+        let x = x - viewport.x;
+        let y = y - viewport.y;
+        if x < 0.0 || y < 0.0 || x >= viewport.width || y >= viewport.height {
+            return Ok(None);
+        }
+
+        let (mesh_pipeline, proxy_pipeline, pipeline_layout) = match (
+            self.mesh_pipeline.as_ref(),
+            self.proxy_pipeline.as_ref(),
+            self.pipeline_layout.as_ref(),
+        ) {
+            (Some(mesh_pipeline), Some(proxy_pipeline), Some(pipeline_layout)) => {
+                (mesh_pipeline, proxy_pipeline, pipeline_layout)
+            }
+            _ => return Ok(None),
+        };
+
+        // Large viewports are scaled down to the size every device supports
+        let scale = (Self::MAX_VIEWPORT_SIZE / viewport.width.max(viewport.height)).min(1.0);
+        let width = viewport.width * scale;
+        let height = viewport.height * scale;
+        let pixel = glm::vec2((x * scale).floor(), (y * scale).floor());
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        let pipeline_data = &world_render.pbr_pipeline_data;
+        let has_indices = pipeline_data.geometry_buffer.index_buffer.is_some();
+        let mut push_constants = PickingPushConstantBlock {
+            viewport_size: glm::vec2(width, height),
+            proxy_size: Self::PROXY_SIZE * scale,
+            entity_index: 0,
+        };
+
+        let draws = Self::draws(world)?;
+        command_pool.execute_once(|command_buffer| {
+            self.rendergraph
+                .execute_pass(command_buffer, Self::PASS, 0, |pass, command_buffer| {
+                    // Flipped like the offscreen pass, then shifted so the picked pixel lands on the target
+                    let picking_viewport = vk::Viewport::builder()
+                        .x(-pixel.x)
+                        .y(height - pixel.y)
+                        .width(width)
+                        .height(-height)
+                        .max_depth(1.0)
+                        .build();
+                    let scissor = vk::Rect2D::builder().extent(pass.extent).build();
+                    unsafe {
+                        device.cmd_set_viewport(command_buffer, 0, &[picking_viewport]);
+                        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                    }
+
+                    for draw in draws.iter() {
+                        push_constants.entity_index = draw.entity_index;
+                        let mesh_name = match draw.mesh_name.as_ref() {
+                            Some(mesh_name) => mesh_name,
+                            None => {
+                                proxy_pipeline.bind(device, command_buffer);
+                                Self::bind(command_buffer, device, pipeline_layout, world_render);
+                                Self::push(
+                                    command_buffer,
+                                    device,
+                                    pipeline_layout,
+                                    &push_constants,
+                                );
+                                unsafe {
+                                    device.cmd_draw(
+                                        command_buffer,
+                                        Self::VERTICES_PER_QUAD,
+                                        1,
+                                        0,
+                                        0,
+                                    );
+                                }
+                                commands.record_draw(2, 1);
+                                continue;
+                            }
+                        };
+                        let mesh = match world.assets.geometry.meshes.get(mesh_name) {
+                            Some(mesh) => mesh,
+                            None => continue,
+                        };
+
+                        mesh_pipeline.bind(device, command_buffer);
+                        Self::bind(command_buffer, device, pipeline_layout, world_render);
+                        pipeline_data.geometry_buffer.bind(device, command_buffer)?;
+
+                        // Pre-skinned vertices replace the interleaved vertex buffer
+                        let vertex_offset = world_render
+                            .skinning
+                            .vertex_offset(draw.entity_index as usize);
+                        if vertex_offset.is_some() {
+                            unsafe {
+                                device.cmd_bind_vertex_buffers(
+                                    command_buffer,
+                                    0,
+                                    &[world_render.skinning.skinned_vertex_buffer()],
+                                    &[0],
+                                );
+                            }
+                        }
+                        let vertex_offset = vertex_offset.unwrap_or_default();
+
+                        Self::push(command_buffer, device, pipeline_layout, &push_constants);
+                        for primitive in mesh.primitives.iter() {
+                            if !pipeline_data.is_drawable(primitive) {
+                                continue;
+                            }
+                            unsafe {
+                                if has_indices {
+                                    device.cmd_draw_indexed(
+                                        command_buffer,
+                                        primitive.number_of_indices as _,
+                                        1,
+                                        primitive.first_index as _,
+                                        vertex_offset,
+                                        0,
+                                    );
+                                } else {
+                                    device.cmd_draw(
+                                        command_buffer,
+                                        primitive.number_of_vertices as _,
+                                        1,
+                                        (primitive.first_vertex as i32 + vertex_offset) as _,
+                                        0,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+        })?;
+
+        let image = self.rendergraph.image(Self::ID)?.handle();
+        let transition = ImageLayoutTransitionBuilder::default()
+            .base_mip_level(0)
+            .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .build()?;
+        transition_image(image, command_pool, &transition)?;
+
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1)
+            .build();
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(subresource)
+            .image_extent(vk::Extent3D::builder().width(1).height(1).depth(1).build())
+            .build();
+        let copy_info = ImageToBufferCopyBuilder::default()
+            .source(image)
+            .destination(self.readback_buffer.handle())
+            .regions(vec![region])
+            .build()?;
+        command_pool.copy_image_to_buffer(&copy_info)?;
+
+        let bytes = self.readback_buffer.download_data(mem::size_of::<i32>())?;
+        let id = i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if id <= 0 {
+            return Ok(None);
+        }
+        entity_at_index(world, (id - 1) as usize)
+    }
+
+    fn bind(
+        command_buffer: vk::CommandBuffer,
+        device: &dragonglass_vulkan::ash::Device,
+        pipeline_layout: &PipelineLayout,
+        world_render: &WorldRender,
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout.handle,
+                0,
+                &[world_render.pbr_pipeline_data.descriptor_set()],
+                &[],
+            );
+        }
+    }
+
+    fn push(
+        command_buffer: vk::CommandBuffer,
+        device: &dragonglass_vulkan::ash::Device,
+        pipeline_layout: &PipelineLayout,
+        push_constants: &PickingPushConstantBlock,
+    ) {
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout.handle,
+                vk::ShaderStageFlags::ALL_GRAPHICS,
+                0,
+                byte_slice_from(push_constants),
+            );
+        }
+    }
+
+    // The visible entities that can be picked, indexed in the order the scene graphs are walked
+    fn draws(world: &World) -> Result<Vec<PickingDraw>> {
+        let active_camera = world.active_camera().ok();
+        let culling_mask = world.active_camera_culling_mask();
+        let mut draws = Vec::new();
+        let mut entity_index: i32 = -1;
+        for graph in world.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                entity_index += 1;
+                let entity = graph[node_index];
+                let entry = world.ecs.entry_ref(entity)?;
+                if entry.get_component::<Hidden>().is_ok()
+                    || Some(entity) == active_camera
+                    || !world.is_layer_visible(entity, culling_mask)
+                {
+                    return Ok(());
+                }
+
+                let mesh_name = entry
+                    .get_component::<MeshRender>()
+                    .ok()
+                    .map(|mesh_render| mesh_render.name.to_string());
+                let is_proxy = entry.get_component::<Light>().is_ok()
+                    || entry.get_component::<Camera>().is_ok();
+                if mesh_name.is_some() || is_proxy {
+                    draws.push(PickingDraw {
+                        entity_index,
+                        mesh_name,
+                    });
+                }
+                Ok(())
+            })?;
+        }
+        Ok(draws)
+    }
+}
+
+struct PickingDraw {
+    entity_index: i32,
+    // Entities without a mesh are drawn as proxy quads
+    mesh_name: Option<String>,
+}
@@ -0,0 +1,104 @@
+use crate::uniforms::ReflectionProbeData;
+use dragonglass_vulkan::pbr::EnvironmentMapSet;
+use dragonglass_world::{
+    legion::component, Entity, EntityStore, Hidden, IntoQuery, ProbeBounds, ReflectionProbe, World,
+};
+use log::warn;
+use nalgebra_glm as glm;
+
+use super::world::PbrPipelineData;
+
+struct BakedProbe {
+    entity: Entity,
+    environment_maps: EnvironmentMapSet,
+}
+
+// The environment maps captured for each reflection probe when the probes were last baked.
+// Each probe's maps stay in the same descriptor slot until the next bake,
+// so probes that were removed or hidden since are disabled rather than moved.
+#[derive(Default)]
+pub struct ReflectionProbes {
+    baked: Vec<BakedProbe>,
+}
+
+impl ReflectionProbes {
+    // The visible probes in the world, up to the number the shader has slots for
+    pub fn probes_to_bake(world: &World) -> Vec<(Entity, ReflectionProbe)> {
+        let mut query = <(Entity, &ReflectionProbe)>::query().filter(!component::<Hidden>());
+        let probes = query
+            .iter(&world.ecs)
+            .map(|(entity, probe)| (*entity, *probe))
+            .collect::<Vec<_>>();
+        if probes.len() > PbrPipelineData::MAX_NUMBER_OF_REFLECTION_PROBES {
+            warn!(
+                "Only the first {} of {} reflection probes will be baked",
+                PbrPipelineData::MAX_NUMBER_OF_REFLECTION_PROBES,
+                probes.len()
+            );
+        }
+        probes
+            .into_iter()
+            .take(PbrPipelineData::MAX_NUMBER_OF_REFLECTION_PROBES)
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.baked.clear();
+    }
+
+    pub fn push(&mut self, entity: Entity, environment_maps: EnvironmentMapSet) {
+        self.baked.push(BakedProbe {
+            entity,
+            environment_maps,
+        });
+    }
+
+    // Ordered by descriptor slot
+    pub fn environment_maps(&self) -> Vec<&EnvironmentMapSet> {
+        self.baked
+            .iter()
+            .map(|probe| &probe.environment_maps)
+            .collect()
+    }
+
+    // The probes follow their entities, so they are uploaded every frame
+    pub fn uniform_data(
+        &self,
+        world: &World,
+    ) -> (
+        i32,
+        [ReflectionProbeData; PbrPipelineData::MAX_NUMBER_OF_REFLECTION_PROBES],
+    ) {
+        let mut data =
+            [ReflectionProbeData::default(); PbrPipelineData::MAX_NUMBER_OF_REFLECTION_PROBES];
+        for (slot, probe) in data.iter_mut().zip(self.baked.iter()) {
+            *slot = Self::probe_data(world, probe.entity).unwrap_or(ReflectionProbeData {
+                kind: -1,
+                ..Default::default()
+            });
+        }
+        (self.baked.len() as i32, data)
+    }
+
+    // Box bounds stay axis aligned, and are only scaled by the probe's transform
+    fn probe_data(world: &World, entity: Entity) -> Option<ReflectionProbeData> {
+        let entry = world.ecs.entry_ref(entity).ok()?;
+        if entry.get_component::<Hidden>().is_ok() {
+            return None;
+        }
+        let probe = entry.get_component::<ReflectionProbe>().ok()?;
+        let transform = world.entity_global_transform(entity).ok()?;
+        let (kind, extents) = match probe.bounds {
+            ProbeBounds::Box { half_extents } => (0, half_extents.component_mul(&transform.scale)),
+            ProbeBounds::Sphere { radius } => {
+                (1, glm::vec3(radius * transform.scale.max(), 0.0, 0.0))
+            }
+        };
+        Some(ReflectionProbeData {
+            position: transform.translation,
+            kind,
+            extents,
+            blend_distance: probe.blend_distance.max(0.0),
+        })
+    }
+}
@@ -1,82 +1,160 @@
-use crate::vulkan::world::WorldRender;
-use anyhow::Result;
-use dragonglass_config::Config;
+use crate::{
+    uniforms::{skybox_projection, Light, WorldUniformBuffer},
+    vulkan::world::{TextureResidency, WorldRender},
+    CubemapCapture,
+};
+use anyhow::{Context as _, Result};
+use dragonglass_config::{Config, VertexLayout};
 use dragonglass_gui::egui::{ClippedMesh, CtxRef};
 use dragonglass_vulkan::{
     ash::vk::{self, CommandBuffer},
     core::{
-        CommandPool, Context, Device, Image, ImageNode, RawImage, RenderGraph, ShaderCache,
-        ShaderPathSetBuilder, Swapchain, SwapchainProperties,
+        transition_image, BlitImageBuilder, CommandPool, CommandStats, Context, Device, GpuTimer,
+        GpuToCpuBuffer, Image, ImageLayoutTransitionBuilder, ImageNode, ImageToBufferCopyBuilder,
+        RawImage, RenderGraph, SamplerSettings, ShaderCache, ShaderPathSetBuilder, Swapchain,
+        SwapchainProperties, UploadQueue,
     },
-    pbr::EnvironmentMapSet,
+    pbr::{Brdflut, EnvironmentMapCache, EnvironmentMapSet},
     render::{FullscreenRender, FullscreenUniformBuffer, SkyboxRender},
 };
-use dragonglass_world::{Camera, EntityStore, PerspectiveCamera, Viewport, World};
+use dragonglass_world::{Entity, Layers, PerspectiveCamera, Viewport, World};
+use image::RgbaImage;
+use log::warn;
 use nalgebra_glm as glm;
-use std::sync::Arc;
+use std::{collections::HashMap, ops::Range, sync::Arc};
 
 use super::{
-    gui::GuiRender,
-    world::{Light, PbrPipelineData, WorldUniformBuffer},
+    fog::FogRender, grid::GridRender, gui::GuiRender, outline::OutlineRender,
+    particles::ParticleRender, picking::PickingRender, probes::ReflectionProbes,
+    sprite::SpriteRender, text::TextRender, world::PbrPipelineData,
 };
 
 pub struct Scene {
-    pub environment_maps: EnvironmentMapSet,
+    // Keyed by the cache key of the hdr each set was created from
+    pub environments: HashMap<u64, EnvironmentMapSet>,
+    pub environment_cache: EnvironmentMapCache,
+    brdflut: Arc<Brdflut>,
+    // Bound when the world has no skybox, which is then lit by its ambient light instead
+    empty_environment: EnvironmentMapSet,
+    active_environment: Option<u64>,
+    // The keys of the world's hdr textures, by index, that have been loaded so far
+    environment_keys: HashMap<usize, u64>,
+    // The skybox and faded skybox the descriptor sets were last written for
+    bound_skyboxes: Option<(Option<usize>, Option<usize>)>,
+    pub reflection_probes: ReflectionProbes,
     pub world_render: Option<WorldRender>,
     pub skybox_render: SkyboxRender,
     pub gui_render: GuiRender,
+    pub fog_render: FogRender,
+    pub grid_render: GridRender,
+    pub text_render: TextRender,
+    pub sprite_render: SpriteRender,
+    pub particle_render: ParticleRender,
+    pub outline_render: OutlineRender,
+    pub picking_render: PickingRender,
     pub fullscreen_pipeline: Option<FullscreenRender>,
     pub rendergraph: RenderGraph,
     pub transient_command_pool: CommandPool,
+    // Textures and geometry are streamed in through this without blocking frames
+    pub uploads: UploadQueue,
     pub shader_cache: ShaderCache,
     pub samples: vk::SampleCountFlags,
+    // How the world's vertices are laid out in its geometry buffer
+    pub vertex_layout: VertexLayout,
+    pub gpu_timer: GpuTimer,
+    // The commands recorded for the most recent frame
+    pub command_stats: CommandStats,
     context: Arc<Context>,
 }
 
 impl Scene {
+    pub const OFFSCREEN_SIZE: u32 = 2048;
+    pub const ENVIRONMENT_CACHE_DIRECTORY: &'static str = "cache/environment_maps";
+
+    // The most samples the device supports, limited to the largest power of two within the requested maximum
+    fn sample_count(context: &Context, max_samples: Option<u32>) -> vk::SampleCountFlags {
+        let supported = context.max_usable_samples();
+        match max_samples {
+            Some(max_samples) => {
+                let requested = 1 << (31 - max_samples.max(1).leading_zeros());
+                vk::SampleCountFlags::from_raw(requested.min(supported.as_raw()))
+            }
+            None => supported,
+        }
+    }
+
     pub fn new(
         context: Arc<Context>,
         swapchain: &Swapchain,
         swapchain_properties: &SwapchainProperties,
+        frames_in_flight: usize,
+        max_samples: Option<u32>,
     ) -> Result<Self> {
         let transient_command_pool = Self::transient_command_pool(
             context.device.clone(),
             context.graphics_queue(),
             context.physical_device.graphics_queue_family_index,
         )?;
-        let samples = context.max_usable_samples();
+        let uploads = UploadQueue::new(&context)?;
+        let samples = Self::sample_count(&context, max_samples);
         let rendergraph =
             Self::create_rendergraph(&context, swapchain, swapchain_properties, samples)?;
         let mut shader_cache = ShaderCache::default();
 
-        let default_hdr_texture =
-            dragonglass_world::Texture::from_hdr("assets/skyboxes/desert.hdr")?;
-        let environment_maps = EnvironmentMapSet::new(
+        let environment_cache = EnvironmentMapCache::new(Self::ENVIRONMENT_CACHE_DIRECTORY);
+        let brdflut = Arc::new(Brdflut::new(
             &context,
             &transient_command_pool,
             &mut shader_cache,
-            &default_hdr_texture,
-        )?;
+        )?);
+        let empty_environment =
+            EnvironmentMapSet::empty(&context, &transient_command_pool, brdflut.clone())?;
 
         let skybox_render = SkyboxRender::new(
             &context,
             &transient_command_pool,
-            &environment_maps.prefilter,
+            &empty_environment.prefilter,
         )?;
 
         let fullscreen_pass = rendergraph.pass_handle("fullscreen")?;
         let gui_render = GuiRender::new(context.clone(), &mut shader_cache, fullscreen_pass)?;
+        let fog_render = FogRender::new(context.clone(), frames_in_flight)?;
+        let grid_render = GridRender::new(context.clone())?;
+        let text_render = TextRender::new(context.clone())?;
+        let sprite_render = SpriteRender::new(context.clone())?;
+        let particle_render = ParticleRender::new(context.clone())?;
+        let outline_render = OutlineRender::new(context.clone());
+        let picking_render = PickingRender::new(context.clone())?;
+        let gpu_timer = GpuTimer::new(&context, frames_in_flight)?;
 
         let mut scene = Self {
-            environment_maps,
+            environments: HashMap::new(),
+            environment_cache,
+            brdflut,
+            empty_environment,
+            active_environment: None,
+            environment_keys: HashMap::new(),
+            bound_skyboxes: None,
+            reflection_probes: ReflectionProbes::default(),
             world_render: None,
             skybox_render,
             gui_render,
+            fog_render,
+            grid_render,
+            text_render,
+            sprite_render,
+            particle_render,
+            outline_render,
+            picking_render,
             fullscreen_pipeline: None,
             rendergraph,
             transient_command_pool,
+            uploads,
             shader_cache,
             samples,
+            vertex_layout: VertexLayout::default(),
+            gpu_timer,
+            command_stats: CommandStats::default(),
             context,
         };
         scene.create_pipelines()?;
@@ -103,7 +181,7 @@ impl Scene {
         self.fullscreen_pipeline = Some(fullscreen_pipeline);
 
         self.gui_render
-            .create_pipeline(&mut self.shader_cache, fullscreen_pass)?;
+            .create_pipeline(&mut self.shader_cache, fullscreen_pass.clone())?;
 
         let offscreen_renderpass = self.rendergraph.pass_handle("offscreen")?;
         self.skybox_render.create_pipeline(
@@ -112,6 +190,25 @@ impl Scene {
             self.samples,
         )?;
 
+        self.fog_render.create_pipeline(
+            &mut self.shader_cache,
+            offscreen_renderpass.clone(),
+            self.samples,
+        )?;
+
+        self.grid_render.create_pipeline(
+            &mut self.shader_cache,
+            offscreen_renderpass.clone(),
+            self.samples,
+        )?;
+
+        self.text_render.create_pipelines(
+            &mut self.shader_cache,
+            offscreen_renderpass.clone(),
+            fullscreen_pass,
+            self.samples,
+        )?;
+
         if let Some(world_render) = self.world_render.as_mut() {
             world_render.create_pipeline(
                 &mut self.shader_cache,
@@ -120,9 +217,41 @@ impl Scene {
             )?;
         }
 
+        self.create_sprite_pipelines()?;
+
         Ok(())
     }
 
+    // Sprites, particles, outlines, and picking ids are drawn with the world render's resources,
+    // so their pipelines are created along with it
+    fn create_sprite_pipelines(&mut self) -> Result<()> {
+        let world_render = match self.world_render.as_ref() {
+            Some(world_render) => world_render,
+            None => return Ok(()),
+        };
+        self.sprite_render.create_pipelines(
+            &mut self.shader_cache,
+            self.rendergraph.pass_handle("offscreen")?,
+            self.rendergraph.pass_handle("fullscreen")?,
+            self.samples,
+            &world_render.pbr_pipeline_data,
+        )?;
+        self.particle_render.create_pipelines(
+            &mut self.shader_cache,
+            self.rendergraph.pass_handle("offscreen")?,
+            self.samples,
+            &world_render.pbr_pipeline_data,
+        )?;
+        self.outline_render.create_pipelines(
+            &mut self.shader_cache,
+            self.rendergraph.pass_handle("offscreen")?,
+            self.samples,
+            world_render,
+        )?;
+        self.picking_render
+            .create_pipelines(&mut self.shader_cache, world_render)
+    }
+
     fn transient_command_pool(
         device: Arc<Device>,
         queue: vk::Queue,
@@ -131,7 +260,7 @@ impl Scene {
         let create_info = vk::CommandPoolCreateInfo::builder()
             .queue_family_index(queue_index)
             .flags(vk::CommandPoolCreateFlags::TRANSIENT);
-        let command_pool = CommandPool::new(device, queue, create_info)?;
+        let command_pool = CommandPool::new(device, queue, "transient", create_info)?;
         Ok(command_pool)
     }
 
@@ -148,7 +277,10 @@ impl Scene {
         let fullscreen = "fullscreen";
         let color = "color";
         let color_resolve = "color_resolve";
-        let offscreen_extent = vk::Extent2D::builder().width(2048).height(2048).build();
+        let offscreen_extent = vk::Extent2D::builder()
+            .width(Self::OFFSCREEN_SIZE)
+            .height(Self::OFFSCREEN_SIZE)
+            .build();
         let mut rendergraph = RenderGraph::new(
             &[offscreen, fullscreen],
             vec![
@@ -230,40 +362,288 @@ impl Scene {
     }
 
     pub fn load_world(&mut self, world: &World) -> Result<()> {
-        world
-            .scene
-            .skybox
-            .as_ref()
-            .and_then(|index| world.hdr_textures.get(*index))
-            .and_then(|texture| {
-                self.environment_maps = EnvironmentMapSet::new(
-                    &self.context,
-                    &self.transient_command_pool,
-                    &mut self.shader_cache,
-                    texture,
-                )
-                .ok()?;
-                self.skybox_render.update_descriptor_set(
-                    self.context.device.clone(),
-                    &self.environment_maps.prefilter,
-                );
-                Some(())
-            });
+        // The previous world's textures and geometry are dropped below
+        self.uploads.wait()?;
 
+        // The world's hdr textures may have changed since they were last loaded
+        self.environment_keys.clear();
         self.world_render = None;
+        self.update_environment(world);
+
+        // Only the environments the world is using are kept
+        let keys = self.environment_keys.values().copied().collect::<Vec<_>>();
+        self.environments.retain(|key, _| keys.contains(key));
+
         let offscreen_renderpass = self.rendergraph.pass_handle("offscreen")?;
         let mut rendering = WorldRender::new(
             &self.context,
             &self.transient_command_pool,
             world,
-            &self.environment_maps,
+            self.environment_maps(),
+            self.vertex_layout,
         )?;
+        rendering
+            .pbr_pipeline_data
+            .update_textures(&self.context, &mut self.uploads, world)?;
+        rendering
+            .pbr_pipeline_data
+            .update_geometry(&mut self.uploads, world)?;
         rendering.create_pipeline(&mut self.shader_cache, offscreen_renderpass, self.samples)?;
         self.world_render = Some(rendering);
+        self.update_reflection_probe_descriptor_set();
+        self.create_sprite_pipelines()?;
+
+        Ok(())
+    }
+
+    // The environment maps used to light the world
+    pub fn environment_maps(&self) -> &EnvironmentMapSet {
+        self.environment(self.active_environment)
+    }
+
+    // Without an environment, the skybox isn't drawn and the world is lit by its ambient light
+    pub fn has_environment(&self) -> bool {
+        self.active_environment.is_some()
+    }
+
+    fn environment(&self, key: Option<u64>) -> &EnvironmentMapSet {
+        key.and_then(|key| self.environments.get(&key))
+            .unwrap_or(&self.empty_environment)
+    }
+
+    // Captures the scene around each reflection probe and generates its environment maps.
+    // The previous bake is discarded first, so probes only see the skybox's lighting and not each other.
+    // Frames in flight can't be using the descriptor set while this runs.
+    pub fn bake_reflection_probes(&mut self, world: &World) -> Result<()> {
+        self.reflection_probes.clear();
+        self.update_reflection_probe_descriptor_set();
+
+        for (entity, probe) in ReflectionProbes::probes_to_bake(world) {
+            let position = world.entity_global_transform(entity)?.translation;
+            let capture = self.capture_cubemap_from(world, position, probe.resolution)?;
+            let environment_maps = EnvironmentMapSet::with_brdflut(
+                &self.context,
+                &self.transient_command_pool,
+                &mut self.shader_cache,
+                &capture.environment_texture(),
+                self.brdflut.clone(),
+            )?;
+            self.reflection_probes.push(entity, environment_maps);
+        }
+
+        self.update_reflection_probe_descriptor_set();
+        Ok(())
+    }
+
+    // Slots without a baked probe are filled with the empty environment, which is never replaced
+    fn update_reflection_probe_descriptor_set(&self) {
+        if let Some(world_render) = self.world_render.as_ref() {
+            world_render
+                .pbr_pipeline_data
+                .update_reflection_probe_descriptor_set(
+                    &self.context.device,
+                    &self.reflection_probes.environment_maps(),
+                    &self.empty_environment,
+                );
+        }
+    }
+
+    // Whether textures were added to or replaced in the world since they were uploaded
+    pub fn textures_changed(&self, world: &World) -> bool {
+        self.world_render.as_ref().map_or(false, |world_render| {
+            world_render.pbr_pipeline_data.textures_changed(world)
+        })
+    }
 
+    // Frames in flight can't be using the world's descriptor set while this runs
+    pub fn update_textures(&mut self, world: &World) -> Result<()> {
+        let grows = self.world_render.as_ref().map_or(false, |world_render| {
+            world_render
+                .pbr_pipeline_data
+                .can_grow_textures(&self.context.device, world.assets.textures.len())
+        });
+        if grows {
+            // The world's descriptor set and pipelines are recreated with more texture slots
+            return self.load_world(world);
+        }
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.pbr_pipeline_data.update_textures(
+                &self.context,
+                &mut self.uploads,
+                world,
+            )?;
+        }
         Ok(())
     }
 
+    // Whether the scene's skybox or the skybox it is fading from have changed
+    pub fn environment_changed(&self, world: &World) -> bool {
+        self.bound_skyboxes != Some(Self::skyboxes(world))
+    }
+
+    // Rebinds the environment maps of the scene's skyboxes, creating them if they haven't been loaded yet.
+    // Frames in flight can't be using the descriptor sets while this runs.
+    pub fn update_environment(&mut self, world: &World) {
+        let (skybox, faded_skybox) = Self::skyboxes(world);
+        let active = skybox.and_then(|index| self.load_environment(world, index));
+        let faded = faded_skybox
+            .and_then(|index| self.load_environment(world, index))
+            .or(active);
+        self.active_environment = active;
+        self.bound_skyboxes = Some((skybox, faded_skybox));
+
+        let device = self.context.device.clone();
+        self.skybox_render.update_descriptor_set(
+            device.clone(),
+            &self.environment(active).prefilter,
+            &self.environment(faded).prefilter,
+        );
+        if let Some(world_render) = self.world_render.as_ref() {
+            world_render.pbr_pipeline_data.update_descriptor_set(
+                &self.context,
+                device,
+                self.environment(active),
+            );
+        }
+    }
+
+    fn skyboxes(world: &World) -> (Option<usize>, Option<usize>) {
+        let faded = world.scene.skybox_fade.map(|fade| fade.from);
+        (world.scene.skybox, faded)
+    }
+
+    // Returns the key of the hdr texture's environment maps, or nothing if they couldn't be created
+    fn load_environment(&mut self, world: &World, index: usize) -> Option<u64> {
+        if let Some(key) = self.environment_keys.get(&index) {
+            return Some(*key);
+        }
+        let texture = world.assets.hdr_textures.get(index)?;
+        let key = world.assets.hdr_key(index)?;
+        if !self.environments.contains_key(&key) {
+            let environment_maps = match self.environment_cache.load_or_create(
+                &self.context,
+                &self.transient_command_pool,
+                &mut self.shader_cache,
+                key,
+                texture,
+                self.brdflut.clone(),
+            ) {
+                Ok(environment_maps) => environment_maps,
+                Err(error) => {
+                    warn!("Failed to create environment maps: {}", error);
+                    return None;
+                }
+            };
+            self.environments.insert(key, environment_maps);
+        }
+        self.environment_keys.insert(index, key);
+        Some(key)
+    }
+
+    pub fn update_vertices(&mut self, world: &World, vertices: Range<usize>) -> Result<()> {
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render
+                .pbr_pipeline_data
+                .update_vertices(&mut self.uploads, world, vertices)?;
+        }
+        Ok(())
+    }
+
+    pub fn geometry_changed(&self, world: &World) -> bool {
+        self.world_render.as_ref().map_or(false, |world_render| {
+            world_render.pbr_pipeline_data.geometry_changed(world)
+        })
+    }
+
+    pub fn update_geometry(&mut self, world: &World) -> Result<()> {
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render
+                .pbr_pipeline_data
+                .update_geometry(&mut self.uploads, world)?;
+        }
+        Ok(())
+    }
+
+    // Hands finished transfers to the graphics queue ahead of this frame,
+    // and lets the world draw the geometry that has arrived
+    pub fn poll_uploads(&mut self) -> Result<()> {
+        self.uploads.poll()?;
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render
+                .pbr_pipeline_data
+                .update_drawable_geometry(&self.uploads);
+        }
+        Ok(())
+    }
+
+    // Whether textures finished uploading and can replace the dummy texture in their slots
+    pub fn textures_uploaded(&self) -> bool {
+        self.world_render.as_ref().map_or(false, |world_render| {
+            world_render
+                .pbr_pipeline_data
+                .textures_uploaded(&self.uploads)
+        })
+    }
+
+    // Frames in flight can't be using the world's descriptor set while this runs
+    pub fn bind_uploaded_textures(&mut self) {
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render
+                .pbr_pipeline_data
+                .bind_uploaded_textures(&self.context.device, &self.uploads);
+        }
+    }
+
+    // Tracks which textures are drawn, and what has to change to keep them within the budget
+    pub fn texture_residency_changes(
+        &mut self,
+        world: &World,
+        config: &Config,
+    ) -> Result<Vec<(usize, TextureResidency)>> {
+        let pipeline_data = match self.world_render.as_mut() {
+            Some(world_render) => &mut world_render.pbr_pipeline_data,
+            None => return Ok(Vec::new()),
+        };
+        let budget = config
+            .graphics
+            .texture_budget
+            .map(|mebibytes| mebibytes as u64 * 1024 * 1024);
+        let unlimited = pipeline_data
+            .texture_residency
+            .iter()
+            .all(|residency| *residency == TextureResidency::Full);
+        if budget.is_none() && unlimited {
+            return Ok(Vec::new());
+        }
+        pipeline_data.mark_used_textures(world)?;
+        Ok(pipeline_data.plan_texture_residency(world, budget))
+    }
+
+    // Frames in flight can't be using the world's descriptor set while this runs
+    pub fn update_texture_residency(
+        &mut self,
+        world: &World,
+        changes: &[(usize, TextureResidency)],
+    ) -> Result<()> {
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.pbr_pipeline_data.update_texture_residency(
+                &self.context,
+                &mut self.uploads,
+                world,
+                changes,
+            )?;
+        }
+        Ok(())
+    }
+
+    // The number of the world's textures that are downscaled and evicted
+    pub fn texture_residency_counts(&self) -> (u64, u64) {
+        self.world_render
+            .as_ref()
+            .map(|world_render| world_render.pbr_pipeline_data.texture_residency_counts())
+            .unwrap_or_default()
+    }
+
     pub fn recreate_rendergraph(
         &mut self,
         swapchain: &Swapchain,
@@ -280,6 +660,38 @@ impl Scene {
         Ok(())
     }
 
+    // Samplers are created with the settings in place at the time,
+    // so the world is reloaded to rebuild its texture samplers when they change
+    fn update_sampler_settings(&mut self, world: &World, config: &Config) -> Result<()> {
+        let filtering = &config.graphics.texture_filtering;
+        let settings = SamplerSettings {
+            anisotropy: filtering.anisotropy,
+            trilinear: filtering.trilinear,
+        };
+        if settings == self.context.device.sampler_settings() {
+            return Ok(());
+        }
+        self.context.device.set_sampler_settings(settings);
+        if self.world_render.is_some() {
+            self.load_world(world)?;
+        }
+        Ok(())
+    }
+
+    pub fn vertex_layout_changed(&self, config: &Config) -> bool {
+        config.graphics.vertex_layout != self.vertex_layout
+    }
+
+    // The geometry buffer and pipelines are built for one layout, so the world is reloaded to switch layouts.
+    // The geometry buffer must not be in use by any frame in flight.
+    pub fn update_vertex_layout(&mut self, world: &World, config: &Config) -> Result<()> {
+        self.vertex_layout = config.graphics.vertex_layout;
+        if self.world_render.is_some() {
+            self.load_world(world)?;
+        }
+        Ok(())
+    }
+
     pub fn update(
         &mut self,
         world: &World,
@@ -289,6 +701,16 @@ impl Scene {
         elapsed_milliseconds: u32,
         config: &Config,
     ) -> Result<()> {
+        dragonglass_profiling::profile_scope!("uniform upload");
+        self.update_sampler_settings(world, config)?;
+
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.wireframe_enabled = config.graphics.wireframe;
+            // Whole vertices are read from the geometry buffer, which only the interleaved layout has
+            world_render.pbr_pipeline_data.compute_skinning =
+                config.graphics.compute_skinning && self.vertex_layout == VertexLayout::Interleaved;
+        }
+
         if let Some(gui_context) = gui_context {
             self.gui_render
                 .update(gui_context, &self.transient_command_pool, clipped_meshes)?;
@@ -308,49 +730,94 @@ impl Scene {
         let camera_entity = world.active_camera()?;
         let camera_transform = world.entity_global_transform(camera_entity)?;
 
-        // Maintain a perspective projection for the skybox
-        let using_ortho_projection = world
-            .ecs
-            .entry_ref(camera_entity)?
-            .get_component::<Camera>()?
-            .is_orthographic();
-        let skybox_projection = if using_ortho_projection {
-            let camera = PerspectiveCamera {
-                aspect_ratio: None,
-                y_fov_rad: 70_f32.to_radians(),
-                z_far: Some(1000.0),
-                z_near: 0.01,
-            };
-            camera.matrix(aspect_ratio)
-        } else {
-            projection
-        };
+        let skybox_projection = skybox_projection(world, camera_entity, projection, aspect_ratio)?;
 
         self.skybox_render.projection = skybox_projection;
         self.skybox_render.view = view;
+        self.skybox_render.blend = world
+            .scene
+            .skybox_fade
+            .map(|fade| fade.blend())
+            .unwrap_or(1.0);
+
+        self.fog_render
+            .update(world, &self.transient_command_pool, projection * view)?;
+
+        self.grid_render.update(
+            &config.graphics.grid,
+            projection * view,
+            camera_transform.translation,
+        );
+
+        self.text_render
+            .update(world, &self.transient_command_pool, view, projection)?;
+
+        self.sprite_render.update(world, view, projection)?;
 
+        self.particle_render.update(world, view, projection)?;
+
+        self.outline_render
+            .update(world, &config.graphics.outline)?;
+
+        self.upload_world_uniforms(
+            world,
+            view,
+            projection,
+            camera_transform.translation,
+            world.active_camera_exposure(),
+        )?;
+
+        Ok(())
+    }
+
+    fn upload_world_uniforms(
+        &mut self,
+        world: &World,
+        view: glm::Mat4,
+        projection: glm::Mat4,
+        camera_position: glm::Vec3,
+        exposure: f32,
+    ) -> Result<()> {
         if let Some(world_render) = self.world_render.as_mut() {
-            world_render.pbr_pipeline_data.update_dynamic_ubo(world)?;
-            let (lights, number_of_lights) = Self::load_lights(world)?;
+            world_render
+                .pbr_pipeline_data
+                .update_entity_buffers(&self.context, world)?;
+            world_render.update_skinning(&self.context, world)?;
+            world_render.update_materials(world);
+        }
+        self.upload_camera_uniforms(world, view, projection, camera_position, exposure, 0)
+    }
 
-            let mut joint_matrices = [glm::Mat4::identity(); PbrPipelineData::MAX_NUMBER_OF_JOINTS];
-            joint_matrices
-                .iter_mut()
-                .zip(world.joint_matrices()?.into_iter())
-                .for_each(|(a, b)| *a = b);
+    // Only the camera differs between views, which read the entities uploaded for the main view
+    fn upload_camera_uniforms(
+        &mut self,
+        world: &World,
+        view: glm::Mat4,
+        projection: glm::Mat4,
+        camera_position: glm::Vec3,
+        exposure: f32,
+        slot: usize,
+    ) -> Result<()> {
+        let environment_lighting = self.has_environment() as i32;
+        let (number_of_reflection_probes, reflection_probes) =
+            self.reflection_probes.uniform_data(world);
+        if let Some(world_render) = self.world_render.as_mut() {
+            let (lights, number_of_lights) = Self::load_lights(world)?;
 
             let ubo = WorldUniformBuffer {
                 view,
                 projection,
-                camera_position: camera_transform.translation,
+                camera_position,
                 number_of_lights,
                 lights,
-                joint_matrices,
+                ambient_light: world.scene.ambient_light.radiance(),
+                environment_lighting,
+                number_of_reflection_probes,
+                exposure,
+                padding: glm::Vec2::zeros(),
+                reflection_probes,
             };
-            world_render
-                .pbr_pipeline_data
-                .uniform_buffer
-                .upload_data(&[ubo], 0)?;
+            world_render.pbr_pipeline_data.upload_uniforms(&ubo, slot)?;
         }
 
         Ok(())
@@ -375,26 +842,64 @@ impl Scene {
         &mut self,
         command_buffer: CommandBuffer,
         world: &World,
-        image_index: usize,
+        (frame_index, image_index): (usize, usize),
         aspect_ratio: f32,
         viewport: Viewport,
         clipped_meshes: &[ClippedMesh],
     ) -> Result<()> {
+        dragonglass_profiling::profile_scope!("command recording");
         let device = &self.context.device.clone();
+        let has_environment = self.has_environment();
+        // Only this frame's commands are counted
+        device.commands.take();
+        self.gpu_timer.begin_frame(command_buffer, frame_index)?;
+
+        self.gpu_timer.begin_scope(command_buffer, "scene");
+        if let Some(world_render) = self.world_render.as_ref() {
+            device.record_label(command_buffer, "skinning", |command_buffer| {
+                world_render.skinning.issue_commands(command_buffer)
+            })?;
+        }
         self.rendergraph.execute_pass(
             command_buffer,
             "offscreen",
             image_index,
             |pass, command_buffer| {
                 device.update_viewport(command_buffer, pass.extent, true)?;
-                self.skybox_render.issue_commands(command_buffer)?;
+                if has_environment {
+                    self.skybox_render.issue_commands(command_buffer)?;
+                }
+                if let Some(world_render) = self.world_render.as_ref() {
+                    world_render.issue_commands(
+                        command_buffer,
+                        world,
+                        aspect_ratio,
+                        world.active_camera_culling_mask(),
+                        true,
+                    )?;
+                    self.sprite_render
+                        .issue_world_commands(command_buffer, &world_render.pbr_pipeline_data)?;
+                    self.particle_render
+                        .issue_commands(command_buffer, &world_render.pbr_pipeline_data)?;
+                }
+                self.grid_render.issue_commands(command_buffer)?;
+                self.fog_render.issue_commands(command_buffer)?;
+                self.text_render.issue_world_commands(command_buffer)?;
+                // Outlines are drawn last so nothing in the scene covers them
                 if let Some(world_render) = self.world_render.as_ref() {
-                    world_render.issue_commands(command_buffer, world, aspect_ratio)?;
+                    self.outline_render.issue_commands(
+                        command_buffer,
+                        world_render,
+                        glm::vec2(viewport.width, viewport.height),
+                    )?;
                 }
                 Ok(())
             },
         )?;
+        self.gpu_timer.end_scope(command_buffer);
 
+        self.gpu_timer
+            .begin_scope(command_buffer, "post processing and gui");
         self.rendergraph.execute_pass(
             command_buffer,
             "fullscreen",
@@ -404,12 +909,401 @@ impl Scene {
                 if let Some(fullscreen_pipeline) = self.fullscreen_pipeline.as_ref() {
                     fullscreen_pipeline.issue_commands(command_buffer)?;
                 }
+                if let Some(world_render) = self.world_render.as_ref() {
+                    self.sprite_render.issue_screen_commands(
+                        command_buffer,
+                        &world_render.pbr_pipeline_data,
+                        viewport,
+                    )?;
+                }
+                self.text_render
+                    .issue_screen_commands(command_buffer, viewport)?;
                 self.gui_render
                     .issue_commands(viewport, command_buffer, clipped_meshes)?;
                 Ok(())
             },
         )?;
+        self.gpu_timer.end_scope(command_buffer);
+        self.command_stats = self.context.device.commands.take();
+
+        Ok(())
+    }
+
+    // Renders the world from the active camera's position once per cubemap face,
+    // reading each face back from the resolved offscreen image.
+    // Post processing and the gui are skipped so the faces only contain the scene.
+    pub fn capture_cubemap(&mut self, world: &World, size: u32) -> Result<CubemapCapture> {
+        let camera_entity = world.active_camera()?;
+        let position = world.entity_global_transform(camera_entity)?.translation;
+        self.capture_cubemap_from(world, position, size)
+    }
+
+    fn capture_cubemap_from(
+        &mut self,
+        world: &World,
+        position: glm::Vec3,
+        size: u32,
+    ) -> Result<CubemapCapture> {
+        let size = size.min(Self::OFFSCREEN_SIZE).max(1);
+        let projection = PerspectiveCamera {
+            aspect_ratio: Some(1.0),
+            y_fov_rad: 90_f32.to_radians(),
+            z_far: None,
+            z_near: 0.01,
+        }
+        .matrix(1.0);
+
+        let buffer = self.readback_buffer(size, size)?;
+        let face_extent = vk::Extent2D::builder().width(size).height(size).build();
+        let mut faces = Vec::new();
+        for (forward, up) in CubemapCapture::face_orientations().iter() {
+            let view = glm::look_at(&position, &(position + forward), up);
+            let face = self.render_offscreen_view(
+                world,
+                &buffer,
+                face_extent,
+                (projection, view),
+                projection,
+                position,
+            )?;
+            faces.push(face);
+        }
+
+        Ok(CubemapCapture { size, faces })
+    }
+
+    // Renders the world from the active camera the same way the last frame was,
+    // without post processing or the gui. Viewports larger than the offscreen image are scaled down to fit.
+    pub fn capture_frame(&mut self, world: &World, viewport: Viewport) -> Result<RgbaImage> {
+        let aspect_ratio = viewport.aspect_ratio();
+        let extent = Self::offscreen_extent(viewport);
+
+        let (projection, view) = world.active_camera_matrices(aspect_ratio)?;
+        let camera_entity = world.active_camera()?;
+        let position = world.entity_global_transform(camera_entity)?.translation;
+
+        let buffer = self.readback_buffer(extent.width, extent.height)?;
+        self.render_offscreen_view(
+            world,
+            &buffer,
+            extent,
+            (projection, view),
+            skybox_projection(world, camera_entity, projection, aspect_ratio)?,
+            position,
+        )
+    }
+
+    // The part of the offscreen image a viewport is rendered to, scaled down to fit when it is larger
+    fn offscreen_extent(viewport: Viewport) -> vk::Extent2D {
+        let scale = (Self::OFFSCREEN_SIZE as f32 / viewport.width.max(viewport.height)).min(1.0);
+        let width = ((viewport.width * scale) as u32).clamp(1, Self::OFFSCREEN_SIZE);
+        let height = ((viewport.height * scale) as u32).clamp(1, Self::OFFSCREEN_SIZE);
+        vk::Extent2D::builder().width(width).height(height).build()
+    }
+
+    // Uploads the uniforms for rendering the world from a camera other than the active one.
+    // Each view has its own slot, so the main view's uniforms in flight are left alone.
+    pub fn update_view(
+        &mut self,
+        world: &World,
+        camera: Entity,
+        aspect_ratio: f32,
+        slot: usize,
+    ) -> Result<()> {
+        let (projection, view) = world.camera_matrices(camera, aspect_ratio)?;
+        let position = world.entity_global_transform(camera)?.translation;
+        self.skybox_render.projection = skybox_projection(world, camera, projection, aspect_ratio)?;
+        self.skybox_render.view = view;
+        self.fog_render.update_view(world, projection * view);
+        self.upload_camera_uniforms(
+            world,
+            view,
+            projection,
+            position,
+            world.camera_exposure(camera),
+            slot,
+        )
+    }
+
+    // Draws after a view go back to reading the main view's uniforms
+    pub fn finish_view(&mut self) {
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.pbr_pipeline_data.select_uniforms(0);
+        }
+    }
+
+    // Renders the world into the offscreen image and scales it into another window's swapchain image.
+    // Like captures, views skip post processing and the gui.
+    pub fn execute_view_passes(
+        &self,
+        command_buffer: CommandBuffer,
+        world: &World,
+        viewport: Viewport,
+        culling_mask: Layers,
+        (target, target_extent): (vk::Image, vk::Extent2D),
+    ) -> Result<()> {
+        let device = self.context.device.clone();
+        let extent = Self::offscreen_extent(viewport);
+        let aspect_ratio = extent.width as f32 / extent.height as f32;
+        let has_environment = self.has_environment();
+        self.rendergraph
+            .execute_pass(command_buffer, "offscreen", 0, |_, command_buffer| {
+                device.update_viewport(command_buffer, extent, true)?;
+                if has_environment {
+                    self.skybox_render.issue_commands(command_buffer)?;
+                }
+                if let Some(world_render) = self.world_render.as_ref() {
+                    world_render.issue_commands(
+                        command_buffer,
+                        world,
+                        aspect_ratio,
+                        culling_mask,
+                        false,
+                    )?;
+                }
+                self.fog_render.issue_commands(command_buffer)?;
+                Ok(())
+            })?;
+
+        let color_resolve = self.rendergraph.image("color_resolve")?.handle();
+        let transfer = vk::PipelineStageFlags::TRANSFER;
+        record_transition(
+            &device,
+            command_buffer,
+            color_resolve,
+            (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            (
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, transfer),
+        )?;
+        // Waits on the same stage the acquired image's semaphore is waited on
+        record_transition(
+            &device,
+            command_buffer,
+            target,
+            (
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+            (vk::AccessFlags::empty(), vk::AccessFlags::TRANSFER_WRITE),
+            (vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, transfer),
+        )?;
+
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1)
+            .build();
+        let corner = |extent: vk::Extent2D| vk::Offset3D {
+            x: extent.width as _,
+            y: extent.height as _,
+            z: 1,
+        };
+        let region = vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([vk::Offset3D::default(), corner(extent)])
+            .dst_subresource(subresource)
+            .dst_offsets([vk::Offset3D::default(), corner(target_extent)])
+            .build();
+        BlitImageBuilder::default()
+            .src_image(color_resolve)
+            .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .dst_image(target)
+            .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .regions(vec![region])
+            .filter(vk::Filter::LINEAR)
+            .build()?
+            .record(&device.handle, command_buffer);
+
+        record_transition(
+            &device,
+            command_buffer,
+            color_resolve,
+            (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (vk::AccessFlags::TRANSFER_READ, vk::AccessFlags::SHADER_READ),
+            (transfer, vk::PipelineStageFlags::FRAGMENT_SHADER),
+        )?;
+        record_transition(
+            &device,
+            command_buffer,
+            target,
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            ),
+            (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty()),
+            (transfer, vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+        )?;
 
         Ok(())
     }
+
+    // Finds the entity drawn at a window position, using the camera and transforms of the last update
+    pub fn pick(
+        &self,
+        world: &World,
+        viewport: Viewport,
+        position: (f32, f32),
+    ) -> Result<Option<Entity>> {
+        let world_render = match self.world_render.as_ref() {
+            Some(world_render) => world_render,
+            None => return Ok(None),
+        };
+        self.picking_render.pick(
+            &self.transient_command_pool,
+            world,
+            world_render,
+            viewport,
+            position,
+        )
+    }
+
+    fn readback_buffer(&self, width: u32, height: u32) -> Result<GpuToCpuBuffer> {
+        GpuToCpuBuffer::readback_buffer(
+            self.context.device.clone(),
+            self.context.allocator.clone(),
+            "view readback",
+            (width * height * 4) as _,
+        )
+    }
+
+    // Renders a single view into the top left of the offscreen image and reads it back
+    fn render_offscreen_view(
+        &mut self,
+        world: &World,
+        buffer: &GpuToCpuBuffer,
+        extent: vk::Extent2D,
+        (projection, view): (glm::Mat4, glm::Mat4),
+        skybox_projection: glm::Mat4,
+        position: glm::Vec3,
+    ) -> Result<RgbaImage> {
+        let color_resolve = self.rendergraph.image("color_resolve")?.handle();
+        let aspect_ratio = extent.width as f32 / extent.height as f32;
+
+        self.skybox_render.projection = skybox_projection;
+        self.skybox_render.view = view;
+        self.fog_render
+            .update(world, &self.transient_command_pool, projection * view)?;
+        self.upload_world_uniforms(
+            world,
+            view,
+            projection,
+            position,
+            world.active_camera_exposure(),
+        )?;
+
+        let device = self.context.device.clone();
+        let has_environment = self.has_environment();
+        let Self {
+            rendergraph,
+            skybox_render,
+            world_render,
+            fog_render,
+            transient_command_pool,
+            ..
+        } = self;
+        transient_command_pool.execute_once(|command_buffer| {
+            rendergraph.execute_pass(command_buffer, "offscreen", 0, |_, command_buffer| {
+                device.update_viewport(command_buffer, extent, true)?;
+                if has_environment {
+                    skybox_render.issue_commands(command_buffer)?;
+                }
+                if let Some(world_render) = world_render.as_ref() {
+                    world_render.issue_commands(
+                        command_buffer,
+                        world,
+                        aspect_ratio,
+                        world.active_camera_culling_mask(),
+                        false,
+                    )?;
+                }
+                fog_render.issue_commands(command_buffer)?;
+                Ok(())
+            })
+        })?;
+
+        transition_color_resolve(
+            transient_command_pool,
+            color_resolve,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )?;
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .layer_count(1)
+            .build();
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(subresource)
+            .image_extent(
+                vk::Extent3D::builder()
+                    .width(extent.width)
+                    .height(extent.height)
+                    .depth(1)
+                    .build(),
+            )
+            .build();
+        let copy_info = ImageToBufferCopyBuilder::default()
+            .source(color_resolve)
+            .destination(buffer.handle())
+            .regions(vec![region])
+            .build()?;
+        transient_command_pool.copy_image_to_buffer(&copy_info)?;
+        transition_color_resolve(
+            transient_command_pool,
+            color_resolve,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+
+        let size = (extent.width * extent.height * 4) as usize;
+        RgbaImage::from_raw(extent.width, extent.height, buffer.download_data(size)?)
+            .context("Failed to read back the offscreen image!")
+    }
+}
+
+fn transition_color_resolve(
+    command_pool: &CommandPool,
+    image: vk::Image,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) -> Result<()> {
+    let transition = ImageLayoutTransitionBuilder::default()
+        .base_mip_level(0)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+        .dst_access_mask(vk::AccessFlags::MEMORY_READ)
+        .src_stage_mask(vk::PipelineStageFlags::ALL_COMMANDS)
+        .dst_stage_mask(vk::PipelineStageFlags::ALL_COMMANDS)
+        .build()?;
+    transition_image(image, command_pool, &transition)
+}
+
+// Records a layout transition into a command buffer that is already being recorded
+fn record_transition(
+    device: &Device,
+    command_buffer: CommandBuffer,
+    image: vk::Image,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_access_mask, dst_access_mask): (vk::AccessFlags, vk::AccessFlags),
+    (src_stage_mask, dst_stage_mask): (vk::PipelineStageFlags, vk::PipelineStageFlags),
+) -> Result<()> {
+    ImageLayoutTransitionBuilder::default()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .src_stage_mask(src_stage_mask)
+        .dst_stage_mask(dst_stage_mask)
+        .build()?
+        .pipeline_barrier(image)?
+        .record(&device.handle, command_buffer);
+    Ok(())
 }
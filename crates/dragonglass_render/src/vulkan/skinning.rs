@@ -0,0 +1,348 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        whole_buffer_barrier, workgroup_count, ComputeDispatch, ComputePipelineSettingsBuilder,
+        Context, DescriptorPool, DescriptorSetLayout, Device, GpuBuffer, Pipeline,
+        PipelineBarrierBuilder, PipelineLayout, ShaderCache, ShaderPathSetBuilder,
+    },
+};
+use dragonglass_world::{legion::EntityStore, Entity, Hidden, MeshRender, Skin, Vertex, World};
+use std::{collections::HashMap, mem, ops::Range, sync::Arc};
+
+use super::world::PbrPipelineData;
+
+pub struct SkinningPushConstantBlock {
+    pub first_source_vertex: u32,
+    pub first_skinned_vertex: u32,
+    pub number_of_vertices: u32,
+    pub joint_offset: u32,
+}
+
+// The vertices of one skinned entity's mesh, skinned with its joints into its own region of the skinned vertex buffer
+struct SkinningJob {
+    first_source_vertex: u32,
+    first_skinned_vertex: u32,
+    number_of_vertices: u32,
+    joint_offset: u32,
+}
+
+// Skins the vertices of every visible skinned entity once per frame in a compute pass,
+// so the world render can draw them as many times as it needs to without skinning them again.
+// Only the interleaved vertex layout is supported, since whole vertices are read from the geometry buffer.
+pub struct SkinningPass {
+    pub pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    descriptor_set_layout: Arc<DescriptorSetLayout>,
+    _descriptor_pool: DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    skinned_buffer: GpuBuffer,
+    skinned_buffer_size: vk::DeviceSize,
+    // The geometry, joint, and skinned vertex buffers the descriptor set was last written for
+    bound_buffers: Option<[vk::Buffer; 3]>,
+    jobs: Vec<SkinningJob>,
+    // Added to the vertex indices of each pre-skinned entity to draw it from the skinned vertex buffer, by entity index
+    vertex_offsets: HashMap<usize, i32>,
+    device: Arc<Device>,
+}
+
+impl SkinningPass {
+    // The skinned vertex buffer starts out with room for this many vertices and doubles in size whenever it runs out
+    pub const INITIAL_NUMBER_OF_VERTICES: usize = 16384;
+
+    // Matches the local size of the compute shader
+    const WORKGROUP_SIZE: u32 = 64;
+
+    pub fn new(context: &Context) -> Result<Self> {
+        let device = context.device.clone();
+        let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(device.clone())?);
+        let descriptor_pool = Self::descriptor_pool(device.clone())?;
+        let descriptor_set = descriptor_pool.allocate_descriptor_sets(
+            descriptor_set_layout.handle,
+            "skinning",
+            1,
+        )?[0];
+        let skinned_buffer_size =
+            (Self::INITIAL_NUMBER_OF_VERTICES * mem::size_of::<Vertex>()) as vk::DeviceSize;
+        let skinned_buffer = Self::create_skinned_buffer(context, skinned_buffer_size)?;
+        Ok(Self {
+            pipeline: None,
+            pipeline_layout: None,
+            descriptor_set_layout,
+            _descriptor_pool: descriptor_pool,
+            descriptor_set,
+            skinned_buffer,
+            skinned_buffer_size,
+            bound_buffers: None,
+            jobs: Vec::new(),
+            vertex_offsets: HashMap::new(),
+            device,
+        })
+    }
+
+    fn descriptor_set_layout(device: Arc<Device>) -> Result<DescriptorSetLayout> {
+        let binding = |binding: u32| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        };
+        // The source vertices, the joint matrices, and the skinned vertices
+        let bindings = [binding(0), binding(1), binding(2)];
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        DescriptorSetLayout::new(device, create_info)
+    }
+
+    fn descriptor_pool(device: Arc<Device>) -> Result<DescriptorPool> {
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 3,
+        }];
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(1);
+        DescriptorPool::new(device, create_info)
+    }
+
+    // Read by the compute shader and then drawn from as a vertex buffer
+    fn create_skinned_buffer(context: &Context, size: vk::DeviceSize) -> Result<GpuBuffer> {
+        GpuBuffer::new(
+            context.device.clone(),
+            context.allocator.clone(),
+            "skinned vertices",
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+        )
+    }
+
+    pub fn create_pipeline(&mut self, shader_cache: &mut ShaderCache) -> Result<()> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .size(mem::size_of::<SkinningPushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .compute("assets/shaders/skinning/skinning.comp.spv")
+            .build()?;
+        let shader_set = shader_cache.create_shader_set(self.device.clone(), &shader_paths)?;
+
+        self.pipeline = None;
+        self.pipeline_layout = None;
+
+        let (pipeline, pipeline_layout) = ComputePipelineSettingsBuilder::default()
+            .descriptor_set_layout(self.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .name("skinning")
+            .push_constant_range(push_constant_range)
+            .build()?
+            .create_pipeline(self.device.clone())?;
+
+        self.pipeline = Some(pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    // The range of the world's vertices that an entity's mesh is drawn from, if the entity is skinned
+    pub fn skinned_vertices(world: &World, entity: Entity) -> Result<Option<Range<usize>>> {
+        let entry = world.ecs.entry_ref(entity)?;
+        if entry.get_component::<Skin>().is_err() {
+            return Ok(None);
+        }
+        let mesh = match entry.get_component::<MeshRender>() {
+            Ok(mesh_render) => match world.assets.geometry.meshes.get(&mesh_render.name) {
+                Some(mesh) => mesh,
+                None => return Ok(None),
+            },
+            Err(_) => return Ok(None),
+        };
+        let start = mesh
+            .primitives
+            .iter()
+            .map(|primitive| primitive.first_vertex)
+            .min();
+        let end = mesh
+            .primitives
+            .iter()
+            .map(|primitive| primitive.first_vertex + primitive.number_of_vertices)
+            .max();
+        Ok(match (start, end) {
+            (Some(start), Some(end)) if start < end => Some(start..end),
+            _ => None,
+        })
+    }
+
+    // Lays out the skinned vertices of each visible skinned entity.
+    // Entities and joints are counted in the same order as the world render's entity buffer.
+    pub fn update(
+        &mut self,
+        context: &Context,
+        world: &World,
+        pipeline_data: &PbrPipelineData,
+    ) -> Result<()> {
+        self.clear();
+
+        let mut entity_index = 0;
+        let mut joint_offset = 0;
+        let mut number_of_vertices = 0;
+        for graph in world.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                let entry = world.ecs.entry_ref(entity)?;
+                let joint_count = entry
+                    .get_component::<Skin>()
+                    .map_or(0, |skin| skin.joints.len());
+
+                if entry.get_component::<Hidden>().is_err() {
+                    if let Some(vertices) = Self::skinned_vertices(world, entity)? {
+                        self.jobs.push(SkinningJob {
+                            first_source_vertex: vertices.start as u32,
+                            first_skinned_vertex: number_of_vertices as u32,
+                            number_of_vertices: vertices.len() as u32,
+                            joint_offset: joint_offset as u32,
+                        });
+                        self.vertex_offsets.insert(
+                            entity_index,
+                            number_of_vertices as i32 - vertices.start as i32,
+                        );
+                        number_of_vertices += vertices.len();
+                    }
+                }
+
+                entity_index += 1;
+                joint_offset += joint_count;
+                Ok(())
+            })?;
+        }
+
+        if self.jobs.is_empty() {
+            return Ok(());
+        }
+
+        let size = (number_of_vertices * mem::size_of::<Vertex>()) as vk::DeviceSize;
+        if size > self.skinned_buffer_size {
+            // Frames in flight may still be drawing from the buffer being replaced
+            unsafe { context.device.handle.device_wait_idle() }?;
+            self.skinned_buffer_size = size.next_power_of_two();
+            self.skinned_buffer = Self::create_skinned_buffer(context, self.skinned_buffer_size)?;
+        }
+
+        // Buffers are only ever replaced once the device is idle, so the descriptor set is not in use
+        let buffers = [
+            pipeline_data.geometry_buffer.vertex_buffer.handle(),
+            pipeline_data.joint_buffer.handle(),
+            self.skinned_buffer.handle(),
+        ];
+        if self.bound_buffers != Some(buffers) {
+            self.update_descriptor_set(buffers);
+            self.bound_buffers = Some(buffers);
+        }
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.jobs.clear();
+        self.vertex_offsets.clear();
+    }
+
+    fn update_descriptor_set(&self, buffers: [vk::Buffer; 3]) {
+        let buffer_infos = buffers
+            .iter()
+            .map(|buffer| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(*buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)
+                    .build()]
+            })
+            .collect::<Vec<_>>();
+        let descriptor_writes = buffer_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_set)
+                    .dst_binding(binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(buffer_info)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.device
+                .handle
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+    }
+
+    // The offset to draw an entity's mesh from the skinned vertex buffer with, if it was pre-skinned this frame
+    pub fn vertex_offset(&self, entity_index: usize) -> Option<i32> {
+        self.vertex_offsets.get(&entity_index).copied()
+    }
+
+    pub fn skinned_vertex_buffer(&self) -> vk::Buffer {
+        self.skinned_buffer.handle()
+    }
+
+    // Must be recorded outside of any render pass, before the world is drawn
+    pub fn issue_commands(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        if self.jobs.is_empty() {
+            return Ok(());
+        }
+
+        let (pipeline, pipeline_layout) =
+            match (self.pipeline.as_ref(), self.pipeline_layout.as_ref()) {
+                (Some(pipeline), Some(pipeline_layout)) => (pipeline, pipeline_layout),
+                _ => return Ok(()),
+            };
+
+        let device = &self.device.handle;
+
+        // The previous frame may still be drawing from the skinned vertices
+        PipelineBarrierBuilder::default()
+            .src_stage_mask(vk::PipelineStageFlags::VERTEX_INPUT)
+            .dst_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+            .build()?
+            .record(device, command_buffer);
+
+        let descriptor_sets = [self.descriptor_set];
+        for job in self.jobs.iter() {
+            let push_constants = SkinningPushConstantBlock {
+                first_source_vertex: job.first_source_vertex,
+                first_skinned_vertex: job.first_skinned_vertex,
+                number_of_vertices: job.number_of_vertices,
+                joint_offset: job.joint_offset,
+            };
+            let push_constants = unsafe { byte_slice_from(&push_constants) };
+            ComputeDispatch {
+                pipeline,
+                pipeline_layout,
+                descriptor_sets: &descriptor_sets,
+                push_constants,
+                workgroups: [
+                    workgroup_count(job.number_of_vertices, Self::WORKGROUP_SIZE),
+                    1,
+                    1,
+                ],
+            }
+            .record(device, command_buffer);
+        }
+
+        PipelineBarrierBuilder::default()
+            .src_stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER)
+            .dst_stage_mask(vk::PipelineStageFlags::VERTEX_INPUT)
+            .buffer_memory_barriers(vec![whole_buffer_barrier(
+                self.skinned_buffer.handle(),
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            )])
+            .build()?
+            .record(device, command_buffer);
+
+        Ok(())
+    }
+}
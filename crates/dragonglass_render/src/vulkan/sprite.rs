@@ -0,0 +1,406 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        Context, CpuToGpuBuffer, GraphicsPipelineSettingsBuilder, Pipeline, PipelineLayout,
+        RenderPass, ShaderCache, ShaderPathSetBuilder,
+    },
+};
+use dragonglass_world::{Viewport, World};
+use nalgebra_glm as glm;
+use std::{mem, sync::Arc};
+
+use super::world::PbrPipelineData;
+
+pub struct SpritePushConstantBlock {
+    pub view_projection: glm::Mat4,
+    // Billboards are laid out along these to face the camera
+    pub camera_right: glm::Vec4,
+    pub camera_up: glm::Vec4,
+    pub screen_size: glm::Vec2,
+    pub screen_space: i32,
+    pub texture_index: i32,
+}
+
+// Quad corners are offset from the anchor, in world units with y up for billboards
+// and in pixels with y down for sprites
+#[derive(Debug, Copy, Clone)]
+pub struct SpriteVertex {
+    pub anchor: glm::Vec3,
+    pub offset: glm::Vec2,
+    pub uv: glm::Vec2,
+    pub tint: glm::Vec4,
+}
+
+// A run of quads that share a texture, drawn with a single draw call
+struct SpriteBatch {
+    screen_space: bool,
+    texture_index: i32,
+    first_vertex: u32,
+    number_of_vertices: u32,
+}
+
+// Draws the world's billboards into the scene after its meshes, and its sprites over the post processed image.
+// Quads sample the world's textures through the descriptor set of the world render.
+pub struct SpriteRender {
+    pub world_pipeline: Option<Pipeline>,
+    pub screen_pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    pub view_projection: glm::Mat4,
+    pub view: glm::Mat4,
+    vertex_buffer: CpuToGpuBuffer,
+    batches: Vec<SpriteBatch>,
+    context: Arc<Context>,
+}
+
+impl SpriteRender {
+    // The vertex buffer starts out with room for this many quads and doubles in size whenever it runs out
+    pub const INITIAL_NUMBER_OF_QUADS: usize = 1024;
+
+    const VERTICES_PER_QUAD: usize = 6;
+
+    pub fn new(context: Arc<Context>) -> Result<Self> {
+        let vertex_buffer = Self::vertex_buffer(&context, Self::INITIAL_NUMBER_OF_QUADS)?;
+        Ok(Self {
+            world_pipeline: None,
+            screen_pipeline: None,
+            pipeline_layout: None,
+            view_projection: glm::Mat4::identity(),
+            view: glm::Mat4::identity(),
+            vertex_buffer,
+            batches: Vec::new(),
+            context,
+        })
+    }
+
+    // The pipelines use the world render's descriptor set layout,
+    // so they are recreated along with the world render
+    pub fn create_pipelines(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        offscreen_render_pass: Arc<RenderPass>,
+        fullscreen_render_pass: Arc<RenderPass>,
+        samples: vk::SampleCountFlags,
+        pipeline_data: &PbrPipelineData,
+    ) -> Result<()> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .size(mem::size_of::<SpritePushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/sprite/sprite.vert.spv")
+            .fragment("assets/shaders/sprite/sprite.frag.spv")
+            .build()?;
+        let shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &shader_paths)?;
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(offscreen_render_pass)
+            .vertex_inputs(Self::vertex_inputs())
+            .vertex_attributes(Self::vertex_attributes())
+            .descriptor_set_layout(pipeline_data.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .rasterization_samples(samples)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .blended(true)
+            .depth_test_enabled(true)
+            .depth_write_enabled(false)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+            .specialization_constants(vec![pipeline_data.texture_capacity as u32]);
+
+        let mut screen_settings = settings.clone();
+        screen_settings
+            .render_pass(fullscreen_render_pass)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .depth_test_enabled(false);
+
+        self.world_pipeline = None;
+        self.screen_pipeline = None;
+        self.pipeline_layout = None;
+
+        let (world_pipeline, pipeline_layout) = settings
+            .name("sprite world")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        let (screen_pipeline, _) = screen_settings
+            .name("sprite screen")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+
+        self.world_pipeline = Some(world_pipeline);
+        self.screen_pipeline = Some(screen_pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    fn vertex_attributes() -> [vk::VertexInputAttributeDescription; 4] {
+        let float_size = std::mem::size_of::<f32>();
+
+        let anchor = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+
+        let offset = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset((3 * float_size) as _)
+            .build();
+
+        let uv = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset((5 * float_size) as _)
+            .build();
+
+        let tint = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((7 * float_size) as _)
+            .build();
+
+        [anchor, offset, uv, tint]
+    }
+
+    fn vertex_inputs() -> [vk::VertexInputBindingDescription; 1] {
+        let vertex_input_binding_description = vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(mem::size_of::<SpriteVertex>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build();
+        [vertex_input_binding_description]
+    }
+
+    fn vertex_buffer(context: &Context, number_of_quads: usize) -> Result<CpuToGpuBuffer> {
+        CpuToGpuBuffer::vertex_buffer(
+            context.device.clone(),
+            context.allocator.clone(),
+            "sprite vertices",
+            (number_of_quads * Self::VERTICES_PER_QUAD * mem::size_of::<SpriteVertex>()) as _,
+        )
+    }
+
+    // Builds the quads of the world's billboards and sprites, batched by texture
+    pub fn update(&mut self, world: &World, view: glm::Mat4, projection: glm::Mat4) -> Result<()> {
+        self.view = view;
+        self.view_projection = projection * view;
+        self.batches.clear();
+
+        let number_of_textures = world.assets.textures.len() as i32;
+        let texture_index = |index: i32| {
+            if index < number_of_textures {
+                index.max(-1)
+            } else {
+                -1
+            }
+        };
+
+        let mut quads = Vec::new();
+        for (transform, billboard) in world.billboards()? {
+            let size = billboard.size.component_mul(&transform.scale.xy());
+            let quad = Quad {
+                anchor: transform.translation,
+                // Centered on the entity with y up
+                min: glm::vec2(-size.x, size.y) / 2.0,
+                max: glm::vec2(size.x, -size.y) / 2.0,
+                tint: billboard.tint,
+            };
+            quads.push((false, texture_index(billboard.texture_index), quad));
+        }
+        for (transform, sprite) in world.sprites()? {
+            let size = sprite.size.component_mul(&transform.scale.xy());
+            let quad = Quad {
+                anchor: transform.translation,
+                min: glm::Vec2::zeros(),
+                max: size,
+                tint: sprite.tint,
+            };
+            quads.push((true, texture_index(sprite.texture_index), quad));
+        }
+        if quads.is_empty() {
+            return Ok(());
+        }
+        quads.sort_by_key(|(screen_space, texture_index, _)| (*screen_space, *texture_index));
+
+        let mut vertices = Vec::new();
+        for (screen_space, texture_index, quad) in quads.iter() {
+            let first_vertex = vertices.len() as u32;
+            quad.add_vertices(&mut vertices);
+            match self.batches.last_mut() {
+                Some(batch)
+                    if batch.screen_space == *screen_space
+                        && batch.texture_index == *texture_index =>
+                {
+                    batch.number_of_vertices += Self::VERTICES_PER_QUAD as u32;
+                }
+                _ => self.batches.push(SpriteBatch {
+                    screen_space: *screen_space,
+                    texture_index: *texture_index,
+                    first_vertex,
+                    number_of_vertices: Self::VERTICES_PER_QUAD as u32,
+                }),
+            }
+        }
+
+        let capacity = self.vertex_buffer.size() as usize / mem::size_of::<SpriteVertex>();
+        if vertices.len() > capacity {
+            // Frames in flight may still be reading the buffer being replaced
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            self.vertex_buffer =
+                Self::vertex_buffer(&self.context, quads.len().next_power_of_two())?;
+        }
+        self.vertex_buffer.upload_data(&vertices, 0)?;
+
+        Ok(())
+    }
+
+    pub fn issue_world_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline_data: &PbrPipelineData,
+    ) -> Result<()> {
+        self.issue_commands(command_buffer, pipeline_data, false, glm::Vec2::zeros())
+    }
+
+    pub fn issue_screen_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline_data: &PbrPipelineData,
+        viewport: Viewport,
+    ) -> Result<()> {
+        // Sprites are positioned relative to the viewport rather than the whole window
+        let vulkan_viewport = vk::Viewport {
+            x: viewport.x,
+            y: viewport.y,
+            width: viewport.width,
+            height: viewport.height,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        unsafe {
+            self.context
+                .device
+                .handle
+                .cmd_set_viewport(command_buffer, 0, &[vulkan_viewport]);
+        }
+        self.issue_commands(
+            command_buffer,
+            pipeline_data,
+            true,
+            glm::vec2(viewport.width, viewport.height),
+        )
+    }
+
+    fn issue_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline_data: &PbrPipelineData,
+        screen_space: bool,
+        screen_size: glm::Vec2,
+    ) -> Result<()> {
+        if !self
+            .batches
+            .iter()
+            .any(|batch| batch.screen_space == screen_space)
+        {
+            return Ok(());
+        }
+
+        let pipeline = if screen_space {
+            self.screen_pipeline.as_ref()
+        } else {
+            self.world_pipeline.as_ref()
+        };
+        let (pipeline, pipeline_layout) = match (pipeline, self.pipeline_layout.as_ref()) {
+            (Some(pipeline), Some(pipeline_layout)) => (pipeline, pipeline_layout),
+            _ => return Ok(()),
+        };
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        pipeline.bind(device, command_buffer);
+
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout.handle,
+                0,
+                &[pipeline_data.descriptor_set()],
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.handle()], &[0]);
+        }
+
+        for batch in self
+            .batches
+            .iter()
+            .filter(|batch| batch.screen_space == screen_space)
+        {
+            let push_constants = SpritePushConstantBlock {
+                view_projection: self.view_projection,
+                camera_right: glm::row(&self.view, 0).transpose(),
+                camera_up: glm::row(&self.view, 1).transpose(),
+                screen_size,
+                screen_space: screen_space as i32,
+                texture_index: batch.texture_index,
+            };
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    pipeline_layout.handle,
+                    vk::ShaderStageFlags::ALL_GRAPHICS,
+                    0,
+                    byte_slice_from(&push_constants),
+                );
+                device.cmd_draw(
+                    command_buffer,
+                    batch.number_of_vertices,
+                    1,
+                    batch.first_vertex,
+                    0,
+                );
+            }
+            commands.record_draw(batch.number_of_vertices / 3, 1);
+        }
+
+        Ok(())
+    }
+}
+
+// The corners of a quad are offset from its anchor, from the corner with the top left uv to the one with the bottom right uv
+struct Quad {
+    anchor: glm::Vec3,
+    min: glm::Vec2,
+    max: glm::Vec2,
+    tint: glm::Vec4,
+}
+
+impl Quad {
+    fn add_vertices(&self, vertices: &mut Vec<SpriteVertex>) {
+        let corners = [
+            glm::vec2(0.0, 0.0),
+            glm::vec2(1.0, 0.0),
+            glm::vec2(1.0, 1.0),
+            glm::vec2(0.0, 1.0),
+        ];
+        for corner in [0, 1, 2, 2, 3, 0] {
+            let uv = corners[corner];
+            vertices.push(SpriteVertex {
+                anchor: self.anchor,
+                offset: self.min + (self.max - self.min).component_mul(&uv),
+                uv,
+                tint: self.tint,
+            });
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use crate::byte_slice_from;
+use anyhow::Result;
+use dragonglass_vulkan::{
+    ash::vk,
+    core::{
+        CommandPool, Context, CpuToGpuBuffer, DescriptorPool, DescriptorSetLayout, Device,
+        GraphicsPipelineSettingsBuilder, ImageDescription, Pipeline, PipelineLayout, RenderPass,
+        Sampler, ShaderCache, ShaderPathSetBuilder, Texture,
+    },
+};
+use dragonglass_world::{SdfFont, Text, TextSpace, Transform, Viewport, World};
+use log::warn;
+use nalgebra_glm as glm;
+use std::{collections::HashMap, mem, sync::Arc};
+
+pub struct TextPushConstantBlock {
+    pub view_projection: glm::Mat4,
+    // Glyphs of world space text are laid out along these to face the camera
+    pub camera_right: glm::Vec4,
+    pub camera_up: glm::Vec4,
+    pub screen_size: glm::Vec2,
+    pub screen_space: i32,
+}
+
+// Glyph corners are offset from the anchor, in world units with y up for world space text
+// and in pixels with y down for screen space text
+#[derive(Debug, Copy, Clone)]
+pub struct TextVertex {
+    pub anchor: glm::Vec3,
+    pub offset: glm::Vec2,
+    pub uv: glm::Vec2,
+    pub color: glm::Vec4,
+}
+
+// A run of glyphs that share a font and a space, drawn with a single draw call
+struct TextBatch {
+    font: String,
+    space: TextSpace,
+    first_vertex: u32,
+    number_of_vertices: u32,
+}
+
+struct FontTexture {
+    _texture: Texture,
+    descriptor_set: vk::DescriptorSet,
+}
+
+// Draws the world's text from its signed distance field fonts.
+// World space text is drawn into the scene and screen space text is drawn over the post processed image.
+pub struct TextRender {
+    pub world_pipeline: Option<Pipeline>,
+    pub screen_pipeline: Option<Pipeline>,
+    pub pipeline_layout: Option<PipelineLayout>,
+    pub view_projection: glm::Mat4,
+    pub view: glm::Mat4,
+    vertex_buffer: CpuToGpuBuffer,
+    batches: Vec<TextBatch>,
+    // Fonts are uploaded the first time they are drawn with and kept until the renderer is dropped
+    fonts: HashMap<String, FontTexture>,
+    sampler: Sampler,
+    descriptor_pool: DescriptorPool,
+    descriptor_set_layout: Arc<DescriptorSetLayout>,
+    context: Arc<Context>,
+}
+
+impl TextRender {
+    pub const MAX_NUMBER_OF_FONTS: u32 = 16;
+
+    // The vertex buffer starts out with room for this many glyphs and doubles in size whenever it runs out
+    pub const INITIAL_NUMBER_OF_GLYPHS: usize = 4096;
+
+    const VERTICES_PER_GLYPH: usize = 6;
+
+    pub fn new(context: Arc<Context>) -> Result<Self> {
+        let device = context.device.clone();
+        let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(device.clone())?);
+        let descriptor_pool = Self::descriptor_pool(device.clone())?;
+        let vertex_buffer = Self::vertex_buffer(&context, Self::INITIAL_NUMBER_OF_GLYPHS)?;
+
+        // Distance fields are interpolated, so they stay sharp when magnified
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .anisotropy_enable(false)
+            .max_anisotropy(1.0)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .mip_lod_bias(0.0)
+            .min_lod(0.0)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = Sampler::new(device, sampler_info)?;
+
+        Ok(Self {
+            world_pipeline: None,
+            screen_pipeline: None,
+            pipeline_layout: None,
+            view_projection: glm::Mat4::identity(),
+            view: glm::Mat4::identity(),
+            vertex_buffer,
+            batches: Vec::new(),
+            fonts: HashMap::new(),
+            sampler,
+            descriptor_pool,
+            descriptor_set_layout,
+            context,
+        })
+    }
+
+    // World space text is depth tested against the scene in the offscreen pass,
+    // while screen space text is drawn in the fullscreen pass before the gui
+    pub fn create_pipelines(
+        &mut self,
+        shader_cache: &mut ShaderCache,
+        offscreen_render_pass: Arc<RenderPass>,
+        fullscreen_render_pass: Arc<RenderPass>,
+        samples: vk::SampleCountFlags,
+    ) -> Result<()> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .size(mem::size_of::<TextPushConstantBlock>() as u32)
+            .build();
+
+        let shader_paths = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/text/text.vert.spv")
+            .fragment("assets/shaders/text/text.frag.spv")
+            .build()?;
+        let shader_set =
+            shader_cache.create_shader_set(self.context.device.clone(), &shader_paths)?;
+
+        let mut settings = GraphicsPipelineSettingsBuilder::default();
+        settings
+            .render_pass(offscreen_render_pass)
+            .vertex_inputs(Self::vertex_inputs().to_vec())
+            .vertex_attributes(Self::vertex_attributes().to_vec())
+            .descriptor_set_layout(self.descriptor_set_layout.clone())
+            .shader_set(shader_set)
+            .rasterization_samples(samples)
+            .push_constant_range(push_constant_range)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .blended(true)
+            .depth_test_enabled(true)
+            .depth_write_enabled(false)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+
+        let mut screen_settings = settings.clone();
+        screen_settings
+            .render_pass(fullscreen_render_pass)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .depth_test_enabled(false);
+
+        self.world_pipeline = None;
+        self.screen_pipeline = None;
+        self.pipeline_layout = None;
+
+        let (world_pipeline, pipeline_layout) = settings
+            .name("text world")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+        let (screen_pipeline, _) = screen_settings
+            .name("text screen")
+            .build()?
+            .create_pipeline(self.context.device.clone())?;
+
+        self.world_pipeline = Some(world_pipeline);
+        self.screen_pipeline = Some(screen_pipeline);
+        self.pipeline_layout = Some(pipeline_layout);
+        Ok(())
+    }
+
+    fn vertex_attributes() -> [vk::VertexInputAttributeDescription; 4] {
+        let float_size = std::mem::size_of::<f32>();
+
+        let anchor = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+
+        let offset = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset((3 * float_size) as _)
+            .build();
+
+        let uv = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset((5 * float_size) as _)
+            .build();
+
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((7 * float_size) as _)
+            .build();
+
+        [anchor, offset, uv, color]
+    }
+
+    fn vertex_inputs() -> [vk::VertexInputBindingDescription; 1] {
+        let vertex_input_binding_description = vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(mem::size_of::<TextVertex>() as _)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build();
+        [vertex_input_binding_description]
+    }
+
+    fn descriptor_set_layout(device: Arc<Device>) -> Result<DescriptorSetLayout> {
+        let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let bindings = [sampler_binding];
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        DescriptorSetLayout::new(device, create_info)
+    }
+
+    // Each font gets its own descriptor set
+    fn descriptor_pool(device: Arc<Device>) -> Result<DescriptorPool> {
+        let sampler_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: Self::MAX_NUMBER_OF_FONTS,
+        };
+
+        let pool_sizes = [sampler_pool_size];
+
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&pool_sizes)
+            .max_sets(Self::MAX_NUMBER_OF_FONTS);
+
+        DescriptorPool::new(device, pool_info)
+    }
+
+    fn vertex_buffer(context: &Context, number_of_glyphs: usize) -> Result<CpuToGpuBuffer> {
+        CpuToGpuBuffer::vertex_buffer(
+            context.device.clone(),
+            context.allocator.clone(),
+            "text vertices",
+            (number_of_glyphs * Self::VERTICES_PER_GLYPH * mem::size_of::<TextVertex>()) as _,
+        )
+    }
+
+    fn load_font(
+        &mut self,
+        name: &str,
+        font: &SdfFont,
+        command_pool: &CommandPool,
+    ) -> Result<FontTexture> {
+        let description = ImageDescription::from_texture(font.texture())?;
+        let texture = Texture::new(
+            &self.context,
+            command_pool,
+            &format!("{} font", name),
+            &description,
+        )?;
+        let descriptor_set = self.descriptor_pool.allocate_descriptor_sets(
+            self.descriptor_set_layout.handle,
+            &format!("{} font", name),
+            1,
+        )?[0];
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.view.handle)
+            .sampler(self.sampler.handle)
+            .build();
+        let image_infos = [image_info];
+
+        let sampler_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos)
+            .build();
+
+        let descriptor_writes = [sampler_descriptor_write];
+
+        unsafe {
+            self.context
+                .device
+                .handle
+                .update_descriptor_sets(&descriptor_writes, &[])
+        }
+
+        log::info!("Loaded font '{}' for text rendering", name);
+        Ok(FontTexture {
+            _texture: texture,
+            descriptor_set,
+        })
+    }
+
+    // Lays out the world's text and batches the glyphs by space and font
+    pub fn update(
+        &mut self,
+        world: &World,
+        command_pool: &CommandPool,
+        view: glm::Mat4,
+        projection: glm::Mat4,
+    ) -> Result<()> {
+        self.view = view;
+        self.view_projection = projection * view;
+        self.batches.clear();
+
+        let mut texts = world.texts()?;
+        if texts.is_empty() {
+            return Ok(());
+        }
+        texts.sort_by(|(_, a), (_, b)| {
+            (a.space == TextSpace::Screen, &a.font).cmp(&(b.space == TextSpace::Screen, &b.font))
+        });
+
+        let mut vertices = Vec::new();
+        for (transform, text) in texts.iter() {
+            let font = match world.assets.fonts.get(&text.font) {
+                Some(font) => font,
+                None => {
+                    warn!("Failed to find font '{}' to draw text with", text.font);
+                    continue;
+                }
+            };
+
+            if !self.fonts.contains_key(&text.font) {
+                if self.fonts.len() >= Self::MAX_NUMBER_OF_FONTS as usize {
+                    warn!(
+                        "Failed to load font '{}', only {} fonts can be drawn with",
+                        text.font,
+                        Self::MAX_NUMBER_OF_FONTS
+                    );
+                    continue;
+                }
+                let font_texture = self.load_font(&text.font, font, command_pool)?;
+                self.fonts.insert(text.font.to_string(), font_texture);
+            }
+
+            let first_vertex = vertices.len() as u32;
+            Self::add_glyphs(&mut vertices, transform, text, font)?;
+            let number_of_vertices = vertices.len() as u32 - first_vertex;
+
+            match self.batches.last_mut() {
+                Some(batch) if batch.space == text.space && batch.font == text.font => {
+                    batch.number_of_vertices += number_of_vertices;
+                }
+                _ => self.batches.push(TextBatch {
+                    font: text.font.to_string(),
+                    space: text.space,
+                    first_vertex,
+                    number_of_vertices,
+                }),
+            }
+        }
+
+        let capacity = self.vertex_buffer.size() as usize / mem::size_of::<TextVertex>();
+        if vertices.len() > capacity {
+            // Frames in flight may still be reading the buffer being replaced
+            unsafe { self.context.device.handle.device_wait_idle() }?;
+            let number_of_glyphs = vertices.len() / Self::VERTICES_PER_GLYPH;
+            self.vertex_buffer =
+                Self::vertex_buffer(&self.context, number_of_glyphs.next_power_of_two())?;
+        }
+        self.vertex_buffer.upload_data(&vertices, 0)?;
+
+        Ok(())
+    }
+
+    fn add_glyphs(
+        vertices: &mut Vec<TextVertex>,
+        transform: &Transform,
+        text: &Text,
+        font: &SdfFont,
+    ) -> Result<()> {
+        let glyphs = font.layout(&text.text)?;
+        let scale = text.size / font.line_height().max(1.0);
+
+        // World space text is centered on its anchor and has y pointing up
+        let (origin, flip) = match text.space {
+            TextSpace::Screen => (glm::Vec2::zeros(), 1.0),
+            TextSpace::World => {
+                let (min, max) = glyphs.iter().fold(
+                    (glm::vec2(f32::MAX, f32::MAX), glm::vec2(f32::MIN, f32::MIN)),
+                    |(min, max), glyph| {
+                        (
+                            glm::min2(&min, &glyph.position),
+                            glm::max2(&max, &(glyph.position + glyph.size)),
+                        )
+                    },
+                );
+                ((min + max) / 2.0, -1.0)
+            }
+        };
+
+        for glyph in glyphs.iter() {
+            let corners = [
+                (
+                    glm::vec2(0.0, 0.0),
+                    glm::vec2(glyph.uv_min.x, glyph.uv_min.y),
+                ),
+                (
+                    glm::vec2(1.0, 0.0),
+                    glm::vec2(glyph.uv_max.x, glyph.uv_min.y),
+                ),
+                (
+                    glm::vec2(1.0, 1.0),
+                    glm::vec2(glyph.uv_max.x, glyph.uv_max.y),
+                ),
+                (
+                    glm::vec2(0.0, 1.0),
+                    glm::vec2(glyph.uv_min.x, glyph.uv_max.y),
+                ),
+            ];
+            for corner in [0, 1, 2, 2, 3, 0] {
+                let (position, uv) = corners[corner];
+                let offset =
+                    (glyph.position + glyph.size.component_mul(&position) - origin) * scale;
+                vertices.push(TextVertex {
+                    anchor: transform.translation,
+                    offset: glm::vec2(offset.x, offset.y * flip),
+                    uv,
+                    color: text.color,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn issue_world_commands(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+        self.issue_commands(command_buffer, TextSpace::World, glm::Vec2::zeros())
+    }
+
+    pub fn issue_screen_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        viewport: Viewport,
+    ) -> Result<()> {
+        // Screen space text is positioned relative to the viewport rather than the whole window
+        let vulkan_viewport = vk::Viewport {
+            x: viewport.x,
+            y: viewport.y,
+            width: viewport.width,
+            height: viewport.height,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        unsafe {
+            self.context
+                .device
+                .handle
+                .cmd_set_viewport(command_buffer, 0, &[vulkan_viewport]);
+        }
+        self.issue_commands(
+            command_buffer,
+            TextSpace::Screen,
+            glm::vec2(viewport.width, viewport.height),
+        )
+    }
+
+    fn issue_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        space: TextSpace,
+        screen_size: glm::Vec2,
+    ) -> Result<()> {
+        if !self.batches.iter().any(|batch| batch.space == space) {
+            return Ok(());
+        }
+
+        let pipeline = match space {
+            TextSpace::World => self.world_pipeline.as_ref(),
+            TextSpace::Screen => self.screen_pipeline.as_ref(),
+        };
+        let (pipeline, pipeline_layout) = match (pipeline, self.pipeline_layout.as_ref()) {
+            (Some(pipeline), Some(pipeline_layout)) => (pipeline, pipeline_layout),
+            _ => return Ok(()),
+        };
+
+        let device = &self.context.device.handle;
+        let commands = &self.context.device.commands;
+        pipeline.bind(device, command_buffer);
+
+        let push_constants = TextPushConstantBlock {
+            view_projection: self.view_projection,
+            camera_right: glm::row(&self.view, 0).transpose(),
+            camera_up: glm::row(&self.view, 1).transpose(),
+            screen_size,
+            screen_space: (space == TextSpace::Screen) as i32,
+        };
+
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout.handle,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                byte_slice_from(&push_constants),
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.handle()], &[0]);
+        }
+
+        for batch in self.batches.iter().filter(|batch| batch.space == space) {
+            let font = match self.fonts.get(&batch.font) {
+                Some(font) => font,
+                None => continue,
+            };
+            unsafe {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout.handle,
+                    0,
+                    &[font.descriptor_set],
+                    &[],
+                );
+                device.cmd_draw(
+                    command_buffer,
+                    batch.number_of_vertices,
+                    1,
+                    batch.first_vertex,
+                    0,
+                );
+            }
+            commands.record_draw(batch.number_of_vertices / 3, 1);
+        }
+
+        Ok(())
+    }
+}
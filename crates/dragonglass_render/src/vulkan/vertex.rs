@@ -0,0 +1,209 @@
+use dragonglass_config::VertexLayout;
+use dragonglass_vulkan::ash::vk;
+use dragonglass_world::Vertex;
+use half::f16;
+use nalgebra_glm as glm;
+use std::mem;
+
+// Everything but the position and skinning attributes
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct SurfaceAttributes {
+    normal: glm::Vec3,
+    tangent: glm::Vec4,
+    uv_0: glm::Vec2,
+    uv_1: glm::Vec2,
+    color_0: glm::Vec3,
+}
+
+// Normals, tangents, and colors are normalized 16 bit integers, and uvs are half floats.
+// Three component attributes are padded to four components to keep them aligned.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct QuantizedSurfaceAttributes {
+    normal: [i16; 4],
+    tangent: [i16; 4],
+    uv_0: [u16; 2],
+    uv_1: [u16; 2],
+    color_0: [u16; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct SkinningAttributes {
+    joint_0: glm::Vec4,
+    weight_0: glm::Vec4,
+}
+
+// The size of a vertex in each of the layout's vertex buffers
+pub fn stream_strides(layout: VertexLayout) -> Vec<usize> {
+    match layout {
+        VertexLayout::Interleaved => vec![mem::size_of::<Vertex>()],
+        VertexLayout::Streams => vec![
+            mem::size_of::<glm::Vec3>(),
+            mem::size_of::<SurfaceAttributes>(),
+            mem::size_of::<SkinningAttributes>(),
+        ],
+        VertexLayout::QuantizedStreams => vec![
+            mem::size_of::<glm::Vec3>(),
+            mem::size_of::<QuantizedSurfaceAttributes>(),
+            mem::size_of::<SkinningAttributes>(),
+        ],
+    }
+}
+
+// Packs the vertices into the bytes of each of the layout's vertex buffers
+pub fn vertex_streams(layout: VertexLayout, vertices: &[Vertex]) -> Vec<Vec<u8>> {
+    match layout {
+        VertexLayout::Interleaved => vec![as_bytes(vertices)],
+        VertexLayout::Streams => {
+            let surfaces = vertices
+                .iter()
+                .map(|vertex| SurfaceAttributes {
+                    normal: vertex.normal,
+                    tangent: vertex.tangent,
+                    uv_0: vertex.uv_0,
+                    uv_1: vertex.uv_1,
+                    color_0: vertex.color_0,
+                })
+                .collect::<Vec<_>>();
+            vec![
+                position_stream(vertices),
+                as_bytes(&surfaces),
+                skinning_stream(vertices),
+            ]
+        }
+        VertexLayout::QuantizedStreams => {
+            let surfaces = vertices
+                .iter()
+                .map(|vertex| QuantizedSurfaceAttributes {
+                    normal: snorm16(&glm::vec3_to_vec4(&vertex.normal)),
+                    tangent: snorm16(&vertex.tangent),
+                    uv_0: half2(&vertex.uv_0),
+                    uv_1: half2(&vertex.uv_1),
+                    color_0: unorm16(&glm::vec3_to_vec4(&vertex.color_0)),
+                })
+                .collect::<Vec<_>>();
+            vec![
+                position_stream(vertices),
+                as_bytes(&surfaces),
+                skinning_stream(vertices),
+            ]
+        }
+    }
+}
+
+pub fn vertex_inputs(layout: VertexLayout) -> Vec<vk::VertexInputBindingDescription> {
+    stream_strides(layout)
+        .into_iter()
+        .enumerate()
+        .map(|(binding, stride)| {
+            vk::VertexInputBindingDescription::builder()
+                .binding(binding as _)
+                .stride(stride as _)
+                .input_rate(vk::VertexInputRate::VERTEX)
+                .build()
+        })
+        .collect()
+}
+
+// The shader inputs are the same for every layout,
+// since quantized attributes are converted back to floats as they are fetched
+pub fn vertex_attributes(layout: VertexLayout) -> Vec<vk::VertexInputAttributeDescription> {
+    let float_size = mem::size_of::<f32>();
+    let attribute = |location: u32, binding: u32, format: vk::Format, offset: usize| {
+        vk::VertexInputAttributeDescription::builder()
+            .binding(binding)
+            .location(location)
+            .format(format)
+            .offset(offset as _)
+            .build()
+    };
+    let vec2 = vk::Format::R32G32_SFLOAT;
+    let vec3 = vk::Format::R32G32B32_SFLOAT;
+    let vec4 = vk::Format::R32G32B32A32_SFLOAT;
+
+    match layout {
+        VertexLayout::Interleaved => vec![
+            attribute(0, 0, vec3, 0),
+            attribute(1, 0, vec3, 3 * float_size),
+            attribute(2, 0, vec2, 6 * float_size),
+            attribute(3, 0, vec2, 8 * float_size),
+            attribute(4, 0, vec4, 10 * float_size),
+            attribute(5, 0, vec4, 14 * float_size),
+            attribute(6, 0, vec3, 18 * float_size),
+            attribute(7, 0, vec4, 21 * float_size),
+        ],
+        VertexLayout::Streams => vec![
+            attribute(0, 0, vec3, 0),
+            attribute(1, 1, vec3, 0),
+            attribute(7, 1, vec4, 3 * float_size),
+            attribute(2, 1, vec2, 7 * float_size),
+            attribute(3, 1, vec2, 9 * float_size),
+            attribute(6, 1, vec3, 11 * float_size),
+            attribute(4, 2, vec4, 0),
+            attribute(5, 2, vec4, 4 * float_size),
+        ],
+        VertexLayout::QuantizedStreams => vec![
+            attribute(0, 0, vec3, 0),
+            attribute(1, 1, vk::Format::R16G16B16A16_SNORM, 0),
+            attribute(7, 1, vk::Format::R16G16B16A16_SNORM, 8),
+            attribute(2, 1, vk::Format::R16G16_SFLOAT, 16),
+            attribute(3, 1, vk::Format::R16G16_SFLOAT, 20),
+            attribute(6, 1, vk::Format::R16G16B16A16_UNORM, 24),
+            attribute(4, 2, vec4, 0),
+            attribute(5, 2, vec4, 4 * float_size),
+        ],
+    }
+}
+
+fn position_stream(vertices: &[Vertex]) -> Vec<u8> {
+    let positions = vertices
+        .iter()
+        .map(|vertex| vertex.position)
+        .collect::<Vec<_>>();
+    as_bytes(&positions)
+}
+
+fn skinning_stream(vertices: &[Vertex]) -> Vec<u8> {
+    let skinning = vertices
+        .iter()
+        .map(|vertex| SkinningAttributes {
+            joint_0: vertex.joint_0,
+            weight_0: vertex.weight_0,
+        })
+        .collect::<Vec<_>>();
+    as_bytes(&skinning)
+}
+
+fn snorm16(value: &glm::Vec4) -> [i16; 4] {
+    let quantize = |value: f32| (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+    [
+        quantize(value.x),
+        quantize(value.y),
+        quantize(value.z),
+        quantize(value.w),
+    ]
+}
+
+fn unorm16(value: &glm::Vec4) -> [u16; 4] {
+    let quantize = |value: f32| (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+    [
+        quantize(value.x),
+        quantize(value.y),
+        quantize(value.z),
+        quantize(value.w),
+    ]
+}
+
+fn half2(value: &glm::Vec2) -> [u16; 2] {
+    [
+        f16::from_f32(value.x).to_bits(),
+        f16::from_f32(value.y).to_bits(),
+    ]
+}
+
+fn as_bytes<T: Copy>(values: &[T]) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+        .to_vec()
+}
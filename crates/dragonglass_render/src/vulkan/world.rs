@@ -1,239 +1,228 @@
-use crate::byte_slice_from;
+use crate::{
+    byte_slice_from,
+    uniforms::{self, EntityData, PushConstantMaterial, WorldUniformBuffer},
+};
 use anyhow::{ensure, Context as AnyhowContext, Result};
+use dragonglass_config::VertexLayout;
 use dragonglass_vulkan::{
     ash::vk,
     core::{
-        CommandPool, Context, CpuToGpuBuffer, DescriptorPool, DescriptorSetLayout, Device,
+        CommandPool, Context, CpuToGpuBuffer, Cubemap, DescriptorPool, DescriptorSetLayout, Device,
         GeometryBuffer, GraphicsPipelineSettingsBuilder, ImageDescription, Pipeline,
         PipelineLayout, RenderPass, Sampler, ShaderCache, ShaderPathSet, ShaderPathSetBuilder,
-        Texture,
+        Texture, UploadId, UploadQueue,
     },
     geometry::Cube,
     pbr::EnvironmentMapSet,
     render::CubeRender,
 };
 use dragonglass_world::{
-    legion::EntityStore, AlphaMode, Filter, Geometry, Hidden, LightKind, Material, Mesh,
-    MeshRender, Skin, Transform, Vertex, World, WrappingMode,
+    legion::EntityStore, AlphaMode, Filter, Hidden, Layers, Lod, Material, MeshRender, Primitive,
+    ShadingModel, Vertex, World, WrappingMode,
 };
 use nalgebra_glm as glm;
-use std::{mem, sync::Arc};
-
-pub struct PushConstantMaterial {
-    pub base_color_factor: glm::Vec4,
-    pub emissive_factor: glm::Vec3,
-    pub color_texture_index: i32,
-    pub color_texture_set: i32,
-    pub metallic_roughness_texture_index: i32,
-    pub metallic_roughness_texture_set: i32, // B channel - metalness values. G channel - roughness values
-    pub normal_texture_index: i32,
-    pub normal_texture_set: i32,
-    pub normal_texture_scale: f32,
-    pub occlusion_texture_index: i32,
-    pub occlusion_texture_set: i32, // R channel - occlusion values
-    pub occlusion_strength: f32,
-    pub emissive_texture_index: i32,
-    pub emissive_texture_set: i32,
-    pub metallic_factor: f32,
-    pub roughness_factor: f32,
-    pub alpha_mode: i32,
-    pub alpha_cutoff: f32,
-    pub is_unlit: i32,
-}
-
-impl From<&Material> for PushConstantMaterial {
-    fn from(material: &Material) -> Self {
-        Self {
-            base_color_factor: material.base_color_factor,
-            metallic_factor: material.metallic_factor,
-            roughness_factor: material.roughness_factor,
-            emissive_factor: material.emissive_factor,
-            alpha_mode: material.alpha_mode as i32,
-            alpha_cutoff: material.alpha_cutoff,
-            is_unlit: if material.is_unlit { 1 } else { 0 },
-            color_texture_index: material.color_texture_index,
-            color_texture_set: material.color_texture_set,
-            metallic_roughness_texture_index: material.metallic_roughness_texture_index,
-            metallic_roughness_texture_set: material.metallic_roughness_texture_set,
-            normal_texture_index: material.normal_texture_index,
-            normal_texture_set: material.normal_texture_set,
-            normal_texture_scale: material.normal_texture_scale,
-            occlusion_texture_index: material.occlusion_texture_index,
-            occlusion_texture_set: material.occlusion_texture_set,
-            occlusion_strength: material.occlusion_strength,
-            emissive_texture_index: material.emissive_texture_index,
-            emissive_texture_set: material.emissive_texture_set,
-        }
-    }
-}
-
-#[derive(Default, Debug, Copy, Clone)]
-pub struct Light {
-    pub direction: glm::Vec3,
-    pub range: f32,
-
-    pub color: glm::Vec3,
-    pub intensity: f32,
-
-    pub position: glm::Vec3,
-    pub inner_cone_cos: f32,
-
-    pub outer_cone_cos: f32,
-    pub kind: i32,
-
-    pub padding: glm::Vec2,
-}
-
-impl Light {
-    pub fn from_node(transform: &Transform, light: &dragonglass_world::Light) -> Self {
-        let mut inner_cone_cos: f32 = 0.0;
-        let mut outer_cone_cos: f32 = 0.0;
-        let kind = match light.kind {
-            LightKind::Directional => 0,
-            LightKind::Point => 1,
-            LightKind::Spot {
-                inner_cone_angle,
-                outer_cone_angle,
-            } => {
-                inner_cone_cos = inner_cone_angle;
-                outer_cone_cos = outer_cone_angle;
-                2
-            }
-        };
-        Self {
-            direction: -1.0 * glm::quat_rotate_vec3(&transform.rotation, &glm::Vec3::z()),
-            range: light.range,
-            color: light.color,
-            intensity: light.intensity,
-            position: transform.translation,
-            inner_cone_cos,
-            outer_cone_cos,
-            kind,
-            padding: glm::vec2(0.0, 0.0),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct WorldUniformBuffer {
-    pub view: glm::Mat4,
-    pub projection: glm::Mat4,
-    pub camera_position: glm::Vec3,
-    pub number_of_lights: u32,
-    pub joint_matrices: [glm::Mat4; PbrPipelineData::MAX_NUMBER_OF_JOINTS],
-    pub lights: [Light; PbrPipelineData::MAX_NUMBER_OF_LIGHTS],
-}
-
-#[derive(Default, Debug, Clone, Copy)]
-pub struct EntityDynamicUniformBuffer {
-    pub model: glm::Mat4,
-    // X is the joint count.
-    // Y is the joint matrix offset.
-    // A vec4 is needed to meet shader uniform data layout requirements
-    pub node_info: glm::Vec4,
+use std::{mem, ops::Range, sync::Arc};
+
+use super::{skinning::SkinningPass, vertex};
+
+// How much of a world texture is on the gpu, which is lowered to stay within the texture budget
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureResidency {
+    Full,
+    // Without this many of its largest levels
+    Downscaled(u32),
+    // Sampled as the dummy texture until it is drawn again
+    Evicted,
 }
 
 pub struct PbrPipelineData {
     pub uniform_buffer: CpuToGpuBuffer,
-    pub dynamic_uniform_buffer: CpuToGpuBuffer,
-    pub dynamic_alignment: u64,
+    // The aligned size of each view's uniforms, and the slot the next draws read
+    uniform_stride: vk::DeviceSize,
+    uniform_slot: usize,
+    // Grown as the world's entities and joints outgrow them
+    pub entity_buffer: CpuToGpuBuffer,
+    pub joint_buffer: CpuToGpuBuffer,
     pub descriptor_set_layout: Arc<DescriptorSetLayout>,
     pub descriptor_pool: DescriptorPool,
-    pub descriptor_set: vk::DescriptorSet,
-    pub textures: Vec<Texture>,
+    // A set per uniform slot, which only differ in the uniforms they point to
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    // Evicted textures are None
+    pub textures: Vec<Option<Texture>>,
     pub samplers: Vec<Sampler>,
+    // The world's revision of each texture when it was uploaded
+    pub texture_revisions: Vec<u64>,
+    pub texture_residency: Vec<TextureResidency>,
+    // The frame each texture was last drawn in, so the least recently used go first
+    pub texture_last_used: Vec<u64>,
+    frame: u64,
+    // Texture slots that are bound to the dummy texture until their uploads are ready
+    pub pending_textures: Vec<(usize, UploadId)>,
     pub geometry_buffer: GeometryBuffer,
+    // How the vertices are split across the geometry buffer's vertex streams
+    pub vertex_layout: VertexLayout,
+    // How much of the world's geometry has been uploaded to the geometry buffer
+    pub number_of_vertices: usize,
+    pub number_of_indices: usize,
+    // The world geometry's generation when it was uploaded
+    pub geometry_generation: u64,
+    // How much of the geometry buffer can be drawn, which trails behind what was uploaded
+    // until the pending geometry uploads are ready
+    pub drawable_vertices: usize,
+    pub drawable_indices: usize,
+    pub pending_geometry: Vec<UploadId>,
     pub dummy_texture: Texture,
     pub dummy_sampler: Sampler,
+    // The number of texture slots in the descriptor set
+    pub texture_capacity: usize,
+    // Whether skinned entities are skinned in a compute pass instead of as they are drawn
+    pub compute_skinning: bool,
 }
 
 impl PbrPipelineData {
-    // These should match the constants defined in the shader
-    pub const MAX_NUMBER_OF_TEXTURES: usize = 200; // TODO: check that this is not larger than the physical device's maxDescriptorSetSamplers
-    pub const MAX_NUMBER_OF_JOINTS: usize = 1000;
-    pub const MAX_NUMBER_OF_LIGHTS: usize = 4; // TODO: Increase this once a deferred or forward+ pipeline is in use
-
-    // This does not need to be matched in the shader
-    pub const MAX_NUMBER_OF_MESHES: usize = 500;
-
+    // The number of texture slots without descriptor indexing, which matches the default in the shader
+    pub const MAX_NUMBER_OF_TEXTURES: usize = 200;
+    pub const MAX_NUMBER_OF_LIGHTS: usize = uniforms::MAX_NUMBER_OF_LIGHTS;
+    pub const MAX_NUMBER_OF_REFLECTION_PROBES: usize = uniforms::MAX_NUMBER_OF_REFLECTION_PROBES;
+    // The brdflut, prefilter and irradiance maps, and each reflection probe's prefilter and irradiance maps,
+    // which are sampled from the same descriptor set as the textures
+    pub const NUMBER_OF_ENVIRONMENT_SAMPLERS: usize = 3 + 2 * Self::MAX_NUMBER_OF_REFLECTION_PROBES;
+
+    // The main view's uniforms use the first slot and each additional window gets its own,
+    // so views don't overwrite the camera of frames still in flight
+    pub const MAX_NUMBER_OF_VIEWS: usize = 4;
+    pub const NUMBER_OF_UNIFORM_SLOTS: usize = Self::MAX_NUMBER_OF_VIEWS + 1;
+
+    pub const INITIAL_NUMBER_OF_ENTITIES: usize = uniforms::INITIAL_NUMBER_OF_ENTITIES;
+    pub const INITIAL_NUMBER_OF_JOINTS: usize = uniforms::INITIAL_NUMBER_OF_JOINTS;
+    pub const MINIMUM_GEOMETRY_BUFFER_SIZE: vk::DeviceSize = uniforms::MINIMUM_GEOMETRY_BUFFER_SIZE;
+
+    // Textures drawn within the budget are downscaled to at most an eighth of their size
+    pub const MAX_DOWNSCALED_LEVELS: u32 = 3;
+
+    // Starts out without any textures or geometry, which are then uploaded through the upload queue
+    // The texture slots are sized to fit the given number of textures
     pub fn new(
         context: &Context,
         command_pool: &CommandPool,
-        world: &World,
         environment_maps: &EnvironmentMapSet,
+        vertex_layout: VertexLayout,
+        number_of_textures: usize,
     ) -> Result<Self> {
         let device = context.device.clone();
         let allocator = context.allocator.clone();
 
-        let mut textures = Vec::new();
-        let mut samplers = Vec::new();
-        for texture in world.textures.iter() {
-            let description = ImageDescription::from_texture(texture)?;
-            textures.push(Texture::new(context, command_pool, &description)?);
-            samplers.push(map_sampler(
-                device.clone(),
-                description.mip_levels,
-                &texture.sampler,
-            )?);
-        }
-
-        let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(device.clone())?);
-        let descriptor_pool = Self::descriptor_pool(device.clone())?;
-        let descriptor_set =
-            descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, 1)?[0];
-
-        let uniform_buffer = CpuToGpuBuffer::uniform_buffer(
+        let texture_capacity = Self::texture_capacity(&device, number_of_textures);
+        let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(
             device.clone(),
-            allocator.clone(),
-            mem::size_of::<WorldUniformBuffer>() as _,
+            texture_capacity,
+        )?);
+        let descriptor_pool = Self::descriptor_pool(device.clone(), texture_capacity)?;
+        let descriptor_sets = descriptor_pool.allocate_descriptor_sets(
+            descriptor_set_layout.handle,
+            "world",
+            Self::NUMBER_OF_UNIFORM_SLOTS as _,
         )?;
 
-        let dynamic_alignment = context.dynamic_alignment_of::<EntityDynamicUniformBuffer>();
-        let dynamic_uniform_buffer = CpuToGpuBuffer::uniform_buffer(
+        let uniform_stride = context.dynamic_alignment_of::<WorldUniformBuffer>();
+        let uniform_buffer = CpuToGpuBuffer::uniform_buffer(
             device.clone(),
             allocator,
-            (Self::MAX_NUMBER_OF_MESHES as u64 * dynamic_alignment) as vk::DeviceSize,
+            "world uniforms",
+            uniform_stride * Self::NUMBER_OF_UNIFORM_SLOTS as vk::DeviceSize,
+        )?;
+
+        let entity_buffer = Self::storage_buffer::<EntityData>(
+            context,
+            "entities",
+            Self::INITIAL_NUMBER_OF_ENTITIES,
         )?;
+        let joint_buffer =
+            Self::storage_buffer::<glm::Mat4>(context, "joints", Self::INITIAL_NUMBER_OF_JOINTS)?;
 
-        let geometry_buffer = Self::geometry_buffer(context, command_pool, &world.geometry)?;
+        let geometry_buffer = Self::geometry_buffer(context, vertex_layout)?;
 
         let empty_description = ImageDescription::empty(1, 1, vk::Format::R8G8B8A8_UNORM);
-        let dummy_texture = Texture::new(context, command_pool, &empty_description)?;
+        let dummy_texture =
+            Texture::new(context, command_pool, "dummy texture", &empty_description)?;
         let dummy_sampler = Sampler::default(device.clone())?;
 
         let data = Self {
             descriptor_pool,
             uniform_buffer,
-            dynamic_uniform_buffer,
-            descriptor_set,
-            dynamic_alignment,
+            uniform_stride,
+            uniform_slot: 0,
+            entity_buffer,
+            joint_buffer,
+            descriptor_sets,
             descriptor_set_layout,
-            textures,
-            samplers,
+            textures: Vec::new(),
+            samplers: Vec::new(),
+            texture_revisions: Vec::new(),
+            texture_residency: Vec::new(),
+            texture_last_used: Vec::new(),
+            frame: 0,
+            pending_textures: Vec::new(),
             geometry_buffer,
+            vertex_layout,
+            number_of_vertices: 0,
+            number_of_indices: 0,
+            geometry_generation: 0,
+            drawable_vertices: 0,
+            drawable_indices: 0,
+            pending_geometry: Vec::new(),
             dummy_texture,
             dummy_sampler,
+            texture_capacity,
+            compute_skinning: false,
         };
-        data.update_descriptor_set(context, device, environment_maps);
+        data.update_descriptor_set(context, device.clone(), environment_maps);
+        data.update_reflection_probe_descriptor_set(&device, &[], environment_maps);
         Ok(data)
     }
 
-    pub fn descriptor_set_layout(device: Arc<Device>) -> Result<DescriptorSetLayout> {
+    // With descriptor indexing, the texture slots can be updated while the descriptor set is bound
+    // and slots that no texture is written to are left unbound rather than filled with the dummy texture.
+    // The slots double to fit the textures, up to what the device can sample alongside the environment maps,
+    // so the descriptor set is only recreated once in a while as textures are added.
+    pub fn texture_capacity(device: &Device, number_of_textures: usize) -> usize {
+        if device.descriptor_indexing_supported() {
+            let limit = (device.max_update_after_bind_textures as usize)
+                .saturating_sub(Self::NUMBER_OF_ENVIRONMENT_SAMPLERS);
+            number_of_textures
+                .next_power_of_two()
+                .min(limit)
+                .max(Self::MAX_NUMBER_OF_TEXTURES)
+        } else {
+            Self::MAX_NUMBER_OF_TEXTURES
+        }
+    }
+
+    // Whether recreating the descriptor set would make room for the textures
+    pub fn can_grow_textures(&self, device: &Device, number_of_textures: usize) -> bool {
+        number_of_textures > self.texture_capacity
+            && Self::texture_capacity(device, number_of_textures) > self.texture_capacity
+    }
+
+    pub fn descriptor_set_layout(
+        device: Arc<Device>,
+        texture_capacity: usize,
+    ) -> Result<DescriptorSetLayout> {
         let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
             .binding(0)
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
             .build();
-        let dynamic_ubo_binding = vk::DescriptorSetLayoutBinding::builder()
+        let entity_binding = vk::DescriptorSetLayoutBinding::builder()
             .binding(1)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::VERTEX)
             .build();
         let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
             .binding(2)
-            .descriptor_count(Self::MAX_NUMBER_OF_TEXTURES as _)
+            .descriptor_count(texture_capacity as _)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
             .build();
@@ -255,135 +244,333 @@ impl PbrPipelineData {
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT)
             .build();
+        let joint_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(6)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .build();
+        let probe_prefilter_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(7)
+            .descriptor_count(Self::MAX_NUMBER_OF_REFLECTION_PROBES as _)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let probe_irradiance_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(8)
+            .descriptor_count(Self::MAX_NUMBER_OF_REFLECTION_PROBES as _)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
         let bindings = [
             ubo_binding,
-            dynamic_ubo_binding,
+            entity_binding,
             sampler_binding,
             brdflut_binding,
             prefilter_binding,
             irradiance_binding,
+            joint_binding,
+            probe_prefilter_binding,
+            probe_irradiance_binding,
+        ];
+        let mut create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+        let binding_flags = [
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
+            vk::DescriptorBindingFlags::empty(),
         ];
-        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+        if device.descriptor_indexing_supported() {
+            create_info = create_info
+                .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+                .push_next(&mut binding_flags_info);
+        }
+
         DescriptorSetLayout::new(device, create_info)
     }
 
-    fn descriptor_pool(device: Arc<Device>) -> Result<DescriptorPool> {
+    fn descriptor_pool(device: Arc<Device>, texture_capacity: usize) -> Result<DescriptorPool> {
+        // Every uniform slot has a whole set
+        let sets = Self::NUMBER_OF_UNIFORM_SLOTS as u32;
         let ubo_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: 1,
+            descriptor_count: sets,
         };
 
-        let dynamic_ubo_pool_size = vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
-            descriptor_count: 1,
+        // The entity and joint buffers
+        let storage_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 2 * sets,
         };
 
         let sampler_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: Self::MAX_NUMBER_OF_TEXTURES as _,
+            descriptor_count: texture_capacity as u32 * sets,
         };
 
         let brdflut_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 1,
+            descriptor_count: sets,
         };
 
         let prefilter_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 1,
+            descriptor_count: sets,
         };
 
         let irradiance_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 1,
+            descriptor_count: sets,
+        };
+
+        // The prefilter and irradiance maps of every reflection probe
+        let probe_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 2 * Self::MAX_NUMBER_OF_REFLECTION_PROBES as u32 * sets,
         };
 
         let pool_sizes = [
             ubo_pool_size,
-            dynamic_ubo_pool_size,
+            storage_pool_size,
             sampler_pool_size,
             brdflut_pool_size,
             prefilter_pool_size,
             irradiance_pool_size,
+            probe_pool_size,
         ];
 
-        let create_info = vk::DescriptorPoolCreateInfo::builder()
+        let mut create_info = vk::DescriptorPoolCreateInfo::builder()
             .pool_sizes(&pool_sizes)
-            .max_sets(1);
+            .max_sets(Self::NUMBER_OF_UNIFORM_SLOTS as _);
+        if device.descriptor_indexing_supported() {
+            create_info = create_info.flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND);
+        }
 
         DescriptorPool::new(device, create_info)
     }
 
-    fn geometry_buffer(
-        context: &Context,
-        pool: &CommandPool,
-        geometry: &Geometry,
-    ) -> Result<GeometryBuffer> {
-        let has_indices = !geometry.indices.is_empty();
-        let index_buffer_size = if has_indices {
-            Some((geometry.indices.len() * std::mem::size_of::<u32>()) as _)
-        } else {
-            None
-        };
-
-        let geometry_buffer = GeometryBuffer::new(
+    // Starts out empty, with a buffer for each of the layout's vertex streams
+    fn geometry_buffer(context: &Context, vertex_layout: VertexLayout) -> Result<GeometryBuffer> {
+        let mut geometry_buffer = GeometryBuffer::new(
             context.device.clone(),
             context.allocator.clone(),
-            (geometry.vertices.len() * std::mem::size_of::<Vertex>()) as _,
-            index_buffer_size,
+            "world geometry",
+            Self::MINIMUM_GEOMETRY_BUFFER_SIZE,
+            None,
         )?;
+        for _ in 1..vertex::stream_strides(vertex_layout).len() {
+            geometry_buffer.add_stream(Self::MINIMUM_GEOMETRY_BUFFER_SIZE)?;
+        }
+        Ok(geometry_buffer)
+    }
+
+    // Leaves room for geometry to be added without growing the buffers every time
+    fn geometry_capacity(size: vk::DeviceSize) -> vk::DeviceSize {
+        (size + size / 2).max(Self::MINIMUM_GEOMETRY_BUFFER_SIZE)
+    }
+
+    pub fn geometry_changed(&self, world: &World) -> bool {
+        let geometry = &world.assets.geometry;
+        geometry.vertices.len() != self.number_of_vertices
+            || geometry.indices.len() != self.number_of_indices
+            || geometry.generation() != self.geometry_generation
+    }
 
-        geometry_buffer
-            .vertex_buffer
-            .upload_data(&geometry.vertices, 0, pool)?;
+    // Geometry is mostly appended to, so only what was added since the last upload is uploaded.
+    // Everything is uploaded again if it was rewritten, such as when the world is cleared or compacted,
+    // and buffers that had to grow are filled again from the world's geometry.
+    // Uploads go through the upload queue, so they run on the transfer queue when there is one.
+    // What is being uploaded isn't drawn until the uploads are ready, while what was already uploaded
+    // stays drawable unless it is being replaced.
+    // The geometry buffer must not be in use by any frame in flight.
+    pub fn update_geometry(&mut self, uploads: &mut UploadQueue, world: &World) -> Result<()> {
+        let geometry = &world.assets.geometry;
+        let rewritten = geometry.generation() != self.geometry_generation
+            || geometry.vertices.len() < self.number_of_vertices
+            || geometry.indices.len() < self.number_of_indices;
+        let (first_vertex, first_index) = if rewritten {
+            (0, 0)
+        } else {
+            (self.number_of_vertices, self.number_of_indices)
+        };
+        let vertices_replaced = self.upload_vertices(uploads, &geometry.vertices, first_vertex)?;
+        let indices_replaced = self.upload_indices(uploads, &geometry.indices, first_index)?;
+        if rewritten || vertices_replaced || indices_replaced {
+            self.drawable_vertices = 0;
+            self.drawable_indices = 0;
+        }
+        self.geometry_generation = geometry.generation();
+        Ok(())
+    }
+
+    // Reuploads a range of vertices that have been modified in place.
+    // Only their values change, so the old ones keep being drawn until the new ones arrive.
+    // Vertices that haven't been uploaded yet are left to the next geometry update.
+    pub fn update_vertices(
+        &mut self,
+        uploads: &mut UploadQueue,
+        world: &World,
+        range: Range<usize>,
+    ) -> Result<()> {
+        let vertices = &world.assets.geometry.vertices;
+        let end = range.end.min(vertices.len()).min(self.number_of_vertices);
+        if range.start >= end {
+            return Ok(());
+        }
+        let strides = vertex::stream_strides(self.vertex_layout);
+        let streams = vertex::vertex_streams(self.vertex_layout, &vertices[range.start..end]);
+        for (stream, (stride, data)) in strides.into_iter().zip(streams.into_iter()).enumerate() {
+            if data.is_empty() {
+                continue;
+            }
+            let upload = self
+                .geometry_buffer
+                .stream_buffer(stream)
+                .context("Failed to access vertex stream!")?
+                .upload_data_async(&data, range.start * stride, uploads)?;
+            self.pending_geometry.push(upload);
+        }
+        Ok(())
+    }
+
+    // Growing drops the old buffers, so any uploads still writing to them have to be done first.
+    // Returns whether the vertex buffers were replaced, which leaves nothing drawable until they are filled.
+    fn upload_vertices(
+        &mut self,
+        uploads: &mut UploadQueue,
+        vertices: &[Vertex],
+        first_vertex: usize,
+    ) -> Result<bool> {
+        let strides = vertex::stream_strides(self.vertex_layout);
+        let grows = strides.iter().enumerate().any(|(stream, stride)| {
+            (vertices.len() * stride) as vk::DeviceSize > self.geometry_buffer.stream_size(stream)
+        });
+        let first_vertex = if grows {
+            uploads.wait()?;
+            for (stream, stride) in strides.iter().enumerate() {
+                let size = (vertices.len() * stride) as vk::DeviceSize;
+                if size > self.geometry_buffer.stream_size(stream) {
+                    self.geometry_buffer
+                        .grow_stream(stream, Self::geometry_capacity(size))?;
+                }
+            }
+            0
+        } else {
+            first_vertex
+        };
+        let streams = vertex::vertex_streams(self.vertex_layout, &vertices[first_vertex..]);
+        for (stream, (stride, data)) in strides.into_iter().zip(streams.into_iter()).enumerate() {
+            if !data.is_empty() {
+                let upload = self
+                    .geometry_buffer
+                    .stream_buffer(stream)
+                    .context("Failed to access vertex stream!")?
+                    .upload_data_async(&data, first_vertex * stride, uploads)?;
+                self.pending_geometry.push(upload);
+            }
+        }
+        self.number_of_vertices = vertices.len();
+        Ok(grows)
+    }
 
-        if has_indices {
-            geometry_buffer
+    // Returns whether the index buffer was replaced
+    fn upload_indices(
+        &mut self,
+        uploads: &mut UploadQueue,
+        indices: &[u32],
+        first_index: usize,
+    ) -> Result<bool> {
+        let size = mem::size_of_val(indices) as vk::DeviceSize;
+        let capacity = self.geometry_buffer.index_buffer_size.unwrap_or_default();
+        let grows = size > capacity;
+        let first_index = if grows {
+            uploads.wait()?;
+            self.geometry_buffer
+                .grow_index_buffer(Self::geometry_capacity(size))?;
+            0
+        } else {
+            first_index
+        };
+        if first_index < indices.len() {
+            let upload = self
+                .geometry_buffer
                 .index_buffer
                 .as_ref()
                 .context("Failed to access index buffer!")?
-                .upload_data(&geometry.indices, 0, pool)?;
+                .upload_data_async(
+                    &indices[first_index..],
+                    first_index * mem::size_of::<u32>(),
+                    uploads,
+                )?;
+            self.pending_geometry.push(upload);
         }
+        self.number_of_indices = indices.len();
+        Ok(grows)
+    }
 
-        Ok(geometry_buffer)
+    // Once every pending geometry upload is ready, all of the uploaded geometry can be drawn
+    pub fn update_drawable_geometry(&mut self, uploads: &UploadQueue) {
+        if self
+            .pending_geometry
+            .iter()
+            .all(|upload| uploads.is_ready(*upload))
+        {
+            self.pending_geometry.clear();
+            self.drawable_vertices = self.number_of_vertices;
+            self.drawable_indices = self.number_of_indices;
+        }
+    }
+
+    // Whether the primitive's vertices and indices have finished uploading
+    pub fn is_drawable(&self, primitive: &Primitive) -> bool {
+        self.is_range_drawable(
+            primitive.first_vertex + primitive.number_of_vertices,
+            primitive.first_index + primitive.number_of_indices,
+        )
     }
 
-    fn update_descriptor_set(
+    // Whether the vertices and indices up to the given ends have finished uploading
+    pub fn is_range_drawable(&self, vertex_end: usize, index_end: usize) -> bool {
+        vertex_end <= self.drawable_vertices && index_end <= self.drawable_indices
+    }
+
+    pub fn update_descriptor_set(
         &self,
         context: &Context,
         device: Arc<Device>,
         environment_maps: &EnvironmentMapSet,
     ) {
         let uniform_buffer_size = mem::size_of::<WorldUniformBuffer>() as vk::DeviceSize;
-        let buffer_info = vk::DescriptorBufferInfo::builder()
-            .buffer(self.uniform_buffer.handle())
-            .offset(0)
-            .range(uniform_buffer_size)
-            .build();
-        let buffer_infos = [buffer_info];
-
-        let dynamic_buffer_info = vk::DescriptorBufferInfo::builder()
-            .buffer(self.dynamic_uniform_buffer.handle())
-            .offset(0)
-            .range(context.dynamic_alignment_of::<EntityDynamicUniformBuffer>())
-            .build();
-        let dynamic_buffer_infos = [dynamic_buffer_info];
-
-        let mut image_infos = self
-            .textures
-            .iter()
-            .zip(self.samplers.iter())
-            .map(|(texture, sampler)| {
-                vk::DescriptorImageInfo::builder()
-                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                    .image_view(texture.view.handle)
-                    .sampler(sampler.handle)
-                    .build()
+        let buffer_infos = (0..self.descriptor_sets.len())
+            .map(|slot| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(self.uniform_buffer.handle())
+                    .offset(slot as vk::DeviceSize * self.uniform_stride)
+                    .range(uniform_buffer_size)
+                    .build()]
             })
             .collect::<Vec<_>>();
 
+        let mut image_infos = (0..self.textures.len())
+            .map(|slot| self.texture_slot(slot))
+            .collect::<Vec<_>>();
+
+        // Partially bound slots don't need to be filled
         let number_of_images = image_infos.len();
-        let required_images = Self::MAX_NUMBER_OF_TEXTURES;
+        let required_images = if device.descriptor_indexing_supported() {
+            number_of_images.max(1)
+        } else {
+            self.texture_capacity
+        };
         if number_of_images < required_images {
             let remaining = required_images - number_of_images;
             for _ in 0..remaining {
@@ -418,24 +605,28 @@ impl PbrPipelineData {
             .build();
         let irradiance_image_infos = [irradiance_image_info];
 
-        let ubo_descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set)
-            .dst_binding(0)
-            .dst_array_element(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-            .buffer_info(&buffer_infos)
-            .build();
-
-        let dynamic_ubo_descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set)
-            .dst_binding(1)
-            .dst_array_element(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
-            .buffer_info(&dynamic_buffer_infos)
-            .build();
+        // Each slot's set points to its own uniforms
+        let ubo_descriptor_writes = self
+            .descriptor_sets
+            .iter()
+            .zip(buffer_infos.iter())
+            .map(|(descriptor_set, buffer_infos)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(buffer_infos)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            device
+                .handle
+                .update_descriptor_sets(&ubo_descriptor_writes, &[])
+        }
 
         let sampler_descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set)
             .dst_binding(2)
             .dst_array_element(0)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
@@ -443,7 +634,6 @@ impl PbrPipelineData {
             .build();
 
         let brdflut_descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set)
             .dst_binding(3)
             .dst_array_element(0)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
@@ -451,7 +641,6 @@ impl PbrPipelineData {
             .build();
 
         let prefilter_descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set)
             .dst_binding(4)
             .dst_array_element(0)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
@@ -459,7 +648,6 @@ impl PbrPipelineData {
             .build();
 
         let irradiance_descriptor_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set)
             .dst_binding(5)
             .dst_array_element(0)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
@@ -467,84 +655,563 @@ impl PbrPipelineData {
             .build();
 
         let descriptor_writes = [
-            ubo_descriptor_write,
-            dynamic_ubo_descriptor_write,
             sampler_descriptor_write,
             brdflut_descriptor_write,
             prefilter_descriptor_write,
             irradiance_descriptor_write,
         ];
+        self.write_descriptor_sets(&device, &descriptor_writes);
 
-        unsafe {
-            device
-                .handle
-                .update_descriptor_sets(&descriptor_writes, &[])
-        }
+        self.update_storage_descriptor_set(&device);
     }
 
-    pub fn update_dynamic_ubo(&mut self, world: &World) -> Result<()> {
-        let world_joint_matrices = world.joint_matrices()?;
-        let number_of_joints = world_joint_matrices.len();
+    // Every slot needs a cubemap, so the ones without a baked probe are filled with the fallback's maps.
+    // Frames in flight can't be using the descriptor set while this runs.
+    pub fn update_reflection_probe_descriptor_set(
+        &self,
+        device: &Device,
+        probes: &[&EnvironmentMapSet],
+        fallback: &EnvironmentMapSet,
+    ) {
+        let maps = (0..Self::MAX_NUMBER_OF_REFLECTION_PROBES)
+            .map(|index| probes.get(index).copied().unwrap_or(fallback))
+            .collect::<Vec<_>>();
+        let image_info = |cubemap: &Cubemap| {
+            vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(cubemap.view.handle)
+                .sampler(cubemap.sampler.handle)
+                .build()
+        };
+        let prefilter_image_infos = maps
+            .iter()
+            .map(|maps| image_info(&maps.prefilter))
+            .collect::<Vec<_>>();
+        let irradiance_image_infos = maps
+            .iter()
+            .map(|maps| image_info(&maps.irradiance))
+            .collect::<Vec<_>>();
+
+        let prefilter_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_binding(7)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&prefilter_image_infos)
+            .build();
+
+        let irradiance_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_binding(8)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&irradiance_image_infos)
+            .build();
+
+        let descriptor_writes = [prefilter_descriptor_write, irradiance_descriptor_write];
+        self.write_descriptor_sets(device, &descriptor_writes);
+    }
+
+    fn update_storage_descriptor_set(&self, device: &Device) {
+        let entity_buffer_infos = [vk::DescriptorBufferInfo::builder()
+            .buffer(self.entity_buffer.handle())
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build()];
+        let joint_buffer_infos = [vk::DescriptorBufferInfo::builder()
+            .buffer(self.joint_buffer.handle())
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build()];
+
+        let entity_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_binding(1)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&entity_buffer_infos)
+            .build();
+
+        let joint_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_binding(6)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&joint_buffer_infos)
+            .build();
+
+        let descriptor_writes = [entity_descriptor_write, joint_descriptor_write];
+        self.write_descriptor_sets(device, &descriptor_writes);
+    }
+
+    fn storage_buffer<T>(context: &Context, name: &str, capacity: usize) -> Result<CpuToGpuBuffer> {
+        CpuToGpuBuffer::storage_buffer(
+            context.device.clone(),
+            context.allocator.clone(),
+            name,
+            (capacity * mem::size_of::<T>()) as _,
+        )
+    }
+
+    pub fn textures_changed(&self, world: &World) -> bool {
+        world.assets.textures.len() != self.textures.len()
+            || self
+                .texture_revisions
+                .iter()
+                .enumerate()
+                .any(|(index, revision)| world.assets.texture_revision(index) != *revision)
+    }
+
+    // Starts uploading textures added to the world since it was loaded and replaced ones,
+    // then points their descriptor slots at the dummy texture until they are ready.
+    // The slots of removed textures go back to the dummy texture.
+    // The descriptor set must not be in use by any frame in flight.
+    pub fn update_textures(
+        &mut self,
+        context: &Context,
+        uploads: &mut UploadQueue,
+        world: &World,
+    ) -> Result<()> {
+        let number_of_textures = world.assets.textures.len();
         ensure!(
-            number_of_joints < Self::MAX_NUMBER_OF_JOINTS,
-            "Too many joints in world: {}/{}",
-            number_of_joints,
-            Self::MAX_NUMBER_OF_JOINTS
+            number_of_textures <= self.texture_capacity,
+            "Too many textures in world: {}/{}",
+            number_of_textures,
+            self.texture_capacity
         );
 
-        self.update_node_ubos(world)?;
+        // Textures that are dropped can't have uploads still writing to them
+        let replaced = number_of_textures < self.textures.len()
+            || self
+                .texture_revisions
+                .iter()
+                .enumerate()
+                .any(|(index, revision)| world.assets.texture_revision(index) != *revision);
+        if replaced {
+            uploads.wait()?;
+        }
+
+        let mut changed_slots = (number_of_textures..self.textures.len()).collect::<Vec<_>>();
+        self.textures.truncate(number_of_textures);
+        self.samplers.truncate(number_of_textures);
+        self.texture_revisions.truncate(number_of_textures);
+        self.texture_residency.truncate(number_of_textures);
+        self.texture_last_used.truncate(number_of_textures);
+
+        for (index, texture) in world.assets.textures.iter().enumerate() {
+            let revision = world.assets.texture_revision(index);
+            if self.texture_revisions.get(index) == Some(&revision) {
+                continue;
+            }
+            let description = ImageDescription::from_texture(texture)?;
+            let name = format!("texture {}", index);
+            let (uploaded, upload) = Texture::upload(context, uploads, &name, &description)?;
+            let sampler = map_sampler(
+                context.device.clone(),
+                description.mip_levels,
+                &texture.sampler,
+            )?;
+            if index < self.textures.len() {
+                self.textures[index] = Some(uploaded);
+                self.samplers[index] = sampler;
+                self.texture_revisions[index] = revision;
+                self.texture_residency[index] = TextureResidency::Full;
+                self.texture_last_used[index] = self.frame;
+            } else {
+                self.textures.push(Some(uploaded));
+                self.samplers.push(sampler);
+                self.texture_revisions.push(revision);
+                self.texture_residency.push(TextureResidency::Full);
+                self.texture_last_used.push(self.frame);
+            }
+            self.pending_textures.retain(|(slot, _)| *slot != index);
+            self.pending_textures.push((index, upload));
+            changed_slots.push(index);
+        }
+        self.pending_textures
+            .retain(|(slot, _)| *slot < number_of_textures);
 
+        self.write_texture_slots(&context.device, &changed_slots);
         Ok(())
     }
 
-    fn update_node_ubos(&mut self, world: &World) -> Result<()> {
-        let mut buffers = vec![EntityDynamicUniformBuffer::default(); Self::MAX_NUMBER_OF_MESHES];
-        let mut joint_offset = 0;
-        let mut weight_offset = 0;
-        let mut ubo_offset = 0;
+    pub fn textures_uploaded(&self, uploads: &UploadQueue) -> bool {
+        self.pending_textures
+            .iter()
+            .any(|(_, upload)| uploads.is_ready(*upload))
+    }
+
+    // Points the slots of textures that finished uploading at them.
+    // The descriptor set must not be in use by any frame in flight.
+    pub fn bind_uploaded_textures(&mut self, device: &Device, uploads: &UploadQueue) {
+        let uploaded = self
+            .pending_textures
+            .iter()
+            .filter(|(_, upload)| uploads.is_ready(*upload))
+            .map(|(slot, _)| *slot)
+            .collect::<Vec<_>>();
+        self.pending_textures
+            .retain(|(_, upload)| !uploads.is_ready(*upload));
+        self.write_texture_slots(device, &uploaded);
+    }
+
+    // Textures that are still uploading or were evicted are sampled as the dummy texture
+    fn texture_slot(&self, slot: usize) -> vk::DescriptorImageInfo {
+        let pending = self
+            .pending_textures
+            .iter()
+            .any(|(pending_slot, _)| *pending_slot == slot);
+        let texture = self.textures.get(slot).and_then(Option::as_ref);
+        let (view, sampler) = match (texture, self.samplers.get(slot)) {
+            (Some(texture), Some(sampler)) if !pending => (texture.view.handle, sampler.handle),
+            _ => (self.dummy_texture.view.handle, self.dummy_sampler.handle),
+        };
+        vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(sampler)
+            .build()
+    }
+
+    fn write_texture_slots(&self, device: &Device, slots: &[usize]) {
+        let image_infos = slots
+            .iter()
+            .map(|slot| [self.texture_slot(*slot)])
+            .collect::<Vec<_>>();
+        let descriptor_writes = slots
+            .iter()
+            .zip(image_infos.iter())
+            .map(|(slot, image_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_binding(2)
+                    .dst_array_element(*slot as _)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        self.write_descriptor_sets(device, &descriptor_writes);
+    }
+
+    // Marks the textures of every mesh that can be drawn this frame as used.
+    // This doesn't depend on the main camera, since other views and probes draw from cameras of their own,
+    // so meshes that are occluded, outside the main camera's layers, or on lod levels it isn't using all count.
+    pub fn mark_used_textures(&mut self, world: &World) -> Result<()> {
+        self.frame += 1;
+        let frame = self.frame;
+        let last_used = &mut self.texture_last_used;
         for graph in world.scene.graphs.iter() {
             graph.walk(|node_index| {
                 let entity = graph[node_index];
+                let entry = world.ecs.entry_ref(entity)?;
+                if entry.get_component::<Hidden>().is_ok() {
+                    return Ok(());
+                }
+                let mut mesh_names = Vec::new();
+                if let Ok(mesh_render) = entry.get_component::<MeshRender>() {
+                    mesh_names.push(mesh_render.name.as_str());
+                }
+                if let Ok(lod) = entry.get_component::<Lod>() {
+                    mesh_names.extend(lod.levels.iter().map(|level| level.mesh.as_str()));
+                }
+                let materials = mesh_names
+                    .into_iter()
+                    .filter_map(|name| world.assets.geometry.meshes.get(name))
+                    .flat_map(|mesh| mesh.primitives.iter())
+                    .filter_map(|primitive| primitive.material_index)
+                    .filter_map(|index| world.assets.materials.get(index));
+                for material in materials {
+                    for index in material.texture_indices() {
+                        if index < 0 {
+                            continue;
+                        }
+                        if let Some(used) = last_used.get_mut(index as usize) {
+                            *used = frame;
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // Works out which textures to downscale, evict, or bring back to fit the budget in bytes.
+    // Textures that weren't drawn this frame are evicted first, least recently used first,
+    // then drawn ones are downscaled a level at a time. Drawn textures that were evicted
+    // come back, and downscaled ones grow again once there is room.
+    pub fn plan_texture_residency(
+        &self,
+        world: &World,
+        budget: Option<u64>,
+    ) -> Vec<(usize, TextureResidency)> {
+        let textures = &world.assets.textures;
+        let drawn = |index: usize| self.texture_last_used[index] == self.frame;
+        let mut plan = self.texture_residency.clone();
+
+        let budget = match budget {
+            Some(budget) => budget,
+            None => {
+                for (index, residency) in plan.iter_mut().enumerate() {
+                    if drawn(index) {
+                        *residency = TextureResidency::Full;
+                    }
+                }
+                return self.residency_changes(&plan);
+            }
+        };
+
+        let bytes = |index: usize, residency| texture_bytes(&textures[index], residency);
+        for (index, residency) in plan.iter_mut().enumerate() {
+            if drawn(index) && *residency == TextureResidency::Evicted {
+                *residency = TextureResidency::Full;
+            }
+        }
+        let mut total = plan
+            .iter()
+            .enumerate()
+            .map(|(index, residency)| bytes(index, *residency))
+            .sum::<u64>();
 
-                let model = world.global_transform(graph, node_index)?;
+        let mut least_recently_used = (0..plan.len()).collect::<Vec<_>>();
+        least_recently_used.sort_by_key(|index| self.texture_last_used[*index]);
 
-                let mut node_info = glm::vec4(0.0, 0.0, 0.0, 0.0);
+        for index in least_recently_used.iter().copied() {
+            if total <= budget {
+                break;
+            }
+            if !drawn(index) {
+                total -= bytes(index, plan[index]);
+                plan[index] = TextureResidency::Evicted;
+            }
+        }
 
-                if let Ok(skin) = world.ecs.entry_ref(entity)?.get_component::<Skin>() {
-                    let joint_count = skin.joints.len();
-                    node_info.x = joint_count as f32;
-                    node_info.y = joint_offset as f32;
-                    joint_offset += joint_count;
+        for levels in 1..=Self::MAX_DOWNSCALED_LEVELS {
+            for index in least_recently_used.iter().copied() {
+                if total <= budget {
+                    break;
+                }
+                let downscaled = match plan[index] {
+                    TextureResidency::Full => levels == 1,
+                    TextureResidency::Downscaled(current) => current + 1 == levels,
+                    TextureResidency::Evicted => false,
+                };
+                if !downscaled || levels > max_downscaled_levels(&textures[index]) {
+                    continue;
                 }
+                let residency = TextureResidency::Downscaled(levels);
+                total = total - bytes(index, plan[index]) + bytes(index, residency);
+                plan[index] = residency;
+            }
+        }
+
+        for index in least_recently_used.iter().rev().copied() {
+            let larger = match plan[index] {
+                TextureResidency::Downscaled(1) => TextureResidency::Full,
+                TextureResidency::Downscaled(levels) => TextureResidency::Downscaled(levels - 1),
+                _ => continue,
+            };
+            if !drawn(index) {
+                continue;
+            }
+            let grown = total - bytes(index, plan[index]) + bytes(index, larger);
+            if grown <= budget {
+                total = grown;
+                plan[index] = larger;
+            }
+        }
 
-                if let Ok(mesh) = world.ecs.entry_ref(entity)?.get_component::<Mesh>() {
-                    let weight_count = mesh.weights.len();
-                    node_info.z = weight_count as f32;
-                    node_info.w = weight_offset as f32;
-                    weight_offset += weight_count;
+        self.residency_changes(&plan)
+    }
+
+    fn residency_changes(&self, plan: &[TextureResidency]) -> Vec<(usize, TextureResidency)> {
+        plan.iter()
+            .zip(self.texture_residency.iter())
+            .enumerate()
+            .filter(|(_, (planned, current))| planned != current)
+            .map(|(index, (planned, _))| (index, *planned))
+            .collect()
+    }
+
+    // Reuploads textures at their new sizes from the world's copy and drops evicted ones.
+    // The descriptor set must not be in use by any frame in flight.
+    pub fn update_texture_residency(
+        &mut self,
+        context: &Context,
+        uploads: &mut UploadQueue,
+        world: &World,
+        changes: &[(usize, TextureResidency)],
+    ) -> Result<()> {
+        // Textures that are dropped can't have uploads still writing to them
+        let replacing_pending = changes
+            .iter()
+            .any(|(index, _)| self.pending_textures.iter().any(|(slot, _)| slot == index));
+        if replacing_pending {
+            uploads.wait()?;
+        }
+
+        for (index, residency) in changes.iter().copied() {
+            self.pending_textures.retain(|(slot, _)| *slot != index);
+            self.texture_residency[index] = residency;
+            let levels = match residency {
+                TextureResidency::Full => 0,
+                TextureResidency::Downscaled(levels) => levels,
+                TextureResidency::Evicted => {
+                    self.textures[index] = None;
+                    continue;
                 }
+            };
+            let texture = world
+                .assets
+                .textures
+                .get(index)
+                .and_then(|texture| texture.downscaled(levels))
+                .context("Failed to downscale texture!")?;
+            let description = ImageDescription::from_texture(&texture)?;
+            let name = format!("texture {}", index);
+            let (uploaded, upload) = Texture::upload(context, uploads, &name, &description)?;
+            self.samplers[index] = map_sampler(
+                context.device.clone(),
+                description.mip_levels,
+                &texture.sampler,
+            )?;
+            self.textures[index] = Some(uploaded);
+            self.pending_textures.push((index, upload));
+        }
 
-                buffers[ubo_offset] = EntityDynamicUniformBuffer { model, node_info };
-                ubo_offset += 1;
+        let slots = changes.iter().map(|(slot, _)| *slot).collect::<Vec<_>>();
+        self.write_texture_slots(&context.device, &slots);
+        Ok(())
+    }
 
-                Ok(())
-            })?;
+    // The number of textures that are downscaled and evicted to stay within the budget
+    pub fn texture_residency_counts(&self) -> (u64, u64) {
+        self.texture_residency
+            .iter()
+            .fold((0, 0), |(downscaled, evicted), residency| match residency {
+                TextureResidency::Full => (downscaled, evicted),
+                TextureResidency::Downscaled(_) => (downscaled + 1, evicted),
+                TextureResidency::Evicted => (downscaled, evicted + 1),
+            })
+    }
+
+    // Writes a view's uniforms into its slot, which the following draws then read
+    pub fn upload_uniforms(&mut self, ubo: &WorldUniformBuffer, slot: usize) -> Result<()> {
+        ensure!(
+            slot < Self::NUMBER_OF_UNIFORM_SLOTS,
+            "Only {} views can be rendered at once",
+            Self::MAX_NUMBER_OF_VIEWS
+        );
+        self.uniform_buffer.upload_data(
+            std::slice::from_ref(ubo),
+            slot * self.uniform_stride as usize,
+        )?;
+        self.uniform_slot = slot;
+        Ok(())
+    }
+
+    // Selects the uniforms of a view that were already uploaded
+    pub fn select_uniforms(&mut self, slot: usize) {
+        self.uniform_slot = slot.min(Self::NUMBER_OF_UNIFORM_SLOTS - 1);
+    }
+
+    // The world's descriptor set with the selected view's uniforms
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.descriptor_sets[self.uniform_slot]
+    }
+
+    // Writes the same descriptors to every slot's set, filling in which set each is written to
+    fn write_descriptor_sets(&self, device: &Device, descriptor_writes: &[vk::WriteDescriptorSet]) {
+        let descriptor_writes = self
+            .descriptor_sets
+            .iter()
+            .flat_map(|descriptor_set| {
+                descriptor_writes
+                    .iter()
+                    .map(move |write| vk::WriteDescriptorSet {
+                        dst_set: *descriptor_set,
+                        ..*write
+                    })
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            device
+                .handle
+                .update_descriptor_sets(&descriptor_writes, &[])
         }
-        let alignment = self.dynamic_alignment;
-        self.dynamic_uniform_buffer
-            .upload_data_aligned(&buffers, 0, alignment)?;
+    }
+
+    // Uploads the transforms and joint matrices of the world's entities,
+    // growing the storage buffers first if they have run out of room
+    pub fn update_entity_buffers(&mut self, context: &Context, world: &World) -> Result<()> {
+        let entities = Self::entity_data(world, self.compute_skinning)?;
+        let joint_matrices = world.joint_matrices()?;
+
+        let entity_capacity = self.entity_buffer.size() as usize / mem::size_of::<EntityData>();
+        let joint_capacity = self.joint_buffer.size() as usize / mem::size_of::<glm::Mat4>();
+        if entities.len() > entity_capacity || joint_matrices.len() > joint_capacity {
+            // Frames in flight may still be reading the buffers being replaced
+            unsafe { context.device.handle.device_wait_idle() }?;
+            if entities.len() > entity_capacity {
+                self.entity_buffer = Self::storage_buffer::<EntityData>(
+                    context,
+                    "entities",
+                    entities.len().next_power_of_two(),
+                )?;
+            }
+            if joint_matrices.len() > joint_capacity {
+                self.joint_buffer = Self::storage_buffer::<glm::Mat4>(
+                    context,
+                    "joints",
+                    joint_matrices.len().next_power_of_two(),
+                )?;
+            }
+            self.update_storage_descriptor_set(&context.device);
+        }
+
+        self.entity_buffer.upload_data(&entities, 0)?;
+        self.joint_buffer.upload_data(&joint_matrices, 0)?;
+
         Ok(())
     }
+
+    // One entry per node in the order the scene graphs are walked, which is the order they are drawn in.
+    // Entities that are skinned in a compute pass have a joint count of zero so they aren't skinned again.
+    pub fn entity_data(world: &World, compute_skinning: bool) -> Result<Vec<EntityData>> {
+        uniforms::entity_data(world, |entity| {
+            Ok(compute_skinning && SkinningPass::skinned_vertices(world, entity)?.is_some())
+        })
+    }
+}
+
+// The opaque and blended pipelines for one shading model variant
+pub struct ShadingPipelines {
+    pub opaque: Pipeline,
+    pub blended: Pipeline,
+    // Without back face culling, for double sided materials
+    pub opaque_double_sided: Pipeline,
+    pub blended_double_sided: Pipeline,
+}
+
+impl ShadingPipelines {
+    pub fn pipeline(&self, alpha_mode: AlphaMode, double_sided: bool) -> &Pipeline {
+        match (alpha_mode, double_sided) {
+            (AlphaMode::Opaque | AlphaMode::Mask, false) => &self.opaque,
+            (AlphaMode::Opaque | AlphaMode::Mask, true) => &self.opaque_double_sided,
+            (AlphaMode::Blend, false) => &self.blended,
+            (AlphaMode::Blend, true) => &self.blended_double_sided,
+        }
+    }
 }
 
 pub struct WorldRender {
     pub cube_render: CubeRender,
     pub pbr_pipeline_data: PbrPipelineData,
-    pub pipeline: Option<Pipeline>,
-    pub pipeline_blended: Option<Pipeline>,
+    // Indexed by ShadingModel::variant
+    pub shading_pipelines: Vec<ShadingPipelines>,
     pub pipeline_wireframe: Option<Pipeline>,
     pub pipeline_layout: Option<PipelineLayout>,
     pub wireframe_enabled: bool,
+    pub skinning: SkinningPass,
+    // Indexed the same as the world's materials
+    materials: Vec<PushConstantMaterial>,
+    materials_revision: Option<u64>,
     device: Arc<Device>,
 }
 
@@ -554,24 +1221,63 @@ impl WorldRender {
         command_pool: &CommandPool,
         world: &World,
         environment_maps: &EnvironmentMapSet,
+        vertex_layout: VertexLayout,
     ) -> Result<Self> {
-        let pipeline_data = PbrPipelineData::new(context, command_pool, world, environment_maps)?;
+        let pipeline_data = PbrPipelineData::new(
+            context,
+            command_pool,
+            environment_maps,
+            vertex_layout,
+            world.assets.textures.len(),
+        )?;
         let cube = Cube::new(
             context.device.clone(),
             context.allocator.clone(),
             command_pool,
         )?;
         let cube_render = CubeRender::new(context.device.clone(), cube);
-        Ok(Self {
+        let skinning = SkinningPass::new(context)?;
+        let mut world_render = Self {
             cube_render,
             pbr_pipeline_data: pipeline_data,
-            pipeline: None,
-            pipeline_blended: None,
+            shading_pipelines: Vec::new(),
             pipeline_wireframe: None,
             pipeline_layout: None,
             wireframe_enabled: false,
+            skinning,
+            materials: Vec::new(),
+            materials_revision: None,
             device: context.device.clone(),
-        })
+        };
+        world_render.update_materials(world);
+        Ok(world_render)
+    }
+
+    // Materials are converted to push constants once, and again only after they have been edited
+    pub fn update_materials(&mut self, world: &World) {
+        let revision = world.assets.materials_revision();
+        if self.materials_revision == Some(revision)
+            && self.materials.len() == world.assets.materials.len()
+        {
+            return;
+        }
+        self.materials = world
+            .assets
+            .materials
+            .iter()
+            .map(PushConstantMaterial::from)
+            .collect();
+        self.materials_revision = Some(revision);
+    }
+
+    // Lays out this frame's pre-skinned vertices, after the entity buffers have been updated
+    pub fn update_skinning(&mut self, context: &Context, world: &World) -> Result<()> {
+        if !self.pbr_pipeline_data.compute_skinning {
+            self.skinning.clear();
+            return Ok(());
+        }
+        self.skinning
+            .update(context, world, &self.pbr_pipeline_data)
     }
 
     fn shader_paths() -> Result<ShaderPathSet> {
@@ -582,6 +1288,15 @@ impl WorldRender {
         Ok(shader_path_set)
     }
 
+    // Unlit materials skip lighting entirely, so they get a fragment shader without it
+    fn unlit_shader_paths() -> Result<ShaderPathSet> {
+        let shader_path_set = ShaderPathSetBuilder::default()
+            .vertex("assets/shaders/world/world.vert.spv")
+            .fragment("assets/shaders/world/unlit.frag.spv")
+            .build()?;
+        Ok(shader_path_set)
+    }
+
     pub fn create_pipeline(
         &mut self,
         shader_cache: &mut ShaderCache,
@@ -590,20 +1305,27 @@ impl WorldRender {
     ) -> Result<()> {
         self.cube_render
             .create_pipeline(shader_cache, render_pass.clone(), samples)?;
+        self.skinning.create_pipeline(shader_cache)?;
 
+        // The entity index follows the material
         let push_constant_range = vk::PushConstantRange::builder()
             .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
-            .size(mem::size_of::<PushConstantMaterial>() as u32)
+            .size((mem::size_of::<PushConstantMaterial>() + mem::size_of::<i32>()) as u32)
             .build();
 
         let shader_paths = Self::shader_paths()?;
         let shader_set = shader_cache.create_shader_set(self.device.clone(), &shader_paths)?;
+        let unlit_shader_paths = Self::unlit_shader_paths()?;
+        let unlit_shader_set =
+            shader_cache.create_shader_set(self.device.clone(), &unlit_shader_paths)?;
 
         let mut settings = GraphicsPipelineSettingsBuilder::default();
         settings
             .render_pass(render_pass)
-            .vertex_inputs(vertex_inputs())
-            .vertex_attributes(vertex_attributes())
+            .vertex_inputs(vertex::vertex_inputs(self.pbr_pipeline_data.vertex_layout))
+            .vertex_attributes(vertex::vertex_attributes(
+                self.pbr_pipeline_data.vertex_layout,
+            ))
             .descriptor_set_layout(self.pbr_pipeline_data.descriptor_set_layout.clone())
             .shader_set(shader_set)
             .rasterization_samples(samples)
@@ -612,30 +1334,63 @@ impl WorldRender {
             .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
             .push_constant_range(push_constant_range);
 
-        let mut blend_settings = settings.clone();
-        blend_settings.blended(true);
+        // The texture array in the shader is sized to match the descriptor set
+        let texture_capacity = self.pbr_pipeline_data.texture_capacity as u32;
 
         let mut wireframe_settings = settings.clone();
-        wireframe_settings.polygon_mode(vk::PolygonMode::LINE);
+        wireframe_settings
+            .polygon_mode(vk::PolygonMode::LINE)
+            .specialization_constants(vec![0, texture_capacity]);
 
-        self.pipeline = None;
-        self.pipeline_blended = None;
+        self.shading_pipelines.clear();
         self.pipeline_wireframe = None;
         self.pipeline_layout = None;
 
         // TODO: Reuse the pipeline layout across these pipelines since they are the same
-        let (pipeline, pipeline_layout) = settings.build()?.create_pipeline(self.device.clone())?;
-
-        let (pipeline_blended, _) = blend_settings
+        let (pipeline_wireframe, pipeline_layout) = wireframe_settings
+            .name("pbr wireframe")
             .build()?
             .create_pipeline(self.device.clone())?;
 
-        let (pipeline_wireframe, _) = wireframe_settings
-            .build()?
-            .create_pipeline(self.device.clone())?;
+        for variant in 0..ShadingModel::NUMBER_OF_VARIANTS as u32 {
+            let mut variant_settings = settings.clone();
+            variant_settings.specialization_constants(vec![variant, texture_capacity]);
+            if variant == ShadingModel::Unlit.variant() {
+                variant_settings.shader_set(unlit_shader_set.clone());
+            }
+
+            let name = format!("pbr variant {}", variant);
+            let (opaque, _) = variant_settings
+                .name(format!("{} opaque", name))
+                .build()?
+                .create_pipeline(self.device.clone())?;
+
+            let (blended, _) = variant_settings
+                .name(format!("{} blended", name))
+                .blended(true)
+                .build()?
+                .create_pipeline(self.device.clone())?;
+
+            variant_settings.cull_mode(vk::CullModeFlags::NONE);
+            let (blended_double_sided, _) = variant_settings
+                .name(format!("{} blended double sided", name))
+                .build()?
+                .create_pipeline(self.device.clone())?;
+
+            let (opaque_double_sided, _) = variant_settings
+                .name(format!("{} opaque double sided", name))
+                .blended(false)
+                .build()?
+                .create_pipeline(self.device.clone())?;
+
+            self.shading_pipelines.push(ShadingPipelines {
+                opaque,
+                blended,
+                opaque_double_sided,
+                blended_double_sided,
+            });
+        }
 
-        self.pipeline = Some(pipeline);
-        self.pipeline_blended = Some(pipeline_blended);
         self.pipeline_wireframe = Some(pipeline_wireframe);
         self.pipeline_layout = Some(pipeline_layout);
 
@@ -647,16 +1402,13 @@ impl WorldRender {
         command_buffer: vk::CommandBuffer,
         world: &World,
         aspect_ratio: f32,
+        culling_mask: Layers,
+        occlusion_culling: bool,
     ) -> Result<()> {
-        let pipeline = self
-            .pipeline
-            .as_ref()
-            .context("Failed to get pipeline for rendering world!")?;
-
-        let pipeline_blended = self
-            .pipeline_blended
-            .as_ref()
-            .context("Failed to get blend pipeline for rendering world!")?;
+        ensure!(
+            self.shading_pipelines.len() == ShadingModel::NUMBER_OF_VARIANTS,
+            "Failed to get shading pipelines for rendering world!"
+        );
 
         let pipeline_wireframe = self
             .pipeline_wireframe
@@ -676,10 +1428,10 @@ impl WorldRender {
                 .geometry_buffer
                 .index_buffer
                 .is_some();
-            let mut ubo_offset: i32 = -1;
+            let mut entity_index: i32 = -1;
             for graph in world.scene.graphs.iter() {
                 graph.walk(|node_index| {
-                    ubo_offset += 1;
+                    entity_index += 1;
                     let entity = graph[node_index];
 
                     if world
@@ -687,6 +1439,8 @@ impl WorldRender {
                         .entry_ref(entity)?
                         .get_component::<Hidden>()
                         .is_ok()
+                        || (occlusion_culling && world.occlusion.is_culled(entity))
+                        || !world.is_layer_visible(entity, culling_mask)
                     {
                         return Ok(());
                     }
@@ -714,58 +1468,94 @@ impl WorldRender {
 
                     match world.ecs.entry_ref(entity)?.get_component::<MeshRender>() {
                         Ok(mesh_render) => {
-                            if let Some(mesh) = world.geometry.meshes.get(&mesh_render.name) {
+                            if let Some(mesh) = world.assets.geometry.meshes.get(&mesh_render.name)
+                            {
                                 if self.wireframe_enabled {
                                     pipeline_wireframe.bind(&self.device.handle, command_buffer);
-                                } else {
-                                    match alpha_mode {
-                                        AlphaMode::Opaque | AlphaMode::Mask => {
-                                            pipeline.bind(&self.device.handle, command_buffer);
-                                        }
-                                        AlphaMode::Blend => {
-                                            pipeline_blended
-                                                .bind(&self.device.handle, command_buffer);
-                                        }
-                                    }
                                 }
 
                                 self.pbr_pipeline_data
                                     .geometry_buffer
                                     .bind(&self.device.handle, command_buffer)?;
 
+                                // Pre-skinned vertices replace the interleaved vertex buffer
+                                let vertex_offset =
+                                    self.skinning.vertex_offset(entity_index as usize);
+                                if vertex_offset.is_some() {
+                                    unsafe {
+                                        self.device.handle.cmd_bind_vertex_buffers(
+                                            command_buffer,
+                                            0,
+                                            &[self.skinning.skinned_vertex_buffer()],
+                                            &[0],
+                                        );
+                                    }
+                                }
+                                let vertex_offset = vertex_offset.unwrap_or_default();
+
                                 unsafe {
                                     self.device.handle.cmd_bind_descriptor_sets(
                                         command_buffer,
                                         vk::PipelineBindPoint::GRAPHICS,
                                         pipeline_layout.handle,
                                         0,
-                                        &[self.pbr_pipeline_data.descriptor_set],
-                                        &[(ubo_offset as u64
-                                            * self.pbr_pipeline_data.dynamic_alignment)
-                                            as _],
+                                        &[self.pbr_pipeline_data.descriptor_set()],
+                                        &[],
+                                    );
+                                    self.device.handle.cmd_push_constants(
+                                        command_buffer,
+                                        pipeline_layout.handle,
+                                        vk::ShaderStageFlags::ALL_GRAPHICS,
+                                        mem::size_of::<PushConstantMaterial>() as _,
+                                        byte_slice_from(&entity_index),
                                     );
                                 }
 
+                                let default_material = Material::default();
+                                let default_push_constant =
+                                    PushConstantMaterial::from(&default_material);
+                                let mut bound_variant = None;
                                 for primitive in mesh.primitives.iter() {
-                                    let material = match primitive.material_index {
+                                    if !self.pbr_pipeline_data.is_drawable(primitive) {
+                                        continue;
+                                    }
+                                    let (primitive_material, material) = match primitive
+                                        .material_index
+                                    {
                                         Some(material_index) => {
                                             let primitive_material =
-                                                world.material_at_index(material_index)?;
+                                                world.assets.material_at_index(material_index)?;
                                             if primitive_material.alpha_mode != *alpha_mode {
                                                 continue;
                                             }
-                                            PushConstantMaterial::from(primitive_material)
+                                            let material =
+                                                self.materials.get(material_index).context(
+                                                    "Failed to lookup material push constant!",
+                                                )?;
+                                            (primitive_material, material)
                                         }
-                                        None => PushConstantMaterial::from(&Material::default()),
+                                        None => (&default_material, &default_push_constant),
                                     };
 
+                                    // Pipelines are only rebound when the shading model or sidedness changes between primitives
+                                    let variant = (
+                                        primitive_material.shading_model.variant(),
+                                        primitive_material.double_sided,
+                                    );
+                                    if !self.wireframe_enabled && bound_variant != Some(variant) {
+                                        self.shading_pipelines[variant.0 as usize]
+                                            .pipeline(*alpha_mode, variant.1)
+                                            .bind(&self.device.handle, command_buffer);
+                                        bound_variant = Some(variant);
+                                    }
+
                                     unsafe {
                                         self.device.handle.cmd_push_constants(
                                             command_buffer,
                                             pipeline_layout.handle,
                                             vk::ShaderStageFlags::ALL_GRAPHICS,
                                             0,
-                                            byte_slice_from(&material),
+                                            byte_slice_from(material),
                                         );
 
                                         if has_indices {
@@ -774,17 +1564,26 @@ impl WorldRender {
                                                 primitive.number_of_indices as _,
                                                 1,
                                                 primitive.first_index as _,
-                                                0,
+                                                vertex_offset,
                                                 0,
                                             );
+                                            self.device.commands.record_draw(
+                                                primitive.number_of_indices as u32 / 3,
+                                                1,
+                                            );
                                         } else {
                                             self.device.handle.cmd_draw(
                                                 command_buffer,
                                                 primitive.number_of_vertices as _,
                                                 1,
-                                                primitive.first_vertex as _,
+                                                (primitive.first_vertex as i32 + vertex_offset)
+                                                    as _,
                                                 0,
                                             );
+                                            self.device.commands.record_draw(
+                                                primitive.number_of_vertices as u32 / 3,
+                                                1,
+                                            );
                                         }
                                     }
                                 }
@@ -802,70 +1601,6 @@ impl WorldRender {
     }
 }
 
-fn vertex_attributes() -> [vk::VertexInputAttributeDescription; 7] {
-    let float_size = std::mem::size_of::<f32>();
-
-    let position = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(0)
-        .format(vk::Format::R32G32B32_SFLOAT)
-        .offset(0)
-        .build();
-
-    let normal = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(1)
-        .format(vk::Format::R32G32B32_SFLOAT)
-        .offset((3 * float_size) as _)
-        .build();
-
-    let uv_0 = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(2)
-        .format(vk::Format::R32G32_SFLOAT)
-        .offset((6 * float_size) as _)
-        .build();
-
-    let uv_1 = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(3)
-        .format(vk::Format::R32G32_SFLOAT)
-        .offset((8 * float_size) as _)
-        .build();
-
-    let joint_0 = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(4)
-        .format(vk::Format::R32G32B32A32_SFLOAT)
-        .offset((10 * float_size) as _)
-        .build();
-
-    let weight_0 = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(5)
-        .format(vk::Format::R32G32B32A32_SFLOAT)
-        .offset((14 * float_size) as _)
-        .build();
-
-    let color_0 = vk::VertexInputAttributeDescription::builder()
-        .binding(0)
-        .location(6)
-        .format(vk::Format::R32G32B32_SFLOAT)
-        .offset((18 * float_size) as _)
-        .build();
-
-    [position, normal, uv_0, uv_1, joint_0, weight_0, color_0]
-}
-
-fn vertex_inputs() -> [vk::VertexInputBindingDescription; 1] {
-    let vertex_input_binding_description = vk::VertexInputBindingDescription::builder()
-        .binding(0)
-        .stride(std::mem::size_of::<Vertex>() as _)
-        .input_rate(vk::VertexInputRate::VERTEX)
-        .build();
-    [vertex_input_binding_description]
-}
-
 fn map_sampler(
     device: Arc<Device>,
     mip_levels: u32,
@@ -906,8 +1641,6 @@ fn map_sampler(
         .address_mode_u(address_mode_u)
         .address_mode_v(address_mode_v)
         .address_mode_w(address_mode_w)
-        .anisotropy_enable(true)
-        .max_anisotropy(16.0)
         .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
         .unnormalized_coordinates(false)
         .compare_enable(false)
@@ -916,5 +1649,40 @@ fn map_sampler(
         .mip_lod_bias(0.0)
         .min_lod(0.0)
         .max_lod(mip_levels as _);
-    Sampler::new(device, sampler_info)
+    Sampler::filtered(device, sampler_info)
+}
+
+// How many of a world texture's largest levels can be dropped
+fn max_downscaled_levels(texture: &dragonglass_world::Texture) -> u32 {
+    let levels = if texture.mip_levels > 1 {
+        texture.mip_levels - 1
+    } else if texture.format.is_block_compressed() {
+        0
+    } else {
+        ImageDescription::calculate_mip_levels(texture.width, texture.height) - 1
+    };
+    levels.min(PbrPipelineData::MAX_DOWNSCALED_LEVELS)
+}
+
+// An estimate of the gpu memory a world texture takes up with its residency,
+// including the mips that are generated for it
+fn texture_bytes(texture: &dragonglass_world::Texture, residency: TextureResidency) -> u64 {
+    let dropped_levels = match residency {
+        TextureResidency::Full => 0,
+        TextureResidency::Downscaled(levels) => levels,
+        TextureResidency::Evicted => return 0,
+    };
+    let (width, height) = texture.mip_dimensions(dropped_levels);
+    let mip_levels = if texture.has_precomputed_mips() {
+        texture.mip_levels.saturating_sub(dropped_levels).max(1)
+    } else {
+        ImageDescription::calculate_mip_levels(width, height)
+    };
+    (0..mip_levels)
+        .map(|level| {
+            let width = (width >> level).max(1);
+            let height = (height >> level).max(1);
+            texture.format.level_size(width, height) as u64
+        })
+        .sum()
 }
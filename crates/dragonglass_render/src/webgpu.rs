@@ -0,0 +1,60 @@
+// A partial backend, which draws the world's meshes lit by its skybox, the skybox, the gui, and picking,
+// to the main window and additional views, and recovers from a lost device.
+// Shadows, reflection probes, particles, sprites, text, fog, outlines, and post processing
+// are only rendered by the vulkan backend.
+// Operations it can't perform return errors rather than silently doing nothing.
+pub use self::device::WgpuRenderBackend;
+
+mod device;
+mod environment;
+mod gui;
+mod picking;
+mod skybox;
+mod texture;
+mod world;
+
+use anyhow::Result;
+use std::{borrow::Cow, mem};
+
+// Shaders are loaded at runtime from the assets directory, like the vulkan backend's spirv
+fn load_shader(device: &wgpu::Device, path: &str) -> Result<wgpu::ShaderModule> {
+    let source = read_shader(path)?;
+    Ok(device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(path),
+        source: wgpu::ShaderSource::Wgsl(source),
+    }))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_shader(path: &str) -> Result<Cow<'static, str>> {
+    use anyhow::Context;
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read shader: {}", path))?;
+    Ok(Cow::Owned(source))
+}
+
+// Browsers have no filesystem to load them from, so they are built in instead
+#[cfg(target_arch = "wasm32")]
+fn read_shader(path: &str) -> Result<Cow<'static, str>> {
+    let source = match path {
+        "assets/shaders/environment/environment.wgsl" => {
+            include_str!("../../../assets/shaders/environment/environment.wgsl")
+        }
+        "assets/shaders/gui/gui.wgsl" => include_str!("../../../assets/shaders/gui/gui.wgsl"),
+        "assets/shaders/picking/picking.wgsl" => {
+            include_str!("../../../assets/shaders/picking/picking.wgsl")
+        }
+        "assets/shaders/skybox/skybox.wgsl" => {
+            include_str!("../../../assets/shaders/skybox/skybox.wgsl")
+        }
+        "assets/shaders/world/world.wgsl" => {
+            include_str!("../../../assets/shaders/world/world.wgsl")
+        }
+        _ => anyhow::bail!("Shader is not built in: {}", path),
+    };
+    Ok(Cow::Borrowed(source))
+}
+
+fn as_bytes<T: Copy>(values: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+}
@@ -0,0 +1,724 @@
+use super::{
+    environment::Environments, gui::GuiRender, picking::PickingRender, skybox::SkyboxRender,
+    world::WorldRender,
+};
+use crate::{
+    uniforms::skybox_projection, CubemapCapture, DeviceLost, GpuTiming, RenderStats, Renderer,
+    ViewId, WindowHandle,
+};
+use anyhow::{bail, Context, Result};
+use dragonglass_config::Config;
+use dragonglass_gui::egui::{ClippedMesh, CtxRef};
+use dragonglass_world::{Entity, Layers, PerspectiveCamera, Viewport, World};
+use image::RgbaImage;
+use log::{info, warn};
+use nalgebra_glm as glm;
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+// Another window the world is presented to, through a surface of its own
+struct View {
+    surface: wgpu::Surface,
+    surface_config: wgpu::SurfaceConfiguration,
+    depth_view: wgpu::TextureView,
+    viewport: Viewport,
+}
+
+impl View {
+    // Views are drawn by the same pipelines as the main window, so they share its surface format
+    fn new(
+        instance: &wgpu::Instance,
+        device: &wgpu::Device,
+        window_handle: &WindowHandle,
+        viewport: Viewport,
+        main_config: &wgpu::SurfaceConfiguration,
+    ) -> Self {
+        let surface = unsafe { instance.create_surface(window_handle) };
+        let surface_config = wgpu::SurfaceConfiguration {
+            width: (viewport.width as u32).max(1),
+            height: (viewport.height as u32).max(1),
+            ..main_config.clone()
+        };
+        surface.configure(device, &surface_config);
+        let depth_view =
+            WgpuRenderBackend::depth_view(device, surface_config.width, surface_config.height);
+        Self {
+            surface,
+            surface_config,
+            depth_view,
+            viewport,
+        }
+    }
+
+    fn configure(&mut self, device: &wgpu::Device) {
+        self.surface_config.width = (self.viewport.width as u32).max(1);
+        self.surface_config.height = (self.viewport.height as u32).max(1);
+        self.surface.configure(device, &self.surface_config);
+        self.depth_view = WgpuRenderBackend::depth_view(
+            device,
+            self.surface_config.width,
+            self.surface_config.height,
+        );
+    }
+
+    fn surface_matches_viewport(&self) -> bool {
+        self.surface_config.width == self.viewport.width as u32
+            && self.surface_config.height == self.viewport.height as u32
+    }
+}
+
+pub struct WgpuRenderBackend {
+    viewport: Viewport,
+    surface: wgpu::Surface,
+    surface_config: wgpu::SurfaceConfiguration,
+    depth_view: wgpu::TextureView,
+    views: HashMap<ViewId, View>,
+    next_view_id: u64,
+    world_render: Option<WorldRender>,
+    picking_render: Option<PickingRender>,
+    environments: Environments,
+    skybox_render: SkyboxRender,
+    gui_render: GuiRender,
+    // What the most recent frame submitted
+    command_stats: RenderStats,
+    // Set by the device's error handler, since wgpu reports a lost device there
+    // rather than returning errors from the calls that ran into it
+    device_lost: Arc<AtomicBool>,
+    queue: wgpu::Queue,
+    device: wgpu::Device,
+    // Surfaces belong to the instance rather than the device, so they outlive a lost device
+    instance: wgpu::Instance,
+}
+
+impl WgpuRenderBackend {
+    // Captures larger than this are scaled down to fit, like the vulkan backend's offscreen image
+    const MAX_CAPTURE_SIZE: u32 = 2048;
+
+    pub fn new(window_handle: &impl HasRawWindowHandle, viewport: Viewport) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+        let surface = unsafe { instance.create_surface(window_handle) };
+        let (adapter, device, queue, device_lost) = Self::create_device(&instance, &surface)?;
+        warn!("The wgpu backend doesn't render shadows, reflection probes, particles, sprites, text, fog, outlines, or post processing");
+
+        let format = Self::surface_format(&surface, &adapter)?;
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: (viewport.width as u32).max(1),
+            height: (viewport.height as u32).max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        surface.configure(&device, &surface_config);
+        let depth_view = Self::depth_view(&device, surface_config.width, surface_config.height);
+
+        let environments = Environments::new(&device, &queue)?;
+        let skybox_render = SkyboxRender::new(&device, &environments, format)?;
+        let gui_render = GuiRender::new(&device, format)?;
+
+        Ok(Self {
+            viewport,
+            surface,
+            surface_config,
+            depth_view,
+            views: HashMap::new(),
+            next_view_id: 0,
+            world_render: None,
+            picking_render: None,
+            environments,
+            skybox_render,
+            gui_render,
+            command_stats: RenderStats::default(),
+            device_lost,
+            queue,
+            device,
+            instance,
+        })
+    }
+
+    fn create_device(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface,
+    ) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue, Arc<AtomicBool>)> {
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: Some(surface),
+            force_fallback_adapter: false,
+        }))
+        .context("Failed to find a graphics adapter supported by wgpu!")?;
+        let adapter_info = adapter.get_info();
+        info!(
+            "Rendering with {} through {:?}",
+            adapter_info.name, adapter_info.backend
+        );
+
+        // Wireframes and block compressed textures are used when the adapter has them
+        let features = adapter.features()
+            & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::TEXTURE_COMPRESSION_BC);
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("dragonglass"),
+                features,
+                limits: wgpu::Limits::default(),
+            },
+            None,
+        ))?;
+
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let lost = device_lost.clone();
+        device.on_uncaptured_error(move |error| {
+            // Any other error is a bug, so it still panics like wgpu's default handler
+            if !error.to_string().to_lowercase().contains("device is lost") {
+                panic!("Unhandled wgpu error: {}", error);
+            }
+            lost.store(true, Ordering::Release);
+        });
+
+        Ok((adapter, device, queue, device_lost))
+    }
+
+    // The shaders tonemap and gamma correct their output themselves
+    fn surface_format(
+        surface: &wgpu::Surface,
+        adapter: &wgpu::Adapter,
+    ) -> Result<wgpu::TextureFormat> {
+        let format = match surface
+            .get_preferred_format(adapter)
+            .context("Failed to find a surface format!")?
+        {
+            wgpu::TextureFormat::Bgra8UnormSrgb => wgpu::TextureFormat::Bgra8Unorm,
+            wgpu::TextureFormat::Rgba8UnormSrgb => wgpu::TextureFormat::Rgba8Unorm,
+            format => format,
+        };
+        Ok(format)
+    }
+
+    fn check_device(&self) -> Result<()> {
+        if self.device_lost.load(Ordering::Acquire) {
+            return Err(DeviceLost.into());
+        }
+        Ok(())
+    }
+
+    fn depth_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("depth"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: WorldRender::DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    // The surface is sized to the viewport, so it is reconfigured whenever the viewport is resized
+    fn configure_surface(&mut self) {
+        let width = self.viewport.width as u32;
+        let height = self.viewport.height as u32;
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.surface.configure(&self.device, &self.surface_config);
+        self.depth_view = Self::depth_view(&self.device, width, height);
+    }
+
+    fn surface_matches_viewport(&self) -> bool {
+        self.surface_config.width == self.viewport.width as u32
+            && self.surface_config.height == self.viewport.height as u32
+    }
+
+    fn render_pass<'a>(
+        encoder: &'a mut wgpu::CommandEncoder,
+        color_view: &'a wgpu::TextureView,
+        depth_view: &'a wgpu::TextureView,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("scene"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: color_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    // The skybox and the world in front of it, for the window, its views, and captures alike
+    fn issue_scene_commands<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        world: &World,
+        culling_mask: Layers,
+        stats: &mut RenderStats,
+    ) -> Result<()> {
+        if self.environments.has_environment() {
+            self.skybox_render.issue_commands(pass, stats);
+        }
+        if let Some(world_render) = self.world_render.as_ref() {
+            world_render.issue_commands(pass, world, culling_mask, stats)?;
+        }
+        Ok(())
+    }
+
+    // Renders the world into a texture of the surface's format and reads it back,
+    // without the gui. The view's camera has already been uploaded.
+    fn render_offscreen_view(&self, world: &World, width: u32, height: u32) -> Result<RgbaImage> {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("capture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.surface_config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let color_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = Self::depth_view(&self.device, width, height);
+
+        // Rows are copied out padded to the alignment buffer copies require
+        let row_size = width * 4;
+        let padded_row_size = wgpu::util::align_to(row_size, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture readback"),
+            size: (padded_row_size * height) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = Self::render_pass(&mut encoder, &color_view, &depth_view);
+            self.issue_scene_commands(
+                &mut pass,
+                world,
+                world.active_camera_culling_mask(),
+                &mut RenderStats::default(),
+            )?;
+        }
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let swizzle = matches!(self.surface_config.format, wgpu::TextureFormat::Bgra8Unorm);
+        let mut pixels = Vec::with_capacity((row_size * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_size as usize) {
+            for texel in row[..row_size as usize].chunks_exact(4) {
+                if swizzle {
+                    pixels.extend_from_slice(&[texel[2], texel[1], texel[0], texel[3]]);
+                } else {
+                    pixels.extend_from_slice(texel);
+                }
+            }
+        }
+        buffer.unmap();
+
+        RgbaImage::from_raw(width, height, pixels)
+            .context("Failed to read back the offscreen image!")
+    }
+
+    // The skybox is drawn with its own projection, since orthographic cameras still see it in perspective
+    fn upload_camera(
+        &mut self,
+        world: &World,
+        camera: Entity,
+        (projection, view): (glm::Mat4, glm::Mat4),
+        skybox_projection: glm::Mat4,
+    ) -> Result<()> {
+        let blend = world
+            .scene
+            .skybox_fade
+            .map(|fade| fade.blend())
+            .unwrap_or(1.0);
+        self.skybox_render
+            .update(&self.queue, (skybox_projection, view), blend);
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.update(&self.device, &self.queue, world, camera, (projection, view))?;
+        }
+        Ok(())
+    }
+
+    fn upload_camera_for_viewport(
+        &mut self,
+        world: &World,
+        camera: Entity,
+        viewport: Viewport,
+    ) -> Result<()> {
+        let aspect_ratio = viewport.aspect_ratio();
+        let matrices = world.camera_matrices(camera, aspect_ratio)?;
+        let skybox_projection = skybox_projection(world, camera, matrices.0, aspect_ratio)?;
+        self.upload_camera(world, camera, matrices, skybox_projection)
+    }
+}
+
+impl Renderer for WgpuRenderBackend {
+    fn load_world(&mut self, world: &World) -> Result<()> {
+        let world_render = WorldRender::new(
+            &self.device,
+            &self.queue,
+            world,
+            &self.environments,
+            self.surface_config.format,
+        )?;
+        self.picking_render = Some(PickingRender::new(&self.device, &world_render)?);
+        self.world_render = Some(world_render);
+        Ok(())
+    }
+
+    fn update_vertices(&mut self, world: &World, vertices: Range<usize>) -> Result<()> {
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.update_vertices(&self.queue, world, vertices);
+        }
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        world: &World,
+        gui_context: Option<&CtxRef>,
+        clipped_meshes: &[ClippedMesh],
+        _elapsed_milliseconds: u32,
+        config: &Config,
+    ) -> Result<()> {
+        self.check_device()?;
+
+        let present_mode = if config.graphics.vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Immediate
+        };
+        if present_mode != self.surface_config.present_mode {
+            self.surface_config.present_mode = present_mode;
+            self.configure_surface();
+            for view in self.views.values_mut() {
+                view.surface_config.present_mode = present_mode;
+                view.configure(&self.device);
+            }
+        }
+
+        if self.environments.changed(world) {
+            self.environments.update(&self.device, &self.queue, world);
+            self.skybox_render
+                .update_environments(&self.device, &self.environments);
+            if let Some(world_render) = self.world_render.as_mut() {
+                world_render.update_environment(&self.device, &self.environments);
+            }
+        }
+
+        if let Some(world_render) = self.world_render.as_mut() {
+            world_render.wireframe_enabled = config.graphics.wireframe;
+            if world_render.textures_changed(world) {
+                world_render.update_textures(&self.device, &self.queue, world)?;
+            }
+            if world_render.geometry_changed(world) {
+                world_render.update_geometry(&self.device, &self.queue, world);
+            }
+        }
+
+        if let Some(gui_context) = gui_context {
+            self.gui_render
+                .update(&self.device, &self.queue, gui_context, clipped_meshes);
+        }
+
+        let camera = world.active_camera()?;
+        self.upload_camera_for_viewport(world, camera, self.viewport)
+    }
+
+    fn render(&mut self, world: &World, clipped_meshes: Vec<ClippedMesh>) -> Result<()> {
+        if self.viewport.width < 1.0 || self.viewport.height < 1.0 {
+            return Ok(());
+        }
+        if !self.surface_matches_viewport() {
+            self.configure_surface();
+        }
+
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.configure_surface();
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                warn!("Timed out acquiring the next frame, skipping it");
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                bail!("Out of memory acquiring the next frame!")
+            }
+        };
+        let color_view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut stats = RenderStats::default();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = Self::render_pass(&mut encoder, &color_view, &self.depth_view);
+            self.issue_scene_commands(
+                &mut pass,
+                world,
+                world.active_camera_culling_mask(),
+                &mut stats,
+            )?;
+            self.gui_render.issue_commands(
+                &self.queue,
+                &mut pass,
+                self.viewport,
+                &clipped_meshes,
+                &mut stats,
+            );
+        }
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+
+        self.command_stats = stats;
+        self.check_device()
+    }
+
+    fn recover(&mut self, world: &World) -> Result<()> {
+        warn!("The graphics device was lost, recreating it");
+        let (adapter, device, queue, device_lost) =
+            Self::create_device(&self.instance, &self.surface)?;
+        let format = Self::surface_format(&self.surface, &adapter)?;
+
+        // Everything on the lost device is destroyed as it is replaced
+        self.world_render = None;
+        self.picking_render = None;
+        self.environments = Environments::new(&device, &queue)?;
+        self.skybox_render = SkyboxRender::new(&device, &self.environments, format)?;
+        self.gui_render = GuiRender::new(&device, format)?;
+        self.device_lost = device_lost;
+        self.queue = queue;
+        self.device = device;
+
+        self.surface_config.format = format;
+        self.surface.configure(&self.device, &self.surface_config);
+        self.depth_view = Self::depth_view(
+            &self.device,
+            self.surface_config.width,
+            self.surface_config.height,
+        );
+        // Views present through the lost device too, so they are configured for the new one
+        for view in self.views.values_mut() {
+            view.surface_config.format = format;
+            view.configure(&self.device);
+        }
+
+        self.load_world(world)
+    }
+
+    fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    fn create_view(
+        &mut self,
+        window_handle: RawWindowHandle,
+        viewport: Viewport,
+    ) -> Result<ViewId> {
+        let window_handle = WindowHandle(window_handle);
+        let view = View::new(
+            &self.instance,
+            &self.device,
+            &window_handle,
+            viewport,
+            &self.surface_config,
+        );
+        let id = ViewId(self.next_view_id);
+        self.next_view_id += 1;
+        self.views.insert(id, view);
+        Ok(id)
+    }
+
+    fn remove_view(&mut self, view: ViewId) -> Result<()> {
+        self.views
+            .remove(&view)
+            .context("Attempted to remove a view that does not exist!")?;
+        Ok(())
+    }
+
+    fn set_view_viewport(&mut self, view: ViewId, viewport: Viewport) -> Result<()> {
+        self.views
+            .get_mut(&view)
+            .context("Attempted to resize a view that does not exist!")?
+            .viewport = viewport;
+        Ok(())
+    }
+
+    fn render_view(&mut self, world: &World, id: ViewId, camera: Entity) -> Result<()> {
+        let view = self
+            .views
+            .get_mut(&id)
+            .context("Attempted to render a view that does not exist!")?;
+        let viewport = view.viewport;
+        if viewport.width < 1.0 || viewport.height < 1.0 {
+            return Ok(());
+        }
+        if !view.surface_matches_viewport() {
+            view.configure(&self.device);
+        }
+
+        let frame = match view.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                view.configure(&self.device);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                warn!("Timed out acquiring the next frame of a view, skipping it");
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                bail!("Out of memory acquiring the next frame of a view!")
+            }
+        };
+        let color_view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.upload_camera_for_viewport(world, camera, viewport)?;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let depth_view = &self.views[&id].depth_view;
+            let mut pass = Self::render_pass(&mut encoder, &color_view, depth_view);
+            self.issue_scene_commands(
+                &mut pass,
+                world,
+                world.camera_culling_mask(camera),
+                &mut RenderStats::default(),
+            )?;
+        }
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+
+        self.check_device()
+    }
+
+    // Renders the world from the active camera's position once per cubemap face
+    fn capture_cubemap(&mut self, world: &World, size: u32) -> Result<CubemapCapture> {
+        let size = size.clamp(1, Self::MAX_CAPTURE_SIZE);
+
+        let camera = world.active_camera()?;
+        let position = world.entity_global_transform(camera)?.translation;
+        let projection = PerspectiveCamera {
+            aspect_ratio: Some(1.0),
+            y_fov_rad: 90_f32.to_radians(),
+            z_far: None,
+            z_near: 0.01,
+        }
+        .matrix(1.0);
+
+        let mut faces = Vec::new();
+        for (forward, up) in CubemapCapture::face_orientations().iter() {
+            let view = glm::look_at(&position, &(position + forward), up);
+            self.upload_camera(world, camera, (projection, view), projection)?;
+            faces.push(self.render_offscreen_view(world, size, size)?);
+        }
+
+        Ok(CubemapCapture { size, faces })
+    }
+
+    // Surfaces are only lit by the skybox's environment, since probes aren't blended in by its shaders
+    fn bake_reflection_probes(&mut self, _world: &World) -> Result<()> {
+        bail!("The wgpu backend doesn't support reflection probes")
+    }
+
+    fn capture_frame(&mut self, world: &World) -> Result<RgbaImage> {
+        let viewport = self.viewport;
+        let scale = (Self::MAX_CAPTURE_SIZE as f32 / viewport.width.max(viewport.height)).min(1.0);
+        let width = ((viewport.width * scale) as u32).clamp(1, Self::MAX_CAPTURE_SIZE);
+        let height = ((viewport.height * scale) as u32).clamp(1, Self::MAX_CAPTURE_SIZE);
+
+        let camera = world.active_camera()?;
+        self.upload_camera_for_viewport(world, camera, viewport)?;
+        self.render_offscreen_view(world, width, height)
+    }
+
+    fn pick(&mut self, world: &World, x: f32, y: f32) -> Result<Option<Entity>> {
+        match (self.world_render.as_ref(), self.picking_render.as_ref()) {
+            (Some(world_render), Some(picking_render)) => picking_render.pick(
+                &self.device,
+                &self.queue,
+                world,
+                world_render,
+                self.viewport,
+                (x, y),
+            ),
+            _ => Ok(None),
+        }
+    }
+
+    // Timestamp queries are an optional wgpu feature, so no passes are timed
+    fn gpu_timings(&self) -> Vec<GpuTiming> {
+        Vec::new()
+    }
+
+    fn stats(&self) -> RenderStats {
+        let (buffer_memory, texture_memory) = self
+            .world_render
+            .as_ref()
+            .map(|world_render| (world_render.buffer_memory(), world_render.texture_memory()))
+            .unwrap_or_default();
+        RenderStats {
+            buffer_memory,
+            texture_memory: texture_memory + self.environments.texture_memory(),
+            ..self.command_stats
+        }
+    }
+}
@@ -0,0 +1,608 @@
+use super::{as_bytes, load_shader};
+use anyhow::{ensure, Result};
+use dragonglass_world::{Format, Texture, World};
+use half::f16;
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    mem,
+    num::{NonZeroU32, NonZeroU64},
+};
+
+// The maps created from one of the world's hdr textures
+pub struct EnvironmentMaps {
+    // Also shown by the skybox, since its first level is the unfiltered environment
+    pub prefilter: wgpu::TextureView,
+    pub irradiance: wgpu::TextureView,
+    // In bytes, for the memory stats
+    size: u64,
+}
+
+// What one pass of the generator reads from its uniform buffer
+#[derive(Copy, Clone)]
+struct Parameters {
+    face: i32,
+    roughness: f32,
+    number_of_samples: u32,
+    padding: f32,
+}
+
+// Renders the environment maps the vulkan backend creates with its pbr module
+struct EnvironmentGenerator {
+    equirectangular_layout: wgpu::BindGroupLayout,
+    cube_layout: wgpu::BindGroupLayout,
+    equirectangular_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    irradiance_pipeline: wgpu::RenderPipeline,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    brdflut_pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+}
+
+impl EnvironmentGenerator {
+    // The hdr cubemap is only needed to generate the other maps, so it is kept from growing too large
+    const MAX_ENVIRONMENT_SIZE: u32 = 1024;
+    const IRRADIANCE_SIZE: u32 = 64;
+    const PREFILTER_SIZE: u32 = 512;
+    const PREFILTER_SAMPLES: u32 = 32;
+    const BRDFLUT_SIZE: u32 = 512;
+
+    // 32-bit float textures can't be filtered without optional features
+    const CUBEMAP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const BRDFLUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+    fn new(device: &wgpu::Device) -> Result<Self> {
+        let shader = load_shader(device, "assets/shaders/environment/environment.wgsl")?;
+        let equirectangular_layout =
+            Self::bind_group_layout(device, 2, wgpu::TextureViewDimension::D2);
+        let cube_layout = Self::bind_group_layout(device, 3, wgpu::TextureViewDimension::Cube);
+
+        let equirectangular_pipeline = Self::pipeline(
+            device,
+            &shader,
+            &[&equirectangular_layout],
+            "fs_equirectangular",
+            Self::CUBEMAP_FORMAT,
+        );
+        let downsample_pipeline = Self::pipeline(
+            device,
+            &shader,
+            &[&cube_layout],
+            "fs_downsample",
+            Self::CUBEMAP_FORMAT,
+        );
+        let irradiance_pipeline = Self::pipeline(
+            device,
+            &shader,
+            &[&cube_layout],
+            "fs_irradiance",
+            Self::CUBEMAP_FORMAT,
+        );
+        let prefilter_pipeline = Self::pipeline(
+            device,
+            &shader,
+            &[&cube_layout],
+            "fs_prefilter",
+            Self::CUBEMAP_FORMAT,
+        );
+        let brdflut_pipeline =
+            Self::pipeline(device, &shader, &[], "fs_brdflut", Self::BRDFLUT_FORMAT);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            equirectangular_layout,
+            cube_layout,
+            equirectangular_pipeline,
+            downsample_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+            brdflut_pipeline,
+            sampler,
+        })
+    }
+
+    // The pass parameters, the sampler, and the texture the pass reads from
+    fn bind_group_layout(
+        device: &wgpu::Device,
+        texture_binding: u32,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(mem::size_of::<Parameters>() as _),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: texture_binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(entry_point),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &[format.into()],
+            }),
+            multiview: None,
+        })
+    }
+
+    fn cubemap(device: &wgpu::Device, label: &str, size: u32, mip_levels: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::CUBEMAP_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+    }
+
+    // Cubemaps are sampled through views of the whole cube, or of a single level of it
+    fn cube_view(texture: &wgpu::Texture, mip_level: Option<u32>) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            base_mip_level: mip_level.unwrap_or(0),
+            mip_level_count: mip_level.and_then(|_| NonZeroU32::new(1)),
+            ..Default::default()
+        })
+    }
+
+    // Faces are rendered to one at a time through views of a single level of a single layer
+    fn face_view(texture: &wgpu::Texture, face: u32, mip_level: u32) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip_level,
+            mip_level_count: NonZeroU32::new(1),
+            base_array_layer: face,
+            array_layer_count: NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+
+    fn mip_levels(size: u32) -> u32 {
+        32 - size.leading_zeros()
+    }
+
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        parameters: &wgpu::Buffer,
+        (texture_binding, view): (u32, &wgpu::TextureView),
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: parameters,
+                        offset: 0,
+                        size: NonZeroU64::new(mem::size_of::<Parameters>() as _),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: texture_binding,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+            ],
+        })
+    }
+
+    fn draw(
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: Option<(&wgpu::BindGroup, u32)>,
+        target: &wgpu::TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("environment"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(pipeline);
+        if let Some((bind_group, offset)) = bind_group {
+            pass.set_bind_group(0, bind_group, &[offset]);
+        }
+        pass.draw(0..3, 0..1);
+    }
+
+    // Doesn't depend on the environment, so it is only created once per device
+    fn brdflut(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("brdflut"),
+            size: wgpu::Extent3d {
+                width: Self::BRDFLUT_SIZE,
+                height: Self::BRDFLUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::BRDFLUT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        Self::draw(&mut encoder, &self.brdflut_pipeline, None, &view);
+        queue.submit(Some(encoder.finish()));
+        view
+    }
+
+    // Black maps that are bound when there is no environment, since the bind groups need something.
+    // New textures are cleared to zero, so nothing has to be written to them.
+    fn empty(device: &wgpu::Device) -> EnvironmentMaps {
+        let prefilter = Self::cubemap(device, "prefilter_cubemap", 1, 1);
+        let irradiance = Self::cubemap(device, "irradiance_cubemap", 1, 1);
+        EnvironmentMaps {
+            prefilter: Self::cube_view(&prefilter, None),
+            irradiance: Self::cube_view(&irradiance, None),
+            size: 2 * Self::cubemap_size(1, 1),
+        }
+    }
+
+    fn cubemap_size(size: u32, mip_levels: u32) -> u64 {
+        (0..mip_levels)
+            .map(|level| 6 * 8 * ((size >> level).max(1) as u64).pow(2))
+            .sum()
+    }
+
+    fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        hdr_texture: &Texture,
+    ) -> Result<EnvironmentMaps> {
+        ensure!(
+            hdr_texture.format == Format::R32G32B32A32F,
+            "Hdr textures in the {:?} format are unsupported!",
+            hdr_texture.format
+        );
+        let equirectangular = Self::upload_hdr(device, queue, hdr_texture)?;
+
+        let environment_size = hdr_texture.width.clamp(1, Self::MAX_ENVIRONMENT_SIZE);
+        let environment_mip_levels = Self::mip_levels(environment_size);
+        let environment = Self::cubemap(
+            device,
+            "hdr_cubemap",
+            environment_size,
+            environment_mip_levels,
+        );
+        let prefilter_mip_levels = Self::mip_levels(Self::PREFILTER_SIZE);
+        let prefilter = Self::cubemap(
+            device,
+            "prefilter_cubemap",
+            Self::PREFILTER_SIZE,
+            prefilter_mip_levels,
+        );
+        let irradiance = Self::cubemap(device, "irradiance_cubemap", Self::IRRADIANCE_SIZE, 1);
+
+        // Every pass reads its parameters from its own slice of a single buffer.
+        // The first six are just the faces, and the rest are each face of each prefiltered level.
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let mut passes = (0..6)
+            .map(|face| Parameters {
+                face,
+                roughness: 0.0,
+                number_of_samples: 0,
+                padding: 0.0,
+            })
+            .collect::<Vec<_>>();
+        for level in 0..prefilter_mip_levels {
+            for face in 0..6 {
+                passes.push(Parameters {
+                    face,
+                    roughness: level as f32 / (prefilter_mip_levels - 1) as f32,
+                    number_of_samples: Self::PREFILTER_SAMPLES,
+                    padding: 0.0,
+                });
+            }
+        }
+        let mut contents = vec![0; passes.len() * alignment];
+        for (index, parameters) in passes.iter().enumerate() {
+            let bytes = as_bytes(std::slice::from_ref(parameters));
+            contents[index * alignment..index * alignment + bytes.len()].copy_from_slice(bytes);
+        }
+        let parameters = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("environment parameters"),
+            size: contents.len() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&parameters, 0, &contents);
+        let face_offset = |face: u32| (face as usize * alignment) as u32;
+        let prefilter_offset =
+            |level: u32, face: u32| ((6 + level as usize * 6 + face as usize) * alignment) as u32;
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        info!("Creating Hdr cubemap");
+        let equirectangular_bind_group = self.bind_group(
+            device,
+            &self.equirectangular_layout,
+            &parameters,
+            (2, &equirectangular),
+        );
+        for face in 0..6 {
+            Self::draw(
+                &mut encoder,
+                &self.equirectangular_pipeline,
+                Some((&equirectangular_bind_group, face_offset(face))),
+                &Self::face_view(&environment, face, 0),
+            );
+        }
+        // Each level is filtered down from the one above it
+        for level in 1..environment_mip_levels {
+            let source = Self::cube_view(&environment, Some(level - 1));
+            let bind_group = self.bind_group(device, &self.cube_layout, &parameters, (3, &source));
+            for face in 0..6 {
+                Self::draw(
+                    &mut encoder,
+                    &self.downsample_pipeline,
+                    Some((&bind_group, face_offset(face))),
+                    &Self::face_view(&environment, face, level),
+                );
+            }
+        }
+
+        let environment_view = Self::cube_view(&environment, None);
+        let environment_bind_group = self.bind_group(
+            device,
+            &self.cube_layout,
+            &parameters,
+            (3, &environment_view),
+        );
+
+        info!("Creating Irradiance cubemap");
+        for face in 0..6 {
+            Self::draw(
+                &mut encoder,
+                &self.irradiance_pipeline,
+                Some((&environment_bind_group, face_offset(face))),
+                &Self::face_view(&irradiance, face, 0),
+            );
+        }
+
+        info!("Creating Prefilter cubemap");
+        for level in 0..prefilter_mip_levels {
+            for face in 0..6 {
+                Self::draw(
+                    &mut encoder,
+                    &self.prefilter_pipeline,
+                    Some((&environment_bind_group, prefilter_offset(level, face))),
+                    &Self::face_view(&prefilter, face, level),
+                );
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        Ok(EnvironmentMaps {
+            prefilter: Self::cube_view(&prefilter, None),
+            irradiance: Self::cube_view(&irradiance, None),
+            size: Self::cubemap_size(Self::PREFILTER_SIZE, prefilter_mip_levels)
+                + Self::cubemap_size(Self::IRRADIANCE_SIZE, 1),
+        })
+    }
+
+    // Halved to 16-bit floats, which can be filtered
+    fn upload_hdr(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        hdr_texture: &Texture,
+    ) -> Result<wgpu::TextureView> {
+        let texel_count = (hdr_texture.width * hdr_texture.height) as usize;
+        ensure!(
+            hdr_texture.pixels.len() >= texel_count * 16,
+            "Hdr texture is missing pixels!"
+        );
+        let pixels = hdr_texture.pixels[..texel_count * 16]
+            .chunks_exact(4)
+            .flat_map(|channel| {
+                let value = f32::from_le_bytes([channel[0], channel[1], channel[2], channel[3]]);
+                f16::from_f32(value).to_le_bytes()
+            })
+            .collect::<Vec<_>>();
+
+        let size = wgpu::Extent3d {
+            width: hdr_texture.width,
+            height: hdr_texture.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hdr"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(hdr_texture.width * 8),
+                rows_per_image: None,
+            },
+            size,
+        );
+        Ok(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+}
+
+// The environment maps of the world's skyboxes, created the first time each one is shown
+pub struct Environments {
+    generator: EnvironmentGenerator,
+    pub brdflut: wgpu::TextureView,
+    // Keyed by the cache key of the hdr each set was created from
+    maps: HashMap<u64, EnvironmentMaps>,
+    // Bound when the world has no skybox, which is then lit by its ambient light instead
+    empty: EnvironmentMaps,
+    active: Option<u64>,
+    faded: Option<u64>,
+    // The skybox and faded skybox the maps were last chosen for
+    bound_skyboxes: Option<(Option<usize>, Option<usize>)>,
+}
+
+impl Environments {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let generator = EnvironmentGenerator::new(device)?;
+        info!("Creating Brdflut");
+        let brdflut = generator.brdflut(device, queue);
+        let empty = EnvironmentGenerator::empty(device);
+        Ok(Self {
+            generator,
+            brdflut,
+            maps: HashMap::new(),
+            empty,
+            active: None,
+            faded: None,
+            bound_skyboxes: None,
+        })
+    }
+
+    // Whether the scene's skybox or the skybox it is fading from have changed
+    pub fn changed(&self, world: &World) -> bool {
+        self.bound_skyboxes != Some(Self::skyboxes(world))
+    }
+
+    // Chooses the maps of the scene's skyboxes, creating them if they haven't been created yet
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, world: &World) {
+        let (skybox, faded_skybox) = Self::skyboxes(world);
+        self.active = skybox.and_then(|index| self.load(device, queue, world, index));
+        self.faded = faded_skybox
+            .and_then(|index| self.load(device, queue, world, index))
+            .or(self.active);
+        self.bound_skyboxes = Some((skybox, faded_skybox));
+    }
+
+    fn skyboxes(world: &World) -> (Option<usize>, Option<usize>) {
+        let faded = world.scene.skybox_fade.map(|fade| fade.from);
+        (world.scene.skybox, faded)
+    }
+
+    // Returns the key of the hdr texture's environment maps, or nothing if they couldn't be created
+    fn load(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &World,
+        index: usize,
+    ) -> Option<u64> {
+        let texture = world.assets.hdr_textures.get(index)?;
+        let key = world.assets.hdr_key(index)?;
+        if !self.maps.contains_key(&key) {
+            match self.generator.generate(device, queue, texture) {
+                Ok(maps) => {
+                    self.maps.insert(key, maps);
+                }
+                Err(error) => {
+                    warn!("Failed to create environment maps: {}", error);
+                    return None;
+                }
+            }
+        }
+        Some(key)
+    }
+
+    // The world is only lit by an environment while it has a skybox
+    pub fn has_environment(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn active(&self) -> &EnvironmentMaps {
+        self.maps(self.active)
+    }
+
+    pub fn faded(&self) -> &EnvironmentMaps {
+        self.maps(self.faded)
+    }
+
+    fn maps(&self, key: Option<u64>) -> &EnvironmentMaps {
+        key.and_then(|key| self.maps.get(&key))
+            .unwrap_or(&self.empty)
+    }
+
+    pub fn texture_memory(&self) -> u64 {
+        let brdflut_size = (EnvironmentGenerator::BRDFLUT_SIZE as u64).pow(2) * 4;
+        self.maps.values().map(|maps| maps.size).sum::<u64>() + self.empty.size + brdflut_size
+    }
+}
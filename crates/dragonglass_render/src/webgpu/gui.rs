@@ -0,0 +1,303 @@
+use super::{as_bytes, load_shader};
+use crate::RenderStats;
+use anyhow::Result;
+use dragonglass_gui::egui::{epaint::Vertex, ClippedMesh, CtxRef};
+use dragonglass_world::Viewport;
+use log::debug;
+use std::{mem, num::NonZeroU32};
+
+pub struct GuiRender {
+    uniform_buffer: wgpu::Buffer,
+    // Recreated whenever the meshes outgrow them
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // Created once the font texture has been uploaded
+    bind_group: Option<wgpu::BindGroup>,
+    font_texture_version: Option<u64>,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl GuiRender {
+    const INITIAL_BUFFER_SIZE: u64 = 1024 * 1024 * 4;
+
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Result<Self> {
+        debug!("Creating gui renderer");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gui uniforms"),
+            size: mem::size_of::<[f32; 4]>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("egui font"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("gui"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline = Self::create_pipeline(device, &bind_group_layout, color_format)?;
+
+        Ok(Self {
+            uniform_buffer,
+            vertex_buffer: Self::buffer(
+                device,
+                Self::INITIAL_BUFFER_SIZE,
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: Self::buffer(
+                device,
+                Self::INITIAL_BUFFER_SIZE,
+                wgpu::BufferUsages::INDEX,
+            ),
+            sampler,
+            bind_group_layout,
+            bind_group: None,
+            font_texture_version: None,
+            pipeline,
+        })
+    }
+
+    fn buffer(device: &wgpu::Device, size: u64, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gui geometry"),
+            size: wgpu::util::align_to(size, wgpu::COPY_BUFFER_ALIGNMENT),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+    ) -> Result<wgpu::RenderPipeline> {
+        let shader = load_shader(device, "assets/shaders/gui/gui.wgsl")?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("gui"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let attributes = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4];
+        Ok(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("gui"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: mem::size_of::<Vertex>() as _,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &attributes,
+                    }],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        // egui's colors have their alpha premultiplied
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            }),
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        gui_context: &CtxRef,
+        clipped_meshes: &[ClippedMesh],
+    ) {
+        self.update_texture(device, queue, gui_context);
+        self.update_buffers(device, queue, clipped_meshes);
+    }
+
+    // The font texture is only uploaded again when egui has rebuilt it
+    fn update_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, gui_context: &CtxRef) {
+        let font_image = gui_context.fonts().font_image();
+        if self.font_texture_version == Some(font_image.version) {
+            return;
+        }
+
+        let pixels = font_image
+            .pixels
+            .iter()
+            .flat_map(|&r| [r, r, r, r])
+            .collect::<Vec<_>>();
+        let size = wgpu::Extent3d {
+            width: font_image.width as _,
+            height: font_image.height as _,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("egui font"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * font_image.width as u32),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("gui"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        }));
+        self.font_texture_version = Some(font_image.version);
+    }
+
+    fn update_buffers(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        clipped_meshes: &[ClippedMesh],
+    ) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for ClippedMesh(_clip_rect, mesh) in clipped_meshes.iter() {
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        let vertex_bytes = as_bytes(&vertices);
+        if vertex_bytes.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::buffer(
+                device,
+                (vertex_bytes.len() as u64).next_power_of_two(),
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+        let index_bytes = as_bytes(&indices);
+        if index_bytes.len() as u64 > self.index_buffer.size() {
+            self.index_buffer = Self::buffer(
+                device,
+                (index_bytes.len() as u64).next_power_of_two(),
+                wgpu::BufferUsages::INDEX,
+            );
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, vertex_bytes);
+        queue.write_buffer(&self.index_buffer, 0, index_bytes);
+    }
+
+    pub fn issue_commands<'a>(
+        &'a self,
+        queue: &wgpu::Queue,
+        pass: &mut wgpu::RenderPass<'a>,
+        viewport: Viewport,
+        clipped_meshes: &[ClippedMesh],
+        stats: &mut RenderStats,
+    ) {
+        let bind_group = match self.bind_group.as_ref() {
+            Some(bind_group) => bind_group,
+            None => return,
+        };
+
+        let screen_size = [viewport.width, viewport.height, 0.0, 0.0];
+        queue.write_buffer(&self.uniform_buffer, 0, as_bytes(&screen_size));
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        stats.pipeline_switches += 1;
+
+        let mut index_offset = 0;
+        let mut vertex_offset = 0;
+        for ClippedMesh(clip_rect, mesh) in clipped_meshes.iter() {
+            let number_of_indices = mesh.indices.len() as u32;
+            let first_index = index_offset;
+            let base_vertex = vertex_offset;
+            index_offset += number_of_indices;
+            vertex_offset += mesh.vertices.len() as i32;
+
+            // Clip rects are clamped to the target, skipping meshes clipped away entirely
+            let min_x = clip_rect.min.x.clamp(0.0, viewport.width).round() as u32;
+            let min_y = clip_rect.min.y.clamp(0.0, viewport.height).round() as u32;
+            let max_x = clip_rect.max.x.clamp(0.0, viewport.width).round() as u32;
+            let max_y = clip_rect.max.y.clamp(0.0, viewport.height).round() as u32;
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+
+            pass.set_scissor_rect(min_x, min_y, max_x - min_x, max_y - min_y);
+            pass.draw_indexed(
+                first_index..first_index + number_of_indices,
+                base_vertex,
+                0..1,
+            );
+            stats.draw_calls += 1;
+            stats.instances += 1;
+            stats.triangles += number_of_indices as u64 / 3;
+        }
+    }
+}
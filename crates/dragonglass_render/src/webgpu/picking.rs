@@ -0,0 +1,344 @@
+use super::{as_bytes, load_shader, world::WorldRender};
+use crate::entity_at_index;
+use anyhow::Result;
+use dragonglass_world::{
+    legion::EntityStore, Camera, Entity, Hidden, Light, MeshRender, Viewport, World,
+};
+use nalgebra_glm as glm;
+use std::{mem, num::NonZeroU32};
+
+// The shader's picking uniforms
+struct PickingUniform {
+    // Scales the picked pixel up to fill the single pixel target
+    pick: glm::Mat4,
+    viewport_size: glm::Vec2,
+    proxy_size: f32,
+    padding: f32,
+}
+
+// Renders the index of the entity covering the picked pixel, offset by one so zero means nothing was drawn.
+// Viewports can't extend past their target in wgpu, so the projection is zoomed in on the pixel instead.
+// Lights and cameras have no geometry, so they are drawn as small screen facing quads.
+pub struct PickingRender {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    mesh_pipeline: wgpu::RenderPipeline,
+    proxy_pipeline: wgpu::RenderPipeline,
+    id_texture: wgpu::Texture,
+    id_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    readback_buffer: wgpu::Buffer,
+}
+
+impl PickingRender {
+    pub const PROXY_SIZE: f32 = 24.0;
+
+    const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Sint;
+    const VERTICES_PER_QUAD: u32 = 6;
+
+    // Created along with the world render, since the pipelines use its bind group layout
+    pub fn new(device: &wgpu::Device, world_render: &WorldRender) -> Result<Self> {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("picking uniforms"),
+            size: mem::size_of::<PickingUniform>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("picking"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("picking"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let (mesh_pipeline, proxy_pipeline) = Self::create_pipelines(
+            device,
+            world_render.world_bind_group_layout(),
+            &bind_group_layout,
+        )?;
+
+        let size = wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        let id_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("entity ids"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let id_view = id_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("picking depth"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: WorldRender::DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Buffer copies need their rows aligned, even a single one
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("picking readback"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            uniform_buffer,
+            bind_group,
+            mesh_pipeline,
+            proxy_pipeline,
+            id_texture,
+            id_view,
+            depth_view,
+            readback_buffer,
+        })
+    }
+
+    fn create_pipelines(
+        device: &wgpu::Device,
+        world_bind_group_layout: &wgpu::BindGroupLayout,
+        picking_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<(wgpu::RenderPipeline, wgpu::RenderPipeline)> {
+        let shader = load_shader(device, "assets/shaders/picking/picking.wgsl")?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("picking"),
+            bind_group_layouts: &[world_bind_group_layout, picking_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_attributes = WorldRender::vertex_attributes();
+        let entity_index_attributes = wgpu::vertex_attr_array![8 => Uint32];
+        let entity_index_buffer = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<u32>() as _,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &entity_index_attributes,
+        };
+        let mesh_buffers = [
+            wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<dragonglass_world::Vertex>() as _,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attributes,
+            },
+            entity_index_buffer.clone(),
+        ];
+        // The proxies skip the vertex buffer, but keep the entity indices in the same slot
+        let proxy_buffers = [
+            wgpu::VertexBufferLayout {
+                array_stride: 0,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[],
+            },
+            entity_index_buffer,
+        ];
+
+        let create_pipeline = |entry_point: &str,
+                               buffers: &[wgpu::VertexBufferLayout],
+                               cull_mode: Option<wgpu::Face>| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("picking"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point,
+                    buffers,
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: WorldRender::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: Self::ID_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            })
+        };
+
+        let mesh = create_pipeline("vs_mesh", &mesh_buffers[..], Some(wgpu::Face::Back));
+        let proxy = create_pipeline("vs_proxy", &proxy_buffers[..], None);
+        Ok((mesh, proxy))
+    }
+
+    // Renders the world as it was last uploaded and reads back the entity under the given window position
+    pub fn pick(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &World,
+        world_render: &WorldRender,
+        viewport: Viewport,
+        (x, y): (f32, f32),
+    ) -> Result<Option<Entity>> {
+        let x = x - viewport.x;
+        let y = y - viewport.y;
+        if x < 0.0 || y < 0.0 || x >= viewport.width || y >= viewport.height {
+            return Ok(None);
+        }
+
+        // The center of the picked pixel in normalized device coordinates, which point up in wgpu
+        let center = glm::vec2(
+            (x.floor() + 0.5) / viewport.width * 2.0 - 1.0,
+            1.0 - (y.floor() + 0.5) / viewport.height * 2.0,
+        );
+        let mut pick = glm::Mat4::identity();
+        pick[(0, 0)] = viewport.width;
+        pick[(1, 1)] = viewport.height;
+        pick[(0, 3)] = -center.x * viewport.width;
+        pick[(1, 3)] = -center.y * viewport.height;
+        let uniform = PickingUniform {
+            pick,
+            viewport_size: glm::vec2(viewport.width, viewport.height),
+            proxy_size: Self::PROXY_SIZE,
+            padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, as_bytes(&[uniform]));
+
+        let active_camera = world.active_camera().ok();
+        let culling_mask = world.active_camera_culling_mask();
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("picking"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.id_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            world_render.bind_world(&mut pass);
+            pass.set_bind_group(1, &self.bind_group, &[]);
+
+            let mut entity_index: u32 = 0;
+            for graph in world.scene.graphs.iter() {
+                graph.walk(|node_index| {
+                    let entity = graph[node_index];
+                    let instances = entity_index..entity_index + 1;
+                    entity_index += 1;
+
+                    let entry = world.ecs.entry_ref(entity)?;
+                    if entry.get_component::<Hidden>().is_ok()
+                        || Some(entity) == active_camera
+                        || !world.is_layer_visible(entity, culling_mask)
+                    {
+                        return Ok(());
+                    }
+
+                    let mesh_render = match entry.get_component::<MeshRender>() {
+                        Ok(mesh_render) => mesh_render,
+                        Err(_) => {
+                            if entry.get_component::<Light>().is_ok()
+                                || entry.get_component::<Camera>().is_ok()
+                            {
+                                pass.set_pipeline(&self.proxy_pipeline);
+                                pass.draw(0..Self::VERTICES_PER_QUAD, instances);
+                            }
+                            return Ok(());
+                        }
+                    };
+                    let mesh = match world.assets.geometry.meshes.get(&mesh_render.name) {
+                        Some(mesh) => mesh,
+                        None => return Ok(()),
+                    };
+
+                    pass.set_pipeline(&self.mesh_pipeline);
+                    for primitive in mesh.primitives.iter() {
+                        if primitive.number_of_indices > 0 {
+                            let first_index = primitive.first_index as u32;
+                            let indices =
+                                first_index..first_index + primitive.number_of_indices as u32;
+                            pass.draw_indexed(indices, 0, instances.clone());
+                        } else {
+                            let first_vertex = primitive.first_vertex as u32;
+                            let vertices =
+                                first_vertex..first_vertex + primitive.number_of_vertices as u32;
+                            pass.draw(vertices, instances.clone());
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            self.id_texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = self.readback_buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+        let id = {
+            let bytes = slice.get_mapped_range();
+            i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        self.readback_buffer.unmap();
+
+        if id <= 0 {
+            return Ok(None);
+        }
+        entity_at_index(world, (id - 1) as usize)
+    }
+}
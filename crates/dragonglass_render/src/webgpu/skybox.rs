@@ -0,0 +1,200 @@
+use super::{as_bytes, environment::Environments, load_shader, world::WorldRender};
+use crate::RenderStats;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use std::mem;
+
+#[derive(Copy, Clone)]
+struct SkyboxUniforms {
+    inverse_projection: glm::Mat4,
+    inverse_view: glm::Mat4,
+    blend: f32,
+}
+
+pub struct SkyboxRender {
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl SkyboxRender {
+    pub fn new(
+        device: &wgpu::Device,
+        environments: &Environments,
+        color_format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("skybox uniforms"),
+            // Uniform blocks are padded out to a multiple of 16 bytes
+            size: wgpu::util::align_to(mem::size_of::<SkyboxUniforms>() as u64, 16),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("skybox"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group_layout = Self::bind_group_layout(device);
+        let bind_group = Self::bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &sampler,
+            environments,
+        );
+        let pipeline = Self::create_pipeline(device, &bind_group_layout, color_format)?;
+        Ok(Self {
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let cube_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                multisampled: false,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                cube_entry(2),
+                cube_entry(3),
+            ],
+        })
+    }
+
+    fn bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        environments: &Environments,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skybox"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&environments.active().prefilter),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&environments.faded().prefilter),
+                },
+            ],
+        })
+    }
+
+    // Drawn first and behind everything, so it neither tests nor writes depth
+    fn create_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+    ) -> Result<wgpu::RenderPipeline> {
+        let shader = load_shader(device, "assets/shaders/skybox/skybox.wgsl")?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skybox"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: WorldRender::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[color_format.into()],
+            }),
+            multiview: None,
+        });
+        Ok(pipeline)
+    }
+
+    // Rebinds the maps of the scene's skyboxes after they have changed
+    pub fn update_environments(&mut self, device: &wgpu::Device, environments: &Environments) {
+        self.bind_group = Self::bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.sampler,
+            environments,
+        );
+    }
+
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        (projection, view): (glm::Mat4, glm::Mat4),
+        blend: f32,
+    ) {
+        let rotation = glm::mat4_to_mat3(&view).transpose();
+        let uniforms = SkyboxUniforms {
+            inverse_projection: glm::inverse(&projection),
+            inverse_view: glm::mat3_to_mat4(&rotation),
+            blend,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, as_bytes(&[uniforms]));
+    }
+
+    pub fn issue_commands<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, stats: &mut RenderStats) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        stats.draw_calls += 1;
+        stats.pipeline_switches += 1;
+        stats.triangles += 1;
+    }
+}
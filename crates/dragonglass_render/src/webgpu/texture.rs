@@ -0,0 +1,235 @@
+use anyhow::{ensure, Result};
+use dragonglass_world::{Filter, Format, Texture, WrappingMode};
+use log::warn;
+use std::num::NonZeroU32;
+
+// A world texture uploaded for sampling, along with the sampler it asked for
+pub struct GpuTexture {
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    // In bytes, for the memory stats
+    pub size: u64,
+    _texture: wgpu::Texture,
+}
+
+impl GpuTexture {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, texture: &Texture) -> Result<Self> {
+        let format = match map_format(texture.format, device.features()) {
+            Some(format) => format,
+            None => {
+                warn!(
+                    "Textures in the {:?} format are unsupported by the wgpu backend, using a white texture instead",
+                    texture.format
+                );
+                return Ok(Self::white(device, queue));
+            }
+        };
+        let levels = texture_levels(texture)?;
+        let sampler = device.create_sampler(&sampler_descriptor(&texture.sampler));
+        Ok(Self::upload(
+            device,
+            queue,
+            format,
+            texture.format.block_size().0,
+            (texture.width, texture.height),
+            &levels,
+            sampler,
+        ))
+    }
+
+    // A single white texel, bound to the texture slots a material leaves empty
+    pub fn white(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        Self::upload(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8Unorm,
+            1,
+            (1, 1),
+            &[vec![255, 255, 255, 255]],
+            sampler,
+        )
+    }
+
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        block_dimension: u32,
+        (width, height): (u32, u32),
+        levels: &[Vec<u8>],
+        sampler: wgpu::Sampler,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        for (level, pixels) in levels.iter().enumerate() {
+            // Block compressed levels are copied whole blocks at a time
+            let blocks_wide =
+                (((width >> level).max(1) + block_dimension - 1) / block_dimension).max(1);
+            let blocks_high =
+                (((height >> level).max(1) + block_dimension - 1) / block_dimension).max(1);
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                pixels,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(pixels.len() as u32 / blocks_high),
+                    rows_per_image: NonZeroU32::new(blocks_high),
+                },
+                wgpu::Extent3d {
+                    width: blocks_wide * block_dimension,
+                    height: blocks_high * block_dimension,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            view,
+            sampler,
+            size: levels.iter().map(|level| level.len() as u64).sum(),
+            _texture: texture,
+        }
+    }
+}
+
+fn map_format(format: Format, features: wgpu::Features) -> Option<wgpu::TextureFormat> {
+    let block_compression = features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC);
+    match format {
+        Format::R8 => Some(wgpu::TextureFormat::R8Unorm),
+        Format::R8G8 => Some(wgpu::TextureFormat::Rg8Unorm),
+        // 24-bit textures are expanded to 32-bit before they are uploaded
+        Format::R8G8B8 | Format::R8G8B8A8 => Some(wgpu::TextureFormat::Rgba8Unorm),
+        Format::B8G8R8 | Format::B8G8R8A8 => Some(wgpu::TextureFormat::Bgra8Unorm),
+        Format::R16F => Some(wgpu::TextureFormat::R16Float),
+        Format::R16G16F => Some(wgpu::TextureFormat::Rg16Float),
+        Format::R16G16B16A16F => Some(wgpu::TextureFormat::Rgba16Float),
+        Format::Bc1 if block_compression => Some(wgpu::TextureFormat::Bc1RgbaUnorm),
+        Format::Bc3 if block_compression => Some(wgpu::TextureFormat::Bc3RgbaUnorm),
+        Format::Bc4 if block_compression => Some(wgpu::TextureFormat::Bc4RUnorm),
+        Format::Bc5 if block_compression => Some(wgpu::TextureFormat::Bc5RgUnorm),
+        Format::Bc7 if block_compression => Some(wgpu::TextureFormat::Bc7RgbaUnorm),
+        // Integer and 32-bit float formats can't be filtered without optional features
+        _ => None,
+    }
+}
+
+// Splits the texture into its mip levels, largest first.
+// Uncompressed 8-bit color textures with a single level have the rest of their mips generated here,
+// where the vulkan backend would blit them on the gpu.
+fn texture_levels(texture: &Texture) -> Result<Vec<Vec<u8>>> {
+    let expand = matches!(texture.format, Format::R8G8B8 | Format::B8G8R8);
+    if !texture.has_precomputed_mips() {
+        let pixels = if expand {
+            expand_24bit(&texture.pixels)
+        } else {
+            texture.pixels.to_vec()
+        };
+        let levels = match texture.format {
+            Format::R8G8B8 | Format::R8G8B8A8 | Format::B8G8R8 | Format::B8G8R8A8 => {
+                generate_mips(pixels, texture.width, texture.height)
+            }
+            _ => vec![pixels],
+        };
+        return Ok(levels);
+    }
+
+    let mut levels = Vec::new();
+    let mut offset = 0;
+    for level in 0..texture.mip_levels.max(1) {
+        let width = (texture.width >> level).max(1);
+        let height = (texture.height >> level).max(1);
+        let size = texture.format.level_size(width, height);
+        ensure!(
+            offset + size <= texture.pixels.len(),
+            "Texture is missing pixels for mip level {}!",
+            level
+        );
+        let pixels = &texture.pixels[offset..offset + size];
+        levels.push(if expand {
+            expand_24bit(pixels)
+        } else {
+            pixels.to_vec()
+        });
+        offset += size;
+    }
+    Ok(levels)
+}
+
+fn expand_24bit(pixels: &[u8]) -> Vec<u8> {
+    pixels
+        .chunks_exact(3)
+        .flat_map(|texel| [texel[0], texel[1], texel[2], 255])
+        .collect()
+}
+
+// Averages each 2x2 block of texels into the next level down until a single texel is left
+fn generate_mips(pixels: Vec<u8>, width: u32, height: u32) -> Vec<Vec<u8>> {
+    let mut levels = vec![pixels];
+    let (mut width, mut height) = (width, height);
+    while width > 1 || height > 1 {
+        let previous = &levels[levels.len() - 1];
+        let next_width = (width / 2).max(1);
+        let next_height = (height / 2).max(1);
+        let mut next = Vec::with_capacity((next_width * next_height * 4) as usize);
+        for y in 0..next_height {
+            for x in 0..next_width {
+                for channel in 0..4 {
+                    let sum = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(offset_x, offset_y)| {
+                            let source_x = (x * 2 + offset_x).min(width - 1);
+                            let source_y = (y * 2 + offset_y).min(height - 1);
+                            previous[((source_y * width + source_x) * 4 + channel) as usize] as u32
+                        })
+                        .sum::<u32>();
+                    next.push((sum / 4) as u8);
+                }
+            }
+        }
+        levels.push(next);
+        width = next_width;
+        height = next_height;
+    }
+    levels
+}
+
+fn sampler_descriptor(sampler: &dragonglass_world::Sampler) -> wgpu::SamplerDescriptor<'static> {
+    let filter = |filter: &Filter| match filter {
+        Filter::Linear => wgpu::FilterMode::Linear,
+        Filter::Nearest => wgpu::FilterMode::Nearest,
+    };
+    let address_mode = |wrapping_mode: &WrappingMode| match wrapping_mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    wgpu::SamplerDescriptor {
+        label: None,
+        address_mode_u: address_mode(&sampler.wrap_s),
+        address_mode_v: address_mode(&sampler.wrap_t),
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: filter(&sampler.mag_filter),
+        min_filter: filter(&sampler.min_filter),
+        mipmap_filter: filter(&sampler.min_filter),
+        ..Default::default()
+    }
+}
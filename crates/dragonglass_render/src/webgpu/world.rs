@@ -0,0 +1,918 @@
+use super::{as_bytes, environment::Environments, load_shader, texture::GpuTexture};
+use crate::{
+    byte_slice_from,
+    uniforms::{
+        self, EntityData, Light, PushConstantMaterial, ReflectionProbeData, WorldUniformBuffer,
+    },
+    RenderStats,
+};
+use anyhow::{Context, Result};
+use dragonglass_world::{
+    legion::EntityStore, AlphaMode, Entity, Hidden, Layers, Material, MeshRender, ShadingModel,
+    Vertex, World,
+};
+use nalgebra_glm as glm;
+use std::{mem, ops::Range};
+
+// The material as the shader reads it from its uniform buffer.
+// The vulkan backend picks the shading model with a specialization constant instead.
+struct MaterialUniform {
+    material: PushConstantMaterial,
+    shading_model: i32,
+}
+
+// A material's uniform buffer and the bind group with its textures
+struct MaterialBinding {
+    bind_group: wgpu::BindGroup,
+    _uniform_buffer: wgpu::Buffer,
+}
+
+// The opaque and blended pipelines for one fragment shader entry point,
+// indexed by whether the material is double sided, which turns off back face culling
+struct ShadingPipelines {
+    opaque: [wgpu::RenderPipeline; 2],
+    blended: [wgpu::RenderPipeline; 2],
+}
+
+impl ShadingPipelines {
+    fn pipeline(&self, alpha_mode: AlphaMode, double_sided: bool) -> &wgpu::RenderPipeline {
+        match alpha_mode {
+            AlphaMode::Opaque | AlphaMode::Mask => &self.opaque[double_sided as usize],
+            AlphaMode::Blend => &self.blended[double_sided as usize],
+        }
+    }
+}
+
+pub struct WorldRender {
+    uniform_buffer: wgpu::Buffer,
+    // Grown as the world's entities and joints outgrow them
+    entity_buffer: wgpu::Buffer,
+    joint_buffer: wgpu::Buffer,
+    // Counts up from zero, so the first instance of each draw selects its entity
+    entity_index_buffer: wgpu::Buffer,
+    world_bind_group_layout: wgpu::BindGroupLayout,
+    world_bind_group: wgpu::BindGroup,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    environment_bind_group_layout: wgpu::BindGroupLayout,
+    // The brdf lookup table and the active skybox's prefiltered and irradiance maps
+    environment_bind_group: wgpu::BindGroup,
+    environment_sampler: wgpu::Sampler,
+    // Whether the world has a skybox to be lit by, rather than its ambient light
+    environment_lighting: bool,
+    // Indexed the same as the world's materials
+    materials: Vec<MaterialBinding>,
+    default_material: MaterialBinding,
+    materials_revision: Option<u64>,
+    textures: Vec<GpuTexture>,
+    // The world's revision of each texture when it was uploaded
+    texture_revisions: Vec<u64>,
+    white_texture: GpuTexture,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // How much of the world's geometry has been uploaded
+    number_of_vertices: usize,
+    number_of_indices: usize,
+    // The world geometry's generation when it was uploaded
+    geometry_generation: u64,
+    lit_pipelines: ShadingPipelines,
+    // Unlit materials skip lighting entirely, so they are drawn with a cheaper fragment shader
+    unlit_pipelines: ShadingPipelines,
+    // Only available when the adapter can rasterize lines
+    wireframe_pipeline: Option<wgpu::RenderPipeline>,
+    pub wireframe_enabled: bool,
+}
+
+impl WorldRender {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // The five material textures and the four terrain layers
+    const NUMBER_OF_TEXTURE_SLOTS: usize = 9;
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &World,
+        environments: &Environments,
+        color_format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("world uniforms"),
+            size: mem::size_of::<WorldUniformBuffer>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let entity_buffer =
+            Self::storage_buffer::<EntityData>(device, uniforms::INITIAL_NUMBER_OF_ENTITIES);
+        let joint_buffer =
+            Self::storage_buffer::<glm::Mat4>(device, uniforms::INITIAL_NUMBER_OF_JOINTS);
+        let entity_index_buffer =
+            Self::entity_index_buffer(device, queue, uniforms::INITIAL_NUMBER_OF_ENTITIES);
+
+        let world_bind_group_layout = Self::world_bind_group_layout(device);
+        let world_bind_group = Self::world_bind_group(
+            device,
+            &world_bind_group_layout,
+            &uniform_buffer,
+            &entity_buffer,
+            &joint_buffer,
+        );
+        let material_bind_group_layout = Self::material_bind_group_layout(device);
+        let environment_bind_group_layout = Self::environment_bind_group_layout(device);
+        let environment_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let environment_bind_group = Self::environment_bind_group(
+            device,
+            &environment_bind_group_layout,
+            &environment_sampler,
+            environments,
+        );
+
+        let white_texture = GpuTexture::white(device, queue);
+        let default_material = Self::material_binding(
+            device,
+            &material_bind_group_layout,
+            &[],
+            &white_texture,
+            &Material::default(),
+        );
+
+        let (lit_pipelines, unlit_pipelines, wireframe_pipeline) = Self::create_pipelines(
+            device,
+            &world_bind_group_layout,
+            &material_bind_group_layout,
+            &environment_bind_group_layout,
+            color_format,
+        )?;
+
+        let mut world_render = Self {
+            uniform_buffer,
+            entity_buffer,
+            joint_buffer,
+            entity_index_buffer,
+            world_bind_group_layout,
+            world_bind_group,
+            material_bind_group_layout,
+            environment_bind_group_layout,
+            environment_bind_group,
+            environment_sampler,
+            environment_lighting: environments.has_environment(),
+            materials: Vec::new(),
+            default_material,
+            materials_revision: None,
+            textures: Vec::new(),
+            texture_revisions: Vec::new(),
+            white_texture,
+            vertex_buffer: Self::geometry_buffer(device, 0, wgpu::BufferUsages::VERTEX),
+            index_buffer: Self::geometry_buffer(device, 0, wgpu::BufferUsages::INDEX),
+            number_of_vertices: 0,
+            number_of_indices: 0,
+            geometry_generation: 0,
+            lit_pipelines,
+            unlit_pipelines,
+            wireframe_pipeline,
+            wireframe_enabled: false,
+        };
+        world_render.update_geometry(device, queue, world);
+        world_render.update_textures(device, queue, world)?;
+        Ok(world_render)
+    }
+
+    fn storage_buffer<T>(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (capacity * mem::size_of::<T>()) as _,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn entity_index_buffer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capacity: usize,
+    ) -> wgpu::Buffer {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("entity indices"),
+            size: (capacity * mem::size_of::<u32>()) as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indices = (0..capacity as u32).collect::<Vec<_>>();
+        queue.write_buffer(&buffer, 0, as_bytes(&indices));
+        buffer
+    }
+
+    // Leaves room for geometry to be added without recreating the buffers every time
+    fn geometry_buffer(
+        device: &wgpu::Device,
+        size: u64,
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        let size = (size + size / 2).max(uniforms::MINIMUM_GEOMETRY_BUFFER_SIZE);
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            // Writes must be a multiple of four bytes long
+            size: wgpu::util::align_to(size, wgpu::COPY_BUFFER_ALIGNMENT),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn world_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("world"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+            ],
+        })
+    }
+
+    fn world_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        entity_buffer: &wgpu::Buffer,
+        joint_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("world"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: entity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: joint_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // The material uniforms followed by the color, metallic roughness, normal, occlusion, and emissive textures,
+    // then the four terrain layers. Each texture is bound with its own sampler.
+    fn material_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        for slot in 0..Self::NUMBER_OF_TEXTURE_SLOTS as u32 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 1 + slot * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2 + slot * 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material"),
+            entries: &entries,
+        })
+    }
+
+    // The brdf lookup table followed by the prefiltered and irradiance cubemaps, which share a sampler
+    fn environment_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment"),
+            entries: &[
+                texture_entry(0, wgpu::TextureViewDimension::D2),
+                texture_entry(1, wgpu::TextureViewDimension::Cube),
+                texture_entry(2, wgpu::TextureViewDimension::Cube),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn environment_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        environments: &Environments,
+    ) -> wgpu::BindGroup {
+        let environment = environments.active();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&environments.brdflut),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&environment.prefilter),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    // Rebinds the active skybox's maps after the scene's skybox has changed
+    pub fn update_environment(&mut self, device: &wgpu::Device, environments: &Environments) {
+        self.environment_bind_group = Self::environment_bind_group(
+            device,
+            &self.environment_bind_group_layout,
+            &self.environment_sampler,
+            environments,
+        );
+        self.environment_lighting = environments.has_environment();
+    }
+
+    fn material_binding(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        textures: &[GpuTexture],
+        white_texture: &GpuTexture,
+        material: &Material,
+    ) -> MaterialBinding {
+        let uniform = MaterialUniform {
+            material: PushConstantMaterial::from(material),
+            shading_model: material.shading_model.variant() as i32,
+        };
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(material.name.as_str()),
+            // Uniform blocks are padded out to a multiple of 16 bytes
+            size: wgpu::util::align_to(mem::size_of::<MaterialUniform>() as u64, 16),
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: true,
+        });
+        uniform_buffer.slice(..).get_mapped_range_mut()[..mem::size_of::<MaterialUniform>()]
+            .copy_from_slice(unsafe { byte_slice_from(&uniform) });
+        uniform_buffer.unmap();
+
+        let texture = |index: i32| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| textures.get(index))
+                .unwrap_or(white_texture)
+        };
+        let terrain_layers = match material.shading_model {
+            ShadingModel::Terrain { layers, .. } => layers,
+            _ => [-1; 4],
+        };
+        let slots = [
+            texture(material.color_texture_index),
+            texture(material.metallic_roughness_texture_index),
+            texture(material.normal_texture_index),
+            texture(material.occlusion_texture_index),
+            texture(material.emissive_texture_index),
+            texture(terrain_layers[0]),
+            texture(terrain_layers[1]),
+            texture(terrain_layers[2]),
+            texture(terrain_layers[3]),
+        ];
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }];
+        for (slot, texture) in slots.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1 + slot as u32 * 2,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2 + slot as u32 * 2,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(material.name.as_str()),
+            layout,
+            entries: &entries,
+        });
+
+        MaterialBinding {
+            bind_group,
+            _uniform_buffer: uniform_buffer,
+        }
+    }
+
+    pub fn vertex_attributes() -> [wgpu::VertexAttribute; 8] {
+        wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+            2 => Float32x2,
+            3 => Float32x2,
+            4 => Float32x4,
+            5 => Float32x4,
+            6 => Float32x3,
+            7 => Float32x4,
+        ]
+    }
+
+    fn create_pipelines(
+        device: &wgpu::Device,
+        world_bind_group_layout: &wgpu::BindGroupLayout,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        environment_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+    ) -> Result<(
+        ShadingPipelines,
+        ShadingPipelines,
+        Option<wgpu::RenderPipeline>,
+    )> {
+        let shader = load_shader(device, "assets/shaders/world/world.wgsl")?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("world"),
+            bind_group_layouts: &[
+                world_bind_group_layout,
+                material_bind_group_layout,
+                environment_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // The geometry is always interleaved, since vertex layouts are a vulkan backend setting
+        let vertex_attributes = Self::vertex_attributes();
+        let entity_index_attributes = wgpu::vertex_attr_array![8 => Uint32];
+        let vertex_buffers = [
+            wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<Vertex>() as _,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attributes,
+            },
+            wgpu::VertexBufferLayout {
+                array_stride: mem::size_of::<u32>() as _,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &entity_index_attributes,
+            },
+        ];
+
+        let create_pipeline = |entry_point, blend, polygon_mode, cull_mode| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("world"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &vertex_buffers,
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode,
+                    polygon_mode,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Self::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            })
+        };
+
+        let back = Some(wgpu::Face::Back);
+        let fill = wgpu::PolygonMode::Fill;
+        let alpha_blending = Some(wgpu::BlendState::ALPHA_BLENDING);
+        let shading_pipelines = |entry_point| ShadingPipelines {
+            opaque: [
+                create_pipeline(entry_point, None, fill, back),
+                create_pipeline(entry_point, None, fill, None),
+            ],
+            blended: [
+                create_pipeline(entry_point, alpha_blending, fill, back),
+                create_pipeline(entry_point, alpha_blending, fill, None),
+            ],
+        };
+        let lit = shading_pipelines("fs_main");
+        let unlit = shading_pipelines("fs_unlit");
+        let wireframe = device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| create_pipeline("fs_main", None, wgpu::PolygonMode::Line, back));
+        Ok((lit, unlit, wireframe))
+    }
+
+    pub fn geometry_changed(&self, world: &World) -> bool {
+        let geometry = &world.assets.geometry;
+        geometry.vertices.len() != self.number_of_vertices
+            || geometry.indices.len() != self.number_of_indices
+            || geometry.generation() != self.geometry_generation
+    }
+
+    // Geometry is mostly appended to, so only what was added since the last upload is written.
+    // Everything is written again when it was rewritten or the buffers had to be recreated to fit it.
+    pub fn update_geometry(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, world: &World) {
+        let geometry = &world.assets.geometry;
+        let rewritten = geometry.generation() != self.geometry_generation
+            || geometry.vertices.len() < self.number_of_vertices
+            || geometry.indices.len() < self.number_of_indices;
+        self.geometry_generation = geometry.generation();
+
+        let vertex_size = mem::size_of_val(geometry.vertices.as_slice()) as u64;
+        let mut first_vertex = if rewritten {
+            0
+        } else {
+            self.number_of_vertices
+        };
+        if vertex_size > self.vertex_buffer.size() {
+            self.vertex_buffer =
+                Self::geometry_buffer(device, vertex_size, wgpu::BufferUsages::VERTEX);
+            first_vertex = 0;
+        }
+        Self::write_padded(
+            queue,
+            &self.vertex_buffer,
+            (first_vertex * mem::size_of::<Vertex>()) as _,
+            as_bytes(&geometry.vertices[first_vertex..]),
+        );
+        self.number_of_vertices = geometry.vertices.len();
+
+        let index_size = mem::size_of_val(geometry.indices.as_slice()) as u64;
+        let mut first_index = if rewritten { 0 } else { self.number_of_indices };
+        if index_size > self.index_buffer.size() {
+            self.index_buffer =
+                Self::geometry_buffer(device, index_size, wgpu::BufferUsages::INDEX);
+            first_index = 0;
+        }
+        Self::write_padded(
+            queue,
+            &self.index_buffer,
+            (first_index * mem::size_of::<u32>()) as _,
+            as_bytes(&geometry.indices[first_index..]),
+        );
+        self.number_of_indices = geometry.indices.len();
+    }
+
+    // Reuploads a range of vertices that have been modified in place.
+    // Vertices that haven't been written yet are left to the next geometry update.
+    pub fn update_vertices(&mut self, queue: &wgpu::Queue, world: &World, range: Range<usize>) {
+        let vertices = &world.assets.geometry.vertices;
+        let end = range.end.min(vertices.len()).min(self.number_of_vertices);
+        if range.start >= end {
+            return;
+        }
+        Self::write_padded(
+            queue,
+            &self.vertex_buffer,
+            (range.start * mem::size_of::<Vertex>()) as _,
+            as_bytes(&vertices[range.start..end]),
+        );
+    }
+
+    // Buffer writes must be a multiple of four bytes long, which three component vertices may not be
+    fn write_padded(queue: &wgpu::Queue, buffer: &wgpu::Buffer, offset: u64, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let padded_length = wgpu::util::align_to(data.len(), wgpu::COPY_BUFFER_ALIGNMENT as usize);
+        if padded_length == data.len() {
+            queue.write_buffer(buffer, offset, data);
+        } else {
+            let mut padded = data.to_vec();
+            padded.resize(padded_length, 0);
+            queue.write_buffer(buffer, offset, &padded);
+        }
+    }
+
+    pub fn textures_changed(&self, world: &World) -> bool {
+        world.assets.textures.len() != self.textures.len()
+            || self
+                .texture_revisions
+                .iter()
+                .enumerate()
+                .any(|(index, revision)| world.assets.texture_revision(index) != *revision)
+    }
+
+    // Uploads textures added to the world since it was loaded and reuploads replaced ones.
+    // The material bind groups are rebuilt afterwards, since they reference the textures directly.
+    pub fn update_textures(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &World,
+    ) -> Result<()> {
+        let number_of_textures = world.assets.textures.len();
+        self.textures.truncate(number_of_textures);
+        self.texture_revisions.truncate(number_of_textures);
+
+        for (index, texture) in world.assets.textures.iter().enumerate() {
+            let revision = world.assets.texture_revision(index);
+            if self.texture_revisions.get(index) == Some(&revision) {
+                continue;
+            }
+            let uploaded = GpuTexture::new(device, queue, texture)?;
+            if index < self.textures.len() {
+                self.textures[index] = uploaded;
+                self.texture_revisions[index] = revision;
+            } else {
+                self.textures.push(uploaded);
+                self.texture_revisions.push(revision);
+            }
+        }
+
+        self.materials_revision = None;
+        self.update_materials(device, world);
+        Ok(())
+    }
+
+    // Material bind groups are created once, and again only after they have been edited
+    fn update_materials(&mut self, device: &wgpu::Device, world: &World) {
+        let revision = world.assets.materials_revision();
+        if self.materials_revision == Some(revision)
+            && self.materials.len() == world.assets.materials.len()
+        {
+            return;
+        }
+        self.materials = world
+            .assets
+            .materials
+            .iter()
+            .map(|material| {
+                Self::material_binding(
+                    device,
+                    &self.material_bind_group_layout,
+                    &self.textures,
+                    &self.white_texture,
+                    material,
+                )
+            })
+            .collect();
+        self.materials_revision = Some(revision);
+    }
+
+    // Uploads the camera, lights, entity transforms, and joint matrices for the next frame,
+    // growing the storage buffers first if they have run out of room
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &World,
+        camera: Entity,
+        (projection, view): (glm::Mat4, glm::Mat4),
+    ) -> Result<()> {
+        self.update_materials(device, world);
+
+        // Skinned entities are always skinned as they are drawn
+        let entities = uniforms::entity_data(world, |_| Ok(false))?;
+        let joint_matrices = world.joint_matrices()?;
+
+        let entity_capacity = self.entity_buffer.size() as usize / mem::size_of::<EntityData>();
+        let joint_capacity = self.joint_buffer.size() as usize / mem::size_of::<glm::Mat4>();
+        if entities.len() > entity_capacity || joint_matrices.len() > joint_capacity {
+            if entities.len() > entity_capacity {
+                let capacity = entities.len().next_power_of_two();
+                self.entity_buffer = Self::storage_buffer::<EntityData>(device, capacity);
+                self.entity_index_buffer = Self::entity_index_buffer(device, queue, capacity);
+            }
+            if joint_matrices.len() > joint_capacity {
+                self.joint_buffer = Self::storage_buffer::<glm::Mat4>(
+                    device,
+                    joint_matrices.len().next_power_of_two(),
+                );
+            }
+            self.world_bind_group = Self::world_bind_group(
+                device,
+                &self.world_bind_group_layout,
+                &self.uniform_buffer,
+                &self.entity_buffer,
+                &self.joint_buffer,
+            );
+        }
+        queue.write_buffer(&self.entity_buffer, 0, as_bytes(&entities));
+        queue.write_buffer(&self.joint_buffer, 0, as_bytes(&joint_matrices));
+
+        let mut lights = [Light::default(); uniforms::MAX_NUMBER_OF_LIGHTS];
+        let world_lights = world.lights()?;
+        for (light, (transform, world_light)) in lights.iter_mut().zip(world_lights.iter()) {
+            *light = Light::from_node(transform, world_light);
+        }
+        let ubo = WorldUniformBuffer {
+            view,
+            projection,
+            camera_position: world.entity_global_transform(camera)?.translation,
+            number_of_lights: world_lights.len() as u32,
+            lights,
+            ambient_light: world.scene.ambient_light.radiance(),
+            environment_lighting: self.environment_lighting as i32,
+            // Probes are only baked by the vulkan backend
+            number_of_reflection_probes: 0,
+            exposure: world.camera_exposure(camera),
+            padding: glm::Vec2::zeros(),
+            reflection_probes: [ReflectionProbeData::default();
+                uniforms::MAX_NUMBER_OF_REFLECTION_PROBES],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, as_bytes(&[ubo]));
+        Ok(())
+    }
+
+    pub fn world_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.world_bind_group_layout
+    }
+
+    // Binds the world's uniforms and geometry, which other passes drawing the world's meshes share
+    pub fn bind_world<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        pass.set_bind_group(0, &self.world_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, self.entity_index_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    pub fn issue_commands<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        world: &World,
+        culling_mask: Layers,
+        stats: &mut RenderStats,
+    ) -> Result<()> {
+        self.bind_world(pass);
+        pass.set_bind_group(2, &self.environment_bind_group, &[]);
+
+        let wireframe_pipeline = self
+            .wireframe_pipeline
+            .as_ref()
+            .filter(|_| self.wireframe_enabled);
+
+        let default_material = Material::default();
+        for alpha_mode in [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend].iter() {
+            // Pipelines are only rebound when the shading or sidedness changes between primitives
+            let mut bound_pipeline = None;
+            if let Some(wireframe_pipeline) = wireframe_pipeline {
+                pass.set_pipeline(wireframe_pipeline);
+                stats.pipeline_switches += 1;
+            }
+
+            let mut entity_index: u32 = 0;
+            for graph in world.scene.graphs.iter() {
+                graph.walk(|node_index| {
+                    let entity = graph[node_index];
+                    let index = entity_index;
+                    entity_index += 1;
+
+                    let entry = world.ecs.entry_ref(entity)?;
+                    if entry.get_component::<Hidden>().is_ok()
+                        || world.occlusion.is_culled(entity)
+                        || !world.is_layer_visible(entity, culling_mask)
+                    {
+                        return Ok(());
+                    }
+
+                    let mesh = match entry.get_component::<MeshRender>() {
+                        Ok(mesh_render) => {
+                            match world.assets.geometry.meshes.get(&mesh_render.name) {
+                                Some(mesh) => mesh,
+                                None => return Ok(()),
+                            }
+                        }
+                        Err(_) => return Ok(()),
+                    };
+
+                    for primitive in mesh.primitives.iter() {
+                        let (material, binding) = match primitive.material_index {
+                            Some(material_index) => (
+                                world.assets.material_at_index(material_index)?,
+                                self.materials
+                                    .get(material_index)
+                                    .context("Failed to lookup material bind group!")?,
+                            ),
+                            None => (&default_material, &self.default_material),
+                        };
+                        if material.alpha_mode != *alpha_mode {
+                            continue;
+                        }
+
+                        let unlit = material.shading_model == ShadingModel::Unlit;
+                        let pipeline = (unlit, material.double_sided);
+                        if wireframe_pipeline.is_none() && bound_pipeline != Some(pipeline) {
+                            let pipelines = if unlit {
+                                &self.unlit_pipelines
+                            } else {
+                                &self.lit_pipelines
+                            };
+                            pass.set_pipeline(
+                                pipelines.pipeline(*alpha_mode, material.double_sided),
+                            );
+                            stats.pipeline_switches += 1;
+                            bound_pipeline = Some(pipeline);
+                        }
+
+                        pass.set_bind_group(1, &binding.bind_group, &[]);
+                        let instances = index..index + 1;
+                        if primitive.number_of_indices > 0 {
+                            let first_index = primitive.first_index as u32;
+                            let indices =
+                                first_index..first_index + primitive.number_of_indices as u32;
+                            pass.draw_indexed(indices, 0, instances);
+                            stats.triangles += primitive.number_of_indices as u64 / 3;
+                        } else {
+                            let first_vertex = primitive.first_vertex as u32;
+                            let vertices =
+                                first_vertex..first_vertex + primitive.number_of_vertices as u32;
+                            pass.draw(vertices, instances);
+                            stats.triangles += primitive.number_of_vertices as u64 / 3;
+                        }
+                        stats.draw_calls += 1;
+                        stats.instances += 1;
+                    }
+
+                    Ok(())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn buffer_memory(&self) -> u64 {
+        [
+            &self.uniform_buffer,
+            &self.entity_buffer,
+            &self.joint_buffer,
+            &self.entity_index_buffer,
+            &self.vertex_buffer,
+            &self.index_buffer,
+        ]
+        .iter()
+        .map(|buffer| buffer.size())
+        .sum()
+    }
+
+    pub fn texture_memory(&self) -> u64 {
+        self.textures
+            .iter()
+            .map(|texture| texture.size)
+            .sum::<u64>()
+            + self.white_texture.size
+    }
+}
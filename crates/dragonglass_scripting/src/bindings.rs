@@ -0,0 +1,372 @@
+use dragonglass_app::Input;
+use dragonglass_world::{
+    rapier3d::geometry::Ray, Entity, EntityStore, IntoQuery, Layers, Name, RigidBody, Transform,
+    World,
+};
+use nalgebra_glm as glm;
+use rhai::{Dynamic, Engine, EvalAltResult, Map};
+use serde::de::DeserializeOwned;
+use std::{cell::RefCell, rc::Rc};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+pub(crate) type SharedHost = Rc<RefCell<ScriptHost>>;
+
+// A script function to call for an entity once its delay has passed
+pub(crate) struct Timer {
+    pub entity: Entity,
+    pub remaining: f32,
+    pub function: String,
+}
+
+// The world and input are lent to the host while scripts run,
+// because the functions registered with the engine have to outlive any borrow of them
+#[derive(Default)]
+pub(crate) struct ScriptHost {
+    pub world: World,
+    pub input: Input,
+    pub timers: Vec<Timer>,
+}
+
+pub(crate) fn register_bindings(engine: &mut Engine, host: &SharedHost) {
+    register_math(engine);
+    register_transforms(engine, host);
+    register_entities(engine, host);
+    register_physics(engine, host);
+    register_input(engine, host);
+    register_time(engine, host);
+}
+
+fn register_math(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<glm::Vec3>("Vec3")
+        .register_fn("vec3", |x: f32, y: f32, z: f32| glm::vec3(x, y, z))
+        .register_get_set(
+            "x",
+            |vector: &mut glm::Vec3| vector.x,
+            |vector: &mut glm::Vec3, value: f32| vector.x = value,
+        )
+        .register_get_set(
+            "y",
+            |vector: &mut glm::Vec3| vector.y,
+            |vector: &mut glm::Vec3, value: f32| vector.y = value,
+        )
+        .register_get_set(
+            "z",
+            |vector: &mut glm::Vec3| vector.z,
+            |vector: &mut glm::Vec3, value: f32| vector.z = value,
+        )
+        .register_fn("+", |a: glm::Vec3, b: glm::Vec3| a + b)
+        .register_fn("-", |a: glm::Vec3, b: glm::Vec3| a - b)
+        .register_fn("-", |vector: glm::Vec3| -vector)
+        .register_fn("*", |vector: glm::Vec3, scalar: f32| vector * scalar)
+        .register_fn("*", |scalar: f32, vector: glm::Vec3| vector * scalar)
+        .register_fn("==", |a: glm::Vec3, b: glm::Vec3| a == b)
+        .register_fn("length", |vector: glm::Vec3| vector.norm())
+        .register_fn("normalize", |vector: glm::Vec3| {
+            vector.try_normalize(f32::EPSILON).unwrap_or_default()
+        })
+        .register_fn("dot", |a: glm::Vec3, b: glm::Vec3| a.dot(&b))
+        .register_fn("cross", |a: glm::Vec3, b: glm::Vec3| a.cross(&b))
+        .register_fn("lerp", |a: glm::Vec3, b: glm::Vec3, t: f32| {
+            glm::lerp(&a, &b, t)
+        })
+        .register_fn("to_string", |vector: &mut glm::Vec3| {
+            format!("({}, {}, {})", vector.x, vector.y, vector.z)
+        });
+}
+
+// Transforms are relative to the entity's parent,
+// and entities with rigid bodies are moved along with their transform
+fn register_transforms(engine: &mut Engine, host: &SharedHost) {
+    let shared = host.clone();
+    engine.register_result_fn("position", move |entity: Entity| {
+        read_transform(&shared, entity, |transform| transform.translation)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn(
+        "set_position",
+        move |entity: Entity, position: glm::Vec3| {
+            write_transform(&shared, entity, |transform| {
+                transform.translation = position
+            })
+        },
+    );
+
+    let shared = host.clone();
+    engine.register_result_fn("translate", move |entity: Entity, offset: glm::Vec3| {
+        write_transform(&shared, entity, |transform| transform.translation += offset)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn(
+        "rotate",
+        move |entity: Entity, axis: glm::Vec3, degrees: f32| {
+            let axis = axis
+                .try_normalize(f32::EPSILON)
+                .ok_or("Cannot rotate around a zero length axis")?;
+            let rotation = glm::quat_angle_axis(degrees.to_radians(), &axis);
+            write_transform(&shared, entity, |transform| {
+                transform.rotation = rotation * transform.rotation
+            })
+        },
+    );
+
+    let shared = host.clone();
+    engine.register_result_fn("look_at", move |entity: Entity, target: glm::Vec3| {
+        write_transform(&shared, entity, |transform| {
+            let direction = target - transform.translation;
+            if let Some(direction) = direction.try_normalize(f32::EPSILON) {
+                transform.look_at(&direction, &glm::Vec3::y());
+            }
+        })
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("scale", move |entity: Entity| {
+        read_transform(&shared, entity, |transform| transform.scale)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("set_scale", move |entity: Entity, scale: glm::Vec3| {
+        write_transform(&shared, entity, |transform| transform.scale = scale)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("forward", move |entity: Entity| {
+        read_transform(&shared, entity, Transform::forward)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("right", move |entity: Entity| {
+        read_transform(&shared, entity, Transform::right)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("up", move |entity: Entity| {
+        read_transform(&shared, entity, Transform::up)
+    });
+}
+
+fn register_entities(engine: &mut Engine, host: &SharedHost) {
+    engine
+        .register_type_with_name::<Entity>("Entity")
+        .register_fn("==", |a: Entity, b: Entity| a == b)
+        .register_fn("!=", |a: Entity, b: Entity| a != b)
+        .register_fn("to_string", |entity: &mut Entity| format!("{:?}", entity));
+
+    let shared = host.clone();
+    engine.register_result_fn("spawn", move |name: &str, position: glm::Vec3| {
+        let mut host = shared.borrow_mut();
+        let transform = Transform {
+            translation: position,
+            ..Default::default()
+        };
+        let entity = host.world.ecs.push((Name(name.to_string()), transform));
+        host.world
+            .scene
+            .default_scenegraph_mut()
+            .map_err(|error| error.to_string())?
+            .add_node(entity);
+        Ok(entity)
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("despawn", move |entity: Entity| {
+        shared
+            .borrow_mut()
+            .world
+            .remove_entity(entity)
+            .map_err(|error| error.to_string().into())
+    });
+
+    let shared = host.clone();
+    engine.register_fn("exists", move |entity: Entity| {
+        shared.borrow().world.ecs.entry_ref(entity).is_ok()
+    });
+
+    // The first entity with the name, or () if there is none
+    let shared = host.clone();
+    engine.register_fn("find", move |name: &str| {
+        let host = shared.borrow();
+        let mut query = <(Entity, &Name)>::query();
+        query
+            .iter(&host.world.ecs)
+            .find(|(_, entity_name)| entity_name.0 == name)
+            .map(|(entity, _)| Dynamic::from(*entity))
+            .unwrap_or(Dynamic::UNIT)
+    });
+
+    let shared = host.clone();
+    engine.register_fn("name", move |entity: Entity| {
+        let host = shared.borrow();
+        host.world
+            .ecs
+            .entry_ref(entity)
+            .ok()
+            .and_then(|entry| {
+                entry
+                    .get_component::<Name>()
+                    .ok()
+                    .map(|name| name.0.clone())
+            })
+            .unwrap_or_default()
+    });
+
+    // Calls a function of the entity's script with the entity after a delay
+    let shared = host.clone();
+    engine.register_fn(
+        "after",
+        move |entity: Entity, seconds: f32, function: &str| {
+            shared.borrow_mut().timers.push(Timer {
+                entity,
+                remaining: seconds,
+                function: function.to_string(),
+            });
+        },
+    );
+}
+
+fn register_physics(engine: &mut Engine, host: &SharedHost) {
+    // A map with the 'entity' that was hit, the 'distance' to it and the 'point' that was hit, or () on a miss.
+    // Only colliders on the default layer are hit unless a mask of the layers to hit is given.
+    let shared = host.clone();
+    engine.register_result_fn(
+        "raycast",
+        move |origin: glm::Vec3, direction: glm::Vec3, max_distance: f32| {
+            raycast(&shared, origin, direction, max_distance, Layers::DEFAULT)
+        },
+    );
+
+    let shared = host.clone();
+    engine.register_result_fn(
+        "raycast",
+        move |origin: glm::Vec3, direction: glm::Vec3, max_distance: f32, layers: i64| {
+            raycast(
+                &shared,
+                origin,
+                direction,
+                max_distance,
+                Layers(layers as u32),
+            )
+        },
+    );
+}
+
+fn raycast(
+    host: &SharedHost,
+    origin: glm::Vec3,
+    direction: glm::Vec3,
+    max_distance: f32,
+    layers: Layers,
+) -> ScriptResult<Dynamic> {
+    let direction = direction
+        .try_normalize(f32::EPSILON)
+        .ok_or("Cannot cast a ray with a zero length direction")?;
+    let ray = Ray::new(origin.into(), direction);
+    let host = host.borrow();
+    // The ray belongs to every layer, so it is only filtered by the layers it is looking for
+    let hit = host
+        .world
+        .cast_ray(&ray, max_distance, Layers::ALL.collision_groups(layers))
+        .map_err(|error| error.to_string())?;
+    Ok(match hit {
+        Some((entity, distance)) => {
+            let mut map = Map::new();
+            map.insert("entity".into(), Dynamic::from(entity));
+            map.insert("distance".into(), Dynamic::from(distance));
+            map.insert("point".into(), Dynamic::from(origin + direction * distance));
+            Dynamic::from(map)
+        }
+        None => Dynamic::UNIT,
+    })
+}
+
+// Keys and mouse buttons are named like their winit variants, such as "Space", "W" or "Left"
+fn register_input(engine: &mut Engine, host: &SharedHost) {
+    let shared = host.clone();
+    engine.register_result_fn("key_pressed", move |name: &str| {
+        let keycode = parse_input::<VirtualKeyCode>(name)?;
+        Ok(shared.borrow().input.is_key_pressed(keycode))
+    });
+
+    let shared = host.clone();
+    engine.register_result_fn("mouse_pressed", move |name: &str| {
+        let button = parse_input::<MouseButton>(name)?;
+        Ok(shared.borrow().input.is_mouse_button_pressed(button))
+    });
+
+    let shared = host.clone();
+    engine.register_fn("action_pressed", move |action: &str| {
+        shared.borrow().input.action_pressed(action)
+    });
+
+    let shared = host.clone();
+    engine.register_fn("action_just_pressed", move |action: &str| {
+        shared.borrow().input.action_just_pressed(action)
+    });
+
+    let shared = host.clone();
+    engine.register_fn("axis", move |axis: &str| shared.borrow().input.axis(axis));
+}
+
+fn register_time(engine: &mut Engine, host: &SharedHost) {
+    let shared = host.clone();
+    engine.register_fn("delta_time", move || shared.borrow().world.clock.delta());
+
+    let shared = host.clone();
+    engine.register_fn("elapsed", move || {
+        shared.borrow().world.clock.elapsed() as f32
+    });
+}
+
+fn parse_input<T: DeserializeOwned>(name: &str) -> ScriptResult<T> {
+    toml::Value::String(name.to_string())
+        .try_into()
+        .map_err(|_| format!("Unknown input '{}'", name).into())
+}
+
+fn read_transform<T>(
+    host: &SharedHost,
+    entity: Entity,
+    read: impl FnOnce(&Transform) -> T,
+) -> ScriptResult<T> {
+    let host = host.borrow();
+    let entry = host
+        .world
+        .ecs
+        .entry_ref(entity)
+        .map_err(|_| format!("Entity {:?} does not exist", entity))?;
+    let transform = entry
+        .get_component::<Transform>()
+        .map_err(|_| format!("Entity {:?} has no transform", entity))?;
+    Ok(read(transform))
+}
+
+fn write_transform(
+    host: &SharedHost,
+    entity: Entity,
+    write: impl FnOnce(&mut Transform),
+) -> ScriptResult<()> {
+    let mut host = host.borrow_mut();
+    let has_rigid_body = {
+        let mut entry = host
+            .world
+            .ecs
+            .entry_mut(entity)
+            .map_err(|_| format!("Entity {:?} does not exist", entity))?;
+        let transform = entry
+            .get_component_mut::<Transform>()
+            .map_err(|_| format!("Entity {:?} has no transform", entity))?;
+        write(transform);
+        entry.get_component::<RigidBody>().is_ok()
+    };
+    if has_rigid_body {
+        host.world
+            .sync_rigid_body_to_transform(entity)
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,4 @@
+mod bindings;
+mod system;
+
+pub use self::system::*;
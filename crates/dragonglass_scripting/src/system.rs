@@ -0,0 +1,280 @@
+use crate::bindings::{register_bindings, ScriptHost, SharedHost};
+use anyhow::Result;
+use dragonglass_app::{Resources, Status, TaskRunner};
+use dragonglass_world::{Entity, EntityStore, IntoQuery, Script};
+use log::{info, warn};
+use rhai::{Dynamic, Engine, ImmutableString, Map, Scope, AST};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, mem,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+struct LoadedScript {
+    // None when the script failed to load, so the error is only reported once per change
+    ast: Option<AST>,
+    modified: Option<SystemTime>,
+}
+
+// The state an entity's script keeps between calls, available to its functions as 'this'
+struct ScriptInstance {
+    path: String,
+    state: Dynamic,
+    started: bool,
+}
+
+// Runs the scripts attached to entities through their Script component.
+// Scripts define 'start(entity)' and 'update(entity, dt)' functions,
+// and are reloaded when their file changes on disk.
+pub struct ScriptSystem {
+    engine: Engine,
+    host: SharedHost,
+    scripts: HashMap<String, LoadedScript>,
+    instances: HashMap<Entity, ScriptInstance>,
+    last_reload_check: Instant,
+}
+
+impl Default for ScriptSystem {
+    fn default() -> Self {
+        let host = Rc::new(RefCell::new(ScriptHost::default()));
+        let mut engine = Engine::new();
+        engine.on_print(|text| info!("{}", text));
+        register_bindings(&mut engine, &host);
+        Self {
+            engine,
+            host,
+            scripts: HashMap::new(),
+            instances: HashMap::new(),
+            last_reload_check: Instant::now(),
+        }
+    }
+}
+
+impl ScriptSystem {
+    const RELOAD_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    // Script errors are logged rather than returned, so one broken script doesn't stop the app
+    pub fn update(&mut self, resources: &mut Resources) -> Result<()> {
+        if self.last_reload_check.elapsed() >= Self::RELOAD_CHECK_INTERVAL {
+            self.last_reload_check = Instant::now();
+            self.reload_changed_scripts();
+        }
+
+        let mut query = <(Entity, &Script)>::query();
+        let attached = query
+            .iter(&resources.world.ecs)
+            .map(|(entity, script)| (*entity, script.path.clone()))
+            .collect::<Vec<_>>();
+        self.sync_instances(&attached);
+
+        let delta_time = resources.world.clock.delta();
+        self.swap_resources(resources);
+        self.run_timers(delta_time);
+        self.run_instances();
+        self.swap_resources(resources);
+        Ok(())
+    }
+
+    // Forgets every script's state, so scripts start over the next time they run
+    pub fn reset(&mut self) {
+        self.instances.clear();
+        self.host.borrow_mut().timers.clear();
+    }
+
+    // Lends the world and input to the scripts, and gives them back when called again
+    fn swap_resources(&mut self, resources: &mut Resources) {
+        let mut host = self.host.borrow_mut();
+        mem::swap(&mut host.world, resources.world);
+        mem::swap(&mut host.input, resources.input);
+    }
+
+    fn sync_instances(&mut self, attached: &[(Entity, String)]) {
+        let attached_entities = attached
+            .iter()
+            .map(|(entity, path)| (*entity, path.as_str()))
+            .collect::<HashMap<_, _>>();
+        self.instances.retain(|entity, instance| {
+            attached_entities.get(entity) == Some(&instance.path.as_str())
+        });
+
+        for (entity, path) in attached {
+            if path.is_empty() || self.instances.contains_key(entity) {
+                continue;
+            }
+            if !self.scripts.contains_key(path) {
+                let script = self.load_script(path);
+                self.scripts.insert(path.to_string(), script);
+            }
+            self.instances.insert(
+                *entity,
+                ScriptInstance {
+                    path: path.to_string(),
+                    state: Dynamic::from(Map::new()),
+                    started: false,
+                },
+            );
+        }
+    }
+
+    // Changed scripts start over with fresh state
+    fn reload_changed_scripts(&mut self) {
+        let changed = self
+            .scripts
+            .iter()
+            .filter(|(path, script)| modified_time(path) != script.modified)
+            .map(|(path, _)| path.to_string())
+            .collect::<Vec<_>>();
+        for path in changed {
+            info!("Reloading script '{}'", path);
+            let script = self.load_script(&path);
+            self.scripts.insert(path.to_string(), script);
+            self.instances.retain(|_, instance| instance.path != path);
+        }
+    }
+
+    fn load_script(&self, path: &str) -> LoadedScript {
+        let modified = modified_time(path);
+        let ast = match fs::read_to_string(path) {
+            Ok(source) => match self.engine.compile(&source) {
+                Ok(ast) => Some(ast),
+                Err(error) => {
+                    warn!("Failed to compile script '{}': {}", path, error);
+                    None
+                }
+            },
+            Err(error) => {
+                warn!("Failed to read script '{}': {}", path, error);
+                None
+            }
+        };
+        LoadedScript { ast, modified }
+    }
+
+    fn run_timers(&mut self, delta_time: f32) {
+        let timers = mem::take(&mut self.host.borrow_mut().timers);
+        let mut pending = Vec::new();
+        for mut timer in timers {
+            timer.remaining -= delta_time;
+            if timer.remaining > 0.0 {
+                pending.push(timer);
+                continue;
+            }
+            self.call(
+                timer.entity,
+                &timer.function,
+                vec![Dynamic::from(timer.entity)],
+            );
+        }
+        // Timers scheduled by the functions that just ran are kept as well
+        self.host.borrow_mut().timers.extend(pending);
+    }
+
+    fn run_instances(&mut self) {
+        let delta_time = self.host.borrow().world.clock.delta();
+        // Scripts run in the same order every time, so replays play out the same way
+        let mut entities = self.instances.keys().copied().collect::<Vec<_>>();
+        entities.sort();
+        for entity in entities {
+            let started = match self.instances.get_mut(&entity) {
+                Some(instance) => mem::replace(&mut instance.started, true),
+                None => continue,
+            };
+            if !started {
+                self.call(entity, "start", vec![Dynamic::from(entity)]);
+            }
+            self.call(
+                entity,
+                "update",
+                vec![Dynamic::from(entity), Dynamic::from(delta_time)],
+            );
+        }
+    }
+
+    // Scripts don't have to define every function, so missing ones are skipped.
+    // Entities despawned by an earlier script this frame are skipped as well.
+    fn call(&mut self, entity: Entity, function: &str, arguments: Vec<Dynamic>) -> Option<Dynamic> {
+        if self.host.borrow().world.ecs.entry_ref(entity).is_err() {
+            return None;
+        }
+        let instance = match self.instances.get_mut(&entity) {
+            Some(instance) => instance,
+            None => return None,
+        };
+        let ast = match self
+            .scripts
+            .get(&instance.path)
+            .and_then(|script| script.ast.as_ref())
+        {
+            Some(ast) => ast,
+            None => return None,
+        };
+        let defined = ast
+            .iter_functions()
+            .any(|metadata| metadata.name == function && metadata.params.len() == arguments.len());
+        if !defined {
+            return None;
+        }
+        let result = self.engine.call_fn_raw(
+            &mut Scope::new(),
+            ast,
+            false,
+            false,
+            function,
+            Some(&mut instance.state),
+            arguments,
+        );
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!(
+                    "Script '{}' failed in '{}': {}",
+                    instance.path, function, error
+                );
+                None
+            }
+        }
+    }
+}
+
+// Script tasks in behavior trees call 'function(entity)' in the entity's script.
+// They return true or "success" when they succeed, "running" while they are still going,
+// and anything else when they fail.
+impl TaskRunner for ScriptSystem {
+    fn run_task(
+        &mut self,
+        resources: &mut Resources,
+        entity: Entity,
+        task: &str,
+    ) -> Result<Status> {
+        self.swap_resources(resources);
+        let result = self.call(entity, task, vec![Dynamic::from(entity)]);
+        self.swap_resources(resources);
+        let status = match result {
+            Some(value) if value.is::<bool>() => {
+                if value.cast::<bool>() {
+                    Status::Success
+                } else {
+                    Status::Failure
+                }
+            }
+            Some(value) if value.is::<ImmutableString>() => {
+                match value.cast::<ImmutableString>().as_str() {
+                    "success" => Status::Success,
+                    "running" => Status::Running,
+                    _ => Status::Failure,
+                }
+            }
+            _ => Status::Failure,
+        };
+        Ok(status)
+    }
+}
+
+fn modified_time(path: impl AsRef<Path>) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -1,8 +1,14 @@
-pub use self::{adapters::*, context::*, frame::*, rendergraph::*, resources::*, swapchain::*};
+pub use self::{
+    adapters::*, context::*, frame::*, memory::MemoryTracker, rendergraph::*, resources::*,
+    stats::*, swapchain::*, upload::*,
+};
 
 mod adapters;
 mod context;
 mod frame;
+mod memory;
 mod rendergraph;
 mod resources;
+mod stats;
 mod swapchain;
+mod upload;
@@ -1,7 +1,9 @@
-pub use self::{command::*, descriptor::*, pipeline::*, render::*, sync::*};
+pub use self::{command::*, compute::*, descriptor::*, pipeline::*, query::*, render::*, sync::*};
 
 mod command;
+mod compute;
 mod descriptor;
 mod pipeline;
+mod query;
 mod render;
 mod sync;
@@ -6,6 +6,8 @@ use std::sync::Arc;
 
 pub struct CommandPool {
     pub handle: vk::CommandPool,
+    // Command buffers allocated from the pool are named after it
+    name: String,
     queue: vk::Queue,
     device: Arc<Device>,
 }
@@ -14,12 +16,15 @@ impl CommandPool {
     pub fn new(
         device: Arc<Device>,
         queue: vk::Queue,
+        name: &str,
         create_info: vk::CommandPoolCreateInfoBuilder,
     ) -> Result<Self> {
         // TODO: Construct create info in this method using just a Queue wrapper that has an index and the queue handle
         let handle = unsafe { device.handle.create_command_pool(&create_info, None)? };
+        device.name(handle, &format!("{} command pool", name))?;
         let command_pool = Self {
             handle,
+            name: name.to_string(),
             queue,
             device,
         };
@@ -37,15 +42,31 @@ impl CommandPool {
             .command_buffer_count(count);
         let command_buffers =
             unsafe { self.device.handle.allocate_command_buffers(&allocate_info) }?;
+        for (index, command_buffer) in command_buffers.iter().enumerate() {
+            self.device.name(
+                *command_buffer,
+                &format!("{} command buffer {}", self.name, index),
+            )?;
+        }
         Ok(command_buffers)
     }
 
+    pub fn queue(&self) -> vk::Queue {
+        self.queue
+    }
+
+    pub fn free_command_buffers(&self, command_buffers: &[vk::CommandBuffer]) {
+        unsafe {
+            self.device
+                .handle
+                .free_command_buffers(self.handle, command_buffers)
+        };
+    }
+
     pub fn copy_buffer_to_buffer(&self, info: &BufferToBufferCopy) -> Result<()> {
         let device = self.device.handle.clone();
         self.execute_once(|command_buffer| {
-            unsafe {
-                device.cmd_copy_buffer(command_buffer, info.source, info.destination, &info.regions)
-            };
+            info.record(&device, command_buffer);
             Ok(())
         })
     }
@@ -53,15 +74,15 @@ impl CommandPool {
     pub fn copy_buffer_to_image(&self, info: &BufferToImageCopy) -> Result<()> {
         let device = self.device.handle.clone();
         self.execute_once(|command_buffer| {
-            unsafe {
-                device.cmd_copy_buffer_to_image(
-                    command_buffer,
-                    info.source,
-                    info.destination,
-                    info.dst_image_layout,
-                    &info.regions,
-                )
-            };
+            info.record(&device, command_buffer);
+            Ok(())
+        })
+    }
+
+    pub fn copy_image_to_buffer(&self, info: &ImageToBufferCopy) -> Result<()> {
+        let device = self.device.handle.clone();
+        self.execute_once(|command_buffer| {
+            info.record(&device, command_buffer);
             Ok(())
         })
     }
@@ -69,16 +90,7 @@ impl CommandPool {
     pub fn copy_image_to_image(&self, info: &ImageToImageCopy) -> Result<()> {
         let device = self.device.handle.clone();
         self.execute_once(|command_buffer| {
-            unsafe {
-                device.cmd_copy_image(
-                    command_buffer,
-                    info.source,
-                    info.source_layout,
-                    info.destination,
-                    info.destination_layout,
-                    &info.regions,
-                )
-            };
+            info.record(&device, command_buffer);
             Ok(())
         })
     }
@@ -86,34 +98,15 @@ impl CommandPool {
     pub fn transition_image_layout(&self, info: &PipelineBarrier) -> Result<()> {
         let device = self.device.handle.clone();
         self.execute_once(|command_buffer| {
-            unsafe {
-                device.cmd_pipeline_barrier(
-                    command_buffer,
-                    info.src_stage_mask,
-                    info.dst_stage_mask,
-                    info.dependency_flags,
-                    &info.memory_barriers,
-                    &info.buffer_memory_barriers,
-                    &info.image_memory_barriers,
-                )
-            };
+            info.record(&device, command_buffer);
             Ok(())
         })
     }
 
     pub fn blit_image(&self, info: &BlitImage) -> Result<()> {
+        let device = self.device.handle.clone();
         self.execute_once(|command_buffer| {
-            unsafe {
-                self.device.handle.cmd_blit_image(
-                    command_buffer,
-                    info.src_image,
-                    info.src_image_layout,
-                    info.dst_image,
-                    info.dst_image_layout,
-                    &info.regions,
-                    info.filter,
-                )
-            }
+            info.record(&device, command_buffer);
             Ok(())
         })
     }
@@ -147,8 +140,8 @@ impl CommandPool {
                 std::time::Duration::from_secs(100).as_nanos() as _,
             )?;
             device.queue_wait_idle(self.queue)?;
-            device.free_command_buffers(self.handle, &command_buffers);
         }
+        self.free_command_buffers(&command_buffers);
 
         Ok(())
     }
@@ -169,6 +162,14 @@ pub struct BufferToBufferCopy {
     pub regions: Vec<vk::BufferCopy>,
 }
 
+impl BufferToBufferCopy {
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_copy_buffer(command_buffer, self.source, self.destination, &self.regions)
+        };
+    }
+}
+
 #[derive(Builder)]
 pub struct BufferToImageCopy {
     pub source: vk::Buffer,
@@ -178,6 +179,43 @@ pub struct BufferToImageCopy {
     pub dst_image_layout: vk::ImageLayout,
 }
 
+impl BufferToImageCopy {
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                self.source,
+                self.destination,
+                self.dst_image_layout,
+                &self.regions,
+            )
+        };
+    }
+}
+
+#[derive(Builder)]
+pub struct ImageToBufferCopy {
+    pub source: vk::Image,
+    #[builder(default = "vk::ImageLayout::TRANSFER_SRC_OPTIMAL")]
+    pub source_layout: vk::ImageLayout,
+    pub destination: vk::Buffer,
+    pub regions: Vec<vk::BufferImageCopy>,
+}
+
+impl ImageToBufferCopy {
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                self.source,
+                self.source_layout,
+                self.destination,
+                &self.regions,
+            )
+        };
+    }
+}
+
 #[derive(Builder)]
 pub struct ImageToImageCopy {
     pub source: vk::Image,
@@ -187,6 +225,21 @@ pub struct ImageToImageCopy {
     pub regions: Vec<vk::ImageCopy>,
 }
 
+impl ImageToImageCopy {
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_copy_image(
+                command_buffer,
+                self.source,
+                self.source_layout,
+                self.destination,
+                self.destination_layout,
+                &self.regions,
+            )
+        };
+    }
+}
+
 #[derive(Builder)]
 pub struct PipelineBarrier {
     pub src_stage_mask: vk::PipelineStageFlags,
@@ -197,9 +250,28 @@ pub struct PipelineBarrier {
     pub memory_barriers: Vec<vk::MemoryBarrier>,
     #[builder(default)]
     pub buffer_memory_barriers: Vec<vk::BufferMemoryBarrier>,
+    #[builder(default)]
     pub image_memory_barriers: Vec<vk::ImageMemoryBarrier>,
 }
 
+impl PipelineBarrier {
+    // Records the barrier into a command buffer that is already being recorded,
+    // such as between a compute dispatch and the draws that read its output
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                self.src_stage_mask,
+                self.dst_stage_mask,
+                self.dependency_flags,
+                &self.memory_barriers,
+                &self.buffer_memory_barriers,
+                &self.image_memory_barriers,
+            )
+        };
+    }
+}
+
 #[derive(Builder)]
 pub struct BlitImage {
     pub src_image: vk::Image,
@@ -209,3 +281,19 @@ pub struct BlitImage {
     pub regions: Vec<vk::ImageBlit>,
     pub filter: vk::Filter,
 }
+
+impl BlitImage {
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_blit_image(
+                command_buffer,
+                self.src_image,
+                self.src_image_layout,
+                self.dst_image,
+                self.dst_image_layout,
+                &self.regions,
+                self.filter,
+            )
+        };
+    }
+}
@@ -0,0 +1,66 @@
+use crate::core::{Pipeline, PipelineLayout};
+use ash::vk;
+
+// The number of workgroups needed to cover every invocation,
+// where each workgroup runs `workgroup_size` invocations as declared by the shader's local size
+pub fn workgroup_count(invocations: u32, workgroup_size: u32) -> u32 {
+    let workgroup_size = workgroup_size.max(1);
+    (invocations + workgroup_size - 1) / workgroup_size
+}
+
+// A barrier covering all of a buffer, for passing data written by one command to the next
+pub fn whole_buffer_barrier(
+    buffer: vk::Buffer,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+) -> vk::BufferMemoryBarrier {
+    vk::BufferMemoryBarrier::builder()
+        .buffer(buffer)
+        .offset(0)
+        .size(vk::WHOLE_SIZE)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .build()
+}
+
+// Everything needed to record a single compute dispatch
+pub struct ComputeDispatch<'a> {
+    pub pipeline: &'a Pipeline,
+    pub pipeline_layout: &'a PipelineLayout,
+    pub descriptor_sets: &'a [vk::DescriptorSet],
+    // Pushed to the compute stage before dispatching, if not empty
+    pub push_constants: &'a [u8],
+    pub workgroups: [u32; 3],
+}
+
+impl<'a> ComputeDispatch<'a> {
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        self.pipeline.bind(device, command_buffer);
+        unsafe {
+            if !self.descriptor_sets.is_empty() {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout.handle,
+                    0,
+                    self.descriptor_sets,
+                    &[],
+                );
+            }
+            if !self.push_constants.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout.handle,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    self.push_constants,
+                );
+            }
+            let [x, y, z] = self.workgroups;
+            device.cmd_dispatch(command_buffer, x, y, z);
+        }
+        self.pipeline.device.commands.record_dispatch();
+    }
+}
@@ -48,9 +48,11 @@ impl DescriptorPool {
         Ok(descriptor_pool)
     }
 
+    // Every set allocated at once shares the name
     pub fn allocate_descriptor_sets(
         &self,
         layout: vk::DescriptorSetLayout,
+        name: &str,
         number_of_sets: u32,
     ) -> Result<Vec<vk::DescriptorSet>> {
         let layouts = (0..number_of_sets).map(|_| layout).collect::<Vec<_>>();
@@ -63,6 +65,9 @@ impl DescriptorPool {
                 .handle
                 .allocate_descriptor_sets(&allocation_info)?
         };
+        for descriptor_set in descriptor_sets.iter() {
+            self.device.name(*descriptor_set, name)?;
+        }
         Ok(descriptor_sets)
     }
 }
@@ -1,5 +1,5 @@
 use crate::core::{DescriptorSetLayout, Device, RenderPass, ShaderSet};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ash::vk;
 use derive_builder::Builder;
 use std::sync::Arc;
@@ -7,7 +7,7 @@ use std::sync::Arc;
 pub struct Pipeline {
     pub handle: vk::Pipeline,
     bindpoint: vk::PipelineBindPoint,
-    device: Arc<Device>,
+    pub(crate) device: Arc<Device>,
 }
 
 impl Pipeline {
@@ -35,7 +35,6 @@ impl Pipeline {
         Ok(pipeline)
     }
 
-    #[allow(dead_code)]
     pub fn new_compute(
         device: Arc<Device>,
         create_info: vk::ComputePipelineCreateInfoBuilder,
@@ -64,6 +63,7 @@ impl Pipeline {
         unsafe {
             device.cmd_bind_pipeline(command_buffer, self.bindpoint, self.handle);
         }
+        self.device.commands.record_pipeline_switch();
     }
 }
 
@@ -107,18 +107,29 @@ pub struct GraphicsPipelineSettings {
     pub descriptor_set_layout: Arc<DescriptorSetLayout>,
     pub shader_set: ShaderSet,
 
+    // Names the pipeline and its layout in frame captures and validation messages
+    #[builder(default)]
+    pub name: String,
+
     #[builder(default)]
     pub blended: bool,
 
     #[builder(default = "vk::BlendFactor::SRC_ALPHA")]
     pub blended_src_color_blend_factor: vk::BlendFactor,
 
+    #[builder(default = "vk::BlendFactor::ONE_MINUS_SRC_ALPHA")]
+    pub blended_dst_color_blend_factor: vk::BlendFactor,
+
     #[builder(default = "true")]
     pub depth_test_enabled: bool,
 
     #[builder(default = "true")]
     pub depth_write_enabled: bool,
 
+    // Pipelines that only write depth or stencil can leave the color attachment untouched
+    #[builder(default = "vk::ColorComponentFlags::all()")]
+    pub color_write_mask: vk::ColorComponentFlags,
+
     #[builder(default)]
     pub stencil_test_enabled: bool,
 
@@ -151,11 +162,30 @@ pub struct GraphicsPipelineSettings {
 
     #[builder(default)]
     pub dynamic_states: Vec<vk::DynamicState>,
+
+    // Values for the shader's specialization constants, with each value's index as its constant_id
+    #[builder(default)]
+    pub specialization_constants: Vec<u32>,
 }
 
 impl GraphicsPipelineSettings {
     pub fn create_pipeline(&self, device: Arc<Device>) -> Result<(Pipeline, PipelineLayout)> {
-        let stages = self.shader_set.stages()?;
+        let specialization_entries = specialization_entries(&self.specialization_constants);
+        let specialization_data = self
+            .specialization_constants
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data)
+            .build();
+        let mut stages = self.shader_set.stages()?;
+        if !self.specialization_constants.is_empty() {
+            stages
+                .iter_mut()
+                .for_each(|stage| stage.p_specialization_info = &specialization_info);
+        }
         let vertex_state_info = self.vertex_input_state();
         let input_assembly_create_info = self.input_assembly_create_info();
         let rasterizer_create_info = self.rasterizer_create_info();
@@ -163,7 +193,11 @@ impl GraphicsPipelineSettings {
         let depth_stencil_info = self.depth_stencil_info();
         let blend_attachment = [self.color_blend_attachment_state().build()];
         let color_blend_state = Self::color_blend_state(&blend_attachment);
-        let pipeline_layout = self.create_pipeline_layout(device.clone());
+        let pipeline_layout = create_pipeline_layout(
+            device.clone(),
+            &self.descriptor_set_layout,
+            self.push_constant_range,
+        )?;
         let viewport_create_info = Self::viewport_create_info();
         let dynamic_state = self.dynamic_state();
         let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
@@ -179,7 +213,8 @@ impl GraphicsPipelineSettings {
             .layout(pipeline_layout.handle)
             .render_pass(self.render_pass.handle)
             .subpass(0);
-        let pipeline = Pipeline::new_graphics(device, pipeline_create_info)?;
+        let pipeline = Pipeline::new_graphics(device.clone(), pipeline_create_info)?;
+        name_pipeline(&device, &self.name, &pipeline, &pipeline_layout)?;
         Ok((pipeline, pipeline_layout))
     }
 
@@ -234,11 +269,12 @@ impl GraphicsPipelineSettings {
     }
 
     fn color_blend_attachment_state(&self) -> vk::PipelineColorBlendAttachmentStateBuilder {
-        if self.blended {
+        let attachment = if self.blended {
             self.blend_attachment_blended()
         } else {
             Self::blend_attachment_opaque()
-        }
+        };
+        attachment.color_write_mask(self.color_write_mask)
     }
 
     fn blend_attachment_opaque<'a>() -> vk::PipelineColorBlendAttachmentStateBuilder<'a> {
@@ -268,7 +304,7 @@ impl GraphicsPipelineSettings {
             )
             .blend_enable(true)
             .src_color_blend_factor(self.blended_src_color_blend_factor)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .dst_color_blend_factor(self.blended_dst_color_blend_factor)
             .color_blend_op(vk::BlendOp::ADD)
             .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
             .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
@@ -285,22 +321,6 @@ impl GraphicsPipelineSettings {
             .blend_constants([0.0, 0.0, 0.0, 0.0])
     }
 
-    fn create_pipeline_layout(&self, device: Arc<Device>) -> PipelineLayout {
-        let descriptor_set_layouts = [self.descriptor_set_layout.handle];
-
-        if let Some(push_constant_range) = self.push_constant_range.as_ref() {
-            let push_constant_ranges = [*push_constant_range];
-            let pipeline_layout_create_info_builder = vk::PipelineLayoutCreateInfo::builder()
-                .push_constant_ranges(&push_constant_ranges)
-                .set_layouts(&descriptor_set_layouts);
-            PipelineLayout::new(device, *pipeline_layout_create_info_builder).unwrap()
-        } else {
-            let pipeline_layout_create_info_builder =
-                vk::PipelineLayoutCreateInfo::builder().set_layouts(&descriptor_set_layouts);
-            PipelineLayout::new(device, *pipeline_layout_create_info_builder).unwrap()
-        }
-    }
-
     fn viewport_create_info<'a>() -> vk::PipelineViewportStateCreateInfoBuilder<'a> {
         vk::PipelineViewportStateCreateInfo::builder()
             .viewport_count(1)
@@ -311,3 +331,101 @@ impl GraphicsPipelineSettings {
         vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&self.dynamic_states)
     }
 }
+
+#[derive(Builder)]
+#[builder(setter(into))]
+pub struct ComputePipelineSettings {
+    pub descriptor_set_layout: Arc<DescriptorSetLayout>,
+    // Only the compute shader of the set is used
+    pub shader_set: ShaderSet,
+
+    #[builder(default)]
+    pub name: String,
+
+    #[builder(default)]
+    pub push_constant_range: Option<vk::PushConstantRange>,
+
+    // Values for the shader's specialization constants, with each value's index as its constant_id
+    #[builder(default)]
+    pub specialization_constants: Vec<u32>,
+}
+
+impl ComputePipelineSettings {
+    pub fn create_pipeline(&self, device: Arc<Device>) -> Result<(Pipeline, PipelineLayout)> {
+        let shader = self
+            .shader_set
+            .compute
+            .as_ref()
+            .context("A compute pipeline requires a compute shader")?;
+
+        let specialization_entries = specialization_entries(&self.specialization_constants);
+        let specialization_data = self
+            .specialization_constants
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data)
+            .build();
+        let mut stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader.module)
+            .name(ShaderSet::entry_point_name()?)
+            .build();
+        if !self.specialization_constants.is_empty() {
+            stage.p_specialization_info = &specialization_info;
+        }
+
+        let pipeline_layout = create_pipeline_layout(
+            device.clone(),
+            &self.descriptor_set_layout,
+            self.push_constant_range,
+        )?;
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage)
+            .layout(pipeline_layout.handle);
+        let pipeline = Pipeline::new_compute(device.clone(), pipeline_create_info)?;
+        name_pipeline(&device, &self.name, &pipeline, &pipeline_layout)?;
+        Ok((pipeline, pipeline_layout))
+    }
+}
+
+fn specialization_entries(specialization_constants: &[u32]) -> Vec<vk::SpecializationMapEntry> {
+    let size = std::mem::size_of::<u32>();
+    (0..specialization_constants.len())
+        .map(|index| {
+            vk::SpecializationMapEntry::builder()
+                .constant_id(index as u32)
+                .offset((index * size) as u32)
+                .size(size)
+                .build()
+        })
+        .collect()
+}
+
+fn name_pipeline(
+    device: &Device,
+    name: &str,
+    pipeline: &Pipeline,
+    pipeline_layout: &PipelineLayout,
+) -> Result<()> {
+    if name.is_empty() {
+        return Ok(());
+    }
+    device.name(pipeline.handle, &format!("{} pipeline", name))?;
+    device.name(pipeline_layout.handle, &format!("{} pipeline layout", name))
+}
+
+fn create_pipeline_layout(
+    device: Arc<Device>,
+    descriptor_set_layout: &DescriptorSetLayout,
+    push_constant_range: Option<vk::PushConstantRange>,
+) -> Result<PipelineLayout> {
+    let descriptor_set_layouts = [descriptor_set_layout.handle];
+    let push_constant_ranges = push_constant_range.into_iter().collect::<Vec<_>>();
+    let create_info = vk::PipelineLayoutCreateInfo::builder()
+        .push_constant_ranges(&push_constant_ranges)
+        .set_layouts(&descriptor_set_layouts);
+    PipelineLayout::new(device, *create_info)
+}
@@ -0,0 +1,189 @@
+use crate::core::{Context, Device};
+use anyhow::{bail, Result};
+use ash::vk;
+use std::sync::Arc;
+
+pub struct QueryPool {
+    pub handle: vk::QueryPool,
+    device: Arc<Device>,
+}
+
+impl QueryPool {
+    pub fn new(device: Arc<Device>, query_type: vk::QueryType, query_count: u32) -> Result<Self> {
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(query_type)
+            .query_count(query_count);
+        let handle = unsafe { device.handle.create_query_pool(&create_info, None) }?;
+        Ok(Self { handle, device })
+    }
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        unsafe { self.device.handle.destroy_query_pool(self.handle, None) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuTiming {
+    pub name: String,
+    pub milliseconds: f64,
+}
+
+// Times named sections of a frame's command buffer with timestamp queries.
+// Each frame in flight gets its own range of queries, which are read back
+// the next time that frame is recorded, after its fence has been waited on.
+pub struct GpuTimer {
+    pool: Option<QueryPool>,
+    // The scopes recorded for each frame in flight
+    frames: Vec<Vec<String>>,
+    frame_index: usize,
+    scope_open: bool,
+    // Nanoseconds per timestamp tick
+    timestamp_period: f64,
+    timings: Vec<GpuTiming>,
+    device: Arc<Device>,
+}
+
+impl GpuTimer {
+    pub const MAX_SCOPES: u32 = 16;
+
+    // Devices without timestamp support get a timer that records nothing
+    pub fn new(context: &Context, frames_in_flight: usize) -> Result<Self> {
+        let limits = context.physical_device_properties().limits;
+        let supported =
+            limits.timestamp_compute_and_graphics == vk::TRUE && limits.timestamp_period > 0.0;
+        let pool = if supported {
+            let query_count = Self::MAX_SCOPES * 2 * frames_in_flight as u32;
+            Some(QueryPool::new(
+                context.device.clone(),
+                vk::QueryType::TIMESTAMP,
+                query_count,
+            )?)
+        } else {
+            None
+        };
+        Ok(Self {
+            pool,
+            frames: vec![Vec::new(); frames_in_flight],
+            frame_index: 0,
+            scope_open: false,
+            timestamp_period: limits.timestamp_period as f64,
+            timings: Vec::new(),
+            device: context.device.clone(),
+        })
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    // The timings of the most recently completed frame
+    pub fn timings(&self) -> &[GpuTiming] {
+        &self.timings
+    }
+
+    // Must be recorded outside of a render pass, before any scopes
+    pub fn begin_frame(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+    ) -> Result<()> {
+        if frame_index >= self.frames.len() {
+            bail!("No gpu timer queries exist for frame index {}", frame_index);
+        }
+        self.frame_index = frame_index;
+        self.scope_open = false;
+
+        let pool = match self.pool.as_ref() {
+            Some(pool) => pool.handle,
+            None => return Ok(()),
+        };
+
+        let first_query = self.first_query();
+        let names = std::mem::take(&mut self.frames[frame_index]);
+        if !names.is_empty() {
+            let mut timestamps = vec![0_u64; names.len() * 2];
+            let result = unsafe {
+                self.device.handle.get_query_pool_results(
+                    pool,
+                    first_query,
+                    timestamps.len() as u32,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+            };
+            // A frame that failed partway through leaves some queries unwritten
+            if result.is_ok() {
+                self.timings = names
+                    .into_iter()
+                    .zip(timestamps.chunks_exact(2))
+                    .map(|(name, range)| GpuTiming {
+                        name,
+                        milliseconds: range[1].saturating_sub(range[0]) as f64
+                            * self.timestamp_period
+                            / 1_000_000.0,
+                    })
+                    .collect();
+            }
+        }
+
+        unsafe {
+            self.device.handle.cmd_reset_query_pool(
+                command_buffer,
+                pool,
+                first_query,
+                Self::MAX_SCOPES * 2,
+            )
+        };
+        Ok(())
+    }
+
+    // Scopes can't be nested. Scopes past the maximum are ignored.
+    pub fn begin_scope(&mut self, command_buffer: vk::CommandBuffer, name: &str) {
+        let pool = match self.pool.as_ref() {
+            Some(pool) => pool.handle,
+            None => return,
+        };
+        let scopes = self.frames[self.frame_index].len() as u32;
+        if self.scope_open || scopes >= Self::MAX_SCOPES {
+            return;
+        }
+        let query = self.first_query() + scopes * 2;
+        unsafe {
+            self.device.handle.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                pool,
+                query,
+            )
+        };
+        self.frames[self.frame_index].push(name.to_string());
+        self.scope_open = true;
+    }
+
+    pub fn end_scope(&mut self, command_buffer: vk::CommandBuffer) {
+        let pool = match self.pool.as_ref() {
+            Some(pool) => pool.handle,
+            None => return,
+        };
+        if !self.scope_open {
+            return;
+        }
+        self.scope_open = false;
+        let scopes = self.frames[self.frame_index].len() as u32;
+        let query = self.first_query() + (scopes - 1) * 2 + 1;
+        unsafe {
+            self.device.handle.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                pool,
+                query,
+            )
+        };
+    }
+
+    fn first_query(&self) -> u32 {
+        self.frame_index as u32 * Self::MAX_SCOPES * 2
+    }
+}
@@ -7,7 +7,10 @@ mod physical_device;
 
 use anyhow::{ensure, Context as AnyhowContext, Result};
 use ash::{
-    extensions::khr::{Surface as AshSurface, Swapchain},
+    extensions::{
+        ext::DebugUtils,
+        khr::{Surface as AshSurface, Swapchain},
+    },
     vk::{self, SurfaceKHR},
 };
 use ash_window::{create_surface, enumerate_required_extensions};
@@ -15,8 +18,10 @@ use gpu_allocator::{
     vulkan::{Allocator, AllocatorCreateDesc},
     AllocatorDebugSettings,
 };
+use log::warn;
 use raw_window_handle::HasRawWindowHandle;
 use std::{
+    ffi::CStr,
     os::raw::c_char,
     sync::{Arc, RwLock},
 };
@@ -35,21 +40,40 @@ pub struct Context {
 }
 
 impl Context {
-    pub fn new(window_handle: &impl HasRawWindowHandle) -> Result<Self> {
-        let instance_extensions = Self::instance_extensions(window_handle)?;
-        let layers = Self::layers()?;
-        let device_extensions = Self::device_extensions();
-        let features = Self::features();
-
+    pub fn new(
+        window_handle: &impl HasRawWindowHandle,
+        mut debug_settings: DebugSettings,
+    ) -> Result<Self> {
         let entry = unsafe { ash::Entry::load()? };
+        if debug_settings.validation && !Self::validation_layer_available(&entry)? {
+            warn!("Vulkan validation was requested, but the validation layer is not installed");
+            debug_settings.validation = false;
+        }
+        let instance_extensions = Self::instance_extensions(window_handle, &debug_settings)?;
+        let layers = Self::layers(&debug_settings)?;
+        let mut device_extensions = Self::device_extensions();
+
         let instance = Instance::new(&entry, &instance_extensions, &layers)?;
         let surface = Surface::new(&entry, &instance.handle, window_handle)?;
         let physical_device = PhysicalDevice::new(&instance.handle, &surface)?;
+        let features = Self::features(&instance.handle, physical_device.handle);
+
+        let (descriptor_indexing, needs_extension) =
+            Self::descriptor_indexing_support(&instance, physical_device.handle)?;
+        if descriptor_indexing && needs_extension {
+            device_extensions.push(vk::ExtDescriptorIndexingFn::name().as_ptr());
+        }
+        let mut descriptor_indexing_features =
+            vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+                .descriptor_binding_sampled_image_update_after_bind(true)
+                .descriptor_binding_partially_bound(true);
 
         let mut queue_indices = vec![
             physical_device.graphics_queue_family_index,
             physical_device.presentation_queue_family_index,
         ];
+        queue_indices.extend(physical_device.transfer_queue_family_index);
+        queue_indices.sort_unstable();
         queue_indices.dedup();
         let queue_create_info_list = queue_indices
             .iter()
@@ -65,13 +89,27 @@ impl Context {
         // has been deprecated as of Vulkan 1.1, but the spec recommends stil
         // passing the layer name pointers here to maintain backwards compatibility
         // with older implementations.
-        let create_info = vk::DeviceCreateInfo::builder()
+        let mut create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(queue_create_info_list.as_slice())
             .enabled_extension_names(&device_extensions)
             .enabled_features(&features)
             .enabled_layer_names(&layers);
+        if descriptor_indexing {
+            create_info = create_info.push_next(&mut descriptor_indexing_features);
+        }
 
-        let device = Device::new(&instance.handle, physical_device.handle, create_info)?;
+        let debug_utils = if debug_settings.object_names {
+            Some(DebugUtils::new(&entry, &instance.handle))
+        } else {
+            None
+        };
+        let device = Device::new(
+            &instance.handle,
+            physical_device.handle,
+            create_info,
+            descriptor_indexing,
+            debug_utils,
+        )?;
         let device = Arc::new(device);
 
         let allocator_create_info = AllocatorCreateDesc {
@@ -89,9 +127,17 @@ impl Context {
             buffer_device_address: false,
         };
         let allocator = Arc::new(RwLock::new(Allocator::new(&allocator_create_info)?));
-
-        let debug = if VulkanDebug::enabled() {
-            Some(VulkanDebug::new(&entry, &instance.handle, device.clone())?)
+        device
+            .memory
+            .set_capacity(Self::device_memory(&instance, physical_device.handle));
+
+        let debug = if debug_settings.enabled() {
+            Some(VulkanDebug::new(
+                &entry,
+                &instance.handle,
+                device.clone(),
+                debug_settings,
+            )?)
         } else {
             None
         };
@@ -107,37 +153,115 @@ impl Context {
         })
     }
 
-    fn instance_extensions(window_handle: &impl HasRawWindowHandle) -> Result<Vec<*const i8>> {
+    // The largest device local heap, which is where textures and geometry live
+    fn device_memory(instance: &Instance, physical_device: vk::PhysicalDevice) -> u64 {
+        let properties = unsafe {
+            instance
+                .handle
+                .get_physical_device_memory_properties(physical_device)
+        };
+        properties.memory_heaps[..properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn instance_extensions(
+        window_handle: &impl HasRawWindowHandle,
+        debug_settings: &DebugSettings,
+    ) -> Result<Vec<*const i8>> {
         let mut extensions: Vec<*const i8> = enumerate_required_extensions(window_handle)?
             .iter()
             .map(|extension| extension.as_ptr())
             .collect();
-        if VulkanDebug::enabled() {
+        if debug_settings.enabled() {
             extensions.push(VulkanDebug::extension_name().as_ptr());
         }
         Ok(extensions)
     }
 
-    fn layers() -> Result<Vec<*const i8>> {
+    fn layers(debug_settings: &DebugSettings) -> Result<Vec<*const i8>> {
         let mut layers = Vec::new();
-        if VulkanDebug::enabled() {
+        if debug_settings.validation {
             layers.push(VulkanDebug::layer_name()?.as_ptr());
         }
         Ok(layers)
     }
 
+    fn validation_layer_available(entry: &ash::Entry) -> Result<bool> {
+        let layer_name = VulkanDebug::layer_name()?;
+        let available = entry.enumerate_instance_layer_properties()?;
+        Ok(available.iter().any(|layer| {
+            let name = unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) };
+            name == layer_name
+        }))
+    }
+
     fn device_extensions() -> Vec<*const c_char> {
         vec![Swapchain::name().as_ptr()]
     }
 
-    fn features<'a>() -> vk::PhysicalDeviceFeaturesBuilder<'a> {
+    fn features<'a>(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceFeaturesBuilder<'a> {
+        // Anisotropic filtering and block compressed textures are optional and are only enabled where available
+        let supported = unsafe { instance.get_physical_device_features(physical_device) };
         vk::PhysicalDeviceFeatures::builder()
             .sample_rate_shading(true)
-            .sampler_anisotropy(true)
+            .sampler_anisotropy(supported.sampler_anisotropy == vk::TRUE)
+            .texture_compression_bc(supported.texture_compression_bc == vk::TRUE)
             .fill_mode_non_solid(true)
             .wide_lines(true)
     }
 
+    // Returns whether descriptor indexing can be used, and whether its extension has to be enabled for it.
+    // It is core as of vulkan 1.2, and querying its features needs at least vulkan 1.1.
+    fn descriptor_indexing_support(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<(bool, bool)> {
+        let properties = unsafe {
+            instance
+                .handle
+                .get_physical_device_properties(physical_device)
+        };
+        let version = instance.api_version.min(properties.api_version);
+        if version < vk::API_VERSION_1_1 {
+            return Ok((false, false));
+        }
+
+        let needs_extension = version < vk::API_VERSION_1_2;
+        if needs_extension {
+            let extensions = unsafe {
+                instance
+                    .handle
+                    .enumerate_device_extension_properties(physical_device)
+            }?;
+            let extension_available = extensions.iter().any(|extension| {
+                let name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+                name == vk::ExtDescriptorIndexingFn::name()
+            });
+            if !extension_available {
+                return Ok((false, false));
+            }
+        }
+
+        let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut indexing_features);
+        unsafe {
+            instance
+                .handle
+                .get_physical_device_features2(physical_device, &mut features)
+        };
+        let supported = indexing_features.descriptor_binding_sampled_image_update_after_bind
+            == vk::TRUE
+            && indexing_features.descriptor_binding_partially_bound == vk::TRUE;
+        Ok((supported, needs_extension))
+    }
+
     pub fn debug(&self) -> Result<&VulkanDebug> {
         self.debug
             .as_ref()
@@ -150,6 +274,23 @@ impl Context {
         )
     }
 
+    // Surfaces for additional windows, which are presented to from the same queue as the first
+    pub fn create_surface(&self, window_handle: &impl HasRawWindowHandle) -> Result<Surface> {
+        let surface = Surface::new(&self.entry, &self.instance.handle, window_handle)?;
+        let supported = unsafe {
+            surface.handle_ash.get_physical_device_surface_support(
+                self.physical_device.handle,
+                self.physical_device.presentation_queue_family_index,
+                surface.handle_khr,
+            )
+        }?;
+        ensure!(
+            supported,
+            "The presentation queue can't present to the window's surface!"
+        );
+        Ok(surface)
+    }
+
     pub fn physical_device_surface_capabilities(&self) -> Result<vk::SurfaceCapabilitiesKHR> {
         let surface = self.surface()?;
         let capabilities = unsafe {
@@ -209,6 +350,18 @@ impl Context {
         Ok(())
     }
 
+    pub fn ensure_sampling_supported(&self, format: vk::Format) -> Result<()> {
+        let properties = self.physical_device_format_properties(format);
+        ensure!(
+            properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE),
+            "Sampling is not supported for format: {:?}",
+            format
+        );
+        Ok(())
+    }
+
     pub fn graphics_queue(&self) -> vk::Queue {
         let index = self.physical_device.graphics_queue_family_index;
         unsafe { self.device.handle.get_device_queue(index, 0) }
@@ -219,6 +372,13 @@ impl Context {
         unsafe { self.device.handle.get_device_queue(index, 0) }
     }
 
+    // Only available when the device has a queue family dedicated to transfers
+    pub fn transfer_queue(&self) -> Option<vk::Queue> {
+        self.physical_device
+            .transfer_queue_family_index
+            .map(|index| unsafe { self.device.handle.get_device_queue(index, 0) })
+    }
+
     pub fn physical_device_properties(&self) -> vk::PhysicalDeviceProperties {
         unsafe {
             self.instance
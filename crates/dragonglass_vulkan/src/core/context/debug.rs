@@ -11,15 +11,45 @@ use std::{
     sync::Arc,
 };
 
+// Which debugging aids the context is created with. Both need the debug utils extension,
+// and validation also needs the Khronos validation layer to be installed.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DebugSettings {
+    // Reports invalid api usage through the log
+    pub validation: bool,
+    // Names objects after what they hold and labels render passes, for frame captures
+    pub object_names: bool,
+}
+
+impl DebugSettings {
+    pub fn enabled(&self) -> bool {
+        self.validation || self.object_names
+    }
+}
+
 pub struct VulkanDebug {
     pub debug: DebugUtils,
-    messenger: DebugUtilsMessengerEXT,
+    messenger: Option<DebugUtilsMessengerEXT>,
+    settings: DebugSettings,
     device: Arc<Device>,
 }
 
 impl VulkanDebug {
-    pub fn new(entry: &Entry, instance: &Instance, device: Arc<Device>) -> Result<Self> {
+    pub fn new(
+        entry: &Entry,
+        instance: &Instance,
+        device: Arc<Device>,
+        settings: DebugSettings,
+    ) -> Result<Self> {
         let debug = DebugUtils::new(entry, instance);
+        if !settings.validation {
+            return Ok(Self {
+                debug,
+                messenger: None,
+                settings,
+                device,
+            });
+        }
 
         let create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
             .message_severity(
@@ -38,13 +68,14 @@ impl VulkanDebug {
 
         Ok(Self {
             debug,
-            messenger,
+            messenger: Some(messenger),
+            settings,
             device,
         })
     }
 
-    pub const fn enabled() -> bool {
-        false
+    pub fn settings(&self) -> DebugSettings {
+        self.settings
     }
 
     pub fn layer_name() -> Result<&'static CStr> {
@@ -79,26 +110,18 @@ impl VulkanDebug {
         self.name_object(name, handle, vk::ObjectType::FENCE)
     }
 
+    // Does nothing unless object names were asked for
     pub fn name_object(&self, name: &str, handle: u64, object_type: vk::ObjectType) -> Result<()> {
-        let object_name = format!("{}\0", name);
-        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
-            .object_type(object_type)
-            .object_name(CStr::from_bytes_with_nul(object_name.as_bytes())?)
-            .object_handle(handle)
-            .build();
-        unsafe {
-            self.debug
-                .debug_utils_set_object_name(self.device.handle.handle(), &name_info)?;
-        }
-        Ok(())
+        self.device.name_raw(name, handle, object_type)
     }
 }
 
 impl Drop for VulkanDebug {
     fn drop(&mut self) {
-        unsafe {
-            self.debug
-                .destroy_debug_utils_messenger(self.messenger, None);
+        if let Some(messenger) = self.messenger.take() {
+            unsafe {
+                self.debug.destroy_debug_utils_messenger(messenger, None);
+            }
         }
     }
 }
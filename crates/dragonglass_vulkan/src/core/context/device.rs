@@ -1,8 +1,24 @@
+use crate::core::{CommandCounters, MemoryTracker, SamplerSettings};
 use anyhow::Result;
-use ash::vk;
+use ash::{
+    extensions::ext::DebugUtils,
+    vk::{self, Handle},
+};
+use std::{ffi::CString, sync::RwLock};
 
 pub struct Device {
     pub handle: ash::Device,
+    // Zero if the device does not support anisotropic filtering
+    pub max_sampler_anisotropy: f32,
+    // How many textures a descriptor set that is updated after being bound can sample.
+    // Zero if descriptor indexing is not enabled on the device.
+    pub max_update_after_bind_textures: u32,
+    // The commands recorded for the current frame and what the device's memory is used for
+    pub commands: CommandCounters,
+    pub memory: MemoryTracker,
+    sampler_settings: RwLock<SamplerSettings>,
+    // Only loaded when object names were asked for, so naming and labels are free otherwise
+    debug_utils: Option<DebugUtils>,
 }
 
 impl Device {
@@ -10,9 +26,100 @@ impl Device {
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
         create_info: vk::DeviceCreateInfoBuilder,
+        descriptor_indexing: bool,
+        debug_utils: Option<DebugUtils>,
     ) -> Result<Self> {
         let handle = unsafe { instance.create_device(physical_device, &create_info, None) }?;
-        Ok(Self { handle })
+        let features = unsafe { instance.get_physical_device_features(physical_device) };
+        let max_sampler_anisotropy = if features.sampler_anisotropy == vk::TRUE {
+            let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+            properties.limits.max_sampler_anisotropy
+        } else {
+            0.0
+        };
+        let max_update_after_bind_textures = if descriptor_indexing {
+            let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+            let mut properties =
+                vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_properties);
+            unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+            // Combined image samplers count against both the sampler and sampled image limits
+            indexing_properties
+                .max_descriptor_set_update_after_bind_samplers
+                .min(indexing_properties.max_descriptor_set_update_after_bind_sampled_images)
+                .min(indexing_properties.max_per_stage_descriptor_update_after_bind_samplers)
+                .min(indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images)
+        } else {
+            0
+        };
+        Ok(Self {
+            handle,
+            max_sampler_anisotropy,
+            max_update_after_bind_textures,
+            commands: CommandCounters::default(),
+            memory: MemoryTracker::default(),
+            sampler_settings: RwLock::new(SamplerSettings::default()),
+            debug_utils,
+        })
+    }
+
+    pub fn sampler_anisotropy_supported(&self) -> bool {
+        self.max_sampler_anisotropy > 0.0
+    }
+
+    pub fn descriptor_indexing_supported(&self) -> bool {
+        self.max_update_after_bind_textures > 0
+    }
+
+    pub fn sampler_settings(&self) -> SamplerSettings {
+        self.sampler_settings
+            .read()
+            .map(|settings| *settings)
+            .unwrap_or_default()
+    }
+
+    // Only affects samplers created after the settings change
+    pub fn set_sampler_settings(&self, settings: SamplerSettings) {
+        if let Ok(mut sampler_settings) = self.sampler_settings.write() {
+            *sampler_settings = settings;
+        }
+    }
+
+    // Names an object in frame captures and validation messages
+    pub fn name<T: Handle>(&self, handle: T, name: &str) -> Result<()> {
+        self.name_raw(name, handle.as_raw(), T::TYPE)
+    }
+
+    pub fn name_raw(&self, name: &str, handle: u64, object_type: vk::ObjectType) -> Result<()> {
+        let debug_utils = match self.debug_utils.as_ref() {
+            Some(debug_utils) => debug_utils,
+            None => return Ok(()),
+        };
+        let object_name = CString::new(name)?;
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(object_type)
+            .object_name(&object_name)
+            .object_handle(handle);
+        unsafe { debug_utils.debug_utils_set_object_name(self.handle.handle(), &name_info) }?;
+        Ok(())
+    }
+
+    // Groups the commands recorded by the action under a label, so captures show which pass they belong to
+    pub fn record_label(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+        action: impl FnOnce(vk::CommandBuffer) -> Result<()>,
+    ) -> Result<()> {
+        let debug_utils = match self.debug_utils.as_ref() {
+            Some(debug_utils) => debug_utils,
+            None => return action(command_buffer),
+        };
+        let label_name = CString::new(name)?;
+        let label = vk::DebugUtilsLabelEXT::builder().label_name(&label_name);
+        unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+        let result = action(command_buffer);
+        unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        result
     }
 
     pub fn record_command_buffer(
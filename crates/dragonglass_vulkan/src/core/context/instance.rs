@@ -5,6 +5,8 @@ use std::ffi::{CStr, CString};
 
 pub struct Instance {
     pub handle: ash::Instance,
+    // The highest vulkan version the loader supports, which the instance was created with
+    pub api_version: u32,
 }
 
 impl Instance {
@@ -15,7 +17,8 @@ impl Instance {
     const ENGINE_NAME: &'static str = "Dragonglass Engine";
 
     pub fn new(entry: &ash::Entry, extensions: &[*const i8], layers: &[*const i8]) -> Result<Self> {
-        let application_create_info = Self::application_create_info(entry)?;
+        let api_version = Self::api_version(entry)?;
+        let application_create_info = Self::application_create_info(api_version)?;
         Self::check_layers_supported(entry, layers)?;
 
         let instance_create_info = vk::InstanceCreateInfo::builder()
@@ -24,10 +27,13 @@ impl Instance {
             .enabled_layer_names(layers);
 
         let handle = unsafe { entry.create_instance(&instance_create_info, None) }?;
-        Ok(Self { handle })
+        Ok(Self {
+            handle,
+            api_version,
+        })
     }
 
-    fn application_create_info(entry: &ash::Entry) -> Result<vk::ApplicationInfo> {
+    fn api_version(entry: &ash::Entry) -> Result<u32> {
         let (major, minor) = match entry.try_enumerate_instance_version()? {
             Some(version) => (
                 vk::api_version_major(version),
@@ -35,14 +41,17 @@ impl Instance {
             ),
             None => (1, 0),
         };
-        log::info!("Vulkan {major}.{minor} supported");
+        info!("Vulkan {}.{} supported", major, minor);
+        Ok(vk::make_api_version(0, major, minor, 0))
+    }
 
+    fn application_create_info(api_version: u32) -> Result<vk::ApplicationInfo> {
         let app_name = CString::new(Self::APPLICATION_NAME)?;
         let engine_name = CString::new(Self::ENGINE_NAME)?;
         let app_info = vk::ApplicationInfo::builder()
             .application_name(&app_name)
             .engine_name(&engine_name)
-            .api_version(vk::make_api_version(0, major, minor, 0))
+            .api_version(api_version)
             .application_version(Self::APPLICATION_VERSION)
             .engine_version(Self::ENGINE_VERSION)
             .build();
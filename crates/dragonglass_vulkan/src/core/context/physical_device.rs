@@ -8,6 +8,8 @@ pub struct PhysicalDevice {
     pub handle: vk::PhysicalDevice,
     pub graphics_queue_family_index: u32,
     pub presentation_queue_family_index: u32,
+    // A queue family that only does transfers, which uploads can run on alongside rendering
+    pub transfer_queue_family_index: Option<u32>,
 }
 
 impl PhysicalDevice {
@@ -49,12 +51,17 @@ impl PhysicalDevice {
         }
 
         let (graphics_queue_family_index, presentation_queue_family_index) = queue_indices.unwrap();
+        let transfer_queue_family_index = Self::find_transfer_queue_family_index(instance, device);
 
         info!("Selected physical device: {:?}", device_name);
+        if let Some(index) = transfer_queue_family_index {
+            info!("Using queue family {} for uploads", index);
+        }
         let physical_device = Self {
             handle: device,
             graphics_queue_family_index,
             presentation_queue_family_index,
+            transfer_queue_family_index,
         };
 
         Ok(Some(physical_device))
@@ -146,14 +153,32 @@ impl PhysicalDevice {
         Ok((graphics_queue, presentation_queue))
     }
 
+    // Prefers a family that can't run compute work either,
+    // since those are usually backed by the gpu's dedicated copy engines
+    fn find_transfer_queue_family_index(
+        instance: &ash::Instance,
+        device: vk::PhysicalDevice,
+    ) -> Option<u32> {
+        let queue_family_properties =
+            unsafe { instance.get_physical_device_queue_family_properties(device) };
+        let transfer_only = |family: &vk::QueueFamilyProperties, excluded: vk::QueueFlags| {
+            family.queue_count > 0
+                && family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !family.queue_flags.intersects(excluded)
+        };
+        let find = |excluded: vk::QueueFlags| {
+            queue_family_properties
+                .iter()
+                .position(|family| transfer_only(family, excluded))
+                .map(|index| index as u32)
+        };
+        find(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            .or_else(|| find(vk::QueueFlags::GRAPHICS))
+    }
+
     fn features_supported(instance: &ash::Instance, device: vk::PhysicalDevice) -> bool {
         let features = unsafe { instance.get_physical_device_features(device) };
-        let required_features = [
-            features.sampler_anisotropy,
-            features.wide_lines,
-            features.fill_mode_non_solid,
-            features.wide_lines,
-        ];
+        let required_features = [features.wide_lines, features.fill_mode_non_solid];
         required_features.iter().all(|feature| *feature == vk::TRUE)
     }
 
@@ -1,6 +1,6 @@
 use crate::core::{
-    create_swapchain, CommandPool, Context, Device, Fence, Semaphore, Swapchain,
-    SwapchainProperties,
+    create_swapchain, create_swapchain_for_surface, CommandPool, Context, Device, Fence, Semaphore,
+    Surface, Swapchain, SwapchainProperties,
 };
 use anyhow::{bail, Context as AnyhowContext, Result};
 use ash::{
@@ -14,16 +14,43 @@ pub struct Frame {
     index: usize,
     locks: Vec<FrameLock>,
     command_buffers: Vec<vk::CommandBuffer>,
-    _command_pool: CommandPool,
+    command_pool: CommandPool,
     frames_in_flight: usize,
     swapchain: Option<Swapchain>,
     pub swapchain_properties: SwapchainProperties,
     pub recreated_swapchain: bool,
+    vsync: bool,
+    // Set when a setting baked into the swapchain changed, so it is rebuilt before the next frame
+    swapchain_outdated: bool,
+    // The viewport the swapchain was last created for
+    swapchain_viewport: Viewport,
+    // Owned by frames presenting to a window other than the context's,
+    // and declared after the swapchain so it is destroyed after it
+    surface: Option<Surface>,
     context: Arc<Context>,
 }
 
 impl Frame {
     pub fn new(context: Arc<Context>, viewport: Viewport, frames_in_flight: usize) -> Result<Self> {
+        Self::create(context, None, viewport, frames_in_flight)
+    }
+
+    // Presents to another window, sharing the context's device and resources
+    pub fn with_surface(
+        context: Arc<Context>,
+        surface: Surface,
+        viewport: Viewport,
+        frames_in_flight: usize,
+    ) -> Result<Self> {
+        Self::create(context, Some(surface), viewport, frames_in_flight)
+    }
+
+    fn create(
+        context: Arc<Context>,
+        surface: Option<Surface>,
+        viewport: Viewport,
+        frames_in_flight: usize,
+    ) -> Result<Self> {
         let frame_locks = (0..frames_in_flight)
             .map(|index| {
                 let frame_lock = FrameLock::new(context.device.clone())?;
@@ -36,12 +63,17 @@ impl Frame {
         let command_pool = CommandPool::new(
             context.device.clone(),
             context.graphics_queue(),
+            "frame",
             vk::CommandPoolCreateInfo::builder()
                 .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
                 .queue_family_index(graphics_queue_index),
         )?;
 
-        let (swapchain, properties) = create_swapchain(&context, viewport)?;
+        let vsync = true;
+        let (swapchain, properties) = match surface.as_ref() {
+            Some(surface) => create_swapchain_for_surface(&context, surface, viewport, vsync)?,
+            None => create_swapchain(&context, viewport, vsync)?,
+        };
         let number_of_framebuffers = swapchain.images()?.len() as _;
         let command_buffers = command_pool
             .allocate_command_buffers(number_of_framebuffers, vk::CommandBufferLevel::PRIMARY)?;
@@ -50,11 +82,15 @@ impl Frame {
             index: 0,
             locks: frame_locks,
             command_buffers,
-            _command_pool: command_pool,
+            command_pool,
             frames_in_flight,
             swapchain: Some(swapchain),
             recreated_swapchain: false,
+            vsync,
+            swapchain_outdated: false,
+            swapchain_viewport: viewport,
             swapchain_properties: properties,
+            surface,
             context,
         })
     }
@@ -63,13 +99,51 @@ impl Frame {
         self.swapchain.as_ref().context("Failed to get swapchain!")
     }
 
+    // The frame in flight that the next render records into
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    pub fn set_vsync(&mut self, vsync: bool) {
+        if self.vsync != vsync {
+            self.vsync = vsync;
+            self.swapchain_outdated = true;
+        }
+    }
+
+    // Destroys the swapchain, so another can be created for the window
+    // when recovering from a lost device
+    pub fn release_swapchain(&mut self) {
+        self.swapchain = None;
+    }
+
     pub fn render(
         &mut self,
         viewport: Viewport,
         mut action: impl FnMut(vk::CommandBuffer, usize) -> Result<()>,
     ) -> Result<()> {
         self.recreated_swapchain = false;
-        self.wait_for_in_flight_fence()?;
+
+        // Minimized windows have nothing to present to
+        if viewport.width < 1.0 || viewport.height < 1.0 {
+            return Ok(());
+        }
+
+        // Not every platform reports resizes through out of date swapchains
+        if viewport.width != self.swapchain_viewport.width
+            || viewport.height != self.swapchain_viewport.height
+        {
+            self.swapchain_outdated = true;
+        }
+
+        {
+            dragonglass_profiling::profile_scope!("wait for gpu");
+            self.wait_for_in_flight_fence()?;
+        }
         if let Some(image_index) = self.acquire_next_frame(viewport)? {
             self.reset_in_flight_fence()?;
             self.context.device.record_command_buffer(
@@ -77,11 +151,19 @@ impl Frame {
                 vk::CommandBufferUsageFlags::empty(),
                 |command_buffer| action(command_buffer, image_index),
             )?;
+            dragonglass_profiling::profile_scope!("present");
             self.submit_command_buffer(image_index)?;
             let result = self.present_next_frame(image_index)?;
             self.check_presentation_result(result, viewport)?;
             self.increment_frame_counter();
         }
+
+        // Recreated after presenting, like an out of date swapchain,
+        // so the renderer can rebuild what depends on it before the next frame
+        if self.swapchain_outdated && !self.recreated_swapchain {
+            self.create_swapchain(viewport)?;
+        }
+
         Ok(())
     }
 
@@ -112,7 +194,13 @@ impl Frame {
             .acquire_next_image(self.frame_lock()?.image_available.handle, vk::Fence::null());
 
         match result {
-            Ok((image_index, _)) => Ok(Some(image_index as usize)),
+            Ok((image_index, is_suboptimal)) => {
+                // A suboptimal swapchain can still be presented to, so it is rebuilt after this frame
+                if is_suboptimal {
+                    self.swapchain_outdated = true;
+                }
+                Ok(Some(image_index as usize))
+            }
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                 self.create_swapchain(viewport)?;
                 Ok(None)
@@ -159,18 +247,35 @@ impl Frame {
     }
 
     fn create_swapchain(&mut self, viewport: Viewport) -> Result<()> {
-        if viewport.width == 0.0 || viewport.height == 0.0 {
+        if viewport.width < 1.0 || viewport.height < 1.0 {
             return Ok(());
         }
 
         unsafe { self.context.device.handle.device_wait_idle() }?;
 
         self.swapchain = None;
-        let (swapchain, properties) = create_swapchain(&self.context, viewport)?;
+        let (swapchain, properties) = match self.surface.as_ref() {
+            Some(surface) => {
+                create_swapchain_for_surface(&self.context, surface, viewport, self.vsync)?
+            }
+            None => create_swapchain(&self.context, viewport, self.vsync)?,
+        };
+        let number_of_images = swapchain.images()?.len();
         self.swapchain = Some(swapchain);
         self.swapchain_properties = properties;
+        self.swapchain_viewport = viewport;
+
+        // The new swapchain can have a different number of images
+        if number_of_images != self.command_buffers.len() {
+            self.command_pool
+                .free_command_buffers(&self.command_buffers);
+            self.command_buffers = self
+                .command_pool
+                .allocate_command_buffers(number_of_images as _, vk::CommandBufferLevel::PRIMARY)?;
+        }
 
         self.recreated_swapchain = true;
+        self.swapchain_outdated = false;
 
         Ok(())
     }
@@ -0,0 +1,114 @@
+use crate::core::{Device, MemoryCategory, MemoryStats};
+use anyhow::Result;
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, Allocator};
+use log::{error, warn};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    RwLock,
+};
+
+// Allocations are warned about past this fraction of the device's memory,
+// which leaves room to free some before they start failing
+const WARNING_THRESHOLD: f64 = 0.9;
+
+// What a device's memory is used for. Each device has its own,
+// so renderers don't count each other's allocations.
+#[derive(Default, Debug)]
+pub struct MemoryTracker {
+    // Bytes currently allocated through gpu-allocator, indexed by MemoryCategory
+    bytes: [AtomicU64; MemoryCategory::ALL.len()],
+    // The size of the largest device local heap, set when the context is created
+    capacity: AtomicU64,
+    // Set once the low memory warning has been logged, until usage drops again
+    warned: AtomicBool,
+}
+
+impl MemoryTracker {
+    pub fn stats(&self) -> MemoryStats {
+        let load = |category: MemoryCategory| self.bytes[category as usize].load(Ordering::Relaxed);
+        MemoryStats {
+            textures: load(MemoryCategory::Textures),
+            render_targets: load(MemoryCategory::RenderTargets),
+            geometry: load(MemoryCategory::Geometry),
+            uniforms: load(MemoryCategory::Uniforms),
+            other: load(MemoryCategory::Other),
+        }
+    }
+
+    // The size of the largest device local heap in bytes, or zero before the context has set it
+    pub fn capacity(&self) -> u64 {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_capacity(&self, bytes: u64) {
+        self.capacity.store(bytes, Ordering::Relaxed);
+        self.warned.store(false, Ordering::Relaxed);
+    }
+
+    fn allocated(&self, category: MemoryCategory, bytes: u64) {
+        self.bytes[category as usize].fetch_add(bytes, Ordering::Relaxed);
+        self.check_usage();
+    }
+
+    fn freed(&self, category: MemoryCategory, bytes: u64) {
+        self.bytes[category as usize].fetch_sub(bytes, Ordering::Relaxed);
+        self.check_usage();
+    }
+
+    fn check_usage(&self) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+        let used = self.stats().total_bytes();
+        let running_low = used as f64 > capacity as f64 * WARNING_THRESHOLD;
+        if running_low && !self.warned.swap(true, Ordering::Relaxed) {
+            warn!(
+                "{} MiB of the gpu's {} MiB are in use, allocations may start failing",
+                used / (1024 * 1024),
+                capacity / (1024 * 1024)
+            );
+        } else if !running_low {
+            self.warned.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+// Every buffer and image is allocated through here,
+// so what the gpu's memory is used for is tracked by category
+pub(crate) fn allocate(
+    device: &Device,
+    allocator: &RwLock<Allocator>,
+    description: &AllocationCreateDesc,
+    category: MemoryCategory,
+) -> Result<Allocation> {
+    let allocation = {
+        let mut allocator = allocator.write().expect("Failed to acquire allocator!");
+        allocator.allocate(description)
+    };
+    let allocation = match allocation {
+        Ok(allocation) => allocation,
+        Err(error) => {
+            error!(
+                "Failed to allocate '{}' with {} MiB of gpu memory in use",
+                description.name,
+                device.memory.stats().total_bytes() / (1024 * 1024)
+            );
+            return Err(error.into());
+        }
+    };
+    device.memory.allocated(category, allocation.size());
+    Ok(allocation)
+}
+
+pub(crate) fn free(
+    device: &Device,
+    allocator: &RwLock<Allocator>,
+    allocation: Allocation,
+    category: MemoryCategory,
+) -> Result<()> {
+    device.memory.freed(category, allocation.size());
+    let mut allocator = allocator.write().expect("Failed to acquire allocator!");
+    allocator.free(allocation)?;
+    Ok(())
+}
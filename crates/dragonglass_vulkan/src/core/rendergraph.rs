@@ -107,9 +107,11 @@ impl RenderGraph {
         for index in self.graph.node_indices() {
             if let Node::Pass(pass_node) = &self.graph[index] {
                 let pass = self.create_pass(index, device.clone())?;
+                device.name(pass.render_pass.handle, &pass_node.name)?;
                 if !pass.presents_to_backbuffer {
                     let attachments = self.framebuffer_attachments(index)?;
                     let framebuffer = pass.create_framebuffer(device.clone(), &attachments)?;
+                    device.name(framebuffer.handle, &pass_node.name)?;
                     self.framebuffers
                         .insert(pass_node.name.to_string(), framebuffer);
                 }
@@ -197,9 +199,12 @@ impl RenderGraph {
         } else {
             self.framebuffer(name)
         }?;
-        pass.execute(command_buffer, framebuffer.handle, |command_buffer| {
-            action(pass, command_buffer)
-        })
+        pass.device
+            .record_label(command_buffer, name, |command_buffer| {
+                pass.execute(command_buffer, framebuffer.handle, |command_buffer| {
+                    action(pass, command_buffer)
+                })
+            })
     }
 
     pub fn pass(&self, name: &str) -> Result<&Pass> {
@@ -311,7 +316,8 @@ impl RenderGraph {
         }
 
         let allocated_image = image_node.allocate_image(device.clone(), allocator)?;
-        let image_view = image_node.create_image_view(device, allocated_image.handle())?;
+        let image_view = image_node.create_image_view(device.clone(), allocated_image.handle())?;
+        device.name(image_view.handle, &format!("{} view", image_node.name))?;
 
         Ok(Some((allocated_image, image_view)))
     }
@@ -425,12 +431,19 @@ impl ImageNode {
             final_layout = vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL;
         }
 
+        // The stencil is cleared along with depth so passes can mask what they draw with it
+        let stencil_load_op = if self.is_depth_stencil() {
+            load_op
+        } else {
+            vk::AttachmentLoadOp::DONT_CARE
+        };
+
         let attachment_description = vk::AttachmentDescription::builder()
             .format(self.format)
             .samples(self.samples)
             .load_op(load_op)
             .store_op(store_op)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_load_op(stencil_load_op)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .final_layout(final_layout)
@@ -470,7 +483,7 @@ impl ImageNode {
             .samples(self.samples)
             .flags(vk::ImageCreateFlags::empty());
 
-        AllocatedImage::new(device, allocator, &create_info)
+        AllocatedImage::new(device, allocator, &self.name, &create_info)
     }
 
     fn usage(&self) -> vk::ImageUsageFlags {
@@ -524,6 +537,7 @@ pub struct Pass {
     pub presents_to_backbuffer: bool,
     pub extent: vk::Extent2D,
     pub clear_values: Vec<vk::ClearValue>,
+    device: Arc<Device>,
 }
 
 impl Pass {
@@ -634,7 +648,7 @@ impl PassBuilder {
             .attachments(&self.attachment_descriptions)
             .subpasses(&subpass_descriptions);
 
-        let render_pass = Arc::new(RenderPass::new(device, &create_info)?);
+        let render_pass = Arc::new(RenderPass::new(device.clone(), &create_info)?);
 
         let extent = self.minimum_extent();
         let Self { clear_values, .. } = self;
@@ -644,6 +658,7 @@ impl PassBuilder {
             presents_to_backbuffer: self.presents_to_backbuffer,
             extent,
             clear_values,
+            device,
         })
     }
 
@@ -674,7 +689,7 @@ fn create_default_sampler(device: Arc<Device>) -> Result<Sampler> {
         .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
         .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
         .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
-        .anisotropy_enable(true)
+        .anisotropy_enable(false)
         .max_anisotropy(1.0)
         .border_color(vk::BorderColor::INT_OPAQUE_WHITE)
         .unnormalized_coordinates(false)
@@ -1,4 +1,7 @@
-use crate::core::{BufferToBufferCopyBuilder, CommandPool, Device};
+use crate::core::{
+    memory, BufferToBufferCopy, BufferToBufferCopyBuilder, CommandPool, Device, MemoryCategory,
+    PipelineBarrier, QueueFamilies, UploadId, UploadQueue,
+};
 use anyhow::{Context, Result};
 use ash::vk;
 use gpu_allocator::{
@@ -21,6 +24,7 @@ impl GpuBuffer {
     pub fn new(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> Result<Self> {
@@ -31,6 +35,7 @@ impl GpuBuffer {
         let buffer = Buffer::new(
             device.clone(),
             allocator.clone(),
+            name,
             buffer_create_info,
             MemoryLocation::GpuOnly,
         )?;
@@ -51,42 +56,165 @@ impl GpuBuffer {
         offset: usize,
         pool: &CommandPool,
     ) -> Result<()> {
-        let size = data.len() * std::mem::size_of::<T>();
+        let staging_buffer = self.staging_buffer(data)?;
+        let info = self.upload_copy(&staging_buffer, offset, std::mem::size_of_val(data))?;
+        pool.copy_buffer_to_buffer(&info)?;
+        Ok(())
+    }
+
+    // Copies the data on the upload queue's transfer queue.
+    // The buffer must not be dropped until the upload is done.
+    pub fn upload_data_async<T: Copy>(
+        &self,
+        data: &[T],
+        offset: usize,
+        uploads: &mut UploadQueue,
+    ) -> Result<UploadId> {
+        let size = std::mem::size_of_val(data);
+        let staging_buffer = self.staging_buffer(data)?;
+        let info = self.upload_copy(&staging_buffer, offset, size)?;
+        let range = (offset as vk::DeviceSize, size as vk::DeviceSize);
+        let families = uploads.queue_families();
+        let device = self.device.handle.clone();
+        uploads.submit(
+            staging_buffer,
+            |command_buffer| {
+                info.record(&device, command_buffer);
+                if let Some(families) = families {
+                    self.ownership_transfer(range, families, true)
+                        .record(&device, command_buffer);
+                }
+                Ok(())
+            },
+            |command_buffer| {
+                let barrier = match families {
+                    Some(families) => self.ownership_transfer(range, families, false),
+                    None => self.upload_barrier(range),
+                };
+                barrier.record(&device, command_buffer);
+                Ok(())
+            },
+        )
+    }
 
+    fn staging_buffer<T: Copy>(&self, data: &[T]) -> Result<CpuToGpuBuffer> {
+        let size = std::mem::size_of_val(data);
         let staging_buffer =
             CpuToGpuBuffer::staging_buffer(self.device.clone(), self.allocator.clone(), size as _)?;
         staging_buffer.upload_data(data, 0)?;
+        Ok(staging_buffer)
+    }
 
+    fn upload_copy(
+        &self,
+        staging_buffer: &CpuToGpuBuffer,
+        offset: usize,
+        size: usize,
+    ) -> Result<BufferToBufferCopy> {
         let region = vk::BufferCopy::builder()
             .size(size as _)
             .dst_offset(offset as _)
             .build();
-
         let info = BufferToBufferCopyBuilder::default()
-            .source(staging_buffer.buffer.handle)
+            .source(staging_buffer.handle())
             .destination(self.buffer.handle)
             .regions(vec![region])
             .build()?;
+        Ok(info)
+    }
 
-        pool.copy_buffer_to_buffer(&info)?;
+    // Hands the uploaded range over between the transfer and graphics queue families.
+    // The transfer queue releases it after copying to it and the graphics queue acquires it.
+    fn ownership_transfer(
+        &self,
+        (offset, size): (vk::DeviceSize, vk::DeviceSize),
+        families: QueueFamilies,
+        release: bool,
+    ) -> PipelineBarrier {
+        let (src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask) = if release {
+            (
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            )
+        } else {
+            (
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::MEMORY_READ,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+            )
+        };
+        let barrier = vk::BufferMemoryBarrier::builder()
+            .buffer(self.buffer.handle)
+            .offset(offset)
+            .size(size)
+            .src_queue_family_index(families.transfer)
+            .dst_queue_family_index(families.graphics)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .build();
+        PipelineBarrier {
+            src_stage_mask,
+            dst_stage_mask,
+            dependency_flags: vk::DependencyFlags::empty(),
+            memory_barriers: Vec::new(),
+            buffer_memory_barriers: vec![barrier],
+            image_memory_barriers: Vec::new(),
+        }
+    }
 
-        Ok(())
+    // Makes an upload copied on the graphics queue visible to the commands submitted after it
+    fn upload_barrier(&self, (offset, size): (vk::DeviceSize, vk::DeviceSize)) -> PipelineBarrier {
+        let barrier = vk::BufferMemoryBarrier::builder()
+            .buffer(self.buffer.handle)
+            .offset(offset)
+            .size(size)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ)
+            .build();
+        PipelineBarrier {
+            src_stage_mask: vk::PipelineStageFlags::TRANSFER,
+            dst_stage_mask: vk::PipelineStageFlags::ALL_COMMANDS,
+            dependency_flags: vk::DependencyFlags::empty(),
+            memory_barriers: Vec::new(),
+            buffer_memory_barriers: vec![barrier],
+            image_memory_barriers: Vec::new(),
+        }
     }
 
+    // Vertices can also be read as storage buffers, so compute passes can process them
     pub fn vertex_buffer(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         size: vk::DeviceSize,
     ) -> Result<Self> {
-        Self::new(device, allocator, size, vk::BufferUsageFlags::VERTEX_BUFFER)
+        Self::new(
+            device,
+            allocator,
+            name,
+            size,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+        )
     }
 
     pub fn index_buffer(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         size: vk::DeviceSize,
     ) -> Result<Self> {
-        Self::new(device, allocator, size, vk::BufferUsageFlags::INDEX_BUFFER)
+        Self::new(
+            device,
+            allocator,
+            name,
+            size,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        )
     }
 }
 
@@ -98,6 +226,7 @@ impl CpuToGpuBuffer {
     fn new(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> Result<Self> {
@@ -108,6 +237,7 @@ impl CpuToGpuBuffer {
         let buffer = Buffer::new(
             device,
             allocator,
+            name,
             buffer_create_info,
             MemoryLocation::CpuToGpu,
         )?;
@@ -122,27 +252,67 @@ impl CpuToGpuBuffer {
         self.buffer.handle
     }
 
+    // Staging buffers only live for a single upload, so they all share a name
     pub fn staging_buffer(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
         size: vk::DeviceSize,
     ) -> Result<Self> {
-        Self::new(device, allocator, size, vk::BufferUsageFlags::TRANSFER_SRC)
+        Self::new(
+            device,
+            allocator,
+            "staging buffer",
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+        )
     }
 
     pub fn uniform_buffer(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         size: vk::DeviceSize,
     ) -> Result<Self> {
         Self::new(
             device,
             allocator,
+            name,
             size,
             vk::BufferUsageFlags::UNIFORM_BUFFER,
         )
     }
 
+    pub fn storage_buffer(
+        device: Arc<Device>,
+        allocator: Arc<RwLock<Allocator>>,
+        name: &str,
+        size: vk::DeviceSize,
+    ) -> Result<Self> {
+        Self::new(
+            device,
+            allocator,
+            name,
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )
+    }
+
+    // For vertices that are rebuilt every frame
+    pub fn vertex_buffer(
+        device: Arc<Device>,
+        allocator: Arc<RwLock<Allocator>>,
+        name: &str,
+        size: vk::DeviceSize,
+    ) -> Result<Self> {
+        Self::new(
+            device,
+            allocator,
+            name,
+            size,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        )
+    }
+
     pub fn upload_data<T>(&self, data: &[T], offset: usize) -> Result<()> {
         let data_pointer = self.mapped_ptr()?.as_ptr();
         unsafe {
@@ -176,9 +346,53 @@ impl CpuToGpuBuffer {
     }
 }
 
+// Host visible memory that the gpu writes into, used to read images back to the cpu
+pub struct GpuToCpuBuffer {
+    buffer: Buffer,
+}
+
+impl GpuToCpuBuffer {
+    pub fn readback_buffer(
+        device: Arc<Device>,
+        allocator: Arc<RwLock<Allocator>>,
+        name: &str,
+        size: vk::DeviceSize,
+    ) -> Result<Self> {
+        let buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = Buffer::new(
+            device,
+            allocator,
+            name,
+            buffer_create_info,
+            MemoryLocation::GpuToCpu,
+        )?;
+        Ok(Self { buffer })
+    }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.buffer.handle
+    }
+
+    pub fn download_data(&self, size: usize) -> Result<Vec<u8>> {
+        let data_pointer = self
+            .buffer
+            .allocation
+            .mapped_ptr()
+            .context("Failed to get mapped buffer ptr!")?
+            .as_ptr() as *const u8;
+        let size = size.min(self.buffer.allocation.size() as usize);
+        let data = unsafe { std::slice::from_raw_parts(data_pointer, size) }.to_vec();
+        Ok(data)
+    }
+}
+
 pub struct Buffer {
     pub handle: vk::Buffer,
     allocation: Allocation,
+    category: MemoryCategory,
     allocator: Arc<RwLock<Allocator>>,
     device: Arc<Device>,
 }
@@ -187,22 +401,21 @@ impl Buffer {
     pub fn new(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         buffer_create_info: vk::BufferCreateInfoBuilder,
         location: MemoryLocation,
     ) -> Result<Self> {
         let handle = unsafe { device.handle.create_buffer(&buffer_create_info, None) }?;
+        device.name(handle, name)?;
         let requirements = unsafe { device.handle.get_buffer_memory_requirements(handle) };
         let allocation_create_info = AllocationCreateDesc {
-            // TODO: Allow custom naming allocations
-            name: "Buffer Allocation",
+            name,
             requirements,
             location,
             linear: true, // Buffers are always linear
         };
-        let allocation = {
-            let mut allocator = allocator.write().expect("Failed to acquire allocator!");
-            allocator.allocate(&allocation_create_info)?
-        };
+        let category = MemoryCategory::of_buffer(buffer_create_info.usage);
+        let allocation = memory::allocate(&device, &allocator, &allocation_create_info, category)?;
         unsafe {
             device
                 .handle
@@ -211,6 +424,7 @@ impl Buffer {
         Ok(Self {
             handle,
             allocation,
+            category,
             allocator,
             device,
         })
@@ -219,54 +433,139 @@ impl Buffer {
 
 impl Drop for Buffer {
     fn drop(&mut self) {
-        let mut allocator = self
-            .allocator
-            .write()
-            .expect("Failed to acquire allocator!");
-        allocator
-            .free(self.allocation.clone())
-            .expect("Failed to free allocated buffer!");
+        memory::free(
+            &self.device,
+            &self.allocator,
+            self.allocation.clone(),
+            self.category,
+        )
+        .expect("Failed to free allocated buffer!");
         unsafe { self.device.handle.destroy_buffer(self.handle, None) };
     }
 }
 
 pub struct GeometryBuffer {
+    // Its buffers are named after it, and keep the name when they are reallocated
+    pub name: String,
     pub vertex_buffer: GpuBuffer,
     pub vertex_buffer_size: vk::DeviceSize,
+    // Vertex buffers bound after the first one, for vertices with attributes split into separate streams
+    pub streams: Vec<VertexStream>,
     pub index_buffer: Option<GpuBuffer>,
     pub index_buffer_size: Option<vk::DeviceSize>,
 }
 
+pub struct VertexStream {
+    pub buffer: GpuBuffer,
+    pub size: vk::DeviceSize,
+}
+
 impl GeometryBuffer {
     pub fn new(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         vertex_buffer_size: vk::DeviceSize,
         index_buffer_size: Option<vk::DeviceSize>,
     ) -> Result<Self> {
-        let vertex_buffer =
-            GpuBuffer::vertex_buffer(device.clone(), allocator.clone(), vertex_buffer_size)?;
+        let vertex_buffer = GpuBuffer::vertex_buffer(
+            device.clone(),
+            allocator.clone(),
+            &Self::vertex_buffer_name(name),
+            vertex_buffer_size,
+        )?;
         let index_buffer = if let Some(index_buffer_size) = index_buffer_size {
-            let index_buffer = GpuBuffer::index_buffer(device, allocator, index_buffer_size)?;
+            let index_buffer = GpuBuffer::index_buffer(
+                device,
+                allocator,
+                &Self::index_buffer_name(name),
+                index_buffer_size,
+            )?;
             Some(index_buffer)
         } else {
             None
         };
         Ok(Self {
+            name: name.to_string(),
             vertex_buffer,
             vertex_buffer_size,
+            streams: Vec::new(),
             index_buffer,
             index_buffer_size,
         })
     }
 
+    fn vertex_buffer_name(name: &str) -> String {
+        format!("{} vertices", name)
+    }
+
+    fn index_buffer_name(name: &str) -> String {
+        format!("{} indices", name)
+    }
+
+    fn stream_name(&self, stream: usize) -> String {
+        format!("{} vertex stream {}", self.name, stream)
+    }
+
+    pub fn add_stream(&mut self, size: vk::DeviceSize) -> Result<()> {
+        let buffer = GpuBuffer::vertex_buffer(
+            self.vertex_buffer.device.clone(),
+            self.vertex_buffer.allocator.clone(),
+            &self.stream_name(self.streams.len() + 1),
+            size,
+        )?;
+        self.streams.push(VertexStream { buffer, size });
+        Ok(())
+    }
+
+    // Streams are numbered by the binding they are bound to, so the vertex buffer is stream zero
+    pub fn stream_buffer(&self, stream: usize) -> Option<&GpuBuffer> {
+        match stream {
+            0 => Some(&self.vertex_buffer),
+            _ => self.streams.get(stream - 1).map(|stream| &stream.buffer),
+        }
+    }
+
+    pub fn stream_size(&self, stream: usize) -> vk::DeviceSize {
+        match stream {
+            0 => self.vertex_buffer_size,
+            _ => self.streams.get(stream - 1).map_or(0, |stream| stream.size),
+        }
+    }
+
+    // Replaces a stream's buffer with a larger one.
+    // Its contents aren't kept, so they have to be uploaded again.
+    pub fn grow_stream(&mut self, stream: usize, size: vk::DeviceSize) -> Result<()> {
+        if stream == 0 {
+            return self.grow_vertex_buffer(size);
+        }
+        let buffer = GpuBuffer::vertex_buffer(
+            self.vertex_buffer.device.clone(),
+            self.vertex_buffer.allocator.clone(),
+            &self.stream_name(stream),
+            size,
+        )?;
+        let stream = self
+            .streams
+            .get_mut(stream - 1)
+            .context("Failed to find vertex stream!")?;
+        stream.buffer = buffer;
+        stream.size = size;
+        Ok(())
+    }
+
     pub fn reallocate_vertex_buffer(
         &mut self,
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
         size: vk::DeviceSize,
     ) -> Result<()> {
-        self.vertex_buffer = GpuBuffer::vertex_buffer(device, allocator, size)?;
+        self.vertex_buffer = GpuBuffer::vertex_buffer(
+            device,
+            allocator,
+            &Self::vertex_buffer_name(&self.name),
+            size,
+        )?;
         self.vertex_buffer_size = size;
         Ok(())
     }
@@ -277,15 +576,47 @@ impl GeometryBuffer {
         allocator: Arc<RwLock<Allocator>>,
         size: vk::DeviceSize,
     ) -> Result<()> {
-        self.index_buffer = Some(GpuBuffer::index_buffer(device, allocator, size)?);
+        self.index_buffer = Some(GpuBuffer::index_buffer(
+            device,
+            allocator,
+            &Self::index_buffer_name(&self.name),
+            size,
+        )?);
+        self.index_buffer_size = Some(size);
+        Ok(())
+    }
+
+    // Replaces the vertex buffer with a larger one, without keeping its contents
+    pub fn grow_vertex_buffer(&mut self, size: vk::DeviceSize) -> Result<()> {
+        self.vertex_buffer = GpuBuffer::vertex_buffer(
+            self.vertex_buffer.device.clone(),
+            self.vertex_buffer.allocator.clone(),
+            &Self::vertex_buffer_name(&self.name),
+            size,
+        )?;
+        self.vertex_buffer_size = size;
+        Ok(())
+    }
+
+    // Replaces the index buffer with a larger one, without keeping its contents.
+    // Creates the index buffer if there isn't one yet.
+    pub fn grow_index_buffer(&mut self, size: vk::DeviceSize) -> Result<()> {
+        self.index_buffer = Some(GpuBuffer::index_buffer(
+            self.vertex_buffer.device.clone(),
+            self.vertex_buffer.allocator.clone(),
+            &Self::index_buffer_name(&self.name),
+            size,
+        )?);
         self.index_buffer_size = Some(size);
         Ok(())
     }
 
     /// Assumes 32-bit index buffers
     pub fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) -> Result<()> {
-        let offsets = [0];
-        let vertex_buffers = [self.vertex_buffer.handle()];
+        let vertex_buffers = std::iter::once(self.vertex_buffer.handle())
+            .chain(self.streams.iter().map(|stream| stream.buffer.handle()))
+            .collect::<Vec<_>>();
+        let offsets = vec![0; vertex_buffers.len()];
         unsafe {
             device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
             if let Some(index_buffer) = self.index_buffer.as_ref() {
@@ -1,8 +1,9 @@
 use crate::core::{
-    BlitImageBuilder, BufferToImageCopyBuilder, CommandPool, Context, Device,
-    PipelineBarrierBuilder,
+    memory, BlitImageBuilder, BufferToImageCopyBuilder, CommandPool, Context, Device,
+    GpuToCpuBuffer, ImageToBufferCopyBuilder, MemoryCategory, PipelineBarrier,
+    PipelineBarrierBuilder, QueueFamilies, UploadId, UploadQueue,
 };
-use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
+use anyhow::{anyhow, bail, ensure, Context as AnyhowContext, Result};
 use ash::vk;
 use derive_builder::Builder;
 use gpu_allocator::{
@@ -10,6 +11,7 @@ use gpu_allocator::{
     MemoryLocation,
 };
 use image::{DynamicImage, ImageBuffer, Pixel, RgbImage};
+use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
     sync::{Arc, RwLock},
@@ -31,6 +33,38 @@ pub struct ImageLayoutTransition {
     pub dst_access_mask: vk::AccessFlags,
     pub src_stage_mask: vk::PipelineStageFlags,
     pub dst_stage_mask: vk::PipelineStageFlags,
+    // Set when the transition also hands the image over to another queue family
+    #[builder(default = "vk::QUEUE_FAMILY_IGNORED")]
+    pub src_queue_family_index: u32,
+    #[builder(default = "vk::QUEUE_FAMILY_IGNORED")]
+    pub dst_queue_family_index: u32,
+}
+
+impl ImageLayoutTransition {
+    pub fn pipeline_barrier(&self, image: vk::Image) -> Result<PipelineBarrier> {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(self.base_mip_level)
+            .level_count(self.level_count)
+            .layer_count(self.layer_count)
+            .build();
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(self.old_layout)
+            .new_layout(self.new_layout)
+            .src_queue_family_index(self.src_queue_family_index)
+            .dst_queue_family_index(self.dst_queue_family_index)
+            .image(image)
+            .subresource_range(subresource_range)
+            .src_access_mask(self.src_access_mask)
+            .dst_access_mask(self.dst_access_mask)
+            .build();
+        let pipeline_barrier = PipelineBarrierBuilder::default()
+            .src_stage_mask(self.src_stage_mask)
+            .dst_stage_mask(self.dst_stage_mask)
+            .image_memory_barriers(vec![image_barrier])
+            .build()?;
+        Ok(pipeline_barrier)
+    }
 }
 
 pub struct ImageDescription {
@@ -39,6 +73,9 @@ pub struct ImageDescription {
     pub height: u32,
     pub pixels: Vec<u8>,
     pub mip_levels: u32,
+    // Where each mip level starts in the pixels when they are all provided up front.
+    // When empty, only the base level is provided and the rest are generated by blitting.
+    pub mip_offsets: Vec<usize>,
 }
 
 impl ImageDescription {
@@ -49,6 +86,7 @@ impl ImageDescription {
             height,
             pixels: Vec::new(),
             mip_levels: Self::calculate_mip_levels(width, height),
+            mip_offsets: Vec::new(),
         }
     }
 
@@ -84,6 +122,7 @@ impl ImageDescription {
             height,
             pixels: image.to_bytes(),
             mip_levels: Self::calculate_mip_levels(width, height),
+            mip_offsets: Vec::new(),
         };
         description.convert_24bit_formats()?;
         Ok(description)
@@ -91,12 +130,31 @@ impl ImageDescription {
 
     pub fn from_texture(data: &dragonglass_world::Texture) -> Result<Self> {
         let format = Self::map_to_vulkan_format(&data.format);
+        if data.has_precomputed_mips() {
+            ensure!(
+                !matches!(
+                    data.format,
+                    dragonglass_world::Format::R8G8B8 | dragonglass_world::Format::B8G8R8
+                ),
+                "24-bit textures with precomputed mips are unsupported!"
+            );
+            return Ok(Self {
+                format,
+                width: data.width,
+                height: data.height,
+                pixels: data.pixels.to_vec(),
+                mip_levels: data.mip_levels.max(1),
+                mip_offsets: data.mip_offsets(),
+            });
+        }
+
         let mut description = Self {
             format,
             width: data.width,
             height: data.height,
             pixels: data.pixels.to_vec(),
             mip_levels: Self::calculate_mip_levels(data.width, data.height),
+            mip_offsets: Vec::new(),
         };
         description.convert_24bit_formats()?;
         Ok(description)
@@ -130,6 +188,12 @@ impl ImageDescription {
             dragonglass_world::Format::R32G32F => vk::Format::R32G32_SFLOAT,
             dragonglass_world::Format::R32G32B32F => vk::Format::R32G32B32_SFLOAT,
             dragonglass_world::Format::R32G32B32A32F => vk::Format::R32G32B32A32_SFLOAT,
+
+            dragonglass_world::Format::Bc1 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            dragonglass_world::Format::Bc3 => vk::Format::BC3_UNORM_BLOCK,
+            dragonglass_world::Format::Bc4 => vk::Format::BC4_UNORM_BLOCK,
+            dragonglass_world::Format::Bc5 => vk::Format::BC5_UNORM_BLOCK,
+            dragonglass_world::Format::Bc7 => vk::Format::BC7_UNORM_BLOCK,
         }
     }
 
@@ -166,22 +230,31 @@ impl ImageDescription {
         &self,
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
     ) -> Result<AllocatedImage> {
-        self.create_image(device, allocator, vk::ImageCreateFlags::empty(), 1)
+        self.create_image(device, allocator, name, vk::ImageCreateFlags::empty(), 1)
     }
 
     pub fn as_cubemap(
         &self,
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
     ) -> Result<AllocatedImage> {
-        self.create_image(device, allocator, vk::ImageCreateFlags::CUBE_COMPATIBLE, 6)
+        self.create_image(
+            device,
+            allocator,
+            name,
+            vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            6,
+        )
     }
 
     fn create_image(
         &self,
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         flags: vk::ImageCreateFlags,
         layers: u32,
     ) -> Result<AllocatedImage> {
@@ -207,7 +280,7 @@ impl ImageDescription {
             .samples(vk::SampleCountFlags::TYPE_1)
             .flags(flags);
 
-        AllocatedImage::new(device, allocator, &create_info)
+        AllocatedImage::new(device, allocator, name, &create_info)
     }
 }
 
@@ -216,29 +289,7 @@ pub fn transition_image(
     pool: &CommandPool,
     info: &ImageLayoutTransition,
 ) -> Result<()> {
-    let subresource_range = vk::ImageSubresourceRange::builder()
-        .aspect_mask(vk::ImageAspectFlags::COLOR)
-        .base_mip_level(info.base_mip_level)
-        .level_count(info.level_count)
-        .layer_count(info.layer_count)
-        .build();
-    let image_barrier = vk::ImageMemoryBarrier::builder()
-        .old_layout(info.old_layout)
-        .new_layout(info.new_layout)
-        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-        .image(image)
-        .subresource_range(subresource_range)
-        .src_access_mask(info.src_access_mask)
-        .dst_access_mask(info.dst_access_mask)
-        .build();
-    let pipeline_barrier_info = PipelineBarrierBuilder::default()
-        .src_stage_mask(info.src_stage_mask)
-        .dst_stage_mask(info.dst_stage_mask)
-        .image_memory_barriers(vec![image_barrier])
-        .build()?;
-    pool.transition_image_layout(&pipeline_barrier_info)?;
-    Ok(())
+    pool.transition_image_layout(&info.pipeline_barrier(image)?)
 }
 
 pub trait Image {
@@ -256,6 +307,7 @@ impl Image for RawImage {
 pub struct AllocatedImage {
     pub handle: vk::Image,
     allocation: Allocation,
+    category: MemoryCategory,
     allocator: Arc<RwLock<Allocator>>,
     device: Arc<Device>,
 }
@@ -270,21 +322,20 @@ impl AllocatedImage {
     pub fn new(
         device: Arc<Device>,
         allocator: Arc<RwLock<Allocator>>,
+        name: &str,
         image_create_info: &vk::ImageCreateInfoBuilder,
     ) -> Result<Self> {
         let handle = unsafe { device.handle.create_image(image_create_info, None) }?;
+        device.name(handle, name)?;
         let requirements = unsafe { device.handle.get_image_memory_requirements(handle) };
         let allocation_create_info = AllocationCreateDesc {
-            // TODO: Allow custom naming allocations
-            name: "Image Allocation",
+            name,
             requirements,
             location: MemoryLocation::GpuOnly,
             linear: true, // Linear texture
         };
-        let allocation = {
-            let mut allocator = allocator.write().expect("Failed to acquire allocator!");
-            allocator.allocate(&allocation_create_info)?
-        };
+        let category = MemoryCategory::of_image(image_create_info.usage);
+        let allocation = memory::allocate(&device, &allocator, &allocation_create_info, category)?;
         unsafe {
             device
                 .handle
@@ -293,6 +344,7 @@ impl AllocatedImage {
         Ok(Self {
             handle,
             allocation,
+            category,
             allocator,
             device,
         })
@@ -304,23 +356,127 @@ impl AllocatedImage {
         pool: &CommandPool,
         description: &ImageDescription,
     ) -> Result<()> {
-        let buffer = CpuToGpuBuffer::staging_buffer(
-            self.device.clone(),
-            self.allocator.clone(),
-            self.allocation.size(),
-        )?;
-        buffer.upload_data(&description.pixels, 0)?;
-        self.transition_base_to_transfer_dst(pool, description.mip_levels)?;
-        self.copy_to_gpu_buffer(pool, buffer.handle(), description)?;
+        if !description.mip_offsets.is_empty() {
+            return self.upload_mips(context, pool, description, 1);
+        }
         context.ensure_linear_blitting_supported(description.format)?;
-        self.generate_mipmaps(pool, description)?;
-        self.transition_base_to_shader_read(pool, description.mip_levels - 1)?;
-        Ok(())
+        let buffer = self.staging_buffer(description)?;
+        pool.execute_once(|command_buffer| {
+            self.record_upload(command_buffer, buffer.handle(), description, 1)?;
+            self.record_finish(command_buffer, description, 1)
+        })
+    }
+
+    // Uploads every mip level from the description's pixels instead of generating them,
+    // which block compressed formats need since they can't be blitted.
+    // Each level holds all of its layers back to back.
+    pub fn upload_mips(
+        &self,
+        context: &Context,
+        pool: &CommandPool,
+        description: &ImageDescription,
+        layer_count: u32,
+    ) -> Result<()> {
+        context.ensure_sampling_supported(description.format)?;
+        let buffer = self.staging_buffer(description)?;
+        pool.execute_once(|command_buffer| {
+            self.record_upload(command_buffer, buffer.handle(), description, layer_count)?;
+            self.record_finish(command_buffer, description, layer_count)
+        })
+    }
+
+    // Copies the pixels on the upload queue's transfer queue, and generates the mips on the graphics queue.
+    // The image must not be dropped until the upload is done.
+    pub fn upload_data_async(
+        &self,
+        context: &Context,
+        uploads: &mut UploadQueue,
+        description: &ImageDescription,
+    ) -> Result<UploadId> {
+        if description.mip_offsets.is_empty() {
+            context.ensure_linear_blitting_supported(description.format)?;
+        } else {
+            context.ensure_sampling_supported(description.format)?;
+        }
+        let buffer = self.staging_buffer(description)?;
+        let staging = buffer.handle();
+        let families = uploads.queue_families();
+        uploads.submit(
+            buffer,
+            |command_buffer| {
+                self.record_upload(command_buffer, staging, description, 1)?;
+                match families {
+                    Some(families) => self.record_ownership_transfer(
+                        command_buffer,
+                        description.mip_levels,
+                        families,
+                        true,
+                    ),
+                    None => Ok(()),
+                }
+            },
+            |command_buffer| {
+                if let Some(families) = families {
+                    self.record_ownership_transfer(
+                        command_buffer,
+                        description.mip_levels,
+                        families,
+                        false,
+                    )?;
+                }
+                self.record_finish(command_buffer, description, 1)
+            },
+        )
+    }
+
+    // Overwrites the base level of an image that frames may already be sampling.
+    // The copy waits for fragment shaders submitted earlier to the pool's queue to finish reading it.
+    pub fn update_data(&self, pool: &CommandPool, description: &ImageDescription) -> Result<()> {
+        let buffer = self.staging_buffer(description)?;
+        pool.execute_once(|command_buffer| {
+            let transition = ImageLayoutTransitionBuilder::default()
+                .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+                .build()?;
+            self.record_transition(command_buffer, &transition)?;
+            self.copy_to_gpu_buffer(command_buffer, buffer.handle(), description)?;
+            self.transition_mips_to_shader_read(command_buffer, 1, 1)
+        })
+    }
+
+    // The bytes of gpu memory the image takes up
+    pub fn size(&self) -> vk::DeviceSize {
+        self.allocation.size()
+    }
+
+    // Images described without pixels, such as placeholders, still get a staging buffer to copy from
+    fn staging_buffer(&self, description: &ImageDescription) -> Result<CpuToGpuBuffer> {
+        let size = self
+            .allocation
+            .size()
+            .max(description.pixels.len() as vk::DeviceSize);
+        let buffer =
+            CpuToGpuBuffer::staging_buffer(self.device.clone(), self.allocator.clone(), size)?;
+        buffer.upload_data(&description.pixels, 0)?;
+        Ok(buffer)
     }
 
-    fn transition_base_to_transfer_dst(&self, pool: &CommandPool, level_count: u32) -> Result<()> {
+    // Copies the base level, or every level when they are all provided,
+    // leaving every level in the transfer destination layout
+    fn record_upload(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        description: &ImageDescription,
+        layer_count: u32,
+    ) -> Result<()> {
         let transition = ImageLayoutTransitionBuilder::default()
-            .level_count(level_count)
+            .level_count(description.mip_levels)
+            .layer_count(layer_count)
             .old_layout(vk::ImageLayout::UNDEFINED)
             .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
             .src_access_mask(vk::AccessFlags::empty())
@@ -328,12 +484,96 @@ impl AllocatedImage {
             .src_stage_mask(vk::PipelineStageFlags::TOP_OF_PIPE)
             .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
             .build()?;
-        transition_image(self.handle, pool, &transition)
+        self.record_transition(command_buffer, &transition)?;
+        if description.mip_offsets.is_empty() {
+            self.copy_to_gpu_buffer(command_buffer, buffer, description)
+        } else {
+            self.copy_mips_to_gpu_buffer(command_buffer, buffer, description, layer_count)
+        }
+    }
+
+    // Generates the mips that weren't uploaded and makes every level ready to be sampled.
+    // Blitting needs a graphics queue, so this can't run on a transfer queue.
+    fn record_finish(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        description: &ImageDescription,
+        layer_count: u32,
+    ) -> Result<()> {
+        if description.mip_offsets.is_empty() {
+            self.generate_mipmaps(command_buffer, description)?;
+            self.transition_base_to_shader_read(command_buffer, description.mip_levels - 1)
+        } else {
+            self.transition_mips_to_shader_read(command_buffer, description.mip_levels, layer_count)
+        }
+    }
+
+    // Hands every level over between the transfer and graphics queue families.
+    // The transfer queue releases the image after copying to it and the graphics queue acquires it.
+    fn record_ownership_transfer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        level_count: u32,
+        families: QueueFamilies,
+        release: bool,
+    ) -> Result<()> {
+        let mut transition = ImageLayoutTransitionBuilder::default();
+        transition
+            .level_count(level_count)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .src_queue_family_index(families.transfer)
+            .dst_queue_family_index(families.graphics);
+        if release {
+            transition
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::empty())
+                .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
+                .dst_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        } else {
+            transition
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE)
+                .src_stage_mask(vk::PipelineStageFlags::TOP_OF_PIPE)
+                .dst_stage_mask(vk::PipelineStageFlags::TRANSFER);
+        }
+        let transition = transition.build()?;
+        self.record_transition(command_buffer, &transition)
+    }
+
+    fn record_transition(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        transition: &ImageLayoutTransition,
+    ) -> Result<()> {
+        transition
+            .pipeline_barrier(self.handle)?
+            .record(&self.device.handle, command_buffer);
+        Ok(())
+    }
+
+    fn transition_mips_to_shader_read(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        level_count: u32,
+        layer_count: u32,
+    ) -> Result<()> {
+        let transition = ImageLayoutTransitionBuilder::default()
+            .level_count(level_count)
+            .layer_count(layer_count)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .build()?;
+        self.record_transition(command_buffer, &transition)
     }
 
     fn transition_base_to_shader_read(
         &self,
-        pool: &CommandPool,
+        command_buffer: vk::CommandBuffer,
         base_mip_level: u32,
     ) -> Result<()> {
         let transition = ImageLayoutTransitionBuilder::default()
@@ -345,12 +585,12 @@ impl AllocatedImage {
             .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
             .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
             .build()?;
-        transition_image(self.handle, pool, &transition)
+        self.record_transition(command_buffer, &transition)
     }
 
     fn transition_mip_transfer_dst_to_src(
         &self,
-        pool: &CommandPool,
+        command_buffer: vk::CommandBuffer,
         base_mip_level: u32,
     ) -> Result<()> {
         let transition = ImageLayoutTransitionBuilder::default()
@@ -363,10 +603,14 @@ impl AllocatedImage {
             .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
             .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
             .build()?;
-        transition_image(self.handle, pool, &transition)
+        self.record_transition(command_buffer, &transition)
     }
 
-    fn transition_mip_to_shader_read(&self, pool: &CommandPool, base_mip_level: u32) -> Result<()> {
+    fn transition_mip_to_shader_read(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        base_mip_level: u32,
+    ) -> Result<()> {
         let transition = ImageLayoutTransitionBuilder::default()
             .base_mip_level(base_mip_level)
             .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
@@ -376,12 +620,12 @@ impl AllocatedImage {
             .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
             .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
             .build()?;
-        transition_image(self.handle, pool, &transition)
+        self.record_transition(command_buffer, &transition)
     }
 
     fn copy_to_gpu_buffer(
         &self,
-        pool: &CommandPool,
+        command_buffer: vk::CommandBuffer,
         buffer: vk::Buffer,
         description: &ImageDescription,
     ) -> Result<()> {
@@ -407,22 +651,64 @@ impl AllocatedImage {
             .destination(self.handle)
             .regions(vec![region])
             .build()?;
-        pool.copy_buffer_to_image(&copy_info)?;
+        copy_info.record(&self.device.handle, command_buffer);
         Ok(())
     }
 
-    pub fn generate_mipmaps(
+    fn copy_mips_to_gpu_buffer(
         &self,
-        pool: &CommandPool,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        description: &ImageDescription,
+        layer_count: u32,
+    ) -> Result<()> {
+        let regions = description
+            .mip_offsets
+            .iter()
+            .take(description.mip_levels as usize)
+            .enumerate()
+            .map(|(level, offset)| {
+                let extent = vk::Extent3D::builder()
+                    .width((description.width >> level).max(1))
+                    .height((description.height >> level).max(1))
+                    .depth(1)
+                    .build();
+                let subresource = vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(level as u32)
+                    .layer_count(layer_count)
+                    .build();
+                vk::BufferImageCopy::builder()
+                    .buffer_offset(*offset as _)
+                    .buffer_row_length(0)
+                    .buffer_image_height(0)
+                    .image_subresource(subresource)
+                    .image_offset(vk::Offset3D::default())
+                    .image_extent(extent)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let copy_info = BufferToImageCopyBuilder::default()
+            .source(buffer)
+            .destination(self.handle)
+            .regions(regions)
+            .build()?;
+        copy_info.record(&self.device.handle, command_buffer);
+        Ok(())
+    }
+
+    fn generate_mipmaps(
+        &self,
+        command_buffer: vk::CommandBuffer,
         description: &ImageDescription,
     ) -> Result<()> {
         let mut width = description.width as i32;
         let mut height = description.height as i32;
         for level in 1..description.mip_levels {
-            self.transition_mip_transfer_dst_to_src(pool, level - 1)?;
+            self.transition_mip_transfer_dst_to_src(command_buffer, level - 1)?;
             let dimensions = MipmapBlitDimensions::new(width, height);
-            self.blit_mipmap(pool, &dimensions, level)?;
-            self.transition_mip_to_shader_read(pool, level - 1)?;
+            self.blit_mipmap(command_buffer, &dimensions, level)?;
+            self.transition_mip_to_shader_read(command_buffer, level - 1)?;
             width = dimensions.next_width;
             height = dimensions.next_height;
         }
@@ -431,7 +717,7 @@ impl AllocatedImage {
 
     fn blit_mipmap(
         &self,
-        pool: &CommandPool,
+        command_buffer: vk::CommandBuffer,
         dimensions: &MipmapBlitDimensions,
         level: u32,
     ) -> Result<()> {
@@ -463,19 +749,20 @@ impl AllocatedImage {
             .filter(vk::Filter::LINEAR)
             .build()?;
 
-        pool.blit_image(&blit_image_info)
+        blit_image_info.record(&self.device.handle, command_buffer);
+        Ok(())
     }
 }
 
 impl Drop for AllocatedImage {
     fn drop(&mut self) {
-        let mut allocator = self
-            .allocator
-            .write()
-            .expect("Failed to acquire allocator!");
-        allocator
-            .free(self.allocation.clone())
-            .expect("Failed to free allocated image!");
+        memory::free(
+            &self.device,
+            &self.allocator,
+            self.allocation.clone(),
+            self.category,
+        )
+        .expect("Failed to free allocated image!");
         unsafe { self.device.handle.destroy_image(self.handle, None) };
     }
 }
@@ -501,6 +788,43 @@ impl Drop for ImageView {
     }
 }
 
+// Global filtering overrides for texture samplers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SamplerSettings {
+    // Values of 1.0 or less disable anisotropic filtering
+    pub anisotropy: f32,
+    // Blends between mip levels instead of snapping to the nearest one
+    pub trilinear: bool,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            anisotropy: 16.0,
+            trilinear: true,
+        }
+    }
+}
+
+impl SamplerSettings {
+    // The anisotropy level is clamped to what the device supports
+    pub fn apply<'a>(
+        &self,
+        device: &Device,
+        create_info: vk::SamplerCreateInfoBuilder<'a>,
+    ) -> vk::SamplerCreateInfoBuilder<'a> {
+        let anisotropy = self.anisotropy.min(device.max_sampler_anisotropy);
+        let create_info = create_info
+            .anisotropy_enable(anisotropy > 1.0)
+            .max_anisotropy(anisotropy.max(1.0));
+        if self.trilinear {
+            create_info
+        } else {
+            create_info.mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        }
+    }
+}
+
 pub struct Sampler {
     pub handle: vk::Sampler,
     device: Arc<Device>,
@@ -513,6 +837,15 @@ impl Sampler {
         Ok(sampler)
     }
 
+    // Creates a sampler with the device's global sampler settings applied
+    pub fn filtered(
+        device: Arc<Device>,
+        create_info: vk::SamplerCreateInfoBuilder,
+    ) -> Result<Self> {
+        let create_info = device.sampler_settings().apply(&device, create_info);
+        Self::new(device, create_info)
+    }
+
     pub fn default(device: Arc<Device>) -> Result<Self> {
         let sampler_info = vk::SamplerCreateInfo::builder()
             .mag_filter(vk::Filter::LINEAR)
@@ -520,8 +853,6 @@ impl Sampler {
             .address_mode_u(vk::SamplerAddressMode::REPEAT)
             .address_mode_v(vk::SamplerAddressMode::REPEAT)
             .address_mode_w(vk::SamplerAddressMode::REPEAT)
-            .anisotropy_enable(true)
-            .max_anisotropy(16.0)
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false)
             .compare_enable(false)
@@ -530,7 +861,7 @@ impl Sampler {
             .mip_lod_bias(0.0)
             .min_lod(0.0)
             .max_lod(1.0);
-        Self::new(device, sampler_info)
+        Self::filtered(device, sampler_info)
     }
 }
 
@@ -589,15 +920,37 @@ impl Texture {
     pub fn new(
         context: &Context,
         command_pool: &CommandPool,
+        name: &str,
         description: &ImageDescription,
     ) -> Result<Self> {
-        let image = description.as_image(context.device.clone(), context.allocator.clone())?;
+        let image =
+            description.as_image(context.device.clone(), context.allocator.clone(), name)?;
         image.upload_data(context, command_pool, description)?;
         let view = Self::image_view(context.device.clone(), &image, description)?;
+        context
+            .device
+            .name(view.handle, &format!("{} view", name))?;
         let texture = Self { image, view };
         Ok(texture)
     }
 
+    // Creates the texture right away, but it can't be sampled until the upload is ready
+    pub fn upload(
+        context: &Context,
+        uploads: &mut UploadQueue,
+        name: &str,
+        description: &ImageDescription,
+    ) -> Result<(Self, UploadId)> {
+        let image =
+            description.as_image(context.device.clone(), context.allocator.clone(), name)?;
+        let view = Self::image_view(context.device.clone(), &image, description)?;
+        context
+            .device
+            .name(view.handle, &format!("{} view", name))?;
+        let upload = image.upload_data_async(context, uploads, description)?;
+        Ok((Self { image, view }, upload))
+    }
+
     fn image_view(
         device: Arc<Device>,
         image: &AllocatedImage,
@@ -619,31 +972,180 @@ impl Texture {
     }
 }
 
+// The faces of every mip level of a cubemap, read back from the gpu
+#[derive(Serialize, Deserialize)]
+pub struct CubemapData {
+    pub size: u32,
+    pub mip_levels: u32,
+    // The raw value of the vulkan format
+    pub format: i32,
+    // Each level holds its six faces back to back, largest level first
+    pub pixels: Vec<u8>,
+}
+
+impl CubemapData {
+    // Where each mip level starts in the pixels, and the size of all of them together
+    pub fn layout(size: u32, mip_levels: u32, format: vk::Format) -> Result<(Vec<usize>, usize)> {
+        let texel_size = match format {
+            vk::Format::R32G32B32A32_SFLOAT => 16,
+            vk::Format::R16G16B16A16_SFLOAT => 8,
+            vk::Format::R8G8B8A8_UNORM => 4,
+            _ => bail!("Cubemaps with the format {:?} can't be read back!", format),
+        };
+        let mut offset = 0;
+        let offsets = (0..mip_levels)
+            .map(|level| {
+                let dimension = (size >> level).max(1) as usize;
+                let level_offset = offset;
+                offset += dimension * dimension * texel_size * 6;
+                level_offset
+            })
+            .collect();
+        Ok((offsets, offset))
+    }
+}
+
 pub struct Cubemap {
     pub image: AllocatedImage,
     pub view: ImageView,
     pub sampler: Sampler,
+    pub size: u32,
+    pub mip_levels: u32,
+    pub format: vk::Format,
 }
 
 impl Cubemap {
     pub fn new(
         context: &Context,
         command_pool: &CommandPool,
+        name: &str,
         description: &ImageDescription,
     ) -> Result<Self> {
-        let image = description.as_cubemap(context.device.clone(), context.allocator.clone())?;
+        let image =
+            description.as_cubemap(context.device.clone(), context.allocator.clone(), name)?;
         if !description.pixels.is_empty() {
-            image.upload_data(context, command_pool, description)?;
+            if description.mip_offsets.is_empty() {
+                image.upload_data(context, command_pool, description)?;
+            } else {
+                image.upload_mips(context, command_pool, description, 6)?;
+            }
         }
         let view = Self::image_view(context.device.clone(), &image, description)?;
+        context
+            .device
+            .name(view.handle, &format!("{} view", name))?;
         let sampler = Self::sampler(context.device.clone(), description.mip_levels as _)?;
         Ok(Self {
             image,
             view,
             sampler,
+            size: description.width,
+            mip_levels: description.mip_levels,
+            format: description.format,
+        })
+    }
+
+    pub fn from_data(
+        context: &Context,
+        command_pool: &CommandPool,
+        name: &str,
+        data: &CubemapData,
+    ) -> Result<Self> {
+        let format = vk::Format::from_raw(data.format);
+        let (mip_offsets, size) = CubemapData::layout(data.size, data.mip_levels, format)?;
+        ensure!(
+            data.pixels.len() >= size,
+            "Cubemap data has {} bytes, but needs {} bytes!",
+            data.pixels.len(),
+            size
+        );
+        let description = ImageDescription {
+            format,
+            width: data.size,
+            height: data.size,
+            pixels: data.pixels.to_vec(),
+            mip_levels: data.mip_levels,
+            mip_offsets,
+        };
+        Self::new(context, command_pool, name, &description)
+    }
+
+    // Reads every face of every mip level back from the gpu
+    pub fn download(&self, context: &Context, command_pool: &CommandPool) -> Result<CubemapData> {
+        let (mip_offsets, size) = CubemapData::layout(self.size, self.mip_levels, self.format)?;
+
+        let buffer = GpuToCpuBuffer::readback_buffer(
+            context.device.clone(),
+            context.allocator.clone(),
+            "cubemap readback",
+            size as _,
+        )?;
+
+        self.transition(
+            command_pool,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        )?;
+        let regions = mip_offsets
+            .iter()
+            .enumerate()
+            .map(|(level, offset)| {
+                let dimension = (self.size >> level).max(1);
+                let subresource = vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(level as u32)
+                    .layer_count(6)
+                    .build();
+                vk::BufferImageCopy::builder()
+                    .buffer_offset(*offset as _)
+                    .image_subresource(subresource)
+                    .image_extent(vk::Extent3D {
+                        width: dimension,
+                        height: dimension,
+                        depth: 1,
+                    })
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let copy_info = ImageToBufferCopyBuilder::default()
+            .source(self.image.handle)
+            .destination(buffer.handle())
+            .regions(regions)
+            .build()?;
+        command_pool.copy_image_to_buffer(&copy_info)?;
+        self.transition(
+            command_pool,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        )?;
+
+        Ok(CubemapData {
+            size: self.size,
+            mip_levels: self.mip_levels,
+            format: self.format.as_raw(),
+            pixels: buffer.download_data(size)?,
         })
     }
 
+    fn transition(
+        &self,
+        command_pool: &CommandPool,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> Result<()> {
+        let transition = ImageLayoutTransitionBuilder::default()
+            .level_count(self.mip_levels)
+            .layer_count(6)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ)
+            .src_stage_mask(vk::PipelineStageFlags::ALL_COMMANDS)
+            .dst_stage_mask(vk::PipelineStageFlags::ALL_COMMANDS)
+            .build()?;
+        transition_image(self.image.handle, command_pool, &transition)
+    }
+
     fn image_view(
         device: Arc<Device>,
         image: &AllocatedImage,
@@ -671,8 +1173,6 @@ impl Cubemap {
             .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
             .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
             .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
-            .anisotropy_enable(true)
-            .max_anisotropy(16.0)
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false)
             .compare_enable(false)
@@ -681,6 +1181,6 @@ impl Cubemap {
             .mip_lod_bias(0.0)
             .min_lod(0.0)
             .max_lod(mip_levels);
-        Sampler::new(device, sampler_info)
+        Sampler::filtered(device, sampler_info)
     }
 }
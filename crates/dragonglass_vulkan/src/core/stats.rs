@@ -0,0 +1,130 @@
+use ash::vk;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CommandStats {
+    pub draw_calls: u64,
+    pub instances: u64,
+    pub triangles: u64,
+    pub pipeline_switches: u64,
+    pub dispatches: u64,
+}
+
+// Counted as commands are recorded, so every pass contributes without
+// having to thread a counter through each of them.
+// Each device has its own, so renderers don't count each other's commands.
+#[derive(Default, Debug)]
+pub struct CommandCounters {
+    draw_calls: AtomicU64,
+    instances: AtomicU64,
+    triangles: AtomicU64,
+    pipeline_switches: AtomicU64,
+    dispatches: AtomicU64,
+}
+
+impl CommandCounters {
+    // The commands recorded since the last time they were taken, which starts counting a new frame
+    pub fn take(&self) -> CommandStats {
+        CommandStats {
+            draw_calls: self.draw_calls.swap(0, Ordering::Relaxed),
+            instances: self.instances.swap(0, Ordering::Relaxed),
+            triangles: self.triangles.swap(0, Ordering::Relaxed),
+            pipeline_switches: self.pipeline_switches.swap(0, Ordering::Relaxed),
+            dispatches: self.dispatches.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    // Line and point draws are recorded with no triangles
+    pub fn record_draw(&self, triangles: u32, instances: u32) {
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+        self.instances
+            .fetch_add(instances as u64, Ordering::Relaxed);
+        self.triangles
+            .fetch_add(triangles as u64 * instances as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_pipeline_switch(&self) {
+        self.pipeline_switches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dispatch(&self) {
+        self.dispatches.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// What gpu memory is used for. Buffers are sorted by what they are bound as,
+// and images by whether they are rendered to or only sampled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    Textures,
+    RenderTargets,
+    Geometry,
+    Uniforms,
+    // Staging and storage buffers
+    Other,
+}
+
+impl MemoryCategory {
+    pub const ALL: [Self; 5] = [
+        Self::Textures,
+        Self::RenderTargets,
+        Self::Geometry,
+        Self::Uniforms,
+        Self::Other,
+    ];
+
+    pub fn of_buffer(usage: vk::BufferUsageFlags) -> Self {
+        if usage
+            .intersects(vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER)
+        {
+            Self::Geometry
+        } else if usage.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+            Self::Uniforms
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn of_image(usage: vk::ImageUsageFlags) -> Self {
+        if usage.intersects(
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        ) {
+            Self::RenderTargets
+        } else {
+            Self::Textures
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub textures: u64,
+    pub render_targets: u64,
+    pub geometry: u64,
+    pub uniforms: u64,
+    pub other: u64,
+}
+
+impl MemoryStats {
+    pub fn bytes(&self, category: MemoryCategory) -> u64 {
+        match category {
+            MemoryCategory::Textures => self.textures,
+            MemoryCategory::RenderTargets => self.render_targets,
+            MemoryCategory::Geometry => self.geometry,
+            MemoryCategory::Uniforms => self.uniforms,
+            MemoryCategory::Other => self.other,
+        }
+    }
+
+    pub fn buffer_bytes(&self) -> u64 {
+        self.geometry + self.uniforms + self.other
+    }
+
+    pub fn image_bytes(&self) -> u64 {
+        self.textures + self.render_targets
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.buffer_bytes() + self.image_bytes()
+    }
+}
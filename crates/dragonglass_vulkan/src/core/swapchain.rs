@@ -57,10 +57,15 @@ pub struct SwapchainProperties {
 }
 
 impl SwapchainProperties {
-    pub fn new(viewport: Viewport, device: vk::PhysicalDevice, surface: &Surface) -> Result<Self> {
+    pub fn new(
+        viewport: Viewport,
+        device: vk::PhysicalDevice,
+        surface: &Surface,
+        vsync: bool,
+    ) -> Result<Self> {
         let extent = Self::select_extent(viewport, device, surface)?;
         let surface_format = Self::select_format(device, surface)?;
-        let present_mode = Self::select_present_mode(device, surface)?;
+        let present_mode = Self::select_present_mode(device, surface, vsync)?;
         let properties = Self {
             surface_format,
             present_mode,
@@ -123,21 +128,28 @@ impl SwapchainProperties {
         }
     }
 
+    // FIFO is the only mode every device is required to support, and it always waits for vertical blank.
+    // Without vsync, mailbox avoids tearing while immediate does not, so it is preferred when available.
     fn select_present_mode(
         device: vk::PhysicalDevice,
         surface: &Surface,
+        vsync: bool,
     ) -> Result<vk::PresentModeKHR> {
+        if vsync {
+            return Ok(vk::PresentModeKHR::FIFO);
+        }
+
         let present_modes = unsafe {
             surface
                 .handle_ash
                 .get_physical_device_surface_present_modes(device, surface.handle_khr)
         }?;
 
-        let present_mode = match present_modes.as_slice() {
-            [vk::PresentModeKHR::MAILBOX, ..] => vk::PresentModeKHR::MAILBOX,
-            [vk::PresentModeKHR::FIFO, ..] => vk::PresentModeKHR::FIFO,
-            _ => vk::PresentModeKHR::IMMEDIATE,
-        };
+        let present_mode = [vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]
+            .iter()
+            .copied()
+            .find(|mode| present_modes.contains(mode))
+            .unwrap_or(vk::PresentModeKHR::FIFO);
 
         Ok(present_mode)
     }
@@ -150,12 +162,23 @@ impl SwapchainProperties {
 pub fn create_swapchain(
     context: &Context,
     viewport: Viewport,
+    vsync: bool,
+) -> Result<(Swapchain, SwapchainProperties)> {
+    create_swapchain_for_surface(context, context.surface()?, viewport, vsync)
+}
+
+// Swapchains for windows other than the one the context was created with
+pub fn create_swapchain_for_surface(
+    context: &Context,
+    surface: &Surface,
+    viewport: Viewport,
+    vsync: bool,
 ) -> Result<(Swapchain, SwapchainProperties)> {
     let properties =
-        SwapchainProperties::new(viewport, context.physical_device.handle, context.surface()?)?;
+        SwapchainProperties::new(viewport, context.physical_device.handle, surface, vsync)?;
 
     let queue_indices = context.physical_device.queue_indices();
-    let create_info = swapchain_create_info(context, &queue_indices, properties)?;
+    let create_info = swapchain_create_info(context, surface, &queue_indices, properties)?;
 
     let swapchain = Swapchain::new(
         &context.instance.handle,
@@ -168,22 +191,31 @@ pub fn create_swapchain(
 
 fn swapchain_create_info<'a>(
     context: &Context,
+    surface: &Surface,
     queue_indices: &'a [u32],
     properties: SwapchainProperties,
 ) -> Result<vk::SwapchainCreateInfoKHRBuilder<'a>> {
-    let capabilities = context.physical_device_surface_capabilities()?;
+    let capabilities = unsafe {
+        surface.handle_ash.get_physical_device_surface_capabilities(
+            context.physical_device.handle,
+            surface.handle_khr,
+        )
+    }?;
     let image_count = std::cmp::max(
         capabilities.max_image_count,
         capabilities.min_image_count + 1,
     );
+    // Views of the world rendered offscreen are blitted straight into the swapchain images
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_DST);
     let builder = vk::SwapchainCreateInfoKHR::builder()
-        .surface(context.surface()?.handle_khr)
+        .surface(surface.handle_khr)
         .min_image_count(image_count)
         .image_format(properties.surface_format.format)
         .image_color_space(properties.surface_format.color_space)
         .image_extent(properties.extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage)
         .pre_transform(capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(properties.present_mode)
@@ -0,0 +1,309 @@
+use crate::core::{CommandPool, Context, CpuToGpuBuffer, Device, Fence, Semaphore};
+use anyhow::Result;
+use ash::vk;
+use log::error;
+use std::sync::Arc;
+
+// The queue families resources are handed between when uploads run on a dedicated transfer queue
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueueFamilies {
+    pub transfer: u32,
+    pub graphics: u32,
+}
+
+// Identifies an upload, so what it was uploaded to can be used once it is ready
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UploadId(u64);
+
+struct Upload {
+    id: UploadId,
+    // The copy on the transfer queue, which signals the semaphore the graphics commands wait on
+    transfer: Option<(vk::CommandBuffer, Fence, Semaphore)>,
+    // Recorded up front, but only submitted once the transfer is done
+    // so the graphics queue never stalls on it
+    graphics_command_buffer: vk::CommandBuffer,
+    // Set once the graphics commands have been submitted
+    graphics_fence: Option<Fence>,
+    _staging_buffer: CpuToGpuBuffer,
+}
+
+// Uploads data to the gpu without waiting for it to arrive.
+// With a dedicated transfer queue, copies run alongside rendering and each resource
+// is handed over to the graphics queue once its copy is done.
+// Otherwise the copies are submitted to the graphics queue ahead of the next frame.
+pub struct UploadQueue {
+    uploads: Vec<Upload>,
+    next_id: u64,
+    families: Option<QueueFamilies>,
+    transfer_pool: Option<CommandPool>,
+    graphics_pool: CommandPool,
+    device: Arc<Device>,
+}
+
+impl UploadQueue {
+    pub fn new(context: &Context) -> Result<Self> {
+        let device = context.device.clone();
+        let graphics_family = context.physical_device.graphics_queue_family_index;
+        let graphics_pool = Self::command_pool(
+            device.clone(),
+            context.graphics_queue(),
+            graphics_family,
+            "upload",
+        )?;
+
+        let (families, transfer_pool) = match (
+            context.physical_device.transfer_queue_family_index,
+            context.transfer_queue(),
+        ) {
+            (Some(transfer_family), Some(queue)) => {
+                let families = QueueFamilies {
+                    transfer: transfer_family,
+                    graphics: graphics_family,
+                };
+                let pool = Self::command_pool(device.clone(), queue, transfer_family, "transfer")?;
+                (Some(families), Some(pool))
+            }
+            _ => (None, None),
+        };
+
+        Ok(Self {
+            uploads: Vec::new(),
+            next_id: 0,
+            families,
+            transfer_pool,
+            graphics_pool,
+            device,
+        })
+    }
+
+    fn command_pool(
+        device: Arc<Device>,
+        queue: vk::Queue,
+        queue_family_index: u32,
+        name: &str,
+    ) -> Result<CommandPool> {
+        let create_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        CommandPool::new(device, queue, name, create_info)
+    }
+
+    // The families ownership is transferred between, if uploads run on a separate transfer queue
+    pub fn queue_families(&self) -> Option<QueueFamilies> {
+        self.families
+    }
+
+    // The transfer commands copy the data and release it to the graphics queue,
+    // and the graphics commands acquire it and finish anything that needs a graphics queue.
+    // Without a transfer queue, both are recorded into one command buffer
+    // and ownership isn't transferred.
+    // The staging buffer is kept until the upload is done.
+    pub fn submit(
+        &mut self,
+        staging_buffer: CpuToGpuBuffer,
+        mut transfer: impl FnMut(vk::CommandBuffer) -> Result<()>,
+        mut graphics: impl FnMut(vk::CommandBuffer) -> Result<()>,
+    ) -> Result<UploadId> {
+        let id = UploadId(self.next_id);
+        self.next_id += 1;
+
+        let graphics_command_buffer = self
+            .graphics_pool
+            .allocate_command_buffers(1, vk::CommandBufferLevel::PRIMARY)?[0];
+        let upload = match self.transfer_pool.as_ref() {
+            Some(transfer_pool) => {
+                let command_buffer =
+                    transfer_pool.allocate_command_buffers(1, vk::CommandBufferLevel::PRIMARY)?[0];
+                self.device.record_command_buffer(
+                    command_buffer,
+                    vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                    transfer,
+                )?;
+                self.device.record_command_buffer(
+                    graphics_command_buffer,
+                    vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                    graphics,
+                )?;
+                let semaphore = Semaphore::new(self.device.clone())?;
+                let fence = Self::submit_commands(
+                    &self.device,
+                    transfer_pool.queue(),
+                    command_buffer,
+                    None,
+                    Some(semaphore.handle),
+                )?;
+                Upload {
+                    id,
+                    transfer: Some((command_buffer, fence, semaphore)),
+                    graphics_command_buffer,
+                    graphics_fence: None,
+                    _staging_buffer: staging_buffer,
+                }
+            }
+            None => {
+                self.device.record_command_buffer(
+                    graphics_command_buffer,
+                    vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                    |command_buffer| {
+                        transfer(command_buffer)?;
+                        graphics(command_buffer)
+                    },
+                )?;
+                let fence = Self::submit_commands(
+                    &self.device,
+                    self.graphics_pool.queue(),
+                    graphics_command_buffer,
+                    None,
+                    None,
+                )?;
+                Upload {
+                    id,
+                    transfer: None,
+                    graphics_command_buffer,
+                    graphics_fence: Some(fence),
+                    _staging_buffer: staging_buffer,
+                }
+            }
+        };
+        self.uploads.push(upload);
+        Ok(id)
+    }
+
+    fn submit_commands(
+        device: &Arc<Device>,
+        queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+        wait_semaphore: Option<vk::Semaphore>,
+        signal_semaphore: Option<vk::Semaphore>,
+    ) -> Result<Fence> {
+        let fence = Fence::new(device.clone(), vk::FenceCreateFlags::empty())?;
+        let command_buffers = [command_buffer];
+        let wait_semaphores = wait_semaphore.into_iter().collect::<Vec<_>>();
+        let wait_stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; wait_semaphores.len()];
+        let signal_semaphores = signal_semaphore.into_iter().collect::<Vec<_>>();
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .signal_semaphores(&signal_semaphores)
+            .build();
+        unsafe {
+            device
+                .handle
+                .queue_submit(queue, &[submit_info], fence.handle)
+        }?;
+        Ok(fence)
+    }
+
+    // Hands finished transfers over to the graphics queue and frees the uploads that are done.
+    // Meant to be called once a frame, before the frame's commands are submitted.
+    pub fn poll(&mut self) -> Result<()> {
+        let Self {
+            uploads,
+            transfer_pool,
+            graphics_pool,
+            device,
+            ..
+        } = self;
+
+        for upload in uploads.iter_mut() {
+            if upload.graphics_fence.is_some() {
+                continue;
+            }
+            let semaphore = match upload.transfer.as_ref() {
+                Some((_, fence, semaphore)) => {
+                    if !unsafe { device.handle.get_fence_status(fence.handle) }? {
+                        continue;
+                    }
+                    semaphore.handle
+                }
+                None => continue,
+            };
+            upload.graphics_fence = Some(Self::submit_commands(
+                device,
+                graphics_pool.queue(),
+                upload.graphics_command_buffer,
+                Some(semaphore),
+                None,
+            )?);
+        }
+
+        let mut pending = Vec::new();
+        for upload in uploads.drain(..) {
+            let done = match upload.graphics_fence.as_ref() {
+                Some(fence) => unsafe { device.handle.get_fence_status(fence.handle) }?,
+                None => false,
+            };
+            if !done {
+                pending.push(upload);
+                continue;
+            }
+            graphics_pool.free_command_buffers(&[upload.graphics_command_buffer]);
+            if let (Some(pool), Some((command_buffer, ..))) =
+                (transfer_pool.as_ref(), upload.transfer.as_ref())
+            {
+                pool.free_command_buffers(&[*command_buffer]);
+            }
+        }
+        *uploads = pending;
+        Ok(())
+    }
+
+    // Commands submitted to the graphics queue after this returns true see the uploaded data
+    pub fn is_ready(&self, id: UploadId) -> bool {
+        !self
+            .uploads
+            .iter()
+            .any(|upload| upload.id == id && upload.graphics_fence.is_none())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.uploads.is_empty()
+    }
+
+    // Blocks until every upload is done, for when what they upload to is about to be replaced
+    pub fn wait(&mut self) -> Result<()> {
+        let transfer_fences = self
+            .uploads
+            .iter()
+            .filter(|upload| upload.graphics_fence.is_none())
+            .filter_map(|upload| upload.transfer.as_ref())
+            .map(|(_, fence, _)| fence.handle)
+            .collect::<Vec<_>>();
+        if !transfer_fences.is_empty() {
+            unsafe {
+                self.device
+                    .handle
+                    .wait_for_fences(&transfer_fences, true, u64::MAX)
+            }?;
+        }
+        self.poll()?;
+
+        let graphics_fences = self
+            .uploads
+            .iter()
+            .filter_map(|upload| upload.graphics_fence.as_ref())
+            .map(|fence| fence.handle)
+            .collect::<Vec<_>>();
+        if !graphics_fences.is_empty() {
+            unsafe {
+                self.device
+                    .handle
+                    .wait_for_fences(&graphics_fences, true, u64::MAX)
+            }?;
+        }
+        self.poll()
+    }
+}
+
+impl Drop for UploadQueue {
+    fn drop(&mut self) {
+        if self.uploads.is_empty() {
+            return;
+        }
+        // The staging buffers and fences can't be destroyed while the gpu is still using them
+        if let Err(error) = unsafe { self.device.handle.device_wait_idle() } {
+            error!("{}", error);
+        }
+    }
+}
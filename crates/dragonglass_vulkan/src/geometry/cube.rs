@@ -62,6 +62,7 @@ impl Cube {
         let geometry_buffer = GeometryBuffer::new(
             device,
             allocator,
+            "cube",
             (VERTICES.len() * std::mem::size_of::<f32>()) as _,
             Some((INDICES.len() * std::mem::size_of::<u32>()) as _),
         )?;
@@ -99,10 +100,10 @@ impl Cube {
         [vertex_input_binding_description]
     }
 
-    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) -> Result<()> {
-        self.geometry_buffer.bind(device, command_buffer)?;
+    pub fn draw(&self, device: &Device, command_buffer: vk::CommandBuffer) -> Result<()> {
+        self.geometry_buffer.bind(&device.handle, command_buffer)?;
         unsafe {
-            device.cmd_draw_indexed(
+            device.handle.cmd_draw_indexed(
                 command_buffer,
                 (INDICES.len() - NUMBER_OF_LINE_SEGMENTS) as _,
                 1,
@@ -111,31 +112,35 @@ impl Cube {
                 0,
             );
         }
+        device
+            .commands
+            .record_draw(((INDICES.len() - NUMBER_OF_LINE_SEGMENTS) / 3) as _, 1);
         Ok(())
     }
 
-    pub fn draw_loops(
-        &self,
-        device: &ash::Device,
-        command_buffer: vk::CommandBuffer,
-    ) -> Result<()> {
-        self.geometry_buffer.bind(device, command_buffer)?;
+    pub fn draw_loops(&self, device: &Device, command_buffer: vk::CommandBuffer) -> Result<()> {
+        self.geometry_buffer.bind(&device.handle, command_buffer)?;
         unsafe {
-            device.cmd_draw_indexed(command_buffer, 6, 1, 0, 0, 0);
-            device.cmd_draw_indexed(command_buffer, 6, 1, 12, 0, 0);
+            device
+                .handle
+                .cmd_draw_indexed(command_buffer, 6, 1, 0, 0, 0);
+            device
+                .handle
+                .cmd_draw_indexed(command_buffer, 6, 1, 12, 0, 0);
         }
+        device.commands.record_draw(0, 1);
+        device.commands.record_draw(0, 1);
         Ok(())
     }
 
-    pub fn draw_segments(
-        &self,
-        device: &ash::Device,
-        command_buffer: vk::CommandBuffer,
-    ) -> Result<()> {
-        self.geometry_buffer.bind(device, command_buffer)?;
+    pub fn draw_segments(&self, device: &Device, command_buffer: vk::CommandBuffer) -> Result<()> {
+        self.geometry_buffer.bind(&device.handle, command_buffer)?;
         unsafe {
-            device.cmd_draw_indexed(command_buffer, 8, 1, 36, 0, 0);
+            device
+                .handle
+                .cmd_draw_indexed(command_buffer, 8, 1, 36, 0, 0);
         }
+        device.commands.record_draw(0, 1);
         Ok(())
     }
 }
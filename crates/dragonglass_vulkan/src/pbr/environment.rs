@@ -1,13 +1,16 @@
 use crate::{
-    core::{CommandPool, Context, Cubemap, ShaderCache},
+    core::{CommandPool, Context, Cubemap, CubemapData, ShaderCache},
     pbr::{load_hdr_map, load_irradiance_map, load_prefilter_map, Brdflut},
 };
 use anyhow::Result;
-use log::info;
+use ash::vk;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, sync::Arc};
 
 pub struct EnvironmentMapSet {
-    pub hdr: Cubemap,
-    pub brdflut: Brdflut,
+    // Doesn't depend on the environment, so it is shared by every set created from the same one
+    pub brdflut: Arc<Brdflut>,
     pub prefilter: Cubemap,
     pub irradiance: Cubemap,
 }
@@ -19,12 +22,22 @@ impl EnvironmentMapSet {
         shader_cache: &mut ShaderCache,
         hdr_texture: &dragonglass_world::Texture,
     ) -> Result<Self> {
+        info!("Creating Brdflut");
+        let brdflut = Arc::new(Brdflut::new(context, command_pool, shader_cache)?);
+        Self::with_brdflut(context, command_pool, shader_cache, hdr_texture, brdflut)
+    }
+
+    pub fn with_brdflut(
+        context: &Context,
+        command_pool: &CommandPool,
+        shader_cache: &mut ShaderCache,
+        hdr_texture: &dragonglass_world::Texture,
+        brdflut: Arc<Brdflut>,
+    ) -> Result<Self> {
+        // The hdr cubemap is only needed to generate the other maps
         info!("Creating Hdr cubemap");
         let hdr = load_hdr_map(context, command_pool, hdr_texture, shader_cache)?;
 
-        info!("Creating Brdflut");
-        let brdflut = Brdflut::new(context, command_pool, shader_cache)?;
-
         info!("Creating Irradiance cubemap");
         let irradiance = load_irradiance_map(context, command_pool, shader_cache, &hdr)?;
 
@@ -32,10 +45,139 @@ impl EnvironmentMapSet {
         let prefilter = load_prefilter_map(context, command_pool, shader_cache, &hdr)?;
 
         Ok(Self {
-            hdr,
             brdflut,
             prefilter,
             irradiance,
         })
     }
+
+    // Black maps that are bound when there is no environment, since the descriptor sets need something
+    pub fn empty(
+        context: &Context,
+        command_pool: &CommandPool,
+        brdflut: Arc<Brdflut>,
+    ) -> Result<Self> {
+        let data = CubemapData {
+            size: 1,
+            mip_levels: 1,
+            format: vk::Format::R8G8B8A8_UNORM.as_raw(),
+            pixels: vec![0; 6 * 4],
+        };
+        Ok(Self {
+            brdflut,
+            prefilter: Cubemap::from_data(context, command_pool, "prefilter_cubemap", &data)?,
+            irradiance: Cubemap::from_data(context, command_pool, "irradiance_cubemap", &data)?,
+        })
+    }
+
+    pub fn from_data(
+        context: &Context,
+        command_pool: &CommandPool,
+        data: &EnvironmentMapData,
+        brdflut: Arc<Brdflut>,
+    ) -> Result<Self> {
+        Ok(Self {
+            brdflut,
+            prefilter: Cubemap::from_data(
+                context,
+                command_pool,
+                "prefilter_cubemap",
+                &data.prefilter,
+            )?,
+            irradiance: Cubemap::from_data(
+                context,
+                command_pool,
+                "irradiance_cubemap",
+                &data.irradiance,
+            )?,
+        })
+    }
+
+    pub fn download(
+        &self,
+        context: &Context,
+        command_pool: &CommandPool,
+    ) -> Result<EnvironmentMapData> {
+        Ok(EnvironmentMapData {
+            version: EnvironmentMapCache::VERSION,
+            prefilter: self.prefilter.download(context, command_pool)?,
+            irradiance: self.irradiance.download(context, command_pool)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EnvironmentMapData {
+    pub version: u32,
+    pub prefilter: CubemapData,
+    pub irradiance: CubemapData,
+}
+
+// Stores generated environment maps on disk so each hdr only has to be processed once.
+// Entries are keyed by a hash of the hdr's pixels, so the same image loaded from anywhere shares one.
+pub struct EnvironmentMapCache {
+    directory: PathBuf,
+}
+
+impl EnvironmentMapCache {
+    // Bumped whenever the generated maps change so stale entries are regenerated
+    pub const VERSION: u32 = 1;
+
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    // Uploads the cached maps for the texture, generating and caching them on a miss.
+    // The key is the texture's content hash, which is computed once when the texture is loaded.
+    // Failing to read or write the cache only costs the time to regenerate the maps.
+    pub fn load_or_create(
+        &self,
+        context: &Context,
+        command_pool: &CommandPool,
+        shader_cache: &mut ShaderCache,
+        key: u64,
+        hdr_texture: &dragonglass_world::Texture,
+        brdflut: Arc<Brdflut>,
+    ) -> Result<EnvironmentMapSet> {
+        if let Some(data) = self.load(key) {
+            info!("Loading cached environment maps {:016x}", key);
+            match EnvironmentMapSet::from_data(context, command_pool, &data, brdflut.clone()) {
+                Ok(environment_maps) => return Ok(environment_maps),
+                Err(error) => warn!("Failed to upload cached environment maps: {}", error),
+            }
+        }
+
+        let environment_maps = EnvironmentMapSet::with_brdflut(
+            context,
+            command_pool,
+            shader_cache,
+            hdr_texture,
+            brdflut,
+        )?;
+        let stored = environment_maps
+            .download(context, command_pool)
+            .and_then(|data| self.store(key, &data));
+        if let Err(error) = stored {
+            warn!("Failed to cache environment maps: {}", error);
+        }
+        Ok(environment_maps)
+    }
+
+    pub fn load(&self, key: u64) -> Option<EnvironmentMapData> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        let data = bincode::deserialize::<EnvironmentMapData>(&bytes).ok()?;
+        (data.version == Self::VERSION).then(|| data)
+    }
+
+    pub fn store(&self, key: u64, data: &EnvironmentMapData) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.path(key), bincode::serialize(data)?)?;
+        Ok(())
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.bin", key))
+    }
 }
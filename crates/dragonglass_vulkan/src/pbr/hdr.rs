@@ -9,7 +9,7 @@ use crate::{
     geometry::Cube,
 };
 use anyhow::Result;
-use ash::vk::{self, Handle};
+use ash::vk;
 use gpu_allocator::vulkan::Allocator;
 use nalgebra_glm as glm;
 use std::{
@@ -29,7 +29,7 @@ pub fn load_hdr_map(
     shader_cache: &mut ShaderCache,
 ) -> Result<Cubemap> {
     let hdr_description = ImageDescription::from_texture(texture)?;
-    let hdr_texture = Texture::new(context, command_pool, &hdr_description)?;
+    let hdr_texture = Texture::new(context, command_pool, "hdr", &hdr_description)?;
 
     let sampler_info = vk::SamplerCreateInfo::builder()
         .mag_filter(vk::Filter::LINEAR)
@@ -37,8 +37,6 @@ pub fn load_hdr_map(
         .address_mode_u(vk::SamplerAddressMode::REPEAT)
         .address_mode_v(vk::SamplerAddressMode::REPEAT)
         .address_mode_w(vk::SamplerAddressMode::REPEAT)
-        .anisotropy_enable(true)
-        .max_anisotropy(16.0)
         .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
         .unnormalized_coordinates(false)
         .compare_enable(false)
@@ -47,14 +45,14 @@ pub fn load_hdr_map(
         .mip_lod_bias(0.0)
         .min_lod(0.0)
         .max_lod(hdr_description.mip_levels as _);
-    let hdr_sampler = Sampler::new(context.device.clone(), sampler_info)?;
+    let hdr_sampler = Sampler::filtered(context.device.clone(), sampler_info)?;
 
     let cubemap_description = ImageDescription::empty(
         hdr_description.width,
         hdr_description.width,
         vk::Format::R32G32B32A32_SFLOAT,
     );
-    let cubemap = Cubemap::new(context, command_pool, &cubemap_description)?;
+    let cubemap = Cubemap::new(context, command_pool, "hdr_cubemap", &cubemap_description)?;
 
     let device = context.device.clone();
     let allocator = context.allocator.clone();
@@ -66,7 +64,7 @@ pub fn load_hdr_map(
     let descriptor_set_layout = Arc::new(descriptor_set_layout(device.clone())?);
     let descriptor_pool = descriptor_pool(device.clone())?;
     let descriptor_set =
-        descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, 1)?[0];
+        descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, "hdr", 1)?[0];
     update_descriptor_set(
         &device.handle,
         descriptor_set,
@@ -132,7 +130,7 @@ pub fn load_hdr_map(
                             &[],
                         );
                     }
-                    cube.draw(&device.handle, command_buffer)?;
+                    cube.draw(&device, command_buffer)?;
                     Ok(())
                 })?;
                 Ok(())
@@ -185,11 +183,6 @@ pub fn load_hdr_map(
         cubemap_description.mip_levels,
     )?;
 
-    if let Ok(debug) = context.debug() {
-        debug.name_image("hdr_cubemap", cubemap.image.handle.as_raw())?;
-        debug.name_image_view("hdr_cubemap_view", cubemap.view.handle.as_raw())?;
-    }
-
     Ok(cubemap)
 }
 
@@ -322,6 +315,7 @@ fn pipeline(
         .vertex_attributes(Cube::vertex_attributes())
         .descriptor_set_layout(descriptor_set_layout)
         .shader_set(shader_set)
+        .name("hdr")
         .rasterization_samples(vk::SampleCountFlags::TYPE_1)
         .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
         .push_constant_range(push_constant_range);
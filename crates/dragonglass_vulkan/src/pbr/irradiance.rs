@@ -9,7 +9,7 @@ use crate::{
     geometry::Cube,
 };
 use anyhow::Result;
-use ash::vk::{self, Handle};
+use ash::vk;
 use gpu_allocator::vulkan::Allocator;
 use nalgebra_glm as glm;
 use std::{
@@ -36,7 +36,12 @@ pub fn load_irradiance_map(
         output_dimension,
         vk::Format::R32G32B32A32_SFLOAT,
     );
-    let output_cubemap = Cubemap::new(context, command_pool, &output_cubemap_description)?;
+    let output_cubemap = Cubemap::new(
+        context,
+        command_pool,
+        "irradiance_cubemap",
+        &output_cubemap_description,
+    )?;
 
     let device = context.device.clone();
     let allocator = context.allocator.clone();
@@ -49,7 +54,7 @@ pub fn load_irradiance_map(
     let descriptor_set_layout = Arc::new(descriptor_set_layout(device.clone())?);
     let descriptor_pool = descriptor_pool(device.clone())?;
     let descriptor_set =
-        descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, 1)?[0];
+        descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, "irradiance", 1)?[0];
 
     update_descriptor_set(&device.handle, descriptor_set, cubemap);
 
@@ -112,7 +117,7 @@ pub fn load_irradiance_map(
                             &[],
                         );
                     }
-                    cube.draw(&device.handle, command_buffer)?;
+                    cube.draw(&device, command_buffer)?;
                     Ok(())
                 })?;
                 Ok(())
@@ -165,14 +170,6 @@ pub fn load_irradiance_map(
         output_cubemap_description.mip_levels,
     )?;
 
-    if let Ok(debug) = context.debug() {
-        debug.name_image("irradiance_cubemap", output_cubemap.image.handle.as_raw())?;
-        debug.name_image_view(
-            "irradiance_cubemap_view",
-            output_cubemap.view.handle.as_raw(),
-        )?;
-    }
-
     Ok(output_cubemap)
 }
 
@@ -304,6 +301,7 @@ fn pipeline(
         .vertex_attributes(Cube::vertex_attributes())
         .descriptor_set_layout(descriptor_set_layout)
         .shader_set(shader_set)
+        .name("irradiance")
         .rasterization_samples(vk::SampleCountFlags::TYPE_1)
         .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
         .push_constant_range(push_constant_range);
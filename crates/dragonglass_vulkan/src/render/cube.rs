@@ -76,6 +76,7 @@ impl CubeRender {
             .vertex_attributes(Cube::vertex_attributes())
             .descriptor_set_layout(descriptor_set_layout)
             .shader_set(shader_set)
+            .name("cube")
             .rasterization_samples(samples)
             .push_constant_range(push_constant_range);
 
@@ -162,7 +163,7 @@ impl CubeRender {
 
         if solid {
             solid_pipeline.bind(&self.device.handle, command_buffer);
-            self.cube.draw(&self.device.handle, command_buffer)?;
+            self.cube.draw(&self.device, command_buffer)?;
         } else {
             loop_pipeline.bind(&self.device.handle, command_buffer);
             unsafe {
@@ -171,14 +172,13 @@ impl CubeRender {
                     .handle
                     .cmd_set_depth_bias(command_buffer, 1.25, 0.0, 1.0);
             }
-            self.cube.draw_loops(&self.device.handle, command_buffer)?;
+            self.cube.draw_loops(&self.device, command_buffer)?;
             let segment_pipeline = self
                 .segment_pipeline
                 .as_ref()
                 .context("Failed to get wireframe pipeline for rendering asset!")?;
             segment_pipeline.bind(&self.device.handle, command_buffer);
-            self.cube
-                .draw_segments(&self.device.handle, command_buffer)?;
+            self.cube.draw_segments(&self.device, command_buffer)?;
         }
 
         Ok(())
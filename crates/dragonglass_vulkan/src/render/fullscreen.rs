@@ -36,11 +36,15 @@ impl FullscreenRender {
         let device = context.device.clone();
         let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(device.clone())?);
         let descriptor_pool = Self::descriptor_pool(device.clone())?;
-        let descriptor_set =
-            descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, 1)?[0];
+        let descriptor_set = descriptor_pool.allocate_descriptor_sets(
+            descriptor_set_layout.handle,
+            "fullscreen",
+            1,
+        )?[0];
         let uniform_buffer = CpuToGpuBuffer::uniform_buffer(
             device.clone(),
             context.allocator.clone(),
+            "fullscreen uniforms",
             mem::size_of::<FullscreenUniformBuffer>() as _,
         )?;
         let settings = Self::settings(
@@ -74,6 +78,7 @@ impl FullscreenRender {
         let shader_set = shader_cache.create_shader_set(device, &shader_paths)?;
         let settings = GraphicsPipelineSettingsBuilder::default()
             .shader_set(shader_set)
+            .name("fullscreen")
             .render_pass(render_pass)
             .vertex_inputs(Vec::new())
             .vertex_attributes(Vec::new())
@@ -172,6 +177,7 @@ impl FullscreenRender {
 
             self.device.handle.cmd_draw(command_buffer, 3, 1, 0, 0);
         };
+        self.device.commands.record_draw(1, 1);
 
         Ok(())
     }
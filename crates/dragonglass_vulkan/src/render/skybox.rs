@@ -16,6 +16,7 @@ use std::sync::Arc;
 pub struct SkyboxPushConstantBlock {
     pub view: glm::Mat4,
     pub projection: glm::Mat4,
+    pub blend: f32,
 }
 
 pub struct SkyboxRender {
@@ -24,6 +25,8 @@ pub struct SkyboxRender {
     pub pipeline_layout: Option<PipelineLayout>,
     pub view: glm::Mat4,
     pub projection: glm::Mat4,
+    // How much of the cubemap to show over the one being faded out
+    pub blend: f32,
     _descriptor_pool: DescriptorPool,
     descriptor_set: vk::DescriptorSet,
     descriptor_set_layout: Arc<DescriptorSetLayout>,
@@ -40,19 +43,20 @@ impl SkyboxRender {
         let descriptor_set_layout = Arc::new(Self::descriptor_set_layout(context.device.clone())?);
         let descriptor_pool = Self::descriptor_pool(context.device.clone())?;
         let descriptor_set =
-            descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, 1)?[0];
+            descriptor_pool.allocate_descriptor_sets(descriptor_set_layout.handle, "skybox", 1)?[0];
         let rendering = Self {
             cube,
             pipeline: None,
             pipeline_layout: None,
             view: glm::Mat4::identity(),
             projection: glm::Mat4::identity(),
+            blend: 1.0,
             _descriptor_pool: descriptor_pool,
             descriptor_set,
             descriptor_set_layout,
             device: context.device.clone(),
         };
-        rendering.update_descriptor_set(context.device.clone(), cubemap);
+        rendering.update_descriptor_set(context.device.clone(), cubemap, cubemap);
         Ok(rendering)
     }
 
@@ -85,6 +89,7 @@ impl SkyboxRender {
             .vertex_attributes(Cube::vertex_attributes())
             .descriptor_set_layout(self.descriptor_set_layout.clone())
             .shader_set(shader_set)
+            .name("skybox")
             .rasterization_samples(samples)
             .depth_test_enabled(false)
             .depth_write_enabled(false)
@@ -123,6 +128,7 @@ impl SkyboxRender {
         let push_constants = SkyboxPushConstantBlock {
             view: self.view,
             projection: self.projection,
+            blend: self.blend,
         };
 
         unsafe {
@@ -144,7 +150,7 @@ impl SkyboxRender {
             );
         }
 
-        self.cube.draw(&self.device.handle, command_buffer)?;
+        self.cube.draw(&self.device, command_buffer)?;
 
         Ok(())
     }
@@ -156,7 +162,13 @@ impl SkyboxRender {
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT)
             .build();
-        let bindings = [sampler_binding];
+        let faded_sampler_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let bindings = [sampler_binding, faded_sampler_binding];
 
         let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
         DescriptorSetLayout::new(device, create_info)
@@ -165,7 +177,7 @@ impl SkyboxRender {
     fn descriptor_pool(device: Arc<Device>) -> Result<DescriptorPool> {
         let sampler_pool_size = vk::DescriptorPoolSize {
             ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            descriptor_count: 1,
+            descriptor_count: 2,
         };
 
         let pool_sizes = [sampler_pool_size];
@@ -177,13 +189,17 @@ impl SkyboxRender {
         DescriptorPool::new(device, pool_info)
     }
 
-    pub fn update_descriptor_set(&self, device: Arc<Device>, cubemap: &Cubemap) {
-        let image_info = vk::DescriptorImageInfo::builder()
-            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-            .image_view(cubemap.view.handle)
-            .sampler(cubemap.sampler.handle)
-            .build();
-        let image_infos = [image_info];
+    // The faded cubemap is blended out as the blend approaches one
+    pub fn update_descriptor_set(&self, device: Arc<Device>, cubemap: &Cubemap, faded: &Cubemap) {
+        let image_info = |cubemap: &Cubemap| {
+            [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(cubemap.view.handle)
+                .sampler(cubemap.sampler.handle)
+                .build()]
+        };
+        let image_infos = image_info(cubemap);
+        let faded_image_infos = image_info(faded);
 
         let sampler_descriptor_write = vk::WriteDescriptorSet::builder()
             .dst_set(self.descriptor_set)
@@ -193,7 +209,15 @@ impl SkyboxRender {
             .image_info(&image_infos)
             .build();
 
-        let descriptor_writes = vec![sampler_descriptor_write];
+        let faded_sampler_descriptor_write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(1)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&faded_image_infos)
+            .build();
+
+        let descriptor_writes = vec![sampler_descriptor_write, faded_sampler_descriptor_write];
 
         unsafe {
             device
@@ -4,26 +4,203 @@ use legion::EntityStore;
 use nalgebra_glm as glm;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Animation {
     pub name: String,
     pub time: f32,
     pub channels: Vec<Channel>,
     pub max_animation_time: f32,
+    #[serde(default = "default_playing")]
+    pub playing: bool,
+    // Non-looping animations stop once they reach either end
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+    // Negative speeds play the animation in reverse
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    // Kept sorted by time
+    #[serde(default)]
+    pub notifies: Vec<AnimationNotify>,
+}
+
+// A named moment in an animation, such as a footstep or the frame an attack lands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationNotify {
+    pub name: String,
+    pub time: f32,
+}
+
+// Sent when playback crosses one of an animation's notifies
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationEvent {
+    // The index of the animation in the world's assets
+    pub animation: usize,
+    pub animation_name: String,
+    pub name: String,
+    pub time: f32,
+}
+
+// Only the clip chosen to play poses its targets, so that clips sharing a skeleton don't fight
+fn default_playing() -> bool {
+    false
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+fn default_speed() -> f32 {
+    1.0
 }
 
 impl Animation {
+    pub fn new(name: &str, channels: Vec<Channel>) -> Self {
+        let max_animation_time = channels
+            .iter()
+            .flat_map(|channel| channel.inputs.iter().copied())
+            .fold(0.0, f32::max);
+        Self {
+            name: name.to_string(),
+            time: 0.0,
+            channels,
+            max_animation_time,
+            playing: default_playing(),
+            looping: default_looping(),
+            speed: default_speed(),
+            notifies: Vec::new(),
+        }
+    }
+
+    // Advances by the frame's time at the animation's speed while playing, then poses its targets.
+    // Stopped animations leave their targets alone.
+    // The notifies crossed along the way are returned in the order they were crossed.
+    pub fn update(&mut self, ecs: &mut Ecs, delta_time: f32) -> Result<Vec<AnimationNotify>> {
+        if !self.playing {
+            return Ok(Vec::new());
+        }
+        let previous = self.time;
+        let wrapped = self.advance(delta_time * self.speed);
+        let crossed = self.crossed_notifies(previous, delta_time * self.speed >= 0.0, wrapped);
+        self.sample(ecs)?;
+        Ok(crossed)
+    }
+
+    // Whether both animations pose any of the same entities
+    pub fn shares_targets(&self, other: &Animation) -> bool {
+        self.channels.iter().any(|channel| {
+            other
+                .channels
+                .iter()
+                .any(|other| other.target == channel.target)
+        })
+    }
+
+    pub fn add_notify(&mut self, name: &str, time: f32) {
+        let notify = AnimationNotify {
+            name: name.to_string(),
+            time: time.max(0.0).min(self.max_animation_time),
+        };
+        let index = self
+            .notifies
+            .iter()
+            .position(|existing| existing.time > notify.time)
+            .unwrap_or(self.notifies.len());
+        self.notifies.insert(index, notify);
+    }
+
+    // Removes every notify with the name, returning whether there were any
+    pub fn remove_notify(&mut self, name: &str) -> bool {
+        let count = self.notifies.len();
+        self.notifies.retain(|notify| notify.name != name);
+        self.notifies.len() != count
+    }
+
+    // Restores the order after notifies were moved in place
+    pub fn sort_notifies(&mut self) {
+        self.notifies.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    // Steps the animation regardless of its playback settings, wrapping around at either end
     pub fn animate(&mut self, ecs: &mut Ecs, step: f32) -> Result<()> {
         self.time += step;
-        // TODO: Allow for specifying a specific animation by name
         if self.time > self.max_animation_time {
             self.time = 0.0;
         }
         if self.time < 0.0 {
             self.time = self.max_animation_time;
         }
+        self.sample(ecs)
+    }
+
+    // Moves the playhead directly, such as when scrubbing through a timeline
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.max(0.0).min(self.max_animation_time);
+    }
+
+    // Returns true when the playhead wrapped around to the other end
+    fn advance(&mut self, step: f32) -> bool {
+        let time = self.time + step;
+        let duration = self.max_animation_time;
+        if (0.0..=duration).contains(&time) {
+            self.time = time;
+            false
+        } else if self.looping && duration > 0.0 {
+            self.time = time.rem_euclid(duration);
+            true
+        } else {
+            self.time = time.max(0.0).min(duration);
+            self.playing = false;
+            false
+        }
+    }
+
+    // The notifies between the previous time and the current one, including the current time
+    fn crossed_notifies(
+        &self,
+        previous: f32,
+        forward: bool,
+        wrapped: bool,
+    ) -> Vec<AnimationNotify> {
+        let current = self.time;
+        let duration = self.max_animation_time;
+        let crossed = |time: f32| match (forward, wrapped) {
+            (true, false) => time > previous && time <= current,
+            (true, true) => time > previous || time <= current,
+            (false, false) => time < previous && time >= current,
+            (false, true) => time < previous || time >= current,
+        };
+        let mut notifies = self
+            .notifies
+            .iter()
+            .filter(|notify| notify.time <= duration && crossed(notify.time))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Notifies past the wrap were crossed after the ones before it
+        let order = |time: f32| match (forward, wrapped) {
+            (true, true) if time <= current => time + duration,
+            (false, true) if time >= current => time - duration,
+            _ => time,
+        };
+        notifies.sort_by(|a, b| {
+            let (a, b) = (order(a.time), order(b.time));
+            let ordering = a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal);
+            if forward {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+        notifies
+    }
 
-        for channel in self.channels.iter_mut() {
+    // Poses the animation's targets at its current time
+    pub fn sample(&self, ecs: &mut Ecs) -> Result<()> {
+        dragonglass_profiling::profile_scope!("animation");
+        for channel in self.channels.iter() {
             let mut input_iter = channel.inputs.iter().enumerate().peekable();
             while let Some((previous_key, previous_time)) = input_iter.next() {
                 if let Some((next_key, next_time)) = input_iter.peek() {
@@ -101,7 +278,7 @@ impl Animation {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Channel {
     pub target: Entity,
     pub inputs: Vec<f32>,
@@ -116,7 +293,7 @@ pub enum Interpolation {
     CubicSpline,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransformationSet {
     Translations(Vec<glm::Vec3>),
     Rotations(Vec<glm::Vec4>),
@@ -0,0 +1,227 @@
+use crate::{Animation, Geometry, LodGeneration, Material, Texture, TextureCompression};
+use anyhow::{anyhow, ensure, Context, Result};
+use bmfont::{BMFont, OrdinateOrientation};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+
+// The render data a world's entities refer to by name or index
+#[derive(Default, Serialize, Deserialize)]
+pub struct AssetStore {
+    pub animations: Vec<Animation>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub hdr_textures: Vec<Texture>,
+    // The names shown for each hdr texture, usually the stem of the file it was loaded from
+    pub hdr_names: Vec<String>,
+    // The content hash of each hdr texture, computed once when it is added
+    #[serde(default)]
+    hdr_keys: Vec<u64>,
+    pub geometry: Geometry,
+    pub fonts: HashMap<String, SdfFont>,
+    // Applied to the textures of gltf files as they are loaded into the world
+    #[serde(skip)]
+    pub texture_compression: TextureCompression,
+    // Generates levels of detail for the meshes of models as they are loaded into the world
+    #[serde(skip)]
+    pub lod_generation: Option<LodGeneration>,
+    // Bumped by every material edit, so renderers know when to refresh what they built from the materials
+    #[serde(skip)]
+    materials_revision: u64,
+    // Bumped when a texture is replaced, indexed the same as the textures.
+    // Missing entries belong to textures that were never replaced.
+    #[serde(skip)]
+    texture_revisions: Vec<u64>,
+}
+
+impl AssetStore {
+    pub fn clear(&mut self) {
+        self.textures.clear();
+        self.animations.clear();
+        self.materials.clear();
+        self.geometry.clear();
+        self.materials_revision += 1;
+        self.texture_revisions.clear();
+    }
+
+    pub fn material_at_index(&self, index: usize) -> Result<&Material> {
+        let error_message = format!("Failed to lookup material at index: {}", index);
+        self.materials.get(index).context(error_message)
+    }
+
+    pub fn update_material(&mut self, index: usize, material: Material) -> Result<()> {
+        let number_of_textures = self.textures.len() as i32;
+        for texture_index in material.texture_indices() {
+            ensure!(
+                texture_index >= -1 && texture_index < number_of_textures,
+                "Material refers to texture {} but only {} textures exist!",
+                texture_index,
+                number_of_textures
+            );
+        }
+        let error_message = format!("Failed to lookup material at index: {}", index);
+        *self.materials.get_mut(index).context(error_message)? = material;
+        self.materials_revision += 1;
+        Ok(())
+    }
+
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    // Only the last material can be removed without changing the indices of the others,
+    // so materials added before others or still used by a mesh are left for compaction
+    pub fn remove_material(&mut self, index: usize) -> bool {
+        let in_use = self.geometry.meshes.values().any(|mesh| {
+            mesh.primitives
+                .iter()
+                .any(|primitive| primitive.material_index == Some(index))
+        });
+        if in_use || index + 1 != self.materials.len() {
+            return false;
+        }
+        self.materials.pop();
+        self.materials_revision += 1;
+        true
+    }
+
+    pub fn materials_revision(&self) -> u64 {
+        self.materials_revision
+    }
+
+    // Returns the index materials can use to refer to the texture
+    pub fn add_texture(&mut self, texture: Texture) -> usize {
+        self.textures.push(texture);
+        self.textures.len() - 1
+    }
+
+    pub fn replace_texture(&mut self, index: usize, texture: Texture) -> Result<()> {
+        let error_message = format!("Failed to lookup texture at index: {}", index);
+        *self.textures.get_mut(index).context(error_message)? = texture;
+        if self.texture_revisions.len() <= index {
+            self.texture_revisions.resize(index + 1, 0);
+        }
+        self.texture_revisions[index] += 1;
+        Ok(())
+    }
+
+    pub fn texture_revision(&self, index: usize) -> u64 {
+        self.texture_revisions
+            .get(index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Returns the index of the hdr texture, which is shared with an identical one that was already added
+    pub fn load_hdr(&mut self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(self.add_hdr(name, Texture::from_environment(path)?))
+    }
+
+    pub fn add_hdr(&mut self, name: impl Into<String>, texture: Texture) -> usize {
+        let key = texture.content_hash();
+        self.add_hdr_with_key(name, texture, key)
+    }
+
+    // Adds an hdr texture whose content hash was already computed, such as while it was loaded in the background
+    pub fn add_hdr_with_key(
+        &mut self,
+        name: impl Into<String>,
+        texture: Texture,
+        key: u64,
+    ) -> usize {
+        self.fill_missing_hdr_keys();
+        if let Some(index) = self.hdr_keys.iter().position(|existing| *existing == key) {
+            return index;
+        }
+        // Worlds saved before hdr textures were named have fewer names than textures
+        self.hdr_names
+            .resize(self.hdr_textures.len(), String::new());
+        self.hdr_names.push(name.into());
+        self.hdr_textures.push(texture);
+        self.hdr_keys.push(key);
+        self.hdr_textures.len() - 1
+    }
+
+    pub fn hdr_key(&self, index: usize) -> Option<u64> {
+        self.hdr_keys.get(index).copied()
+    }
+
+    // Worlds saved before hdr textures were hashed are hashed once when they are loaded
+    pub(crate) fn fill_missing_hdr_keys(&mut self) {
+        for texture in self.hdr_textures.iter().skip(self.hdr_keys.len()) {
+            self.hdr_keys.push(texture.content_hash());
+        }
+    }
+
+    pub fn hdr_name(&self, index: usize) -> String {
+        match self.hdr_names.get(index) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("Environment {}", index),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SdfFont {
+    texture: Texture,
+    font: BMFont,
+}
+
+impl SdfFont {
+    pub fn new(font_path: impl AsRef<Path>, texture_path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(font_path)?;
+        let font = BMFont::new(file, OrdinateOrientation::TopToBottom)?;
+        let texture = Texture::from_file(texture_path)?;
+        Ok(Self { texture, font })
+    }
+
+    // The texture holding the distance field of every glyph
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    // In pixels, at the size the font was generated at
+    pub fn line_height(&self) -> f32 {
+        self.font.line_height() as f32
+    }
+
+    // Lays out each line of the text below the previous one, starting from the origin
+    pub fn layout(&self, text: &str) -> Result<Vec<GlyphQuad>> {
+        let characters = self
+            .font
+            .parse(text)
+            .map_err(|error| anyhow!("Failed to lay out text '{}': {:?}", text, error))?;
+        let texture_size = glm::vec2(self.texture.width as f32, self.texture.height as f32);
+        let glyphs = characters
+            .iter()
+            .map(|character| {
+                let screen_rect = &character.screen_rect;
+                let page_rect = &character.page_rect;
+                let uv_min = glm::vec2(page_rect.x as f32, page_rect.y as f32);
+                let uv_max = uv_min + glm::vec2(page_rect.width as f32, page_rect.height as f32);
+                GlyphQuad {
+                    position: glm::vec2(screen_rect.x as f32, screen_rect.y as f32),
+                    size: glm::vec2(screen_rect.width as f32, screen_rect.height as f32),
+                    uv_min: uv_min.component_div(&texture_size),
+                    uv_max: uv_max.component_div(&texture_size),
+                }
+            })
+            .collect();
+        Ok(glyphs)
+    }
+}
+
+// A glyph's rectangle in pixels with y pointing down, and the corners of its image in the font's texture
+#[derive(Debug, Copy, Clone)]
+pub struct GlyphQuad {
+    pub position: glm::Vec2,
+    pub size: glm::Vec2,
+    pub uv_min: glm::Vec2,
+    pub uv_max: glm::Vec2,
+}
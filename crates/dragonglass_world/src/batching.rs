@@ -0,0 +1,156 @@
+use crate::{
+    compact_world, BoundingBox, Entity, Mesh, MeshRender, Name, Primitive, Skin, Static, Transform,
+    Vertex, World,
+};
+use anyhow::Result;
+use legion::{component, IntoQuery};
+use nalgebra_glm as glm;
+use std::collections::BTreeMap;
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct BatchReport {
+    pub entities_batched: usize,
+    pub primitives_merged: usize,
+    pub batches_created: usize,
+}
+
+// Merges the meshes of entities marked Static into one mesh per material,
+// with their vertices pre-transformed into world space so each batch is drawn once.
+// Batched entities keep their transforms and colliders but no longer render on their own.
+pub fn batch_static_meshes(world: &mut World) -> Result<BatchReport> {
+    let mut query =
+        <(Entity, &MeshRender)>::query().filter(component::<Static>() & !component::<Skin>());
+    let candidates = query
+        .iter(&world.sim.ecs)
+        .map(|(entity, mesh_render)| (*entity, mesh_render.name.to_string()))
+        .collect::<Vec<_>>();
+
+    let mut report = BatchReport::default();
+
+    // Keyed by material index, sorted so batches are created in a deterministic order
+    let mut batches = BTreeMap::<Option<usize>, (Vec<Vertex>, Vec<u32>)>::new();
+    let mut batched_entities = Vec::new();
+    for (entity, mesh_name) in candidates.into_iter() {
+        let geometry = &world.assets.geometry;
+        let mesh = match geometry.meshes.get(&mesh_name) {
+            Some(mesh) if can_batch(mesh) => mesh,
+            _ => continue,
+        };
+
+        let matrix = world.sim.entity_global_transform_matrix(entity)?;
+        let normal_matrix = glm::mat4_to_mat3(&glm::transpose(&glm::inverse(&matrix)));
+
+        for primitive in mesh.primitives.iter() {
+            let (vertices, indices) = batches.entry(primitive.material_index).or_default();
+            let offset = vertices.len() as u32;
+
+            let first_vertex = primitive.first_vertex;
+            vertices.extend(
+                geometry.vertices[first_vertex..first_vertex + primitive.number_of_vertices]
+                    .iter()
+                    .map(|vertex| transform_vertex(vertex, &matrix, &normal_matrix)),
+            );
+
+            let first_index = primitive.first_index;
+            indices.extend(
+                geometry.indices[first_index..first_index + primitive.number_of_indices]
+                    .iter()
+                    .map(|index| index - first_vertex as u32 + offset),
+            );
+
+            report.primitives_merged += 1;
+        }
+        batched_entities.push(entity);
+    }
+
+    if batched_entities.is_empty() {
+        return Ok(report);
+    }
+
+    for (material_index, (vertices, indices)) in batches.into_iter() {
+        let geometry = &mut world.assets.geometry;
+
+        let mut bounding_box = BoundingBox::new_invalid();
+        vertices
+            .iter()
+            .for_each(|vertex| bounding_box.fit_point(vertex.position));
+
+        let first_vertex = geometry.vertices.len();
+        let first_index = geometry.indices.len();
+        let primitive = Primitive {
+            first_vertex,
+            first_index,
+            number_of_vertices: vertices.len(),
+            number_of_indices: indices.len(),
+            material_index,
+            morph_targets: Vec::new(),
+            bounding_box,
+        };
+        geometry.vertices.extend(vertices.into_iter());
+        geometry
+            .indices
+            .extend(indices.into_iter().map(|index| index + first_vertex as u32));
+
+        let mut batch_number = 1;
+        let mut name = format!("Static Batch {}", batch_number);
+        while geometry.meshes.contains_key(&name) {
+            batch_number += 1;
+            name = format!("Static Batch {}", batch_number);
+        }
+        geometry.meshes.insert(
+            name.to_string(),
+            Mesh {
+                name: name.to_string(),
+                primitives: vec![primitive],
+                weights: Vec::new(),
+            },
+        );
+
+        let entity = world.sim.ecs.push((
+            Name(name.to_string()),
+            Transform::default(),
+            MeshRender { name },
+        ));
+        world.sim.scene.default_scenegraph_mut()?.add_node(entity);
+        report.batches_created += 1;
+    }
+
+    for entity in batched_entities.iter() {
+        if let Some(mut entry) = world.sim.ecs.entry(*entity) {
+            entry.remove_component::<MeshRender>();
+        }
+    }
+    report.entities_batched = batched_entities.len();
+
+    // The source meshes are dropped if nothing else renders them
+    compact_world(world)?;
+
+    Ok(report)
+}
+
+// Morph targets and skinning deform vertices at runtime, so those meshes stay unbatched.
+// Batches are always drawn indexed, so unindexed primitives are left alone as well.
+fn can_batch(mesh: &Mesh) -> bool {
+    !mesh.primitives.is_empty()
+        && mesh.weights.is_empty()
+        && mesh
+            .primitives
+            .iter()
+            .all(|primitive| primitive.morph_targets.is_empty() && primitive.number_of_indices > 0)
+}
+
+fn transform_vertex(vertex: &Vertex, matrix: &glm::Mat4, normal_matrix: &glm::Mat3) -> Vertex {
+    let position = matrix * glm::vec4(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+    let normal = normal_matrix * vertex.normal;
+    // Tangents follow the surface rather than its normal, and mirroring flips their handedness
+    let rotation = glm::mat4_to_mat3(matrix);
+    let tangent = rotation * glm::vec4_to_vec3(&vertex.tangent);
+    let tangent = tangent.try_normalize(f32::EPSILON).unwrap_or(tangent);
+    let handedness = vertex.tangent.w * glm::determinant(&rotation).signum();
+    Vertex {
+        position: glm::vec4_to_vec3(&position),
+        normal: normal.try_normalize(f32::EPSILON).unwrap_or(normal),
+        tangent: glm::vec4(tangent.x, tangent.y, tangent.z, handedness),
+        ..*vertex
+    }
+}
@@ -0,0 +1,90 @@
+use crate::Entity;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BlackboardValue {
+    Bool(bool),
+    Number(f32),
+    Vector(glm::Vec3),
+    Entity(Entity),
+    Text(String),
+}
+
+// Named values an entity's behavior tree reads and writes, such as the target it is chasing
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Blackboard {
+    values: HashMap<String, BlackboardValue>,
+}
+
+impl Blackboard {
+    pub fn set(&mut self, key: &str, value: BlackboardValue) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BlackboardValue> {
+        self.values.get(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<BlackboardValue> {
+        self.values.remove(key)
+    }
+
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            BlackboardValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, key: &str) -> Option<f32> {
+        match self.get(key)? {
+            BlackboardValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn vector(&self, key: &str) -> Option<glm::Vec3> {
+        match self.get(key)? {
+            BlackboardValue::Vector(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn entity(&self, key: &str) -> Option<Entity> {
+        match self.get(key)? {
+            BlackboardValue::Entity(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            BlackboardValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+// Runs the behavior tree registered under a name with the app's behavior tree system each frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BehaviorTree {
+    pub tree: String,
+    pub blackboard: Blackboard,
+    pub enabled: bool,
+    // The child each running sequence or selector is on, by the node's index in the tree,
+    // so they resume where they left off on the next frame
+    pub cursors: HashMap<usize, usize>,
+}
+
+impl BehaviorTree {
+    pub fn new(tree: impl Into<String>) -> Self {
+        Self {
+            tree: tree.into(),
+            blackboard: Blackboard::default(),
+            enabled: true,
+            cursors: HashMap::new(),
+        }
+    }
+}
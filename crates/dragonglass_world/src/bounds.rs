@@ -0,0 +1,268 @@
+use crate::{
+    occlusion::{clip_corners, outside_frustum},
+    BoundingBox, Entity, MeshRender, World,
+};
+use anyhow::Result;
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use petgraph::visit::Dfs;
+use std::collections::{HashMap, HashSet};
+
+struct CachedBounds {
+    // What the bounds were computed from, so they are only recomputed when one changes
+    matrix: glm::Mat4,
+    mesh: String,
+    bounds: BoundingBox,
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: BoundingBox,
+        entity: Entity,
+    },
+    Branch {
+        bounds: BoundingBox,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &BoundingBox {
+        match self {
+            Self::Leaf { bounds, .. } | Self::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+// The world space bounds of every mesh, with a bounding volume hierarchy over them for spatial queries.
+// Bounds are cached per entity and only recomputed when its global transform or mesh changes,
+// and the hierarchy is rebuilt when any of them do.
+#[derive(Default)]
+pub struct SceneBounds {
+    entities: HashMap<Entity, CachedBounds>,
+    nodes: Vec<BvhNode>,
+    root: Option<usize>,
+}
+
+impl SceneBounds {
+    // The bounds of an entity's mesh in world space as of the last update
+    pub fn entity_bounds(&self, entity: Entity) -> Option<&BoundingBox> {
+        self.entities.get(&entity).map(|cached| &cached.bounds)
+    }
+
+    // The global transform an entity's mesh bounds were computed with
+    pub(crate) fn entity_matrix(&self, entity: Entity) -> Option<&glm::Mat4> {
+        self.entities.get(&entity).map(|cached| &cached.matrix)
+    }
+
+    // The bounds around every mesh in the world
+    pub fn scene_bounds(&self) -> Option<&BoundingBox> {
+        self.root.map(|root| self.nodes[root].bounds())
+    }
+
+    // Every entity whose bounds pass the test, skipping the branches that fail it
+    pub fn query(&self, overlaps: impl Fn(&BoundingBox) -> bool) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        let mut stack = self.root.into_iter().collect::<Vec<_>>();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !overlaps(node.bounds()) {
+                continue;
+            }
+            match node {
+                BvhNode::Leaf { entity, .. } => entities.push(*entity),
+                BvhNode::Branch { left, right, .. } => {
+                    stack.push(*left);
+                    stack.push(*right);
+                }
+            }
+        }
+        entities
+    }
+
+    pub fn entities_in_box(&self, bounds: &BoundingBox) -> Vec<Entity> {
+        self.query(|node| node.intersects(bounds))
+    }
+
+    pub fn entities_in_sphere(&self, center: &glm::Vec3, radius: f32) -> Vec<Entity> {
+        self.query(|node| node.intersects_sphere(center, radius))
+    }
+
+    // The entities that may be inside the view, which is conservative near the frustum's corners
+    pub fn entities_in_view(&self, view_projection: &glm::Mat4) -> Vec<Entity> {
+        self.query(|node| !outside_frustum(&clip_corners(view_projection, node)))
+    }
+
+    fn rebuild(&mut self) {
+        self.nodes.clear();
+        let mut items = self
+            .entities
+            .iter()
+            .filter(|(_, cached)| cached.bounds.is_valid())
+            .map(|(entity, cached)| (*entity, cached.bounds.clone()))
+            .collect::<Vec<_>>();
+        self.root = if items.is_empty() {
+            None
+        } else {
+            Some(self.build(&mut items))
+        };
+    }
+
+    // Splits the items in half along the longest axis of their centers until each node holds one
+    fn build(&mut self, items: &mut [(Entity, BoundingBox)]) -> usize {
+        if items.len() == 1 {
+            let (entity, bounds) = items[0].clone();
+            self.nodes.push(BvhNode::Leaf { bounds, entity });
+            return self.nodes.len() - 1;
+        }
+
+        let mut centers = BoundingBox::new_invalid();
+        items
+            .iter()
+            .for_each(|(_, bounds)| centers.fit_point(bounds.center()));
+        let extents = centers.extents();
+        let axis = if extents.x >= extents.y && extents.x >= extents.z {
+            0
+        } else if extents.y >= extents.z {
+            1
+        } else {
+            2
+        };
+        items.sort_by(|(_, a), (_, b)| {
+            a.center()[axis]
+                .partial_cmp(&b.center()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let (left_items, right_items) = items.split_at_mut(items.len() / 2);
+        let left = self.build(left_items);
+        let right = self.build(right_items);
+        let mut bounds = self.nodes[left].bounds().clone();
+        bounds.fit_box(self.nodes[right].bounds());
+        self.nodes.push(BvhNode::Branch {
+            bounds,
+            left,
+            right,
+        });
+        self.nodes.len() - 1
+    }
+}
+
+impl World {
+    // Recomputes the bounds of meshes that moved or changed since the last update
+    pub fn update_bounds(&mut self) -> Result<()> {
+        dragonglass_profiling::profile_scope!("bounds");
+        let mut bounds = std::mem::take(&mut self.bounds);
+        let result = self.refresh_bounds(&mut bounds);
+        self.bounds = bounds;
+        result
+    }
+
+    fn refresh_bounds(&self, bounds: &mut SceneBounds) -> Result<()> {
+        let mut globals = HashMap::new();
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                if self
+                    .ecs
+                    .entry_ref(entity)?
+                    .get_component::<MeshRender>()
+                    .is_ok()
+                {
+                    globals.insert(entity, self.global_transform(graph, node_index)?);
+                }
+                Ok(())
+            })?;
+        }
+
+        let mut changed = false;
+        let mut seen = HashSet::new();
+        let mut query = <(Entity, &MeshRender)>::query();
+        for (entity, mesh_render) in query.iter(&self.ecs) {
+            seen.insert(*entity);
+            let matrix = match globals.get(entity) {
+                Some(matrix) => *matrix,
+                // Not in a scenegraph, so the entity only has a local transform
+                None => self.entity_global_transform_matrix(*entity)?,
+            };
+            let unchanged = bounds.entities.get(entity).map_or(false, |cached| {
+                cached.matrix == matrix && cached.mesh == mesh_render.name
+            });
+            if unchanged {
+                continue;
+            }
+            let mesh_bounds = match self.assets.geometry.meshes.get(&mesh_render.name) {
+                Some(mesh) => mesh.bounding_box().transformed(&matrix),
+                None => BoundingBox::new_invalid(),
+            };
+            bounds.entities.insert(
+                *entity,
+                CachedBounds {
+                    matrix,
+                    mesh: mesh_render.name.to_string(),
+                    bounds: mesh_bounds,
+                },
+            );
+            changed = true;
+        }
+
+        let count = bounds.entities.len();
+        bounds.entities.retain(|entity, _| seen.contains(entity));
+        changed |= bounds.entities.len() != count;
+
+        if changed {
+            bounds.rebuild();
+        }
+        Ok(())
+    }
+
+    pub fn entity_bounds(&self, entity: Entity) -> Option<BoundingBox> {
+        self.bounds.entity_bounds(entity).cloned()
+    }
+
+    // The bounds around the meshes of an entity and everything beneath it in its scenegraph
+    pub fn hierarchy_bounds(&self, entity: Entity) -> Option<BoundingBox> {
+        let mut bounds = BoundingBox::new_invalid();
+        let mut fit = |entity: Entity| {
+            if let Some(entity_bounds) = self.bounds.entity_bounds(entity) {
+                if entity_bounds.is_valid() {
+                    bounds.fit_box(entity_bounds);
+                }
+            }
+        };
+        let node = self
+            .scene
+            .graphs
+            .iter()
+            .find_map(|graph| graph.find_node(entity).map(|index| (graph, index)));
+        match node {
+            Some((graph, index)) => {
+                let mut dfs = Dfs::new(&graph.0, index);
+                while let Some(index) = dfs.next(&graph.0) {
+                    fit(graph[index]);
+                }
+            }
+            None => fit(entity),
+        }
+        if bounds.is_valid() {
+            Some(bounds)
+        } else {
+            None
+        }
+    }
+
+    // The bounds around every mesh as of the last update
+    pub fn scene_bounds(&self) -> Option<BoundingBox> {
+        self.bounds.scene_bounds().cloned()
+    }
+
+    // Entities whose mesh bounds touch the sphere, for broad phase queries like explosions and triggers
+    pub fn entities_in_sphere(&self, center: &glm::Vec3, radius: f32) -> Vec<Entity> {
+        self.bounds.entities_in_sphere(center, radius)
+    }
+
+    pub fn entities_in_box(&self, bounds: &BoundingBox) -> Vec<Entity> {
+        self.bounds.entities_in_box(bounds)
+    }
+}
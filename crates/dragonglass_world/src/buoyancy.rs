@@ -0,0 +1,164 @@
+use crate::{BoundingBox, Entity, RigidBody, SimWorld};
+use anyhow::Result;
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use rapier3d::{geometry::Collider, na::Point3};
+use serde::{Deserialize, Serialize};
+
+// Where the fluid of a buoyancy volume is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BuoyancyRegion {
+    // A box centered on the entity, scaled and rotated with it and then fit with an axis aligned box
+    Box { half_extents: glm::Vec3 },
+    // The bounds of the colliders on the entity's rigid body, which are best made sensors
+    // so they don't also push bodies out of the fluid
+    Colliders,
+}
+
+// Fluid that floats the dynamic rigid bodies inside it and slows them down.
+// Bodies are treated as submerged by how much of each collider's bounds is in the fluid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuoyancyVolume {
+    pub region: BuoyancyRegion,
+    // Bodies denser than the fluid sink and lighter ones float. Water is 1.0 in rapier's default units.
+    pub fluid_density: f32,
+    // The fraction of a submerged body's velocity relative to the flow that is lost each second
+    pub linear_drag: f32,
+    pub angular_drag: f32,
+    // The velocity of the fluid, which drag carries submerged bodies along with
+    pub flow: glm::Vec3,
+}
+
+impl Default for BuoyancyVolume {
+    fn default() -> Self {
+        Self::new(BuoyancyRegion::Box {
+            half_extents: glm::vec3(0.5, 0.5, 0.5),
+        })
+    }
+}
+
+impl BuoyancyVolume {
+    pub fn new(region: BuoyancyRegion) -> Self {
+        Self {
+            region,
+            fluid_density: 1.0,
+            linear_drag: 1.0,
+            angular_drag: 1.0,
+            flow: glm::Vec3::zeros(),
+        }
+    }
+}
+
+impl SimWorld {
+    // Applies buoyancy and drag to bodies in the fluid for one physics step
+    pub fn update_buoyancy(&mut self, delta_time: f32) -> Result<()> {
+        let mut query = <(Entity, &BuoyancyVolume)>::query();
+        let volumes = query
+            .iter(&self.ecs)
+            .map(|(entity, volume)| (*entity, volume.clone()))
+            .collect::<Vec<_>>();
+        if volumes.is_empty() {
+            return Ok(());
+        }
+
+        let mut regions = Vec::new();
+        for (entity, volume) in volumes.into_iter() {
+            let bounds = self.buoyancy_bounds(entity, &volume)?;
+            if bounds.is_valid() {
+                let handle = self
+                    .ecs
+                    .entry_ref(entity)?
+                    .get_component::<RigidBody>()
+                    .ok()
+                    .map(|rigid_body| rigid_body.handle);
+                regions.push((handle, bounds, volume));
+            }
+        }
+
+        let gravity = self.physics.gravity;
+        let mut query = <&RigidBody>::query();
+        let handles = query
+            .iter(&self.ecs)
+            .map(|rigid_body| rigid_body.handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            for (volume_handle, region, volume) in regions.iter() {
+                // A collider defined volume doesn't float its own body
+                if *volume_handle == Some(handle) {
+                    continue;
+                }
+                let physics = &mut self.physics;
+                let body = match physics.bodies.get_mut(handle) {
+                    Some(body) if body.is_dynamic() => body,
+                    _ => continue,
+                };
+
+                let colliders = body.colliders().to_vec();
+                let mut submerged_fraction = 0.0;
+                for collider_handle in colliders.iter() {
+                    let collider = &physics.colliders[*collider_handle];
+                    let bounds = collider_bounds(collider);
+                    let overlap = bounds.intersection(region);
+                    if !overlap.is_valid() || bounds.volume() <= f32::EPSILON {
+                        continue;
+                    }
+                    let fraction = overlap.volume() / bounds.volume();
+                    let volume_submerged = collider.shape().mass_properties(1.0).mass() * fraction;
+
+                    // Pushing up from the middle of the submerged part rights bodies as they float
+                    let buoyancy = -gravity * volume.fluid_density * volume_submerged * delta_time;
+                    body.apply_impulse_at_point(buoyancy, Point3::from(overlap.center()), true);
+                    submerged_fraction += fraction / colliders.len() as f32;
+                }
+                if submerged_fraction <= 0.0 {
+                    continue;
+                }
+
+                let linear_loss = (volume.linear_drag * submerged_fraction * delta_time).min(1.0);
+                let relative_velocity = body.linvel() - volume.flow;
+                body.apply_impulse(-relative_velocity * body.mass() * linear_loss, true);
+                let angular_loss = (volume.angular_drag * submerged_fraction * delta_time).min(1.0);
+                body.set_angvel(body.angvel() * (1.0 - angular_loss), true);
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the point is inside a buoyancy volume, such as for a camera going underwater
+    pub fn is_point_submerged(&self, point: &glm::Vec3) -> Result<bool> {
+        let mut query = <(Entity, &BuoyancyVolume)>::query();
+        for (entity, volume) in query.iter(&self.ecs) {
+            if self.buoyancy_bounds(*entity, volume)?.contains_point(point) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn buoyancy_bounds(&self, entity: Entity, volume: &BuoyancyVolume) -> Result<BoundingBox> {
+        let bounds = match &volume.region {
+            BuoyancyRegion::Box { half_extents } => {
+                let global = self.entity_global_transform_matrix(entity)?;
+                BoundingBox::new(-half_extents, *half_extents).transformed(&global)
+            }
+            BuoyancyRegion::Colliders => {
+                let mut bounds = BoundingBox::new_invalid();
+                let entry = self.ecs.entry_ref(entity)?;
+                if let Ok(rigid_body) = entry.get_component::<RigidBody>() {
+                    if let Some(body) = self.physics.bodies.get(rigid_body.handle) {
+                        for handle in body.colliders() {
+                            bounds.fit_box(&collider_bounds(&self.physics.colliders[*handle]));
+                        }
+                    }
+                }
+                bounds
+            }
+        };
+        Ok(bounds)
+    }
+}
+
+fn collider_bounds(collider: &Collider) -> BoundingBox {
+    let aabb = collider.compute_aabb();
+    BoundingBox::new(aabb.mins.coords, aabb.maxs.coords)
+}
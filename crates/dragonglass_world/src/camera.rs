@@ -1,11 +1,21 @@
+use crate::Layers;
 use nalgebra_glm as glm;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub name: String,
     pub projection: Projection,
     pub enabled: bool,
+    // Scales the scene's light before it is tonemapped, brightening or darkening the image
+    pub exposure: f32,
+    // The layers this camera draws. Entities on none of them are skipped.
+    #[serde(default = "all_layers")]
+    pub culling_mask: Layers,
+}
+
+fn all_layers() -> Layers {
+    Layers::ALL
 }
 
 impl Camera {
@@ -24,13 +34,13 @@ impl Camera {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Projection {
     Perspective(PerspectiveCamera),
     Orthographic(OrthographicCamera),
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct PerspectiveCamera {
     pub aspect_ratio: Option<f32>,
     pub y_fov_rad: f32,
@@ -54,7 +64,7 @@ impl PerspectiveCamera {
     }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct OrthographicCamera {
     pub x_mag: f32,
     pub y_mag: f32,
@@ -0,0 +1,300 @@
+use crate::{Camera, Entity, Projection, SimWorld, SplineKind, SplineSamples, Transform};
+use anyhow::{Context, Result};
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+// Smoothly changes a perspective camera's vertical field of view, easing in and out.
+// It is removed once the camera arrives at the new field of view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FovTransition {
+    pub from: f32,
+    pub to: f32,
+    // Seconds the transition takes
+    pub duration: f32,
+    pub elapsed: f32,
+}
+
+impl FovTransition {
+    pub fn fov(&self) -> f32 {
+        let t = if self.duration > 0.0 {
+            (self.elapsed / self.duration).min(1.0)
+        } else {
+            1.0
+        };
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.from + (self.to - self.from) * eased
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+// Trauma based camera shake. Events such as explosions add trauma, which decays over time.
+// The shake grows with the square of the trauma, so small amounts of it stay subtle.
+// Only the view is shaken, the camera's transform is left alone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraShake {
+    // From zero to one
+    pub trauma: f32,
+    // Trauma lost each second
+    pub decay: f32,
+    // The largest yaw, pitch, and roll in radians at full trauma
+    pub max_angles: glm::Vec3,
+    // The largest offset along each of the camera's axes at full trauma
+    pub max_offset: glm::Vec3,
+    // How quickly the shake changes direction, in noise samples per second
+    pub frequency: f32,
+    pub seed: u32,
+    time: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 0.8,
+            max_angles: glm::vec3(0.1, 0.1, 0.05),
+            max_offset: glm::Vec3::zeros(),
+            frequency: 15.0,
+            seed: 0,
+            time: 0.0,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn shake(&self) -> f32 {
+        self.trauma * self.trauma
+    }
+
+    // The camera space offset applied to the view
+    pub fn matrix(&self) -> glm::Mat4 {
+        let shake = self.shake();
+        if shake <= 0.0 {
+            return glm::Mat4::identity();
+        }
+        let t = self.time * self.frequency;
+        let sample = |channel: u32| smooth_noise(self.seed.wrapping_add(channel), t) * shake;
+        let angles = self
+            .max_angles
+            .component_mul(&glm::vec3(sample(0), sample(1), sample(2)));
+        let offset = self
+            .max_offset
+            .component_mul(&glm::vec3(sample(3), sample(4), sample(5)));
+        glm::translation(&offset)
+            * glm::rotation(angles.x, &glm::Vec3::y())
+            * glm::rotation(angles.y, &glm::Vec3::x())
+            * glm::rotation(angles.z, &glm::Vec3::z())
+    }
+
+    fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+        self.trauma = (self.trauma - self.decay * delta_time).max(0.0);
+    }
+}
+
+// Smoothly interpolated value noise from -1 to 1
+fn smooth_noise(seed: u32, t: f32) -> f32 {
+    let hash = |index: i32| {
+        let mut value = (index as u32).wrapping_mul(0x9E37_79B9) ^ seed.wrapping_mul(0x85EB_CA6B);
+        value ^= value >> 16;
+        value = value.wrapping_mul(0x7FEB_352D);
+        value ^= value >> 15;
+        value as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+    let index = t.floor();
+    let fraction = t - index;
+    let eased = fraction * fraction * (3.0 - 2.0 * fraction);
+    let (first, second) = (hash(index as i32), hash(index as i32 + 1));
+    first + (second - first) * eased
+}
+
+// Moves a camera through the positions of control point entities for cutscenes,
+// along a Catmull-Rom spline that passes through every point.
+// The camera faces along the path unless it has something to look at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub points: Vec<Entity>,
+    // Seconds to travel the whole path
+    pub duration: f32,
+    pub looping: bool,
+    pub look_at: Option<Entity>,
+    pub playing: bool,
+    pub elapsed: f32,
+}
+
+impl CameraPath {
+    pub fn new(points: Vec<Entity>, duration: f32) -> Self {
+        Self {
+            points,
+            duration,
+            looping: false,
+            look_at: None,
+            playing: true,
+            elapsed: 0.0,
+        }
+    }
+
+    // How far along the path the camera is, from zero to one
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        if self.looping {
+            (self.elapsed / self.duration).fract()
+        } else {
+            (self.elapsed / self.duration).min(1.0)
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.looping && self.elapsed >= self.duration
+    }
+}
+
+impl SimWorld {
+    // The active camera's exposure, or one when there is no active camera
+    pub fn active_camera_exposure(&self) -> f32 {
+        self.active_camera()
+            .map(|entity| self.camera_exposure(entity))
+            .unwrap_or(1.0)
+    }
+
+    // A camera's exposure, or one when the entity has no camera
+    pub fn camera_exposure(&self, camera: Entity) -> f32 {
+        self.ecs
+            .entry_ref(camera)
+            .ok()
+            .and_then(|entry| {
+                entry
+                    .get_component::<Camera>()
+                    .ok()
+                    .map(|camera| camera.exposure)
+            })
+            .unwrap_or(1.0)
+    }
+
+    // Starts smoothly changing a camera's vertical field of view, in radians
+    pub fn animate_fov(&mut self, camera: Entity, fov: f32, duration: f32) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(camera)
+            .context("Failed to find the camera to animate")?;
+        let from = match &entry.get_component::<Camera>()?.projection {
+            Projection::Perspective(perspective) => perspective.y_fov_rad,
+            Projection::Orthographic(_) => return Ok(()),
+        };
+        entry.add_component(FovTransition {
+            from,
+            to: fov,
+            duration,
+            elapsed: 0.0,
+        });
+        Ok(())
+    }
+
+    pub fn add_camera_trauma(&mut self, camera: Entity, amount: f32) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(camera)
+            .context("Failed to find the camera to shake")?;
+        match entry.get_component_mut::<CameraShake>() {
+            Ok(shake) => shake.add_trauma(amount),
+            Err(_) => {
+                let mut shake = CameraShake::default();
+                shake.add_trauma(amount);
+                entry.add_component(shake);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn update_camera_rigs(&mut self, delta_time: f32) -> Result<()> {
+        self.update_fov_transitions(delta_time);
+        for shake in <&mut CameraShake>::query().iter_mut(&mut self.ecs) {
+            shake.update(delta_time);
+        }
+        self.update_camera_paths(delta_time)
+    }
+
+    fn update_fov_transitions(&mut self, delta_time: f32) {
+        let mut finished = Vec::new();
+        let mut query = <(Entity, &mut FovTransition, &mut Camera)>::query();
+        for (entity, transition, camera) in query.iter_mut(&mut self.ecs) {
+            transition.elapsed += delta_time;
+            if let Projection::Perspective(perspective) = &mut camera.projection {
+                perspective.y_fov_rad = transition.fov();
+            }
+            if transition.finished() {
+                finished.push(*entity);
+            }
+        }
+        for entity in finished.into_iter() {
+            if let Some(mut entry) = self.ecs.entry(entity) {
+                entry.remove_component::<FovTransition>();
+            }
+        }
+    }
+
+    fn update_camera_paths(&mut self, delta_time: f32) -> Result<()> {
+        let mut query = <(Entity, &mut CameraPath)>::query();
+        let paths = query
+            .iter_mut(&mut self.ecs)
+            .filter(|(_, path)| path.playing && !path.points.is_empty())
+            .map(|(entity, path)| {
+                path.elapsed += delta_time;
+                (*entity, path.clone())
+            })
+            .collect::<Vec<_>>();
+
+        for (entity, path) in paths.into_iter() {
+            // Points that were removed are skipped
+            let points = path
+                .points
+                .iter()
+                .filter_map(|point| self.entity_global_transform(*point).ok())
+                .map(|transform| transform.translation)
+                .collect::<Vec<_>>();
+            if points.is_empty() {
+                continue;
+            }
+            // Sampled like any other spline, so the camera moves along it at a constant speed
+            let samples = SplineSamples::new(SplineKind::CatmullRom, &points, path.looping);
+            let distance = path.progress() * samples.length();
+            let position = samples.position_at(distance);
+
+            let look_at = path
+                .look_at
+                .and_then(|target| self.entity_global_transform(target).ok());
+            // The end of a path that doesn't loop faces the way it arrived
+            let direction = match look_at {
+                Some(target) => target.translation - position,
+                None => samples.tangent_at(distance),
+            };
+
+            let mut global = self.entity_global_transform(entity)?;
+            global.translation = position;
+            if direction.norm() > f32::EPSILON {
+                global.look_at(&direction.normalize(), &glm::Vec3::y());
+            }
+            let local = self.local_transform_from_global(entity, &global.matrix())?;
+            let mut entry = self
+                .ecs
+                .entry(entity)
+                .context("A camera following a path no longer exists")?;
+            *entry.get_component_mut::<Transform>()? = local;
+            if path.finished() {
+                if let Ok(component) = entry.get_component_mut::<CameraPath>() {
+                    component.playing = false;
+                }
+            }
+        }
+        Ok(())
+    }
+}
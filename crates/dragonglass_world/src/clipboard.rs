@@ -0,0 +1,350 @@
+use crate::{
+    Camera, Entity, Hidden, Light, Material, Mesh, MeshRender, Name, SceneGraph, Texture,
+    Transform, Vertex, World,
+};
+use anyhow::{Context, Result};
+use legion::EntityStore;
+use petgraph::prelude::*;
+use std::collections::HashMap;
+
+// A self-contained copy of an entity hierarchy that can be pasted into any world
+#[derive(Default, Clone)]
+pub struct Clipboard {
+    nodes: Vec<ClipboardNode>,
+    // The entities each node was copied from
+    entities: Vec<Entity>,
+    meshes: HashMap<String, ClipboardMesh>,
+}
+
+#[derive(Clone)]
+struct ClipboardNode {
+    parent: Option<usize>,
+    name: Option<String>,
+    transform: Transform,
+    camera: Option<Camera>,
+    light: Option<Light>,
+    mesh: Option<String>,
+    hidden: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct ClipboardMesh {
+    pub(crate) mesh: Mesh,
+    pub(crate) vertices: Vec<Vec<Vertex>>,
+    pub(crate) indices: Vec<Vec<u32>>,
+    pub(crate) materials: Vec<Option<Material>>,
+    pub(crate) textures: HashMap<i32, Texture>,
+}
+
+impl Clipboard {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Copies the entity and all of its descendants in the scenegraph
+    pub fn copy(world: &World, entity: Entity) -> Result<Self> {
+        let mut clipboard = Self::default();
+        let graph = world
+            .sim
+            .scene
+            .graphs
+            .iter()
+            .find(|graph| graph.find_node(entity).is_some());
+        match graph {
+            Some(graph) => {
+                let index = graph
+                    .find_node(entity)
+                    .context("Failed to find entity in the scenegraph!")?;
+                clipboard.copy_node(world, graph, index, None)?;
+            }
+            None => {
+                clipboard.copy_entity(world, entity, None)?;
+            }
+        }
+        Ok(clipboard)
+    }
+
+    fn copy_node(
+        &mut self,
+        world: &World,
+        graph: &SceneGraph,
+        index: NodeIndex,
+        parent: Option<usize>,
+    ) -> Result<()> {
+        let node = self.copy_entity(world, graph[index], parent)?;
+        let mut children = graph.neighbors(index, Outgoing);
+        while let Some(child) = children.next_node(&graph.0) {
+            self.copy_node(world, graph, child, Some(node))?;
+        }
+        Ok(())
+    }
+
+    fn copy_entity(
+        &mut self,
+        world: &World,
+        entity: Entity,
+        parent: Option<usize>,
+    ) -> Result<usize> {
+        let entry = world.sim.ecs.entry_ref(entity)?;
+
+        let mesh = match entry.get_component::<MeshRender>() {
+            Ok(mesh_render) => {
+                if !self.meshes.contains_key(&mesh_render.name) {
+                    let mesh = Self::copy_mesh(world, &mesh_render.name)?;
+                    self.meshes.insert(mesh_render.name.to_string(), mesh);
+                }
+                Some(mesh_render.name.to_string())
+            }
+            Err(_) => None,
+        };
+
+        self.nodes.push(ClipboardNode {
+            parent,
+            name: entry
+                .get_component::<Name>()
+                .ok()
+                .map(|name| name.0.to_string()),
+            transform: entry
+                .get_component::<Transform>()
+                .ok()
+                .copied()
+                .unwrap_or_default(),
+            camera: entry.get_component::<Camera>().ok().cloned(),
+            light: entry.get_component::<Light>().ok().copied(),
+            mesh,
+            hidden: entry.get_component::<Hidden>().is_ok(),
+        });
+        self.entities.push(entity);
+
+        Ok(self.nodes.len() - 1)
+    }
+
+    pub(crate) fn copy_mesh(world: &World, name: &str) -> Result<ClipboardMesh> {
+        let mesh = world
+            .assets
+            .geometry
+            .meshes
+            .get(name)
+            .context(format!("Failed to find mesh '{}' to copy", name))?
+            .clone();
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut materials = Vec::new();
+        let mut textures = HashMap::new();
+        for primitive in mesh.primitives.iter() {
+            let first_vertex = primitive.first_vertex;
+            vertices.push(
+                world.assets.geometry.vertices
+                    [first_vertex..first_vertex + primitive.number_of_vertices]
+                    .to_vec(),
+            );
+
+            // Indices are stored relative to the primitive so they can be rebased on paste
+            let first_index = primitive.first_index;
+            indices.push(
+                world.assets.geometry.indices
+                    [first_index..first_index + primitive.number_of_indices]
+                    .iter()
+                    .map(|index| index - first_vertex as u32)
+                    .collect(),
+            );
+
+            let material = primitive
+                .material_index
+                .and_then(|index| world.assets.materials.get(index))
+                .cloned();
+            if let Some(material) = material.as_ref() {
+                for texture_index in material.texture_indices() {
+                    if texture_index < 0 || textures.contains_key(&texture_index) {
+                        continue;
+                    }
+                    if let Some(texture) = world.assets.textures.get(texture_index as usize) {
+                        textures.insert(texture_index, texture.clone());
+                    }
+                }
+            }
+            materials.push(material);
+        }
+
+        Ok(ClipboardMesh {
+            mesh,
+            vertices,
+            indices,
+            materials,
+            textures,
+        })
+    }
+
+    // Returns the root entities that were created
+    pub fn paste(&self, world: &mut World) -> Result<Vec<Entity>> {
+        let entities = self.instantiate(world, None, false)?;
+        Ok(self
+            .nodes
+            .iter()
+            .zip(entities)
+            .filter(|(node, _)| node.parent.is_none())
+            .map(|(_, entity)| entity)
+            .collect())
+    }
+
+    // Recreates the copied entities beneath the given parent,
+    // returning pairs of the original entity and the entity that replaced it
+    pub fn restore(
+        &self,
+        world: &mut World,
+        parent: Option<Entity>,
+    ) -> Result<Vec<(Entity, Entity)>> {
+        let entities = self.instantiate(world, parent, false)?;
+        Ok(self.entities.iter().copied().zip(entities).collect())
+    }
+
+    // Like restore, but the new entities render the meshes they were copied from
+    // instead of copies of them, so edits to those meshes and their materials affect every instance.
+    // Meshes that no longer exist in the world are pasted as copies.
+    pub fn restore_linked(
+        &self,
+        world: &mut World,
+        parent: Option<Entity>,
+    ) -> Result<Vec<(Entity, Entity)>> {
+        let entities = self.instantiate(world, parent, true)?;
+        Ok(self.entities.iter().copied().zip(entities).collect())
+    }
+
+    fn instantiate(
+        &self,
+        world: &mut World,
+        parent: Option<Entity>,
+        linked: bool,
+    ) -> Result<Vec<Entity>> {
+        let mut mesh_names = HashMap::new();
+        for (name, mesh) in self.meshes.iter() {
+            let new_name = if linked && world.assets.geometry.meshes.contains_key(name) {
+                name.to_string()
+            } else {
+                paste_mesh(world, name, mesh)
+            };
+            mesh_names.insert(name.to_string(), new_name);
+        }
+
+        let mut entities = Vec::new();
+        for node in self.nodes.iter() {
+            let entity = world.sim.ecs.push((node.transform,));
+            let mut entry = world
+                .sim
+                .ecs
+                .entry(entity)
+                .context("Failed to find entity!")?;
+            if let Some(name) = node.name.as_ref() {
+                entry.add_component(Name(name.to_string()));
+            }
+            if let Some(camera) = node.camera.as_ref() {
+                let mut camera = camera.clone();
+                camera.enabled = false;
+                entry.add_component(camera);
+            }
+            if let Some(light) = node.light {
+                entry.add_component(light);
+            }
+            if let Some(mesh) = node.mesh.as_ref() {
+                entry.add_component(MeshRender {
+                    name: mesh_names[mesh].to_string(),
+                });
+            }
+            if node.hidden {
+                entry.add_component(Hidden);
+            }
+            entities.push(entity);
+        }
+
+        let parent_node = parent.and_then(|parent| {
+            world
+                .sim
+                .scene
+                .graphs
+                .iter()
+                .enumerate()
+                .find_map(|(offset, graph)| graph.find_node(parent).map(|index| (offset, index)))
+        });
+
+        let mut new_graph = SceneGraph::new();
+        let graph = match parent_node {
+            Some((offset, _)) => &mut world.sim.scene.graphs[offset],
+            None => &mut new_graph,
+        };
+        let indices = entities
+            .iter()
+            .map(|entity| graph.add_node(*entity))
+            .collect::<Vec<_>>();
+        for (offset, node) in self.nodes.iter().enumerate() {
+            match (node.parent, parent_node) {
+                (Some(parent), _) => graph.add_edge(indices[parent], indices[offset]),
+                (None, Some((_, parent_index))) => graph.add_edge(parent_index, indices[offset]),
+                (None, None) => {}
+            }
+        }
+        if new_graph.number_of_nodes() > 0 {
+            world.sim.scene.graphs.push(new_graph);
+        }
+
+        Ok(entities)
+    }
+}
+
+pub(crate) fn paste_mesh(world: &mut World, name: &str, clipboard_mesh: &ClipboardMesh) -> String {
+    let mut texture_map = HashMap::new();
+    for (index, texture) in clipboard_mesh.textures.iter() {
+        texture_map.insert(*index, world.assets.textures.len() as i32);
+        world.assets.textures.push(texture.clone());
+    }
+
+    let mut mesh = clipboard_mesh.mesh.clone();
+    for (offset, primitive) in mesh.primitives.iter_mut().enumerate() {
+        let first_vertex = world.assets.geometry.vertices.len();
+        let first_index = world.assets.geometry.indices.len();
+        world
+            .assets
+            .geometry
+            .vertices
+            .extend_from_slice(&clipboard_mesh.vertices[offset]);
+        world.assets.geometry.indices.extend(
+            clipboard_mesh.indices[offset]
+                .iter()
+                .map(|index| index + first_vertex as u32),
+        );
+        primitive.first_vertex = first_vertex;
+        primitive.first_index = first_index;
+
+        primitive.material_index = clipboard_mesh.materials[offset].as_ref().map(|material| {
+            let mut material = material.clone();
+            remap_texture_indices(&mut material, &texture_map);
+            world.assets.materials.push(material);
+            world.assets.materials.len() - 1
+        });
+    }
+
+    let mut new_name = name.to_string();
+    let mut copy_number = 1;
+    while world.assets.geometry.meshes.contains_key(&new_name) {
+        new_name = format!("{} ({})", name, copy_number);
+        copy_number += 1;
+    }
+    mesh.name = new_name.to_string();
+    world
+        .assets
+        .geometry
+        .meshes
+        .insert(new_name.to_string(), mesh);
+    new_name
+}
+
+fn remap_texture_indices(material: &mut Material, texture_map: &HashMap<i32, i32>) {
+    let remap = |value: &mut i32| {
+        if let Some(index) = texture_map.get(value) {
+            *value = *index;
+        }
+    };
+    for index in material.texture_indices_mut() {
+        remap(index);
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+// Simulation time, advanced only by SimWorld::tick.
+// Gameplay should read time from here rather than the system clock so it can be paused,
+// scaled, stepped, and replayed deterministically.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clock {
+    pub time_scale: f32,
+    pub paused: bool,
+    // Ticks by the same amount every frame regardless of the frame rate,
+    // so a session plays out the same way on fast and slow machines
+    #[serde(default)]
+    fixed_step: Option<f32>,
+    // Frames to advance by while paused
+    #[serde(skip)]
+    pending_steps: u32,
+    delta: f32,
+    elapsed: f64,
+    frame: u64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            time_scale: 1.0,
+            paused: false,
+            fixed_step: None,
+            pending_steps: 0,
+            delta: 0.0,
+            elapsed: 0.0,
+            frame: 0,
+        }
+    }
+}
+
+impl Clock {
+    // How far the world advances for each frame stepped while paused, before scaling
+    pub const STEP_SECONDS: f32 = 1.0 / 60.0;
+
+    // Stepped frames use a fixed length so stepping through a simulation is repeatable
+    pub fn advance(&mut self, delta_time: f32) {
+        let delta_time = self.fixed_step.unwrap_or(delta_time);
+        self.delta = if !self.paused {
+            delta_time * self.time_scale
+        } else if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            self.fixed_step.unwrap_or(Self::STEP_SECONDS) * self.time_scale
+        } else {
+            0.0
+        };
+        self.elapsed += self.delta as f64;
+        self.frame += 1;
+    }
+
+    // Values below one slow the world down, and zero freezes it without pausing
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    // Advances the world by a single frame while paused
+    pub fn step(&mut self) {
+        self.step_frames(1);
+    }
+
+    pub fn step_frames(&mut self, frames: u32) {
+        if self.paused {
+            self.pending_steps += frames;
+        }
+    }
+
+    pub fn fixed_step(&self) -> Option<f32> {
+        self.fixed_step
+    }
+
+    pub fn set_fixed_step(&mut self, step: Option<f32>) {
+        self.fixed_step = step.filter(|step| *step > 0.0);
+    }
+
+    // The scaled time in seconds since the previous tick
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    // The scaled time in seconds since the world started
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn reset(&mut self) {
+        self.delta = 0.0;
+        self.elapsed = 0.0;
+        self.frame = 0;
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    Billboard, Lod, Material, Mesh, MeshRender, ParticleEmitter, Sprite, Texture, Vertex, World,
+};
+use anyhow::Result;
+use legion::IntoQuery;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct CompactionReport {
+    pub vertices_reclaimed: usize,
+    pub indices_reclaimed: usize,
+    pub meshes_reclaimed: usize,
+    pub materials_reclaimed: usize,
+    pub textures_reclaimed: usize,
+}
+
+impl CompactionReport {
+    pub fn reclaimed_anything(&self) -> bool {
+        self.vertices_reclaimed > 0
+            || self.indices_reclaimed > 0
+            || self.meshes_reclaimed > 0
+            || self.materials_reclaimed > 0
+            || self.textures_reclaimed > 0
+    }
+}
+
+// Describes how much of the world's geometry and texture storage is no longer referenced
+#[derive(Default, Debug, Copy, Clone)]
+pub struct Fragmentation {
+    pub used_vertices: usize,
+    pub total_vertices: usize,
+    pub used_textures: usize,
+    pub total_textures: usize,
+}
+
+impl Fragmentation {
+    // The fraction of storage that compaction would reclaim, from 0.0 to 1.0
+    pub fn wasted_ratio(&self) -> f32 {
+        let total = self.total_vertices + self.total_textures;
+        if total == 0 {
+            return 0.0;
+        }
+        let used = self.used_vertices + self.used_textures;
+        1.0 - (used as f32 / total as f32)
+    }
+}
+
+pub fn fragmentation(world: &World) -> Fragmentation {
+    let meshes = referenced_meshes(world);
+    let used_vertices = meshes
+        .iter()
+        .filter_map(|name| world.assets.geometry.meshes.get(name))
+        .flat_map(|mesh| mesh.primitives.iter())
+        .map(|primitive| primitive.number_of_vertices)
+        .sum();
+    let used_textures = referenced_textures(world, &referenced_materials(world, &meshes)).len();
+    Fragmentation {
+        used_vertices,
+        total_vertices: world.assets.geometry.vertices.len(),
+        used_textures,
+        total_textures: world.assets.textures.len(),
+    }
+}
+
+// Repacks geometry, materials, and textures so that only data referenced by entities remains.
+// Primitive offsets, material indices, and texture indices are rewritten to match.
+pub fn compact_world(world: &mut World) -> Result<CompactionReport> {
+    let meshes = referenced_meshes(world);
+    let materials = referenced_materials(world, &meshes);
+    let textures = referenced_textures(world, &materials);
+
+    let mut report = CompactionReport {
+        vertices_reclaimed: world.assets.geometry.vertices.len(),
+        indices_reclaimed: world.assets.geometry.indices.len(),
+        meshes_reclaimed: world.assets.geometry.meshes.len(),
+        materials_reclaimed: world.assets.materials.len(),
+        textures_reclaimed: world.assets.textures.len(),
+    };
+
+    let texture_map = compact_textures(&mut world.assets.textures, &textures);
+    remap_component_textures(world, &texture_map);
+    let material_map = compact_materials(&mut world.assets.materials, &materials, &texture_map);
+
+    let geometry = &mut world.assets.geometry;
+    geometry.meshes.retain(|name, _| meshes.contains(name));
+
+    // Sorting keeps the packed layout deterministic between runs
+    let mut names = geometry.meshes.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for name in names.iter() {
+        let mesh = match geometry.meshes.get_mut(name) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        repack_mesh(
+            mesh,
+            &geometry.vertices,
+            &geometry.indices,
+            &mut vertices,
+            &mut indices,
+            &material_map,
+        );
+    }
+    geometry.vertices = vertices;
+    geometry.indices = indices;
+    geometry.mark_rewritten();
+
+    report.vertices_reclaimed -= world.assets.geometry.vertices.len();
+    report.indices_reclaimed -= world.assets.geometry.indices.len();
+    report.meshes_reclaimed -= world.assets.geometry.meshes.len();
+    report.materials_reclaimed -= world.assets.materials.len();
+    report.textures_reclaimed -= world.assets.textures.len();
+
+    Ok(report)
+}
+
+fn repack_mesh(
+    mesh: &mut Mesh,
+    old_vertices: &[Vertex],
+    old_indices: &[u32],
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    material_map: &HashMap<usize, usize>,
+) {
+    for primitive in mesh.primitives.iter_mut() {
+        let first_vertex = vertices.len();
+        let first_index = indices.len();
+
+        let old_first_vertex = primitive.first_vertex;
+        vertices.extend_from_slice(
+            &old_vertices[old_first_vertex..old_first_vertex + primitive.number_of_vertices],
+        );
+
+        let old_first_index = primitive.first_index;
+        indices.extend(
+            old_indices[old_first_index..old_first_index + primitive.number_of_indices]
+                .iter()
+                .map(|index| index - old_first_vertex as u32 + first_vertex as u32),
+        );
+
+        primitive.first_vertex = first_vertex;
+        primitive.first_index = first_index;
+        primitive.material_index = primitive
+            .material_index
+            .and_then(|index| material_map.get(&index).copied());
+    }
+}
+
+fn compact_textures(textures: &mut Vec<Texture>, used: &HashSet<usize>) -> HashMap<i32, i32> {
+    let mut texture_map = HashMap::new();
+    let mut compacted = Vec::new();
+    for (index, texture) in textures.drain(..).enumerate() {
+        if used.contains(&index) {
+            texture_map.insert(index as i32, compacted.len() as i32);
+            compacted.push(texture);
+        }
+    }
+    *textures = compacted;
+    texture_map
+}
+
+// Components that draw a texture directly rather than through a material
+fn remap_component_textures(world: &mut World, texture_map: &HashMap<i32, i32>) {
+    let remap = |value: &mut i32| {
+        if *value != -1 {
+            *value = texture_map.get(value).copied().unwrap_or(-1);
+        }
+    };
+    let ecs = &mut world.sim.ecs;
+    for emitter in <&mut ParticleEmitter>::query().iter_mut(ecs) {
+        remap(&mut emitter.texture_index);
+    }
+    for sprite in <&mut Sprite>::query().iter_mut(ecs) {
+        remap(&mut sprite.texture_index);
+    }
+    for billboard in <&mut Billboard>::query().iter_mut(ecs) {
+        remap(&mut billboard.texture_index);
+    }
+}
+
+fn compact_materials(
+    materials: &mut Vec<Material>,
+    used: &HashSet<usize>,
+    texture_map: &HashMap<i32, i32>,
+) -> HashMap<usize, usize> {
+    let remap = |value: &mut i32| {
+        if *value != -1 {
+            *value = texture_map.get(value).copied().unwrap_or(-1);
+        }
+    };
+
+    let mut material_map = HashMap::new();
+    let mut compacted = Vec::new();
+    for (index, mut material) in materials.drain(..).enumerate() {
+        if !used.contains(&index) {
+            continue;
+        }
+        for index in material.texture_indices_mut() {
+            remap(index);
+        }
+        material_map.insert(index, compacted.len());
+        compacted.push(material);
+    }
+    *materials = compacted;
+    material_map
+}
+
+// Every level of detail is kept, not only the level currently drawn
+fn referenced_meshes(world: &World) -> HashSet<String> {
+    let mut query = <&MeshRender>::query();
+    let mut meshes = query
+        .iter(&world.sim.ecs)
+        .map(|mesh_render| mesh_render.name.to_string())
+        .collect::<HashSet<_>>();
+    let mut query = <&Lod>::query();
+    meshes.extend(
+        query
+            .iter(&world.sim.ecs)
+            .flat_map(|lod| lod.levels.iter().map(|level| level.mesh.to_string())),
+    );
+    meshes
+}
+
+fn referenced_materials(world: &World, meshes: &HashSet<String>) -> HashSet<usize> {
+    meshes
+        .iter()
+        .filter_map(|name| world.assets.geometry.meshes.get(name))
+        .flat_map(|mesh| mesh.primitives.iter())
+        .filter_map(|primitive| primitive.material_index)
+        .collect()
+}
+
+// Includes the textures that particles, sprites, and billboards draw directly
+fn referenced_textures(world: &World, materials: &HashSet<usize>) -> HashSet<usize> {
+    let ecs = &world.sim.ecs;
+    materials
+        .iter()
+        .filter_map(|index| world.assets.materials.get(*index))
+        .flat_map(|material| material.texture_indices())
+        .chain(
+            <&ParticleEmitter>::query()
+                .iter(ecs)
+                .map(|emitter| emitter.texture_index),
+        )
+        .chain(
+            <&Sprite>::query()
+                .iter(ecs)
+                .map(|sprite| sprite.texture_index),
+        )
+        .chain(
+            <&Billboard>::query()
+                .iter(ecs)
+                .map(|billboard| billboard.texture_index),
+        )
+        .filter(|index| *index >= 0)
+        .map(|index| index as usize)
+        .collect()
+}
@@ -0,0 +1,271 @@
+use crate::{Format, Sampler, Texture};
+use anyhow::{anyhow, bail, ensure, Result};
+use image::{imageops, imageops::FilterType, ImageBuffer, RgbaImage};
+use intel_tex_2::{bc7, RgbaSurface};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// How imported textures are stored.
+// BC7 takes a quarter of the memory of RGBA8, at the cost of some quality and a slower import.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureCompression {
+    None,
+    Bc7,
+}
+
+impl Default for TextureCompression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl Texture {
+    // Loads a 2D ktx2 texture along with all of its mip levels.
+    // Basis Universal ktx2 files have no gpu format of their own, so they need to be loaded as .basis files instead.
+    pub fn from_ktx2(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path.as_ref())?;
+        let reader = ktx2::Reader::new(&data[..])
+            .map_err(|error| anyhow!("Failed to read ktx2 file: {:?}", error))?;
+        let header = reader.header();
+        ensure!(
+            header.face_count <= 1 && header.layer_count <= 1 && header.pixel_depth <= 1,
+            "Only 2D ktx2 textures are supported!"
+        );
+
+        let format = match header.format {
+            Some(format) => map_ktx2_format(format)?,
+            None => {
+                bail!("Basis Universal ktx2 textures are not supported, use a .basis file instead!")
+            }
+        };
+
+        let mut pixels = Vec::new();
+        for level in reader.levels() {
+            match header.supercompression_scheme {
+                None => pixels.extend_from_slice(level),
+                Some(scheme) if scheme == ktx2::SupercompressionScheme::Zstandard => {
+                    pixels.extend(zstd::stream::decode_all(level)?)
+                }
+                Some(scheme) => bail!("Unsupported ktx2 supercompression scheme: {:?}", scheme),
+            }
+        }
+
+        let texture = Self {
+            pixels,
+            format,
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            // Zero levels asks the loader to generate them, which the gpu does for uncompressed formats
+            mip_levels: header.level_count.max(1),
+            sampler: Sampler::default(),
+        };
+        texture.ensure_complete()?;
+        Ok(texture)
+    }
+
+    // Transcodes the first image of a Basis Universal file, along with its mips
+    #[cfg(feature = "basisu")]
+    pub fn from_basis(path: impl AsRef<Path>, compression: TextureCompression) -> Result<Self> {
+        use basis_universal::{TranscodeParameters, Transcoder, TranscoderTextureFormat};
+
+        let data = std::fs::read(path.as_ref())?;
+        basis_universal::transcoder_init();
+        let mut transcoder = Transcoder::new();
+        transcoder
+            .prepare_transcoding(&data)
+            .map_err(|_| anyhow!("Failed to prepare basis file for transcoding!"))?;
+
+        let (transcoder_format, format) = match compression {
+            TextureCompression::None => (TranscoderTextureFormat::RGBA32, Format::R8G8B8A8),
+            TextureCompression::Bc7 => (TranscoderTextureFormat::BC7_RGBA, Format::Bc7),
+        };
+
+        let description = transcoder
+            .image_level_description(&data, 0, 0)
+            .ok_or_else(|| anyhow!("Failed to read the base level of the basis file!"))?;
+        let mip_levels = transcoder.image_level_count(&data, 0).max(1);
+
+        let mut pixels = Vec::new();
+        for level_index in 0..mip_levels {
+            let parameters = TranscodeParameters {
+                image_index: 0,
+                level_index,
+                ..Default::default()
+            };
+            let level = transcoder
+                .transcode_image_level(&data, transcoder_format, parameters)
+                .map_err(|error| {
+                    anyhow!(
+                        "Failed to transcode basis level {}: {:?}",
+                        level_index,
+                        error
+                    )
+                })?;
+            pixels.extend(level);
+        }
+        transcoder.end_transcoding();
+
+        let texture = Self {
+            pixels,
+            format,
+            width: description.original_width,
+            height: description.original_height,
+            mip_levels,
+            sampler: Sampler::default(),
+        };
+        texture.ensure_complete()?;
+        Ok(texture)
+    }
+
+    // Replaces an 8-bit texture with a block compressed one, along with a full chain of mips generated on the cpu.
+    // Textures that are already compressed, provide their own mips, or use other formats are left as they are.
+    pub fn compress(&mut self, compression: TextureCompression) -> Result<()> {
+        if compression == TextureCompression::None || self.has_precomputed_mips() {
+            return Ok(());
+        }
+        let image = match self.to_rgba8() {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+
+        let settings = if image.pixels().any(|pixel| pixel[3] < u8::MAX) {
+            bc7::alpha_very_fast_settings()
+        } else {
+            bc7::opaque_very_fast_settings()
+        };
+
+        let levels = self.mip_chain(image);
+        self.mip_levels = levels.len() as u32;
+        self.pixels = levels
+            .iter()
+            .flat_map(|level| compress_bc7(level, &settings))
+            .collect();
+        self.format = Format::Bc7;
+        Ok(())
+    }
+
+    // Generates a full chain of mips on the cpu for an 8-bit texture with a single level,
+    // which is stored as RGBA8 so it no longer needs its mips generated on the gpu when it is loaded.
+    // Textures that provide their own mips or use other formats are left as they are.
+    pub fn generate_mips(&mut self) -> Result<()> {
+        if self.has_precomputed_mips() {
+            return Ok(());
+        }
+        let image = match self.to_rgba8() {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+        let levels = self.mip_chain(image);
+        self.mip_levels = levels.len() as u32;
+        self.pixels = levels
+            .into_iter()
+            .flat_map(|level| level.into_raw())
+            .collect();
+        self.format = Format::R8G8B8A8;
+        Ok(())
+    }
+
+    // Every level from the full size image down, matching the number of levels the gpu would have generated
+    fn mip_chain(&self, image: RgbaImage) -> Vec<RgbaImage> {
+        let mip_levels = ((self.width.min(self.height) as f32).log2().floor() + 1.0) as u32;
+        let mut levels = vec![image];
+        for level in 1..mip_levels {
+            let (width, height) = self.mip_dimensions(level);
+            let previous = &levels[levels.len() - 1];
+            let next = imageops::resize(previous, width, height, FilterType::Triangle);
+            levels.push(next);
+        }
+        levels
+    }
+
+    fn ensure_complete(&self) -> Result<()> {
+        let (width, height) = self.mip_dimensions(self.mip_levels - 1);
+        let expected = self.mip_offsets()[self.mip_levels as usize - 1]
+            + self.format.level_size(width, height);
+        ensure!(
+            self.pixels.len() >= expected,
+            "Texture has {} bytes of pixels, but its {} mip levels need {} bytes!",
+            self.pixels.len(),
+            self.mip_levels,
+            expected
+        );
+        Ok(())
+    }
+
+    fn to_rgba8(&self) -> Option<RgbaImage> {
+        let base = self
+            .pixels
+            .get(..self.format.level_size(self.width, self.height))?;
+        let pixels = match self.format {
+            Format::R8G8B8A8 => base.to_vec(),
+            Format::B8G8R8A8 => base
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+                .collect(),
+            Format::R8G8B8 => base
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+                .collect(),
+            Format::B8G8R8 => base
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], u8::MAX])
+                .collect(),
+            Format::R8G8 => base
+                .chunks_exact(2)
+                .flat_map(|pixel| [pixel[0], pixel[1], 0, u8::MAX])
+                .collect(),
+            Format::R8 => base
+                .iter()
+                .flat_map(|value| [*value, *value, *value, u8::MAX])
+                .collect(),
+            _ => return None,
+        };
+        RgbaImage::from_raw(self.width, self.height, pixels)
+    }
+}
+
+// Blocks along the right and bottom edges are padded out by repeating the last texel
+fn compress_bc7(image: &RgbaImage, settings: &bc7::EncodeSettings) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let padded_width = (width + 3) / 4 * 4;
+    let padded_height = (height + 3) / 4 * 4;
+    let padded: RgbaImage = ImageBuffer::from_fn(padded_width, padded_height, |x, y| {
+        *image.get_pixel(x.min(width - 1), y.min(height - 1))
+    });
+    let surface = RgbaSurface {
+        data: padded.as_raw(),
+        width: padded_width,
+        height: padded_height,
+        stride: padded_width * 4,
+    };
+    bc7::compress_blocks(settings, &surface)
+}
+
+// sRGB formats are loaded the same as their linear counterparts,
+// matching how the other texture loaders treat color data
+fn map_ktx2_format(format: ktx2::Format) -> Result<Format> {
+    let formats = [
+        (ktx2::Format::R8_UNORM, Format::R8),
+        (ktx2::Format::R8G8_UNORM, Format::R8G8),
+        (ktx2::Format::R8G8B8A8_UNORM, Format::R8G8B8A8),
+        (ktx2::Format::R8G8B8A8_SRGB, Format::R8G8B8A8),
+        (ktx2::Format::B8G8R8A8_UNORM, Format::B8G8R8A8),
+        (ktx2::Format::B8G8R8A8_SRGB, Format::B8G8R8A8),
+        (ktx2::Format::R16G16B16A16_UNORM, Format::R16G16B16A16),
+        (ktx2::Format::R16G16B16A16_SFLOAT, Format::R16G16B16A16F),
+        (ktx2::Format::R32G32B32A32_SFLOAT, Format::R32G32B32A32F),
+        (ktx2::Format::BC1_RGBA_UNORM_BLOCK, Format::Bc1),
+        (ktx2::Format::BC1_RGBA_SRGB_BLOCK, Format::Bc1),
+        (ktx2::Format::BC3_UNORM_BLOCK, Format::Bc3),
+        (ktx2::Format::BC3_SRGB_BLOCK, Format::Bc3),
+        (ktx2::Format::BC4_UNORM_BLOCK, Format::Bc4),
+        (ktx2::Format::BC5_UNORM_BLOCK, Format::Bc5),
+        (ktx2::Format::BC7_UNORM_BLOCK, Format::Bc7),
+        (ktx2::Format::BC7_SRGB_BLOCK, Format::Bc7),
+    ];
+    formats
+        .iter()
+        .find(|(ktx2_format, _)| *ktx2_format == format)
+        .map(|(_, format)| *format)
+        .ok_or_else(|| anyhow!("Unsupported ktx2 texture format: {:?}", format))
+}
@@ -0,0 +1,37 @@
+use nalgebra_glm as glm;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DebugLine {
+    pub start: glm::Vec3,
+    pub end: glm::Vec3,
+    pub color: glm::Vec4,
+}
+
+// Lines in world space queued for a single frame, such as skeletons, rays, and bounds.
+// Apps draw and clear them once per frame, so anything that should stay visible is queued every frame.
+#[derive(Default, Debug)]
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, start: glm::Vec3, end: glm::Vec3, color: glm::Vec4) {
+        self.lines.push(DebugLine { start, end, color });
+    }
+
+    // Three short lines crossing at a point along each axis
+    pub fn cross(&mut self, center: glm::Vec3, size: f32, color: glm::Vec4) {
+        let half = size / 2.0;
+        for axis in [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()] {
+            self.line(center - axis * half, center + axis * half, color);
+        }
+    }
+
+    pub fn lines(&self) -> &[DebugLine] {
+        &self.lines
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
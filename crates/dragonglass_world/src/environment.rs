@@ -0,0 +1,358 @@
+use crate::{Format, Sampler, Texture};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use image::io::Reader as ImageReader;
+use nalgebra_glm as glm;
+use std::path::{Path, PathBuf};
+
+// The files skyboxes can be loaded from, dispatched on by file extension.
+// A folder holding the six faces of a cubemap can be loaded as well.
+pub const ENVIRONMENT_EXTENSIONS: &[&str] = &["hdr", "ktx2", "png", "jpg", "jpeg"];
+
+// The file stems each cubemap face is recognized by, in +X, -X, +Y, -Y, +Z, -Z order
+const FACE_NAMES: [&[&str]; 6] = [
+    &["px", "posx", "positive_x", "right"],
+    &["nx", "negx", "negative_x", "left"],
+    &["py", "posy", "positive_y", "top", "up"],
+    &["ny", "negy", "negative_y", "bottom", "down"],
+    &["pz", "posz", "positive_z", "front"],
+    &["nz", "negz", "negative_z", "back"],
+];
+
+// Cubemaps are unwrapped into an equirectangular map four faces wide,
+// which is capped to keep large cubemaps from taking up gigabytes as floats
+const MAX_EQUIRECTANGULAR_WIDTH: u32 = 4096;
+
+// Whether the path can be loaded as a skybox.
+// LDR images are only treated as environments when they have the 2:1 aspect of an equirectangular map,
+// so that heightmaps and regular textures sharing their extensions aren't mistaken for one.
+pub fn is_environment_file(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return FACE_NAMES
+            .iter()
+            .all(|names| find_face(path, names).is_ok());
+    }
+    match lowercase_extension(path).as_deref() {
+        Some("hdr") | Some("ktx2") => true,
+        Some(extension) if ENVIRONMENT_EXTENSIONS.contains(&extension) => {
+            is_equirectangular_image(path)
+        }
+        _ => false,
+    }
+}
+
+pub fn is_equirectangular_image(path: impl AsRef<Path>) -> bool {
+    image::image_dimensions(path).map_or(false, |(width, height)| width == height * 2)
+}
+
+impl Texture {
+    // Loads any supported environment as the equirectangular float texture skyboxes are generated from
+    pub fn from_environment(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return Self::from_cubemap_faces(path);
+        }
+        match lowercase_extension(path).as_deref() {
+            Some("hdr") => Self::from_hdr(path),
+            Some("ktx2") => Self::from_ktx2_cubemap(path),
+            _ => Self::from_ldr_equirectangular(path),
+        }
+    }
+
+    // LDR images are stored in srgb, so they are converted to linear to light the scene correctly
+    pub fn from_ldr_equirectangular(path: impl AsRef<Path>) -> Result<Self> {
+        let face = Face::from_ldr(path.as_ref())?;
+        Ok(linear_texture(face.width, face.height, &face.pixels))
+    }
+
+    // Loads a folder of six face images, named like 'px.png' or 'right.png'
+    pub fn from_cubemap_faces(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref();
+        let faces = FACE_NAMES
+            .iter()
+            .map(|names| {
+                let path = find_face(directory, names)?;
+                match lowercase_extension(&path).as_deref() {
+                    Some("hdr") => Face::from_texture(&Texture::from_hdr(&path)?),
+                    _ => Face::from_ldr(&path),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        cubemap_to_equirectangular(&faces)
+    }
+
+    // Only the base level of uncompressed cubemaps is read
+    pub fn from_ktx2_cubemap(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path.as_ref())?;
+        let reader = ktx2::Reader::new(&data[..])
+            .map_err(|error| anyhow!("Failed to read ktx2 file: {:?}", error))?;
+        let header = reader.header();
+        ensure!(
+            header.face_count == 6 && header.layer_count <= 1,
+            "Only ktx2 cubemaps can be loaded as environments!"
+        );
+
+        let level = reader
+            .levels()
+            .next()
+            .context("The ktx2 cubemap has no levels!")?;
+        let level = match header.supercompression_scheme {
+            None => level.to_vec(),
+            Some(scheme) if scheme == ktx2::SupercompressionScheme::Zstandard => {
+                zstd::stream::decode_all(level)?
+            }
+            Some(scheme) => bail!("Unsupported ktx2 supercompression scheme: {:?}", scheme),
+        };
+
+        let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+        let format = header
+            .format
+            .context("Basis Universal ktx2 cubemaps are not supported!")?;
+        let face_size = level.len() / 6;
+        let faces = level
+            .chunks_exact(face_size)
+            .map(|bytes| {
+                let pixels = decode_ktx2_pixels(format, bytes)?;
+                ensure!(
+                    pixels.len() == (width * height) as usize,
+                    "The ktx2 cubemap faces are smaller than its dimensions!"
+                );
+                Ok(Face {
+                    width,
+                    height,
+                    pixels,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        cubemap_to_equirectangular(&faces)
+    }
+}
+
+// A cubemap face or equirectangular image as linear colors
+struct Face {
+    width: u32,
+    height: u32,
+    pixels: Vec<glm::Vec4>,
+}
+
+impl Face {
+    fn from_ldr(path: &Path) -> Result<Self> {
+        let image = ImageReader::open(path)?.decode()?.to_rgba8();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                glm::vec4(
+                    srgb_to_linear(pixel[0]),
+                    srgb_to_linear(pixel[1]),
+                    srgb_to_linear(pixel[2]),
+                    1.0,
+                )
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn from_texture(texture: &Texture) -> Result<Self> {
+        ensure!(
+            texture.format == Format::R32G32B32A32F,
+            "Expected an rgba float texture!"
+        );
+        Ok(Self {
+            width: texture.width,
+            height: texture.height,
+            pixels: decode_ktx2_pixels(ktx2::Format::R32G32B32A32_SFLOAT, &texture.pixels)?,
+        })
+    }
+
+    // Bilinearly samples the face, with uvs in the zero to one range
+    fn sample(&self, u: f32, v: f32) -> glm::Vec4 {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (x.fract(), y.fract());
+        let texel = |x: u32, y: u32| self.pixels[(y * self.width + x) as usize];
+        let top = glm::lerp(&texel(x0, y0), &texel(x1, y0), tx);
+        let bottom = glm::lerp(&texel(x0, y1), &texel(x1, y1), tx);
+        glm::lerp(&top, &bottom, ty)
+    }
+}
+
+fn lowercase_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
+
+fn find_face(directory: &Path, names: &[&str]) -> Result<PathBuf> {
+    std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| {
+            let is_image = lowercase_extension(path).map_or(false, |extension| {
+                ENVIRONMENT_EXTENSIONS.contains(&extension.as_str()) && extension != "ktx2"
+            });
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            is_image && names.contains(&stem.as_str())
+        })
+        .with_context(|| {
+            format!(
+                "No cubemap face named any of {:?} in '{}'",
+                names,
+                directory.display()
+            )
+        })
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn decode_ktx2_pixels(format: ktx2::Format, bytes: &[u8]) -> Result<Vec<glm::Vec4>> {
+    let pixels = match format {
+        ktx2::Format::R8G8B8A8_SRGB => bytes
+            .chunks_exact(4)
+            .map(|texel| {
+                glm::vec4(
+                    srgb_to_linear(texel[0]),
+                    srgb_to_linear(texel[1]),
+                    srgb_to_linear(texel[2]),
+                    1.0,
+                )
+            })
+            .collect(),
+        ktx2::Format::R8G8B8A8_UNORM => bytes
+            .chunks_exact(4)
+            .map(|texel| {
+                glm::vec4(
+                    texel[0] as f32 / 255.0,
+                    texel[1] as f32 / 255.0,
+                    texel[2] as f32 / 255.0,
+                    1.0,
+                )
+            })
+            .collect(),
+        ktx2::Format::R16G16B16A16_SFLOAT => bytes
+            .chunks_exact(8)
+            .map(|texel| {
+                let channel = |index: usize| {
+                    f16_to_f32(u16::from_le_bytes([texel[index * 2], texel[index * 2 + 1]]))
+                };
+                glm::vec4(channel(0), channel(1), channel(2), 1.0)
+            })
+            .collect(),
+        ktx2::Format::R32G32B32A32_SFLOAT => bytes
+            .chunks_exact(16)
+            .map(|texel| {
+                let channel = |index: usize| {
+                    let mut channel = [0; 4];
+                    channel.copy_from_slice(&texel[index * 4..index * 4 + 4]);
+                    f32::from_le_bytes(channel)
+                };
+                glm::vec4(channel(0), channel(1), channel(2), 1.0)
+            })
+            .collect(),
+        format => bail!("Unsupported ktx2 cubemap format: {:?}", format),
+    };
+    Ok(pixels)
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = (bits as u32 >> 15) << 31;
+    let exponent = (bits as u32 >> 10) & 0x1f;
+    let mantissa = bits as u32 & 0x3ff;
+    match exponent {
+        0 => {
+            // Zero and subnormals
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            if sign == 0 {
+                magnitude
+            } else {
+                -magnitude
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+// Unwraps the faces using the same mapping the equirectangular to cubemap shader samples with
+fn cubemap_to_equirectangular(faces: &[Face]) -> Result<Texture> {
+    ensure!(faces.len() == 6, "A cubemap needs six faces!");
+    let size = faces[0].width;
+    ensure!(
+        faces
+            .iter()
+            .all(|face| face.width == size && face.height == size),
+        "Cubemap faces must all be square and the same size!"
+    );
+
+    let width = (size * 4).min(MAX_EQUIRECTANGULAR_WIDTH);
+    let height = width / 2;
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let theta = ((y as f32 + 0.5) / height as f32 - 0.5) * std::f32::consts::PI;
+        for x in 0..width {
+            let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+            let direction = glm::vec3(
+                theta.cos() * phi.cos(),
+                -theta.sin(),
+                theta.cos() * phi.sin(),
+            );
+            let (face, u, v) = cube_face_uv(&direction);
+            pixels.push(faces[face].sample(u, v));
+        }
+    }
+    Ok(linear_texture(width, height, &pixels))
+}
+
+// Picks the face a direction lands on and where, following the vulkan cubemap addressing rules
+fn cube_face_uv(direction: &glm::Vec3) -> (usize, f32, f32) {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, s, t, major) = if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z, -y, ax)
+        } else {
+            (1, z, -y, ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x, z, ay)
+        } else {
+            (3, x, -z, ay)
+        }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+    (face, (s / major + 1.0) * 0.5, (t / major + 1.0) * 0.5)
+}
+
+fn linear_texture(width: u32, height: u32, pixels: &[glm::Vec4]) -> Texture {
+    let pixels = pixels
+        .iter()
+        .flat_map(|pixel| pixel.iter().flat_map(|channel| channel.to_ne_bytes()))
+        .collect();
+    Texture {
+        pixels,
+        format: Format::R32G32B32A32F,
+        width,
+        height,
+        mip_levels: 1,
+        sampler: Sampler::default(),
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{
+    AlphaMode, ImportedMesh, ImportedModel, ImportedNode, Material, Texture, Transform, Vertex,
+};
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use nalgebra_glm as glm;
+use russimp::{
+    material::{PropertyTypeInfo, TextureType},
+    node::Node,
+    scene::{PostProcess, Scene},
+};
+use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc};
+
+impl ImportedModel {
+    // Imports the node hierarchy, meshes, and basic materials through assimp.
+    // Skinning and animations are not imported.
+    pub fn from_fbx(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let path_str = path
+            .to_str()
+            .context("Fbx file paths must be valid unicode!")?;
+        let scene = Scene::from_file(
+            path_str,
+            vec![
+                PostProcess::Triangulate,
+                PostProcess::GenerateSmoothNormals,
+                PostProcess::JoinIdenticalVertices,
+                PostProcess::FlipUVs,
+            ],
+        )
+        .map_err(|error| anyhow!("Failed to import fbx file: {:?}", error))?;
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut model = Self::default();
+        let mut texture_indices = HashMap::new();
+        for fbx_material in scene.materials.iter() {
+            let mut material = map_fbx_material(fbx_material);
+            if let Some(file) = material_texture(fbx_material, TextureType::Diffuse) {
+                let index = *texture_indices.entry(file.to_string()).or_insert_with(|| {
+                    match Texture::from_file(directory.join(&file)) {
+                        Ok(texture) => {
+                            model.textures.push(texture);
+                            model.textures.len() as i32 - 1
+                        }
+                        Err(error) => {
+                            warn!("Failed to load fbx texture '{}': {}", file, error);
+                            -1
+                        }
+                    }
+                });
+                if index != -1 {
+                    material.color_texture_index = index;
+                    material.color_texture_set = 0;
+                }
+            }
+            model.materials.push(material);
+        }
+
+        // Meshes referring to materials the scene doesn't have are left without one
+        let number_of_materials = model.materials.len();
+        model.meshes = scene
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let mut mesh = map_fbx_mesh(mesh);
+                mesh.material_index = mesh
+                    .material_index
+                    .filter(|index| *index < number_of_materials);
+                mesh
+            })
+            .collect();
+
+        if let Some(root) = scene.root.as_ref() {
+            add_node(&mut model, root, None);
+        }
+
+        Ok(model)
+    }
+}
+
+fn add_node(model: &mut ImportedModel, node: &Rc<RefCell<Node>>, parent: Option<usize>) {
+    let node = node.borrow();
+    let mut imported_node = ImportedNode::new(node.name.to_string());
+    imported_node.transform = Transform::from(map_matrix(&node.transformation));
+    imported_node.meshes = node.meshes.iter().map(|index| *index as usize).collect();
+    imported_node.parent = parent;
+    let index = model.nodes.len();
+    model.nodes.push(imported_node);
+    for child in node.children.iter() {
+        add_node(model, child, Some(index));
+    }
+}
+
+// Assimp matrices are row major
+fn map_matrix(matrix: &russimp::Matrix4x4) -> glm::Mat4 {
+    glm::mat4(
+        matrix.a1, matrix.a2, matrix.a3, matrix.a4, matrix.b1, matrix.b2, matrix.b3, matrix.b4,
+        matrix.c1, matrix.c2, matrix.c3, matrix.c4, matrix.d1, matrix.d2, matrix.d3, matrix.d4,
+    )
+}
+
+fn map_fbx_mesh(mesh: &russimp::mesh::Mesh) -> ImportedMesh {
+    let uvs = mesh.texture_coords.get(0).and_then(|uvs| uvs.as_ref());
+    let colors = mesh.colors.get(0).and_then(|colors| colors.as_ref());
+    let vertices = mesh
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(index, position)| Vertex {
+            position: glm::vec3(position.x, position.y, position.z),
+            normal: mesh
+                .normals
+                .get(index)
+                .map_or(glm::Vec3::zeros(), |normal| {
+                    glm::vec3(normal.x, normal.y, normal.z)
+                }),
+            uv_0: uvs
+                .and_then(|uvs| uvs.get(index))
+                .map_or(glm::Vec2::zeros(), |uv| glm::vec2(uv.x, uv.y)),
+            color_0: colors
+                .and_then(|colors| colors.get(index))
+                .map_or(glm::vec3(1.0, 1.0, 1.0), |color| {
+                    glm::vec3(color.r, color.g, color.b)
+                }),
+            ..Default::default()
+        })
+        .collect();
+    // Points and lines are left in faces with fewer indices after triangulating, which aren't drawn
+    let indices = mesh
+        .faces
+        .iter()
+        .filter(|face| face.0.len() == 3)
+        .flat_map(|face| face.0.iter().copied())
+        .collect();
+    ImportedMesh {
+        name: mesh.name.to_string(),
+        vertices,
+        indices,
+        material_index: Some(mesh.material_index as usize),
+    }
+}
+
+fn map_fbx_material(fbx_material: &russimp::material::Material) -> Material {
+    let mut material = Material {
+        emissive_factor: glm::Vec3::zeros(),
+        metallic_factor: 0.0,
+        ..Default::default()
+    };
+    for property in fbx_material.properties.iter() {
+        match (property.key.as_str(), &property.data) {
+            ("?mat.name", PropertyTypeInfo::String(name)) => material.name = name.to_string(),
+            ("$clr.diffuse", PropertyTypeInfo::FloatArray(color)) if color.len() >= 3 => {
+                material.base_color_factor.x = color[0];
+                material.base_color_factor.y = color[1];
+                material.base_color_factor.z = color[2];
+            }
+            ("$clr.emissive", PropertyTypeInfo::FloatArray(color)) if color.len() >= 3 => {
+                material.emissive_factor = glm::vec3(color[0], color[1], color[2]);
+            }
+            ("$mat.opacity", PropertyTypeInfo::FloatArray(opacity)) if !opacity.is_empty() => {
+                material.base_color_factor.w = opacity[0];
+                if opacity[0] < 1.0 {
+                    material.alpha_mode = AlphaMode::Blend;
+                }
+            }
+            // The same Blinn-Phong conversion used for obj materials
+            ("$mat.shininess", PropertyTypeInfo::FloatArray(shininess))
+                if !shininess.is_empty() =>
+            {
+                material.roughness_factor = (2.0 / (shininess[0].max(0.0) + 2.0)).sqrt();
+            }
+            _ => {}
+        }
+    }
+    material
+}
+
+fn material_texture(
+    fbx_material: &russimp::material::Material,
+    texture_type: TextureType,
+) -> Option<String> {
+    fbx_material
+        .properties
+        .iter()
+        .filter(|property| property.key == "$tex.file" && property.semantic == texture_type)
+        .find_map(|property| match &property.data {
+            PropertyTypeInfo::String(file) => Some(file.to_string()),
+            _ => None,
+        })
+}
@@ -0,0 +1,347 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Mesh {
+    pub name: String,
+    pub primitives: Vec<Primitive>,
+    pub weights: Vec<f32>,
+}
+
+impl Mesh {
+    pub fn bounding_box(&self) -> BoundingBox {
+        let mut bounding_box = BoundingBox::new_invalid();
+        self.primitives
+            .iter()
+            .map(|primitive| &primitive.bounding_box)
+            .for_each(|primitive_bounding_box| bounding_box.fit_box(primitive_bounding_box));
+        bounding_box
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl BoundingBox {
+    pub fn new_invalid() -> Self {
+        Self {
+            min: glm::vec3(f32::MAX, f32::MAX, f32::MAX),
+            max: glm::vec3(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+
+    pub fn new(min: glm::Vec3, max: glm::Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn extents(&self) -> glm::Vec3 {
+        glm::abs(&(self.max - self.min))
+    }
+
+    pub fn half_extents(&self) -> glm::Vec3 {
+        self.extents() / 2.0
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        self.min + self.half_extents()
+    }
+
+    pub fn fit_box(&mut self, bounding_box: &Self) {
+        self.fit_point(bounding_box.min);
+        self.fit_point(bounding_box.max);
+    }
+
+    pub fn fit_point(&mut self, point: glm::Vec3) {
+        self.min.x = f32::min(self.min.x, point.x);
+        self.min.y = f32::min(self.min.y, point.y);
+        self.min.z = f32::min(self.min.z, point.z);
+
+        self.max.x = f32::max(self.max.x, point.x);
+        self.max.y = f32::max(self.max.y, point.y);
+        self.max.z = f32::max(self.max.z, point.z);
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.min.x <= self.max.x && self.min.y <= self.max.y && self.min.z <= self.max.z
+    }
+
+    pub fn corners(&self) -> [glm::Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            glm::vec3(min.x, min.y, min.z),
+            glm::vec3(min.x, min.y, max.z),
+            glm::vec3(min.x, max.y, min.z),
+            glm::vec3(min.x, max.y, max.z),
+            glm::vec3(max.x, min.y, min.z),
+            glm::vec3(max.x, min.y, max.z),
+            glm::vec3(max.x, max.y, min.z),
+            glm::vec3(max.x, max.y, max.z),
+        ]
+    }
+
+    // The axis aligned box around this box after it is transformed, such as into world space
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Self {
+        let mut bounding_box = Self::new_invalid();
+        for corner in self.corners().iter() {
+            let corner = matrix * glm::vec4(corner.x, corner.y, corner.z, 1.0);
+            bounding_box.fit_point(corner.xyz());
+        }
+        bounding_box
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    // The box both boxes cover, which is invalid when they don't overlap
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self::new(
+            glm::max2(&self.min, &other.min),
+            glm::min2(&self.max, &other.max),
+        )
+    }
+
+    pub fn volume(&self) -> f32 {
+        let extents = self.extents();
+        extents.x * extents.y * extents.z
+    }
+
+    pub fn intersects_sphere(&self, center: &glm::Vec3, radius: f32) -> bool {
+        let closest = glm::clamp_vec(center, &self.min, &self.max);
+        glm::distance2(&closest, center) <= radius * radius
+    }
+
+    pub fn contains_point(&self, point: &glm::Vec3) -> bool {
+        (0..3).all(|axis| point[axis] >= self.min[axis] && point[axis] <= self.max[axis])
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Primitive {
+    pub first_vertex: usize,
+    pub first_index: usize,
+    pub number_of_vertices: usize,
+    pub number_of_indices: usize,
+    pub material_index: Option<usize>,
+    pub morph_targets: Vec<MorphTarget>,
+    pub bounding_box: BoundingBox,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MorphTarget {
+    pub positions: Vec<glm::Vec4>,
+    pub normals: Vec<glm::Vec4>,
+    pub tangents: Vec<glm::Vec4>,
+}
+
+impl MorphTarget {
+    pub fn total_length(&self) -> usize {
+        self.positions.len() + self.normals.len() + self.tangents.len()
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Geometry {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub meshes: HashMap<String, Mesh>,
+    // Bumped whenever existing vertices or indices are replaced rather than appended to,
+    // so renderers know to upload them again even if the counts are unchanged
+    #[serde(skip)]
+    generation: u64,
+}
+
+impl Geometry {
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.mark_rewritten();
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Call after replacing vertices or indices that were already there
+    pub fn mark_rewritten(&mut self) {
+        self.generation += 1;
+    }
+
+    // Appends a mesh with a single primitive, returning the name it was stored under.
+    // Indices are relative to the mesh's own vertices.
+    // Tangents are generated unless the vertices provide their own.
+    pub fn add_mesh(
+        &mut self,
+        name: &str,
+        vertices: &[Vertex],
+        indices: &[u32],
+        material_index: Option<usize>,
+    ) -> String {
+        let first_vertex = self.vertices.len();
+        let first_index = self.indices.len();
+
+        let mut bounding_box = BoundingBox::new_invalid();
+        vertices
+            .iter()
+            .for_each(|vertex| bounding_box.fit_point(vertex.position));
+
+        self.vertices.extend_from_slice(vertices);
+        self.indices
+            .extend(indices.iter().map(|index| index + first_vertex as u32));
+        if vertices
+            .iter()
+            .all(|vertex| vertex.tangent == glm::Vec4::zeros())
+        {
+            generate_tangents(&mut self.vertices[first_vertex..], indices);
+        }
+
+        let primitive = Primitive {
+            first_vertex,
+            first_index,
+            number_of_vertices: vertices.len(),
+            number_of_indices: indices.len(),
+            material_index,
+            morph_targets: Vec::new(),
+            bounding_box,
+        };
+
+        let name = self.unique_mesh_name(name);
+        self.meshes.insert(
+            name.to_string(),
+            Mesh {
+                name: name.to_string(),
+                primitives: vec![primitive],
+                weights: Vec::new(),
+            },
+        );
+        name
+    }
+
+    // Geometry at the end of the buffers is reclaimed right away, and the rest is left for compaction
+    pub fn remove_mesh(&mut self, name: &str) -> Option<Mesh> {
+        let mesh = self.meshes.remove(name)?;
+        for primitive in mesh.primitives.iter().rev() {
+            if primitive.first_vertex + primitive.number_of_vertices == self.vertices.len() {
+                self.vertices.truncate(primitive.first_vertex);
+            }
+            if primitive.first_index + primitive.number_of_indices == self.indices.len() {
+                self.indices.truncate(primitive.first_index);
+            }
+        }
+        // Geometry added in its place can match the old counts
+        self.mark_rewritten();
+        Some(mesh)
+    }
+
+    // Numbers the name if a mesh is already stored under it
+    pub fn unique_mesh_name(&self, name: &str) -> String {
+        let mut unique_name = name.to_string();
+        let mut number = 1;
+        while self.meshes.contains_key(&unique_name) {
+            unique_name = format!("{} ({})", name, number);
+            number += 1;
+        }
+        unique_name
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Vertex {
+    pub position: glm::Vec3,
+    pub normal: glm::Vec3,
+    pub uv_0: glm::Vec2,
+    pub uv_1: glm::Vec2,
+    pub joint_0: glm::Vec4,
+    pub weight_0: glm::Vec4,
+    pub color_0: glm::Vec3,
+    // The direction uv_0's u coordinate increases in, with the handedness of the bitangent in w.
+    // Zeroed tangents are treated as missing, and normal maps fall back to screen space derivatives.
+    pub tangent: glm::Vec4,
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Self {
+            position: glm::Vec3::default(),
+            normal: glm::Vec3::default(),
+            uv_0: glm::Vec2::default(),
+            uv_1: glm::Vec2::default(),
+            joint_0: glm::Vec4::default(),
+            weight_0: glm::Vec4::default(),
+            color_0: glm::vec3(1.0, 1.0, 1.0),
+            tangent: glm::Vec4::default(),
+        }
+    }
+}
+
+// Generates mikktspace tangents from the normals and first uv set of a list of triangles.
+// Indices are relative to the vertices, and vertices without indices are treated as a list of triangles.
+// Vertices shared between triangles keep the tangent of the last triangle using them.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut mesh = TangentMesh { vertices, indices };
+    if !mikktspace::generate_tangents(&mut mesh) {
+        mesh.vertices
+            .iter_mut()
+            .for_each(|vertex| vertex.tangent = glm::Vec4::zeros());
+    }
+}
+
+struct TangentMesh<'a> {
+    vertices: &'a mut [Vertex],
+    indices: &'a [u32],
+}
+
+impl TangentMesh<'_> {
+    fn index(&self, face: usize, vertex: usize) -> usize {
+        let offset = face * 3 + vertex;
+        if self.indices.is_empty() {
+            offset
+        } else {
+            self.indices[offset] as usize
+        }
+    }
+
+    fn vertex(&self, face: usize, vertex: usize) -> &Vertex {
+        &self.vertices[self.index(face, vertex)]
+    }
+}
+
+impl mikktspace::Geometry for TangentMesh<'_> {
+    fn num_faces(&self) -> usize {
+        if self.indices.is_empty() {
+            self.vertices.len() / 3
+        } else {
+            self.indices.len() / 3
+        }
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vertex: usize) -> [f32; 3] {
+        self.vertex(face, vertex).position.into()
+    }
+
+    fn normal(&self, face: usize, vertex: usize) -> [f32; 3] {
+        self.vertex(face, vertex).normal.into()
+    }
+
+    fn tex_coord(&self, face: usize, vertex: usize) -> [f32; 2] {
+        self.vertex(face, vertex).uv_0.into()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vertex: usize) {
+        let index = self.index(face, vertex);
+        self.vertices[index].tangent = glm::Vec4::from(tangent);
+    }
+}
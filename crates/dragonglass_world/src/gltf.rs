@@ -1,14 +1,16 @@
 use crate::{
-    AlphaMode, Animation, BoundingBox, Camera, Channel, Ecs, Entity, Filter, Format, Geometry,
-    Interpolation, Joint, Light, LightKind, Material, Mesh, MeshRender, MorphTarget, Name,
-    OrthographicCamera, PerspectiveCamera, Primitive, Projection, Sampler, Scene, SceneGraph, Skin,
-    Texture, Transform, TransformationSet, Vertex, World, WrappingMode,
+    generate_tangents, AlphaMode, AmbientLight, Animation, BoundingBox, Camera, Channel, Ecs,
+    Entity, Filter, Format, Geometry, Interpolation, Joint, Layers, Light, LightKind, Material,
+    Mesh, MeshRender, MorphTarget, Name, OrthographicCamera, PerspectiveCamera, Primitive,
+    Projection, Sampler, Scene, SceneGraph, ShadingModel, Skin, Texture, TextureCompression,
+    Transform, TransformationSet, Vertex, World, WrappingMode,
 };
 use anyhow::{Context, Result};
 use gltf::animation::util::ReadOutputs;
 use legion::EntityStore;
 use nalgebra_glm as glm;
 use petgraph::prelude::*;
+use serde::Deserialize;
 use std::path::Path;
 
 pub fn create_scene_graph(node: &gltf::Node, ecs: &mut Ecs, entities: &[Entity]) -> SceneGraph {
@@ -46,13 +48,113 @@ fn node_transform(node: &gltf::Node) -> Transform {
 
 const DEFAULT_NAME: &str = "<Unnamed>";
 
+pub struct GltfAsset {
+    pub document: gltf::Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+    pub images: Vec<gltf::image::Data>,
+    // Indexed the same as the document's materials
+    pub material_extensions: Vec<MaterialExtensions>,
+}
+
+impl GltfAsset {
+    pub fn import(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        // The file is read once, and the extensions gltf doesn't parse are read from the same bytes
+        let bytes = std::fs::read(path)?;
+        let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(&bytes)?;
+        let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+        let images = gltf::import_images(&document, path.parent(), &buffers)?;
+        let material_extensions =
+            MaterialExtensions::from_slice(&bytes, document.materials().len())?;
+        Ok(Self {
+            document,
+            buffers,
+            images,
+            material_extensions,
+        })
+    }
+}
+
+// Material extensions the gltf crate doesn't parse, which are read from the document's json instead
+#[derive(Default, Debug, Clone, Deserialize)]
+pub struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_clearcoat")]
+    pub clearcoat: Option<ClearcoatExtension>,
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    pub emissive_strength: Option<EmissiveStrengthExtension>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearcoatExtension {
+    #[serde(default)]
+    pub clearcoat_factor: f32,
+    #[serde(default)]
+    pub clearcoat_roughness_factor: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmissiveStrengthExtension {
+    #[serde(default = "EmissiveStrengthExtension::default_strength")]
+    pub emissive_strength: f32,
+}
+
+impl EmissiveStrengthExtension {
+    fn default_strength() -> f32 {
+        1.0
+    }
+}
+
+impl MaterialExtensions {
+    fn from_slice(bytes: &[u8], number_of_materials: usize) -> Result<Vec<Self>> {
+        #[derive(Default, Deserialize)]
+        struct MaterialJson {
+            #[serde(default)]
+            extensions: MaterialExtensions,
+        }
+
+        #[derive(Deserialize)]
+        struct DocumentJson {
+            #[serde(default)]
+            materials: Vec<MaterialJson>,
+        }
+
+        let json = if bytes.starts_with(b"glTF") {
+            gltf::Glb::from_slice(bytes)?.json
+        } else {
+            bytes.into()
+        };
+        let document: DocumentJson = serde_json::from_slice(&json)?;
+        let mut extensions = document
+            .materials
+            .into_iter()
+            .map(|material| material.extensions)
+            .collect::<Vec<_>>();
+        extensions.resize_with(number_of_materials, Self::default);
+        Ok(extensions)
+    }
+}
+
 pub fn load_gltf(path: impl AsRef<Path>, world: &mut World) -> Result<()> {
-    let (gltf, buffers, images) = gltf::import(path)?;
+    let asset = GltfAsset::import(path)?;
+    load_gltf_asset(&asset, world)?;
+    Ok(())
+}
+
+// Returns the entities that were created for the asset's nodes
+pub fn load_gltf_asset(asset: &GltfAsset, world: &mut World) -> Result<Vec<Entity>> {
+    let GltfAsset {
+        document: gltf,
+        buffers,
+        images,
+        material_extensions,
+    } = asset;
 
-    let number_of_materials = world.materials.len();
+    let number_of_materials = world.assets.materials.len();
 
-    let number_of_textures = world.textures.len();
-    let mut materials = load_materials(&gltf)?;
+    let number_of_textures = world.assets.textures.len();
+    let mut materials = load_materials(gltf, material_extensions)?;
     materials.iter_mut().for_each(|material| {
         let increment = |value: &mut i32| {
             if *value != -1_i32 {
@@ -67,31 +169,40 @@ pub fn load_gltf(path: impl AsRef<Path>, world: &mut World) -> Result<()> {
     });
     materials
         .into_iter()
-        .for_each(|material| world.materials.push(material));
+        .for_each(|material| world.assets.materials.push(material));
 
-    load_textures(&gltf, &images)?
+    load_textures(gltf, images, world.assets.texture_compression)?
         .into_iter()
-        .for_each(|texture| world.textures.push(texture));
+        .for_each(|texture| world.assets.textures.push(texture));
 
     let entities = world
+        .sim
         .ecs
         .extend((0..gltf.nodes().len()).map(|_| ()))
         .to_vec();
 
-    load_animations(&gltf, &buffers, &entities)?
-        .into_iter()
-        .for_each(|node| world.animations.push(node));
+    // The model's first clip plays, and the rest wait to be chosen
+    let mut animations = load_animations(gltf, buffers, &entities)?;
+    if let Some(animation) = animations.first_mut() {
+        animation.playing = true;
+    }
+    world.assets.animations.extend(animations);
 
     load_nodes(
-        &gltf,
-        &buffers,
-        &mut world.ecs,
-        &mut world.geometry,
+        gltf,
+        buffers,
+        &mut world.sim.ecs,
+        &mut world.assets.geometry,
         &entities,
     )?;
 
     for entity in entities.iter() {
-        if let Ok(mesh) = world.ecs.entry_mut(*entity)?.get_component_mut::<Mesh>() {
+        if let Ok(mesh) = world
+            .sim
+            .ecs
+            .entry_mut(*entity)?
+            .get_component_mut::<Mesh>()
+        {
             mesh.primitives.iter_mut().for_each(|primitive| {
                 if let Some(material_index) = primitive.material_index.as_mut() {
                     *material_index += number_of_materials
@@ -101,14 +212,14 @@ pub fn load_gltf(path: impl AsRef<Path>, world: &mut World) -> Result<()> {
     }
 
     // Only merge default scene
-    let new_scenes = load_scenes(&gltf, &mut world.ecs, &entities);
+    let new_scenes = load_scenes(gltf, &mut world.sim.ecs, &entities);
     if let Some(new_scene) = new_scenes.into_iter().next() {
         new_scene.graphs.into_iter().for_each(|graph| {
-            world.scene.graphs.push(graph);
+            world.sim.scene.graphs.push(graph);
         });
     }
 
-    Ok(())
+    Ok(entities)
 }
 
 fn load_samplers(document: &gltf::Document) -> Vec<Sampler> {
@@ -157,7 +268,11 @@ fn map_gltf_sampler(sampler: gltf::texture::Sampler) -> Sampler {
     }
 }
 
-fn load_textures(gltf: &gltf::Document, images: &[gltf::image::Data]) -> Result<Vec<Texture>> {
+fn load_textures(
+    gltf: &gltf::Document,
+    images: &[gltf::image::Data],
+    compression: TextureCompression,
+) -> Result<Vec<Texture>> {
     let samplers = load_samplers(gltf);
     let mut textures = Vec::new();
     for texture in gltf.textures() {
@@ -174,13 +289,15 @@ fn load_textures(gltf: &gltf::Document, images: &[gltf::image::Data]) -> Result<
         let image_index = texture.source().index();
         let image = images.get(image_index).context(image_error_message)?;
 
-        let texture = Texture {
+        let mut texture = Texture {
             pixels: image.pixels.to_vec(),
             format: map_gltf_format(image.format),
             width: image.width,
             height: image.height,
+            mip_levels: 1,
             sampler,
         };
+        texture.compress(compression)?;
         textures.push(texture);
     }
     Ok(textures)
@@ -201,7 +318,10 @@ fn map_gltf_format(format: gltf::image::Format) -> Format {
     }
 }
 
-fn load_material(primitive_material: &gltf::Material) -> Result<Material> {
+fn load_material(
+    primitive_material: &gltf::Material,
+    extensions: &MaterialExtensions,
+) -> Result<Material> {
     let mut material = Material {
         name: primitive_material
             .name()
@@ -216,7 +336,23 @@ fn load_material(primitive_material: &gltf::Material) -> Result<Material> {
     material.emissive_factor = glm::Vec3::from(primitive_material.emissive_factor());
     material.alpha_mode = map_gltf_alpha_mode(&primitive_material.alpha_mode());
     material.alpha_cutoff = primitive_material.alpha_cutoff().unwrap_or(0.5);
-    material.is_unlit = primitive_material.unlit();
+    material.double_sided = primitive_material.double_sided();
+    if primitive_material.unlit() {
+        material.shading_model = ShadingModel::Unlit;
+    }
+    if let Some(transmission) = primitive_material.transmission() {
+        material.transmission_factor = transmission.transmission_factor();
+    }
+    if let Some(ior) = primitive_material.ior() {
+        material.ior = ior;
+    }
+    if let Some(clearcoat) = extensions.clearcoat.as_ref() {
+        material.clearcoat_factor = clearcoat.clearcoat_factor;
+        material.clearcoat_roughness_factor = clearcoat.clearcoat_roughness_factor;
+    }
+    if let Some(emissive_strength) = extensions.emissive_strength.as_ref() {
+        material.emissive_strength = emissive_strength.emissive_strength;
+    }
     if let Some(base_color_texture) = pbr.base_color_texture() {
         material.color_texture_index = base_color_texture.texture().index() as i32;
         material.color_texture_set = base_color_texture.tex_coord() as i32;
@@ -260,6 +396,8 @@ fn load_scenes(gltf: &gltf::Document, ecs: &mut Ecs, entities: &[Entity]) -> Vec
                 .map(|node| create_scene_graph(&node, ecs, entities))
                 .collect(),
             skybox: None,
+            skybox_fade: None,
+            ambient_light: AmbientLight::default(),
         })
         .collect::<Vec<_>>()
 }
@@ -335,6 +473,8 @@ fn load_camera(camera: &gltf::Camera) -> Result<Camera> {
         name: camera.name().unwrap_or(DEFAULT_NAME).to_string(),
         projection,
         enabled: false,
+        exposure: 1.0,
+        culling_mask: Layers::ALL,
     })
 }
 
@@ -369,6 +509,16 @@ fn load_primitive(
     let first_vertex = geometry.vertices.len();
     let number_of_indices = load_primitive_indices(primitive, buffers, geometry)?;
     let number_of_vertices = load_primitive_vertices(primitive, buffers, geometry)?;
+    // Normal mapping needs tangents, so they are generated for primitives that don't provide them
+    if primitive.get(&gltf::Semantic::Tangents).is_none()
+        && primitive.mode() == gltf::mesh::Mode::Triangles
+    {
+        let indices = geometry.indices[first_index..]
+            .iter()
+            .map(|index| index - first_vertex as u32)
+            .collect::<Vec<_>>();
+        generate_tangents(&mut geometry.vertices[first_vertex..], &indices);
+    }
     let bounding_box = primitive.bounding_box();
     let morph_targets = load_morph_targets(primitive, buffers)?;
     let bounding_box = BoundingBox::new(
@@ -418,6 +568,11 @@ fn load_primitive_vertices(
         .read_tex_coords(1)
         .map_or(vec![glm::vec2(0.0, 0.0); number_of_vertices], map_to_vec2);
 
+    let tangents = reader.read_tangents().map_or(
+        vec![glm::vec4(0.0, 0.0, 0.0, 0.0); number_of_vertices],
+        |tangents| tangents.map(glm::Vec4::from).collect::<Vec<_>>(),
+    );
+
     let convert_joints = |joints: gltf::mesh::util::ReadJoints<'_>| -> Vec<glm::Vec4> {
         joints
             .into_u16()
@@ -460,6 +615,7 @@ fn load_primitive_vertices(
             joint_0: joints_0[index],
             weight_0: weights_0[index],
             color_0: colors_0[index],
+            tangent: tangents[index],
         });
     }
 
@@ -538,7 +694,7 @@ fn load_animations(
 ) -> Result<Vec<Animation>> {
     let mut animations = Vec::new();
     for animation in gltf.animations() {
-        let name = animation.name().unwrap_or(DEFAULT_NAME).to_string();
+        let name = animation.name().unwrap_or(DEFAULT_NAME);
         let mut channels = Vec::new();
         for channel in animation.channels() {
             let sampler = channel.sampler();
@@ -586,17 +742,7 @@ fn load_animations(
             });
         }
 
-        let max_animation_time = channels
-            .iter()
-            .flat_map(|channel| channel.inputs.iter().copied())
-            .fold(0.0, f32::max);
-
-        animations.push(Animation {
-            channels,
-            time: 0.0,
-            max_animation_time,
-            name,
-        });
+        animations.push(Animation::new(name, channels));
     }
     Ok(animations)
 }
@@ -609,10 +755,13 @@ fn map_gltf_interpolation(interpolation: gltf::animation::Interpolation) -> Inte
     }
 }
 
-fn load_materials(gltf: &gltf::Document) -> Result<Vec<Material>> {
+fn load_materials(
+    gltf: &gltf::Document,
+    material_extensions: &[MaterialExtensions],
+) -> Result<Vec<Material>> {
     let mut materials = Vec::new();
-    for material in gltf.materials() {
-        materials.push(load_material(&material)?);
+    for (material, extensions) in gltf.materials().zip(material_extensions.iter()) {
+        materials.push(load_material(&material, extensions)?);
     }
     Ok(materials)
 }
@@ -654,6 +803,7 @@ fn load_light(light: &gltf::khr_lights_punctual::Light) -> Light {
         intensity: light.intensity(),
         range: light.range().unwrap_or(-1.0), // if no range is present, range is assumed to be infinite
         kind: map_gltf_light_kind(light.kind()),
+        ..Default::default()
     }
 }
 
@@ -0,0 +1,339 @@
+use crate::{Entity, SimWorld, Transform};
+use anyhow::{Context, Result};
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IkSolver {
+    // Solved exactly for chains of two bones, such as legs and arms
+    TwoBone,
+    // Solves chains of any length, such as spines and tails, refining them each iteration
+    Fabrik { iterations: usize, tolerance: f32 },
+}
+
+impl Default for IkSolver {
+    fn default() -> Self {
+        Self::TwoBone
+    }
+}
+
+// Added to the last joint of a chain, such as a foot or a hand, to reach it toward a target.
+// The joints above it are rotated after animations have posed them each frame,
+// so feet can be planted on terrain and hands aimed at what they hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IkChain {
+    pub target: Entity,
+    // The chain bends toward this entity, such as a point in front of a knee
+    pub pole: Option<Entity>,
+    // How many bones above this joint are rotated. The two bone solver always uses two.
+    pub length: usize,
+    pub solver: IkSolver,
+    // Blends from the animated pose at zero to the solved pose at one
+    pub weight: f32,
+    pub enabled: bool,
+}
+
+impl IkChain {
+    pub fn two_bone(target: Entity, pole: Option<Entity>) -> Self {
+        Self {
+            target,
+            pole,
+            length: 2,
+            solver: IkSolver::TwoBone,
+            weight: 1.0,
+            enabled: true,
+        }
+    }
+
+    pub fn fabrik(target: Entity, length: usize) -> Self {
+        Self {
+            target,
+            pole: None,
+            length,
+            solver: IkSolver::Fabrik {
+                iterations: 10,
+                tolerance: 0.001,
+            },
+            weight: 1.0,
+            enabled: true,
+        }
+    }
+}
+
+// The rotation a joint was sampled with before a chain turned it, and the rotation it was turned to.
+// Joints that no animation poses keep the turned rotation, so the next solve restores the sampled one
+// rather than blending from its own result.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct IkPose {
+    sampled: glm::Quat,
+    solved: glm::Quat,
+}
+
+impl SimWorld {
+    pub fn solve_ik_chains(&mut self) -> Result<()> {
+        let mut query = <(Entity, &IkChain)>::query();
+        let chains = query
+            .iter(&self.ecs)
+            .filter(|(_, chain)| chain.enabled && chain.weight > 0.0)
+            .map(|(entity, chain)| (*entity, chain.clone()))
+            .collect::<Vec<_>>();
+        let mut previous = std::mem::take(&mut self.ik_poses);
+        for (end, chain) in chains.into_iter() {
+            // Chains whose target was removed are turned off rather than failing every frame
+            if self.ecs.entry_ref(chain.target).is_err() {
+                log::warn!("Disabling an ik chain whose target no longer exists");
+                if let Some(mut entry) = self.ecs.entry(end) {
+                    if let Ok(chain) = entry.get_component_mut::<IkChain>() {
+                        chain.enabled = false;
+                    }
+                }
+                continue;
+            }
+            self.solve_ik_chain(end, &chain, &mut previous)?;
+        }
+        // Joints no chain turned this frame go back to their sampled pose
+        for (joint, pose) in previous.into_iter() {
+            self.restore_sampled_rotation(joint, &pose);
+        }
+        Ok(())
+    }
+
+    fn restore_sampled_rotation(&mut self, joint: Entity, pose: &IkPose) {
+        let mut entry = match self.ecs.entry_mut(joint) {
+            Ok(entry) => entry,
+            Err(_) => return,
+        };
+        if let Ok(transform) = entry.get_component_mut::<Transform>() {
+            if transform.rotation == pose.solved {
+                transform.rotation = pose.sampled;
+            }
+        }
+    }
+
+    fn solve_ik_chain(
+        &mut self,
+        end: Entity,
+        chain: &IkChain,
+        previous: &mut HashMap<Entity, IkPose>,
+    ) -> Result<()> {
+        let length = match chain.solver {
+            IkSolver::TwoBone => 2,
+            IkSolver::Fabrik { .. } => chain.length,
+        };
+
+        // Ordered from the root of the chain down to its end
+        let mut joints = vec![end];
+        while joints.len() <= length {
+            match self.parent_of(joints[joints.len() - 1]) {
+                Some(parent) => joints.push(parent),
+                None => break,
+            }
+        }
+        joints.reverse();
+        if joints.len() < 2 {
+            return Ok(());
+        }
+
+        for joint in joints.iter() {
+            if let Some(pose) = previous.remove(joint) {
+                self.restore_sampled_rotation(*joint, &pose);
+            }
+        }
+
+        let positions = joints
+            .iter()
+            .map(|joint| self.global_position(*joint))
+            .collect::<Result<Vec<_>>>()?;
+        let target = self.global_position(chain.target)?;
+        let pole = chain.pole.and_then(|pole| self.global_position(pole).ok());
+
+        let solved = match chain.solver {
+            IkSolver::TwoBone if positions.len() == 3 => solve_two_bone(&positions, &target, pole),
+            IkSolver::TwoBone => return Ok(()),
+            IkSolver::Fabrik {
+                iterations,
+                tolerance,
+            } => solve_fabrik(&positions, &target, pole, iterations, tolerance),
+        };
+
+        // Each joint is turned so its child lies along the solved direction,
+        // working down from the root since turning a joint moves every joint below it
+        for index in 0..joints.len() - 1 {
+            self.aim_joint(
+                joints[index],
+                joints[index + 1],
+                &solved[index + 1],
+                chain.weight,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn aim_joint(
+        &mut self,
+        joint: Entity,
+        child: Entity,
+        desired_child: &glm::Vec3,
+        weight: f32,
+    ) -> Result<()> {
+        let global = Transform::from(self.entity_global_transform_matrix(joint)?);
+        let child_position = self.global_position(child)?;
+        let current = child_position - global.translation;
+        let desired = desired_child - global.translation;
+        if current.norm() < f32::EPSILON || desired.norm() < f32::EPSILON {
+            return Ok(());
+        }
+        let turn = glm::quat_rotation(&current.normalize(), &desired.normalize());
+        let rotation = glm::quat_normalize(&(turn * global.rotation));
+
+        let parent_rotation = match self.parent_of(joint) {
+            Some(parent) => Transform::from(self.entity_global_transform_matrix(parent)?).rotation,
+            None => glm::Quat::identity(),
+        };
+        let local_rotation = glm::quat_normalize(&(glm::quat_inverse(&parent_rotation) * rotation));
+
+        let mut entry = self
+            .ecs
+            .entry_mut(joint)
+            .context("An ik chain's joint no longer exists")?;
+        let transform = entry.get_component_mut::<Transform>()?;
+        let sampled = transform.rotation;
+        transform.rotation = if weight >= 1.0 {
+            local_rotation
+        } else {
+            glm::quat_slerp(&sampled, &local_rotation, weight)
+        };
+        let solved = transform.rotation;
+        self.ik_poses
+            .entry(joint)
+            .or_insert(IkPose { sampled, solved })
+            .solved = solved;
+        Ok(())
+    }
+
+    fn global_position(&self, entity: Entity) -> Result<glm::Vec3> {
+        let matrix = self.entity_global_transform_matrix(entity)?;
+        Ok(glm::vec3(matrix.m14, matrix.m24, matrix.m34))
+    }
+}
+
+// Places the middle joint where both bones keep their lengths and the end reaches as close to the target as it can
+fn solve_two_bone(
+    positions: &[glm::Vec3],
+    target: &glm::Vec3,
+    pole: Option<glm::Vec3>,
+) -> Vec<glm::Vec3> {
+    let (root, middle, end) = (positions[0], positions[1], positions[2]);
+    let upper = (middle - root).norm();
+    let lower = (end - middle).norm();
+
+    let to_target = target - root;
+    let direction = match to_target.try_normalize(f32::EPSILON) {
+        Some(direction) => direction,
+        None => return positions.to_vec(),
+    };
+    let reach = to_target
+        .norm()
+        .max((upper - lower).abs() + 0.0001)
+        .min(upper + lower - 0.0001);
+
+    // The chain bends toward the pole, or keeps bending the way it already does
+    let hint = pole.map(|pole| pole - root).unwrap_or(middle - root);
+    let bend = perpendicular(&hint, &direction);
+
+    let cos_root = ((upper * upper + reach * reach - lower * lower) / (2.0 * upper * reach))
+        .max(-1.0)
+        .min(1.0);
+    let sin_root = (1.0 - cos_root * cos_root).sqrt();
+    let solved_middle = root + direction * (upper * cos_root) + bend * (upper * sin_root);
+    let solved_end = root + direction * reach;
+    vec![root, solved_middle, solved_end]
+}
+
+// Forward and backward reaching inverse kinematics, which alternately pins the end to the target
+// and the root back in place until the end is within the tolerance
+fn solve_fabrik(
+    positions: &[glm::Vec3],
+    target: &glm::Vec3,
+    pole: Option<glm::Vec3>,
+    iterations: usize,
+    tolerance: f32,
+) -> Vec<glm::Vec3> {
+    let lengths = positions
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).norm())
+        .collect::<Vec<_>>();
+    let root = positions[0];
+    let last = positions.len() - 1;
+    let mut solved = positions.to_vec();
+
+    // Out of reach, the chain is stretched straight toward the target
+    if (target - root).norm() >= lengths.iter().sum::<f32>() {
+        let direction = (target - root).normalize();
+        for index in 1..solved.len() {
+            solved[index] = solved[index - 1] + direction * lengths[index - 1];
+        }
+        return solved;
+    }
+
+    for _ in 0..iterations {
+        solved[last] = *target;
+        for index in (0..last).rev() {
+            let direction = (solved[index] - solved[index + 1])
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(glm::Vec3::y);
+            solved[index] = solved[index + 1] + direction * lengths[index];
+        }
+
+        solved[0] = root;
+        for index in 1..solved.len() {
+            let direction = (solved[index] - solved[index - 1])
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(glm::Vec3::y);
+            solved[index] = solved[index - 1] + direction * lengths[index - 1];
+        }
+
+        if let Some(pole) = pole.as_ref() {
+            bend_toward_pole(&mut solved, pole);
+        }
+
+        if (solved[last] - target).norm() <= tolerance {
+            break;
+        }
+    }
+    solved
+}
+
+// Turns each inner joint around the line through its neighbors to face the pole,
+// which keeps the lengths of both of its bones
+fn bend_toward_pole(solved: &mut [glm::Vec3], pole: &glm::Vec3) {
+    for index in 1..solved.len() - 1 {
+        let (previous, next) = (solved[index - 1], solved[index + 1]);
+        let axis = match (next - previous).try_normalize(f32::EPSILON) {
+            Some(axis) => axis,
+            None => continue,
+        };
+        let offset = solved[index] - previous;
+        let along = axis * offset.dot(&axis);
+        let radius = (offset - along).norm();
+        let toward_pole = perpendicular(&(pole - previous), &axis);
+        solved[index] = previous + along + toward_pole * radius;
+    }
+}
+
+// The unit part of a vector at right angles to a direction, or any such unit vector if there is none
+fn perpendicular(vector: &glm::Vec3, direction: &glm::Vec3) -> glm::Vec3 {
+    let perpendicular = vector - direction * vector.dot(direction);
+    perpendicular
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(|| {
+            let fallback = if direction.x.abs() < 0.9 {
+                glm::Vec3::x()
+            } else {
+                glm::Vec3::y()
+            };
+            direction.cross(&fallback).normalize()
+        })
+}
@@ -0,0 +1,31 @@
+use crate::{paste_mesh, Clipboard, Entity, MeshRender, World};
+use anyhow::{Context, Result};
+
+// Gives a linked instance its own copy of the mesh and materials it was sharing,
+// so it can be edited without affecting the other instances. Textures stay shared.
+// Returns false if the entity was not sharing its mesh with anything.
+pub fn unlink_mesh(world: &mut World, entity: Entity) -> Result<bool> {
+    let mesh_name = world
+        .sim
+        .ecs
+        .entry_ref(entity)?
+        .get_component::<MeshRender>()?
+        .name
+        .to_string();
+    if world.sim.mesh_instances(&mesh_name).len() < 2 {
+        return Ok(false);
+    }
+
+    let mut mesh = Clipboard::copy_mesh(world, &mesh_name)?;
+    mesh.textures.clear();
+    let name = paste_mesh(world, &mesh_name, &mesh);
+
+    world
+        .sim
+        .ecs
+        .entry(entity)
+        .context("Failed to find entity!")?
+        .get_component_mut::<MeshRender>()?
+        .name = name;
+    Ok(true)
+}
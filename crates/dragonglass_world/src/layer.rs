@@ -0,0 +1,79 @@
+#[cfg(feature = "assets")]
+use crate::{compact_world, load_gltf_asset, GltfAsset, World};
+use crate::{Entity, SimWorld};
+#[cfg(feature = "assets")]
+use anyhow::Result;
+use legion::IntoQuery;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+#[cfg(feature = "assets")]
+use std::path::Path;
+
+// Marks an entity as belonging to a named layer so the layer can be unloaded as a unit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer(pub String);
+
+#[cfg(feature = "assets")]
+pub fn load_gltf_layer(path: impl AsRef<Path>, world: &mut World, layer: &str) -> Result<()> {
+    let asset = GltfAsset::import(path)?;
+    load_gltf_layer_asset(&asset, world, layer)?;
+    Ok(())
+}
+
+#[cfg(feature = "assets")]
+pub fn load_gltf_layer_asset(
+    asset: &GltfAsset,
+    world: &mut World,
+    layer: &str,
+) -> Result<Vec<Entity>> {
+    let entities = load_gltf_asset(asset, world)?;
+    for entity in entities.iter() {
+        if let Some(mut entry) = world.sim.ecs.entry(*entity) {
+            entry.add_component(Layer(layer.to_string()));
+        }
+    }
+    Ok(entities)
+}
+
+pub fn layer_entities(world: &SimWorld, layer: &str) -> Vec<Entity> {
+    let mut query = <(Entity, &Layer)>::query();
+    query
+        .iter(&world.ecs)
+        .filter(|(_, entity_layer)| entity_layer.0 == layer)
+        .map(|(entity, _)| *entity)
+        .collect()
+}
+
+pub fn layer_names(world: &SimWorld) -> Vec<String> {
+    let mut query = <&Layer>::query();
+    let mut names = query
+        .iter(&world.ecs)
+        .map(|layer| layer.0.to_string())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+pub fn is_layer_loaded(world: &SimWorld, layer: &str) -> bool {
+    let mut query = <&Layer>::query();
+    query
+        .iter(&world.ecs)
+        .any(|entity_layer| entity_layer.0 == layer)
+}
+
+// Releases the layer's entities and their descendants, scenegraph nodes, colliders, animations,
+// and any geometry and textures that are no longer referenced afterwards
+#[cfg(feature = "assets")]
+pub fn unload_layer(world: &mut World, layer: &str) -> Result<()> {
+    let entities = layer_entities(world, layer);
+    if entities.is_empty() {
+        return Ok(());
+    }
+
+    world.take_entities(&entities)?;
+    compact_world(world)?;
+
+    Ok(())
+}
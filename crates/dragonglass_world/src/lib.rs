@@ -1,24 +1,120 @@
 mod animation;
+mod behavior;
+mod buoyancy;
 mod camera;
-mod gltf;
+mod camera_rig;
+mod clock;
+mod debug_draw;
+mod geometry;
+mod hash;
+mod ik;
+mod layer;
+mod particles;
 mod physics;
+mod physics_lod;
+mod probe;
+mod ragdoll;
+mod random;
 mod registry;
 mod scenegraph;
+mod script;
+mod shapes;
+mod sim;
+mod skeleton;
+mod spline;
+mod tags;
 mod texture;
 mod transform;
+mod vehicle;
+mod visibility;
+
+// Loading and storing render assets pulls in the image and gltf crates,
+// so headless users can opt out of them and only use the SimWorld
+#[cfg(feature = "assets")]
+mod assets;
+#[cfg(feature = "assets")]
+mod batching;
+#[cfg(feature = "assets")]
+mod bounds;
+#[cfg(feature = "assets")]
+mod clipboard;
+#[cfg(feature = "assets")]
+mod compaction;
+#[cfg(feature = "assets")]
+mod compression;
+#[cfg(feature = "assets")]
+mod environment;
+#[cfg(feature = "fbx")]
+mod fbx;
+#[cfg(feature = "assets")]
+mod gltf;
+#[cfg(feature = "assets")]
+mod instancing;
+#[cfg(feature = "assets")]
+mod lod;
+#[cfg(feature = "assets")]
+mod model;
+#[cfg(feature = "assets")]
+mod obj;
+#[cfg(feature = "assets")]
+mod occlusion;
+#[cfg(feature = "assets")]
+mod painting;
+#[cfg(feature = "assets")]
+mod processed;
+#[cfg(feature = "assets")]
+mod reload;
+#[cfg(feature = "assets")]
+mod removal;
+#[cfg(feature = "assets")]
+mod snapshot;
+#[cfg(feature = "assets")]
+mod sprite;
+#[cfg(feature = "assets")]
+mod terrain;
+#[cfg(feature = "assets")]
+mod text;
+#[cfg(feature = "assets")]
 mod world;
 
 pub use self::{
     animation::*,
+    behavior::*,
+    buoyancy::*,
     camera::*,
-    gltf::*,
+    camera_rig::*,
+    clock::*,
+    debug_draw::*,
+    geometry::*,
+    hash::*,
+    ik::*,
+    layer::*,
     legion::{EntityStore, IntoQuery},
+    particles::*,
     physics::*,
+    physics_lod::*,
+    probe::*,
+    ragdoll::*,
+    random::*,
     registry::*,
     scenegraph::*,
+    script::*,
+    shapes::*,
+    sim::*,
+    skeleton::*,
+    spline::*,
+    tags::*,
     texture::*,
     transform::*,
-    world::*,
+    vehicle::*,
+    visibility::*,
+};
+
+#[cfg(feature = "assets")]
+pub use self::{
+    assets::*, batching::*, bounds::*, clipboard::*, compaction::*, compression::*, environment::*,
+    gltf::*, instancing::*, lod::*, model::*, occlusion::*, painting::*, processed::*, reload::*,
+    removal::*, snapshot::*, sprite::*, terrain::*, text::*, world::*,
 };
 pub use legion;
 pub use petgraph;
@@ -30,3 +126,14 @@ pub struct Hidden;
 
 #[derive(Serialize, Deserialize)]
 pub struct Name(pub String);
+
+// Selected and hovered entities are outlined in the viewport
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Selected;
+
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Hovered;
+
+// Marks an entity that never moves, so its mesh can be merged into a static batch
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Static;
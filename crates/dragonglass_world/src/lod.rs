@@ -0,0 +1,250 @@
+#[cfg(feature = "lod_generation")]
+use crate::Mesh;
+use crate::{Camera, Entity, MeshRender, World};
+use anyhow::Result;
+#[cfg(feature = "lod_generation")]
+use anyhow::{anyhow, Context};
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LodMetric {
+    // Thresholds are distances from the camera, increasing with each level
+    Distance,
+    // Thresholds are the fraction of the screen's height the mesh's bounds cover, decreasing with each level.
+    // Unlike distances, these account for the mesh's size and the camera's field of view.
+    ScreenCoverage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LodLevel {
+    pub mesh: String,
+    // Where this level starts being drawn. The first level is drawn until the second starts.
+    pub threshold: f32,
+}
+
+// Swaps an entity's mesh for simpler ones as it becomes less visible to the active camera.
+// The first level is the full detail mesh.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lod {
+    pub levels: Vec<LodLevel>,
+    pub metric: LodMetric,
+    current: usize,
+}
+
+impl Lod {
+    // A level needs to come this fraction past its threshold to be left for a more detailed one,
+    // so meshes sitting right on a boundary don't swap every frame
+    pub const HYSTERESIS: f32 = 0.9;
+
+    pub fn new(metric: LodMetric, levels: Vec<LodLevel>) -> Self {
+        Self {
+            levels,
+            metric,
+            current: 0,
+        }
+    }
+
+    pub fn distances(levels: &[(&str, f32)]) -> Self {
+        Self::new(LodMetric::Distance, Self::levels(levels))
+    }
+
+    pub fn screen_coverage(levels: &[(&str, f32)]) -> Self {
+        Self::new(LodMetric::ScreenCoverage, Self::levels(levels))
+    }
+
+    fn levels(levels: &[(&str, f32)]) -> Vec<LodLevel> {
+        levels
+            .iter()
+            .map(|(mesh, threshold)| LodLevel {
+                mesh: mesh.to_string(),
+                threshold: *threshold,
+            })
+            .collect()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    fn target_level(&self, value: f32) -> usize {
+        let mut target = 0;
+        for (index, level) in self.levels.iter().enumerate().skip(1) {
+            let reached = match self.metric {
+                LodMetric::Distance => {
+                    let threshold = if self.current >= index {
+                        level.threshold * Self::HYSTERESIS
+                    } else {
+                        level.threshold
+                    };
+                    value >= threshold
+                }
+                LodMetric::ScreenCoverage => {
+                    let threshold = if self.current >= index {
+                        level.threshold / Self::HYSTERESIS
+                    } else {
+                        level.threshold
+                    };
+                    value <= threshold
+                }
+            };
+            if !reached {
+                break;
+            }
+            target = index;
+        }
+        target
+    }
+}
+
+impl World {
+    // Moves every entity with a Lod to the level the active camera calls for,
+    // changing the mesh it renders when its level changes
+    pub fn update_lods(&mut self, aspect_ratio: f32) -> Result<()> {
+        dragonglass_profiling::profile_scope!("mesh lod");
+        let camera_entity = match self.sim.active_camera() {
+            Ok(entity) => entity,
+            Err(_) => return Ok(()),
+        };
+        let camera_position = self.sim.entity_global_transform(camera_entity)?.translation;
+        let (projection, _view) = self.sim.active_camera_matrices(aspect_ratio)?;
+        let orthographic = self
+            .sim
+            .ecs
+            .entry_ref(camera_entity)?
+            .get_component::<Camera>()?
+            .is_orthographic();
+
+        let mut query = <(Entity, &Lod)>::query();
+        let entities = query
+            .iter(&self.sim.ecs)
+            .filter(|(_, lod)| !lod.levels.is_empty())
+            .map(|(entity, lod)| (*entity, lod.clone()))
+            .collect::<Vec<_>>();
+
+        for (entity, mut lod) in entities.into_iter() {
+            let transform = self.sim.entity_global_transform(entity)?;
+            let distance = glm::distance(&camera_position, &transform.translation);
+            let value = match lod.metric {
+                LodMetric::Distance => distance,
+                LodMetric::ScreenCoverage => {
+                    // The bounds of the full detail mesh are used for every level so they agree on coverage
+                    let radius = match self.assets.geometry.meshes.get(&lod.levels[0].mesh) {
+                        Some(mesh) => {
+                            let scale = transform.scale.abs();
+                            mesh.bounding_box().half_extents().norm()
+                                * scale.x.max(scale.y).max(scale.z)
+                        }
+                        None => continue,
+                    };
+                    // The projection's vertical scale maps view space heights to half the screen
+                    let half_height = if orthographic {
+                        radius * projection.m22
+                    } else {
+                        radius * projection.m22 / distance.max(f32::EPSILON)
+                    };
+                    half_height.abs()
+                }
+            };
+
+            let target = lod.target_level(value);
+            if target == lod.current {
+                continue;
+            }
+            lod.current = target;
+            let mesh = lod.levels[target].mesh.to_string();
+            if let Some(mut entry) = self.sim.ecs.entry(entity) {
+                if let Ok(mesh_render) = entry.get_component_mut::<MeshRender>() {
+                    mesh_render.name = mesh;
+                }
+                if let Ok(component) = entry.get_component_mut::<Lod>() {
+                    *component = lod;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// How levels of detail are generated for meshes as models are imported
+#[derive(Debug, Clone, PartialEq)]
+pub struct LodGeneration {
+    // The fraction of the original triangles each generated level keeps, and the distance it starts at
+    pub levels: Vec<(f32, f32)>,
+    // The most a simplified surface may deviate from the original, relative to the mesh's size
+    pub target_error: f32,
+}
+
+impl Default for LodGeneration {
+    fn default() -> Self {
+        Self {
+            levels: vec![(0.5, 20.0), (0.25, 50.0), (0.1, 100.0)],
+            target_error: 0.01,
+        }
+    }
+}
+
+#[cfg(feature = "lod_generation")]
+impl World {
+    // Adds simplified copies of a mesh and returns a Lod that switches between them by distance.
+    // The copies share the original's vertices and only store fewer indices.
+    pub fn generate_lods(&mut self, mesh_name: &str, generation: &LodGeneration) -> Result<Lod> {
+        let geometry = &mut self.assets.geometry;
+        let mesh = geometry
+            .meshes
+            .get(mesh_name)
+            .cloned()
+            .context("Failed to find the mesh to generate levels of detail for")?;
+
+        let mut levels = vec![LodLevel {
+            mesh: mesh_name.to_string(),
+            threshold: 0.0,
+        }];
+        for (ratio, distance) in generation.levels.iter() {
+            let mut simplified = Mesh {
+                name: geometry.unique_mesh_name(&format!("{} LOD{}", mesh_name, levels.len())),
+                primitives: mesh.primitives.clone(),
+                weights: mesh.weights.clone(),
+            };
+            for primitive in simplified.primitives.iter_mut() {
+                let first_vertex = primitive.first_vertex;
+                let positions = geometry.vertices
+                    [first_vertex..first_vertex + primitive.number_of_vertices]
+                    .iter()
+                    .map(|vertex| [vertex.position.x, vertex.position.y, vertex.position.z])
+                    .collect::<Vec<_>>();
+                let indices = geometry.indices
+                    [primitive.first_index..primitive.first_index + primitive.number_of_indices]
+                    .iter()
+                    .map(|index| index - first_vertex as u32)
+                    .collect::<Vec<_>>();
+                let adapter = meshopt::VertexDataAdapter::new(
+                    meshopt::typed_to_bytes(&positions),
+                    std::mem::size_of::<[f32; 3]>(),
+                    0,
+                )
+                .map_err(|error| anyhow!("Failed to read mesh positions: {:?}", error))?;
+                let target_count = ((indices.len() as f32 * ratio) as usize / 3) * 3;
+                let simplified_indices =
+                    meshopt::simplify(&indices, &adapter, target_count, generation.target_error);
+
+                primitive.first_index = geometry.indices.len();
+                primitive.number_of_indices = simplified_indices.len();
+                geometry.indices.extend(
+                    simplified_indices
+                        .iter()
+                        .map(|index| index + first_vertex as u32),
+                );
+            }
+            levels.push(LodLevel {
+                mesh: simplified.name.to_string(),
+                threshold: *distance,
+            });
+            geometry
+                .meshes
+                .insert(simplified.name.to_string(), simplified);
+        }
+        Ok(Lod::new(LodMetric::Distance, levels))
+    }
+}
@@ -0,0 +1,265 @@
+#[cfg(feature = "lod_generation")]
+use crate::Lod;
+use crate::{
+    generate_tangents, load_gltf_asset, load_processed_model, BoundingBox, Entity, GltfAsset,
+    LodGeneration, Material, Mesh, MeshRender, Name, Primitive, ProcessedModel, SceneGraph,
+    Texture, Transform, Vertex, World,
+};
+use anyhow::{bail, Context, Result};
+use nalgebra_glm as glm;
+use petgraph::prelude::*;
+use std::path::Path;
+
+// The model formats that can be imported, dispatched on by file extension
+pub const MODEL_EXTENSIONS: &[&str] = &["glb", "gltf", "obj", "fbx"];
+
+pub enum ModelAsset {
+    Gltf(GltfAsset),
+    // Formats that are converted into the world's own representation while importing
+    Imported(ImportedModel),
+    // Any format, already processed into the runtime representation by an earlier import
+    Processed(ProcessedModel),
+}
+
+impl ModelAsset {
+    pub fn import(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("glb") | Some("gltf") => Ok(Self::Gltf(GltfAsset::import(path)?)),
+            Some("obj") => Ok(Self::Imported(ImportedModel::from_obj(path)?)),
+            #[cfg(feature = "fbx")]
+            Some("fbx") => Ok(Self::Imported(ImportedModel::from_fbx(path)?)),
+            #[cfg(not(feature = "fbx"))]
+            Some("fbx") => bail!("FBX import requires the 'fbx' feature to be enabled!"),
+            _ => bail!(
+                "Unsupported model file '{}', expected one of {:?}",
+                path.display(),
+                MODEL_EXTENSIONS
+            ),
+        }
+    }
+}
+
+pub fn is_model_file(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            MODEL_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+pub fn load_model(path: impl AsRef<Path>, world: &mut World) -> Result<()> {
+    let asset = ModelAsset::import(path)?;
+    load_model_asset(&asset, world)?;
+    Ok(())
+}
+
+// Returns the entities that were created for the asset's nodes
+pub fn load_model_asset(asset: &ModelAsset, world: &mut World) -> Result<Vec<Entity>> {
+    let entities = match asset {
+        ModelAsset::Gltf(asset) => load_gltf_asset(asset, world)?,
+        ModelAsset::Imported(model) => load_imported_model(model, world)?,
+        ModelAsset::Processed(model) => load_processed_model(model, world)?,
+    };
+    if let Some(generation) = world.assets.lod_generation.clone() {
+        add_generated_lods(world, &entities, &generation)?;
+    }
+    Ok(entities)
+}
+
+// Entities sharing a mesh share its generated levels as well
+#[cfg(feature = "lod_generation")]
+fn add_generated_lods(
+    world: &mut World,
+    entities: &[Entity],
+    generation: &LodGeneration,
+) -> Result<()> {
+    let mut generated = std::collections::HashMap::new();
+    for entity in entities.iter() {
+        let mesh_name = match world.ecs.entry_ref(*entity)?.get_component::<MeshRender>() {
+            Ok(mesh_render) => mesh_render.name.to_string(),
+            Err(_) => continue,
+        };
+        let lod = match generated.get(&mesh_name) {
+            Some(lod) => lod.clone(),
+            None => {
+                let lod = world.generate_lods(&mesh_name, generation)?;
+                generated.insert(mesh_name, lod.clone());
+                lod
+            }
+        };
+        if let Some(mut entry) = world.ecs.entry(*entity) {
+            entry.add_component(lod);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "lod_generation"))]
+fn add_generated_lods(_: &mut World, _: &[Entity], _: &LodGeneration) -> Result<()> {
+    log::warn!("Generating levels of detail requires the 'lod_generation' feature to be enabled!");
+    Ok(())
+}
+
+// A model converted from a format without its own loader.
+// Material texture indices and mesh indices are local to the model until it is loaded into a world.
+#[derive(Default, Debug, Clone)]
+pub struct ImportedModel {
+    pub meshes: Vec<ImportedMesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub nodes: Vec<ImportedNode>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ImportedMesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub material_index: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedNode {
+    pub name: String,
+    pub transform: Transform,
+    // Every mesh a node references is drawn as one primitive of the node's mesh
+    pub meshes: Vec<usize>,
+    pub parent: Option<usize>,
+}
+
+impl ImportedNode {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            transform: Transform::new(
+                glm::Vec3::zeros(),
+                glm::Quat::identity(),
+                glm::vec3(1.0, 1.0, 1.0),
+            ),
+            meshes: Vec::new(),
+            parent: None,
+        }
+    }
+}
+
+fn load_imported_model(model: &ImportedModel, world: &mut World) -> Result<Vec<Entity>> {
+    let number_of_materials = world.assets.materials.len();
+    let number_of_textures = world.assets.textures.len() as i32;
+    for material in model.materials.iter() {
+        let mut material = material.clone();
+        for index in material.texture_indices_mut() {
+            if *index != -1 {
+                *index += number_of_textures;
+            }
+        }
+        world.assets.materials.push(material);
+    }
+
+    let compression = world.assets.texture_compression;
+    for texture in model.textures.iter() {
+        let mut texture = texture.clone();
+        texture.compress(compression)?;
+        world.assets.textures.push(texture);
+    }
+
+    let entities = world
+        .sim
+        .ecs
+        .extend((0..model.nodes.len()).map(|_| ()))
+        .to_vec();
+
+    let geometry = &mut world.assets.geometry;
+    for (node, entity) in model.nodes.iter().zip(entities.iter()) {
+        let mut entry = world
+            .sim
+            .ecs
+            .entry(*entity)
+            .context("Failed to find entity!")?;
+        entry.add_component(Name(node.name.to_string()));
+        entry.add_component(node.transform);
+
+        if node.meshes.is_empty() {
+            continue;
+        }
+
+        let mut primitives = Vec::new();
+        for mesh_index in node.meshes.iter() {
+            let mesh = model
+                .meshes
+                .get(*mesh_index)
+                .context("Failed to lookup mesh specified by node!")?;
+
+            let first_vertex = geometry.vertices.len();
+            let first_index = geometry.indices.len();
+            let mut bounding_box = BoundingBox::new_invalid();
+            mesh.vertices
+                .iter()
+                .for_each(|vertex| bounding_box.fit_point(vertex.position));
+            geometry.vertices.extend_from_slice(&mesh.vertices);
+            geometry
+                .indices
+                .extend(mesh.indices.iter().map(|index| index + first_vertex as u32));
+            generate_tangents(&mut geometry.vertices[first_vertex..], &mesh.indices);
+
+            primitives.push(Primitive {
+                first_vertex,
+                first_index,
+                number_of_vertices: mesh.vertices.len(),
+                number_of_indices: mesh.indices.len(),
+                material_index: mesh.material_index.map(|index| index + number_of_materials),
+                morph_targets: Vec::new(),
+                bounding_box,
+            });
+        }
+
+        let mut name = node.name.to_string();
+        while geometry.meshes.contains_key(&name) {
+            name += "_";
+        }
+        geometry.meshes.insert(
+            name.to_string(),
+            Mesh {
+                name: name.to_string(),
+                primitives,
+                weights: Vec::new(),
+            },
+        );
+        entry.add_component(MeshRender { name });
+    }
+
+    // Each root node becomes a graph in the active scene, the same as gltf root nodes do
+    for (index, node) in model.nodes.iter().enumerate() {
+        if node.parent.is_some() {
+            continue;
+        }
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(entities[index]);
+        graph_children(&mut graph, model, &entities, index, root);
+        world.sim.scene.graphs.push(graph);
+    }
+
+    Ok(entities)
+}
+
+fn graph_children(
+    graph: &mut SceneGraph,
+    model: &ImportedModel,
+    entities: &[Entity],
+    parent: usize,
+    parent_index: NodeIndex,
+) {
+    for (index, node) in model.nodes.iter().enumerate() {
+        if node.parent != Some(parent) {
+            continue;
+        }
+        let node_index = graph.add_node(entities[index]);
+        graph.add_edge(parent_index, node_index);
+        graph_children(graph, model, entities, index, node_index);
+    }
+}
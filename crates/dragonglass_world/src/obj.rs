@@ -0,0 +1,172 @@
+use crate::{AlphaMode, ImportedMesh, ImportedModel, ImportedNode, Material, Texture, Vertex};
+use anyhow::Result;
+use log::warn;
+use nalgebra_glm as glm;
+use std::{collections::HashMap, path::Path};
+
+impl ImportedModel {
+    // Each object in the file becomes a node with a single mesh.
+    // Textures are resolved relative to the file, and ones that fail to load are left out of their material.
+    pub fn from_obj(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let options = tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        };
+        let (models, materials) = tobj::load_obj(path, &options)?;
+        let materials = materials.unwrap_or_else(|error| {
+            warn!(
+                "Failed to load materials for '{}': {}",
+                path.display(),
+                error
+            );
+            Vec::new()
+        });
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut model = Self::default();
+        let mut texture_indices = HashMap::new();
+        let mut load_texture = |file: &str, textures: &mut Vec<Texture>| -> i32 {
+            if file.is_empty() {
+                return -1;
+            }
+            if let Some(index) = texture_indices.get(file) {
+                return *index;
+            }
+            let index = match Texture::from_file(directory.join(file)) {
+                Ok(texture) => {
+                    textures.push(texture);
+                    textures.len() as i32 - 1
+                }
+                Err(error) => {
+                    warn!("Failed to load obj texture '{}': {}", file, error);
+                    -1
+                }
+            };
+            texture_indices.insert(file.to_string(), index);
+            index
+        };
+
+        for obj_material in materials.iter() {
+            let mut material = map_obj_material(obj_material);
+            material.color_texture_index =
+                load_texture(&obj_material.diffuse_texture, &mut model.textures);
+            if material.color_texture_index != -1 {
+                material.color_texture_set = 0;
+            }
+            material.normal_texture_index =
+                load_texture(&obj_material.normal_texture, &mut model.textures);
+            if material.normal_texture_index != -1 {
+                material.normal_texture_set = 0;
+            }
+            model.materials.push(material);
+        }
+
+        for obj_model in models.into_iter() {
+            let mut mesh = map_obj_mesh(&obj_model);
+            let number_of_materials = model.materials.len();
+            mesh.material_index = mesh
+                .material_index
+                .filter(|index| *index < number_of_materials);
+            let mut node = ImportedNode::new(obj_model.name);
+            node.meshes.push(model.meshes.len());
+            model.meshes.push(mesh);
+            model.nodes.push(node);
+        }
+
+        Ok(model)
+    }
+}
+
+fn map_obj_mesh(model: &tobj::Model) -> ImportedMesh {
+    let mesh = &model.mesh;
+    let mut vertices: Vec<Vertex> = (0..mesh.positions.len() / 3)
+        .map(|index| {
+            let vec3 = |values: &[f32], default: glm::Vec3| {
+                values
+                    .get(index * 3..index * 3 + 3)
+                    .map_or(default, |value| glm::vec3(value[0], value[1], value[2]))
+            };
+            // Obj texture coordinates start at the bottom left, rather than the top left
+            let uv_0 = mesh
+                .texcoords
+                .get(index * 2..index * 2 + 2)
+                .map_or(glm::Vec2::zeros(), |uv| glm::vec2(uv[0], 1.0 - uv[1]));
+            Vertex {
+                position: vec3(&mesh.positions, glm::Vec3::zeros()),
+                normal: vec3(&mesh.normals, glm::Vec3::zeros()),
+                uv_0,
+                color_0: vec3(&mesh.vertex_color, glm::vec3(1.0, 1.0, 1.0)),
+                ..Default::default()
+            }
+        })
+        .collect();
+    if mesh.normals.is_empty() {
+        generate_normals(&mut vertices, &mesh.indices);
+    }
+    ImportedMesh {
+        name: model.name.to_string(),
+        vertices,
+        indices: mesh.indices.clone(),
+        material_index: mesh.material_id,
+    }
+}
+
+// Files without normals get each vertex's normal from the faces around it, weighted by their area
+fn generate_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        if a.max(b).max(c) >= vertices.len() {
+            continue;
+        }
+        let normal = glm::cross(
+            &(vertices[b].position - vertices[a].position),
+            &(vertices[c].position - vertices[a].position),
+        );
+        for index in [a, b, c] {
+            vertices[index].normal += normal;
+        }
+    }
+    for vertex in vertices.iter_mut() {
+        if glm::length(&vertex.normal) > f32::EPSILON {
+            vertex.normal = glm::normalize(&vertex.normal);
+        }
+    }
+}
+
+// Obj materials are Blinn-Phong, so they are mapped onto a non-metallic pbr material
+fn map_obj_material(obj_material: &tobj::Material) -> Material {
+    let [red, green, blue] = obj_material.diffuse;
+    // A dissolve of zero is what materials without one are given, so it is treated as opaque
+    let alpha = if obj_material.dissolve > 0.0 {
+        obj_material.dissolve
+    } else {
+        1.0
+    };
+    let mut material = Material {
+        name: obj_material.name.to_string(),
+        base_color_factor: glm::vec4(red, green, blue, alpha),
+        emissive_factor: glm::Vec3::zeros(),
+        metallic_factor: 0.0,
+        roughness_factor: (2.0 / (obj_material.shininess + 2.0)).sqrt(),
+        ..Default::default()
+    };
+    if alpha < 1.0 {
+        material.alpha_mode = AlphaMode::Blend;
+    }
+    if let Some(emissive) = obj_material.unknown_param.get("Ke") {
+        let values = emissive
+            .split_whitespace()
+            .filter_map(|value| value.parse::<f32>().ok())
+            .collect::<Vec<_>>();
+        if let [red, green, blue] = values[..] {
+            material.emissive_factor = glm::vec3(red, green, blue);
+        }
+    }
+    material
+}
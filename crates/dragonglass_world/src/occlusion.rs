@@ -0,0 +1,308 @@
+use crate::{BoundingBox, Entity, Hidden, MeshRender, Skin, World};
+use anyhow::Result;
+use legion::{component, EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Marks an entity as solid enough to hide what is behind it, such as a wall or a large rock.
+// Its box is drawn into a small depth buffer each frame, and meshes entirely behind those boxes are not rendered.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Occluder {
+    // The box in the entity's space to occlude with, which must fit inside the mesh.
+    // Unset uses the bounds of the entity's mesh, which suits box shaped meshes like walls and floors.
+    pub bounds: Option<BoundingBox>,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OcclusionStats {
+    pub occluders: usize,
+    // Meshes that were tested this frame
+    pub tested: usize,
+    pub frustum_culled: usize,
+    pub occluded: usize,
+}
+
+impl OcclusionStats {
+    pub fn visible(&self) -> usize {
+        self.tested - self.frustum_culled - self.occluded
+    }
+}
+
+// Culls meshes outside the camera's view or hidden behind occluders,
+// using a software rasterized hierarchical depth buffer so it works the same with every renderer.
+// Renderers skip the entities it culled during the last update.
+pub struct OcclusionCulling {
+    pub enabled: bool,
+    // Each level halves the previous one, keeping the farthest depth of the texels it covers.
+    // Depths are stored inverted so the cleared buffer is infinitely far away.
+    levels: Vec<Vec<f32>>,
+    culled: HashSet<Entity>,
+    stats: OcclusionStats,
+}
+
+impl Default for OcclusionCulling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            levels: Vec::new(),
+            culled: HashSet::new(),
+            stats: OcclusionStats::default(),
+        }
+    }
+}
+
+impl OcclusionCulling {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 128;
+    const NEAR: f32 = 0.01;
+
+    pub fn is_culled(&self, entity: Entity) -> bool {
+        self.culled.contains(&entity)
+    }
+
+    pub fn stats(&self) -> OcclusionStats {
+        self.stats
+    }
+
+    fn clear(&mut self) {
+        self.culled.clear();
+        self.stats = OcclusionStats::default();
+        self.levels.clear();
+        let (mut width, mut height) = (Self::WIDTH, Self::HEIGHT);
+        loop {
+            self.levels.push(vec![0.0; width * height]);
+            if width == 1 && height == 1 {
+                break;
+            }
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+        }
+    }
+
+    fn level_size(level: usize) -> (usize, usize) {
+        (
+            (Self::WIDTH >> level).max(1),
+            (Self::HEIGHT >> level).max(1),
+        )
+    }
+
+    fn rasterize_box(&mut self, clip_corners: &[glm::Vec4; 8]) {
+        // Triangles crossing the near plane are skipped, which only makes the occluder smaller
+        if clip_corners.iter().any(|corner| corner.w < Self::NEAR) {
+            return;
+        }
+        let screen = clip_corners.iter().map(Self::to_screen).collect::<Vec<_>>();
+        for face in BOX_FACES.iter() {
+            self.rasterize_triangle(&screen[face[0]], &screen[face[1]], &screen[face[2]]);
+            self.rasterize_triangle(&screen[face[0]], &screen[face[2]], &screen[face[3]]);
+        }
+    }
+
+    // Pixel coordinates with the inverse depth, which interpolates linearly across the screen
+    fn to_screen(clip: &glm::Vec4) -> glm::Vec3 {
+        let ndc = clip.xy() / clip.w;
+        glm::vec3(
+            (ndc.x + 1.0) / 2.0 * Self::WIDTH as f32,
+            (1.0 - ndc.y) / 2.0 * Self::HEIGHT as f32,
+            1.0 / clip.w,
+        )
+    }
+
+    fn rasterize_triangle(&mut self, a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) {
+        let edge = |from: &glm::Vec3, to: &glm::Vec3, x: f32, y: f32| {
+            (to.x - from.x) * (y - from.y) - (to.y - from.y) * (x - from.x)
+        };
+        let area = edge(a, b, c.x, c.y);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+        let min_x = a.x.min(b.x).min(c.x).max(0.0) as usize;
+        let min_y = a.y.min(b.y).min(c.y).max(0.0) as usize;
+        let max_x = (a.x.max(b.x).max(c.x).ceil() as usize).min(Self::WIDTH);
+        let max_y = (a.y.max(b.y).max(c.y).ceil() as usize).min(Self::HEIGHT);
+        let depth = &mut self.levels[0];
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(b, c, px, py) / area;
+                let wb = edge(c, a, px, py) / area;
+                let wc = edge(a, b, px, py) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let inverse_depth = wa * a.z + wb * b.z + wc * c.z;
+                let texel = &mut depth[y * Self::WIDTH + x];
+                *texel = texel.max(inverse_depth);
+            }
+        }
+    }
+
+    fn build_pyramid(&mut self) {
+        for level in 1..self.levels.len() {
+            let (width, height) = Self::level_size(level);
+            let (previous_width, previous_height) = Self::level_size(level - 1);
+            for y in 0..height {
+                for x in 0..width {
+                    let mut farthest = f32::MAX;
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let (sx, sy) = (
+                            (x * 2 + dx).min(previous_width - 1),
+                            (y * 2 + dy).min(previous_height - 1),
+                        );
+                        farthest = farthest.min(self.levels[level - 1][sy * previous_width + sx]);
+                    }
+                    self.levels[level][y * width + x] = farthest;
+                }
+            }
+        }
+    }
+
+    // Whether every texel the screen rect covers has an occluder nearer than the given depth.
+    // The rect is tested at the level where it covers only a few texels.
+    fn is_occluded(&self, min: &glm::Vec2, max: &glm::Vec2, nearest_inverse_depth: f32) -> bool {
+        let size = (max.x - min.x).max(max.y - min.y).max(1.0);
+        let level = (size.log2().ceil().max(0.0) as usize).min(self.levels.len() - 1);
+        let (width, height) = Self::level_size(level);
+        let scale = (1 << level) as f32;
+        let min_x = ((min.x / scale).floor().max(0.0) as usize).min(width - 1);
+        let min_y = ((min.y / scale).floor().max(0.0) as usize).min(height - 1);
+        let max_x = ((max.x / scale).floor().max(0.0) as usize).min(width - 1);
+        let max_y = ((max.y / scale).floor().max(0.0) as usize).min(height - 1);
+        (min_y..=max_y).all(|y| {
+            (min_x..=max_x).all(|x| self.levels[level][y * width + x] > nearest_inverse_depth)
+        })
+    }
+}
+
+// The corners of each face of a box, indexed as in BoundingBox::corners
+const BOX_FACES: [[usize; 4]; 6] = [
+    [0, 1, 3, 2],
+    [4, 6, 7, 5],
+    [0, 4, 5, 1],
+    [2, 3, 7, 6],
+    [0, 2, 6, 4],
+    [1, 5, 7, 3],
+];
+
+pub(crate) fn clip_corners(matrix: &glm::Mat4, bounding_box: &BoundingBox) -> [glm::Vec4; 8] {
+    let corners = bounding_box.corners();
+    let mut clip = [glm::Vec4::zeros(); 8];
+    for (clip, corner) in clip.iter_mut().zip(corners.iter()) {
+        *clip = matrix * glm::vec4(corner.x, corner.y, corner.z, 1.0);
+    }
+    clip
+}
+
+// True when every corner is outside the same side of the view, ignoring the far plane
+pub(crate) fn outside_frustum(clip: &[glm::Vec4; 8]) -> bool {
+    let outside = |test: &dyn Fn(&glm::Vec4) -> bool| clip.iter().all(test);
+    outside(&|corner| corner.x < -corner.w)
+        || outside(&|corner| corner.x > corner.w)
+        || outside(&|corner| corner.y < -corner.w)
+        || outside(&|corner| corner.y > corner.w)
+        || outside(&|corner| corner.w < OcclusionCulling::NEAR)
+}
+
+impl World {
+    // Culls meshes against the active camera for the renderers to skip this frame.
+    // Mesh bounds are brought up to date first, so they can be culled a branch at a time.
+    pub fn update_occlusion(&mut self, aspect_ratio: f32) -> Result<()> {
+        self.update_bounds()?;
+        dragonglass_profiling::profile_scope!("occlusion culling");
+        let mut occlusion = std::mem::take(&mut self.occlusion);
+        let result = self.cull(&mut occlusion, aspect_ratio);
+        self.occlusion = occlusion;
+        result
+    }
+
+    fn cull(&self, occlusion: &mut OcclusionCulling, aspect_ratio: f32) -> Result<()> {
+        occlusion.clear();
+        if !occlusion.enabled {
+            return Ok(());
+        }
+        let (projection, view) = match self.active_camera_matrices(aspect_ratio) {
+            Ok(matrices) => matrices,
+            Err(_) => return Ok(()),
+        };
+        let view_projection = projection * view;
+
+        let mut query = <(Entity, &Occluder)>::query().filter(!component::<Hidden>());
+        for (entity, occluder) in query.iter(&self.ecs) {
+            let bounds = match occluder.bounds.as_ref() {
+                Some(bounds) => bounds.clone(),
+                None => match self.mesh_bounds(*entity) {
+                    Some(bounds) => bounds,
+                    None => continue,
+                },
+            };
+            // Occluders without a mesh aren't in the bounds cache
+            let model = match self.bounds.entity_matrix(*entity) {
+                Some(matrix) => *matrix,
+                None => self.entity_global_transform_matrix(*entity)?,
+            };
+            occlusion.rasterize_box(&clip_corners(&(view_projection * model), &bounds));
+            occlusion.stats.occluders += 1;
+        }
+        occlusion.build_pyramid();
+
+        // Skinned meshes are animated beyond their bounds, so they are always drawn
+        let mut query = <Entity>::query()
+            .filter(component::<MeshRender>() & !component::<Hidden>() & !component::<Skin>());
+        let in_view = self
+            .bounds
+            .entities_in_view(&view_projection)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        for entity in query.iter(&self.ecs) {
+            let bounds = match self.mesh_bounds(*entity) {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            occlusion.stats.tested += 1;
+            if !in_view.contains(entity) {
+                occlusion.stats.frustum_culled += 1;
+                occlusion.culled.insert(*entity);
+                continue;
+            }
+            let model = match self.bounds.entity_matrix(*entity) {
+                Some(matrix) => *matrix,
+                None => continue,
+            };
+            let clip = clip_corners(&(view_projection * model), &bounds);
+            if outside_frustum(&clip) {
+                occlusion.stats.frustum_culled += 1;
+                occlusion.culled.insert(*entity);
+                continue;
+            }
+            // Meshes reaching behind the camera surround it, so they are never occluded
+            if clip.iter().any(|corner| corner.w < OcclusionCulling::NEAR) {
+                continue;
+            }
+            let mut min = glm::vec2(f32::MAX, f32::MAX);
+            let mut max = glm::vec2(f32::MIN, f32::MIN);
+            let mut nearest_inverse_depth = 0.0_f32;
+            for corner in clip.iter() {
+                let screen = OcclusionCulling::to_screen(corner);
+                min = glm::min2(&min, &screen.xy());
+                max = glm::max2(&max, &screen.xy());
+                nearest_inverse_depth = nearest_inverse_depth.max(screen.z);
+            }
+            if occlusion.is_occluded(&min, &max, nearest_inverse_depth) {
+                occlusion.stats.occluded += 1;
+                occlusion.culled.insert(*entity);
+            }
+        }
+        Ok(())
+    }
+
+    fn mesh_bounds(&self, entity: Entity) -> Option<BoundingBox> {
+        let entry = self.ecs.entry_ref(entity).ok()?;
+        let mesh_render = entry.get_component::<MeshRender>().ok()?;
+        self.assets
+            .geometry
+            .meshes
+            .get(&mesh_render.name)
+            .map(|mesh| mesh.bounding_box())
+    }
+}
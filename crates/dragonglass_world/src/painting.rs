@@ -0,0 +1,259 @@
+use crate::{Entity, MeshRender, Primitive, World};
+use anyhow::{Context, Result};
+use nalgebra_glm as glm;
+use rapier3d::geometry::Ray;
+use std::{collections::BTreeMap, ops::Range};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VertexBrush {
+    pub color: glm::Vec3,
+    // In world units
+    pub radius: f32,
+    // The fraction of the radius over which the brush fades out, 0.0 is a hard edge
+    pub falloff: f32,
+    // How far each dab blends the vertex colors towards the brush color
+    pub strength: f32,
+}
+
+impl Default for VertexBrush {
+    fn default() -> Self {
+        Self {
+            color: glm::vec3(1.0, 0.0, 0.0),
+            radius: 0.25,
+            falloff: 0.5,
+            strength: 0.25,
+        }
+    }
+}
+
+impl VertexBrush {
+    pub fn weight(&self, distance: f32) -> f32 {
+        if distance > self.radius {
+            return 0.0;
+        }
+        let inner_radius = self.radius * (1.0 - self.falloff.clamp(0.0, 1.0));
+        let fade = if distance <= inner_radius {
+            1.0
+        } else {
+            1.0 - glm::smoothstep(inner_radius, self.radius, distance)
+        };
+        fade * self.strength.clamp(0.0, 1.0)
+    }
+}
+
+// A vertex addressed relative to its primitive,
+// so edits stay valid when compaction moves the mesh within the geometry buffers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshVertex {
+    pub primitive: usize,
+    pub vertex: usize,
+}
+
+// Vertex colors for part of a mesh
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct VertexColors {
+    pub mesh: String,
+    pub colors: BTreeMap<MeshVertex, glm::Vec3>,
+}
+
+impl VertexColors {
+    pub fn new(mesh: &str) -> Self {
+        Self {
+            mesh: mesh.to_string(),
+            colors: BTreeMap::new(),
+        }
+    }
+
+    // Reads the current colors of the same vertices
+    pub fn current(&self, world: &World) -> Result<Self> {
+        let mesh = world
+            .assets
+            .geometry
+            .meshes
+            .get(&self.mesh)
+            .context(format!("Failed to find mesh '{}'!", self.mesh))?;
+        let mut colors = Self::new(&self.mesh);
+        for mesh_vertex in self.colors.keys() {
+            let primitive = &mesh.primitives[mesh_vertex.primitive];
+            let vertex =
+                &world.assets.geometry.vertices[primitive.first_vertex + mesh_vertex.vertex];
+            colors.colors.insert(*mesh_vertex, vertex.color_0);
+        }
+        Ok(colors)
+    }
+
+    // The range of the geometry's vertices that these colors are for
+    pub fn vertex_range(&self, world: &World) -> Option<Range<usize>> {
+        let mesh = world.assets.geometry.meshes.get(&self.mesh)?;
+        let vertices = self.colors.keys().map(|mesh_vertex| {
+            mesh.primitives[mesh_vertex.primitive].first_vertex + mesh_vertex.vertex
+        });
+        let start = vertices.clone().min()?;
+        let end = vertices.max()? + 1;
+        Some(start..end)
+    }
+
+    pub fn apply(&self, world: &mut World) -> Result<()> {
+        let geometry = &mut world.assets.geometry;
+        let mesh = geometry
+            .meshes
+            .get(&self.mesh)
+            .context(format!("Failed to find mesh '{}'!", self.mesh))?;
+        for (mesh_vertex, color) in self.colors.iter() {
+            let primitive = &mesh.primitives[mesh_vertex.primitive];
+            geometry.vertices[primitive.first_vertex + mesh_vertex.vertex].color_0 = *color;
+        }
+        Ok(())
+    }
+}
+
+// The distance along the ray to the closest triangle of the entity's mesh
+pub fn raycast_mesh(world: &World, entity: Entity, ray: &Ray) -> Result<Option<f32>> {
+    let mesh_name = match world
+        .sim
+        .ecs
+        .entry_ref(entity)?
+        .get_component::<MeshRender>()
+    {
+        Ok(mesh_render) => mesh_render.name.to_string(),
+        Err(_) => return Ok(None),
+    };
+    let mesh = match world.assets.geometry.meshes.get(&mesh_name) {
+        Some(mesh) => mesh,
+        None => return Ok(None),
+    };
+
+    // The ray is moved into the mesh's local space rather than transforming every vertex
+    let matrix = world.sim.entity_global_transform_matrix(entity)?;
+    let inverse = glm::inverse(&matrix);
+    let origin = ray.origin.coords;
+    let origin = glm::vec4_to_vec3(&(inverse * glm::vec4(origin.x, origin.y, origin.z, 1.0)));
+    let direction = glm::vec4_to_vec3(&(inverse * glm::vec4(ray.dir.x, ray.dir.y, ray.dir.z, 0.0)));
+
+    let mut closest: Option<f32> = None;
+    for primitive in mesh.primitives.iter() {
+        for [a, b, c] in primitive_triangles(world, primitive) {
+            let vertices = &world.assets.geometry.vertices;
+            if let Some(t) = intersect_triangle(
+                &origin,
+                &direction,
+                &vertices[a].position,
+                &vertices[b].position,
+                &vertices[c].position,
+            ) {
+                if closest.map_or(true, |closest| t < closest) {
+                    closest = Some(t);
+                }
+            }
+        }
+    }
+
+    // The direction was transformed without normalizing, so the distance is already in world units
+    Ok(closest)
+}
+
+// Blends the colors of the vertices within the brush around a world space point.
+// Linked instances share their vertices, so they are painted together.
+// Returns the colors the painted vertices had before this dab.
+pub fn paint_vertex_colors(
+    world: &mut World,
+    entity: Entity,
+    center: &glm::Vec3,
+    brush: &VertexBrush,
+) -> Result<VertexColors> {
+    let mesh_name = world
+        .sim
+        .ecs
+        .entry_ref(entity)?
+        .get_component::<MeshRender>()?
+        .name
+        .to_string();
+    let matrix = world.sim.entity_global_transform_matrix(entity)?;
+
+    let geometry = &mut world.assets.geometry;
+    let mesh = geometry
+        .meshes
+        .get(&mesh_name)
+        .context(format!("Failed to find mesh '{}'!", mesh_name))?;
+
+    let mut previous = VertexColors::new(&mesh_name);
+    for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+        let first_vertex = primitive.first_vertex;
+        let vertices =
+            &mut geometry.vertices[first_vertex..first_vertex + primitive.number_of_vertices];
+        for (vertex_index, vertex) in vertices.iter_mut().enumerate() {
+            let position =
+                matrix * glm::vec4(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+            let position = glm::vec4_to_vec3(&position);
+            let weight = brush.weight(glm::distance(&position, center));
+            if weight <= 0.0 {
+                continue;
+            }
+            previous.colors.insert(
+                MeshVertex {
+                    primitive: primitive_index,
+                    vertex: vertex_index,
+                },
+                vertex.color_0,
+            );
+            vertex.color_0 = glm::lerp(&vertex.color_0, &brush.color, weight);
+        }
+    }
+    Ok(previous)
+}
+
+fn primitive_triangles(world: &World, primitive: &Primitive) -> Vec<[usize; 3]> {
+    let geometry = &world.assets.geometry;
+    if primitive.number_of_indices > 0 {
+        geometry.indices[primitive.first_index..primitive.first_index + primitive.number_of_indices]
+            .chunks_exact(3)
+            .map(|triangle| {
+                [
+                    triangle[0] as usize,
+                    triangle[1] as usize,
+                    triangle[2] as usize,
+                ]
+            })
+            .collect()
+    } else {
+        (0..primitive.number_of_vertices / 3)
+            .map(|triangle| {
+                let first = primitive.first_vertex + triangle * 3;
+                [first, first + 1, first + 2]
+            })
+            .collect()
+    }
+}
+
+// Möller–Trumbore, returning the distance along the direction to the hit
+fn intersect_triangle(
+    origin: &glm::Vec3,
+    direction: &glm::Vec3,
+    a: &glm::Vec3,
+    b: &glm::Vec3,
+    c: &glm::Vec3,
+) -> Option<f32> {
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = direction.cross(&edge_2);
+    let determinant = edge_1.dot(&p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = origin - a;
+    let u = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&edge_1);
+    let v = direction.dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge_2.dot(&q) * inverse_determinant;
+    (t > 0.0).then(|| t)
+}
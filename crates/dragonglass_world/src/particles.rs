@@ -0,0 +1,176 @@
+use crate::{Rng, SimWorld};
+use anyhow::Result;
+use legion::EntityStore;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParticleBlend {
+    // Overlapping particles brighten each other, for fire, sparks, and muzzle flashes
+    Additive,
+    // Particles cover what is behind them, for smoke and dust
+    Alpha,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Particle {
+    pub position: glm::Vec3,
+    pub velocity: glm::Vec3,
+    // In seconds
+    pub age: f32,
+}
+
+// Spawns particles at its entity's position that fly out within a cone around the entity's y axis.
+// Particles are simulated in world space, so they trail behind emitters that move.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticleEmitter {
+    // Whether new particles are spawned continuously. Bursts are spawned either way.
+    pub emitting: bool,
+    // Particles per second
+    pub spawn_rate: f32,
+    // In seconds
+    pub lifetime: f32,
+    pub speed: f32,
+    // The angle between the cone's axis and its sides, in radians
+    pub spread: f32,
+    // Applied to every particle, such as gravity or a rising force for smoke
+    pub acceleration: glm::Vec3,
+    // Colors and sizes are interpolated over each particle's life
+    pub start_color: glm::Vec4,
+    pub end_color: glm::Vec4,
+    pub start_size: f32,
+    pub end_size: f32,
+    // The index of the texture in the world's assets, or -1 for solid squares
+    pub texture_index: i32,
+    pub blend: ParticleBlend,
+    // New particles are not spawned while this many are alive
+    pub max_particles: usize,
+    #[serde(skip)]
+    particles: Vec<Particle>,
+    // The fraction of a particle that was due to spawn on the previous update
+    #[serde(skip)]
+    spawn_remainder: f32,
+    #[serde(skip)]
+    pending_burst: usize,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            emitting: true,
+            spawn_rate: 20.0,
+            lifetime: 2.0,
+            speed: 2.0,
+            spread: 0.3,
+            acceleration: glm::Vec3::zeros(),
+            start_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            end_color: glm::vec4(1.0, 1.0, 1.0, 0.0),
+            start_size: 0.2,
+            end_size: 0.05,
+            texture_index: -1,
+            blend: ParticleBlend::Alpha,
+            max_particles: 1000,
+            particles: Vec::new(),
+            spawn_remainder: 0.0,
+            pending_burst: 0,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    // Spawns this many particles at once on the next update
+    pub fn burst(&mut self, count: usize) {
+        self.pending_burst += count;
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.spawn_remainder = 0.0;
+        self.pending_burst = 0;
+    }
+
+    // How far through its life the particle is, from zero to one
+    pub fn life(&self, particle: &Particle) -> f32 {
+        (particle.age / self.lifetime.max(f32::EPSILON)).min(1.0)
+    }
+
+    pub fn color(&self, particle: &Particle) -> glm::Vec4 {
+        glm::lerp(&self.start_color, &self.end_color, self.life(particle))
+    }
+
+    pub fn size(&self, particle: &Particle) -> f32 {
+        glm::lerp_scalar(self.start_size, self.end_size, self.life(particle))
+    }
+
+    pub fn update(&mut self, delta_time: f32, transform: &glm::Mat4, rng: &mut Rng) {
+        let lifetime = self.lifetime;
+        let acceleration = self.acceleration;
+        self.particles
+            .retain(|particle| particle.age + delta_time < lifetime);
+        for particle in self.particles.iter_mut() {
+            particle.age += delta_time;
+            particle.velocity += acceleration * delta_time;
+            particle.position += particle.velocity * delta_time;
+        }
+
+        let mut count = std::mem::take(&mut self.pending_burst);
+        if self.emitting {
+            let due = self.spawn_rate.max(0.0) * delta_time + self.spawn_remainder;
+            count += due.floor() as usize;
+            self.spawn_remainder = due.fract();
+        }
+        count = count.min(self.max_particles.saturating_sub(self.particles.len()));
+        for _ in 0..count {
+            let particle = self.spawn(transform, rng);
+            self.particles.push(particle);
+        }
+    }
+
+    fn spawn(&self, transform: &glm::Mat4, rng: &mut Rng) -> Particle {
+        // Uniformly distributed over the cap of a sphere
+        let cos_theta = rng.range_f32(self.spread.min(PI).cos(), 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = rng.range_f32(0.0, 2.0 * PI);
+        let direction = glm::vec3(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+        let direction = glm::mat4_to_mat3(transform) * direction;
+        let direction = if direction.norm() > f32::EPSILON {
+            direction.normalize()
+        } else {
+            glm::Vec3::y()
+        };
+        Particle {
+            position: transform.column(3).xyz(),
+            velocity: direction * self.speed,
+            age: 0.0,
+        }
+    }
+}
+
+impl SimWorld {
+    pub fn update_particles(&mut self) -> Result<()> {
+        let mut emitters = Vec::new();
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                let entry = self.ecs.entry_ref(entity)?;
+                if entry.get_component::<ParticleEmitter>().is_ok() {
+                    emitters.push((entity, self.global_transform(graph, node_index)?));
+                }
+                Ok(())
+            })?;
+        }
+
+        let delta_time = self.clock.delta();
+        for (entity, transform) in emitters.into_iter() {
+            let mut entry = self.ecs.entry_mut(entity)?;
+            let emitter = entry.get_component_mut::<ParticleEmitter>()?;
+            emitter.update(delta_time, &transform, &mut self.rng);
+        }
+        Ok(())
+    }
+}
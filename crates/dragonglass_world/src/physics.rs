@@ -1,13 +1,19 @@
 pub use rapier3d;
 
+use crate::{Entity, SimWorld};
+use anyhow::{Context, Result};
+use legion::EntityStore;
 use rapier3d::{
-    dynamics::{CCDSolver, IntegrationParameters, RigidBodySet},
-    geometry::{BroadPhase, ColliderSet, NarrowPhase},
+    dynamics::{
+        CCDSolver, IntegrationParameters, RigidBodyActivation, RigidBodyBuilder, RigidBodySet,
+    },
+    geometry::{BroadPhase, ColliderBuilder, ColliderSet, NarrowPhase},
     na::Vector3,
     pipeline::{PhysicsPipeline, QueryPipeline},
-    prelude::{ImpulseJointSet, IslandManager, MultibodyJointSet, RigidBodyHandle},
+    prelude::{ImpulseJointSet, IslandManager, MultibodyJointSet, RigidBodyHandle, RigidBodyType},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 pub type Handle = rapier3d::dynamics::RigidBodyHandle;
 pub type ColliderHandle = rapier3d::geometry::ColliderHandle;
 
@@ -26,6 +32,109 @@ impl RigidBody {
     }
 }
 
+// How an entity's colliders slide, bounce, and weigh.
+// Entities without the component use rapier's defaults.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsMaterial {
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+        }
+    }
+}
+
+impl PhysicsMaterial {
+    pub fn apply(&self, builder: ColliderBuilder) -> ColliderBuilder {
+        builder
+            .friction(self.friction)
+            .restitution(self.restitution)
+            .density(self.density)
+    }
+}
+
+// How an entity's rigid body moves, damps, and sleeps.
+// Entities without the component get rapier's defaults.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RigidBodySettings {
+    // Continuous collision detection, which keeps fast bodies from tunneling through thin colliders
+    pub ccd_enabled: bool,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    pub gravity_scale: f32,
+    pub lock_rotations: bool,
+    pub can_sleep: bool,
+    // The velocities a body has to stay under for a while before it falls asleep
+    pub sleep_linear_threshold: f32,
+    pub sleep_angular_threshold: f32,
+}
+
+impl Default for RigidBodySettings {
+    fn default() -> Self {
+        Self {
+            ccd_enabled: false,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            gravity_scale: 1.0,
+            lock_rotations: false,
+            can_sleep: true,
+            sleep_linear_threshold: RigidBodyActivation::default_linear_threshold(),
+            sleep_angular_threshold: RigidBodyActivation::default_angular_threshold(),
+        }
+    }
+}
+
+impl RigidBodySettings {
+    // Builds a new body with the settings.
+    // The builder has no way to set the sleep thresholds, so they are set on the built body.
+    pub fn build(&self, builder: RigidBodyBuilder) -> rapier3d::dynamics::RigidBody {
+        let builder = builder
+            .ccd_enabled(self.ccd_enabled)
+            .linear_damping(self.linear_damping)
+            .angular_damping(self.angular_damping)
+            .gravity_scale(self.gravity_scale)
+            .can_sleep(self.can_sleep);
+        let builder = if self.lock_rotations {
+            builder.lock_rotations()
+        } else {
+            builder
+        };
+        let mut body = builder.build();
+        self.apply_activation(body.activation_mut());
+        body
+    }
+
+    // Updates a body that already exists, waking it so the change takes effect
+    pub fn apply_to_body(&self, body: &mut rapier3d::dynamics::RigidBody) {
+        body.enable_ccd(self.ccd_enabled);
+        body.set_linear_damping(self.linear_damping);
+        body.set_angular_damping(self.angular_damping);
+        body.set_gravity_scale(self.gravity_scale, true);
+        body.lock_rotations(self.lock_rotations, true);
+        self.apply_activation(body.activation_mut());
+        body.wake_up(true);
+    }
+
+    fn apply_activation(&self, activation: &mut RigidBodyActivation) {
+        if self.can_sleep {
+            activation.linear_threshold = self.sleep_linear_threshold;
+            activation.angular_threshold = self.sleep_angular_threshold;
+        } else {
+            // Negative thresholds are never reached, which is how rapier keeps bodies awake
+            activation.linear_threshold = -1.0;
+            activation.angular_threshold = -1.0;
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct WorldPhysics {
     pub gravity: Vector3<f32>,
@@ -42,6 +151,9 @@ pub struct WorldPhysics {
     pub ccd_solver: CCDSolver,
     #[serde(skip)]
     pub pipeline: PhysicsPipeline,
+    // Pairs of colliders that were touching after the last step
+    #[serde(skip)]
+    touching: HashSet<(ColliderHandle, ColliderHandle)>,
 }
 
 impl Default for WorldPhysics {
@@ -65,17 +177,22 @@ impl WorldPhysics {
             query_pipeline: QueryPipeline::default(),
             ccd_solver: CCDSolver::new(),
             pipeline: PhysicsPipeline::new(),
+            touching: HashSet::new(),
         }
     }
 
-    pub fn remove_rigid_body(&mut self, handle: RigidBodyHandle) {
+    // Its colliders and joints are removed along with it
+    pub fn remove_rigid_body(
+        &mut self,
+        handle: RigidBodyHandle,
+    ) -> Option<rapier3d::dynamics::RigidBody> {
         self.bodies.remove(
             handle,
             &mut self.islands,
             &mut self.colliders,
             &mut self.impulse_joints,
             &mut self.multibody_joints,
-        );
+        )
     }
 
     pub fn set_gravity(&mut self, gravity: Vector3<f32>) {
@@ -103,4 +220,241 @@ impl WorldPhysics {
         self.query_pipeline
             .update(&self.islands, &self.bodies, &self.colliders);
     }
+
+    // The pairs of colliders that started touching since the last call, followed by those that stopped.
+    // Contacts and sensor intersections both count as touching.
+    // Pairs are sorted by handle so the order doesn't change between runs, which replays rely on.
+    pub fn touching_changes(
+        &mut self,
+    ) -> (
+        Vec<(ColliderHandle, ColliderHandle)>,
+        Vec<(ColliderHandle, ColliderHandle)>,
+    ) {
+        let touching = self.touching_pairs();
+        let mut started = touching
+            .difference(&self.touching)
+            .copied()
+            .collect::<Vec<_>>();
+        let mut stopped = self
+            .touching
+            .difference(&touching)
+            .copied()
+            .collect::<Vec<_>>();
+        let key = |(first, second): &(ColliderHandle, ColliderHandle)| {
+            (first.into_raw_parts(), second.into_raw_parts())
+        };
+        started.sort_by_key(key);
+        stopped.sort_by_key(key);
+        self.touching = touching;
+        (started, stopped)
+    }
+
+    // Treats the colliders touching now as already reported,
+    // so restoring a saved physics state doesn't report its contacts as new
+    pub fn sync_touching(&mut self) {
+        self.touching = self.touching_pairs();
+    }
+
+    fn touching_pairs(&self) -> HashSet<(ColliderHandle, ColliderHandle)> {
+        let contacts = self
+            .narrow_phase
+            .contact_pairs()
+            .filter(|pair| pair.has_any_active_contact)
+            .map(|pair| (pair.collider1, pair.collider2));
+        let intersections = self
+            .narrow_phase
+            .intersection_pairs()
+            .filter(|(_, _, intersecting)| *intersecting)
+            .map(|(first, second, _)| (first, second));
+        contacts.chain(intersections).collect()
+    }
+
+    pub fn stats(&self) -> PhysicsStats {
+        let mut stats = PhysicsStats {
+            colliders: self.colliders.len(),
+            impulse_joints: self.impulse_joints.len(),
+            ..Default::default()
+        };
+        for (_, body) in self.bodies.iter() {
+            match body.body_type() {
+                RigidBodyType::Dynamic if body.is_sleeping() => stats.sleeping_bodies += 1,
+                RigidBodyType::Dynamic => stats.active_bodies += 1,
+                RigidBodyType::Static => stats.static_bodies += 1,
+                _ => stats.kinematic_bodies += 1,
+            }
+        }
+        stats.contact_pairs = self
+            .narrow_phase
+            .contact_pairs()
+            .filter(|pair| pair.has_any_active_contact)
+            .count();
+        let (islands, active_islands) = self.count_islands();
+        stats.islands = islands;
+        stats.active_islands = active_islands;
+        stats
+    }
+
+    // Islands are groups of dynamic bodies connected through contacts or joints.
+    // Static and kinematic bodies don't join islands together, so they are left out.
+    // Returns the number of islands and how many of them have a body awake.
+    fn count_islands(&self) -> (usize, usize) {
+        let handles = self
+            .bodies
+            .iter()
+            .filter(|(_, body)| body.is_dynamic())
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        let offsets = handles
+            .iter()
+            .enumerate()
+            .map(|(offset, handle)| (*handle, offset))
+            .collect::<HashMap<_, _>>();
+        let mut parents = (0..handles.len()).collect::<Vec<_>>();
+
+        let mut join = |first: Option<RigidBodyHandle>, second: Option<RigidBodyHandle>| {
+            let first = first.and_then(|handle| offsets.get(&handle));
+            let second = second.and_then(|handle| offsets.get(&handle));
+            if let (Some(first), Some(second)) = (first, second) {
+                let (first, second) = (
+                    island_root(&mut parents, *first),
+                    island_root(&mut parents, *second),
+                );
+                parents[first] = second;
+            }
+        };
+
+        let body_of = |collider: ColliderHandle| {
+            self.colliders
+                .get(collider)
+                .and_then(|collider| collider.parent())
+        };
+        for pair in self.narrow_phase.contact_pairs() {
+            if pair.has_any_active_contact {
+                join(body_of(pair.collider1), body_of(pair.collider2));
+            }
+        }
+        for (_, joint) in self.impulse_joints.iter() {
+            join(Some(joint.body1), Some(joint.body2));
+        }
+
+        let mut islands = HashSet::new();
+        let mut active_islands = HashSet::new();
+        for (offset, handle) in handles.iter().enumerate() {
+            let island = island_root(&mut parents, offset);
+            islands.insert(island);
+            if !self.bodies[*handle].is_sleeping() {
+                active_islands.insert(island);
+            }
+        }
+        (islands.len(), active_islands.len())
+    }
+
+    pub fn wake_all(&mut self) {
+        self.bodies
+            .iter_mut()
+            .for_each(|(_, body)| body.wake_up(true));
+    }
+
+    pub fn sleep_all(&mut self) {
+        self.bodies
+            .iter_mut()
+            .filter(|(_, body)| body.is_dynamic())
+            .for_each(|(_, body)| body.sleep());
+    }
+}
+
+fn island_root(parents: &mut [usize], mut offset: usize) -> usize {
+    while parents[offset] != offset {
+        parents[offset] = parents[parents[offset]];
+        offset = parents[offset];
+    }
+    offset
+}
+
+// Sent when the colliders of two entities' rigid bodies start or stop touching
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CollisionEvent {
+    Started(Entity, Entity),
+    Stopped(Entity, Entity),
+}
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct PhysicsStats {
+    pub active_bodies: usize,
+    pub sleeping_bodies: usize,
+    pub kinematic_bodies: usize,
+    pub static_bodies: usize,
+    pub islands: usize,
+    pub active_islands: usize,
+    pub colliders: usize,
+    pub impulse_joints: usize,
+    pub contact_pairs: usize,
+}
+
+impl SimWorld {
+    pub fn entity_rigid_body_settings(&self, entity: Entity) -> RigidBodySettings {
+        self.ecs
+            .entry_ref(entity)
+            .ok()
+            .and_then(|entry| entry.get_component::<RigidBodySettings>().ok().copied())
+            .unwrap_or_default()
+    }
+
+    // Rigid bodies added later use the settings, and the entity's existing rigid body is updated
+    pub fn set_rigid_body_settings(
+        &mut self,
+        entity: Entity,
+        settings: RigidBodySettings,
+    ) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(entity)
+            .context("Failed to find the entity to set the rigid body settings of")?;
+        entry.add_component(settings);
+        let handle = match entry.get_component::<RigidBody>() {
+            Ok(rigid_body) => rigid_body.handle,
+            Err(_) => return Ok(()),
+        };
+        if let Some(body) = self.physics.bodies.get_mut(handle) {
+            settings.apply_to_body(body);
+        }
+        Ok(())
+    }
+
+    pub fn entity_physics_material(&self, entity: Entity) -> PhysicsMaterial {
+        self.ecs
+            .entry_ref(entity)
+            .ok()
+            .and_then(|entry| entry.get_component::<PhysicsMaterial>().ok().copied())
+            .unwrap_or_default()
+    }
+
+    // Colliders added later use the material, and those already on the entity's rigid body are updated
+    pub fn set_physics_material(
+        &mut self,
+        entity: Entity,
+        material: PhysicsMaterial,
+    ) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(entity)
+            .context("Failed to find the entity to set the physics material of")?;
+        entry.add_component(material);
+        let handle = match entry.get_component::<RigidBody>() {
+            Ok(rigid_body) => rigid_body.handle,
+            Err(_) => return Ok(()),
+        };
+        let colliders = match self.physics.bodies.get(handle) {
+            Some(body) => body.colliders().to_vec(),
+            None => return Ok(()),
+        };
+        for collider_handle in colliders {
+            if let Some(collider) = self.physics.colliders.get_mut(collider_handle) {
+                collider.set_friction(material.friction);
+                collider.set_restitution(material.restitution);
+                collider.set_density(material.density);
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,294 @@
+use crate::{ColliderHandle, Entity, Handle, RigidBody, SimWorld};
+use anyhow::Result;
+use legion::IntoQuery;
+use nalgebra_glm as glm;
+use rapier3d::{
+    geometry::SharedShape,
+    na::{Isometry3, Vector3},
+    prelude::RigidBodyType,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Marks the entities physics detail is kept around, usually the player.
+// The active camera is used when no entity has it.
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct PhysicsFocus;
+
+// Lets a dynamic rigid body trade accuracy for speed when it is far from every focus.
+// Simplified bodies collide using the bounding boxes of their colliders,
+// and frozen bodies stop simulating until a focus comes back within range.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhysicsLod {
+    pub simplify_distance: f32,
+    pub freeze_distance: f32,
+    // Bodies outside the camera's view are simplified even when they are close
+    pub simplify_offscreen: bool,
+    level: PhysicsLodLevel,
+}
+
+impl Default for PhysicsLod {
+    fn default() -> Self {
+        Self::new(30.0, 80.0)
+    }
+}
+
+impl PhysicsLod {
+    // A body needs to come this fraction of a distance closer to regain detail,
+    // so bodies sitting right on a boundary don't swap shapes every frame
+    pub const HYSTERESIS: f32 = 0.9;
+
+    pub fn new(simplify_distance: f32, freeze_distance: f32) -> Self {
+        Self {
+            simplify_distance,
+            freeze_distance,
+            simplify_offscreen: false,
+            level: PhysicsLodLevel::Full,
+        }
+    }
+
+    pub fn detail(&self) -> PhysicsDetail {
+        match self.level {
+            PhysicsLodLevel::Full => PhysicsDetail::Full,
+            PhysicsLodLevel::Simplified { .. } => PhysicsDetail::Simplified,
+            PhysicsLodLevel::Frozen { .. } => PhysicsDetail::Frozen,
+        }
+    }
+
+    // Colliders get new handles when their body is removed and restored
+    pub(crate) fn remap_colliders(&mut self, handles: &HashMap<ColliderHandle, ColliderHandle>) {
+        let shapes = match &mut self.level {
+            PhysicsLodLevel::Full => return,
+            PhysicsLodLevel::Simplified { shapes } => shapes,
+            PhysicsLodLevel::Frozen { shapes, .. } => shapes,
+        };
+        for (handle, _) in shapes.iter_mut() {
+            *handle = handles.get(handle).copied().unwrap_or(*handle);
+        }
+    }
+
+    fn target_detail(&self, distance: f32, onscreen: bool) -> PhysicsDetail {
+        let current = self.detail();
+        let threshold = |distance: f32, detail: PhysicsDetail| {
+            if current >= detail {
+                distance * Self::HYSTERESIS
+            } else {
+                distance
+            }
+        };
+        if distance > threshold(self.freeze_distance, PhysicsDetail::Frozen) {
+            PhysicsDetail::Frozen
+        } else if distance > threshold(self.simplify_distance, PhysicsDetail::Simplified)
+            || (self.simplify_offscreen && !onscreen)
+        {
+            PhysicsDetail::Simplified
+        } else {
+            PhysicsDetail::Full
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PhysicsDetail {
+    Full,
+    Simplified,
+    Frozen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum PhysicsLodLevel {
+    Full,
+    // The original shapes of the colliders that were swapped for bounding boxes
+    Simplified {
+        shapes: Vec<(ColliderHandle, SharedShape)>,
+    },
+    // Frozen bodies are simplified as well, and resume with the velocities they had
+    Frozen {
+        shapes: Vec<(ColliderHandle, SharedShape)>,
+        linear_velocity: Vector3<f32>,
+        angular_velocity: Vector3<f32>,
+    },
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PhysicsLodStats {
+    pub full: usize,
+    pub simplified: usize,
+    pub frozen: usize,
+}
+
+impl SimWorld {
+    // Moves every body with a PhysicsLod to the level of detail its distance from the nearest focus calls for.
+    // Passing the camera's view projection lets bodies marked to do so be simplified when off-screen.
+    pub fn update_physics_lod(&mut self, view_projection: Option<&glm::Mat4>) -> Result<()> {
+        dragonglass_profiling::profile_scope!("physics lod");
+        let focus_points = self.physics_focus_points()?;
+        if focus_points.is_empty() {
+            return Ok(());
+        }
+
+        let mut query = <(Entity, &RigidBody, &PhysicsLod)>::query();
+        let bodies = query
+            .iter(&self.ecs)
+            .map(|(entity, rigid_body, lod)| (*entity, rigid_body.handle, lod.clone()))
+            .collect::<Vec<_>>();
+
+        for (entity, handle, mut lod) in bodies.into_iter() {
+            // Only dynamic bodies are managed, and frozen ones stay static until they thaw
+            let position = match self.physics.bodies.get(handle) {
+                Some(body) if body.is_dynamic() || lod.detail() == PhysicsDetail::Frozen => {
+                    *body.translation()
+                }
+                _ => continue,
+            };
+            let distance = focus_points
+                .iter()
+                .map(|point| glm::distance(point, &position))
+                .fold(f32::MAX, f32::min);
+            let onscreen = view_projection.map_or(true, |matrix| is_onscreen(matrix, &position));
+
+            let target = lod.target_detail(distance, onscreen);
+            if target == lod.detail() {
+                continue;
+            }
+            self.change_physics_detail(handle, &mut lod, target);
+            if let Some(mut entry) = self.ecs.entry(entity) {
+                if let Ok(component) = entry.get_component_mut::<PhysicsLod>() {
+                    *component = lod;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn physics_lod_stats(&self) -> PhysicsLodStats {
+        let mut stats = PhysicsLodStats::default();
+        let mut query = <&PhysicsLod>::query();
+        for lod in query.iter(&self.ecs) {
+            match lod.detail() {
+                PhysicsDetail::Full => stats.full += 1,
+                PhysicsDetail::Simplified => stats.simplified += 1,
+                PhysicsDetail::Frozen => stats.frozen += 1,
+            }
+        }
+        stats
+    }
+
+    fn physics_focus_points(&self) -> Result<Vec<glm::Vec3>> {
+        let mut query = <(Entity, &PhysicsFocus)>::query();
+        let entities = query
+            .iter(&self.ecs)
+            .map(|(entity, _)| *entity)
+            .collect::<Vec<_>>();
+        let mut points = entities
+            .into_iter()
+            .map(|entity| Ok(self.entity_global_transform(entity)?.translation))
+            .collect::<Result<Vec<_>>>()?;
+        if points.is_empty() {
+            if let Ok(camera) = self.active_camera() {
+                points.push(self.entity_global_transform(camera)?.translation);
+            }
+        }
+        Ok(points)
+    }
+
+    fn change_physics_detail(
+        &mut self,
+        handle: Handle,
+        lod: &mut PhysicsLod,
+        target: PhysicsDetail,
+    ) {
+        let physics = &mut self.physics;
+        let body = match physics.bodies.get_mut(handle) {
+            Some(body) => body,
+            None => return,
+        };
+
+        let level = std::mem::replace(&mut lod.level, PhysicsLodLevel::Full);
+        let shapes = match level {
+            PhysicsLodLevel::Full => simplify_colliders(body.colliders(), &mut physics.colliders),
+            PhysicsLodLevel::Simplified { shapes } => shapes,
+            PhysicsLodLevel::Frozen {
+                shapes,
+                linear_velocity,
+                angular_velocity,
+            } => {
+                body.set_body_type(RigidBodyType::Dynamic);
+                body.set_linvel(linear_velocity, true);
+                body.set_angvel(angular_velocity, true);
+                shapes
+            }
+        };
+
+        lod.level = match target {
+            PhysicsDetail::Full => {
+                for (collider_handle, shape) in shapes.into_iter() {
+                    if let Some(collider) = physics.colliders.get_mut(collider_handle) {
+                        collider.set_shape(shape);
+                    }
+                }
+                body.wake_up(true);
+                PhysicsLodLevel::Full
+            }
+            PhysicsDetail::Simplified => PhysicsLodLevel::Simplified { shapes },
+            PhysicsDetail::Frozen => {
+                let linear_velocity = *body.linvel();
+                let angular_velocity = *body.angvel();
+                body.set_body_type(RigidBodyType::Static);
+                PhysicsLodLevel::Frozen {
+                    shapes,
+                    linear_velocity,
+                    angular_velocity,
+                }
+            }
+        };
+    }
+}
+
+// Swaps meshes and hulls for boxes, returning the shapes that were replaced.
+// Colliders that are already primitives are left alone.
+fn simplify_colliders(
+    handles: &[ColliderHandle],
+    colliders: &mut rapier3d::geometry::ColliderSet,
+) -> Vec<(ColliderHandle, SharedShape)> {
+    let mut shapes = Vec::new();
+    for handle in handles.iter() {
+        let collider = match colliders.get_mut(*handle) {
+            Some(collider) => collider,
+            None => continue,
+        };
+        let shape = collider.shared_shape().clone();
+        if shape.as_ball().is_some() || shape.as_cuboid().is_some() || shape.as_capsule().is_some()
+        {
+            continue;
+        }
+
+        let aabb = shape.compute_local_aabb();
+        let half_extents = aabb.half_extents();
+        let cuboid = SharedShape::cuboid(half_extents.x, half_extents.y, half_extents.z);
+        let center = aabb.center().coords;
+        let simplified = if center.norm() > f32::EPSILON {
+            SharedShape::compound(vec![(
+                Isometry3::translation(center.x, center.y, center.z),
+                cuboid,
+            )])
+        } else {
+            cuboid
+        };
+        collider.set_shape(simplified);
+        shapes.push((*handle, shape));
+    }
+    shapes
+}
+
+// Only the body's origin is tested, so a small margin keeps large bodies
+// from being simplified while their edges are still in view
+fn is_onscreen(view_projection: &glm::Mat4, position: &glm::Vec3) -> bool {
+    const MARGIN: f32 = 1.2;
+    let clip = view_projection * glm::vec4(position.x, position.y, position.z, 1.0);
+    if clip.w <= 0.0 {
+        return false;
+    }
+    let ndc = clip.xyz() / clip.w;
+    ndc.x.abs() <= MARGIN && ndc.y.abs() <= MARGIN
+}
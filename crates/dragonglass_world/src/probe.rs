@@ -0,0 +1,32 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+// The region around a reflection probe that it lights, relative to the probe's position
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum ProbeBounds {
+    Box { half_extents: glm::Vec3 },
+    Sphere { radius: f32 },
+}
+
+// Captures the scene around it into a cubemap that replaces the skybox's lighting
+// for objects within its bounds. Probes are only captured when they are baked.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct ReflectionProbe {
+    pub bounds: ProbeBounds,
+    // The size of each face of the captured cubemap
+    pub resolution: u32,
+    // How far inside the bounds objects blend from the skybox's lighting to the probe's
+    pub blend_distance: f32,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            bounds: ProbeBounds::Box {
+                half_extents: glm::vec3(5.0, 5.0, 5.0),
+            },
+            resolution: 256,
+            blend_distance: 1.0,
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use crate::{
+    load_model_asset, Animation, Camera, Entity, Fnv1a, Joint, Light, Material, Mesh, MeshRender,
+    ModelAsset, Name, SceneGraph, Skin, Texture, TextureCompression, Transform, Vertex, World,
+    ENTITY_SERIALIZER,
+};
+use anyhow::{ensure, Context, Result};
+use legion::{serialize::set_entity_serializer, EntityStore};
+use log::{info, warn};
+use petgraph::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// A model converted into the world's runtime representation, so loading it skips decoding images,
+// compressing textures and generating their mips, building geometry, and generating tangents.
+// Material texture indices, primitive offsets, and mesh indices are local to the model until it is loaded into a world.
+#[derive(Serialize, Deserialize)]
+pub struct ProcessedModel {
+    version: u32,
+    pub textures: Vec<Texture>,
+    // Identical vertices within each primitive are stored once
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub materials: Vec<Material>,
+    pub meshes: Vec<Mesh>,
+    pub nodes: Vec<ProcessedNode>,
+    pub animations: Vec<Animation>,
+    // The entities the nodes were processed as, which animation channels and skin joints refer to
+    entities: Vec<Entity>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProcessedNode {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub parent: Option<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<Camera>,
+    pub light: Option<Light>,
+    pub skin: Option<Skin>,
+}
+
+impl ProcessedModel {
+    // Bumped whenever the processed format or the processing changes so stale artifacts are processed again
+    pub const VERSION: u32 = 2;
+
+    pub fn process(asset: &ModelAsset, compression: TextureCompression) -> Result<Self> {
+        // The model is loaded into a world of its own and read back out of it
+        let mut world = World::new()?;
+        world.assets.texture_compression = compression;
+        let entities = load_model_asset(asset, &mut world)?;
+        let indices = entities
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| (*entity, index))
+            .collect::<HashMap<_, _>>();
+
+        let mut parents = HashMap::new();
+        for graph in world.scene.graphs.iter() {
+            for edge in graph.0.edge_indices() {
+                if let Some((parent, child)) = graph.0.edge_endpoints(edge) {
+                    parents.insert(graph[child], graph[parent]);
+                }
+            }
+        }
+
+        // Textures left uncompressed get their mips now, so loading them doesn't generate them on the gpu
+        let mut textures = std::mem::take(&mut world.assets.textures);
+        for texture in textures.iter_mut() {
+            texture.generate_mips()?;
+        }
+
+        let mut model = Self {
+            version: Self::VERSION,
+            textures,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            materials: std::mem::take(&mut world.assets.materials),
+            meshes: Vec::new(),
+            nodes: Vec::new(),
+            animations: std::mem::take(&mut world.assets.animations),
+            entities: entities.clone(),
+        };
+
+        let mut mesh_indices = HashMap::new();
+        for entity in entities.iter() {
+            let entry = world.ecs.entry_ref(*entity)?;
+            let mesh = match entry.get_component::<MeshRender>() {
+                Ok(mesh_render) => match mesh_indices.get(&mesh_render.name) {
+                    Some(index) => Some(*index),
+                    None => {
+                        let mesh = world
+                            .assets
+                            .geometry
+                            .meshes
+                            .get(&mesh_render.name)
+                            .context("Failed to find the mesh of a processed node!")?;
+                        model.add_mesh(
+                            mesh,
+                            &world.assets.geometry.vertices,
+                            &world.assets.geometry.indices,
+                        );
+                        mesh_indices.insert(mesh_render.name.to_string(), model.meshes.len() - 1);
+                        Some(model.meshes.len() - 1)
+                    }
+                },
+                Err(_) => None,
+            };
+            model.nodes.push(ProcessedNode {
+                name: entry
+                    .get_component::<Name>()
+                    .ok()
+                    .map(|name| name.0.to_string()),
+                transform: entry
+                    .get_component::<Transform>()
+                    .ok()
+                    .copied()
+                    .unwrap_or_default(),
+                parent: parents
+                    .get(entity)
+                    .and_then(|parent| indices.get(parent))
+                    .copied(),
+                mesh,
+                camera: entry.get_component::<Camera>().ok().cloned(),
+                light: entry.get_component::<Light>().ok().copied(),
+                skin: entry.get_component::<Skin>().ok().cloned(),
+            });
+        }
+
+        Ok(model)
+    }
+
+    // Copies a mesh's primitives out of the world's geometry, with their indices made relative to the model
+    fn add_mesh(&mut self, mesh: &Mesh, vertices: &[Vertex], indices: &[u32]) {
+        let mut mesh = mesh.clone();
+        for primitive in mesh.primitives.iter_mut() {
+            let source_vertices = &vertices
+                [primitive.first_vertex..primitive.first_vertex + primitive.number_of_vertices];
+            let source_indices = &indices
+                [primitive.first_index..primitive.first_index + primitive.number_of_indices];
+            let first_vertex = self.vertices.len();
+            let first_index = self.indices.len();
+
+            // Morph targets are stored per vertex, so their primitives are kept as they are
+            if primitive.morph_targets.is_empty() && !source_indices.is_empty() {
+                let mut unique = HashMap::new();
+                for index in source_indices.iter() {
+                    let vertex = source_vertices[*index as usize - primitive.first_vertex];
+                    let next = (self.vertices.len() - first_vertex) as u32;
+                    let local = *unique.entry(vertex_key(&vertex)).or_insert_with(|| {
+                        self.vertices.push(vertex);
+                        next
+                    });
+                    self.indices.push(first_vertex as u32 + local);
+                }
+            } else {
+                self.vertices.extend_from_slice(source_vertices);
+                self.indices.extend(
+                    source_indices
+                        .iter()
+                        .map(|index| *index - primitive.first_vertex as u32 + first_vertex as u32),
+                );
+            }
+
+            primitive.first_vertex = first_vertex;
+            primitive.first_index = first_index;
+            primitive.number_of_vertices = self.vertices.len() - first_vertex;
+            primitive.number_of_indices = self.indices.len() - first_index;
+        }
+        self.meshes.push(mesh);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let bytes = set_entity_serializer(&*ENTITY_SERIALIZER, || bincode::serialize(self))?;
+        Ok(zstd::stream::encode_all(bytes.as_slice(), 0)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bytes = zstd::stream::decode_all(bytes)?;
+        let model: Self =
+            set_entity_serializer(&*ENTITY_SERIALIZER, || bincode::deserialize(&bytes))?;
+        ensure!(
+            model.version == Self::VERSION,
+            "Processed model is version {}, expected version {}",
+            model.version,
+            Self::VERSION
+        );
+        Ok(model)
+    }
+}
+
+// Vertices are compared by their bits, so only exact duplicates are merged
+fn vertex_key(vertex: &Vertex) -> Vec<u32> {
+    [
+        vertex.position.as_slice(),
+        vertex.normal.as_slice(),
+        vertex.uv_0.as_slice(),
+        vertex.uv_1.as_slice(),
+        vertex.joint_0.as_slice(),
+        vertex.weight_0.as_slice(),
+        vertex.color_0.as_slice(),
+        vertex.tangent.as_slice(),
+    ]
+    .iter()
+    .flat_map(|values| values.iter().map(|value| value.to_bits()))
+    .collect()
+}
+
+pub fn load_processed_model(model: &ProcessedModel, world: &mut World) -> Result<Vec<Entity>> {
+    let number_of_materials = world.assets.materials.len();
+    let number_of_textures = world.assets.textures.len() as i32;
+    for material in model.materials.iter() {
+        let mut material = material.clone();
+        for index in material.texture_indices_mut() {
+            if *index != -1 {
+                *index += number_of_textures;
+            }
+        }
+        world.assets.materials.push(material);
+    }
+    world.assets.textures.extend(model.textures.iter().cloned());
+
+    let geometry = &mut world.assets.geometry;
+    let first_vertex = geometry.vertices.len();
+    let first_index = geometry.indices.len();
+    geometry.vertices.extend_from_slice(&model.vertices);
+    geometry.indices.extend(
+        model
+            .indices
+            .iter()
+            .map(|index| index + first_vertex as u32),
+    );
+
+    // Meshes are named the same way gltf meshes are, so names already in the world get a suffix
+    let mut mesh_names = Vec::new();
+    for mesh in model.meshes.iter() {
+        let mut mesh = mesh.clone();
+        for primitive in mesh.primitives.iter_mut() {
+            primitive.first_vertex += first_vertex;
+            primitive.first_index += first_index;
+            if let Some(material_index) = primitive.material_index.as_mut() {
+                *material_index += number_of_materials;
+            }
+        }
+        while geometry.meshes.contains_key(&mesh.name) {
+            mesh.name += "_";
+        }
+        mesh_names.push(mesh.name.to_string());
+        geometry.meshes.insert(mesh.name.to_string(), mesh);
+    }
+
+    let entities = world
+        .sim
+        .ecs
+        .extend((0..model.nodes.len()).map(|_| ()))
+        .to_vec();
+    let remap = model
+        .entities
+        .iter()
+        .copied()
+        .zip(entities.iter().copied())
+        .collect::<HashMap<_, _>>();
+    let remap = |entity: Entity| remap.get(&entity).copied().unwrap_or(entity);
+
+    for (node, entity) in model.nodes.iter().zip(entities.iter()) {
+        let mut entry = world
+            .sim
+            .ecs
+            .entry(*entity)
+            .context("Failed to find entity!")?;
+        if let Some(name) = node.name.as_ref() {
+            entry.add_component(Name(name.to_string()));
+        }
+        entry.add_component(node.transform);
+        if let Some(mesh) = node.mesh.and_then(|index| mesh_names.get(index)) {
+            entry.add_component(MeshRender {
+                name: mesh.to_string(),
+            });
+        }
+        if let Some(camera) = node.camera.as_ref() {
+            entry.add_component(camera.clone());
+        }
+        if let Some(light) = node.light {
+            entry.add_component(light);
+        }
+        if let Some(skin) = node.skin.as_ref() {
+            entry.add_component(Skin {
+                name: skin.name.to_string(),
+                joints: skin
+                    .joints
+                    .iter()
+                    .map(|joint| Joint {
+                        target: remap(joint.target),
+                        inverse_bind_matrix: joint.inverse_bind_matrix,
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    // The model's first clip plays, and the rest wait to be chosen
+    for (index, animation) in model.animations.iter().enumerate() {
+        let mut animation = animation.clone();
+        animation.playing = index == 0;
+        for channel in animation.channels.iter_mut() {
+            channel.target = remap(channel.target);
+        }
+        world.assets.animations.push(animation);
+    }
+
+    // Each root node becomes a graph in the active scene, the same as gltf root nodes do
+    for (index, node) in model.nodes.iter().enumerate() {
+        if node.parent.is_some() {
+            continue;
+        }
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(entities[index]);
+        graph_children(&mut graph, model, &entities, index, root);
+        world.sim.scene.graphs.push(graph);
+    }
+
+    Ok(entities)
+}
+
+fn graph_children(
+    graph: &mut SceneGraph,
+    model: &ProcessedModel,
+    entities: &[Entity],
+    parent: usize,
+    parent_index: NodeIndex,
+) {
+    for (index, node) in model.nodes.iter().enumerate() {
+        if node.parent != Some(parent) {
+            continue;
+        }
+        let node_index = graph.add_node(entities[index]);
+        graph.add_edge(parent_index, node_index);
+        graph_children(graph, model, entities, index, node_index);
+    }
+}
+
+// Stores processed models on disk so each source file only has to be imported once.
+// Entries are keyed by a hash of the source's contents and the processing settings,
+// so moving a file keeps its entry and editing it makes a new one.
+// Entries left behind by edits are pruned oldest first once the cache grows past its limit.
+#[derive(Debug, Clone)]
+pub struct ProcessedModelCache {
+    directory: PathBuf,
+    max_bytes: u64,
+}
+
+impl Default for ProcessedModelCache {
+    fn default() -> Self {
+        Self::new(Self::DIRECTORY)
+    }
+}
+
+impl ProcessedModelCache {
+    pub const DIRECTORY: &'static str = ".cache/models";
+    pub const MAX_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_bytes: Self::MAX_BYTES,
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    // Hashes the file along with the buffers and images a .gltf file refers to
+    pub fn key(path: impl AsRef<Path>, compression: TextureCompression) -> Result<u64> {
+        let path = path.as_ref();
+        let mut hash = Fnv1a::default();
+        hash.write(&fs::read(path)?);
+        for dependency in gltf_dependencies(path) {
+            if let Ok(bytes) = fs::read(&dependency) {
+                hash.write(&bytes);
+            }
+        }
+        hash.write(&[compression as u8]);
+        hash.write(&ProcessedModel::VERSION.to_le_bytes());
+        Ok(hash.finish())
+    }
+
+    // Loads the processed model for the file, importing and processing it on a miss.
+    // Failing to read or write the cache only costs the time to process the model again.
+    pub fn load_or_process(
+        &self,
+        path: impl AsRef<Path>,
+        compression: TextureCompression,
+    ) -> Result<ProcessedModel> {
+        let path = path.as_ref();
+        let key = Self::key(path, compression)?;
+        if let Some(model) = self.load(key) {
+            info!(
+                "Loading processed model {:016x} for {}",
+                key,
+                path.display()
+            );
+            return Ok(model);
+        }
+
+        let model = ProcessedModel::process(&ModelAsset::import(path)?, compression)?;
+        if let Err(error) = self.store(key, &model) {
+            warn!(
+                "Failed to cache processed model {}: {}",
+                path.display(),
+                error
+            );
+        }
+        if let Err(error) = self.prune(key) {
+            warn!("Failed to prune processed model cache: {}", error);
+        }
+        Ok(model)
+    }
+
+    // Entries that can't be read back, such as ones from an older version, are removed
+    pub fn load(&self, key: u64) -> Option<ProcessedModel> {
+        let path = self.path(key);
+        let bytes = fs::read(&path).ok()?;
+        match ProcessedModel::from_bytes(&bytes) {
+            Ok(model) => Some(model),
+            Err(error) => {
+                warn!("Removing processed model {:016x}: {}", key, error);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    pub fn store(&self, key: u64, model: &ProcessedModel) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.path(key), model.to_bytes()?)?;
+        Ok(())
+    }
+
+    // Removes the oldest entries until the cache fits within its limit, keeping the given entry
+    pub fn prune(&self, keep: u64) -> Result<()> {
+        let keep = self.path(keep);
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("bin") {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+
+        let mut total = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, path) in entries.into_iter() {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            fs::remove_file(&path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.bin", key))
+    }
+}
+
+// The external files of a .gltf file, which .glb files and embedded data don't have
+fn gltf_dependencies(path: &Path) -> Vec<PathBuf> {
+    let is_gltf = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("gltf"));
+    if !is_gltf {
+        return Vec::new();
+    }
+    let document = match gltf::Gltf::open(path) {
+        Ok(gltf) => gltf.document,
+        Err(_) => return Vec::new(),
+    };
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let buffers = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri.to_string()),
+            gltf::buffer::Source::Bin => None,
+        });
+    let images = document.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri.to_string()),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffers
+        .chain(images)
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| directory.join(uri))
+        .collect()
+}
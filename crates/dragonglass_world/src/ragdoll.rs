@@ -0,0 +1,472 @@
+use crate::{Entity, Handle, Layers, Name, SimWorld, Skin, Transform};
+use anyhow::{bail, Context, Result};
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use rapier3d::{
+    dynamics::{JointAxesMask, JointAxis, JointData, RigidBodyBuilder, RigidBodyType},
+    geometry::{ColliderBuilder, InteractionGroups, SharedShape},
+    na::{Isometry3, Point3},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// How a ragdoll is built from a skeleton
+#[derive(Debug, Clone)]
+pub struct RagdollSettings {
+    // Bones shorter than this fraction of the longest bone, such as fingers and toes, follow their parents
+    pub min_bone_fraction: f32,
+    // The radius of each bone's capsule as a fraction of its length
+    pub radius_fraction: f32,
+    pub density: f32,
+    // How far a ball joint can swing away from its rest pose and twist around its bone, in radians
+    pub swing_limit: f32,
+    pub twist_limit: f32,
+    // How far a hinge like a knee or elbow bends around the bone's x axis, in radians.
+    // Rigs whose hinges bend the other way need these negated and swapped.
+    pub hinge_limits: [f32; 2],
+    // Bones whose names contain any of these become hinges instead of ball joints
+    pub hinge_names: Vec<String>,
+    pub collision_groups: InteractionGroups,
+    // How long getting up blends from the fallen pose back to the animated one, in seconds
+    pub blend_duration: f32,
+}
+
+impl Default for RagdollSettings {
+    fn default() -> Self {
+        Self {
+            min_bone_fraction: 0.1,
+            radius_fraction: 0.2,
+            density: 1.0,
+            swing_limit: 60_f32.to_radians(),
+            twist_limit: 30_f32.to_radians(),
+            hinge_limits: [-150_f32.to_radians(), 0.0],
+            hinge_names: [
+                "knee", "elbow", "forearm", "calf", "shin", "lowerleg", "lowerarm",
+            ]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+            // Ragdolls don't collide with their own bones, whose capsules overlap at every joint
+            collision_groups: Self::LAYER.collision_groups(Layers::ALL.without(Self::LAYER)),
+            blend_duration: 0.5,
+        }
+    }
+}
+
+impl RagdollSettings {
+    pub const LAYER: Layers = Layers::layer(31);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagdollBone {
+    // The skin joint the body drives
+    pub joint: Entity,
+    pub body: Handle,
+    // The scale of the joint's global transform, which bodies don't have
+    pub scale: glm::Vec3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RagdollState {
+    // Animations pose the skeleton, and the bodies follow it as kinematic hitboxes
+    Animated,
+    // Physics poses the skeleton
+    Simulated,
+    // Getting up, blending from the local transforms the bones had when physics let go
+    BlendingBack { elapsed: f32, from: Vec<Transform> },
+}
+
+// Rigid bodies for the major bones of a skin, kept on the entity with the skin.
+// Bones are ordered parents first, so each bone's parent is posed before it is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ragdoll {
+    pub bones: Vec<RagdollBone>,
+    pub state: RagdollState,
+    pub blend_duration: f32,
+}
+
+impl Ragdoll {
+    pub fn is_simulated(&self) -> bool {
+        matches!(self.state, RagdollState::Simulated)
+    }
+}
+
+impl SimWorld {
+    // Builds a capsule for each major bone of the skin on the entity, jointed to its nearest major ancestor.
+    // The ragdoll starts out following the animation until it is enabled.
+    pub fn generate_ragdoll(&mut self, entity: Entity, settings: &RagdollSettings) -> Result<()> {
+        if self
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Ragdoll>()
+            .is_ok()
+        {
+            self.remove_ragdoll(entity)?;
+        }
+        let joints = self
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Skin>()
+            .context("Ragdolls can only be generated for entities with a skin")?
+            .joints
+            .iter()
+            .map(|joint| joint.target)
+            .collect::<Vec<_>>();
+
+        // Parents first, so bodies and poses can be built on their ancestors
+        let mut joints = joints
+            .iter()
+            .map(|joint| (*joint, self.joint_depth(*joint)))
+            .collect::<Vec<_>>();
+        joints.sort_by_key(|(_, depth)| *depth);
+        let joints = joints
+            .into_iter()
+            .map(|(joint, _)| joint)
+            .collect::<Vec<_>>();
+
+        // Each bone runs from its joint to the middle of its child joints
+        let mut globals = Vec::new();
+        for joint in joints.iter() {
+            globals.push(Transform::from(
+                self.entity_global_transform_matrix(*joint)?,
+            ));
+        }
+        let tips = joints
+            .iter()
+            .map(|joint| {
+                let children = joints
+                    .iter()
+                    .zip(globals.iter())
+                    .filter(|(child, _)| self.parent_of(**child) == Some(*joint))
+                    .map(|(_, global)| global.translation)
+                    .collect::<Vec<_>>();
+                if children.is_empty() {
+                    None
+                } else {
+                    Some(children.iter().sum::<glm::Vec3>() / children.len() as f32)
+                }
+            })
+            .collect::<Vec<_>>();
+        let longest = globals
+            .iter()
+            .zip(tips.iter())
+            .filter_map(|(global, tip)| tip.map(|tip| glm::distance(&global.translation, &tip)))
+            .fold(0.0, f32::max);
+        if longest <= f32::EPSILON {
+            bail!("The skin has no bones long enough to build a ragdoll from");
+        }
+
+        let mut bones: Vec<RagdollBone> = Vec::new();
+        for ((joint, global), tip) in joints.iter().zip(globals.iter()).zip(tips.iter()) {
+            let tip = match tip {
+                Some(tip) => *tip,
+                None => continue,
+            };
+            let length = glm::distance(&global.translation, &tip);
+            if length < longest * settings.min_bone_fraction {
+                continue;
+            }
+
+            let isometry = global.as_isometry();
+            let body = RigidBodyBuilder::new(RigidBodyType::KinematicPositionBased)
+                .position(isometry)
+                .build();
+            let body = self.physics.bodies.insert(body);
+            let tip = isometry.inverse_transform_point(&Point3::from(tip));
+            let collider = ColliderBuilder::new(SharedShape::capsule(
+                Point3::origin(),
+                tip,
+                length * settings.radius_fraction,
+            ))
+            .density(settings.density)
+            .collision_groups(settings.collision_groups)
+            .build();
+            let physics = &mut self.physics;
+            physics
+                .colliders
+                .insert_with_parent(collider, body, &mut physics.bodies);
+
+            if let Some(parent) = self.nearest_bone_ancestor(*joint, &bones) {
+                // Limits are measured from the pose the skeleton was in when the ragdoll was generated
+                let parent_isometry = *self.physics.bodies[parent.body].position();
+                let joint_data = self
+                    .ragdoll_joint(*joint, settings)
+                    .local_frame1(parent_isometry.inverse() * isometry)
+                    .local_frame2(Isometry3::identity());
+                self.physics
+                    .impulse_joints
+                    .insert(parent.body, body, joint_data);
+            }
+
+            bones.push(RagdollBone {
+                joint: *joint,
+                body,
+                scale: global.scale,
+            });
+        }
+
+        self.ecs
+            .entry(entity)
+            .context("Failed to find the entity to add a ragdoll to")?
+            .add_component(Ragdoll {
+                bones,
+                state: RagdollState::Animated,
+                blend_duration: settings.blend_duration,
+            });
+        Ok(())
+    }
+
+    // Removes the ragdoll's bodies along with their colliders and joints
+    pub fn remove_ragdoll(&mut self, entity: Entity) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(entity)
+            .context("Failed to find the entity to remove a ragdoll from")?;
+        let ragdoll = match entry.get_component::<Ragdoll>() {
+            Ok(ragdoll) => ragdoll.clone(),
+            Err(_) => return Ok(()),
+        };
+        entry.remove_component::<Ragdoll>();
+        for bone in ragdoll.bones.iter() {
+            self.physics.remove_rigid_body(bone.body);
+        }
+        Ok(())
+    }
+
+    // Hands the skeleton to physics, or starts blending back to the animation to get up
+    pub fn set_ragdoll_enabled(&mut self, entity: Entity, enabled: bool) -> Result<()> {
+        let ragdoll = self
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Ragdoll>()
+            .context("The entity has no ragdoll")?
+            .clone();
+        if enabled == ragdoll.is_simulated() {
+            return Ok(());
+        }
+
+        let state = if enabled {
+            RagdollState::Simulated
+        } else {
+            let mut from = Vec::new();
+            for bone in ragdoll.bones.iter() {
+                let transform = self
+                    .ecs
+                    .entry_ref(bone.joint)
+                    .ok()
+                    .and_then(|entry| entry.get_component::<Transform>().ok().copied());
+                from.push(transform.unwrap_or_default());
+            }
+            RagdollState::BlendingBack { elapsed: 0.0, from }
+        };
+        let body_type = if enabled {
+            RigidBodyType::Dynamic
+        } else {
+            RigidBodyType::KinematicPositionBased
+        };
+        for bone in ragdoll.bones.iter() {
+            if let Some(body) = self.physics.bodies.get_mut(bone.body) {
+                body.set_body_type(body_type);
+                body.wake_up(true);
+            }
+        }
+
+        self.ecs
+            .entry(entity)
+            .context("Failed to find the ragdoll")?
+            .get_component_mut::<Ragdoll>()?
+            .state = state;
+        Ok(())
+    }
+
+    // Poses simulated skeletons from their bodies and moves the bodies of animated ones to their bones.
+    // Runs after animations and inverse kinematics, so simulated ragdolls override what they posed.
+    pub fn update_ragdolls(&mut self, delta_time: f32) -> Result<()> {
+        let mut query = <(Entity, &Ragdoll)>::query();
+        let ragdolls = query
+            .iter(&self.ecs)
+            .map(|(entity, ragdoll)| (*entity, ragdoll.clone()))
+            .collect::<Vec<_>>();
+
+        for (entity, mut ragdoll) in ragdolls.into_iter() {
+            match &mut ragdoll.state {
+                RagdollState::Animated => self.follow_bones(&ragdoll.bones)?,
+                RagdollState::Simulated => {
+                    for bone in ragdoll.bones.iter() {
+                        let isometry = match self.physics.bodies.get(bone.body) {
+                            Some(body) => *body.position(),
+                            None => continue,
+                        };
+                        let global = isometry.to_homogeneous() * glm::scaling(&bone.scale);
+                        self.set_joint_transform(bone.joint, &global)?;
+                    }
+                }
+                RagdollState::BlendingBack { elapsed, from } => {
+                    *elapsed += delta_time;
+                    let progress = if ragdoll.blend_duration > 0.0 {
+                        (*elapsed / ragdoll.blend_duration).min(1.0)
+                    } else {
+                        1.0
+                    };
+                    let amount = progress * progress * (3.0 - 2.0 * progress);
+                    for (bone, from) in ragdoll.bones.iter().zip(from.iter()) {
+                        let mut entry = match self.ecs.entry_mut(bone.joint) {
+                            Ok(entry) => entry,
+                            Err(_) => continue,
+                        };
+                        let transform = match entry.get_component_mut::<Transform>() {
+                            Ok(transform) => transform,
+                            Err(_) => continue,
+                        };
+                        *transform = Transform::new(
+                            glm::lerp(&from.translation, &transform.translation, amount),
+                            glm::quat_slerp(&from.rotation, &transform.rotation, amount),
+                            glm::lerp(&from.scale, &transform.scale, amount),
+                        );
+                    }
+                    self.follow_bones(&ragdoll.bones)?;
+                    if progress >= 1.0 {
+                        ragdoll.state = RagdollState::Animated;
+                    }
+                }
+            }
+
+            if let Ok(mut entry) = self.ecs.entry_mut(entity) {
+                if let Ok(existing) = entry.get_component_mut::<Ragdoll>() {
+                    existing.state = ragdoll.state;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Moves kinematic bodies to where their bones are. Joints that were removed are skipped.
+    fn follow_bones(&mut self, bones: &[RagdollBone]) -> Result<()> {
+        let globals = self.joint_globals(bones)?;
+        for bone in bones.iter() {
+            let global = match globals.get(&bone.joint) {
+                Some(global) => global,
+                None => continue,
+            };
+            let isometry: Isometry3<f32> = Transform::from(*global).as_isometry();
+            if let Some(body) = self.physics.bodies.get_mut(bone.body) {
+                body.set_next_kinematic_position(isometry);
+            }
+        }
+        Ok(())
+    }
+
+    // The global transforms of the skeleton the bones belong to, from a single walk of its scenegraph
+    fn joint_globals(&self, bones: &[RagdollBone]) -> Result<HashMap<Entity, glm::Mat4>> {
+        let mut globals = HashMap::new();
+        let graph = bones.iter().find_map(|bone| {
+            self.scene
+                .graphs
+                .iter()
+                .find(|graph| graph.find_node(bone.joint).is_some())
+        });
+        let graph = match graph {
+            Some(graph) => graph,
+            None => return Ok(globals),
+        };
+        let mut node_globals = HashMap::new();
+        graph.walk(|index| {
+            let local = self
+                .ecs
+                .entry_ref(graph[index])
+                .ok()
+                .and_then(|entry| {
+                    entry
+                        .get_component::<Transform>()
+                        .ok()
+                        .map(Transform::matrix)
+                })
+                .unwrap_or_else(glm::Mat4::identity);
+            let global = match graph
+                .parent_of(index)
+                .and_then(|parent| node_globals.get(&parent))
+            {
+                Some(parent_global) => parent_global * local,
+                None => local,
+            };
+            node_globals.insert(index, global);
+            globals.insert(graph[index], global);
+            Ok(())
+        })?;
+        Ok(globals)
+    }
+
+    fn set_joint_transform(&mut self, joint: Entity, global: &glm::Mat4) -> Result<()> {
+        let local = match self.local_transform_from_global(joint, global) {
+            Ok(local) => local,
+            Err(_) => return Ok(()),
+        };
+        if let Ok(mut entry) = self.ecs.entry_mut(joint) {
+            if let Ok(transform) = entry.get_component_mut::<Transform>() {
+                *transform = local;
+            }
+        }
+        Ok(())
+    }
+
+    fn joint_depth(&self, joint: Entity) -> usize {
+        let mut depth = 0;
+        let mut current = joint;
+        while let Some(parent) = self.parent_of(current) {
+            depth += 1;
+            current = parent;
+        }
+        depth
+    }
+
+    fn nearest_bone_ancestor<'a>(
+        &self,
+        joint: Entity,
+        bones: &'a [RagdollBone],
+    ) -> Option<&'a RagdollBone> {
+        let mut current = joint;
+        while let Some(parent) = self.parent_of(current) {
+            if let Some(bone) = bones.iter().find(|bone| bone.joint == parent) {
+                return Some(bone);
+            }
+            current = parent;
+        }
+        None
+    }
+
+    fn ragdoll_joint(&self, joint: Entity, settings: &RagdollSettings) -> JointData {
+        let name = self
+            .ecs
+            .entry_ref(joint)
+            .ok()
+            .and_then(|entry| {
+                entry
+                    .get_component::<Name>()
+                    .ok()
+                    .map(|name| name.0.to_lowercase())
+            })
+            .unwrap_or_default();
+        let is_hinge = settings
+            .hinge_names
+            .iter()
+            .any(|hinge| name.contains(&hinge.to_lowercase()));
+        if is_hinge {
+            JointData::locked_axes(JointAxesMask::LOCKED_REVOLUTE_AXES)
+                .limit_axis(JointAxis::AngX, settings.hinge_limits)
+        } else {
+            JointData::locked_axes(JointAxesMask::LOCKED_SPHERICAL_AXES)
+                .limit_axis(
+                    JointAxis::AngX,
+                    [-settings.twist_limit, settings.twist_limit],
+                )
+                .limit_axis(
+                    JointAxis::AngY,
+                    [-settings.swing_limit, settings.swing_limit],
+                )
+                .limit_axis(
+                    JointAxis::AngZ,
+                    [-settings.swing_limit, settings.swing_limit],
+                )
+        }
+    }
+}
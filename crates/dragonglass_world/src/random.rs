@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+// A small permuted congruential generator (PCG32).
+// It is platform independent and stored with the world, so seeded runs are reproducible.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+    increment: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED)
+    }
+}
+
+impl Rng {
+    pub const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+    const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+    const STREAM: u64 = 0xda3e_39cb_94b9_5bdb;
+
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self {
+            seed,
+            state: 0,
+            increment: (Self::STREAM << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        let rotation = (state >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    // Uniformly distributed in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // Uniformly distributed in [min, max)
+    pub fn range_u32(&mut self, min: u32, max: u32) -> u32 {
+        if max <= min {
+            return min;
+        }
+        let span = max - min;
+        // Rejection sampling avoids modulo bias
+        let threshold = span.wrapping_neg() % span;
+        loop {
+            let value = self.next_u32();
+            if value >= threshold {
+                return min + value % span;
+            }
+        }
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        let index = self.range_u32(0, items.len() as u32) as usize;
+        items.get(index)
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = self.range_u32(0, index as u32 + 1) as usize;
+            items.swap(index, other);
+        }
+    }
+}
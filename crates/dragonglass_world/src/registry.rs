@@ -1,37 +1,254 @@
-use crate::{Camera, Ecs, Light, MeshRender, Name, RigidBody, Skin, Transform, World};
-use anyhow::Result;
+use crate::{
+    BehaviorTree, BuoyancyVolume, Camera, CameraPath, CameraShake, Ecs, FovTransition, IkChain,
+    Layer, Layers, Light, MeshRender, Name, ParticleEmitter, PathFollower, PhysicsFocus,
+    PhysicsLod, PhysicsMaterial, Ragdoll, RaycastVehicle, ReflectionProbe, RigidBody,
+    RigidBodySettings, Script, SimWorld, Skin, Socket, Spline, Static, Tags, Team, Transform,
+    VisionSource,
+};
+#[cfg(feature = "assets")]
+use crate::{Billboard, Lod, Occluder, Sprite, Terrain, Text, World};
+use anyhow::{bail, Context, Result};
 use lazy_static::lazy_static;
 use legion::{
     serialize::{set_entity_serializer, Canon},
     storage::Component,
-    Registry,
+    Entity, IntoQuery, Registry,
+};
+use log::warn;
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor},
+    ser, Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
 };
-use serde::{de::DeserializeSeed, Deserialize, Deserializer, Serialize, Serializer};
-use std::sync::{Arc, RwLock};
 
 lazy_static! {
-    pub static ref COMPONENT_REGISTRY: Arc<RwLock<Registry<String>>> = {
-        let mut registry = Registry::default();
-        registry.register::<Name>("name".to_string());
-        registry.register::<Transform>("transform".to_string());
-        registry.register::<Camera>("camera".to_string());
-        registry.register::<MeshRender>("mesh".to_string());
-        registry.register::<Skin>("skin".to_string());
-        registry.register::<Light>("light".to_string());
-        registry.register::<RigidBody>("rigid_body".to_string());
+    pub static ref COMPONENT_REGISTRY: Arc<RwLock<ComponentRegistry>> = {
+        let mut registry = ComponentRegistry::default();
+        registry.register_builtin::<Name>("name");
+        registry.register_builtin::<Tags>("tags");
+        registry.register_builtin::<Layers>("layers");
+        registry.register_builtin::<Transform>("transform");
+        registry.register_builtin::<Camera>("camera");
+        registry.register_builtin::<FovTransition>("fov_transition");
+        registry.register_builtin::<CameraShake>("camera_shake");
+        registry.register_builtin::<CameraPath>("camera_path");
+        registry.register_builtin::<Spline>("spline");
+        registry.register_builtin::<PathFollower>("path_follower");
+        registry.register_builtin::<MeshRender>("mesh");
+        registry.register_builtin::<Skin>("skin");
+        registry.register_builtin::<IkChain>("ik_chain");
+        registry.register_builtin::<Socket>("socket");
+        registry.register_builtin::<Light>("light");
+        registry.register_builtin::<RigidBody>("rigid_body");
+        registry.register_builtin::<RigidBodySettings>("rigid_body_settings");
+        registry.register_builtin::<PhysicsMaterial>("physics_material");
+        registry.register_builtin::<RaycastVehicle>("raycast_vehicle");
+        registry.register_builtin::<BuoyancyVolume>("buoyancy_volume");
+        registry.register_builtin::<Ragdoll>("ragdoll");
+        registry.register_builtin::<Layer>("layer");
+        registry.register_builtin::<Team>("team");
+        registry.register_builtin::<VisionSource>("vision_source");
+        registry.register_builtin::<Static>("static");
+        registry.register_builtin::<PhysicsLod>("physics_lod");
+        registry.register_builtin::<PhysicsFocus>("physics_focus");
+        registry.register_builtin::<ParticleEmitter>("particle_emitter");
+        registry.register_builtin::<ReflectionProbe>("reflection_probe");
+        registry.register_builtin::<Script>("script");
+        registry.register_builtin::<BehaviorTree>("behavior_tree");
+        #[cfg(feature = "assets")]
+        registry.register_builtin::<Terrain>("terrain");
+        #[cfg(feature = "assets")]
+        registry.register_builtin::<Lod>("lod");
+        #[cfg(feature = "assets")]
+        registry.register_builtin::<Occluder>("occluder");
+        #[cfg(feature = "assets")]
+        registry.register_builtin::<Text>("text");
+        #[cfg(feature = "assets")]
+        registry.register_builtin::<Sprite>("sprite");
+        #[cfg(feature = "assets")]
+        registry.register_builtin::<Billboard>("billboard");
         Arc::new(RwLock::new(registry))
     };
     pub static ref ENTITY_SERIALIZER: Canon = Canon::default();
 }
 
-pub fn register_component<T: Component + Serialize + for<'de> Deserialize<'de>>(
+type SerializeFn = Box<dyn Fn(&Ecs) -> Result<Vec<(Entity, Vec<u8>)>> + Send + Sync>;
+type DeserializeFn = Box<dyn Fn(&mut Ecs, Entity, &[u8]) -> Result<()> + Send + Sync>;
+
+// A component saved through functions it was registered with, rather than its serde implementation
+struct ComponentCodec {
+    name: String,
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+// Every instance of a component saved through its codec, stored alongside the ecs
+#[derive(Serialize, Deserialize)]
+struct ComponentTable {
+    name: String,
+    components: Vec<(Entity, Vec<u8>)>,
+}
+
+// The components saved with worlds, by the name they are saved under.
+// Names are part of the save format, so they shouldn't change once worlds have been saved with them.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    registry: Registry<String>,
+    codecs: Vec<ComponentCodec>,
+    types: HashMap<String, (TypeId, &'static str)>,
+}
+
+impl ComponentRegistry {
+    fn register_builtin<T: Component + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        self.register::<T>(name)
+            .expect("Built in components must have unique names!");
+    }
+
+    pub fn register<T: Component + Serialize + DeserializeOwned>(
+        &mut self,
+        name: &str,
+    ) -> Result<()> {
+        if self.claim_name::<T>(name)? {
+            self.registry.register::<T>(name.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn register_with<T: Component>(
+        &mut self,
+        name: &str,
+        serialize: impl Fn(&T) -> Result<Vec<u8>> + Send + Sync + 'static,
+        deserialize: impl Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+    ) -> Result<()> {
+        if !self.claim_name::<T>(name)? {
+            return Ok(());
+        }
+        let serialize: SerializeFn = Box::new(move |ecs| {
+            let mut query = <(Entity, &T)>::query();
+            query
+                .iter(ecs)
+                .map(|(entity, component)| Ok((*entity, serialize(component)?)))
+                .collect()
+        });
+        let deserialize: DeserializeFn = Box::new(move |ecs, entity, bytes| {
+            let component = deserialize(bytes)?;
+            match ecs.entry(entity) {
+                Some(mut entry) => entry.add_component(component),
+                // Entities with only codec components have nothing else saved to create them
+                None => {
+                    ecs.push_with_id(entity, (component,));
+                }
+            }
+            Ok(())
+        });
+        self.codecs.push(ComponentCodec {
+            name: name.to_string(),
+            serialize,
+            deserialize,
+        });
+        Ok(())
+    }
+
+    // Registering the same component under the same name again does nothing
+    fn claim_name<T: Component>(&mut self, name: &str) -> Result<bool> {
+        let type_id = TypeId::of::<T>();
+        if let Some((registered, registered_name)) = self.types.get(name) {
+            if *registered == type_id {
+                return Ok(false);
+            }
+            bail!(
+                "Can't register '{}' as the component '{}', which is already registered for '{}'",
+                type_name::<T>(),
+                name,
+                registered_name
+            );
+        }
+        if let Some((existing, _)) = self.types.iter().find(|(_, (id, _))| *id == type_id) {
+            bail!(
+                "The component '{}' is already registered as '{}'",
+                type_name::<T>(),
+                existing
+            );
+        }
+        self.types
+            .insert(name.to_string(), (type_id, type_name::<T>()));
+        Ok(true)
+    }
+
+    pub fn is_registered<T: Component>(&self) -> bool {
+        let type_id = TypeId::of::<T>();
+        self.types.values().any(|(id, _)| *id == type_id)
+    }
+
+    // The names every registered component is saved under
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.types.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn tables(&self, ecs: &Ecs) -> Result<Vec<ComponentTable>> {
+        self.codecs
+            .iter()
+            .map(|codec| {
+                let components = (codec.serialize)(ecs)
+                    .with_context(|| format!("Failed to save the '{}' components", codec.name))?;
+                Ok(ComponentTable {
+                    name: codec.name.to_string(),
+                    components,
+                })
+            })
+            .collect()
+    }
+
+    // Tables for components that aren't registered are skipped,
+    // so worlds saved by a game can still be opened by tools that don't know its components
+    fn insert_tables(&self, ecs: &mut Ecs, tables: Vec<ComponentTable>) -> Result<()> {
+        for table in tables {
+            let codec = match self.codecs.iter().find(|codec| codec.name == table.name) {
+                Some(codec) => codec,
+                None => {
+                    warn!(
+                        "Skipped {} '{}' components that aren't registered",
+                        table.components.len(),
+                        table.name
+                    );
+                    continue;
+                }
+            };
+            for (entity, bytes) in table.components.iter() {
+                (codec.deserialize)(ecs, *entity, bytes)
+                    .with_context(|| format!("Failed to load a '{}' component", table.name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Components registered by name are saved with the world and restored when it is loaded.
+// Games register their own components before saving or loading worlds that contain them.
+pub fn register_component<T: Component + Serialize + DeserializeOwned>(key: &str) -> Result<()> {
+    COMPONENT_REGISTRY
+        .write()
+        .expect("Failed to access component registry!")
+        .register::<T>(key)
+}
+
+// For components that don't implement serde's traits, or are saved in a different form than they are used in
+pub fn register_component_with<T: Component>(
     key: &str,
+    serialize: impl Fn(&T) -> Result<Vec<u8>> + Send + Sync + 'static,
+    deserialize: impl Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
 ) -> Result<()> {
-    let mut registry = COMPONENT_REGISTRY
+    COMPONENT_REGISTRY
         .write()
-        .expect("Failed to access component registry!");
-    registry.register::<T>(key.to_string());
-    Ok(())
+        .expect("Failed to access component registry!")
+        .register_with(key, serialize, deserialize)
 }
 
 pub fn serialize_ecs<S>(ecs: &Ecs, serializer: S) -> Result<S::Ok, S::Error>
@@ -41,7 +258,11 @@ where
     let registry = (*COMPONENT_REGISTRY)
         .read()
         .expect("Failed to get the component registry lock!");
-    ecs.as_serializable(legion::any(), &*registry, &*ENTITY_SERIALIZER)
+    let tables = registry.tables(ecs).map_err(ser::Error::custom)?;
+    (
+        ecs.as_serializable(legion::any(), &registry.registry, &*ENTITY_SERIALIZER),
+        tables,
+    )
         .serialize(serializer)
 }
 
@@ -49,21 +270,71 @@ pub fn deserialize_ecs<'de, D>(deserializer: D) -> Result<Ecs, D::Error>
 where
     D: Deserializer<'de>,
 {
-    (*COMPONENT_REGISTRY)
+    let registry = (*COMPONENT_REGISTRY)
         .read()
-        .expect("Failed to get the component registry lock!")
-        .as_deserialize(&*ENTITY_SERIALIZER)
-        .deserialize(deserializer)
+        .expect("Failed to get the component registry lock!");
+    deserializer.deserialize_tuple(
+        2,
+        EcsVisitor {
+            registry: &registry,
+        },
+    )
+}
+
+// Reads the ecs, then the tables of components saved through their codecs
+struct EcsVisitor<'a> {
+    registry: &'a ComponentRegistry,
+}
+
+impl<'a, 'de> Visitor<'de> for EcsVisitor<'a> {
+    type Value = Ecs;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "an ecs followed by its registered component tables"
+        )
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut ecs = seq
+            .next_element_seed(self.registry.registry.as_deserialize(&*ENTITY_SERIALIZER))?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let tables: Vec<ComponentTable> = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        self.registry
+            .insert_tables(&mut ecs, tables)
+            .map_err(de::Error::custom)?;
+        Ok(ecs)
+    }
 }
 
+#[cfg(feature = "assets")]
 pub fn world_as_bytes(world: &World) -> Result<Vec<u8>> {
     Ok(set_entity_serializer(&*ENTITY_SERIALIZER, || {
         bincode::serialize(world)
     })?)
 }
 
+#[cfg(feature = "assets")]
 pub fn world_from_bytes(bytes: &[u8]) -> Result<World> {
     Ok(set_entity_serializer(&*ENTITY_SERIALIZER, || {
         bincode::deserialize(bytes)
     })?)
 }
+
+pub fn sim_world_as_bytes(sim: &SimWorld) -> Result<Vec<u8>> {
+    Ok(set_entity_serializer(&*ENTITY_SERIALIZER, || {
+        bincode::serialize(sim)
+    })?)
+}
+
+pub fn sim_world_from_bytes(bytes: &[u8]) -> Result<SimWorld> {
+    Ok(set_entity_serializer(&*ENTITY_SERIALIZER, || {
+        bincode::deserialize(bytes)
+    })?)
+}
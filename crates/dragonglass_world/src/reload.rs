@@ -0,0 +1,223 @@
+use crate::{
+    load_model_asset, Camera, Entity, Joint, Light, Lod, MeshRender, ModelAsset, Name, RigidBody,
+    Skin, Transform, World,
+};
+use anyhow::Result;
+use legion::{storage::Component, world::Entry, EntityStore};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// What a model's node defines on its entity, which is replaced when the model is reloaded
+struct NodeComponents {
+    transform: Option<Transform>,
+    mesh: Option<MeshRender>,
+    skin: Option<Skin>,
+    camera: Option<Camera>,
+    light: Option<Light>,
+    lod: Option<Lod>,
+}
+
+// Replaces the entities a model was instantiated as with the model's new contents, such as after
+// its file is exported again. Entities are matched to the new nodes by name and keep their identity,
+// along with anything the model doesn't define on them like rigid bodies and scripts.
+// Roots keep where they were placed, and edits to the scenegraph outside the model, such as
+// parenting the model or adding children to its nodes, are kept.
+// Entities without a matching node are removed and nodes without a matching entity are added.
+// Returns the model's entities after reloading. The old meshes and materials are left for compaction.
+pub fn reload_model_asset(
+    asset: &ModelAsset,
+    world: &mut World,
+    previous: &[Entity],
+) -> Result<Vec<Entity>> {
+    let previous = previous
+        .iter()
+        .copied()
+        .filter(|entity| world.ecs.entry_ref(*entity).is_ok())
+        .collect::<Vec<_>>();
+    let previous_set = previous.iter().copied().collect::<HashSet<_>>();
+
+    // The model's animations are loaded again along with it
+    world.assets.animations.retain(|animation| {
+        !animation
+            .channels
+            .iter()
+            .any(|channel| previous_set.contains(&channel.target))
+    });
+
+    let existing_graphs = world.sim.scene.graphs.len();
+    let entities = load_model_asset(asset, world)?;
+
+    // Each new node with its parent, parents first
+    let mut hierarchy = Vec::new();
+    for graph in world.sim.scene.graphs[existing_graphs..].iter() {
+        graph.walk(|index| {
+            let parent = graph.parent_of(index).map(|parent| graph[parent]);
+            hierarchy.push((graph[index], parent));
+            Ok(())
+        })?;
+    }
+    let roots = hierarchy
+        .iter()
+        .filter(|(_, parent)| parent.is_none())
+        .map(|(entity, _)| *entity)
+        .collect::<HashSet<_>>();
+
+    // Nodes sharing a name are matched in the order they were loaded
+    let mut names = HashMap::<String, VecDeque<Entity>>::new();
+    for entity in previous.iter() {
+        names
+            .entry(entity_name(world, *entity))
+            .or_default()
+            .push_back(*entity);
+    }
+    let mut mapping = HashMap::new();
+    for entity in entities.iter() {
+        let name = entity_name(world, *entity);
+        if let Some(existing) = names.get_mut(&name).and_then(|queue| queue.pop_front()) {
+            mapping.insert(*entity, existing);
+        }
+    }
+    let remap = |entity: Entity| mapping.get(&entity).copied().unwrap_or(entity);
+
+    for (entity, existing) in mapping.iter() {
+        let components = node_components(world, *entity, &remap)?;
+        let mut entry = match world.sim.ecs.entry(*existing) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if !roots.contains(entity) {
+            replace_component(&mut entry, components.transform);
+        }
+        replace_component(&mut entry, components.mesh);
+        replace_component(&mut entry, components.skin);
+        replace_component(&mut entry, components.camera);
+        replace_component(&mut entry, components.light);
+        replace_component(&mut entry, components.lod);
+    }
+
+    for animation in world.assets.animations.iter_mut() {
+        for channel in animation.channels.iter_mut() {
+            channel.target = remap(channel.target);
+        }
+    }
+
+    // Edges within the model are rebuilt from the new nodes, and the rest of the scenegraph is kept
+    let matched = mapping.values().copied().collect::<HashSet<_>>();
+    for graph in world.sim.scene.graphs[..existing_graphs].iter_mut() {
+        graph.0.retain_edges(|graph, edge| {
+            graph.edge_endpoints(edge).map_or(true, |(parent, child)| {
+                !(previous_set.contains(&graph[parent]) && previous_set.contains(&graph[child]))
+            })
+        });
+        graph.0.retain_nodes(|graph, index| {
+            !previous_set.contains(&graph[index]) || matched.contains(&graph[index])
+        });
+    }
+    let mut placed = HashSet::new();
+    for (entity, parent) in hierarchy.into_iter() {
+        let parent = match parent {
+            Some(parent) => remap(parent),
+            None => {
+                if mapping.contains_key(&entity) {
+                    placed.insert(entity);
+                }
+                continue;
+            }
+        };
+        let child = remap(entity);
+        let graph = world.sim.scene.graphs[..existing_graphs]
+            .iter_mut()
+            .find_map(|graph| graph.find_node(parent).map(|index| (graph, index)));
+        if let Some((graph, parent_index)) = graph {
+            match graph.find_node(child) {
+                // Nodes reparented outside the model stay where they were put
+                Some(index) if graph.has_parents(index) => {}
+                Some(index) => graph.add_edge(parent_index, index),
+                None => {
+                    let index = graph.add_node(child);
+                    graph.add_edge(parent_index, index);
+                }
+            }
+            placed.insert(entity);
+        } else if mapping.contains_key(&entity) {
+            placed.insert(entity);
+        }
+    }
+    // What's left of the new graphs are added nodes whose parents aren't in the scene yet
+    for graph in world.sim.scene.graphs[existing_graphs..].iter_mut() {
+        graph
+            .0
+            .retain_nodes(|graph, index| !placed.contains(&graph[index]));
+    }
+    world
+        .sim
+        .scene
+        .graphs
+        .retain(|graph| graph.number_of_nodes() > 0);
+
+    for entity in previous.iter().filter(|entity| !matched.contains(entity)) {
+        let rigid_body_handle = world.ecs.entry_ref(*entity).ok().and_then(|entry| {
+            entry
+                .get_component::<RigidBody>()
+                .ok()
+                .map(|body| body.handle)
+        });
+        if let Some(handle) = rigid_body_handle {
+            world.sim.physics.remove_rigid_body(handle);
+        }
+        world.sim.ecs.remove(*entity);
+    }
+    for entity in mapping.keys() {
+        world.sim.ecs.remove(*entity);
+    }
+
+    Ok(entities.into_iter().map(remap).collect())
+}
+
+fn entity_name(world: &World, entity: Entity) -> String {
+    world
+        .ecs
+        .entry_ref(entity)
+        .ok()
+        .and_then(|entry| {
+            entry
+                .get_component::<Name>()
+                .ok()
+                .map(|name| name.0.clone())
+        })
+        .unwrap_or_default()
+}
+
+fn node_components(
+    world: &World,
+    entity: Entity,
+    remap: &impl Fn(Entity) -> Entity,
+) -> Result<NodeComponents> {
+    let entry = world.ecs.entry_ref(entity)?;
+    // Joints target the new nodes, which are replaced by the entities they match
+    let skin = entry.get_component::<Skin>().ok().map(|skin| Skin {
+        name: skin.name.clone(),
+        joints: skin
+            .joints
+            .iter()
+            .map(|joint| Joint {
+                target: remap(joint.target),
+                inverse_bind_matrix: joint.inverse_bind_matrix,
+            })
+            .collect(),
+    });
+    Ok(NodeComponents {
+        transform: entry.get_component::<Transform>().ok().copied(),
+        mesh: entry.get_component::<MeshRender>().ok().cloned(),
+        skin,
+        camera: entry.get_component::<Camera>().ok().cloned(),
+        light: entry.get_component::<Light>().ok().copied(),
+        lod: entry.get_component::<Lod>().ok().cloned(),
+    })
+}
+
+fn replace_component<T: Component>(entry: &mut Entry, component: Option<T>) {
+    match component {
+        Some(component) => entry.add_component(component),
+        None => entry.remove_component::<T>(),
+    }
+}
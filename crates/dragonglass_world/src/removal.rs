@@ -0,0 +1,237 @@
+use crate::{
+    Animation, ColliderHandle, Ecs, Entity, Handle, PhysicsLod, Ragdoll, RigidBody, SceneGraph,
+    World,
+};
+use anyhow::Result;
+use legion::{component, EntityStore, IntoQuery};
+use petgraph::prelude::*;
+use rapier3d::{dynamics::JointData, geometry::Collider};
+use std::collections::{HashMap, HashSet};
+
+// Marks the entities being moved out of the world's ecs
+struct Removing;
+
+// A rigid body taken out of the physics world along with its colliders
+struct RemovedBody {
+    handle: Handle,
+    body: rapier3d::dynamics::RigidBody,
+    colliders: Vec<(ColliderHandle, Collider)>,
+}
+
+struct RemovedNode {
+    entity: Entity,
+    parent: Option<Entity>,
+    // An entity left in the same scenegraph, which roots are put back beside
+    sibling: Option<Entity>,
+}
+
+// Entities taken out of a world with every component they had, so they can be put back exactly as they were.
+// The entities keep their handles, so anything that referred to them still does once they are restored.
+pub struct RemovedEntities {
+    ecs: Ecs,
+    // Where each entity was in the scenegraph, with parents before their children.
+    // Entities that weren't in the scenegraph are restored without a place in it.
+    nodes: Vec<RemovedNode>,
+    bodies: Vec<RemovedBody>,
+    joints: Vec<(Handle, Handle, JointData)>,
+    animations: Vec<Animation>,
+}
+
+impl World {
+    // Takes the entities and their scenegraph descendants out of the world, along with their rigid bodies,
+    // ragdolls, joints, and any animations that target them
+    pub fn take_entities(&mut self, entities: &[Entity]) -> Result<RemovedEntities> {
+        let sim = &mut self.sim;
+        let mut removed = entities.iter().copied().collect::<HashSet<_>>();
+        for graph in sim.scene.graphs.iter() {
+            for entity in entities.iter() {
+                if let Some(index) = graph.find_node(*entity) {
+                    let mut dfs = Dfs::new(&graph.0, index);
+                    while let Some(node_index) = dfs.next(&graph.0) {
+                        removed.insert(graph[node_index]);
+                    }
+                }
+            }
+        }
+        removed.retain(|entity| sim.ecs.entry_ref(*entity).is_ok());
+
+        let mut nodes = Vec::new();
+        for graph in sim.scene.graphs.iter() {
+            let sibling = graph
+                .0
+                .node_indices()
+                .map(|index| graph[index])
+                .find(|entity| !removed.contains(entity));
+            graph.walk(|index| {
+                let entity = graph[index];
+                if removed.contains(&entity) {
+                    let parent = graph.parent_of(index).map(|parent| graph[parent]);
+                    nodes.push(RemovedNode {
+                        entity,
+                        parent,
+                        sibling,
+                    });
+                }
+                Ok(())
+            })?;
+        }
+        for graph in sim.scene.graphs.iter_mut() {
+            graph
+                .0
+                .retain_nodes(|graph, index| !removed.contains(&graph[index]));
+        }
+        sim.scene.graphs.retain(|graph| graph.number_of_nodes() > 0);
+
+        // Ragdoll bones are bodies of their own rather than components of the joints
+        let mut handles = Vec::new();
+        for entity in removed.iter() {
+            let entry = sim.ecs.entry_ref(*entity)?;
+            if let Ok(rigid_body) = entry.get_component::<RigidBody>() {
+                handles.push(rigid_body.handle);
+            }
+            if let Ok(ragdoll) = entry.get_component::<Ragdoll>() {
+                handles.extend(ragdoll.bones.iter().map(|bone| bone.body));
+            }
+        }
+        let physics = &mut sim.physics;
+        let joints = physics
+            .impulse_joints
+            .iter()
+            .filter(|(_, joint)| handles.contains(&joint.body1) || handles.contains(&joint.body2))
+            .map(|(_, joint)| (joint.body1, joint.body2, joint.data))
+            .collect();
+        let mut bodies = Vec::new();
+        for handle in handles.into_iter() {
+            let colliders = physics
+                .bodies
+                .get(handle)
+                .map(|body| {
+                    body.colliders()
+                        .iter()
+                        .filter_map(|handle| {
+                            physics
+                                .colliders
+                                .get(*handle)
+                                .map(|collider| (*handle, collider.clone()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            if let Some(body) = physics.remove_rigid_body(handle) {
+                bodies.push(RemovedBody {
+                    handle,
+                    body,
+                    colliders,
+                });
+            }
+        }
+
+        let (animations, kept) = std::mem::take(&mut self.assets.animations)
+            .into_iter()
+            .partition(|animation| {
+                animation
+                    .channels
+                    .iter()
+                    .any(|channel| removed.contains(&channel.target))
+            });
+        self.assets.animations = kept;
+
+        let sim = &mut self.sim;
+        for entity in removed.iter() {
+            if let Some(mut entry) = sim.ecs.entry(*entity) {
+                entry.add_component(Removing);
+            }
+        }
+        let mut ecs = Ecs::default();
+        ecs.move_from(&mut sim.ecs, &component::<Removing>());
+        for entity in removed.iter() {
+            if let Some(mut entry) = ecs.entry(*entity) {
+                entry.remove_component::<Removing>();
+            }
+        }
+
+        Ok(RemovedEntities {
+            ecs,
+            nodes,
+            bodies,
+            joints,
+            animations,
+        })
+    }
+
+    // Puts removed entities back where they were in the scenegraph, with new handles for their physics
+    pub fn restore_entities(&mut self, removed: RemovedEntities) -> Result<()> {
+        let RemovedEntities {
+            mut ecs,
+            nodes,
+            bodies,
+            joints,
+            animations,
+        } = removed;
+
+        let physics = &mut self.sim.physics;
+        let mut body_handles = HashMap::new();
+        let mut collider_handles = HashMap::new();
+        for removed_body in bodies.into_iter() {
+            let handle = physics.bodies.insert(removed_body.body);
+            for (previous, collider) in removed_body.colliders.into_iter() {
+                let collider_handle =
+                    physics
+                        .colliders
+                        .insert_with_parent(collider, handle, &mut physics.bodies);
+                collider_handles.insert(previous, collider_handle);
+            }
+            body_handles.insert(removed_body.handle, handle);
+        }
+        let body_handle = |handle: Handle| body_handles.get(&handle).copied().unwrap_or(handle);
+        for (body1, body2, data) in joints.into_iter() {
+            let (body1, body2) = (body_handle(body1), body_handle(body2));
+            // Joints to bodies that were removed since are left out
+            if physics.bodies.contains(body1) && physics.bodies.contains(body2) {
+                physics.impulse_joints.insert(body1, body2, data);
+            }
+        }
+
+        for rigid_body in <&mut RigidBody>::query().iter_mut(&mut ecs) {
+            rigid_body.handle = body_handle(rigid_body.handle);
+            for collider in rigid_body.colliders.iter_mut() {
+                *collider = collider_handles.get(collider).copied().unwrap_or(*collider);
+            }
+        }
+        for ragdoll in <&mut Ragdoll>::query().iter_mut(&mut ecs) {
+            for bone in ragdoll.bones.iter_mut() {
+                bone.body = body_handle(bone.body);
+            }
+        }
+        for lod in <&mut PhysicsLod>::query().iter_mut(&mut ecs) {
+            lod.remap_colliders(&collider_handles);
+        }
+        self.sim.ecs.move_from(&mut ecs, &legion::any());
+
+        let graphs = &mut self.sim.scene.graphs;
+        for node in nodes.into_iter() {
+            let anchor = node.parent.or(node.sibling);
+            let graph = anchor.and_then(|anchor| {
+                graphs
+                    .iter_mut()
+                    .find_map(|graph| graph.find_node(anchor).map(|index| (graph, index)))
+            });
+            match graph {
+                Some((graph, anchor_index)) => {
+                    let index = graph.add_node(node.entity);
+                    if node.parent.is_some() {
+                        graph.add_edge(anchor_index, index);
+                    }
+                }
+                None => {
+                    let mut graph = SceneGraph::new();
+                    graph.add_node(node.entity);
+                    graphs.push(graph);
+                }
+            }
+        }
+
+        self.assets.animations.extend(animations);
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+// Attaches the behavior in a script file to an entity.
+// The script's 'start' function is called with the entity once, then 'update' every frame.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Script {
+    pub path: String,
+}
+
+impl Script {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+}
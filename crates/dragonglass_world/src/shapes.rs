@@ -0,0 +1,247 @@
+use crate::Vertex;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// Unit primitives generated at runtime. Each is centered on the origin and fits in a unit cube.
+// Vertices are duplicated along uv seams and hard edges, so every face has continuous uvs
+// and tangents derived from them stay well behaved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Shape {
+    Cube,
+    Sphere,
+    Plane,
+    Capsule,
+    Cylinder,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 5] = [
+        Shape::Cube,
+        Shape::Sphere,
+        Shape::Plane,
+        Shape::Capsule,
+        Shape::Cylinder,
+    ];
+
+    const SLICES: u32 = 32;
+    const STACKS: u32 = 16;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cube => "Cube",
+            Self::Sphere => "Sphere",
+            Self::Plane => "Plane",
+            Self::Capsule => "Capsule",
+            Self::Cylinder => "Cylinder",
+        }
+    }
+
+    // Returns the vertices and the indices of the triangles, wound counter clockwise
+    pub fn generate(&self) -> (Vec<Vertex>, Vec<u32>) {
+        match self {
+            Self::Cube => cube(),
+            Self::Sphere => sphere(Self::SLICES, Self::STACKS),
+            Self::Plane => plane(1),
+            Self::Capsule => capsule(Self::SLICES, Self::STACKS / 2),
+            Self::Cylinder => cylinder(Self::SLICES),
+        }
+    }
+}
+
+pub fn cube() -> (Vec<Vertex>, Vec<u32>) {
+    // The normal of each face, followed by the directions its uvs run along
+    let faces = [
+        (glm::Vec3::x(), -glm::Vec3::z(), glm::Vec3::y()),
+        (-glm::Vec3::x(), glm::Vec3::z(), glm::Vec3::y()),
+        (glm::Vec3::y(), glm::Vec3::x(), -glm::Vec3::z()),
+        (-glm::Vec3::y(), glm::Vec3::x(), glm::Vec3::z()),
+        (glm::Vec3::z(), glm::Vec3::x(), glm::Vec3::y()),
+        (-glm::Vec3::z(), -glm::Vec3::x(), glm::Vec3::y()),
+    ];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for (normal, right, up) in faces.iter() {
+        let first = vertices.len() as u32;
+        let corners = [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
+        for (x, y) in corners.iter() {
+            vertices.push(vertex(
+                normal * 0.5 + right * *x + up * *y,
+                *normal,
+                glm::vec2(x + 0.5, 0.5 - y),
+            ));
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    (vertices, indices)
+}
+
+// A square on the xz plane facing up, split into a grid of quads
+pub fn plane(subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let subdivisions = subdivisions.max(1);
+    let mut vertices = Vec::new();
+    for row in 0..=subdivisions {
+        for column in 0..=subdivisions {
+            let u = column as f32 / subdivisions as f32;
+            let v = row as f32 / subdivisions as f32;
+            vertices.push(vertex(
+                glm::vec3(u - 0.5, 0.0, 0.5 - v),
+                glm::Vec3::y(),
+                glm::vec2(u, 1.0 - v),
+            ));
+        }
+    }
+
+    let mut indices = Vec::new();
+    let stride = subdivisions + 1;
+    for row in 0..subdivisions {
+        for column in 0..subdivisions {
+            let corner = row * stride + column;
+            let above = corner + stride;
+            indices.extend_from_slice(&[corner, corner + 1, above + 1, corner, above + 1, above]);
+        }
+    }
+    (vertices, indices)
+}
+
+pub fn sphere(slices: u32, stacks: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let profile = (0..=stacks)
+        .map(|stack| {
+            let angle = PI * stack as f32 / stacks as f32;
+            ProfilePoint {
+                radius: 0.5 * angle.sin(),
+                height: 0.5 * angle.cos(),
+                normal: glm::vec2(angle.sin(), angle.cos()),
+                v: stack as f32 / stacks as f32,
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    revolve(&profile, slices, &mut vertices, &mut indices);
+    (vertices, indices)
+}
+
+// Hemispheres joined by a cylinder, with a radius of a quarter of its height
+pub fn capsule(slices: u32, hemisphere_stacks: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let radius = 0.25;
+    let half_length = 0.25;
+    let hemisphere_stacks = hemisphere_stacks.max(1);
+    let mut profile = Vec::new();
+    for (center, first_angle) in [(half_length, 0.0), (-half_length, PI / 2.0)] {
+        for stack in 0..=hemisphere_stacks {
+            let angle = first_angle + (PI / 2.0) * stack as f32 / hemisphere_stacks as f32;
+            let height = center + radius * angle.cos();
+            profile.push(ProfilePoint {
+                radius: radius * angle.sin(),
+                height,
+                normal: glm::vec2(angle.sin(), angle.cos()),
+                v: 0.5 - height,
+            });
+        }
+    }
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    revolve(&profile, slices, &mut vertices, &mut indices);
+    (vertices, indices)
+}
+
+pub fn cylinder(slices: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let profile = [
+        ProfilePoint {
+            radius: 0.5,
+            height: 0.5,
+            normal: glm::vec2(1.0, 0.0),
+            v: 0.0,
+        },
+        ProfilePoint {
+            radius: 0.5,
+            height: -0.5,
+            normal: glm::vec2(1.0, 0.0),
+            v: 1.0,
+        },
+    ];
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    revolve(&profile, slices, &mut vertices, &mut indices);
+
+    // The caps get their own vertices so their edges stay sharp
+    for (height, normal) in [(0.5, glm::Vec3::y()), (-0.5, -glm::Vec3::y())] {
+        let center = vertices.len() as u32;
+        vertices.push(vertex(
+            glm::vec3(0.0, height, 0.0),
+            normal,
+            glm::vec2(0.5, 0.5),
+        ));
+        for slice in 0..=slices {
+            let (sin, cos) = (2.0 * PI * slice as f32 / slices as f32).sin_cos();
+            vertices.push(vertex(
+                glm::vec3(0.5 * sin, height, 0.5 * cos),
+                normal,
+                glm::vec2(0.5 + 0.5 * sin, 0.5 + 0.5 * cos),
+            ));
+        }
+        for slice in 0..slices {
+            let (current, next) = (center + 1 + slice, center + 2 + slice);
+            if height > 0.0 {
+                indices.extend_from_slice(&[center, current, next]);
+            } else {
+                indices.extend_from_slice(&[center, next, current]);
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+// A point on the outline of a shape that is spun around the y axis
+struct ProfilePoint {
+    radius: f32,
+    height: f32,
+    // The radial and vertical components of the normal
+    normal: glm::Vec2,
+    v: f32,
+}
+
+fn revolve(
+    profile: &[ProfilePoint],
+    slices: u32,
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+) {
+    let first = vertices.len() as u32;
+    for point in profile.iter() {
+        for slice in 0..=slices {
+            let u = slice as f32 / slices as f32;
+            let (sin, cos) = (2.0 * PI * u).sin_cos();
+            vertices.push(vertex(
+                glm::vec3(point.radius * sin, point.height, point.radius * cos),
+                glm::vec3(point.normal.x * sin, point.normal.y, point.normal.x * cos),
+                glm::vec2(u, point.v),
+            ));
+        }
+    }
+
+    // Triangles that would collapse into a pole are skipped
+    let stride = slices + 1;
+    for ring in 0..profile.len().saturating_sub(1) {
+        for slice in 0..slices {
+            let top = first + ring as u32 * stride + slice;
+            let bottom = top + stride;
+            if profile[ring + 1].radius > f32::EPSILON {
+                indices.extend_from_slice(&[top, bottom, bottom + 1]);
+            }
+            if profile[ring].radius > f32::EPSILON {
+                indices.extend_from_slice(&[top, bottom + 1, top + 1]);
+            }
+        }
+    }
+}
+
+fn vertex(position: glm::Vec3, normal: glm::Vec3, uv_0: glm::Vec2) -> Vertex {
+    Vertex {
+        position,
+        normal,
+        uv_0,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,912 @@
+use crate::{
+    deserialize_ecs, serialize_ecs, sim_world_as_bytes, sim_world_from_bytes, AnimationEvent,
+    Camera, CameraShake, Clock, ColliderHandle, CollisionEvent, DebugDraw, Ecs, Entity, FogOfWar,
+    Hidden, Layers, Name, PerspectiveCamera, Projection, RigidBody, Rng, SceneGraph,
+    SceneGraphNode, Transform, WorldPhysics,
+};
+use anyhow::{bail, Context, Result};
+use legion::{storage::Component, EntityStore, IntoQuery};
+use na::Point3;
+use nalgebra as na;
+use nalgebra_glm as glm;
+use petgraph::prelude::*;
+use rapier3d::{
+    dynamics::RigidBodyBuilder,
+    geometry::{ColliderBuilder, InteractionGroups, Ray},
+    prelude::RigidBodyType,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// The simulated part of a world: entities, their scenegraphs, physics, and time.
+// It has no render assets, so headless servers and tools can run it on its own.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SimWorld {
+    #[serde(serialize_with = "serialize_ecs", deserialize_with = "deserialize_ecs")]
+    pub ecs: Ecs,
+    pub physics: WorldPhysics,
+    pub scene: Scene,
+    pub rng: Rng,
+    pub clock: Clock,
+    #[serde(skip)]
+    pub fog_of_war: Option<FogOfWar>,
+    // Collisions from the physics steps since they were last taken
+    #[serde(skip)]
+    collision_events: Vec<CollisionEvent>,
+    // Notifies crossed by animations since they were last taken
+    #[serde(skip)]
+    pub(crate) animation_events: Vec<AnimationEvent>,
+    #[serde(skip)]
+    pub debug_draw: DebugDraw,
+    // The poses inverse kinematics last turned joints from and to
+    #[serde(skip)]
+    pub(crate) ik_poses: HashMap<Entity, crate::ik::IkPose>,
+}
+
+impl SimWorld {
+    pub const MAIN_CAMERA_NAME: &'static str = "Main Camera";
+
+    pub fn new() -> Result<Self> {
+        let mut sim = Self::default();
+        sim.initialize()?;
+        Ok(sim)
+    }
+
+    fn initialize(&mut self) -> Result<()> {
+        self.scene = Scene::default();
+        self.scene.name = "Main Scene".to_string();
+        self.add_default_camera()?;
+        Ok(())
+    }
+
+    fn add_default_camera(&mut self) -> Result<()> {
+        let position = glm::vec3(0.0, 0.0, 10.0);
+        let mut transform = Transform {
+            translation: position,
+            ..Default::default()
+        };
+        transform.look_at(&(-position), &glm::Vec3::y());
+
+        let camera_entity = self.ecs.push((
+            Name("Default Camera".to_string()),
+            transform,
+            Camera {
+                name: Self::MAIN_CAMERA_NAME.to_string(),
+                projection: Projection::Perspective(PerspectiveCamera {
+                    aspect_ratio: None,
+                    y_fov_rad: 70_f32.to_radians(),
+                    z_far: Some(1000.0),
+                    z_near: 0.1,
+                }),
+                enabled: true,
+                exposure: 1.0,
+                culling_mask: Layers::ALL,
+            },
+        ));
+
+        self.scene.default_scenegraph_mut()?.add_node(camera_entity);
+
+        Ok(())
+    }
+
+    pub fn add_default_light(&mut self) -> Result<()> {
+        let position = glm::vec3(-4.0, 10.0, 0.0);
+        let mut transform = Transform {
+            translation: position,
+            ..Default::default()
+        };
+        transform.look_at(&(-position), &glm::Vec3::y());
+        let light_entity = self.ecs.push((
+            Name("Default Light".to_string()),
+            transform,
+            Light {
+                color: glm::vec3(200.0, 200.0, 200.0),
+                kind: LightKind::Point,
+                ..Default::default()
+            },
+        ));
+        self.scene.default_scenegraph_mut()?.add_node(light_entity);
+        Ok(())
+    }
+
+    pub fn active_camera(&self) -> Result<Entity> {
+        let mut query = <(Entity, &Camera)>::query();
+        for (entity, camera) in query.iter(&self.ecs) {
+            if camera.enabled {
+                return Ok(*entity);
+            }
+        }
+        bail!("The world must have at least one entity with an enabled camera component to render with!")
+    }
+
+    pub fn global_transform(&self, graph: &SceneGraph, index: NodeIndex) -> Result<glm::Mat4> {
+        let entity = graph[index];
+        let transform = match self.ecs.entry_ref(entity)?.get_component::<Transform>() {
+            Ok(transform) => transform.matrix(),
+            Err(_) => bail!(
+                "A transform component was requested from a component that does not have one!"
+            ),
+        };
+        let mut incoming_walker = graph.0.neighbors_directed(index, Incoming).detach();
+        match incoming_walker.next_node(&graph.0) {
+            Some(parent_index) => Ok(self.global_transform(graph, parent_index)? * transform),
+            None => Ok(transform),
+        }
+    }
+
+    pub fn entity_global_transform_matrix(&self, entity: Entity) -> Result<glm::Mat4> {
+        let mut transform = glm::Mat4::identity();
+        let mut found = false;
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                if entity != graph[node_index] {
+                    return Ok(());
+                }
+                transform = self.global_transform(graph, node_index)?;
+                found = true;
+                Ok(())
+            })?;
+            if found {
+                break;
+            }
+        }
+        if !found {
+            // TODO: Maybe returning an error if the global transform of an entity that isn't in the scenegraph is better...
+            // Not found in the scenegraph, so the entity just have a local transform
+            transform = self
+                .ecs
+                .entry_ref(entity)?
+                .get_component::<Transform>()?
+                .matrix();
+        }
+        Ok(transform)
+    }
+
+    pub fn entity_global_transform(&self, entity: Entity) -> Result<Transform> {
+        let transform_matrix = self.entity_global_transform_matrix(entity)?;
+        Ok(Transform::from(transform_matrix))
+    }
+
+    // The global transform of an entity's parent, which is the space its local transform is in
+    pub fn parent_global_transform_matrix(&self, entity: Entity) -> Result<glm::Mat4> {
+        match self.parent_of(entity) {
+            Some(parent) => self.entity_global_transform_matrix(parent),
+            None => Ok(glm::Mat4::identity()),
+        }
+    }
+
+    // The local transform that places an entity at a global transform, given its parents
+    pub fn local_transform_from_global(
+        &self,
+        entity: Entity,
+        global: &glm::Mat4,
+    ) -> Result<Transform> {
+        let parent = self.parent_global_transform_matrix(entity)?;
+        Ok(Transform::from(glm::inverse(&parent) * global))
+    }
+
+    pub fn set_entity_global_transform(
+        &mut self,
+        entity: Entity,
+        global: &Transform,
+    ) -> Result<()> {
+        let local = self.local_transform_from_global(entity, &global.matrix())?;
+        *self
+            .ecs
+            .entry_mut(entity)
+            .context("Failed to find the entity to set the global transform of")?
+            .get_component_mut::<Transform>()? = local;
+        Ok(())
+    }
+
+    // Converts a point in an entity's local space to world space
+    pub fn local_to_world_point(&self, entity: Entity, point: &glm::Vec3) -> Result<glm::Vec3> {
+        let matrix = self.entity_global_transform_matrix(entity)?;
+        Ok((matrix * glm::vec4(point.x, point.y, point.z, 1.0)).xyz())
+    }
+
+    pub fn world_to_local_point(&self, entity: Entity, point: &glm::Vec3) -> Result<glm::Vec3> {
+        let inverse = glm::inverse(&self.entity_global_transform_matrix(entity)?);
+        Ok((inverse * glm::vec4(point.x, point.y, point.z, 1.0)).xyz())
+    }
+
+    // Directions ignore translation, but are still rotated and scaled
+    pub fn local_to_world_vector(&self, entity: Entity, vector: &glm::Vec3) -> Result<glm::Vec3> {
+        let matrix = self.entity_global_transform_matrix(entity)?;
+        Ok((matrix * glm::vec4(vector.x, vector.y, vector.z, 0.0)).xyz())
+    }
+
+    pub fn world_to_local_vector(&self, entity: Entity, vector: &glm::Vec3) -> Result<glm::Vec3> {
+        let inverse = glm::inverse(&self.entity_global_transform_matrix(entity)?);
+        Ok((inverse * glm::vec4(vector.x, vector.y, vector.z, 0.0)).xyz())
+    }
+
+    // Turns an entity to face a point in world space, whatever its parents are rotated by
+    pub fn entity_look_at(
+        &mut self,
+        entity: Entity,
+        target: &glm::Vec3,
+        up: &glm::Vec3,
+    ) -> Result<()> {
+        let parent = self.parent_global_transform_matrix(entity)?;
+        let inverse = glm::inverse(&parent);
+        let target = (inverse * glm::vec4(target.x, target.y, target.z, 1.0)).xyz();
+        let up = (inverse * glm::vec4(up.x, up.y, up.z, 0.0)).xyz();
+        self.ecs
+            .entry_mut(entity)
+            .context("Failed to find the entity to turn")?
+            .get_component_mut::<Transform>()?
+            .look_at_point(&target, &up);
+        Ok(())
+    }
+
+    pub fn active_camera_matrices(&self, aspect_ratio: f32) -> Result<(glm::Mat4, glm::Mat4)> {
+        self.camera_matrices(self.active_camera()?, aspect_ratio)
+    }
+
+    // The projection and view matrices of any camera, whether or not it is the active one
+    pub fn camera_matrices(
+        &self,
+        camera_entity: Entity,
+        aspect_ratio: f32,
+    ) -> Result<(glm::Mat4, glm::Mat4)> {
+        let transform = self.entity_global_transform(camera_entity)?;
+        let mut view = transform.as_view_matrix();
+        let projection = {
+            let entry = self.ecs.entry_ref(camera_entity)?;
+            if let Ok(shake) = entry.get_component::<CameraShake>() {
+                view = glm::inverse(&shake.matrix()) * view;
+            }
+            let camera = entry.get_component::<Camera>()?;
+            camera.projection_matrix(aspect_ratio)
+        };
+        Ok((projection, view))
+    }
+
+    pub fn active_camera_is_main(&self) -> Result<bool> {
+        let entity = self.active_camera()?;
+        let entry = self.ecs.entry_ref(entity)?;
+        let camera = entry.get_component::<Camera>()?;
+        Ok(camera.name == Self::MAIN_CAMERA_NAME)
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.ecs.clear();
+        self.scene.graphs.clear();
+        self.initialize()
+    }
+
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        sim_world_as_bytes(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        sim_world_from_bytes(bytes)
+    }
+
+    pub fn lights(&self) -> Result<Vec<(Transform, Light)>> {
+        let mut lights = Vec::new();
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                let node_transform = self.global_transform(graph, node_index)?;
+                if let Ok(light) = self.ecs.entry_ref(entity)?.get_component::<Light>() {
+                    lights.push((Transform::from(node_transform), *light));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(lights)
+    }
+
+    // Every component of a type on entities in the scene that aren't hidden,
+    // along with the global transform of its entity
+    pub fn visible_components<T: Component + Clone>(&self) -> Result<Vec<(Transform, T)>> {
+        let mut components = Vec::new();
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                let entry = self.ecs.entry_ref(entity)?;
+                if entry.get_component::<Hidden>().is_ok() {
+                    return Ok(());
+                }
+                if let Ok(component) = entry.get_component::<T>() {
+                    let transform = self.global_transform(graph, node_index)?;
+                    components.push((Transform::from(transform), component.clone()));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(components)
+    }
+
+    pub fn joint_matrices(&self) -> Result<Vec<glm::Mat4>> {
+        let mut offset = 0;
+        let mut number_of_joints = 0;
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                if let Ok(skin) = self.ecs.entry_ref(entity)?.get_component::<Skin>() {
+                    number_of_joints += skin.joints.len();
+                }
+                Ok(())
+            })?;
+        }
+        let mut joint_matrices = vec![glm::Mat4::identity(); number_of_joints];
+        for graph in self.scene.graphs.iter() {
+            graph.walk(|node_index| {
+                let entity = graph[node_index];
+                let node_transform = self.global_transform(graph, node_index)?;
+                if let Ok(skin) = self.ecs.entry_ref(entity)?.get_component::<Skin>() {
+                    for joint in skin.joints.iter() {
+                        let joint_transform = {
+                            let mut transform = glm::Mat4::identity();
+                            for graph in self.scene.graphs.iter() {
+                                if let Some(index) = graph.find_node(joint.target) {
+                                    transform = self.global_transform(graph, index)?;
+                                }
+                            }
+                            transform
+                        };
+                        joint_matrices[offset] = glm::inverse(&node_transform)
+                            * joint_transform
+                            * joint.inverse_bind_matrix;
+                        offset += 1;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(joint_matrices)
+    }
+
+    pub fn add_cylinder_collider(
+        &mut self,
+        entity: Entity,
+        half_height: f32,
+        radius: f32,
+        collision_groups: InteractionGroups,
+    ) -> Result<()> {
+        let collider = self
+            .entity_physics_material(entity)
+            .apply(ColliderBuilder::cylinder(half_height, radius))
+            .collision_groups(collision_groups)
+            .build();
+
+        let rigid_body_handle = self
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<RigidBody>()?
+            .handle;
+
+        self.physics.colliders.insert_with_parent(
+            collider,
+            rigid_body_handle,
+            &mut self.physics.bodies,
+        );
+
+        Ok(())
+    }
+
+    pub fn add_rigid_body(&mut self, entity: Entity, rigid_body_type: RigidBodyType) -> Result<()> {
+        let handle = {
+            let isometry =
+                Transform::from(self.entity_global_transform_matrix(entity)?).as_isometry();
+
+            // Insert a corresponding rigid body
+            let rigid_body = self
+                .entity_rigid_body_settings(entity)
+                .build(RigidBodyBuilder::new(rigid_body_type).position(isometry));
+            self.physics.bodies.insert(rigid_body)
+        };
+        self.ecs
+            .entry(entity)
+            .context("")?
+            .add_component(RigidBody::new(handle));
+        Ok(())
+    }
+
+    pub fn remove_rigid_body(&mut self, entity: Entity) -> Result<()> {
+        let mut entry = self.ecs.entry(entity).context("Failed to find entity!")?;
+        let rigid_body_handle = entry.get_component::<RigidBody>()?.handle;
+        entry.remove_component::<RigidBody>();
+        self.physics.remove_rigid_body(rigid_body_handle);
+        Ok(())
+    }
+
+    pub fn parent_of(&self, entity: Entity) -> Option<Entity> {
+        self.scene.graphs.iter().find_map(|graph| {
+            let index = graph.find_node(entity)?;
+            graph.parent_of(index).map(|parent| graph[parent])
+        })
+    }
+
+    // Every entity rendering the named mesh.
+    // Linked duplicates share their mesh, so these are all the instances that an edit to it affects.
+    pub fn mesh_instances(&self, mesh_name: &str) -> Vec<Entity> {
+        let mut query = <(Entity, &MeshRender)>::query();
+        query
+            .iter(&self.ecs)
+            .filter(|(_, mesh_render)| mesh_render.name == mesh_name)
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+
+    pub fn flatten_scenegraphs(&self) -> Vec<SceneGraphNode> {
+        let mut offset = 0;
+        self.scene
+            .graphs
+            .iter()
+            .flat_map(|graph| {
+                let mut graph_nodes = graph.collect_nodes().expect("Failed to collect nodes");
+                graph_nodes
+                    .iter_mut()
+                    .for_each(|node| node.offset += offset);
+                offset += graph_nodes.len() as u32;
+                graph_nodes
+            })
+            .collect::<Vec<_>>()
+    }
+
+    pub fn mouse_ray(&mut self, configuration: &MouseRayConfiguration) -> Result<Ray> {
+        let MouseRayConfiguration {
+            viewport,
+            projection_matrix,
+            view_matrix,
+            mouse_position,
+        } = *configuration;
+
+        let mut position = mouse_position;
+        position.y = viewport.height - position.y;
+
+        let near_point = glm::vec2_to_vec3(&position);
+
+        let mut far_point = near_point;
+        far_point.z = 1.0;
+
+        let viewport = viewport.as_glm_vec();
+        let p_near = glm::unproject_zo(&near_point, &view_matrix, &projection_matrix, viewport);
+        let p_far = glm::unproject_zo(&far_point, &view_matrix, &projection_matrix, viewport);
+
+        let direction = (p_far - p_near).normalize();
+        let ray = Ray::new(Point3::from(p_near), direction);
+
+        Ok(ray)
+    }
+
+    pub fn pick_object(
+        &mut self,
+        mouse_ray_configuration: &MouseRayConfiguration,
+        interact_distance: f32,
+        groups: InteractionGroups,
+    ) -> Result<Option<Entity>> {
+        let ray = self.mouse_ray(mouse_ray_configuration)?;
+        let hit = self.cast_ray(&ray, interact_distance, groups)?;
+        Ok(hit.map(|(entity, _)| entity))
+    }
+
+    // The entity whose collider the ray hits first, and how far along the ray it was hit
+    pub fn cast_ray(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        groups: InteractionGroups,
+    ) -> Result<Option<(Entity, f32)>> {
+        let hit = self.physics.query_pipeline.cast_ray(
+            &self.physics.colliders,
+            ray,
+            max_distance,
+            true,
+            groups,
+            None,
+        );
+
+        let (handle, distance) = match hit {
+            Some(hit) => hit,
+            None => return Ok(None),
+        };
+        let collider = &self.physics.colliders[handle];
+        let rigid_body_handle = collider
+            .parent()
+            .context("Failed to get a collider's parent!")?;
+        let mut query = <(Entity, &RigidBody)>::query();
+        let entity = query
+            .iter(&self.ecs)
+            .find(|(_, rigid_body)| rigid_body.handle == rigid_body_handle)
+            .map(|(entity, _)| *entity);
+        Ok(entity.map(|entity| (entity, distance)))
+    }
+
+    pub fn tick(&mut self, delta_time: f32) -> Result<()> {
+        self.clock.advance(delta_time);
+        // Skyboxes keep fading while the simulation is paused
+        self.scene.update_skybox_fade(delta_time);
+        self.sync_collider_layers();
+        // Path followers move first, so kinematic bodies on paths carry what they touch this step
+        if self.clock.delta() > 0.0 {
+            dragonglass_profiling::profile_scope!("path followers");
+            self.update_path_followers(self.clock.delta())?;
+        }
+        if self.clock.delta() > 0.0 {
+            let steps = self.physics_steps(self.clock.delta());
+            let step = self.clock.delta() / steps as f32;
+            for _ in 0..steps {
+                // Vehicles and fluids push on their bodies just before the step that moves them
+                {
+                    dragonglass_profiling::profile_scope!("vehicles");
+                    self.update_vehicles(step)?;
+                }
+                {
+                    dragonglass_profiling::profile_scope!("buoyancy");
+                    self.update_buoyancy(step)?;
+                }
+                dragonglass_profiling::profile_scope!("physics");
+                self.physics.update(step);
+                self.collect_collision_events();
+            }
+        } else {
+            // A step without time moves nothing, but picks up colliders moved while paused,
+            // such as in the editor, so picking and ray casts still see them
+            self.physics.update(0.0);
+        }
+        if self.clock.delta() > 0.0 {
+            dragonglass_profiling::profile_scope!("particles");
+            self.update_particles()?;
+        }
+        if self.clock.delta() > 0.0 {
+            dragonglass_profiling::profile_scope!("camera rigs");
+            self.update_camera_rigs(self.clock.delta())?;
+        }
+        dragonglass_profiling::profile_scope!("fog of war");
+        self.update_fog_of_war()?;
+        Ok(())
+    }
+
+    pub fn take_collision_events(&mut self) -> Vec<CollisionEvent> {
+        std::mem::take(&mut self.collision_events)
+    }
+
+    pub fn take_animation_events(&mut self) -> Vec<AnimationEvent> {
+        std::mem::take(&mut self.animation_events)
+    }
+
+    // Colliders that were removed, or that have no entity, are left out
+    fn collect_collision_events(&mut self) {
+        let (started, stopped) = self.physics.touching_changes();
+        if started.is_empty() && stopped.is_empty() {
+            return;
+        }
+
+        let mut query = <(Entity, &RigidBody)>::query();
+        let entities = query
+            .iter(&self.ecs)
+            .map(|(entity, rigid_body)| (rigid_body.handle, *entity))
+            .collect::<HashMap<_, _>>();
+        let colliders = &self.physics.colliders;
+        let entity_of = |collider: ColliderHandle| {
+            colliders
+                .get(collider)
+                .and_then(|collider| collider.parent())
+                .and_then(|handle| entities.get(&handle).copied())
+        };
+        let pairs = |pairs: Vec<(ColliderHandle, ColliderHandle)>| {
+            pairs
+                .into_iter()
+                .filter_map(|(first, second)| Some((entity_of(first)?, entity_of(second)?)))
+                .collect::<Vec<_>>()
+        };
+
+        let started = pairs(started)
+            .into_iter()
+            .map(|(first, second)| CollisionEvent::Started(first, second));
+        let stopped = pairs(stopped)
+            .into_iter()
+            .map(|(first, second)| CollisionEvent::Stopped(first, second));
+        self.collision_events.extend(started.chain(stopped));
+    }
+
+    /// Sync the entity's physics rigid body with its transform
+    pub fn sync_rigid_body_to_transform(&mut self, entity: Entity) -> Result<()> {
+        let entry = self.ecs.entry_ref(entity)?;
+        let rigid_body = entry.get_component::<RigidBody>()?;
+        let transform = entry.get_component::<Transform>()?;
+        if let Some(body) = self.physics.bodies.get_mut(rigid_body.handle) {
+            let mut position = *body.position();
+            position.translation.vector = transform.translation;
+            body.set_position(position, true);
+            body.set_rotation(glm::quat_euler_angles(&transform.rotation), true);
+        }
+        Ok(())
+    }
+
+    /// Sync the entity's transform with its physics rigid body
+    pub fn sync_transform_to_rigid_body(&mut self, entity: Entity) -> Result<()> {
+        let rigid_body_handle = self
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<RigidBody>()?
+            .handle;
+        let mut entry = self.ecs.entry(entity).context("Failed to find entity!")?;
+        let transform = entry.get_component_mut::<Transform>()?;
+        if let Some(body) = self.physics.bodies.get(rigid_body_handle) {
+            let position = body.position();
+            transform.translation = position.translation.vector;
+            transform.rotation = *position.rotation.quaternion();
+        }
+        if let Some(body) = self.physics.bodies.get_mut(rigid_body_handle) {
+            body.wake_up(true);
+        }
+        Ok(())
+    }
+
+    /// Sync the render transforms with the physics rigid bodies
+    pub fn sync_all_rigid_bodies(&mut self) {
+        let mut query = <(&RigidBody, &mut Transform)>::query();
+        for (rigid_body, transform) in query.iter_mut(&mut self.ecs) {
+            if let Some(body) = self.physics.bodies.get(rigid_body.handle) {
+                let position = body.position();
+                transform.translation = position.translation.vector;
+                transform.rotation = *position.rotation.quaternion();
+            }
+        }
+    }
+
+    /// Sync the render transforms with the dynamic physics rigid bodies,
+    /// leaving static and kinematic bodies to follow their transforms
+    pub fn sync_dynamic_rigid_bodies(&mut self) {
+        let mut query = <(&RigidBody, &mut Transform)>::query();
+        for (rigid_body, transform) in query.iter_mut(&mut self.ecs) {
+            if let Some(body) = self.physics.bodies.get(rigid_body.handle) {
+                if !body.is_dynamic() {
+                    continue;
+                }
+                let position = body.position();
+                transform.translation = position.translation.vector;
+                transform.rotation = *position.rotation.quaternion();
+            }
+        }
+    }
+
+    pub fn entity_model_matrix(
+        &self,
+        entity: Entity,
+        global_transform: glm::Mat4,
+    ) -> Result<glm::Mat4> {
+        let entry = self.ecs.entry_ref(entity)?;
+        let model = match entry.get_component::<RigidBody>() {
+            Ok(rigid_body) => {
+                let body = self
+                    .physics
+                    .bodies
+                    .get(rigid_body.handle)
+                    .context("Failed to acquire physics body to render!")?;
+                let position = body.position();
+                let translation = position.translation.vector;
+                let rotation = *position.rotation.quaternion();
+                let scale = Transform::from(global_transform).scale;
+                Transform::new(translation, rotation, scale).matrix()
+            }
+            Err(_) => global_transform,
+        };
+        Ok(model)
+    }
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn aspect_ratio(&self) -> f32 {
+        let height = if self.height > 0.0 { self.height } else { 1.0 };
+        self.width / height
+    }
+
+    pub fn as_glm_vec(&self) -> glm::Vec4 {
+        glm::vec4(self.x, self.y, self.width, self.height)
+    }
+}
+
+pub struct MouseRayConfiguration {
+    pub viewport: Viewport,
+    pub projection_matrix: glm::Mat4,
+    pub view_matrix: glm::Mat4,
+    pub mouse_position: glm::Vec2,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scene {
+    pub name: String,
+    pub graphs: Vec<SceneGraph>,
+    // An index into the world's hdr textures
+    pub skybox: Option<usize>,
+    #[serde(skip)]
+    pub skybox_fade: Option<SkyboxFade>,
+    pub ambient_light: AmbientLight,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            name: "Unnamed Scene".to_string(),
+            graphs: vec![SceneGraph::default()],
+            skybox: None,
+            skybox_fade: None,
+            ambient_light: AmbientLight::default(),
+        }
+    }
+}
+
+// Lights every surface evenly when the scene has no skybox to light it with
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct AmbientLight {
+    pub color: glm::Vec3,
+    pub intensity: f32,
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self {
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 0.3,
+        }
+    }
+}
+
+impl AmbientLight {
+    pub fn radiance(&self) -> glm::Vec3 {
+        self.color * self.intensity
+    }
+}
+
+// A crossfade from a previous skybox to the scene's current one
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkyboxFade {
+    pub from: usize,
+    pub elapsed: f32,
+    pub duration: f32,
+}
+
+impl SkyboxFade {
+    // How much of the current skybox is shown, from 0 to 1
+    pub fn blend(&self) -> f32 {
+        (self.elapsed / self.duration).clamp(0.0, 1.0)
+    }
+}
+
+impl Scene {
+    pub fn set_skybox(&mut self, index: usize) {
+        self.skybox = Some(index);
+        self.skybox_fade = None;
+    }
+
+    // Scenes without a skybox switch to the new one immediately
+    pub fn fade_skybox(&mut self, index: usize, duration: f32) {
+        self.skybox_fade = match self.skybox {
+            Some(from) if from != index && duration > 0.0 => Some(SkyboxFade {
+                from,
+                elapsed: 0.0,
+                duration,
+            }),
+            _ => None,
+        };
+        self.skybox = Some(index);
+    }
+
+    pub fn update_skybox_fade(&mut self, delta_time: f32) {
+        if let Some(fade) = self.skybox_fade.as_mut() {
+            fade.elapsed += delta_time;
+            if fade.elapsed >= fade.duration {
+                self.skybox_fade = None;
+            }
+        }
+    }
+
+    pub fn default_scenegraph_mut(&mut self) -> Result<&mut SceneGraph> {
+        match self.graphs.iter_mut().next() {
+            Some(graph) => Ok(graph),
+            None => bail!("Failed to find default scenegraph in scene: {}!", self.name),
+        }
+    }
+}
+
+// The 'name' field is purposefully omitted to keep the struct 'Copy'able
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Light {
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    pub kind: LightKind,
+    pub shadows: ShadowSettings,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+impl Default for LightKind {
+    fn default() -> Self {
+        Self::Directional
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShadowResolution {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl ShadowResolution {
+    pub const ALL: [ShadowResolution; 4] = [Self::Low, Self::Medium, Self::High, Self::Ultra];
+
+    // The width and height of the light's shadow map in texels
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::Low => 512,
+            Self::Medium => 1024,
+            Self::High => 2048,
+            Self::Ultra => 4096,
+        }
+    }
+}
+
+impl Default for ShadowResolution {
+    fn default() -> Self {
+        Self::Medium
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct ShadowSettings {
+    pub casts_shadows: bool,
+    pub resolution: ShadowResolution,
+    // Offsets the depth compared against the shadow map.
+    // Too little causes shadow acne and too much causes peter-panning.
+    pub depth_bias: f32,
+    // Offsets the lookup position along the surface normal,
+    // which fixes acne on surfaces at grazing angles
+    pub normal_offset_bias: f32,
+    // The radius of the filtering kernel in texels, where zero gives hard shadows
+    pub blur_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            casts_shadows: true,
+            resolution: ShadowResolution::default(),
+            depth_bias: 0.005,
+            normal_offset_bias: 0.02,
+            blur_radius: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Skin {
+    pub name: String,
+    pub joints: Vec<Joint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Joint {
+    pub target: Entity,
+    pub inverse_bind_matrix: glm::Mat4,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshRender {
+    pub name: String,
+}
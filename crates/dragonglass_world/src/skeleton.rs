@@ -0,0 +1,111 @@
+use crate::{Entity, Name, SimWorld, Skin, Transform};
+use anyhow::{Context, Result};
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+// Attaches an entity to a named joint of a skin, such as a weapon to a hand or a hat to a head.
+// The entity follows the animated joint each frame, wherever it is in the scenegraph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Socket {
+    // The entity with the skin component
+    pub skin: Entity,
+    pub joint: String,
+    // Places the attached entity relative to the joint
+    pub offset: Transform,
+}
+
+impl Socket {
+    pub fn new(skin: Entity, joint: &str) -> Self {
+        Self {
+            skin,
+            joint: joint.to_string(),
+            offset: Transform::default(),
+        }
+    }
+}
+
+impl SimWorld {
+    // The joint entity of a skin with the given name
+    pub fn find_joint(&self, skin: Entity, name: &str) -> Result<Option<Entity>> {
+        let entry = self.ecs.entry_ref(skin)?;
+        let skin = entry.get_component::<Skin>()?;
+        Ok(skin.joints.iter().map(|joint| joint.target).find(|joint| {
+            self.ecs
+                .entry_ref(*joint)
+                .ok()
+                .and_then(|entry| {
+                    entry
+                        .get_component::<Name>()
+                        .ok()
+                        .map(|joint_name| joint_name.0 == name)
+                })
+                .unwrap_or(false)
+        }))
+    }
+
+    // Moves each socketed entity onto its joint.
+    // Runs after animations and inverse kinematics so attachments follow the final pose.
+    pub fn update_sockets(&mut self) -> Result<()> {
+        let mut query = <(Entity, &Socket)>::query();
+        let sockets = query
+            .iter(&self.ecs)
+            .map(|(entity, socket)| (*entity, socket.clone()))
+            .collect::<Vec<_>>();
+        for (entity, socket) in sockets.into_iter() {
+            // Sockets whose skin or joint is gone are dropped, so they are only reported once
+            let joint = match self.find_joint(socket.skin, &socket.joint).ok().flatten() {
+                Some(joint) => joint,
+                None => {
+                    log::warn!(
+                        "Removing socket whose joint '{}' no longer exists",
+                        socket.joint
+                    );
+                    if let Some(mut entry) = self.ecs.entry(entity) {
+                        entry.remove_component::<Socket>();
+                    }
+                    continue;
+                }
+            };
+            let global = self.entity_global_transform_matrix(joint)? * socket.offset.matrix();
+            let local = self.local_transform_from_global(entity, &global)?;
+            if let Ok(transform) = self
+                .ecs
+                .entry_mut(entity)
+                .context("A socketed entity no longer exists")?
+                .get_component_mut::<Transform>()
+            {
+                *transform = local;
+            }
+        }
+        Ok(())
+    }
+
+    // Queues a line from each joint to its parent joint and a cross at each joint for every skin
+    pub fn draw_skeletons(&mut self) -> Result<()> {
+        let color = glm::vec4(0.3, 0.9, 1.0, 1.0);
+        let mut query = <&Skin>::query();
+        let joints = query
+            .iter(&self.ecs)
+            .map(|skin| skin.joints.iter().map(|joint| joint.target).collect())
+            .collect::<Vec<Vec<Entity>>>();
+        for skin_joints in joints.iter() {
+            for joint in skin_joints.iter() {
+                let position = self.joint_position(*joint)?;
+                self.debug_draw.cross(position, 0.05, color);
+                if let Some(parent) = self.parent_of(*joint) {
+                    if skin_joints.contains(&parent) {
+                        let parent_position = self.joint_position(parent)?;
+                        self.debug_draw.line(parent_position, position, color);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn joint_position(&self, joint: Entity) -> Result<glm::Vec3> {
+        let matrix = self.entity_global_transform_matrix(joint)?;
+        Ok(glm::vec3(matrix.m14, matrix.m24, matrix.m34))
+    }
+}
@@ -0,0 +1,41 @@
+use crate::World;
+use anyhow::Result;
+
+// A serialized copy of the world, including its physics state,
+// that can be restored later to return the world to exactly how it was
+pub struct WorldSnapshot {
+    bytes: Vec<u8>,
+}
+
+impl WorldSnapshot {
+    pub fn size_in_bytes(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // Snapshots read back from a file, such as the start of a replay
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+}
+
+impl World {
+    pub fn snapshot(&self) -> Result<WorldSnapshot> {
+        Ok(WorldSnapshot {
+            bytes: self.as_bytes()?,
+        })
+    }
+
+    // Entities keep the same handles they had when the snapshot was taken
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) -> Result<()> {
+        let mut world = Self::from_bytes(&snapshot.bytes)?;
+        // Runtime-only state isn't serialized, so it is carried over
+        world.sim.fog_of_war = self.fog_of_war.take();
+        world.sim.physics.sync_touching();
+        *self = world;
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{Entity, RigidBody, SimWorld, Transform};
+use anyhow::{Context, Result};
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplineKind {
+    // Passes through every control point
+    CatmullRom,
+    // Cubic segments that pass through every third point, starting with the first.
+    // The two points between each pair are handles the curve bends toward.
+    Bezier,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SplinePoint {
+    // A position in the space of the spline's entity, so the curve moves with it
+    Position(glm::Vec3),
+    // Follows an entity's position in the world, such as a marker placed in the scene
+    Entity(Entity),
+}
+
+// A curve through control points, for moving platforms, camera paths, and patrol routes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spline {
+    pub kind: SplineKind,
+    pub points: Vec<SplinePoint>,
+    // Joins the last point back to the first
+    pub looping: bool,
+}
+
+impl Spline {
+    pub fn catmull_rom(points: Vec<SplinePoint>) -> Self {
+        Self {
+            kind: SplineKind::CatmullRom,
+            points,
+            looping: false,
+        }
+    }
+
+    pub fn bezier(points: Vec<SplinePoint>) -> Self {
+        Self {
+            kind: SplineKind::Bezier,
+            points,
+            looping: false,
+        }
+    }
+}
+
+// A point on a spline through the given positions at a progress from zero to one
+pub fn evaluate_spline(
+    kind: SplineKind,
+    points: &[glm::Vec3],
+    progress: f32,
+    looping: bool,
+) -> glm::Vec3 {
+    match kind {
+        SplineKind::CatmullRom => catmull_rom(points, progress, looping),
+        SplineKind::Bezier => bezier(points, progress, looping),
+    }
+}
+
+// Splits a progress along a spline into a segment and how far along that segment it is
+fn segment_at(segments: i32, progress: f32, looping: bool) -> (i32, f32) {
+    let progress = if looping {
+        progress.rem_euclid(1.0)
+    } else {
+        progress.clamp(0.0, 1.0)
+    };
+    let position = progress * segments as f32;
+    let segment = (position.floor() as i32).min(segments - 1);
+    (segment, position - segment as f32)
+}
+
+// Looping splines join the last point back to the first
+pub fn catmull_rom(points: &[glm::Vec3], progress: f32, looping: bool) -> glm::Vec3 {
+    match points.len() {
+        0 => return glm::Vec3::zeros(),
+        1 => return points[0],
+        _ => {}
+    }
+    let count = points.len() as i32;
+    let segments = if looping { count } else { count - 1 };
+    let (segment, t) = segment_at(segments, progress, looping);
+    let point = |index: i32| {
+        if looping {
+            points[index.rem_euclid(count) as usize]
+        } else {
+            points[index.clamp(0, count - 1) as usize]
+        }
+    };
+    let (p0, p1, p2, p3) = (
+        point(segment - 1),
+        point(segment),
+        point(segment + 1),
+        point(segment + 2),
+    );
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+// Points left over after the last full segment are ignored.
+// Looping splines use the first point as the end of their last segment.
+pub fn bezier(points: &[glm::Vec3], progress: f32, looping: bool) -> glm::Vec3 {
+    let count = points.len() as i32;
+    let segments = if looping { count / 3 } else { (count - 1) / 3 };
+    if segments < 1 {
+        return points.first().copied().unwrap_or_else(glm::Vec3::zeros);
+    }
+    let (segment, t) = segment_at(segments, progress, looping);
+    let point = |index: i32| points[index.rem_euclid(count) as usize];
+    let first = segment * 3;
+    let (p0, p1, p2, p3) = (
+        point(first),
+        point(first + 1),
+        point(first + 2),
+        point(first + 3),
+    );
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+// A spline sampled into straight pieces, so positions can be found by the distance traveled along it
+#[derive(Default, Debug, Clone)]
+pub struct SplineSamples {
+    pub positions: Vec<glm::Vec3>,
+    // The distance along the spline to each position
+    pub distances: Vec<f32>,
+}
+
+impl SplineSamples {
+    pub const SAMPLES_PER_SEGMENT: usize = 16;
+
+    pub fn new(kind: SplineKind, points: &[glm::Vec3], looping: bool) -> Self {
+        let count = (points.len().max(2) * Self::SAMPLES_PER_SEGMENT) as f32;
+        let mut samples = Self::default();
+        for index in 0..=count as usize {
+            let position = evaluate_spline(kind, points, index as f32 / count, looping);
+            let distance = match samples.positions.last() {
+                Some(previous) => samples.length() + glm::distance(previous, &position),
+                None => 0.0,
+            };
+            samples.positions.push(position);
+            samples.distances.push(distance);
+        }
+        samples
+    }
+
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or_default()
+    }
+
+    pub fn position_at(&self, distance: f32) -> glm::Vec3 {
+        let (index, t) = self.piece_at(distance);
+        match self.positions.get(index + 1) {
+            Some(next) => glm::lerp(&self.positions[index], next, t),
+            None => self
+                .positions
+                .get(index)
+                .copied()
+                .unwrap_or_else(glm::Vec3::zeros),
+        }
+    }
+
+    // The normalized direction of the spline at a distance along it
+    pub fn tangent_at(&self, distance: f32) -> glm::Vec3 {
+        let (index, _) = self.piece_at(distance);
+        match (self.positions.get(index), self.positions.get(index + 1)) {
+            (Some(start), Some(end)) if glm::distance(start, end) > f32::EPSILON => {
+                (end - start).normalize()
+            }
+            _ => glm::Vec3::zeros(),
+        }
+    }
+
+    // The index of the piece a distance falls on and how far along the piece it is
+    fn piece_at(&self, distance: f32) -> (usize, f32) {
+        if self.positions.len() < 2 {
+            return (0, 0.0);
+        }
+        let distance = distance.clamp(0.0, self.length());
+        let index = match self
+            .distances
+            .binary_search_by(|probe| probe.total_cmp(&distance))
+        {
+            Ok(index) => index,
+            Err(index) => index.saturating_sub(1),
+        }
+        .min(self.positions.len() - 2);
+        let (start, end) = (self.distances[index], self.distances[index + 1]);
+        let t = if end > start {
+            (distance - start) / (end - start)
+        } else {
+            0.0
+        };
+        (index, t)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PathMode {
+    // Stops at the end of the spline
+    Once,
+    // Starts over from the beginning
+    Loop,
+    // Turns around at each end
+    PingPong,
+}
+
+// Moves an entity along a spline at a constant speed.
+// Kinematic rigid bodies are moved by the physics step, so they carry what rests on them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathFollower {
+    pub spline: Entity,
+    // Units per second along the spline
+    pub speed: f32,
+    // How far along the spline the entity is
+    pub distance: f32,
+    pub mode: PathMode,
+    // Turns the entity to face the way it is moving
+    pub orient: bool,
+    pub playing: bool,
+    // Moving back toward the start, when ping ponging
+    pub reversed: bool,
+}
+
+impl PathFollower {
+    pub fn new(spline: Entity, speed: f32) -> Self {
+        Self {
+            spline,
+            speed,
+            distance: 0.0,
+            mode: PathMode::Loop,
+            orient: true,
+            playing: true,
+            reversed: false,
+        }
+    }
+
+    fn advance(&mut self, delta_time: f32, length: f32) {
+        let step = self.speed * delta_time;
+        match self.mode {
+            PathMode::Once => {
+                self.distance = (self.distance + step).min(length);
+                if self.distance >= length {
+                    self.playing = false;
+                }
+            }
+            PathMode::Loop if length > 0.0 => {
+                self.distance = (self.distance + step).rem_euclid(length);
+            }
+            PathMode::Loop => self.distance = 0.0,
+            PathMode::PingPong => {
+                let step = if self.reversed { -step } else { step };
+                self.distance += step;
+                if self.distance >= length {
+                    self.distance = length - (self.distance - length);
+                    self.reversed = true;
+                } else if self.distance <= 0.0 {
+                    self.distance = -self.distance;
+                    self.reversed = false;
+                }
+                self.distance = self.distance.clamp(0.0, length);
+            }
+        }
+    }
+}
+
+impl SimWorld {
+    // The control points of a spline in world space
+    pub fn spline_positions(&self, entity: Entity) -> Result<Vec<glm::Vec3>> {
+        let spline = self
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<Spline>()?
+            .clone();
+        let matrix = self.entity_global_transform_matrix(entity)?;
+        spline
+            .points
+            .iter()
+            .map(|point| match point {
+                SplinePoint::Position(position) => {
+                    Ok((matrix * glm::vec4(position.x, position.y, position.z, 1.0)).xyz())
+                }
+                SplinePoint::Entity(entity) => {
+                    Ok(self.entity_global_transform(*entity)?.translation)
+                }
+            })
+            .collect()
+    }
+
+    pub fn sample_spline(&self, entity: Entity) -> Result<SplineSamples> {
+        let (kind, looping) = {
+            let entry = self.ecs.entry_ref(entity)?;
+            let spline = entry.get_component::<Spline>()?;
+            (spline.kind, spline.looping)
+        };
+        let positions = self.spline_positions(entity)?;
+        Ok(SplineSamples::new(kind, &positions, looping))
+    }
+
+    // Queues the curve of a spline and a cross at each of its control points
+    pub fn draw_spline(&mut self, entity: Entity, color: glm::Vec4) -> Result<()> {
+        let samples = self.sample_spline(entity)?;
+        for pair in samples.positions.windows(2) {
+            self.debug_draw.line(pair[0], pair[1], color);
+        }
+        for position in self.spline_positions(entity)?.iter() {
+            self.debug_draw.cross(*position, 0.1, color);
+        }
+        Ok(())
+    }
+
+    pub fn update_path_followers(&mut self, delta_time: f32) -> Result<()> {
+        let mut query = <(Entity, &PathFollower)>::query();
+        let followers = query
+            .iter(&self.ecs)
+            .filter(|(_, follower)| follower.playing)
+            .map(|(entity, follower)| (*entity, follower.clone()))
+            .collect::<Vec<_>>();
+
+        for (entity, mut follower) in followers.into_iter() {
+            let samples = match self.sample_spline(follower.spline) {
+                Ok(samples) => samples,
+                Err(_) => {
+                    log::warn!("A path follower's spline no longer exists");
+                    continue;
+                }
+            };
+            follower.advance(delta_time, samples.length());
+
+            let mut global = self.entity_global_transform(entity)?;
+            global.translation = samples.position_at(follower.distance);
+            let mut direction = samples.tangent_at(follower.distance);
+            if follower.reversed {
+                direction = -direction;
+            }
+            if follower.orient && direction.norm() > f32::EPSILON {
+                global.look_at(&direction, &glm::Vec3::y());
+            }
+            let local = self.local_transform_from_global(entity, &global.matrix())?;
+
+            let mut entry = self
+                .ecs
+                .entry(entity)
+                .context("A path follower no longer exists")?;
+            *entry.get_component_mut::<Transform>()? = local;
+            *entry.get_component_mut::<PathFollower>()? = follower;
+            let handle = entry
+                .get_component::<RigidBody>()
+                .ok()
+                .map(|rigid_body| rigid_body.handle);
+            if let Some(body) = handle.and_then(|handle| self.physics.bodies.get_mut(handle)) {
+                if body.is_kinematic() {
+                    body.set_next_kinematic_position(global.as_isometry());
+                } else {
+                    body.set_position(global.as_isometry(), true);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{Transform, World};
+use anyhow::Result;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+// A textured quad drawn over the scene, with its top left corner placed
+// at the entity's x and y translation in pixels from the top left of the viewport.
+// The size is in pixels and is scaled by the entity's x and y scale.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Sprite {
+    // The index of the texture in the world's assets, or -1 for a solid quad
+    pub texture_index: i32,
+    pub size: glm::Vec2,
+    pub tint: glm::Vec4,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            texture_index: -1,
+            size: glm::vec2(64.0, 64.0),
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// A textured quad centered on the entity that always faces the camera.
+// The size is in world units and is scaled by the entity's x and y scale.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Billboard {
+    // The index of the texture in the world's assets, or -1 for a solid quad
+    pub texture_index: i32,
+    pub size: glm::Vec2,
+    pub tint: glm::Vec4,
+}
+
+impl Default for Billboard {
+    fn default() -> Self {
+        Self {
+            texture_index: -1,
+            size: glm::vec2(1.0, 1.0),
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl World {
+    pub fn sprites(&self) -> Result<Vec<(Transform, Sprite)>> {
+        self.visible_components::<Sprite>()
+    }
+
+    pub fn billboards(&self) -> Result<Vec<(Transform, Billboard)>> {
+        self.visible_components::<Billboard>()
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{Camera, Entity, RigidBody, SimWorld};
+use anyhow::{Context, Result};
+use legion::{EntityStore, IntoQuery};
+use rapier3d::geometry::InteractionGroups;
+use serde::{Deserialize, Serialize};
+use std::ops::{BitAnd, BitOr};
+
+// Up to 32 layers an entity belongs to, for filtering what cameras draw and what colliders touch.
+// Unrelated to the named Layer used for streaming parts of a world in and out.
+// Entities without the component are on the default layer.
+// The colliders of an entity with the component are kept in its layers, and keep colliding with what they were built to.
+// Colliders of entities without it keep the groups they were built with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self(u32::MAX);
+    pub const DEFAULT: Self = Self::layer(0);
+
+    // A single layer, from 0 to 31
+    pub const fn layer(index: u32) -> Self {
+        Self(1 << index)
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    // Colliders in these groups only collide with colliders on the layers given
+    pub const fn collision_groups(self, collides_with: Self) -> InteractionGroups {
+        InteractionGroups::new(self.0, collides_with.0)
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl BitOr for Layers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+impl BitAnd for Layers {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+// Free form labels for finding entities, such as "enemy" or "pickup"
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tags(pub Vec<String>);
+
+impl Tags {
+    pub fn has(&self, tag: &str) -> bool {
+        self.0.iter().any(|existing| existing == tag)
+    }
+}
+
+impl SimWorld {
+    pub fn entities_with_tag(&self, tag: &str) -> Vec<Entity> {
+        let mut query = <(Entity, &Tags)>::query();
+        query
+            .iter(&self.ecs)
+            .filter(|(_, tags)| tags.has(tag))
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+
+    pub fn has_tag(&self, entity: Entity, tag: &str) -> bool {
+        self.ecs
+            .entry_ref(entity)
+            .ok()
+            .and_then(|entry| entry.get_component::<Tags>().ok().map(|tags| tags.has(tag)))
+            .unwrap_or(false)
+    }
+
+    pub fn add_tag(&mut self, entity: Entity, tag: &str) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(entity)
+            .context("Failed to find the entity to tag")?;
+        match entry.get_component_mut::<Tags>() {
+            Ok(tags) => {
+                if !tags.has(tag) {
+                    tags.0.push(tag.to_string());
+                }
+            }
+            Err(_) => entry.add_component(Tags(vec![tag.to_string()])),
+        }
+        Ok(())
+    }
+
+    pub fn remove_tag(&mut self, entity: Entity, tag: &str) -> Result<()> {
+        let mut entry = self
+            .ecs
+            .entry(entity)
+            .context("Failed to find the entity to untag")?;
+        if let Ok(tags) = entry.get_component_mut::<Tags>() {
+            tags.0.retain(|existing| existing != tag);
+        }
+        Ok(())
+    }
+
+    // The layers an entity is on, which is the default layer when it has no Layers component
+    pub fn entity_layers(&self, entity: Entity) -> Layers {
+        self.ecs
+            .entry_ref(entity)
+            .ok()
+            .and_then(|entry| entry.get_component::<Layers>().ok().copied())
+            .unwrap_or_default()
+    }
+
+    pub fn set_entity_layers(&mut self, entity: Entity, layers: Layers) -> Result<()> {
+        self.ecs
+            .entry(entity)
+            .context("Failed to find the entity to set the layers of")?
+            .add_component(layers);
+        Ok(())
+    }
+
+    // Puts the colliders of each entity with a Layers component in its layers, before physics steps
+    pub fn sync_collider_layers(&mut self) {
+        let mut query = <(&RigidBody, &Layers)>::query();
+        let physics = &mut self.physics;
+        for (rigid_body, layers) in query.iter(&self.ecs) {
+            let colliders = match physics.bodies.get(rigid_body.handle) {
+                Some(body) => body.colliders().to_vec(),
+                None => continue,
+            };
+            for handle in colliders.into_iter() {
+                if let Some(collider) = physics.colliders.get_mut(handle) {
+                    let groups = collider.collision_groups();
+                    if groups.memberships != layers.0 {
+                        collider
+                            .set_collision_groups(InteractionGroups::new(layers.0, groups.filter));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn entities_on_layer(&self, layers: Layers) -> Vec<Entity> {
+        let mut query = <Entity>::query();
+        query
+            .iter(&self.ecs)
+            .copied()
+            .filter(|entity| self.entity_layers(*entity).intersects(layers))
+            .collect()
+    }
+
+    // The layers a camera draws, or every layer when the entity has no camera
+    pub fn camera_culling_mask(&self, camera: Entity) -> Layers {
+        self.ecs
+            .entry_ref(camera)
+            .ok()
+            .and_then(|entry| {
+                entry
+                    .get_component::<Camera>()
+                    .ok()
+                    .map(|camera| camera.culling_mask)
+            })
+            .unwrap_or(Layers::ALL)
+    }
+
+    pub fn active_camera_culling_mask(&self) -> Layers {
+        self.active_camera()
+            .map(|camera| self.camera_culling_mask(camera))
+            .unwrap_or(Layers::ALL)
+    }
+
+    // Whether a camera with the mask draws the entity
+    pub fn is_layer_visible(&self, entity: Entity, culling_mask: Layers) -> bool {
+        self.entity_layers(entity).intersects(culling_mask)
+    }
+}
@@ -0,0 +1,236 @@
+use crate::Vertex;
+use anyhow::{ensure, Context, Result};
+use image::{codecs::png::PngDecoder, ColorType, ImageDecoder};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, path::Path};
+
+// The image formats heightmaps can be imported from, dispatched on by file extension
+pub const HEIGHTMAP_EXTENSIONS: &[&str] = &["png", "exr"];
+
+// Pngs are only treated as heightmaps when they are grayscale, so color images aren't mistaken for one
+pub fn is_heightmap_file(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    match extension.as_deref() {
+        Some("exr") => true,
+        Some("png") => is_grayscale_png(path),
+        _ => false,
+    }
+}
+
+// Only the header is read, rather than decoding the image
+fn is_grayscale_png(path: &Path) -> bool {
+    let decoder = match File::open(path).map(|file| PngDecoder::new(BufReader::new(file))) {
+        Ok(Ok(decoder)) => decoder,
+        _ => return false,
+    };
+    matches!(
+        decoder.color_type(),
+        ColorType::L8 | ColorType::L16 | ColorType::La8 | ColorType::La16
+    )
+}
+
+// A grid of heights in the zero to one range, stored row by row
+#[derive(Default, Debug, Clone)]
+pub struct Heightmap {
+    pub width: u32,
+    pub depth: u32,
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(width: u32, depth: u32, heights: Vec<f32>) -> Result<Self> {
+        ensure!(
+            width > 1 && depth > 1,
+            "Heightmaps must be at least 2x2 texels, but this one is {}x{}!",
+            width,
+            depth
+        );
+        ensure!(
+            heights.len() == (width * depth) as usize,
+            "Heightmap has {} heights but {}x{} are required!",
+            heights.len(),
+            width,
+            depth
+        );
+        Ok(Self {
+            width,
+            depth,
+            heights,
+        })
+    }
+
+    // Only the red channel of color images is used
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        if extension.as_deref() == Some("exr") {
+            return Self::from_exr(path);
+        }
+        let image = image::open(path)?.to_luma16();
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel[0] as f32 / u16::MAX as f32)
+            .collect();
+        Self::new(image.width(), image.height(), heights)
+    }
+
+    // Exr heights are stored as is rather than in the zero to one range, so they are normalized
+    fn from_exr(path: &Path) -> Result<Self> {
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |resolution, _| {
+                (
+                    resolution.width(),
+                    vec![0.0; resolution.width() * resolution.height()],
+                )
+            },
+            |(width, heights): &mut (usize, Vec<f32>),
+             position,
+             (red, _, _, _): (f32, f32, f32, f32)| {
+                heights[position.y() * *width + position.x()] = red;
+            },
+        )
+        .with_context(|| format!("Failed to read exr heightmap '{}'", path.display()))?;
+        let size = image.layer_data.size;
+        let (_, mut heights) = image.layer_data.channel_data.pixels;
+
+        let lowest = heights.iter().copied().fold(f32::MAX, f32::min);
+        let highest = heights.iter().copied().fold(f32::MIN, f32::max);
+        let range = (highest - lowest).max(f32::EPSILON);
+        heights
+            .iter_mut()
+            .for_each(|height| *height = (*height - lowest) / range);
+        Self::new(size.width() as u32, size.height() as u32, heights)
+    }
+
+    // Coordinates past the edges are clamped onto them
+    pub fn height(&self, x: u32, z: u32) -> f32 {
+        let x = x.min(self.width - 1);
+        let z = z.min(self.depth - 1);
+        self.heights[(z * self.width + x) as usize]
+    }
+}
+
+// Marks the root entity of a terrain, whose children draw its chunks
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Terrain {
+    // The extents of the terrain along x and z, and the height that a heightmap value of one is raised to.
+    // The terrain is centered on its entity horizontally and rises up from it.
+    pub size: glm::Vec3,
+    // The number of quads along each side of a chunk
+    pub chunk_size: u32,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            size: glm::vec3(256.0, 32.0, 256.0),
+            chunk_size: 64,
+        }
+    }
+}
+
+impl Terrain {
+    // One unit between neighboring texels, with hills a tenth as high as the terrain is wide
+    pub fn for_heightmap(heightmap: &Heightmap) -> Self {
+        let width = (heightmap.width - 1) as f32;
+        let depth = (heightmap.depth - 1) as f32;
+        Self {
+            size: glm::vec3(width, 0.1 * width.max(depth), depth),
+            ..Default::default()
+        }
+    }
+
+    // Neighboring chunks duplicate the vertices along their shared edge.
+    // Uvs span the whole terrain rather than each chunk, so they can be used to sample a splat map.
+    pub fn chunks(&self, heightmap: &Heightmap) -> Vec<TerrainChunk> {
+        let chunk_size = self.chunk_size.max(1);
+        let mut chunks = Vec::new();
+        for (row, first_z) in (0..heightmap.depth - 1)
+            .step_by(chunk_size as usize)
+            .enumerate()
+        {
+            let last_z = (first_z + chunk_size).min(heightmap.depth - 1);
+            for (column, first_x) in (0..heightmap.width - 1)
+                .step_by(chunk_size as usize)
+                .enumerate()
+            {
+                let last_x = (first_x + chunk_size).min(heightmap.width - 1);
+
+                let mut vertices = Vec::new();
+                for z in first_z..=last_z {
+                    for x in first_x..=last_x {
+                        vertices.push(Vertex {
+                            position: self.position(heightmap, x, z),
+                            normal: self.normal(heightmap, x, z),
+                            uv_0: glm::vec2(
+                                x as f32 / (heightmap.width - 1) as f32,
+                                z as f32 / (heightmap.depth - 1) as f32,
+                            ),
+                            ..Default::default()
+                        });
+                    }
+                }
+
+                let mut indices = Vec::new();
+                let stride = last_x - first_x + 1;
+                for z in 0..last_z - first_z {
+                    for x in 0..last_x - first_x {
+                        let corner = z * stride + x;
+                        let below = corner + stride;
+                        indices.extend_from_slice(&[
+                            corner,
+                            below,
+                            below + 1,
+                            corner,
+                            below + 1,
+                            corner + 1,
+                        ]);
+                    }
+                }
+
+                chunks.push(TerrainChunk {
+                    column: column as u32,
+                    row: row as u32,
+                    vertices,
+                    indices,
+                });
+            }
+        }
+        chunks
+    }
+
+    fn position(&self, heightmap: &Heightmap, x: u32, z: u32) -> glm::Vec3 {
+        glm::vec3(
+            (x as f32 / (heightmap.width - 1) as f32 - 0.5) * self.size.x,
+            heightmap.height(x, z) * self.size.y,
+            (z as f32 / (heightmap.depth - 1) as f32 - 0.5) * self.size.z,
+        )
+    }
+
+    // Central differences between the neighboring heights, which matches across chunk edges
+    fn normal(&self, heightmap: &Heightmap, x: u32, z: u32) -> glm::Vec3 {
+        let along_x =
+            self.position(heightmap, x + 1, z) - self.position(heightmap, x.saturating_sub(1), z);
+        let along_z =
+            self.position(heightmap, x, z + 1) - self.position(heightmap, x, z.saturating_sub(1));
+        along_z.cross(&along_x).normalize()
+    }
+}
+
+// A square section of a terrain, with its vertices in the terrain's space
+#[derive(Default, Debug, Clone)]
+pub struct TerrainChunk {
+    pub column: u32,
+    pub row: u32,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
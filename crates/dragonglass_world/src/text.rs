@@ -0,0 +1,58 @@
+use crate::{Transform, World};
+use anyhow::Result;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextSpace {
+    // Drawn over the scene, with the top left of the text placed
+    // at the entity's x and y translation in pixels from the top left of the viewport
+    Screen,
+    // Centered on the entity's position and turned to face the camera
+    World,
+}
+
+// Text drawn with one of the world's signed distance field fonts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Text {
+    pub text: String,
+    // The name of the font in the world's assets
+    pub font: String,
+    // The height of a line, in pixels for screen space text and in world units for world space text
+    pub size: f32,
+    pub color: glm::Vec4,
+    pub space: TextSpace,
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            font: Self::DEFAULT_FONT.to_string(),
+            size: 32.0,
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            space: TextSpace::World,
+        }
+    }
+}
+
+impl Text {
+    pub const DEFAULT_FONT: &'static str = "default";
+
+    pub fn new(text: impl Into<String>, space: TextSpace) -> Self {
+        Self {
+            text: text.into(),
+            space,
+            ..Default::default()
+        }
+    }
+}
+
+impl World {
+    // Every visible text in the scene, along with the global transform of its entity
+    pub fn texts(&self) -> Result<Vec<(Transform, Text)>> {
+        let mut texts = self.visible_components::<Text>()?;
+        texts.retain(|(_, text)| !text.text.is_empty());
+        Ok(texts)
+    }
+}
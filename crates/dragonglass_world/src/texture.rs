@@ -1,21 +1,120 @@
+use crate::Fnv1a;
+#[cfg(feature = "basisu")]
+use crate::TextureCompression;
+#[cfg(feature = "assets")]
 use anyhow::{bail, Result};
+#[cfg(feature = "assets")]
 use image::{hdr::HdrDecoder, io::Reader as ImageReader, DynamicImage, GenericImageView};
 use nalgebra_glm as glm;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "assets")]
 use std::{io::BufReader, path::Path};
 
-// FIXME: Add mip levels
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Texture {
+    // Every mip level back to back, largest first
     pub pixels: Vec<u8>,
     pub format: Format,
     pub width: u32,
     pub height: u32,
+    // Uncompressed textures with a single level have the rest of their mips generated on the gpu.
+    // Block compressed textures can't be blitted, so they are only ever sampled at the levels they provide.
+    pub mip_levels: u32,
     pub sampler: Sampler,
 }
 
+impl Texture {
+    // A hash of the texture's contents, the same wherever and whenever it is loaded
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = Fnv1a::default();
+        hash.write(&self.width.to_le_bytes());
+        hash.write(&self.height.to_le_bytes());
+        hash.write(&self.pixels);
+        hash.finish()
+    }
+
+    pub fn has_precomputed_mips(&self) -> bool {
+        self.mip_levels > 1 || self.format.is_block_compressed()
+    }
+
+    pub fn mip_dimensions(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    // Where each mip level starts in the pixels
+    pub fn mip_offsets(&self) -> Vec<usize> {
+        let mut offset = 0;
+        (0..self.mip_levels.max(1))
+            .map(|level| {
+                let (width, height) = self.mip_dimensions(level);
+                let level_offset = offset;
+                offset += self.format.level_size(width, height);
+                level_offset
+            })
+            .collect()
+    }
+
+    // A copy without the largest levels, so it takes up less memory on the gpu.
+    // Textures with precomputed mips drop their largest ones, and single level textures
+    // are point sampled down. Block compressed textures without mips can't be downscaled.
+    pub fn downscaled(&self, levels: u32) -> Option<Self> {
+        if levels == 0 {
+            return Some(self.clone());
+        }
+        if self.mip_levels > 1 {
+            let levels = levels.min(self.mip_levels - 1);
+            let offset = *self.mip_offsets().get(levels as usize)?;
+            let (width, height) = self.mip_dimensions(levels);
+            return Some(Self {
+                pixels: self.pixels.get(offset..)?.to_vec(),
+                format: self.format,
+                width,
+                height,
+                mip_levels: self.mip_levels - levels,
+                sampler: self.sampler.clone(),
+            });
+        }
+        if self.format.is_block_compressed() {
+            return None;
+        }
+        let (width, height) = self.mip_dimensions(levels);
+        let (_, texel_size) = self.format.block_size();
+        let step = 1 << levels;
+        let mut pixels = Vec::with_capacity(self.format.level_size(width, height));
+        for y in 0..height {
+            let source_y = (y * step).min(self.height - 1) as usize;
+            for x in 0..width {
+                let source_x = (x * step).min(self.width - 1) as usize;
+                let offset = (source_y * self.width as usize + source_x) * texel_size;
+                pixels.extend_from_slice(self.pixels.get(offset..offset + texel_size)?);
+            }
+        }
+        Some(Self {
+            pixels,
+            format: self.format,
+            width,
+            height,
+            mip_levels: self.mip_levels,
+            sampler: self.sampler.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "assets")]
 impl Texture {
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("ktx2") => return Self::from_ktx2(path),
+            #[cfg(feature = "basisu")]
+            Some("basis") => return Self::from_basis(path, TextureCompression::Bc7),
+            _ => {}
+        }
+
         let image = ImageReader::open(path)?.decode()?;
         let pixels = image.to_bytes();
         let (width, height) = image.dimensions();
@@ -26,6 +125,7 @@ impl Texture {
             format,
             width,
             height,
+            mip_levels: 1,
             sampler: Sampler::default(),
         })
     }
@@ -61,6 +161,7 @@ impl Texture {
             format: Format::R32G32B32A32F,
             width,
             height,
+            mip_levels: 1,
             sampler: Sampler::default(),
         })
     }
@@ -90,6 +191,47 @@ pub enum Format {
     R32G32F,
     R32G32B32F,
     R32G32B32A32F,
+    // Block compressed formats store 4x4 texel blocks
+    Bc1,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc7,
+}
+
+impl Format {
+    pub fn is_block_compressed(&self) -> bool {
+        matches!(
+            self,
+            Self::Bc1 | Self::Bc3 | Self::Bc4 | Self::Bc5 | Self::Bc7
+        )
+    }
+
+    // The width and height in texels of a block, and its size in bytes.
+    // Uncompressed formats have single texel blocks.
+    pub fn block_size(&self) -> (u32, usize) {
+        match self {
+            Self::R8 => (1, 1),
+            Self::R8G8 | Self::R16 | Self::R16F => (1, 2),
+            Self::R8G8B8 | Self::B8G8R8 => (1, 3),
+            Self::R8G8B8A8 | Self::B8G8R8A8 | Self::R16G16 | Self::R16G16F => (1, 4),
+            Self::R32 | Self::R32F => (1, 4),
+            Self::R16G16B16 | Self::R16G16B16F => (1, 6),
+            Self::R16G16B16A16 | Self::R16G16B16A16F | Self::R32G32 | Self::R32G32F => (1, 8),
+            Self::R32G32B32 | Self::R32G32B32F => (1, 12),
+            Self::R32G32B32A32 | Self::R32G32B32A32F => (1, 16),
+            Self::Bc1 | Self::Bc4 => (4, 8),
+            Self::Bc3 | Self::Bc5 | Self::Bc7 => (4, 16),
+        }
+    }
+
+    // The number of bytes a single mip level of the given size takes up
+    pub fn level_size(&self, width: u32, height: u32) -> usize {
+        let (block_dimension, block_bytes) = self.block_size();
+        let blocks_wide = ((width + block_dimension - 1) / block_dimension) as usize;
+        let blocks_high = ((height + block_dimension - 1) / block_dimension) as usize;
+        blocks_wide * blocks_high * block_bytes
+    }
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
@@ -147,7 +289,17 @@ pub struct Material {
     pub roughness_factor: f32,
     pub alpha_mode: AlphaMode,
     pub alpha_cutoff: f32,
-    pub is_unlit: bool,
+    // Both sides of each face are drawn, as with foliage and cloth
+    pub double_sided: bool,
+    pub shading_model: ShadingModel,
+    // Scales the emissive factor past one, for emission brighter than the color range allows
+    pub emissive_strength: f32,
+    // A second, clear specular layer on top of the material, like the lacquer on car paint
+    pub clearcoat_factor: f32,
+    pub clearcoat_roughness_factor: f32,
+    // How much light passes through the surface, as with glass
+    pub transmission_factor: f32,
+    pub ior: f32,
 }
 
 impl Default for Material {
@@ -172,8 +324,45 @@ impl Default for Material {
             roughness_factor: 1.0,
             alpha_mode: AlphaMode::Opaque,
             alpha_cutoff: 0.5,
-            is_unlit: false,
+            double_sided: false,
+            shading_model: ShadingModel::Pbr,
+            emissive_strength: 1.0,
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            transmission_factor: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+impl Material {
+    // Every texture slot of the material, including the unused ones set to -1
+    pub fn texture_indices(&self) -> Vec<i32> {
+        let mut indices = vec![
+            self.color_texture_index,
+            self.metallic_roughness_texture_index,
+            self.normal_texture_index,
+            self.occlusion_texture_index,
+            self.emissive_texture_index,
+        ];
+        if let ShadingModel::Terrain { layers, .. } = &self.shading_model {
+            indices.extend_from_slice(layers);
+        }
+        indices
+    }
+
+    pub fn texture_indices_mut(&mut self) -> Vec<&mut i32> {
+        let mut indices = vec![
+            &mut self.color_texture_index,
+            &mut self.metallic_roughness_texture_index,
+            &mut self.normal_texture_index,
+            &mut self.occlusion_texture_index,
+            &mut self.emissive_texture_index,
+        ];
+        if let ShadingModel::Terrain { layers, .. } = &mut self.shading_model {
+            indices.extend(layers.iter_mut());
         }
+        indices
     }
 }
 
@@ -189,3 +378,67 @@ impl Default for AlphaMode {
         Self::Opaque
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ShadingModel {
+    Pbr,
+    Unlit,
+    // Diffuse lighting is quantized into bands, with smoothness blurring the edges between them.
+    // A rim light brightens the silhouette.
+    Toon {
+        bands: u32,
+        smoothness: f32,
+        rim_strength: f32,
+    },
+    // Up to four layer textures blended by a splat map, which is the material's color texture.
+    // The first layer covers the whole surface and the splat map's red, green, and blue channels
+    // paint the other three over it. Layers repeat across the surface the given number of times.
+    Terrain {
+        layers: [i32; 4],
+        tiling: f32,
+    },
+}
+
+impl Default for ShadingModel {
+    fn default() -> Self {
+        Self::Pbr
+    }
+}
+
+impl ShadingModel {
+    // Each variant is rendered by its own specialized pipeline
+    pub const NUMBER_OF_VARIANTS: usize = 4;
+
+    pub fn toon() -> Self {
+        Self::Toon {
+            bands: 3,
+            smoothness: 0.05,
+            rim_strength: 0.25,
+        }
+    }
+
+    pub fn terrain(layers: [i32; 4]) -> Self {
+        Self::Terrain {
+            layers,
+            tiling: 32.0,
+        }
+    }
+
+    pub fn variant(&self) -> u32 {
+        match self {
+            Self::Pbr => 0,
+            Self::Unlit => 1,
+            Self::Toon { .. } => 2,
+            Self::Terrain { .. } => 3,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pbr => "PBR",
+            Self::Unlit => "Unlit",
+            Self::Toon { .. } => "Toon",
+            Self::Terrain { .. } => "Terrain",
+        }
+    }
+}
@@ -3,7 +3,7 @@ use nalgebra as na;
 use nalgebra_glm as glm;
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Transform {
     pub translation: glm::Vec3,
     pub rotation: glm::Quat,
@@ -87,6 +87,15 @@ impl Transform {
     pub fn look_at(&mut self, target: &glm::Vec3, up: &glm::Vec3) {
         self.rotation = glm::quat_conjugate(&glm::quat_look_at(target, up));
     }
+
+    // Faces a point rather than a direction. The point is in the same space as the translation,
+    // which is the parent's space for an entity in a scenegraph.
+    pub fn look_at_point(&mut self, target: &glm::Vec3, up: &glm::Vec3) {
+        let direction = target - self.translation;
+        if direction.norm() > f32::EPSILON {
+            self.look_at(&direction.normalize(), up);
+        }
+    }
 }
 
 impl From<glm::Mat4> for Transform {
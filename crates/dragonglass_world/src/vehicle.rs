@@ -0,0 +1,240 @@
+use crate::{Clock, ColliderHandle, Entity, RigidBody, SimWorld};
+use anyhow::Result;
+use legion::{EntityStore, IntoQuery};
+use nalgebra_glm as glm;
+use rapier3d::{
+    geometry::{InteractionGroups, Ray},
+    na::{Point3, Vector3},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// A wheel hanging from the chassis on a spring, found by casting a ray straight down from where it attaches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Wheel {
+    // Where the top of the suspension attaches, relative to the chassis
+    pub position: glm::Vec3,
+    pub radius: f32,
+    // How far the suspension reaches when nothing presses on it
+    pub suspension_rest_length: f32,
+    pub suspension_stiffness: f32,
+    pub suspension_damping: f32,
+    // How much of the wheel's load it can push sideways and along the ground with before sliding
+    pub friction: f32,
+    pub steered: bool,
+    pub driven: bool,
+    #[serde(skip)]
+    pub state: WheelState,
+}
+
+impl Wheel {
+    pub fn new(position: glm::Vec3, steered: bool, driven: bool) -> Self {
+        Self {
+            position,
+            radius: 0.35,
+            suspension_rest_length: 0.4,
+            suspension_stiffness: 60.0,
+            suspension_damping: 6.0,
+            friction: 1.5,
+            steered,
+            driven,
+            state: WheelState::default(),
+        }
+    }
+}
+
+// What a wheel touched during the last physics step, for drawing wheels and playing effects
+#[derive(Default, Debug, Copy, Clone)]
+pub struct WheelState {
+    pub in_contact: bool,
+    pub suspension_length: f32,
+    pub contact_point: glm::Vec3,
+    pub steer_angle: f32,
+}
+
+// What the driver asks of the vehicle, set by game code each frame
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VehicleControls {
+    // From -1.0 in reverse to 1.0 at full throttle
+    pub throttle: f32,
+    // From 0.0 to 1.0
+    pub brake: f32,
+    // From -1.0 turning left to 1.0 turning right
+    pub steer: f32,
+}
+
+// Drives the dynamic rigid body of its entity as a car, with each wheel a suspension ray.
+// The chassis faces along its transform's forward axis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaycastVehicle {
+    pub wheels: Vec<Wheel>,
+    // The force spread across the driven wheels at full throttle
+    pub engine_force: f32,
+    // The force each wheel can brake with
+    pub brake_force: f32,
+    pub max_steer_angle: f32,
+    // What the wheels' rays can hit
+    pub collision_groups: InteractionGroups,
+    pub controls: VehicleControls,
+}
+
+impl Default for RaycastVehicle {
+    fn default() -> Self {
+        Self {
+            wheels: Vec::new(),
+            engine_force: 20.0,
+            brake_force: 15.0,
+            max_steer_angle: 35_f32.to_radians(),
+            collision_groups: InteractionGroups::all(),
+            controls: VehicleControls::default(),
+        }
+    }
+}
+
+impl RaycastVehicle {
+    // Four wheels at the corners of a chassis with the given half extents, steering with the front pair
+    pub fn four_wheeled(half_extents: glm::Vec3) -> Self {
+        let corner = |x: f32, z: f32, front: bool| {
+            Wheel::new(
+                glm::vec3(x * half_extents.x, -half_extents.y, z * half_extents.z),
+                front,
+                true,
+            )
+        };
+        Self {
+            wheels: vec![
+                corner(-1.0, -1.0, true),
+                corner(1.0, -1.0, true),
+                corner(-1.0, 1.0, false),
+                corner(1.0, 1.0, false),
+            ],
+            ..Default::default()
+        }
+    }
+}
+
+// Keeps a long frame from stepping physics more times than it can afford
+const MAX_VEHICLE_STEPS: u32 = 8;
+
+impl SimWorld {
+    // Vehicle springs are only stable over short steps, so while there are vehicles
+    // each frame is split into physics steps no longer than the clock's fixed step
+    pub(crate) fn physics_steps(&self, delta_time: f32) -> u32 {
+        if <&RaycastVehicle>::query().iter(&self.ecs).next().is_none() {
+            return 1;
+        }
+        let step = self.clock.fixed_step().unwrap_or(Clock::STEP_SECONDS);
+        ((delta_time / step).ceil() as u32).clamp(1, MAX_VEHICLE_STEPS)
+    }
+
+    // Applies suspension, engine, brake, and tire forces to every vehicle for one physics step
+    pub fn update_vehicles(&mut self, delta_time: f32) -> Result<()> {
+        let mut query = <(&RigidBody, &mut RaycastVehicle)>::query();
+        for (rigid_body, vehicle) in query.iter_mut(&mut self.ecs) {
+            let chassis_colliders = match self.physics.bodies.get(rigid_body.handle) {
+                Some(body) if body.is_dynamic() => {
+                    body.colliders().iter().copied().collect::<HashSet<_>>()
+                }
+                _ => continue,
+            };
+            let driven_wheels = vehicle.wheels.iter().filter(|wheel| wheel.driven).count();
+            let wheel_count = vehicle.wheels.len();
+            let controls = vehicle.controls;
+
+            for wheel in vehicle.wheels.iter_mut() {
+                let body = &self.physics.bodies[rigid_body.handle];
+                let position = *body.position();
+                // The share of the chassis each wheel carries, for forces that stop motion outright
+                let mass = body.mass() / wheel_count as f32;
+                let up = position.rotation * Vector3::y();
+                let attachment = position * Point3::from(wheel.position);
+
+                wheel.state.steer_angle = if wheel.steered {
+                    -controls.steer.clamp(-1.0, 1.0) * vehicle.max_steer_angle
+                } else {
+                    0.0
+                };
+                let steering = glm::quat_angle_axis(wheel.state.steer_angle, &glm::Vec3::y());
+                let forward =
+                    position.rotation * glm::quat_rotate_vec3(&steering, &(-glm::Vec3::z()));
+
+                let reach = wheel.suspension_rest_length + wheel.radius;
+                let ray = Ray::new(attachment, -up);
+                let filter = |collider: ColliderHandle| !chassis_colliders.contains(&collider);
+                let hit = self.physics.query_pipeline.cast_ray(
+                    &self.physics.colliders,
+                    &ray,
+                    reach,
+                    true,
+                    vehicle.collision_groups,
+                    Some(&filter),
+                );
+
+                let distance = match hit {
+                    Some((_, distance)) => distance,
+                    None => {
+                        wheel.state.in_contact = false;
+                        wheel.state.suspension_length = wheel.suspension_rest_length;
+                        wheel.state.contact_point = (attachment - up * reach).coords;
+                        continue;
+                    }
+                };
+                let contact = ray.point_at(distance);
+                wheel.state.in_contact = true;
+                wheel.state.suspension_length = distance - wheel.radius;
+                wheel.state.contact_point = contact.coords;
+
+                // The spring pushes the chassis up, and the damper resists it moving along the spring
+                let velocity = body.velocity_at_point(&contact);
+                let compression = wheel.suspension_rest_length - wheel.state.suspension_length;
+                let load = (wheel.suspension_stiffness * compression
+                    - wheel.suspension_damping * velocity.dot(&up))
+                .max(0.0);
+                let mut impulse = up * load * delta_time;
+
+                // Tires push along the ground, so forces are kept level with it
+                let forward = (forward - up * forward.dot(&up)).normalize();
+                let side = forward.cross(&up);
+                let grip = wheel.friction * load * delta_time;
+
+                if wheel.driven && driven_wheels > 0 {
+                    let drive = controls.throttle.clamp(-1.0, 1.0) * vehicle.engine_force
+                        / driven_wheels as f32;
+                    impulse += forward * (drive * delta_time).clamp(-grip, grip);
+                }
+
+                // Brakes oppose rolling without reversing it
+                let rolling = velocity.dot(&forward);
+                let braking = (rolling * mass)
+                    .abs()
+                    .min(controls.brake.clamp(0.0, 1.0) * vehicle.brake_force * delta_time);
+                impulse -= forward * rolling.signum() * braking.min(grip);
+
+                // Tires resist sliding sideways, up to what their grip allows
+                let sliding = velocity.dot(&side) * mass;
+                impulse -= side * sliding.clamp(-grip, grip);
+
+                if let Some(body) = self.physics.bodies.get_mut(rigid_body.handle) {
+                    body.apply_impulse_at_point(impulse, contact, true);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Where each wheel's center is in world space, for placing the meshes of the wheels
+    pub fn vehicle_wheel_positions(&self, entity: Entity) -> Result<Vec<glm::Vec3>> {
+        let global = self.entity_global_transform(entity)?;
+        let down = -global.up();
+        let entry = self.ecs.entry_ref(entity)?;
+        let vehicle = entry.get_component::<RaycastVehicle>()?;
+        Ok(vehicle
+            .wheels
+            .iter()
+            .map(|wheel| {
+                let attachment = global.matrix() * wheel.position.push(1.0);
+                attachment.xyz() + down * wheel.state.suspension_length
+            })
+            .collect())
+    }
+}
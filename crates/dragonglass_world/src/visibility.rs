@@ -0,0 +1,245 @@
+use crate::{ColliderHandle, Entity, RigidBody, SimWorld};
+use anyhow::Result;
+use legion::{EntityStore, IntoQuery};
+use na::Point3;
+use nalgebra as na;
+use nalgebra_glm as glm;
+use rapier3d::geometry::{InteractionGroups, Ray};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Team(pub u32);
+
+// Reveals the fog of war around an entity for the entity's team
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct VisionSource {
+    pub radius: f32,
+    // Line of sight rays are cast at this height above the entity
+    pub eye_height: f32,
+}
+
+impl Default for VisionSource {
+    fn default() -> Self {
+        Self {
+            radius: 10.0,
+            eye_height: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellVisibility {
+    Unexplored,
+    Explored,
+    Visible,
+}
+
+// A grid over the XZ plane tracking what each team can currently see and has seen before
+pub struct FogOfWar {
+    pub origin: glm::Vec2,
+    pub cell_size: f32,
+    pub width: usize,
+    pub depth: usize,
+    // The team whose view is drawn by the renderer
+    pub displayed_team: Team,
+    // The overlay is drawn as a plane at this height, above the units it hides
+    pub overlay_height: f32,
+    pub line_of_sight: bool,
+    pub occluder_groups: InteractionGroups,
+    teams: HashMap<Team, Vec<CellVisibility>>,
+}
+
+impl FogOfWar {
+    pub fn new(origin: glm::Vec2, cell_size: f32, width: usize, depth: usize) -> Self {
+        Self {
+            origin,
+            cell_size,
+            width,
+            depth,
+            displayed_team: Team(0),
+            overlay_height: 10.0,
+            line_of_sight: true,
+            occluder_groups: InteractionGroups::all(),
+            teams: HashMap::new(),
+        }
+    }
+
+    pub fn extents(&self) -> glm::Vec2 {
+        glm::vec2(self.width as f32, self.depth as f32) * self.cell_size
+    }
+
+    pub fn cell_at(&self, position: &glm::Vec3) -> Option<(usize, usize)> {
+        let offset = (glm::vec2(position.x, position.z) - self.origin) / self.cell_size;
+        if offset.x < 0.0 || offset.y < 0.0 {
+            return None;
+        }
+        let (x, z) = (offset.x as usize, offset.y as usize);
+        if x >= self.width || z >= self.depth {
+            return None;
+        }
+        Some((x, z))
+    }
+
+    pub fn cell_center(&self, x: usize, z: usize) -> glm::Vec2 {
+        self.origin + glm::vec2(x as f32 + 0.5, z as f32 + 0.5) * self.cell_size
+    }
+
+    pub fn cell_visibility(&self, team: Team, x: usize, z: usize) -> CellVisibility {
+        self.teams
+            .get(&team)
+            .and_then(|cells| cells.get(z * self.width + x))
+            .copied()
+            .unwrap_or(CellVisibility::Unexplored)
+    }
+
+    pub fn visibility_at(&self, team: Team, position: &glm::Vec3) -> CellVisibility {
+        match self.cell_at(position) {
+            Some((x, z)) => self.cell_visibility(team, x, z),
+            None => CellVisibility::Unexplored,
+        }
+    }
+
+    pub fn is_position_visible(&self, team: Team, position: &glm::Vec3) -> bool {
+        self.visibility_at(team, position) == CellVisibility::Visible
+    }
+
+    // Forgets everything the team has explored
+    pub fn reset_team(&mut self, team: Team) {
+        self.teams.remove(&team);
+    }
+
+    // One RGBA pixel per cell, with alpha controlling how strongly the cell is fogged
+    pub fn overlay_pixels(&self) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.width * self.depth * 4);
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                let alpha = match self.cell_visibility(self.displayed_team, x, z) {
+                    CellVisibility::Unexplored => 255,
+                    CellVisibility::Explored => 160,
+                    CellVisibility::Visible => 0,
+                };
+                pixels.extend_from_slice(&[0, 0, 0, alpha]);
+            }
+        }
+        pixels
+    }
+
+    pub fn update(&mut self, world: &SimWorld) -> Result<()> {
+        let number_of_cells = self.width * self.depth;
+        for cells in self.teams.values_mut() {
+            cells.resize(number_of_cells, CellVisibility::Unexplored);
+            cells
+                .iter_mut()
+                .filter(|cell| **cell == CellVisibility::Visible)
+                .for_each(|cell| *cell = CellVisibility::Explored);
+        }
+
+        let mut query = <(Entity, &Team, &VisionSource)>::query();
+        let sources = query
+            .iter(&world.ecs)
+            .map(|(entity, team, source)| (*entity, *team, *source))
+            .collect::<Vec<_>>();
+
+        for (entity, team, source) in sources.into_iter() {
+            let position = world.entity_global_transform(entity)?.translation;
+            let visible = self.visible_cells(world, entity, &position, &source)?;
+            let cells = self
+                .teams
+                .entry(team)
+                .or_insert_with(|| vec![CellVisibility::Unexplored; number_of_cells]);
+            for index in visible.into_iter() {
+                cells[index] = CellVisibility::Visible;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visible_cells(
+        &self,
+        world: &SimWorld,
+        entity: Entity,
+        position: &glm::Vec3,
+        source: &VisionSource,
+    ) -> Result<Vec<usize>> {
+        let rigid_body = world
+            .ecs
+            .entry_ref(entity)?
+            .get_component::<RigidBody>()
+            .ok()
+            .map(|rigid_body| rigid_body.handle);
+
+        // Units should not block their own view
+        let filter =
+            |handle: ColliderHandle| match (rigid_body, world.physics.colliders.get(handle)) {
+                (Some(rigid_body), Some(collider)) => collider.parent() != Some(rigid_body),
+                _ => true,
+            };
+
+        let eye = glm::vec3(position.x, position.y + source.eye_height, position.z);
+        let radius = glm::vec2(source.radius, source.radius);
+        let start = (glm::vec2(position.x, position.z) - radius - self.origin) / self.cell_size;
+        let end = (glm::vec2(position.x, position.z) + radius - self.origin) / self.cell_size;
+        let clamp = |value: f32, limit: usize| (value.max(0.0) as usize).min(limit - 1);
+
+        let mut cells = Vec::new();
+        if self.width == 0 || self.depth == 0 || end.x < 0.0 || end.y < 0.0 {
+            return Ok(cells);
+        }
+        for z in clamp(start.y, self.depth)..=clamp(end.y, self.depth) {
+            for x in clamp(start.x, self.width)..=clamp(end.x, self.width) {
+                let center = self.cell_center(x, z);
+                let target = glm::vec3(center.x, eye.y, center.y);
+                let distance = glm::distance(&eye, &target);
+                if distance > source.radius {
+                    continue;
+                }
+
+                if self.line_of_sight && distance > self.cell_size {
+                    let ray = Ray::new(Point3::from(eye), (target - eye) / distance);
+                    let hit = world.physics.query_pipeline.cast_ray(
+                        &world.physics.colliders,
+                        &ray,
+                        distance,
+                        true,
+                        self.occluder_groups,
+                        Some(&filter),
+                    );
+                    if hit.is_some() {
+                        continue;
+                    }
+                }
+
+                cells.push(z * self.width + x);
+            }
+        }
+        Ok(cells)
+    }
+}
+
+impl SimWorld {
+    // Entities are always visible to their own team.
+    // Without fog of war, everything is visible.
+    pub fn is_visible(&self, entity: Entity, team: Team) -> Result<bool> {
+        let entry = self.ecs.entry_ref(entity)?;
+        if entry.get_component::<Team>().ok() == Some(&team) {
+            return Ok(true);
+        }
+        let fog_of_war = match self.fog_of_war.as_ref() {
+            Some(fog_of_war) => fog_of_war,
+            None => return Ok(true),
+        };
+        let position = self.entity_global_transform(entity)?.translation;
+        Ok(fog_of_war.is_position_visible(team, &position))
+    }
+
+    pub fn update_fog_of_war(&mut self) -> Result<()> {
+        if let Some(mut fog_of_war) = self.fog_of_war.take() {
+            let result = fog_of_war.update(self);
+            self.fog_of_war = Some(fog_of_war);
+            result?;
+        }
+        Ok(())
+    }
+}
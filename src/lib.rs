@@ -6,14 +6,31 @@ pub mod audio {
     pub use dragonglass_audio::*;
 }
 
+pub mod config {
+    pub use dragonglass_config::*;
+}
+
 pub mod gui {
     pub use dragonglass_gui::*;
 }
 
+#[cfg(feature = "network")]
+pub mod network {
+    pub use dragonglass_network::*;
+}
+
+pub mod profiling {
+    pub use dragonglass_profiling::*;
+}
+
 pub mod render {
     pub use dragonglass_render::*;
 }
 
+pub mod scripting {
+    pub use dragonglass_scripting::*;
+}
+
 pub mod world {
     pub use dragonglass_world::*;
 }